@@ -0,0 +1,140 @@
+-- Rebuild the sync operation log for the sync engine and add its tables
+--
+-- Operations are now identified by UUID, carry a timestamp, a hybrid
+-- logical clock stamp and a version vector, and `sequence` is the
+-- operation's counter in its device's version vector entry. Operations of
+-- the old log are kept, numbered per device in the order they were queued.
+
+-- The initial schema creates the old table; created here too so the
+-- rebuild also runs on databases set up without it
+CREATE TABLE IF NOT EXISTS sync_operations (
+    id INTEGER PRIMARY KEY,
+    device_id TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    operation_type TEXT NOT NULL,
+    entity_type TEXT NOT NULL,
+    entity_id TEXT,
+    payload TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    synced BOOLEAN NOT NULL DEFAULT FALSE,
+    synced_at TIMESTAMP
+);
+
+ALTER TABLE sync_operations RENAME TO sync_operations_old;
+
+CREATE TABLE sync_operations (
+    id TEXT PRIMARY KEY,
+    device_id TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    operation_type INTEGER NOT NULL, -- 0 create, 1 update, 2 delete, 3 reference
+    entity_type TEXT NOT NULL,
+    entity_id TEXT,
+    payload TEXT NOT NULL,           -- JSON payload
+    timestamp INTEGER NOT NULL,
+    hlc TEXT NOT NULL DEFAULT '',    -- hybrid logical clock stamp, sorting in stamp order
+    vector_clock TEXT NOT NULL,      -- JSON object of device IDs to counters
+    sequence INTEGER NOT NULL DEFAULT 0,
+    synced INTEGER NOT NULL DEFAULT 0,
+    synced_at INTEGER
+);
+
+INSERT INTO sync_operations
+    (id, device_id, user_id, operation_type, entity_type, entity_id, payload,
+     timestamp, vector_clock, sequence, synced, synced_at)
+SELECT
+    CAST(id AS TEXT),
+    device_id,
+    user_id,
+    CASE operation_type WHEN 'create' THEN 0 WHEN 'update' THEN 1 WHEN 'delete' THEN 2 ELSE 3 END,
+    entity_type,
+    entity_id,
+    payload,
+    CAST(strftime('%s', created_at) AS INTEGER),
+    json_object(device_id, sequence),
+    sequence,
+    CASE WHEN synced THEN 1 ELSE 0 END,
+    CAST(strftime('%s', synced_at) AS INTEGER)
+FROM (
+    SELECT *, ROW_NUMBER() OVER (PARTITION BY device_id ORDER BY created_at, id) AS sequence
+    FROM sync_operations_old
+);
+
+DROP TABLE sync_operations_old;
+
+CREATE INDEX idx_sync_operations_synced ON sync_operations(synced, timestamp);
+CREATE INDEX idx_sync_operations_sequence ON sync_operations(user_id, device_id, sequence);
+CREATE INDEX idx_sync_operations_entity ON sync_operations(entity_type, entity_id);
+
+-- How far exchanges with each peer got, per user and origin device: the
+-- highest sequence the peer acknowledged (sent) or this device applied
+-- from it (received)
+CREATE TABLE IF NOT EXISTS sync_checkpoints (
+    peer_id TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    direction TEXT NOT NULL,         -- sent or received
+    device_id TEXT NOT NULL,
+    sequence INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (peer_id, user_id, direction, device_id)
+);
+
+-- What each device of a user last reported holding, per origin device,
+-- directly or relayed by a peer, and when it reported it
+CREATE TABLE IF NOT EXISTS sync_progress (
+    user_id INTEGER NOT NULL,
+    device_id TEXT NOT NULL,
+    origin_device_id TEXT NOT NULL,
+    sequence INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, device_id, origin_device_id)
+);
+
+-- Conflicts whose automatic resolution dropped a change, kept for review.
+-- Versions are JSON objects of the entity's fields, or null if deleted
+CREATE TABLE IF NOT EXISTS sync_conflicts (
+    id TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    entity_type TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    ancestor TEXT,                   -- version both devices had seen, if any
+    local TEXT NOT NULL,
+    remote TEXT NOT NULL,
+    applied TEXT NOT NULL,           -- version automatic resolution kept
+    local_operation_id TEXT NOT NULL,
+    remote_operation_id TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'open', -- open or resolved
+    resolution TEXT,                 -- local, remote or manual
+    resolution_operation_id TEXT,
+    created_at INTEGER NOT NULL,
+    resolved_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_conflicts_user ON sync_conflicts(user_id, status, created_at);
+
+-- Highest sequence per user and origin device up to which operations were
+-- compacted, and may no longer be stored one by one
+CREATE TABLE IF NOT EXISTS sync_compaction (
+    user_id INTEGER NOT NULL,
+    device_id TEXT NOT NULL,
+    sequence INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, device_id)
+);
+
+-- Pairing tokens of devices allowed to sync a user's operations over the
+-- LAN, stored as SHA-256 hashes
+CREATE TABLE IF NOT EXISTS sync_pairings (
+    user_id INTEGER NOT NULL,
+    token_hash TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, token_hash)
+);
+
+-- ID this device syncs under, created on first launch, and whether paired
+-- devices may reach it over the LAN
+CREATE TABLE IF NOT EXISTS sync_device (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    device_id TEXT NOT NULL,
+    lan_sync INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
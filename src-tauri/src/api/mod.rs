@@ -23,6 +23,7 @@ pub mod users;
 pub mod forum;
 pub mod quiz;
 pub mod integration;
+pub mod sync;
 
 // Unified API clients
 pub mod unified_clients;
@@ -42,7 +43,7 @@ pub use unified_clients::{create_canvas_client_adapter, create_discourse_client_
 /// # Returns
 /// A Router with all API routes
 pub fn create_router(state: Arc<AppState>) -> Router {
-    Router::new()
+    let mut router = Router::new()
         .nest("/api/auth", auth::auth_routes())
         .nest("/api/courses", courses::course_routes())
         .nest("/api/users", users::user_routes())
@@ -68,8 +69,14 @@ pub fn create_router(state: Arc<AppState>) -> Router {
             "/api/courses/:id/forum/activity",
             get(integration::get_course_forum_activity)
         )
-        .merge(discussion_routes::discussion_routes())
-        .with_state(state)
+        .merge(discussion_routes::discussion_routes());
+
+    // Devices sync with the hub through it
+    match state.get_sync_engine() {
+        Ok(engine) => router = router.nest("/api/sync", sync::sync_routes(engine)),
+        Err(e) => log::warn!("Sync hub endpoints disabled: {}", e),
+    }
+    router.with_state(state)
 }
 
 async fn health_check() -> &'static str {
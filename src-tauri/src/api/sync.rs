@@ -2,6 +2,8 @@ use axum::{
     extract::{Json, State},
     http::StatusCode,
     response::IntoResponse,
+    routing::post,
+    Router,
 };
 use std::sync::Arc;
 
@@ -9,6 +11,15 @@ use crate::core::auth::Claims;
 use crate::core::errors::AppError;
 use crate::sync::engine::SyncEngine;
 use crate::sync::operations::SyncBatch;
+use crate::sync::protocol::{self, SyncRequest, SyncResponse};
+
+/// Routes of the sync hub, to be nested under `/api/sync`
+pub fn sync_routes<S: Clone + Send + Sync + 'static>(engine: Arc<SyncEngine>) -> Router<S> {
+    Router::new()
+        .route("/batch", post(receive_sync_batch))
+        .route("/exchange", post(exchange_sync))
+        .with_state(engine)
+}
 
 // Receive sync batch from client
 pub async fn receive_sync_batch(
@@ -36,4 +47,16 @@ pub async fn receive_sync_batch(
         Some(batch) => Ok((StatusCode::OK, Json(batch))),
         None => Ok((StatusCode::OK, Json(serde_json::json!({ "message": "Sync successful" })))),
     }
-}
\ No newline at end of file
+}
+
+// Answer a request of the device-to-device sync protocol
+pub async fn exchange_sync(
+    claims: Claims,
+    State(engine): State<Arc<SyncEngine>>,
+    Json(request): Json<SyncRequest>,
+) -> Result<Json<SyncResponse>, AppError> {
+    let user_id = claims.sub.parse::<i64>()
+        .map_err(|_| AppError::AuthError("Invalid user ID in token".to_string()))?;
+
+    Ok(Json(protocol::handle_request(&engine, user_id, request).await?))
+}
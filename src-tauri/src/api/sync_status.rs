@@ -1,10 +1,13 @@
 use serde::{Deserialize, Serialize};
 use tauri::{command, State};
 use sqlx::SqlitePool;
+use std::sync::Arc;
 use crate::error::Error;
 use crate::services::integration::canvas_integration::CanvasIntegrationService;
 use crate::services::integration::discourse_integration::DiscourseIntegrationService;
 use crate::services::integration::sync_service::IntegrationSyncService;
+use crate::sync::engine::SyncEngine;
+use crate::sync::hlc::ClockDrift;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncStatus {
@@ -14,6 +17,8 @@ pub struct SyncStatus {
     pub pending_syncs: i32,
     pub sync_in_progress: bool,
     pub sync_errors: Vec<String>,
+    /// Devices whose clocks are off from this one's beyond the threshold
+    pub clock_drift: Vec<ClockDrift>,
 }
 
 #[command]
@@ -22,6 +27,7 @@ pub async fn get_sync_status(
     canvas: State<'_, CanvasIntegrationService>,
     discourse: State<'_, DiscourseIntegrationService>,
     sync_service: State<'_, IntegrationSyncService>,
+    sync_engine: State<'_, Arc<SyncEngine>>,
 ) -> Result<SyncStatus, Error> {
     // Get current connection status
     let canvas_connected = canvas.check_connectivity().await.is_ok();
@@ -78,6 +84,7 @@ pub async fn get_sync_status(
         pending_syncs,
         sync_in_progress,
         sync_errors: errors,
+        clock_drift: sync_engine.clock_drift().await,
     })
 }
 
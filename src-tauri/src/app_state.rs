@@ -14,9 +14,17 @@ use crate::repositories::forum_repository::ForumRepository;
 use crate::modules::quiz::services::QuizService;
 use crate::services::auth::AuthService;
 use crate::services::sync::SyncService;
+use crate::services::sync::maintenance_service::{SyncCleanupConfig, SyncMaintenanceService};
+use crate::sync::engine::SyncEngine;
+use crate::sync::sync_queue::SyncQueue;
 use crate::services::search::SearchService;
 use crate::quiz::cmi5::Cmi5Service;
 use crate::quiz::scorm::ScormService;
+use crate::quiz::lti::LtiService;
+use crate::quiz::lti::outcomes::OutcomeBindingStore;
+use crate::quiz::banks::BankStore;
+use crate::quiz::xapi::{StatementQueue, XApiClient, XApiClientConfig, XApiStatement};
+use crate::quiz::xapi::lrs::LrsCredentials;
 use crate::quiz::ui_controller::UiController;
 use crate::quiz::taking_controller::QuizTakingController;
 
@@ -31,6 +39,7 @@ pub struct AppState {
     pub jwt_secret: Vec<u8>,
     pub data_dir: PathBuf,
     pub is_online: std::sync::atomic::AtomicBool,
+    pub lrs_credentials: LrsCredentials,
 
     // Repositories
     pub quiz_repository: Option<Arc<QuizRepository>>,
@@ -42,9 +51,16 @@ pub struct AppState {
     pub quiz_service: Option<Arc<QuizService>>,
     pub auth_service: Option<Arc<AuthService>>,
     pub sync_service: Option<Arc<SyncService>>,
+    pub sync_engine: Option<Arc<SyncEngine>>,
+    pub sync_maintenance: Option<Arc<SyncMaintenanceService>>,
     pub search_service: Option<Arc<SearchService>>,
     pub cmi5_service: Option<Arc<Cmi5Service>>,
-    pub scorm_service: Option<Arc<Mutex<ScormService>>>,
+    pub scorm_service: Option<Arc<tokio::sync::Mutex<ScormService>>>,
+    pub lti_service: Option<Arc<tokio::sync::Mutex<LtiService>>>,
+    pub lti_outcome_bindings: Option<Arc<OutcomeBindingStore>>,
+    pub question_banks: Option<Arc<BankStore>>,
+    pub xapi_queue: Option<Arc<StatementQueue>>,
+    pub xapi_client: Option<Arc<XApiClient>>,
     pub ui_controller: Arc<Mutex<UiController>>,
     pub quiz_taking_controller: Arc<Mutex<QuizTakingController>>,
 }
@@ -57,6 +73,7 @@ impl AppState {
             jwt_secret,
             data_dir,
             is_online: std::sync::atomic::AtomicBool::new(true),
+            lrs_credentials: LrsCredentials::from_env(),
 
             // Initialize repositories to None
             quiz_repository: None,
@@ -68,9 +85,16 @@ impl AppState {
             quiz_service: None,
             auth_service: None,
             sync_service: None,
+            sync_engine: None,
+            sync_maintenance: None,
             search_service: None,
             cmi5_service: None,
             scorm_service: None,
+            lti_service: None,
+            lti_outcome_bindings: None,
+            question_banks: None,
+            xapi_queue: None,
+            xapi_client: None,
             ui_controller: Arc::new(Mutex::new(UiController::new())),
             quiz_taking_controller: Arc::new(Mutex::new(QuizTakingController::new())),
         }
@@ -90,9 +114,14 @@ impl AppState {
         state = state.with_auth_service();
         state = state.with_quiz_service().await?;
         state = state.with_sync_service();
+        state = state.with_sync_engine().await;
+        state = state.with_sync_maintenance().await;
         state = state.with_search_service();
         state = state.with_cmi5_service()?;
-        state = state.with_scorm_service()?;
+        state = state.with_scorm_service().await?;
+        state = state.with_lti_service().await?;
+        state = state.with_xapi_queue().await?;
+        state = state.with_question_banks().await?;
         state = state.with_ui_controller();
         state = state.with_quiz_taking_controller();
 
@@ -187,6 +216,51 @@ impl AppState {
         self.sync_service.clone().ok_or_else(|| anyhow!("Sync service not initialized"))
     }
 
+    /// Open the sync engine of this device. Without it device sync is off,
+    /// and the rest of the application starts regardless.
+    pub async fn with_sync_engine(mut self) -> Self {
+        match SyncEngine::open(self.db_pool.clone()).await {
+            Ok(engine) => self.sync_engine = Some(Arc::new(engine)),
+            Err(e) => log::error!("Failed to open sync engine, device sync disabled: {}", e),
+        }
+        self
+    }
+
+    pub fn get_sync_engine(&self) -> Result<Arc<SyncEngine>> {
+        self.sync_engine.clone().ok_or_else(|| anyhow!("Sync engine not initialized"))
+    }
+
+    /// Clean up the sync queue and compact the sync engine's operation log
+    /// in the background. Skipped without a sync engine; failing to start it
+    /// does not stop the application either.
+    pub async fn with_sync_maintenance(mut self) -> Self {
+        let Some(engine) = self.sync_engine.clone() else {
+            log::warn!("Sync maintenance disabled: no sync engine");
+            return self;
+        };
+
+        // Space compaction frees is only returned to the file system once
+        // the database is set up for it, which rewrites it the first time
+        if let Err(e) = engine.enable_incremental_vacuum().await {
+            log::warn!("Space freed by sync compaction will not be reclaimed: {}", e);
+        }
+
+        let service = SyncMaintenanceService::new(
+            Arc::new(SyncQueue::new(self.db_pool.clone())),
+            self.db_pool.clone(),
+            SyncCleanupConfig::default(),
+        ).with_sync_engine(engine);
+        match service.start().await {
+            Ok(()) => self.sync_maintenance = Some(Arc::new(service)),
+            Err(e) => log::error!("Failed to start sync maintenance: {}", e),
+        }
+        self
+    }
+
+    pub fn get_sync_maintenance(&self) -> Result<Arc<SyncMaintenanceService>> {
+        self.sync_maintenance.clone().ok_or_else(|| anyhow!("Sync maintenance not initialized"))
+    }
+
     pub fn with_search_service(mut self) -> Self {
         let service = SearchService::new(self.db_pool.clone());
         self.search_service = Some(Arc::new(service));
@@ -197,15 +271,18 @@ impl AppState {
         self.search_service.clone().ok_or_else(|| anyhow!("Search service not initialized"))
     }
 
+    /// cmi5 statements and state go to the embedded LRS, served by the API
+    /// server under `/xapi`
     pub fn with_cmi5_service(mut self) -> Result<Self> {
+        let endpoint = embedded_lrs_endpoint();
         let launch_service = Arc::new(crate::quiz::cmi5::LaunchService::new(
-            "https://example.com/lrs",
-            "https://example.com/lrs/auth"
+            &endpoint,
+            &format!("{}/auth", endpoint)
         ));
 
         let service = Cmi5Service::new(
-            "https://example.com/lrs",
-            Some("test:test"),
+            &endpoint,
+            Some(&self.lrs_credentials.basic()),
             launch_service
         ).map_err(|e| anyhow!("Failed to create cmi5 service: {}", e))?;
 
@@ -217,22 +294,105 @@ impl AppState {
         self.cmi5_service.clone().ok_or_else(|| anyhow!("CMI5 service not initialized"))
     }
 
-    pub fn with_scorm_service(mut self) -> Result<Self> {
+    pub async fn with_scorm_service(mut self) -> Result<Self> {
         let scorm_package_dir = self.data_dir.join("scorm_packages");
         std::fs::create_dir_all(&scorm_package_dir)
             .map_err(|e| anyhow!("Failed to create SCORM package directory: {}", e))?;
 
         let service = ScormService::new(scorm_package_dir)
-            .map_err(|e| anyhow!("Failed to create SCORM service: {}", e))?;
+            .map_err(|e| anyhow!("Failed to create SCORM service: {}", e))?
+            .with_store(self.db_pool.clone())
+            .await
+            .map_err(|e| anyhow!("Failed to load SCORM packages and sessions: {}", e))?;
 
-        self.scorm_service = Some(Arc::new(Mutex::new(service)));
+        self.scorm_service = Some(Arc::new(tokio::sync::Mutex::new(service)));
         Ok(self)
     }
 
-    pub fn get_scorm_service(&self) -> Result<Arc<Mutex<ScormService>>> {
+    pub fn get_scorm_service(&self) -> Result<Arc<tokio::sync::Mutex<ScormService>>> {
         self.scorm_service.clone().ok_or_else(|| anyhow!("SCORM service not initialized"))
     }
 
+    pub async fn with_lti_service(mut self) -> Result<Self> {
+        let outcome_bindings = OutcomeBindingStore::new(self.db_pool.clone())
+            .await
+            .map_err(|e| anyhow!("Failed to create LTI outcome binding store: {}", e))?;
+
+        // The launch URL registered with LTI 1.3 platforms is deployment specific
+        let redirect_uri = std::env::var("LTI_REDIRECT_URI").ok();
+        let service = LtiService::configured(&self.db_pool, redirect_uri.as_deref())
+            .await
+            .map_err(|e| anyhow!("Failed to configure LTI service: {}", e))?;
+
+        self.lti_service = Some(Arc::new(tokio::sync::Mutex::new(service)));
+        self.lti_outcome_bindings = Some(Arc::new(outcome_bindings));
+        Ok(self)
+    }
+
+    pub fn get_lti_service(&self) -> Result<Arc<tokio::sync::Mutex<LtiService>>> {
+        self.lti_service.clone().ok_or_else(|| anyhow!("LTI service not initialized"))
+    }
+
+    pub fn get_lti_outcome_bindings(&self) -> Result<Arc<OutcomeBindingStore>> {
+        self.lti_outcome_bindings.clone().ok_or_else(|| anyhow!("LTI outcome bindings not initialized"))
+    }
+
+    pub async fn with_xapi_queue(mut self) -> Result<Self> {
+        let queue = StatementQueue::new(self.db_pool.clone())
+            .await
+            .map_err(|e| anyhow!("Failed to create xAPI statement queue: {}", e))?;
+
+        // Statements are always queued, and forwarded in the background when
+        // an LRS is configured; until then they wait in the queue
+        if let (Ok(endpoint), Ok(username), Ok(password)) = (
+            std::env::var("XAPI_ENDPOINT"),
+            std::env::var("XAPI_USERNAME"),
+            std::env::var("XAPI_PASSWORD"),
+        ) {
+            let client = XApiClient::new(XApiClientConfig {
+                endpoint,
+                username,
+                password,
+                version: std::env::var("XAPI_VERSION").unwrap_or_else(|_| "1.0.3".to_string()),
+            })
+            .map_err(|e| anyhow!("Failed to create xAPI client: {}", e))?;
+            let client = Arc::new(client);
+
+            crate::quiz::xapi::queue::spawn_flush_loop(queue.clone(), client.clone(), std::time::Duration::from_secs(60));
+            self.xapi_client = Some(client);
+        }
+
+        self.xapi_queue = Some(Arc::new(queue));
+        Ok(self)
+    }
+
+    /// Queue a statement for the LRS; until one is configured it waits in the
+    /// queue
+    pub async fn track_xapi(&self, statement: &XApiStatement) -> Result<()> {
+        self.get_xapi_queue()?
+            .enqueue(statement)
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow!("Failed to queue xAPI statement: {}", e))
+    }
+
+    pub fn get_xapi_queue(&self) -> Result<Arc<StatementQueue>> {
+        self.xapi_queue.clone().ok_or_else(|| anyhow!("xAPI statement queue not initialized"))
+    }
+
+    pub async fn with_question_banks(mut self) -> Result<Self> {
+        let banks = BankStore::new(self.db_pool.clone())
+            .await
+            .map_err(|e| anyhow!("Failed to create question bank store: {}", e))?;
+
+        self.question_banks = Some(Arc::new(banks));
+        Ok(self)
+    }
+
+    pub fn get_question_banks(&self) -> Result<Arc<BankStore>> {
+        self.question_banks.clone().ok_or_else(|| anyhow!("Question bank store not initialized"))
+    }
+
     pub fn with_ui_controller(self) -> Self {
         // UI controller is already initialized in new()
         self
@@ -251,3 +411,19 @@ impl AppState {
         self.quiz_taking_controller.clone()
     }
 }
+
+/// URL of the embedded LRS, at the address the API server listens on; a
+/// server listening on every interface is reached through the loopback one
+fn embedded_lrs_endpoint() -> String {
+    let addr = std::env::var("SERVER_ADDR")
+        .ok()
+        .and_then(|addr| addr.parse::<std::net::SocketAddr>().ok())
+        .unwrap_or_else(|| std::net::SocketAddr::from(([127, 0, 0, 1], 3000)));
+    let addr = if addr.ip().is_unspecified() {
+        std::net::SocketAddr::new(std::net::Ipv4Addr::LOCALHOST.into(), addr.port())
+    } else {
+        addr
+    };
+
+    format!("http://{}/xapi", addr)
+}
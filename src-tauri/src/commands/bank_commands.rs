@@ -0,0 +1,150 @@
+use crate::quiz::banks::{QuestionBank, QuestionGroup};
+use crate::quiz::QuizEngine;
+use crate::AppState;
+use chrono::Utc;
+use tauri::State;
+use uuid::Uuid;
+
+/// Banks hold questions with their answers, so only instructors see and
+/// change them
+async fn bank_manager(engine: &QuizEngine, user_id: &str) -> Result<Uuid, String> {
+    let user_id = Uuid::parse_str(user_id).map_err(|e| e.to_string())?;
+
+    if !engine.can_manage_question_banks(user_id).await.map_err(|e| e.to_string())? {
+        return Err("Permission denied: only instructors may manage question banks".to_string());
+    }
+    Ok(user_id)
+}
+
+/// Groups change which questions a quiz draws, so only its editors see and
+/// change them
+async fn group_manager(engine: &QuizEngine, user_id: &str, quiz_id: &str) -> Result<(), String> {
+    let user_id = Uuid::parse_str(user_id).map_err(|e| e.to_string())?;
+    let quiz_id = Uuid::parse_str(quiz_id).map_err(|e| e.to_string())?;
+
+    if !engine.can_manage_question_groups(user_id, quiz_id).await.map_err(|e| e.to_string())? {
+        return Err("Permission denied: only those who may edit the quiz may manage its question groups".to_string());
+    }
+    Ok(())
+}
+
+/// Create or update a question bank; banks of another author are not changed
+#[tauri::command]
+pub async fn save_question_bank(
+    state: State<'_, AppState>,
+    engine: State<'_, QuizEngine>,
+    user_id: String,
+    mut bank: QuestionBank,
+) -> Result<QuestionBank, String> {
+    let user_id = bank_manager(&engine, &user_id).await?;
+    let banks = state.get_question_banks().map_err(|e| e.to_string())?;
+
+    if let Ok(existing) = banks.get_bank(bank.id).await {
+        if existing.author_id.is_some_and(|author_id| author_id != user_id) {
+            return Err("Permission denied: the question bank belongs to another author".to_string());
+        }
+    }
+
+    bank.author_id.get_or_insert(user_id);
+    bank.updated_at = Utc::now();
+    banks.save_bank(&bank).await.map_err(|e| e.to_string())?;
+
+    Ok(bank)
+}
+
+#[tauri::command]
+pub async fn get_question_bank(
+    state: State<'_, AppState>,
+    engine: State<'_, QuizEngine>,
+    user_id: String,
+    bank_id: String,
+) -> Result<QuestionBank, String> {
+    bank_manager(&engine, &user_id).await?;
+    let banks = state.get_question_banks().map_err(|e| e.to_string())?;
+    let bank_id = Uuid::parse_str(&bank_id).map_err(|e| e.to_string())?;
+
+    banks.get_bank(bank_id).await.map_err(|e| e.to_string())
+}
+
+/// List the banks a user may draw from
+#[tauri::command]
+pub async fn list_question_banks(
+    state: State<'_, AppState>,
+    engine: State<'_, QuizEngine>,
+    user_id: String,
+    course_ids: Vec<String>,
+    tag: Option<String>,
+) -> Result<Vec<QuestionBank>, String> {
+    let user_id = bank_manager(&engine, &user_id).await?;
+    let banks = state.get_question_banks().map_err(|e| e.to_string())?;
+    let course_ids = course_ids.iter()
+        .map(|id| Uuid::parse_str(id))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    banks.list_banks(user_id, &course_ids, tag.as_deref()).await.map_err(|e| e.to_string())
+}
+
+/// Delete a question bank; banks of another author are not deleted
+#[tauri::command]
+pub async fn delete_question_bank(
+    state: State<'_, AppState>,
+    engine: State<'_, QuizEngine>,
+    user_id: String,
+    bank_id: String,
+) -> Result<(), String> {
+    let user_id = bank_manager(&engine, &user_id).await?;
+    let banks = state.get_question_banks().map_err(|e| e.to_string())?;
+    let bank_id = Uuid::parse_str(&bank_id).map_err(|e| e.to_string())?;
+
+    let bank = banks.get_bank(bank_id).await.map_err(|e| e.to_string())?;
+    if bank.author_id.is_some_and(|author_id| author_id != user_id) {
+        return Err("Permission denied: the question bank belongs to another author".to_string());
+    }
+
+    banks.delete_bank(bank_id).await.map_err(|e| e.to_string())
+}
+
+/// Add or update a group that draws questions from a bank into a quiz
+#[tauri::command]
+pub async fn save_question_group(
+    state: State<'_, AppState>,
+    engine: State<'_, QuizEngine>,
+    user_id: String,
+    group: QuestionGroup,
+) -> Result<(), String> {
+    group_manager(&engine, &user_id, &group.quiz_id).await?;
+    let banks = state.get_question_banks().map_err(|e| e.to_string())?;
+
+    // Fail early rather than when an attempt starts
+    banks.get_bank(group.bank_id).await.map_err(|e| e.to_string())?;
+    banks.save_group(&group).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_question_group(
+    state: State<'_, AppState>,
+    engine: State<'_, QuizEngine>,
+    user_id: String,
+    quiz_id: String,
+    group_id: String,
+) -> Result<(), String> {
+    group_manager(&engine, &user_id, &quiz_id).await?;
+    let banks = state.get_question_banks().map_err(|e| e.to_string())?;
+    let group_id = Uuid::parse_str(&group_id).map_err(|e| e.to_string())?;
+
+    banks.delete_group(&quiz_id, group_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_quiz_question_groups(
+    state: State<'_, AppState>,
+    engine: State<'_, QuizEngine>,
+    user_id: String,
+    quiz_id: String,
+) -> Result<Vec<QuestionGroup>, String> {
+    group_manager(&engine, &user_id, &quiz_id).await?;
+    let banks = state.get_question_banks().map_err(|e| e.to_string())?;
+
+    banks.groups_for_quiz(&quiz_id).await.map_err(|e| e.to_string())
+}
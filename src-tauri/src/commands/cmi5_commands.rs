@@ -1,4 +1,4 @@
-use crate::quiz::cmi5::{Cmi5Service, LaunchMode, Cmi5Score};
+use crate::quiz::cmi5::{Cmi5Service, Cmi5Launch, LaunchMode, Cmi5Score};
 use crate::AppState;
 use tauri::State;
 use std::path::PathBuf;
@@ -90,7 +90,7 @@ pub async fn launch_cmi5_assignable_unit(
     course_id: String,
     au_id: String,
     actor_id: String,
-) -> Result<String, String> {
+) -> Result<Cmi5Launch, String> {
     let cmi5_service = state.cmi5_service.clone();
     
     cmi5_service.launch_assignable_unit(
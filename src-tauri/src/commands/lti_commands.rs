@@ -0,0 +1,106 @@
+use crate::quiz::lti::{LtiLaunchRequest, LtiPlatformConfig};
+use crate::quiz::lti::platforms::PlatformStore;
+use crate::quiz::lti::roster::{RosterMember, RosterStore};
+use crate::AppState;
+use tauri::State;
+use uuid::Uuid;
+
+/// Register an LTI platform, or update its configuration
+#[tauri::command]
+pub async fn save_lti_platform(
+    state: State<'_, AppState>,
+    platform: LtiPlatformConfig,
+) -> Result<(), String> {
+    let lti_service = state.get_lti_service().map_err(|e| e.to_string())?;
+
+    let store = PlatformStore::new(state.db_pool.clone()).await.map_err(|e| e.to_string())?;
+    store.save(&platform).await.map_err(|e| e.to_string())?;
+
+    lti_service.lock().await.add_platform(platform);
+    Ok(())
+}
+
+/// List the registered LTI platforms
+#[tauri::command]
+pub async fn list_lti_platforms(state: State<'_, AppState>) -> Result<Vec<LtiPlatformConfig>, String> {
+    let store = PlatformStore::new(state.db_pool.clone()).await.map_err(|e| e.to_string())?;
+    store.list().await.map_err(|e| e.to_string())
+}
+
+/// Remove a registered LTI platform
+#[tauri::command]
+pub async fn remove_lti_platform(
+    state: State<'_, AppState>,
+    platform_id: String,
+) -> Result<bool, String> {
+    let platform_id = Uuid::parse_str(&platform_id).map_err(|e| e.to_string())?;
+    let lti_service = state.get_lti_service().map_err(|e| e.to_string())?;
+
+    let store = PlatformStore::new(state.db_pool.clone()).await.map_err(|e| e.to_string())?;
+    let removed = store.remove(&platform_id).await.map_err(|e| e.to_string())?;
+
+    lti_service.lock().await.remove_platform(&platform_id);
+    Ok(removed)
+}
+
+/// Remember where the grade of an LTI-launched quiz has to be sent
+///
+/// Returns false if the launch carries no outcome or AGS service.
+#[tauri::command]
+pub async fn record_lti_launch(
+    state: State<'_, AppState>,
+    launch: LtiLaunchRequest,
+    quiz_id: String,
+    user_id: String,
+) -> Result<bool, String> {
+    let lti_service = state.get_lti_service().map_err(|e| e.to_string())?;
+    let bindings = state.get_lti_outcome_bindings().map_err(|e| e.to_string())?;
+
+    let binding = {
+        let lti_service = lti_service.lock().await;
+        lti_service.outcome_binding_for_launch(&launch, &quiz_id, &user_id)
+    };
+
+    match binding {
+        Some(binding) => {
+            bindings.save(&binding).await.map_err(|e| e.to_string())?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Import a course roster from an LTI 1.3 platform through NRPS
+#[tauri::command]
+pub async fn import_lti_roster(
+    state: State<'_, AppState>,
+    platform_id: String,
+    context_id: String,
+    context_memberships_url: String,
+) -> Result<usize, String> {
+    let platform_id = Uuid::parse_str(&platform_id).map_err(|e| e.to_string())?;
+    let lti_service = state.get_lti_service().map_err(|e| e.to_string())?;
+
+    let members = {
+        let lti_service = lti_service.lock().await;
+        lti_service.fetch_roster(&platform_id, &context_memberships_url)
+            .await
+            .map_err(|e| e.to_string())?
+    };
+
+    let store = RosterStore::new(state.db_pool.clone()).await.map_err(|e| e.to_string())?;
+    store.import(&platform_id, &context_id, &members).await.map_err(|e| e.to_string())
+}
+
+/// Get the imported roster of an LTI context
+#[tauri::command]
+pub async fn get_lti_roster(
+    state: State<'_, AppState>,
+    platform_id: String,
+    context_id: String,
+) -> Result<Vec<RosterMember>, String> {
+    let platform_id = Uuid::parse_str(&platform_id).map_err(|e| e.to_string())?;
+
+    let store = RosterStore::new(state.db_pool.clone()).await.map_err(|e| e.to_string())?;
+    store.list(&platform_id, &context_id).await.map_err(|e| e.to_string())
+}
@@ -2,6 +2,9 @@
 pub mod integration_commands;
 pub mod cmi5_commands;
 pub mod scorm_commands;
+pub mod lti_commands;
+pub mod bank_commands;
+pub mod xapi_commands;
 pub mod quenti_commands; // Kept for backward compatibility
 pub mod ordo_quiz_commands;
 pub mod migration_commands;
@@ -10,6 +13,9 @@ pub mod migration_commands;
 pub use integration_commands::*;
 pub use cmi5_commands::*;
 pub use scorm_commands::*;
+pub use lti_commands::*;
+pub use bank_commands::*;
+pub use xapi_commands::*;
 pub use quenti_commands::*; // Kept for backward compatibility
 pub use ordo_quiz_commands::*;
 pub use migration_commands::*;
@@ -1,10 +1,9 @@
 use crate::AppState;
+use crate::quiz::scorm::{NavigationRequest, SequencingOutcome};
 use tauri::State;
 use std::path::PathBuf;
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
-use std::sync::Arc;
-use tokio::sync::Mutex;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ScormPackageInfo {
@@ -33,12 +32,13 @@ pub async fn import_scorm_package(
     state: State<'_, AppState>,
     package_path: String,
 ) -> Result<String, String> {
-    let scorm_service = state.scorm_service.clone();
+    let scorm_service = state.get_scorm_service().map_err(|e| e.to_string())?;
     let path = PathBuf::from(package_path);
     
     let mut service = scorm_service.lock().await;
     
     let package_id = service.import_package(&path)
+        .await
         .map_err(|e| e.to_string())?;
     
     Ok(package_id.to_string())
@@ -49,7 +49,7 @@ pub async fn import_scorm_package(
 pub async fn get_scorm_packages(
     state: State<'_, AppState>,
 ) -> Result<Vec<ScormPackageInfo>, String> {
-    let scorm_service = state.scorm_service.clone();
+    let scorm_service = state.get_scorm_service().map_err(|e| e.to_string())?;
     let service = scorm_service.lock().await;
     
     let packages = service.get_packages();
@@ -69,14 +69,14 @@ pub async fn get_scorm_packages(
     Ok(package_infos)
 }
 
-/// Launch a SCORM package
+/// Launch a SCORM package, resuming the user's unfinished session if any
 #[tauri::command]
 pub async fn launch_scorm_package(
     state: State<'_, AppState>,
     package_id: String,
     user_id: String,
 ) -> Result<String, String> {
-    let scorm_service = state.scorm_service.clone();
+    let scorm_service = state.get_scorm_service().map_err(|e| e.to_string())?;
     let mut service = scorm_service.lock().await;
     
     let package_uuid = Uuid::parse_str(&package_id)
@@ -85,7 +85,8 @@ pub async fn launch_scorm_package(
     let user_uuid = Uuid::parse_str(&user_id)
         .map_err(|e| format!("Invalid user ID: {}", e))?;
     
-    let session_id = service.create_session(&package_uuid, &user_uuid)
+    service.launch_session(&package_uuid, &user_uuid)
+        .await
         .map_err(|e| e.to_string())?;
     
     let launch_url = service.get_launch_url(&package_uuid)
@@ -100,7 +101,7 @@ pub async fn get_scorm_user_sessions(
     state: State<'_, AppState>,
     user_id: String,
 ) -> Result<Vec<ScormSessionInfo>, String> {
-    let scorm_service = state.scorm_service.clone();
+    let scorm_service = state.get_scorm_service().map_err(|e| e.to_string())?;
     let service = scorm_service.lock().await;
     
     let user_uuid = Uuid::parse_str(&user_id)
@@ -134,7 +135,7 @@ pub async fn handle_scorm_api_call(
     function: String,
     args: Vec<String>,
 ) -> Result<String, String> {
-    let scorm_service = state.scorm_service.clone();
+    let scorm_service = state.get_scorm_service().map_err(|e| e.to_string())?;
     let mut service = scorm_service.lock().await;
     
     let session_uuid = Uuid::parse_str(&session_id)
@@ -143,7 +144,38 @@ pub async fn handle_scorm_api_call(
     let args_str: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
     
     let result = service.handle_api_call(&session_uuid, &function, &args_str)
+        .await
         .map_err(|e| e.to_string())?;
     
     Ok(result)
 }
+
+/// Process a SCORM 2004 navigation request (e.g. `continue`, `previous`,
+/// `{target=item_2}choice`, `exitAll`) and return the launch URL of the
+/// activity to deliver, if any
+#[tauri::command]
+pub async fn navigate_scorm_session(
+    state: State<'_, AppState>,
+    session_id: String,
+    request: String,
+) -> Result<Option<String>, String> {
+    let scorm_service = state.get_scorm_service().map_err(|e| e.to_string())?;
+    let mut service = scorm_service.lock().await;
+    
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+    
+    let request = NavigationRequest::parse(&request)
+        .ok_or_else(|| format!("Invalid navigation request: {}", request))?;
+    
+    let package_id = service.get_session(&session_uuid)
+        .map(|session| session.package_id)
+        .ok_or_else(|| "Session not found".to_string())?;
+    
+    match service.navigate(&session_uuid, request).await.map_err(|e| e.to_string())? {
+        SequencingOutcome::Deliver(activity_id) => service.get_activity_launch_url(&package_id, &activity_id)
+            .map(Some)
+            .map_err(|e| e.to_string()),
+        SequencingOutcome::EndSession | SequencingOutcome::Idle => Ok(None),
+    }
+}
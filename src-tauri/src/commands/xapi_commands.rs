@@ -0,0 +1,12 @@
+use crate::quiz::xapi::XApiQueueStatus;
+use crate::AppState;
+use tauri::State;
+
+/// Get the status of the outbound xAPI statement queue
+#[tauri::command]
+pub async fn get_xapi_queue_status(
+    state: State<'_, AppState>,
+) -> Result<XApiQueueStatus, String> {
+    let queue = state.get_xapi_queue().map_err(|e| e.to_string())?;
+    queue.status().await.map_err(|e| e.to_string())
+}
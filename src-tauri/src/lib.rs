@@ -19,6 +19,18 @@ pub mod core {
     // Add other core modules as needed
 }
 
+pub mod sync {
+    pub mod operations;
+    pub mod conflicts;
+    pub mod engine;
+    pub mod version_vector;
+    pub mod protocol;
+    pub mod crdt;
+    pub mod hlc;
+    pub mod review;
+    pub mod compaction;
+}
+
 pub mod database {
     pub mod repositories {
         pub mod user;
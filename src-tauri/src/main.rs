@@ -44,6 +44,7 @@ use rusqlite::Connection;
 use tokio::sync::Mutex;
 use tauri::Manager;
 use tower_http::cors::{Any, CorsLayer};
+use crate::sync::engine::SyncEngine;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 // Import from lms_lib for our handlers to use
@@ -405,6 +406,41 @@ pub fn run() {
                 discourse_service.clone(),
             ));
 
+            // Open the offline sync engine under this device's stored ID and
+            // answer paired devices syncing over the LAN; without it, device
+            // sync stays off and the rest of the app starts regardless
+            match rt.block_on(SyncEngine::open(db.clone())) {
+                Ok(sync_engine) => {
+                    let sync_engine = Arc::new(sync_engine);
+
+                    // Other machines reach the listener only once the user
+                    // turned LAN sync on; until then it answers on this one
+                    let lan_sync = rt.block_on(sync_engine.lan_sync_enabled()).unwrap_or_else(|e| {
+                        log::warn!("Failed to read the LAN sync setting, keeping it off: {}", e);
+                        false
+                    });
+                    let lan_addr = if lan_sync {
+                        std::env::var("SYNC_LAN_ADDR").unwrap_or_else(|_| "0.0.0.0:47800".to_string())
+                    } else {
+                        "127.0.0.1:47800".to_string()
+                    };
+                    let lan_engine = sync_engine.clone();
+                    rt.spawn(async move {
+                        match tokio::net::TcpListener::bind(&lan_addr).await {
+                            Ok(listener) => {
+                                log::info!("Listening for LAN sync on {}", lan_addr);
+                                if let Err(e) = sync::protocol::tcp::serve(listener, lan_engine).await {
+                                    log::error!("LAN sync listener stopped: {}", e);
+                                }
+                            }
+                            Err(e) => log::warn!("LAN sync disabled, failed to bind {}: {}", lan_addr, e),
+                        }
+                    });
+                    app.manage(sync_engine);
+                }
+                Err(e) => log::error!("Failed to open sync engine, device sync disabled: {}", e),
+            }
+
             // Create and start the batch sync service
             let batch_sync_service = BatchSyncService::new(
                 db.clone(),
@@ -578,6 +614,17 @@ pub fn run() {
             get_sync_history,
             get_sync_history_stats,
 
+            // Sync conflict review commands
+            sync::commands::list_conflicts,
+            sync::commands::get_conflict_diff,
+            sync::commands::resolve_conflict,
+
+            // Device sync commands
+            sync::commands::create_sync_pairing,
+            sync::commands::set_lan_sync,
+            sync::commands::sync_with_peer,
+            sync::commands::sync_with_hub,
+
             // Discussion topic commands
             list_topics,
             get_topic,
@@ -590,14 +637,22 @@ pub fn run() {
             get_recent_topics,
             get_active_topics,
 
+            // Quiz code execution commands
+            quiz::commands::run_code_question,
+
             // Quiz flashcard commands
             quiz::commands::rate_flashcard,
             quiz::commands::create_flashcard_session,
             quiz::commands::get_flashcard_stats,
+            quiz::commands::get_deck_scheduler_settings,
+            quiz::commands::set_deck_scheduler_settings,
+            quiz::commands::optimize_deck_scheduler,
 
             // Quiz analytics commands
             quiz::commands::get_user_stats,
             quiz::commands::get_quiz_analytics,
+            quiz::commands::get_item_analysis,
+            quiz::commands::export_item_analysis_csv,
             quiz::commands::generate_user_report,
             quiz::commands::generate_quiz_report,
 
@@ -605,8 +660,20 @@ pub fn run() {
             quiz::commands::export_quiz,
             quiz::commands::export_quiz_to_file,
             quiz::commands::export_quiz_with_options,
+            quiz::commands::export_quiz_qti,
             quiz::commands::import_quiz_from_file,
             quiz::commands::import_quiz,
+            quiz::commands::import_quiz_qti,
+            quiz::commands::import_anki_package,
+
+            // Timed exam commands
+            quiz::commands::start_timed_exam,
+            quiz::commands::resume_timed_exam,
+            quiz::commands::resume_timed_exams,
+            quiz::commands::autosave_exam_answers,
+            quiz::commands::submit_timed_exam,
+            quiz::commands::grant_time_accommodation,
+            quiz::commands::get_exam_events,
 
             // Quiz course integration commands
             quiz::commands::add_quiz_to_course,
@@ -655,6 +722,9 @@ pub fn run() {
             quiz::template_commands::create_template,
             quiz::template_commands::create_template_from_quiz,
             quiz::template_commands::add_question_template,
+            quiz::template_commands::add_formula_question_template,
+            quiz::template_commands::generate_formula_question,
+            quiz::template_commands::grade_formula_answer,
             quiz::template_commands::update_template,
             quiz::template_commands::delete_template,
             quiz::template_commands::get_template,
@@ -714,6 +784,27 @@ pub fn run() {
             commands::scorm_commands::launch_scorm_package,
             commands::scorm_commands::get_scorm_user_sessions,
             commands::scorm_commands::handle_scorm_api_call,
+            commands::scorm_commands::navigate_scorm_session,
+
+            // LTI commands
+            commands::lti_commands::save_lti_platform,
+            commands::lti_commands::list_lti_platforms,
+            commands::lti_commands::remove_lti_platform,
+            commands::lti_commands::record_lti_launch,
+            commands::lti_commands::import_lti_roster,
+            commands::lti_commands::get_lti_roster,
+
+            // Question bank commands
+            commands::bank_commands::save_question_bank,
+            commands::bank_commands::get_question_bank,
+            commands::bank_commands::list_question_banks,
+            commands::bank_commands::delete_question_bank,
+            commands::bank_commands::save_question_group,
+            commands::bank_commands::delete_question_group,
+            commands::bank_commands::get_quiz_question_groups,
+
+            // xAPI commands
+            commands::xapi_commands::get_xapi_queue_status,
 
             // Ordo Quiz commands
             commands::ordo_quiz_commands::launch_ordo_quiz_app,
@@ -878,18 +969,24 @@ async fn main() {
     let assignment_repo = Arc::new(AssignmentRepository::new(db_pool.clone()));
     let course_category_repo = CourseCategoryRepository::new(db_pool.clone());
 
-    // Set up sync engine
-    let sync_engine = Arc::new(SyncEngine::new(db_pool.clone()));
-
-    // Initialize sync engine
-    sync_engine.initialize().await.expect("Failed to initialize sync engine");
+    // Set up sync engine, under this device's stored ID; without it, device
+    // sync stays off and the server starts regardless
+    let sync_engine = match SyncEngine::open(db_pool.clone()).await {
+        Ok(sync_engine) => Some(Arc::new(sync_engine)),
+        Err(e) => {
+            tracing::error!("Failed to open sync engine, device sync disabled: {}", e);
+            None
+        }
+    };
 
     // Set up sync service
-    let sync_service = SyncService::new(
-        sync_engine.clone(),
-        config.sync.sync_endpoint.clone(),
-        config.sync.sync_interval,
-    );
+    let sync_service = sync_engine.clone().map(|sync_engine| {
+        SyncService::new(
+            sync_engine,
+            config.sync.sync_endpoint.clone(),
+            config.sync.sync_interval,
+        )
+    });
 
     // Set up authentication service
     let auth_service = Arc::new(AuthService::new(
@@ -916,8 +1013,11 @@ async fn main() {
         )
         .layer(cors)
         .layer(Extension(auth_service))
-        .layer(Extension(sync_engine))
         .layer(Extension(db_pool));
+    let app = match sync_engine {
+        Some(sync_engine) => app.layer(Extension(sync_engine)),
+        None => app,
+    };
 
     // Run the server
     let addr = SocketAddr::from(([127, 0, 0, 1], config.server.port));
@@ -1302,10 +1402,34 @@ async fn main() {
     });
 
     // Create API router with unified AppState
-    let app = api::api_router().with_state(app_state.clone());
+    let mut app = api::api_router();
 
-    // Start server
-    let addr = "127.0.0.1:3000".parse().expect("Invalid server address");
+    // LTI platforms redirect the user's browser to the tool's login and
+    // launch endpoints, so they are served next to the API
+    match app_state.get_lti_service() {
+        Ok(lti_service) => app = app.nest("/lti", routes::lti::create_routes(lti_service)),
+        Err(e) => log::warn!("LTI endpoints disabled: {}", e),
+    }
+
+    // The embedded LRS, so that xAPI and cmi5 content are tracked offline
+    match crate::quiz::xapi::lrs::LocalLrs::new(pool.clone()).await {
+        Ok(lrs) => app = app.nest("/xapi", routes::xapi::create_routes(lrs, app_state.lrs_credentials.clone())),
+        Err(e) => log::warn!("xAPI LRS endpoints disabled: {}", e),
+    }
+
+    // Devices sync with the hub through it
+    match app_state.get_sync_engine() {
+        Ok(engine) => app = app.nest("/api/sync", api::sync::sync_routes(engine)),
+        Err(e) => log::warn!("Sync hub endpoints disabled: {}", e),
+    }
+    let app = app.with_state(app_state.clone());
+
+    // Start server; LTI platforms have to reach it, so it can be bound to
+    // another address than the loopback one
+    let addr = std::env::var("SERVER_ADDR")
+        .unwrap_or_else(|_| "127.0.0.1:3000".to_string())
+        .parse()
+        .expect("Invalid server address");
     info!("Starting server on {}", addr);
 
     // Ensure Meilisearch is properly stopped when the app exits
@@ -1,3 +1,5 @@
+pub mod item_analysis;
+
 use super::models::{Quiz, Question, Answer, QuizAttempt, FlashcardData};
 use super::storage::HybridQuizStore;
 use super::spaced_repetition::{FlashcardStatistics};
@@ -325,6 +327,25 @@ impl AnalyticsEngine {
         })
     }
     
+    /// Get item analysis statistics of a quiz from its completed attempts
+    pub async fn get_item_analysis(&self, quiz_id: Uuid, period: TimePeriod) -> Result<item_analysis::ItemAnalysis, Box<dyn Error + Send + Sync>> {
+        let (start_date, end_date) = self.get_time_range(period);
+        let quiz = self.store.get_quiz(quiz_id).await?;
+
+        let mut attempts = self.store.get_quiz_attempts(quiz_id, start_date, end_date).await?;
+        attempts.retain(|attempt| attempt.completed_at.is_some());
+        for attempt in &mut attempts {
+            attempt.answers = self.store.get_attempt_answers(attempt.id).await?;
+        }
+
+        Ok(item_analysis::analyze(&quiz, &attempts))
+    }
+
+    /// Export a quiz's item analysis as CSV
+    pub async fn export_item_analysis_csv(&self, quiz_id: Uuid, period: TimePeriod) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(self.get_item_analysis(quiz_id, period).await?.to_csv())
+    }
+    
     /// Calculate study streak (consecutive days of study)
     async fn calculate_study_streak(&self, user_id: Uuid) -> Result<i32, Box<dyn Error + Send + Sync>> {
         // Get all study dates for the user
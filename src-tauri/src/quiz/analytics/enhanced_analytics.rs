@@ -5,6 +5,7 @@ use std::collections::HashMap;
 use anyhow::{Result, anyhow};
 use crate::quiz::models::{Quiz, Question, Answer, QuizAttempt};
 use crate::quiz::analytics::AnalyticsService;
+use crate::quiz::analytics::item_analysis::{self, ItemAnalysis};
 use std::sync::Arc;
 
 /// Enhanced analytics service
@@ -364,6 +365,7 @@ impl EnhancedAnalyticsService {
         
         // Calculate question performance
         let mut question_performance = Vec::new();
+        let item_analysis = item_analysis::analyze(&quiz, &attempts);
         
         for question in &quiz.questions {
             // Get all answers for this question
@@ -409,10 +411,11 @@ impl EnhancedAnalyticsService {
                 *answer_distribution.entry(answer_key).or_insert(0) += 1;
             }
             
-            // Calculate discrimination index
-            // This is a more complex calculation that requires sorting attempts by score
-            // and comparing performance on this question between high and low scorers
-            let discrimination_index = None; // Simplified for now
+            // Upper/lower 27% discrimination index from the item analysis
+            let discrimination_index = item_analysis.items.iter()
+                .find(|item| item.question_id == question.id)
+                .and_then(|item| item.discrimination_index)
+                .map(|index| index as f32);
             
             question_performance.push(QuestionPerformanceMetrics {
                 question_id: question.id,
@@ -441,6 +444,20 @@ impl EnhancedAnalyticsService {
         })
     }
     
+    /// Get item analysis statistics: per question difficulty, discrimination
+    /// and distractor selection, and the quiz's reliability
+    pub async fn get_item_analysis(&self, quiz_id: &Uuid) -> Result<ItemAnalysis> {
+        let quiz = self.analytics.get_quiz(quiz_id).await?;
+        let attempts = self.analytics.get_quiz_attempts(quiz_id).await?;
+        
+        Ok(item_analysis::analyze(&quiz, &attempts))
+    }
+    
+    /// Export item analysis statistics as CSV
+    pub async fn export_item_analysis_csv(&self, quiz_id: &Uuid) -> Result<String> {
+        Ok(self.get_item_analysis(quiz_id).await?.to_csv())
+    }
+    
     /// Get user performance metrics
     pub async fn get_user_performance_metrics(&self, user_id: &Uuid) -> Result<UserPerformanceMetrics> {
         // Get all attempts for this user
@@ -0,0 +1,374 @@
+// Item analysis
+//
+// Classical test theory statistics of a quiz, computed from its completed
+// attempts. Each question is scored 1 when answered correctly and 0 when
+// answered wrongly or left unanswered, and an attempt's total is the number
+// of questions it got right. Questions with answers still waiting for manual
+// review are left out, as their scores are not known yet.
+//
+// Per question this gives the difficulty (p-value, the share of attempts
+// that got it right), the point-biserial correlation between the question
+// and the rest of the quiz, and the discrimination index: the p-value among
+// the top 27% of attempts by total minus the p-value among the bottom 27%.
+// For choice questions, how often each option was picked, overall and in
+// both groups, shows which distractors do their job. Per quiz it gives
+// Cronbach's alpha, which for right/wrong scoring is KR-20, and the standard
+// error of measurement.
+
+#[cfg(test)]
+mod tests;
+
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::quiz::models::{Answer, Question, Quiz, QuizAttempt};
+
+/// Share of attempts in each of the upper and lower groups
+pub const GROUP_FRACTION: f64 = 0.27;
+
+/// Questions more attempts than this got right tell students apart poorly
+pub const TOO_EASY_P_VALUE: f64 = 0.9;
+
+/// Questions fewer attempts than this got right may be flawed
+pub const TOO_HARD_P_VALUE: f64 = 0.2;
+
+/// Point-biserial correlation below which a question discriminates poorly
+pub const LOW_DISCRIMINATION: f64 = 0.2;
+
+/// Distractors picked by fewer attempts than this are not doing their job
+pub const NONFUNCTIONING_DISTRACTOR_RATE: f64 = 0.05;
+
+/// Something an instructor should look at when revising a question
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ItemFlag {
+    TooEasy,
+    TooHard,
+    LowDiscrimination,
+    /// Students doing worse on the rest of the quiz did better on this
+    /// question, which is often a wrong answer key
+    NegativeDiscrimination,
+    /// A distractor almost nobody picks
+    NonfunctioningDistractor,
+    /// A distractor the upper group picks more than the lower group
+    AttractiveDistractor,
+}
+
+impl ItemFlag {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ItemFlag::TooEasy => "too_easy",
+            ItemFlag::TooHard => "too_hard",
+            ItemFlag::LowDiscrimination => "low_discrimination",
+            ItemFlag::NegativeDiscrimination => "negative_discrimination",
+            ItemFlag::NonfunctioningDistractor => "nonfunctioning_distractor",
+            ItemFlag::AttractiveDistractor => "attractive_distractor",
+        }
+    }
+}
+
+/// How often an option of a choice question was picked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OptionAnalysis {
+    pub choice_id: Uuid,
+    pub text: String,
+    pub is_correct: bool,
+    pub selected: usize,
+    /// Share of all attempts that picked it
+    pub selection_rate: f64,
+    pub upper_rate: f64,
+    pub lower_rate: f64,
+}
+
+/// Statistics of one question
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemStatistics {
+    pub question_id: Uuid,
+    pub question_text: String,
+    pub answered: usize,
+    /// Share of attempts that left it unanswered
+    pub omit_rate: f64,
+    /// Share of attempts that got it right
+    pub p_value: f64,
+    /// Correlation between the question and the total of the other
+    /// questions; `None` when either does not vary
+    pub point_biserial: Option<f64>,
+    /// Upper group p-value minus lower group p-value; `None` with too few
+    /// attempts to form both groups
+    pub discrimination_index: Option<f64>,
+    pub upper_p_value: Option<f64>,
+    pub lower_p_value: Option<f64>,
+    /// Empty unless the question has choices
+    pub options: Vec<OptionAnalysis>,
+    pub flags: Vec<ItemFlag>,
+}
+
+/// Item analysis of a quiz
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemAnalysis {
+    pub quiz_id: Uuid,
+    pub quiz_title: String,
+    /// Completed attempts analyzed
+    pub attempts: usize,
+    /// Size of each of the upper and lower groups
+    pub group_size: usize,
+    pub mean_score: f64,
+    pub score_std_dev: f64,
+    /// Cronbach's alpha, equal to KR-20 for right/wrong scoring; `None`
+    /// with fewer than two questions or when totals do not vary
+    pub cronbach_alpha: Option<f64>,
+    /// Standard error of measurement, in questions
+    pub standard_error_of_measurement: Option<f64>,
+    pub items: Vec<ItemStatistics>,
+    /// Questions left out for having answers awaiting review
+    pub ungraded_questions: Vec<Uuid>,
+}
+
+/// Analyze the completed attempts at a quiz. The attempts need their
+/// answers loaded.
+pub fn analyze(quiz: &Quiz, attempts: &[QuizAttempt]) -> ItemAnalysis {
+    let attempts: Vec<&QuizAttempt> = attempts.iter().filter(|a| a.completed_at.is_some()).collect();
+
+    let (questions, ungraded): (Vec<&Question>, Vec<&Question>) = quiz.questions.iter().partition(|question| {
+        attempts.iter()
+            .flat_map(|attempt| attempt.answers.iter())
+            .all(|answer| answer.question_id != question.id || answer.is_correct.is_some())
+    });
+
+    // Scores by attempt, then by question
+    let scores: Vec<Vec<f64>> = attempts.iter()
+        .map(|attempt| {
+            questions.iter()
+                .map(|question| {
+                    let correct = attempt.answers.iter()
+                        .any(|answer| answer.question_id == question.id && answer.is_correct == Some(true));
+                    if correct { 1.0 } else { 0.0 }
+                })
+                .collect()
+        })
+        .collect();
+    let totals: Vec<f64> = scores.iter().map(|row| row.iter().sum()).collect();
+
+    // Attempts by total, best first, for the upper and lower groups
+    let n = attempts.len();
+    let group_size = ((n as f64 * GROUP_FRACTION).round() as usize).min(n / 2);
+    let mut ranked: Vec<usize> = (0..n).collect();
+    ranked.sort_by(|&a, &b| totals[b].total_cmp(&totals[a]));
+    let upper = &ranked[..group_size];
+    let lower = &ranked[n - group_size..];
+
+    let items = questions.iter().enumerate()
+        .map(|(j, question)| {
+            let column: Vec<f64> = scores.iter().map(|row| row[j]).collect();
+            let rest: Vec<f64> = totals.iter().zip(&column).map(|(total, score)| total - score).collect();
+            let answered = attempts.iter()
+                .filter(|attempt| attempt.answers.iter().any(|answer| answer.question_id == question.id))
+                .count();
+
+            let group_p = |group: &[usize]| (!group.is_empty())
+                .then(|| group.iter().map(|&i| column[i]).sum::<f64>() / group.len() as f64);
+            let upper_p_value = group_p(upper);
+            let lower_p_value = group_p(lower);
+
+            let options = analyze_options(question, &attempts, upper, lower);
+            let mut item = ItemStatistics {
+                question_id: question.id,
+                question_text: question.content.text.clone(),
+                answered,
+                omit_rate: rate(n - answered, n),
+                p_value: mean(&column),
+                point_biserial: correlation(&column, &rest),
+                discrimination_index: upper_p_value.zip(lower_p_value).map(|(upper, lower)| upper - lower),
+                upper_p_value,
+                lower_p_value,
+                options,
+                flags: Vec::new(),
+            };
+            item.flags = flags(&item, n);
+            item
+        })
+        .collect::<Vec<_>>();
+
+    let total_variance = variance(&totals);
+    let cronbach_alpha = match (questions.len(), total_variance) {
+        (k, Some(total_variance)) if k >= 2 && total_variance > 0.0 => {
+            let item_variance: f64 = (0..k)
+                .filter_map(|j| variance(&scores.iter().map(|row| row[j]).collect::<Vec<_>>()))
+                .sum();
+            let k = k as f64;
+            Some(k / (k - 1.0) * (1.0 - item_variance / total_variance))
+        }
+        _ => None,
+    };
+    let score_std_dev = total_variance.unwrap_or(0.0).sqrt();
+
+    ItemAnalysis {
+        quiz_id: quiz.id,
+        quiz_title: quiz.title.clone(),
+        attempts: n,
+        group_size,
+        mean_score: mean(&totals),
+        score_std_dev,
+        cronbach_alpha,
+        // A negative alpha means no measured reliability, not a larger error
+        standard_error_of_measurement: cronbach_alpha.map(|alpha| score_std_dev * (1.0 - alpha.clamp(0.0, 1.0)).sqrt()),
+        items,
+        ungraded_questions: ungraded.iter().map(|question| question.id).collect(),
+    }
+}
+
+fn analyze_options(question: &Question, attempts: &[&QuizAttempt], upper: &[usize], lower: &[usize]) -> Vec<OptionAnalysis> {
+    let correct: &[Uuid] = match &question.correct_answer {
+        Answer::Choice(id) => std::slice::from_ref(id),
+        Answer::Choices(ids) => ids,
+        _ => &[],
+    };
+
+    // Whether each attempt picked each choice
+    let picks: Vec<HashMap<Uuid, bool>> = attempts.iter()
+        .map(|attempt| {
+            let picked: &[Uuid] = match attempt.answers.iter().find(|answer| answer.question_id == question.id).map(|answer| &answer.answer) {
+                Some(Answer::Choice(id)) => std::slice::from_ref(id),
+                Some(Answer::Choices(ids)) => ids,
+                _ => &[],
+            };
+            question.choices.iter().map(|choice| (choice.id, picked.contains(&choice.id))).collect()
+        })
+        .collect();
+    let picked_by = |group: &[usize], choice_id: Uuid| group.iter().filter(|&&i| picks[i][&choice_id]).count();
+    let everyone: Vec<usize> = (0..attempts.len()).collect();
+
+    question.choices.iter()
+        .map(|choice| {
+            let selected = picked_by(&everyone, choice.id);
+            OptionAnalysis {
+                choice_id: choice.id,
+                text: choice.text.clone(),
+                is_correct: correct.contains(&choice.id),
+                selected,
+                selection_rate: rate(selected, attempts.len()),
+                upper_rate: rate(picked_by(upper, choice.id), upper.len()),
+                lower_rate: rate(picked_by(lower, choice.id), lower.len()),
+            }
+        })
+        .collect()
+}
+
+fn flags(item: &ItemStatistics, attempts: usize) -> Vec<ItemFlag> {
+    let mut flags = Vec::new();
+    if attempts == 0 {
+        return flags;
+    }
+
+    if item.p_value > TOO_EASY_P_VALUE {
+        flags.push(ItemFlag::TooEasy);
+    } else if item.p_value < TOO_HARD_P_VALUE {
+        flags.push(ItemFlag::TooHard);
+    }
+    match item.point_biserial {
+        Some(r) if r < 0.0 => flags.push(ItemFlag::NegativeDiscrimination),
+        Some(r) if r < LOW_DISCRIMINATION => flags.push(ItemFlag::LowDiscrimination),
+        _ => {}
+    }
+
+    let distractors = || item.options.iter().filter(|option| !option.is_correct);
+    if distractors().any(|option| option.selection_rate < NONFUNCTIONING_DISTRACTOR_RATE) {
+        flags.push(ItemFlag::NonfunctioningDistractor);
+    }
+    if item.discrimination_index.is_some() && distractors().any(|option| option.upper_rate > option.lower_rate) {
+        flags.push(ItemFlag::AttractiveDistractor);
+    }
+    flags
+}
+
+impl ItemAnalysis {
+    /// Write the analysis as CSV: a summary of the quiz, then a row per
+    /// question, then a row per option of the choice questions, each
+    /// section with its own header and separated by a blank line
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+
+        csv.push_str("Quiz,Attempts,Group size,Mean score,Standard deviation,Cronbach's alpha (KR-20),Standard error of measurement\n");
+        row(&mut csv, &[
+            quote(&self.quiz_title),
+            self.attempts.to_string(),
+            self.group_size.to_string(),
+            number(Some(self.mean_score)),
+            number(Some(self.score_std_dev)),
+            number(self.cronbach_alpha),
+            number(self.standard_error_of_measurement),
+        ]);
+
+        csv.push_str("\nQuestion ID,Question,Answered,Omit rate,P-value,Point-biserial,Discrimination index,Upper p-value,Lower p-value,Flags\n");
+        for item in &self.items {
+            row(&mut csv, &[
+                item.question_id.to_string(),
+                quote(&item.question_text),
+                item.answered.to_string(),
+                number(Some(item.omit_rate)),
+                number(Some(item.p_value)),
+                number(item.point_biserial),
+                number(item.discrimination_index),
+                number(item.upper_p_value),
+                number(item.lower_p_value),
+                quote(&item.flags.iter().map(|flag| flag.as_str()).collect::<Vec<_>>().join(" ")),
+            ]);
+        }
+
+        csv.push_str("\nQuestion ID,Option,Correct,Selected,Selection rate,Upper rate,Lower rate\n");
+        for item in &self.items {
+            for option in &item.options {
+                row(&mut csv, &[
+                    item.question_id.to_string(),
+                    quote(&option.text),
+                    option.is_correct.to_string(),
+                    option.selected.to_string(),
+                    number(Some(option.selection_rate)),
+                    number(Some(option.upper_rate)),
+                    number(Some(option.lower_rate)),
+                ]);
+            }
+        }
+
+        csv
+    }
+}
+
+fn row(csv: &mut String, fields: &[String]) {
+    let _ = writeln!(csv, "{}", fields.join(","));
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+fn number(value: Option<f64>) -> String {
+    value.map(|value| format!("{:.4}", value)).unwrap_or_default()
+}
+
+fn rate(count: usize, total: usize) -> f64 {
+    if total == 0 { 0.0 } else { count as f64 / total as f64 }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() { 0.0 } else { values.iter().sum::<f64>() / values.len() as f64 }
+}
+
+/// Sample variance
+fn variance(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = mean(values);
+    Some(values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64)
+}
+
+/// Pearson correlation, which for a right/wrong score is the point-biserial
+fn correlation(x: &[f64], y: &[f64]) -> Option<f64> {
+    let (mean_x, mean_y) = (mean(x), mean(y));
+    let covariance: f64 = x.iter().zip(y).map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let spread_x: f64 = x.iter().map(|x| (x - mean_x).powi(2)).sum();
+    let spread_y: f64 = y.iter().map(|y| (y - mean_y).powi(2)).sum();
+    (spread_x > 0.0 && spread_y > 0.0).then(|| covariance / (spread_x * spread_y).sqrt())
+}
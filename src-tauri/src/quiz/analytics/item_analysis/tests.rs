@@ -0,0 +1,177 @@
+use super::*;
+use crate::quiz::models::{AnswerType, Choice, QuestionAnswer, QuestionContent};
+use chrono::Utc;
+
+fn content(text: &str) -> QuestionContent {
+    QuestionContent {
+        text: text.to_string(),
+        rich_text: None,
+        image_url: None,
+        audio_url: None,
+        drag_drop_content: None,
+        hotspot_content: None,
+        drawing_content: None,
+        code_execution_content: None,
+        math_equation_content: None,
+        timeline_content: None,
+        diagram_labeling_content: None,
+        short_answer_content: None,
+    }
+}
+
+fn choice_question(quiz: &mut Quiz, text: &str, options: &[&str]) -> Vec<Uuid> {
+    let mut question = Question::new(quiz.id, content(text), AnswerType::MultipleChoice);
+    question.choices = options.iter()
+        .map(|text| Choice { id: Uuid::new_v4(), text: text.to_string(), rich_text: None, image_url: None, feedback: None })
+        .collect();
+    question.set_correct_answer(Answer::Choice(question.choices[0].id));
+    let ids = question.choices.iter().map(|choice| choice.id).collect();
+    quiz.add_question(question);
+    ids
+}
+
+fn text_question(quiz: &mut Quiz, text: &str, answer_type: AnswerType) {
+    quiz.add_question(Question::new(quiz.id, content(text), answer_type));
+}
+
+fn attempt(quiz: &Quiz, answers: Vec<(usize, Answer, Option<bool>)>) -> QuizAttempt {
+    QuizAttempt {
+        id: Uuid::new_v4(),
+        quiz_id: quiz.id,
+        user_id: Uuid::new_v4(),
+        started_at: Utc::now(),
+        completed_at: Some(Utc::now()),
+        score: None,
+        answers: answers.into_iter()
+            .map(|(index, answer, is_correct)| QuestionAnswer {
+                question_id: quiz.questions[index].id,
+                answer,
+                is_correct,
+                time_spent: 30,
+            })
+            .collect(),
+        time_spent: 300,
+    }
+}
+
+fn text(correct: bool) -> (Answer, Option<bool>) {
+    (Answer::Text(if correct { "right" } else { "wrong" }.to_string()), Some(correct))
+}
+
+fn assert_close(actual: Option<f64>, expected: f64) {
+    let actual = actual.expect("statistic missing");
+    assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+}
+
+#[test]
+fn test_statistics() {
+    let mut quiz = Quiz::new("Cells \"101\"".to_string(), None);
+    let options = choice_question(&mut quiz, "Powerhouse of the cell?", &["Mitochondria", "Nucleus", "Ribosome"]);
+    text_question(&mut quiz, "Name the cell's outer layer", AnswerType::ShortAnswer);
+    text_question(&mut quiz, "Name the green pigment", AnswerType::ShortAnswer);
+    text_question(&mut quiz, "Explain osmosis", AnswerType::Essay);
+
+    let picked = |index: usize, correct: bool| (0, Answer::Choice(options[index]), Some(correct));
+    let answer = |question: usize, correct: bool| {
+        let (answer, is_correct) = text(correct);
+        (question, answer, is_correct)
+    };
+    let mut attempts = vec![
+        attempt(&quiz, vec![picked(0, true), answer(1, true), answer(2, true), (3, Answer::Text("...".to_string()), None)]),
+        attempt(&quiz, vec![picked(0, true), answer(1, true), answer(2, false)]),
+        attempt(&quiz, vec![picked(0, true), answer(1, false), answer(2, true)]),
+        attempt(&quiz, vec![picked(1, false), answer(1, true), answer(2, false)]),
+        attempt(&quiz, vec![answer(1, false), answer(2, false)]),
+    ];
+    let mut unfinished = attempt(&quiz, vec![picked(2, false)]);
+    unfinished.completed_at = None;
+    attempts.push(unfinished);
+
+    let analysis = analyze(&quiz, &attempts);
+    assert_eq!(analysis.attempts, 5);
+    assert_eq!(analysis.group_size, 1);
+    assert_eq!(analysis.ungraded_questions, vec![quiz.questions[3].id]);
+    assert_eq!(analysis.items.len(), 3);
+    assert_close(Some(analysis.mean_score), 1.6);
+    assert_close(Some(analysis.score_std_dev), 1.3f64.sqrt());
+    assert_close(analysis.cronbach_alpha, 6.0 / 13.0);
+    assert_close(analysis.standard_error_of_measurement, 0.7f64.sqrt());
+
+    let first = &analysis.items[0];
+    assert_eq!(first.answered, 4);
+    assert_close(Some(first.omit_rate), 0.2);
+    assert_close(Some(first.p_value), 0.6);
+    assert_close(first.point_biserial, 1.0 / 2.4f64.sqrt());
+    assert_close(first.discrimination_index, 1.0);
+    assert_eq!(first.flags, vec![ItemFlag::NonfunctioningDistractor]);
+
+    let rates: Vec<_> = first.options.iter()
+        .map(|option| (option.is_correct, option.selected, option.upper_rate, option.lower_rate))
+        .collect();
+    assert_eq!(rates, vec![(true, 3, 1.0, 0.0), (false, 1, 0.0, 0.0), (false, 0, 0.0, 0.0)]);
+
+    let third = &analysis.items[2];
+    assert_close(Some(third.p_value), 0.4);
+    assert_close(third.point_biserial, 0.6 / 3.36f64.sqrt());
+    assert!(third.options.is_empty());
+    assert!(third.flags.is_empty());
+}
+
+#[test]
+fn test_miskeyed_question() {
+    // The stronger students pick the second option, which suggests it is
+    // the real answer
+    let mut quiz = Quiz::new("Miskeyed".to_string(), None);
+    let options = choice_question(&mut quiz, "Which one?", &["Keyed", "Actually right"]);
+    text_question(&mut quiz, "Other", AnswerType::ShortAnswer);
+
+    let attempts: Vec<_> = [(1, true), (1, true), (0, false), (0, false)].into_iter()
+        .map(|(option, other)| {
+            let (answer, is_correct) = text(other);
+            attempt(&quiz, vec![(0, Answer::Choice(options[option]), Some(option == 0)), (1, answer, is_correct)])
+        })
+        .collect();
+
+    let analysis = analyze(&quiz, &attempts);
+    let item = &analysis.items[0];
+    assert_close(item.point_biserial, -1.0);
+    assert_close(item.discrimination_index, -1.0);
+    assert_eq!(item.flags, vec![ItemFlag::NegativeDiscrimination, ItemFlag::AttractiveDistractor]);
+
+    // Every total is the same, so there is no reliability to speak of
+    assert_eq!(analysis.cronbach_alpha, None);
+    assert_eq!(analysis.standard_error_of_measurement, None);
+}
+
+#[test]
+fn test_without_attempts() {
+    let mut quiz = Quiz::new("Empty".to_string(), None);
+    choice_question(&mut quiz, "Which one?", &["A", "B"]);
+
+    let analysis = analyze(&quiz, &[]);
+    assert_eq!((analysis.attempts, analysis.group_size), (0, 0));
+    assert_eq!(analysis.cronbach_alpha, None);
+    assert_eq!(analysis.items[0].point_biserial, None);
+    assert_eq!(analysis.items[0].discrimination_index, None);
+    assert!(analysis.items[0].flags.is_empty());
+}
+
+#[test]
+fn test_csv() {
+    let mut quiz = Quiz::new("Cells \"101\"".to_string(), None);
+    let options = choice_question(&mut quiz, "Powerhouse, of the cell?", &["Mitochondria", "Nucleus"]);
+    let attempts = vec![
+        attempt(&quiz, vec![(0, Answer::Choice(options[0]), Some(true))]),
+        attempt(&quiz, vec![(0, Answer::Choice(options[1]), Some(false))]),
+    ];
+
+    let csv = analyze(&quiz, &attempts).to_csv();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "Quiz,Attempts,Group size,Mean score,Standard deviation,Cronbach's alpha (KR-20),Standard error of measurement");
+    assert_eq!(lines[1], "\"Cells \"\"101\"\"\",2,1,0.5000,0.7071,,");
+    assert_eq!(lines[2], "");
+    assert_eq!(lines[4], format!("{},\"Powerhouse, of the cell?\",2,0.0000,0.5000,,1.0000,1.0000,0.0000,\"\"", quiz.questions[0].id));
+    assert_eq!(lines[5], "");
+    assert_eq!(lines[7], format!("{},\"Mitochondria\",true,1,0.5000,1.0000,0.0000", quiz.questions[0].id));
+    assert_eq!(lines.len(), 9);
+}
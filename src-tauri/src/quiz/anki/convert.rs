@@ -0,0 +1,237 @@
+// Turning the cards of an Anki package into quizzes and flashcard data
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use uuid::Uuid;
+
+use super::package::{AnkiPackage, Card, Note, NoteKind, NoteType, Review};
+use super::render::{self, Context, Side};
+use crate::quiz::models::{Answer, AnswerType, FlashcardData, Question, QuestionContent, Quiz, StudyMode};
+use crate::quiz::qti::SkippedQuestion;
+use crate::quiz::spaced_repetition::{self, FlashcardRating, Fsrs, ReviewLogEntry};
+
+/// Learning cards are due at epoch seconds rather than on a day number
+const TIMESTAMP_DUE: i64 = 1_000_000_000;
+
+/// Quizzes and scheduling state converted from an Anki package
+#[derive(Debug, Clone)]
+pub struct AnkiImport {
+    /// A quiz per deck with cards
+    pub quizzes: Vec<Quiz>,
+
+    /// The importing user's scheduling state of every card not suspended
+    pub flashcards: Vec<FlashcardData>,
+
+    /// The importing user's review log
+    pub reviews: Vec<ReviewLogEntry>,
+
+    /// Cards that could not be imported
+    pub skipped: Vec<SkippedQuestion>,
+}
+
+/// Convert a package for a user. `assets` maps the package's media
+/// filenames to the IDs of the assets they were stored as.
+pub fn convert(package: &AnkiPackage, assets: &HashMap<String, String>, user_id: Uuid, now: DateTime<Utc>) -> AnkiImport {
+    let notes: HashMap<i64, &Note> = package.notes.iter().map(|note| (note.id, note)).collect();
+    let mut reviews_by_card: HashMap<i64, Vec<&Review>> = HashMap::new();
+    for review in &package.reviews {
+        reviews_by_card.entry(review.card_id).or_default().push(review);
+    }
+
+    let mut quizzes: BTreeMap<i64, Quiz> = BTreeMap::new();
+    let mut tags: BTreeMap<i64, BTreeSet<String>> = BTreeMap::new();
+    let mut import = AnkiImport { quizzes: Vec::new(), flashcards: Vec::new(), reviews: Vec::new(), skipped: Vec::new() };
+
+    for card in &package.cards {
+        let skip = |reason: &str| SkippedQuestion {
+            identifier: card.id.to_string(),
+            title: String::new(),
+            reason: reason.to_string(),
+        };
+        let Some(note) = notes.get(&card.note_id) else {
+            import.skipped.push(skip("its note is missing"));
+            continue;
+        };
+        let Some(note_type) = package.note_types.get(&note.note_type_id) else {
+            import.skipped.push(skip("its note type is missing"));
+            continue;
+        };
+
+        let deck = package.decks.get(&card.deck_id).map(String::as_str).unwrap_or("Imported from Anki");
+        let quiz = quizzes.entry(card.deck_id).or_insert_with(|| {
+            let mut quiz = Quiz::new(deck.to_string(), Some(user_id));
+            quiz.study_mode = StudyMode::Flashcards;
+            quiz.settings.study_mode = StudyMode::Flashcards;
+            quiz
+        });
+
+        let question = match card_question(card, note, note_type, deck, quiz.id, assets) {
+            Ok(question) => question,
+            Err(reason) => {
+                let mut skipped = skip(reason);
+                skipped.title = note.fields.first().map(|field| render::html_to_text(field)).unwrap_or_default();
+                import.skipped.push(skipped);
+                continue;
+            }
+        };
+
+        let reviews: Vec<(DateTime<Utc>, FlashcardRating)> = reviews_by_card.get(&card.id).into_iter().flatten()
+            .filter(|review| review.kind != Review::MANUAL)
+            .filter_map(|review| Some((review.reviewed_at(), rating(review.ease)?)))
+            .collect();
+        import.reviews.extend(reviews.iter().map(|&(reviewed_at, rating)| ReviewLogEntry {
+            question_id: question.id,
+            user_id,
+            deck_id: quiz.id,
+            rating,
+            reviewed_at,
+        }));
+        if card.queue != Card::SUSPENDED {
+            import.flashcards.push(flashcard_data(card, &reviews, question.id, user_id, package.created, now));
+        }
+
+        tags.entry(card.deck_id).or_default().extend(note.tags.iter().cloned());
+        quiz.add_question(question);
+    }
+
+    import.quizzes = quizzes.into_iter()
+        .filter(|(_, quiz)| !quiz.questions.is_empty())
+        .map(|(deck_id, mut quiz)| {
+            quiz.tags = tags.remove(&deck_id).unwrap_or_default().into_iter().collect();
+            quiz
+        })
+        .collect();
+    import
+}
+
+/// Anki's answer buttons as ratings
+fn rating(ease: i64) -> Option<FlashcardRating> {
+    match ease {
+        1 => Some(FlashcardRating::Blackout),
+        2 => Some(FlashcardRating::Difficult),
+        3 => Some(FlashcardRating::Hesitation),
+        4 => Some(FlashcardRating::Perfect),
+        _ => None,
+    }
+}
+
+fn card_question(
+    card: &Card,
+    note: &Note,
+    note_type: &NoteType,
+    deck: &str,
+    quiz_id: Uuid,
+    assets: &HashMap<String, String>,
+) -> Result<Question, &'static str> {
+    let (template, cloze) = match note_type.kind {
+        NoteKind::Standard => (note_type.templates.get(card.ord as usize), None),
+        NoteKind::Cloze => (note_type.templates.first(), Some(card.ord as u32 + 1)),
+    };
+    let template = template.ok_or("its card template is missing")?;
+
+    let mut context = Context {
+        fields: note_type.fields.iter().map(String::as_str).zip(note.fields.iter().map(String::as_str)).collect(),
+        tags: note.tags.join(" "),
+        deck,
+        note_type: &note_type.name,
+        card: &template.name,
+        cloze,
+        side: Side::Front,
+        front_side: String::new(),
+    };
+    let front = render::render(&template.front, &context);
+    context.side = Side::Back;
+    context.front_side = front.clone();
+    let back = render::render(&template.back, &context);
+
+    let (images, sounds) = render::media_references(&front);
+    let text = render::html_to_text(&front);
+    if text.is_empty() && images.is_empty() && sounds.is_empty() {
+        return Err("its front is empty");
+    }
+
+    let (answer, explanation) = match cloze {
+        Some(number) => {
+            let answers: Vec<String> = note.fields.iter()
+                .flat_map(|field| render::cloze_answers(field, number))
+                .collect();
+            if answers.is_empty() {
+                return Err("its cloze is missing");
+            }
+            // The back shows the whole text with the cloze filled in
+            (answers.join(", "), Some(render::html_to_text(&back)))
+        }
+        None => (render::html_to_text(render::answer_part(&back, &front)), None),
+    };
+
+    let content = QuestionContent {
+        text,
+        rich_text: Some(render::sanitize(&render::rewrite_media(&front, assets))),
+        image_url: images.iter().find_map(|image| assets.get(image)).map(|id| format!("{}{}", super::ASSET_URL_PREFIX, id)),
+        audio_url: sounds.iter().find_map(|sound| assets.get(sound)).map(|id| format!("{}{}", super::ASSET_URL_PREFIX, id)),
+        drag_drop_content: None,
+        hotspot_content: None,
+        drawing_content: None,
+        code_execution_content: None,
+        math_equation_content: None,
+        timeline_content: None,
+        diagram_labeling_content: None,
+        short_answer_content: None,
+    };
+    let mut question = Question::new(quiz_id, content, AnswerType::ShortAnswer);
+    question.set_correct_answer(Answer::Text(answer));
+    question.explanation = explanation.filter(|explanation| !explanation.is_empty());
+    Ok(question)
+}
+
+/// Scheduling state of a card. The card keeps Anki's SM-2 state and due
+/// date; replaying its reviews with FSRS gives it a memory state, so decks
+/// switched to FSRS pick up where Anki left off.
+fn flashcard_data(
+    card: &Card,
+    reviews: &[(DateTime<Utc>, FlashcardRating)],
+    question_id: Uuid,
+    user_id: Uuid,
+    created: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> FlashcardData {
+    let mut data = FlashcardData {
+        question_id,
+        user_id,
+        ease_factor: 2.5,
+        interval: 0,
+        repetitions: 0,
+        due_date: now,
+        last_reviewed: now,
+        stability: None,
+        difficulty: None,
+    };
+
+    // New cards, including ones reset after being studied, are due now
+    if card.kind == Card::NEW {
+        return data;
+    }
+
+    let fsrs = Fsrs::default();
+    for &(reviewed_at, rating) in reviews {
+        spaced_repetition::update_fsrs(&fsrs, &mut data, rating, reviewed_at);
+    }
+
+    data.ease_factor = if card.factor > 0 { (card.factor as f32 / 1000.0).max(1.3) } else { 2.5 };
+    data.interval = card.interval.max(0) as i32;
+    data.repetitions = if reviews.is_empty() {
+        (card.reps - card.lapses).max(0) as i32
+    } else {
+        reviews.iter().rev().take_while(|(_, rating)| *rating != FlashcardRating::Blackout).count() as i32
+    };
+    data.due_date = if card.kind != Card::REVIEW && card.due > TIMESTAMP_DUE {
+        Utc.timestamp_opt(card.due, 0).single().unwrap_or(now)
+    } else {
+        created + Duration::days(card.due)
+    };
+    data.last_reviewed = match reviews.last() {
+        Some(&(reviewed_at, _)) => reviewed_at,
+        None => data.due_date - Duration::days(data.interval as i64),
+    };
+    data
+}
@@ -0,0 +1,73 @@
+// Anki deck packages
+//
+// `.apkg` and `.colpkg` files are zip archives holding the Anki collection,
+// a SQLite database, next to the media files. Media files are stored under
+// their index ("0", "1", ...) and the `media` file maps each index to the
+// filename the notes refer to. Anki 2.1.50 and later compress the collection
+// with zstd as `collection.anki21b` unless the deck is exported with
+// "Support older Anki versions"; their `meta` file says so, and their media
+// map is a zstd-compressed protobuf list with the media files compressed too.
+//
+// Each deck becomes a flashcard quiz and each card one of its questions.
+// Anki renders a card from its note's fields with a template of the note
+// type, and the rendered front and back become the question text and its
+// answer. A cloze note has a card per cloze number, whose question hides
+// that cloze and whose answer is the hidden text. Images and sounds are
+// stored in the asset cache and referenced from the rich text as
+// `asset:<id>`. The importing user takes over the scheduling of the cards:
+// the SM-2 state and due date come from the card, and its review log is
+// replayed to build the FSRS memory state and the user's review log.
+
+mod convert;
+mod package;
+mod proto;
+mod render;
+#[cfg(test)]
+mod tests;
+
+pub use convert::{convert, AnkiImport};
+pub use package::{read_package, AnkiPackage, Card, CardTemplate, Note, NoteKind, NoteType, Review};
+
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::qti::SkippedQuestion;
+
+/// Prefix of references to assets in imported rich text
+pub const ASSET_URL_PREFIX: &str = "asset:";
+
+/// Errors reading an Anki package
+#[derive(Debug, Error)]
+pub enum AnkiError {
+    #[error("Invalid package archive: {0}")]
+    Archive(#[from] zip::result::ZipError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid collection: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Invalid package: {0}")]
+    InvalidPackage(String),
+}
+
+/// Outcome of importing an Anki package
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnkiImportReport {
+    /// IDs of the imported quizzes, one per deck
+    pub quiz_ids: Vec<Uuid>,
+
+    /// Cards imported as questions
+    pub cards: usize,
+
+    /// Media files stored in the asset cache
+    pub media: usize,
+
+    /// Reviews replayed into the user's review log
+    pub reviews: usize,
+
+    /// Cards that could not be imported
+    pub skipped: Vec<SkippedQuestion>,
+}
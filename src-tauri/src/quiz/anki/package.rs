@@ -0,0 +1,408 @@
+// Reading the collection and media of an Anki package
+//
+// In the legacy schema (11) that `collection.anki2` and `collection.anki21`
+// use, note types and decks are JSON in the single row of `col`. Anki 2.1.28
+// and later (schema 18, as in `collection.anki21b`) move them into tables of
+// their own, with the note type and template settings in protobuf. Notes,
+// cards and the review log have a table each in both.
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use uuid::Uuid;
+use zip::ZipArchive;
+
+use super::proto::{self, VERSION_LATEST};
+use super::AnkiError;
+
+/// Field separator in `notes.flds`
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// Largest size of a file of the package once decompressed, so that a small
+/// package cannot expand to fill memory
+const MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Kind of note type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteKind {
+    /// A card per template
+    Standard,
+    /// A card per cloze number
+    Cloze,
+}
+
+/// Template rendering the cards of a note type
+#[derive(Debug, Clone, PartialEq)]
+pub struct CardTemplate {
+    pub name: String,
+    pub ord: i64,
+    pub front: String,
+    pub back: String,
+}
+
+/// Note type, naming the fields of its notes
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteType {
+    pub id: i64,
+    pub name: String,
+    pub kind: NoteKind,
+    pub fields: Vec<String>,
+    pub templates: Vec<CardTemplate>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub id: i64,
+    pub note_type_id: i64,
+    pub tags: Vec<String>,
+    pub fields: Vec<String>,
+}
+
+/// Card of a note with its scheduling state
+#[derive(Debug, Clone, PartialEq)]
+pub struct Card {
+    pub id: i64,
+    pub note_id: i64,
+    /// Home deck, also for cards moved to a filtered deck
+    pub deck_id: i64,
+    /// Template index, or cloze number minus one
+    pub ord: i64,
+    /// 0 new, 1 learning, 2 review, 3 relearning
+    pub kind: i64,
+    /// -1 when suspended
+    pub queue: i64,
+    /// Day number for review cards, epoch seconds for learning steps
+    pub due: i64,
+    /// Days, or negative seconds for learning steps
+    pub interval: i64,
+    /// Ease factor in permille
+    pub factor: i64,
+    pub reps: i64,
+    pub lapses: i64,
+}
+
+impl Card {
+    pub const NEW: i64 = 0;
+    pub const REVIEW: i64 = 2;
+    pub const SUSPENDED: i64 = -1;
+}
+
+/// Entry of the review log
+#[derive(Debug, Clone, PartialEq)]
+pub struct Review {
+    /// Time of the review in epoch milliseconds
+    pub id: i64,
+    pub card_id: i64,
+    /// 1 Again, 2 Hard, 3 Good, 4 Easy; 0 for manual rescheduling
+    pub ease: i64,
+    /// 0 learning, 1 review, 2 relearning, 3 filtered, 4 manual
+    pub kind: i64,
+}
+
+impl Review {
+    pub const MANUAL: i64 = 4;
+
+    pub fn reviewed_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(self.id).single().unwrap_or_default()
+    }
+}
+
+/// Contents of an Anki package
+#[derive(Debug, Clone, Default)]
+pub struct AnkiPackage {
+    /// Creation of the collection, from which review cards count their due day
+    pub created: DateTime<Utc>,
+    pub note_types: HashMap<i64, NoteType>,
+    /// Deck names by ID
+    pub decks: HashMap<i64, String>,
+    pub notes: Vec<Note>,
+    pub cards: Vec<Card>,
+    pub reviews: Vec<Review>,
+    /// Media files by the filename notes refer to them with
+    pub media: Vec<(String, Vec<u8>)>,
+}
+
+/// Read an `.apkg` or `.colpkg` file
+pub async fn read_package(data: &[u8]) -> Result<AnkiPackage, AnkiError> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+
+    // Packages of the latest version compress the collection, the media map
+    // and the media files with zstd
+    let compressed = if archive.index_for_name("meta").is_some() {
+        proto::package_version(&read_file(&mut archive, "meta")?)? >= VERSION_LATEST
+    } else {
+        false
+    };
+
+    // Newer packages also hold a `collection.anki2` that only asks to update Anki
+    let collection = if archive.index_for_name("collection.anki21b").is_some() {
+        decompress(&read_file(&mut archive, "collection.anki21b")?, MAX_ENTRY_SIZE)?
+    } else if archive.index_for_name("collection.anki21").is_some() {
+        read_file(&mut archive, "collection.anki21")?
+    } else if archive.index_for_name("collection.anki2").is_some() {
+        read_file(&mut archive, "collection.anki2")?
+    } else {
+        return Err(AnkiError::InvalidPackage("the package holds no collection".to_string()));
+    };
+
+    let media = read_media(&mut archive, compressed)?;
+    let mut package = read_collection(&collection).await?;
+    package.media = media;
+    Ok(package)
+}
+
+fn read_file(archive: &mut ZipArchive<Cursor<&[u8]>>, path: &str) -> Result<Vec<u8>, AnkiError> {
+    let file = archive.by_name(path)
+        .map_err(|_| AnkiError::InvalidPackage(format!("{} is missing", path)))?;
+    read_limited(file, MAX_ENTRY_SIZE)
+}
+
+/// Decompress a zstd-compressed file of the package
+pub(super) fn decompress(data: &[u8], limit: u64) -> Result<Vec<u8>, AnkiError> {
+    read_limited(zstd::Decoder::new(data)?, limit)
+}
+
+/// Read to the end, failing rather than reading more than `limit` bytes
+fn read_limited(reader: impl Read, limit: u64) -> Result<Vec<u8>, AnkiError> {
+    let mut content = Vec::new();
+    reader.take(limit + 1).read_to_end(&mut content)?;
+    if content.len() as u64 > limit {
+        return Err(AnkiError::InvalidPackage(format!("a file is larger than {} bytes once decompressed", limit)));
+    }
+    Ok(content)
+}
+
+fn read_media(archive: &mut ZipArchive<Cursor<&[u8]>>, compressed: bool) -> Result<Vec<(String, Vec<u8>)>, AnkiError> {
+    if archive.index_for_name("media").is_none() {
+        return Ok(Vec::new());
+    }
+
+    // Archive names of the files with the filenames notes refer to them with:
+    // a JSON object in legacy packages, a protobuf list in the latest ones
+    let map = read_file(archive, "media")?;
+    let mut entries: Vec<(String, String)> = if compressed {
+        proto::media_entries(&decompress(&map, MAX_ENTRY_SIZE)?)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let index = entry.legacy_zip_filename.unwrap_or(index as u64);
+                (index.to_string(), entry.name)
+            })
+            .collect()
+    } else {
+        serde_json::from_slice::<HashMap<String, String>>(&map)
+            .map_err(|e| AnkiError::InvalidPackage(format!("invalid media list: {}", e)))?
+            .into_iter()
+            .collect()
+    };
+    entries.sort();
+
+    let mut media = Vec::new();
+    for (index, filename) in entries {
+        // Anki leaves out files it could not find when exporting
+        if archive.index_for_name(&index).is_some() {
+            let content = read_file(archive, &index)?;
+            let content = if compressed { decompress(&content, MAX_ENTRY_SIZE)? } else { content };
+            media.push((filename, content));
+        }
+    }
+    Ok(media)
+}
+
+/// Collection database copied out of the archive, removed when dropped
+struct CollectionFile(PathBuf);
+
+impl Drop for CollectionFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+async fn read_collection(collection: &[u8]) -> Result<AnkiPackage, AnkiError> {
+    // SQLite can only open databases from files
+    let file = CollectionFile(std::env::temp_dir().join(format!("ordo-anki-{}.db", Uuid::new_v4())));
+    std::fs::write(&file.0, collection)?;
+
+    let options = SqliteConnectOptions::new().filename(&file.0).read_only(true);
+    let pool = SqlitePoolOptions::new().max_connections(1).connect_with(options).await?;
+    let package = read_tables(&pool).await;
+    pool.close().await;
+    package
+}
+
+async fn read_tables(pool: &SqlitePool) -> Result<AnkiPackage, AnkiError> {
+    let col = sqlx::query("SELECT crt, models, decks FROM col").fetch_one(pool).await?;
+    let models: String = col.get("models");
+    let decks: String = col.get("decks");
+
+    let newer = sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'notetypes'")
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+    let (note_types, decks) = if newer {
+        (read_note_types(pool).await?, read_decks(pool).await?)
+    } else {
+        (parse_note_types(&models)?, parse_decks(&decks)?)
+    };
+
+    let notes = sqlx::query("SELECT id, mid, tags, flds FROM notes ORDER BY id")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| Note {
+            id: row.get("id"),
+            note_type_id: row.get("mid"),
+            tags: row.get::<String, _>("tags").split_whitespace().map(str::to_string).collect(),
+            fields: row.get::<String, _>("flds").split(FIELD_SEPARATOR).map(str::to_string).collect(),
+        })
+        .collect();
+
+    let cards = sqlx::query(
+        "SELECT id, nid, did, ord, type, queue, due, ivl, factor, reps, lapses, odid, odue FROM cards ORDER BY nid, ord"
+    )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            // Cards in a filtered deck keep their home deck and due date apart
+            let original_deck: i64 = row.get("odid");
+            let (deck_id, due) = if original_deck != 0 {
+                (original_deck, row.get("odue"))
+            } else {
+                (row.get("did"), row.get("due"))
+            };
+            Card {
+                id: row.get("id"),
+                note_id: row.get("nid"),
+                deck_id,
+                ord: row.get("ord"),
+                kind: row.get("type"),
+                queue: row.get("queue"),
+                due,
+                interval: row.get("ivl"),
+                factor: row.get("factor"),
+                reps: row.get("reps"),
+                lapses: row.get("lapses"),
+            }
+        })
+        .collect();
+
+    let reviews = sqlx::query("SELECT id, cid, ease, type FROM revlog ORDER BY id")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| Review {
+            id: row.get("id"),
+            card_id: row.get("cid"),
+            ease: row.get("ease"),
+            kind: row.get("type"),
+        })
+        .collect();
+
+    Ok(AnkiPackage {
+        created: Utc.timestamp_opt(col.get("crt"), 0).single().unwrap_or_default(),
+        note_types,
+        decks,
+        notes,
+        cards,
+        reviews,
+        media: Vec::new(),
+    })
+}
+
+/// Note types from the tables of schema 18
+async fn read_note_types(pool: &SqlitePool) -> Result<HashMap<i64, NoteType>, AnkiError> {
+    let mut note_types = HashMap::new();
+    for row in sqlx::query("SELECT id, name, config FROM notetypes").fetch_all(pool).await? {
+        let id: i64 = row.get("id");
+        note_types.insert(id, NoteType {
+            id,
+            name: row.get("name"),
+            kind: proto::note_type_kind(row.get::<&[u8], _>("config"))?,
+            fields: Vec::new(),
+            templates: Vec::new(),
+        });
+    }
+
+    for row in sqlx::query("SELECT ntid, name FROM fields ORDER BY ntid, ord").fetch_all(pool).await? {
+        if let Some(note_type) = note_types.get_mut(&row.get::<i64, _>("ntid")) {
+            note_type.fields.push(row.get("name"));
+        }
+    }
+
+    for row in sqlx::query("SELECT ntid, ord, name, config FROM templates ORDER BY ntid, ord").fetch_all(pool).await? {
+        if let Some(note_type) = note_types.get_mut(&row.get::<i64, _>("ntid")) {
+            let (front, back) = proto::template_formats(row.get::<&[u8], _>("config"))?;
+            note_type.templates.push(CardTemplate { name: row.get("name"), ord: row.get("ord"), front, back });
+        }
+    }
+    Ok(note_types)
+}
+
+/// Deck names from the table of schema 18, which separates the levels of
+/// nested decks with the field separator rather than "::"
+async fn read_decks(pool: &SqlitePool) -> Result<HashMap<i64, String>, AnkiError> {
+    Ok(sqlx::query("SELECT id, name FROM decks")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.get("id"), row.get::<String, _>("name").replace(FIELD_SEPARATOR, "::")))
+        .collect())
+}
+
+fn parse_json(json: &str, what: &str) -> Result<HashMap<String, Value>, AnkiError> {
+    if json.trim().is_empty() {
+        return Ok(HashMap::new());
+    }
+    serde_json::from_str(json).map_err(|e| AnkiError::InvalidPackage(format!("invalid {}: {}", what, e)))
+}
+
+fn parse_note_types(json: &str) -> Result<HashMap<i64, NoteType>, AnkiError> {
+    let mut note_types = HashMap::new();
+    for (key, model) in parse_json(json, "note types")? {
+        let id = model["id"].as_i64().or_else(|| key.parse().ok())
+            .ok_or_else(|| AnkiError::InvalidPackage(format!("note type {} has no ID", key)))?;
+
+        let mut fields: Vec<(i64, String)> = model["flds"].as_array().into_iter().flatten()
+            .map(|field| (field["ord"].as_i64().unwrap_or(0), text(&field["name"])))
+            .collect();
+        fields.sort();
+
+        let mut templates: Vec<CardTemplate> = model["tmpls"].as_array().into_iter().flatten()
+            .map(|template| CardTemplate {
+                name: text(&template["name"]),
+                ord: template["ord"].as_i64().unwrap_or(0),
+                front: text(&template["qfmt"]),
+                back: text(&template["afmt"]),
+            })
+            .collect();
+        templates.sort_by_key(|template| template.ord);
+
+        note_types.insert(id, NoteType {
+            id,
+            name: text(&model["name"]),
+            kind: if model["type"].as_i64() == Some(1) { NoteKind::Cloze } else { NoteKind::Standard },
+            fields: fields.into_iter().map(|(_, name)| name).collect(),
+            templates,
+        });
+    }
+    Ok(note_types)
+}
+
+fn parse_decks(json: &str) -> Result<HashMap<i64, String>, AnkiError> {
+    Ok(parse_json(json, "decks")?
+        .into_iter()
+        .filter_map(|(key, deck)| {
+            let id = deck["id"].as_i64().or_else(|| key.parse().ok())?;
+            Some((id, text(&deck["name"])))
+        })
+        .collect())
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
@@ -0,0 +1,136 @@
+// Reading the protobuf messages of newer Anki packages
+//
+// Packages made by Anki 2.1.50 and later describe themselves, their media
+// and parts of the collection in protobuf. The importer needs only a handful
+// of fields, so rather than generating code for Anki's message definitions
+// the wire format is walked directly and unknown fields are skipped.
+
+use super::AnkiError;
+use super::package::NoteKind;
+
+/// `PackageMetadata.Version` of packages with a compressed collection and media
+pub const VERSION_LATEST: u64 = 3;
+
+/// A field of a message as found on the wire
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WireValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// A file listed in the media map
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaEntry {
+    /// Filename the notes refer to the file with
+    pub name: String,
+    /// Name of the file in the archive, if not its index in the list
+    pub legacy_zip_filename: Option<u64>,
+}
+
+/// `PackageMetadata.version`, 0 when unknown
+pub fn package_version(meta: &[u8]) -> Result<u64, AnkiError> {
+    Ok(last_varint(&fields(meta)?, 1).unwrap_or(0))
+}
+
+/// Entries of `MediaEntries`, in archive order
+pub fn media_entries(data: &[u8]) -> Result<Vec<MediaEntry>, AnkiError> {
+    fields(data)?
+        .into_iter()
+        .filter_map(|(number, value)| match (number, value) {
+            (1, WireValue::Bytes(entry)) => Some(entry),
+            _ => None,
+        })
+        .map(|entry| {
+            let entry = fields(entry)?;
+            Ok(MediaEntry {
+                name: last_string(&entry, 1)?.unwrap_or_default(),
+                legacy_zip_filename: last_varint(&entry, 255),
+            })
+        })
+        .collect()
+}
+
+/// Kind in a `Notetype.Config`
+pub fn note_type_kind(config: &[u8]) -> Result<NoteKind, AnkiError> {
+    Ok(match last_varint(&fields(config)?, 1) {
+        Some(1) => NoteKind::Cloze,
+        _ => NoteKind::Standard,
+    })
+}
+
+/// Front and back formats in a `Notetype.Template.Config`
+pub fn template_formats(config: &[u8]) -> Result<(String, String), AnkiError> {
+    let config = fields(config)?;
+    Ok((last_string(&config, 1)?.unwrap_or_default(), last_string(&config, 2)?.unwrap_or_default()))
+}
+
+fn fields(mut data: &[u8]) -> Result<Vec<(u64, WireValue<'_>)>, AnkiError> {
+    let mut fields = Vec::new();
+    while !data.is_empty() {
+        let key = varint(&mut data)?;
+        let value = match key & 7 {
+            0 => WireValue::Varint(varint(&mut data)?),
+            1 => {
+                take(&mut data, 8)?;
+                WireValue::Fixed
+            }
+            2 => {
+                let length = varint(&mut data)?;
+                WireValue::Bytes(take(&mut data, usize::try_from(length).unwrap_or(usize::MAX))?)
+            }
+            5 => {
+                take(&mut data, 4)?;
+                WireValue::Fixed
+            }
+            wire_type => return Err(invalid(&format!("unsupported wire type {}", wire_type))),
+        };
+        fields.push((key >> 3, value));
+    }
+    Ok(fields)
+}
+
+fn varint(data: &mut &[u8]) -> Result<u64, AnkiError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first().ok_or_else(|| invalid("truncated varint"))?;
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint too long"))
+}
+
+fn take<'a>(data: &mut &'a [u8], length: usize) -> Result<&'a [u8], AnkiError> {
+    if data.len() < length {
+        return Err(invalid("truncated field"));
+    }
+    let (value, rest) = data.split_at(length);
+    *data = rest;
+    Ok(value)
+}
+
+// A field that occurs more than once takes its last value
+
+fn last_varint(fields: &[(u64, WireValue)], number: u64) -> Option<u64> {
+    fields.iter().rev().find_map(|(n, value)| match value {
+        WireValue::Varint(value) if *n == number => Some(*value),
+        _ => None,
+    })
+}
+
+fn last_string(fields: &[(u64, WireValue)], number: u64) -> Result<Option<String>, AnkiError> {
+    let bytes = fields.iter().rev().find_map(|(n, value)| match value {
+        WireValue::Bytes(bytes) if *n == number => Some(*bytes),
+        _ => None,
+    });
+    bytes
+        .map(|bytes| String::from_utf8(bytes.to_vec()).map_err(|_| invalid("string is not UTF-8")))
+        .transpose()
+}
+
+fn invalid(message: &str) -> AnkiError {
+    AnkiError::InvalidPackage(format!("invalid protobuf message: {}", message))
+}
@@ -0,0 +1,241 @@
+// Rendering Anki card templates
+//
+// Templates are a small subset of Mustache: `{{Field}}` replacements with
+// optional filters such as `{{cloze:Text}}` or `{{text:Field}}`, sections
+// `{{#Field}}...{{/Field}}` kept when the field is not empty, and inverted
+// sections `{{^Field}}...{{/Field}}` kept when it is. Rendered cards are
+// HTML, which is turned into plain text for the question text and answer.
+// Decks come from anywhere, so the HTML kept as rich text is first cleaned
+// down to an allow-list of tags and attributes.
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+
+use super::ASSET_URL_PREFIX;
+
+static CLOZE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)\{\{c(\d+)::(.*?)\}\}").unwrap());
+static IMAGE: Lazy<Regex> = Lazy::new(|| Regex::new(
+    r#"(?i)<img\b[^>]*?\bsrc\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#
+).unwrap());
+static SOUND: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[sound:([^\]]+)\]").unwrap());
+static ANSWER_DIVIDER: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)<hr[^>]*\bid\s*=\s*["']?answer["']?[^>]*>"#).unwrap());
+static HIDDEN: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<style\b.*?</style>|<script\b.*?</script>").unwrap());
+static LINE_BREAK: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<br\s*/?>|</(div|p|li|tr|h\d)>").unwrap());
+static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+static ENTITY: Lazy<Regex> = Lazy::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap());
+
+/// Side of a card being rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Front,
+    Back,
+}
+
+/// What a template is rendered with
+pub struct Context<'a> {
+    pub fields: HashMap<&'a str, &'a str>,
+    pub tags: String,
+    pub deck: &'a str,
+    pub note_type: &'a str,
+    pub card: &'a str,
+    /// Cloze number of a cloze card
+    pub cloze: Option<u32>,
+    pub side: Side,
+    /// Rendered front, for `{{FrontSide}}` on the back
+    pub front_side: String,
+}
+
+/// Render a template
+pub fn render(template: &str, context: &Context) -> String {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            output.push_str(&rest[start..]);
+            return output;
+        };
+        let tag = after[..end].trim();
+        rest = &after[end + 2..];
+
+        if let Some((inverted, name)) = tag.strip_prefix('#').map(|name| (false, name))
+            .or_else(|| tag.strip_prefix('^').map(|name| (true, name)))
+        {
+            let name = name.trim();
+            let close = format!("{{{{/{}}}}}", name);
+            let (inner, remaining) = match rest.find(&close) {
+                Some(index) => (&rest[..index], &rest[index + close.len()..]),
+                None => (rest, ""),
+            };
+            let present = context.fields.get(name).is_some_and(|value| !html_to_text(value).is_empty());
+            if present != inverted {
+                output.push_str(&render(inner, context));
+            }
+            rest = remaining;
+        } else if !tag.starts_with('/') && !tag.starts_with('!') {
+            output.push_str(&replacement(tag, context));
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn replacement(tag: &str, context: &Context) -> String {
+    let mut filters: Vec<&str> = tag.split(':').map(str::trim).collect();
+    let name = filters.pop().unwrap_or_default();
+
+    // Answers are typed into the question itself rather than a box on the card
+    if filters.contains(&"type") {
+        return String::new();
+    }
+
+    let mut value = match name {
+        "FrontSide" => context.front_side.clone(),
+        "Tags" => context.tags.clone(),
+        "Deck" => context.deck.to_string(),
+        "Subdeck" => context.deck.rsplit("::").next().unwrap_or_default().to_string(),
+        "Type" => context.note_type.to_string(),
+        "Card" => context.card.to_string(),
+        _ => context.fields.get(name).copied().unwrap_or_default().to_string(),
+    };
+
+    // Filters apply from the innermost out
+    for filter in filters.iter().rev() {
+        value = match *filter {
+            "cloze" => match context.cloze {
+                Some(number) => cloze(&value, number, context.side),
+                None => value,
+            },
+            "text" => html_to_text(&value),
+            _ => value,
+        };
+    }
+    value
+}
+
+/// Render the clozes of a field: the card's cloze is hidden on the front
+/// and highlighted on the back, the others are shown as plain text
+pub fn cloze(text: &str, number: u32, side: Side) -> String {
+    CLOZE.replace_all(text, |captures: &Captures| {
+        let (answer, hint) = split_cloze(&captures[2]);
+        if captures[1].parse::<u32>().ok() != Some(number) {
+            return answer.to_string();
+        }
+        match side {
+            Side::Front => format!("<span class=\"cloze\">[{}]</span>", hint.unwrap_or("...")),
+            Side::Back => format!("<span class=\"cloze\">{}</span>", answer),
+        }
+    }).into_owned()
+}
+
+/// Hidden text of a cloze number
+pub fn cloze_answers(text: &str, number: u32) -> Vec<String> {
+    CLOZE.captures_iter(text)
+        .filter(|captures| captures[1].parse::<u32>().ok() == Some(number))
+        .map(|captures| html_to_text(split_cloze(&captures[2]).0))
+        .collect()
+}
+
+fn split_cloze(content: &str) -> (&str, Option<&str>) {
+    match content.split_once("::") {
+        Some((answer, hint)) => (answer, Some(hint)),
+        None => (content, None),
+    }
+}
+
+/// The part of a rendered back that is not the front: what follows
+/// `<hr id=answer>`, or failing that what follows the front
+pub fn answer_part<'a>(back: &'a str, front: &str) -> &'a str {
+    if let Some(divider) = ANSWER_DIVIDER.find(back) {
+        return &back[divider.end()..];
+    }
+    back.strip_prefix(front).unwrap_or(back)
+}
+
+/// Images and sounds an HTML field refers to
+pub fn media_references(html: &str) -> (Vec<String>, Vec<String>) {
+    let images = IMAGE.captures_iter(html)
+        .filter_map(|captures| captures.get(1).or(captures.get(2)).or(captures.get(3)))
+        .map(|source| decode_entities(source.as_str()))
+        .collect();
+    let sounds = SOUND.captures_iter(html)
+        .map(|captures| decode_entities(&captures[1]))
+        .collect();
+    (images, sounds)
+}
+
+/// Point image sources and sound tags at stored assets. References to
+/// files the package did not contain are left as they are.
+pub fn rewrite_media(html: &str, assets: &HashMap<String, String>) -> String {
+    let html = IMAGE.replace_all(html, |captures: &Captures| {
+        let whole = captures.get(0).unwrap();
+        let source = captures.get(1).or(captures.get(2)).or(captures.get(3)).unwrap();
+        match assets.get(&decode_entities(source.as_str())) {
+            Some(asset_id) => format!(
+                "{}{}{}{}",
+                &html[whole.start()..source.start()],
+                ASSET_URL_PREFIX,
+                asset_id,
+                &html[source.end()..whole.end()],
+            ),
+            None => whole.as_str().to_string(),
+        }
+    });
+
+    SOUND.replace_all(&html, |captures: &Captures| {
+        match assets.get(&decode_entities(&captures[1])) {
+            Some(asset_id) => format!("<audio controls src=\"{}{}\"></audio>", ASSET_URL_PREFIX, asset_id),
+            None => captures[0].to_string(),
+        }
+    }).into_owned()
+}
+
+/// Rendered HTML without scripts, styles, event handlers or links to
+/// anything but web pages and stored assets
+pub fn sanitize(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_tags(["audio"])
+        .add_tag_attributes("audio", ["controls", "src"])
+        .add_url_schemes([ASSET_URL_PREFIX.trim_end_matches(':')])
+        .clean(html)
+        .to_string()
+}
+
+/// Plain text of rendered HTML, a line per block
+pub fn html_to_text(html: &str) -> String {
+    let text = HIDDEN.replace_all(html, "");
+    let text = SOUND.replace_all(&text, "");
+    let text = LINE_BREAK.replace_all(&text, "\n");
+    let text = TAG.replace_all(&text, "");
+    let text = decode_entities(&text);
+
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn decode_entities(text: &str) -> String {
+    ENTITY.replace_all(text, |captures: &Captures| {
+        let entity = &captures[1];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32)
+            }
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        decoded.map(String::from).unwrap_or_else(|| captures[0].to_string())
+    }).into_owned()
+}
@@ -0,0 +1,415 @@
+use super::*;
+use super::render::{self, Context, Side};
+use crate::quiz::models::{Answer, StudyMode};
+use crate::quiz::spaced_repetition::FlashcardRating;
+use chrono::{Duration, TimeZone, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+const CREATED: i64 = 1_600_000_000;
+const DAY_MS: i64 = 86_400_000;
+
+const SCHEMA: &str = "
+    CREATE TABLE col (id INTEGER PRIMARY KEY, crt INTEGER NOT NULL, models TEXT NOT NULL, decks TEXT NOT NULL);
+    CREATE TABLE notes (id INTEGER PRIMARY KEY, mid INTEGER NOT NULL, tags TEXT NOT NULL, flds TEXT NOT NULL);
+    CREATE TABLE cards (
+        id INTEGER PRIMARY KEY, nid INTEGER NOT NULL, did INTEGER NOT NULL, ord INTEGER NOT NULL,
+        type INTEGER NOT NULL, queue INTEGER NOT NULL, due INTEGER NOT NULL, ivl INTEGER NOT NULL,
+        factor INTEGER NOT NULL, reps INTEGER NOT NULL, lapses INTEGER NOT NULL,
+        odue INTEGER NOT NULL DEFAULT 0, odid INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE revlog (id INTEGER PRIMARY KEY, cid INTEGER NOT NULL, ease INTEGER NOT NULL, type INTEGER NOT NULL);
+";
+
+const MODELS: &str = r#"{
+    "1": {"id": 1, "name": "Basic", "type": 0,
+          "flds": [{"name": "Back", "ord": 1}, {"name": "Front", "ord": 0}],
+          "tmpls": [{"name": "Card 1", "ord": 0, "qfmt": "{{Front}}", "afmt": "{{FrontSide}}<hr id=answer>{{Back}}"}]},
+    "2": {"id": 2, "name": "Cloze", "type": 1,
+          "flds": [{"name": "Text", "ord": 0}, {"name": "Back Extra", "ord": 1}],
+          "tmpls": [{"name": "Cloze", "ord": 0, "qfmt": "{{cloze:Text}}", "afmt": "{{cloze:Text}}<br>{{Back Extra}}"}]}
+}"#;
+
+const DECKS: &str = r#"{"1": {"id": 1, "name": "Default"}, "10": {"id": 10, "name": "Biology::Cells"}}"#;
+
+/// The note types and decks of `MODELS` and `DECKS` in the tables of schema 18
+const SCHEMA_18: &str = "
+    CREATE TABLE notetypes (id INTEGER PRIMARY KEY, name TEXT NOT NULL, config BLOB NOT NULL);
+    CREATE TABLE fields (ntid INTEGER NOT NULL, ord INTEGER NOT NULL, name TEXT NOT NULL, PRIMARY KEY (ntid, ord));
+    CREATE TABLE templates (ntid INTEGER NOT NULL, ord INTEGER NOT NULL, name TEXT NOT NULL, config BLOB NOT NULL, PRIMARY KEY (ntid, ord));
+    CREATE TABLE decks (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+    INSERT INTO fields VALUES (1, 0, 'Front'), (1, 1, 'Back'), (2, 0, 'Text'), (2, 1, 'Back Extra');
+    INSERT INTO decks VALUES (1, 'Default'), (10, 'Biology' || char(31) || 'Cells');
+";
+
+/// Encode a protobuf varint
+fn varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn varint_field(number: u64, value: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    varint(number << 3, &mut out);
+    varint(value, &mut out);
+    out
+}
+
+fn bytes_field(number: u64, value: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    varint((number << 3) | 2, &mut out);
+    varint(value.len() as u64, &mut out);
+    out.extend_from_slice(value);
+    out
+}
+
+/// Card 1000 is a basic review card, 2000 and 2001 the two cards of a cloze
+/// note, one new and one suspended
+async fn collection() -> Vec<u8> {
+    build_collection(false).await
+}
+
+/// The collection of `collection` in schema 18, as in `collection.anki21b`
+async fn collection_18() -> Vec<u8> {
+    build_collection(true).await
+}
+
+async fn build_collection(schema_18: bool) -> Vec<u8> {
+    let path = std::env::temp_dir().join(format!("ordo-anki-test-{}.db", Uuid::new_v4()));
+    let options = SqliteConnectOptions::new().filename(&path).create_if_missing(true);
+    let pool = SqlitePoolOptions::new().max_connections(1).connect_with(options).await.unwrap();
+
+    sqlx::query(SCHEMA).execute(&pool).await.unwrap();
+    if schema_18 {
+        sqlx::query(SCHEMA_18).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO col (id, crt, models, decks) VALUES (1, ?, '', '')")
+            .bind(CREATED)
+            .execute(&pool).await.unwrap();
+
+        for (id, name, kind) in [(1, "Basic", 0), (2, "Cloze", 1)] {
+            // Field 3 stands in for the settings the importer skips
+            let config = [varint_field(1, kind), bytes_field(3, b"css")].concat();
+            sqlx::query("INSERT INTO notetypes (id, name, config) VALUES (?, ?, ?)")
+                .bind(id).bind(name).bind(config)
+                .execute(&pool).await.unwrap();
+        }
+        let templates = [
+            (1, "Card 1", "{{Front}}", "{{FrontSide}}<hr id=answer>{{Back}}"),
+            (2, "Cloze", "{{cloze:Text}}", "{{cloze:Text}}<br>{{Back Extra}}"),
+        ];
+        for (note_type, name, front, back) in templates {
+            let config = [bytes_field(1, front.as_bytes()), bytes_field(2, back.as_bytes()), varint_field(5, 1)].concat();
+            sqlx::query("INSERT INTO templates (ntid, ord, name, config) VALUES (?, 0, ?, ?)")
+                .bind(note_type).bind(name).bind(config)
+                .execute(&pool).await.unwrap();
+        }
+    } else {
+        sqlx::query("INSERT INTO col (id, crt, models, decks) VALUES (1, ?, ?, ?)")
+            .bind(CREATED).bind(MODELS).bind(DECKS)
+            .execute(&pool).await.unwrap();
+    }
+
+    let notes = [
+        (100, 1, " cells biology ", "What is the <b>powerhouse</b> of the cell?<img src=\"mito.png\">\x1fThe mitochondria"),
+        (200, 2, " cells ", "{{c1::Ribosomes}} make {{c2::proteins::molecules}}\x1fTranslation"),
+    ];
+    for (id, model, tags, fields) in notes {
+        sqlx::query("INSERT INTO notes (id, mid, tags, flds) VALUES (?, ?, ?, ?)")
+            .bind(id).bind(model).bind(tags).bind(fields)
+            .execute(&pool).await.unwrap();
+    }
+
+    let cards = [
+        (1000, 100, 0, 2, 2, 1500, 30, 2300, 4, 1),
+        (2000, 200, 0, 0, 0, 1, 0, 0, 0, 0),
+        (2001, 200, 1, 2, -1, 1400, 10, 2500, 3, 0),
+    ];
+    for (id, note, ord, kind, queue, due, interval, factor, reps, lapses) in cards {
+        sqlx::query(
+            "INSERT INTO cards (id, nid, did, ord, type, queue, due, ivl, factor, reps, lapses) VALUES (?, ?, 10, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+            .bind(id).bind(note).bind(ord).bind(kind).bind(queue).bind(due).bind(interval).bind(factor).bind(reps).bind(lapses)
+            .execute(&pool).await.unwrap();
+    }
+
+    let start = CREATED * 1000;
+    let reviews = [
+        (start, 1000, 3, 0),
+        (start + DAY_MS, 1000, 3, 1),
+        (start + 5 * DAY_MS, 1000, 1, 1),
+        (start + 6 * DAY_MS, 1000, 3, 2),
+        (start + 7 * DAY_MS, 1000, 0, 4),
+        (start + 20 * DAY_MS, 1000, 3, 1),
+        (start + 2 * DAY_MS, 2001, 4, 0),
+    ];
+    for (id, card, ease, kind) in reviews {
+        sqlx::query("INSERT INTO revlog (id, cid, ease, type) VALUES (?, ?, ?, ?)")
+            .bind(id).bind(card).bind(ease).bind(kind)
+            .execute(&pool).await.unwrap();
+    }
+
+    pool.close().await;
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    data
+}
+
+fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in files {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+async fn sample_package() -> AnkiPackage {
+    let collection = collection().await;
+    let media = br#"{"0": "mito.png", "1": "missing.mp3"}"#;
+    let data = archive(&[("collection.anki2", &collection), ("media", media), ("0", &[1, 2, 3])]);
+    read_package(&data).await.unwrap()
+}
+
+fn context<'a>(fields: &[(&'a str, &'a str)], cloze: Option<u32>, side: Side) -> Context<'a> {
+    Context {
+        fields: fields.iter().copied().collect(),
+        tags: "cells".to_string(),
+        deck: "Biology::Cells",
+        note_type: "Basic",
+        card: "Card 1",
+        cloze,
+        side,
+        front_side: "FRONT".to_string(),
+    }
+}
+
+mod templates {
+    use super::*;
+
+    #[test]
+    fn test_fields_and_sections() {
+        let context = context(&[("Front", "Cell"), ("Hint", "")], None, Side::Back);
+        assert_eq!(render::render("{{Front}} ({{Subdeck}}, {{Tags}})", &context), "Cell (Cells, cells)");
+        assert_eq!(render::render("{{#Front}}has {{Front}}{{/Front}}{{#Hint}}hint{{/Hint}}", &context), "has Cell");
+        assert_eq!(render::render("{{^Hint}}no hint{{/Hint}}", &context), "no hint");
+        assert_eq!(render::render("{{FrontSide}} {{type:Front}}{{Unknown}}", &context), "FRONT ");
+        assert_eq!(render::render("{{text:Front}} {{", &context), "Cell {{");
+    }
+
+    #[test]
+    fn test_cloze() {
+        let text = "{{c1::Ribosomes}} make {{c2::proteins::molecules}}";
+        assert_eq!(render::cloze(text, 1, Side::Front), "<span class=\"cloze\">[...]</span> make proteins");
+        assert_eq!(render::cloze(text, 2, Side::Front), "Ribosomes make <span class=\"cloze\">[molecules]</span>");
+        assert_eq!(render::cloze(text, 2, Side::Back), "Ribosomes make <span class=\"cloze\">proteins</span>");
+        assert_eq!(render::cloze_answers(text, 2), vec!["proteins".to_string()]);
+        assert!(render::cloze_answers(text, 3).is_empty());
+
+        let context = context(&[("Text", text)], Some(1), Side::Front);
+        assert_eq!(render::html_to_text(&render::render("{{cloze:Text}}", &context)), "[...] make proteins");
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        assert_eq!(
+            render::html_to_text("<div>One&nbsp;&amp; <b>two</b></div><div>three<br/>four</div>[sound:a.mp3]<style>.x {}</style>"),
+            "One & two\nthree\nfour"
+        );
+        assert_eq!(render::html_to_text("&lt;p&gt; &#233; &#x41; &bogus;"), "<p> é A &bogus;");
+    }
+
+    #[test]
+    fn test_media() {
+        let html = r#"<img class="x" src="a&amp;b.png"> <img src='c.jpg'> [sound:d.mp3]"#;
+        let (images, sounds) = render::media_references(html);
+        assert_eq!(images, vec!["a&b.png".to_string(), "c.jpg".to_string()]);
+        assert_eq!(sounds, vec!["d.mp3".to_string()]);
+
+        let assets: HashMap<String, String> = [("a&b.png", "1"), ("d.mp3", "2")].iter()
+            .map(|(name, id)| (name.to_string(), id.to_string()))
+            .collect();
+        assert_eq!(
+            render::rewrite_media(html, &assets),
+            r#"<img class="x" src="asset:1"> <img src='c.jpg'> <audio controls src="asset:2"></audio>"#
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let html = r#"<b onclick="steal()">Q</b><script>steal()</script><img src="asset:1" onerror="steal()"><a href="javascript:steal()">x</a><audio controls src="asset:2"></audio>"#;
+        let clean = render::sanitize(html);
+        assert!(!clean.contains("steal"));
+        assert!(clean.contains("<b>Q</b>"));
+        assert!(clean.contains(r#"<img src="asset:1">"#));
+        assert!(clean.contains(r#"<audio controls="" src="asset:2"></audio>"#));
+    }
+
+    #[test]
+    fn test_answer_part() {
+        assert_eq!(render::answer_part("Q<hr id=answer>A", "Q"), "A");
+        assert_eq!(render::answer_part("Q\n\nA", "Q"), "\n\nA");
+        assert_eq!(render::answer_part("A", "Q"), "A");
+    }
+}
+
+mod packages {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_package() {
+        let package = sample_package().await;
+
+        assert_eq!(package.created, Utc.timestamp_opt(CREATED, 0).unwrap());
+        assert_eq!(package.note_types[&1].fields, vec!["Front".to_string(), "Back".to_string()]);
+        assert_eq!(package.note_types[&2].kind, NoteKind::Cloze);
+        assert_eq!(package.decks[&10], "Biology::Cells");
+        assert_eq!(package.notes[0].tags, vec!["cells".to_string(), "biology".to_string()]);
+        assert_eq!(package.notes[1].fields[1], "Translation");
+        assert_eq!(package.cards.len(), 3);
+        assert_eq!(package.reviews.len(), 7);
+
+        // Files missing from the archive are left out
+        assert_eq!(package.media, vec![("mito.png".to_string(), vec![1, 2, 3])]);
+    }
+
+    #[tokio::test]
+    async fn test_read_compressed_package() {
+        let legacy = sample_package().await;
+
+        let collection = zstd::encode_all(collection_18().await.as_slice(), 0).unwrap();
+        let media = [
+            bytes_field(1, &[bytes_field(1, b"mito.png"), varint_field(2, 3)].concat()),
+            bytes_field(1, &bytes_field(1, b"missing.mp3")),
+            bytes_field(1, &[bytes_field(1, b"renamed.png"), varint_field(255, 7)].concat()),
+        ].concat();
+        let media = zstd::encode_all(media.as_slice(), 0).unwrap();
+        let file = zstd::encode_all([1u8, 2, 3].as_slice(), 0).unwrap();
+        let other = zstd::encode_all([4u8].as_slice(), 0).unwrap();
+        let data = archive(&[
+            ("collection.anki2", b"Please update to the latest Anki version"),
+            ("collection.anki21b", &collection),
+            ("meta", &varint_field(1, 3)),
+            ("media", &media),
+            ("0", &file),
+            ("7", &other),
+        ]);
+        let package = read_package(&data).await.unwrap();
+
+        assert_eq!(package.created, legacy.created);
+        assert_eq!(package.note_types, legacy.note_types);
+        assert_eq!(package.decks, legacy.decks);
+        assert_eq!(package.notes, legacy.notes);
+        assert_eq!(package.cards, legacy.cards);
+        assert_eq!(package.reviews, legacy.reviews);
+        assert_eq!(package.media, vec![
+            ("mito.png".to_string(), vec![1, 2, 3]),
+            ("renamed.png".to_string(), vec![4]),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_packages() {
+        let data = archive(&[("collection.anki2", b"stub"), ("collection.anki21b", b"not zstd")]);
+        assert!(matches!(read_package(&data).await, Err(AnkiError::Io(_))));
+
+        let collection = collection().await;
+        let data = archive(&[("collection.anki2", &collection), ("media", b"\x0a\x05mito")]);
+        assert!(matches!(read_package(&data).await, Err(AnkiError::InvalidPackage(_))));
+
+        let media = zstd::encode_all([0x0au8, 0x05, b'm'].as_slice(), 0).unwrap();
+        let data = archive(&[("collection.anki2", &collection), ("meta", &varint_field(1, 3)), ("media", &media)]);
+        assert!(matches!(read_package(&data).await, Err(AnkiError::InvalidPackage(_))));
+
+        let data = archive(&[("media", b"{}")]);
+        assert!(matches!(read_package(&data).await, Err(AnkiError::InvalidPackage(_))));
+
+        assert!(matches!(read_package(b"not a zip").await, Err(AnkiError::Archive(_))));
+
+        // Files expanding past the limit are refused rather than read
+        let large = zstd::encode_all([0u8; 1024].as_slice(), 0).unwrap();
+        assert_eq!(super::super::package::decompress(&large, 1024).unwrap().len(), 1024);
+        assert!(matches!(super::super::package::decompress(&large, 1023), Err(AnkiError::InvalidPackage(_))));
+    }
+}
+
+mod conversion {
+    use super::*;
+
+    fn sample_import(package: &AnkiPackage, now: chrono::DateTime<Utc>) -> AnkiImport {
+        let assets = [("mito.png".to_string(), "a1".to_string())].into_iter().collect();
+        convert(package, &assets, Uuid::new_v4(), now)
+    }
+
+    #[tokio::test]
+    async fn test_cards_become_questions() {
+        let import = sample_import(&sample_package().await, Utc::now());
+
+        assert_eq!(import.quizzes.len(), 1);
+        let quiz = &import.quizzes[0];
+        assert_eq!(quiz.title, "Biology::Cells");
+        assert_eq!(quiz.study_mode, StudyMode::Flashcards);
+        assert_eq!(quiz.tags, vec!["biology".to_string(), "cells".to_string()]);
+        assert!(import.skipped.is_empty());
+
+        let basic = &quiz.questions[0];
+        assert_eq!(basic.content.text, "What is the powerhouse of the cell?");
+        assert_eq!(basic.content.image_url.as_deref(), Some("asset:a1"));
+        assert!(basic.content.rich_text.as_deref().unwrap().contains("<b>powerhouse</b>"));
+        assert_eq!(basic.correct_answer, Answer::Text("The mitochondria".to_string()));
+
+        let first_cloze = &quiz.questions[1];
+        assert_eq!(first_cloze.content.text, "[...] make proteins");
+        assert_eq!(first_cloze.correct_answer, Answer::Text("Ribosomes".to_string()));
+        assert_eq!(first_cloze.explanation.as_deref(), Some("Ribosomes make proteins\nTranslation"));
+
+        let second_cloze = &quiz.questions[2];
+        assert_eq!(second_cloze.content.text, "Ribosomes make [molecules]");
+        assert_eq!(second_cloze.correct_answer, Answer::Text("proteins".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_scheduling_is_carried_over() {
+        let now = Utc::now();
+        let import = sample_import(&sample_package().await, now);
+        let questions = &import.quizzes[0].questions;
+
+        // The suspended card has no scheduling state, but keeps its reviews
+        assert_eq!(import.flashcards.len(), 2);
+        assert_eq!(import.reviews.len(), 6);
+        assert!(import.reviews.iter().all(|review| review.deck_id == import.quizzes[0].id));
+        assert_eq!(import.reviews[2].rating, FlashcardRating::Blackout);
+
+        let review_card = &import.flashcards[0];
+        let created = Utc.timestamp_opt(CREATED, 0).unwrap();
+        assert_eq!(review_card.question_id, questions[0].id);
+        assert_eq!(review_card.ease_factor, 2.3);
+        assert_eq!(review_card.interval, 30);
+        assert_eq!(review_card.repetitions, 2);
+        assert_eq!(review_card.due_date, created + Duration::days(1500));
+        assert_eq!(review_card.last_reviewed, created + Duration::days(20));
+        assert!(review_card.stability.is_some() && review_card.difficulty.is_some());
+
+        let new_card = &import.flashcards[1];
+        assert_eq!(new_card.question_id, questions[1].id);
+        assert_eq!(new_card.due_date, now);
+        assert_eq!((new_card.repetitions, new_card.stability), (0, None));
+    }
+
+    #[tokio::test]
+    async fn test_broken_cards_are_skipped() {
+        let mut package = sample_package().await;
+        package.note_types.get_mut(&1).unwrap().templates.clear();
+        package.notes.retain(|note| note.id != 200);
+
+        let import = sample_import(&package, Utc::now());
+        assert!(import.quizzes.is_empty());
+        assert_eq!(import.skipped.len(), 3);
+        assert_eq!(import.skipped[0].identifier, "1000");
+        assert_eq!(import.skipped[0].title, "What is the powerhouse of the cell?");
+        assert_eq!(import.skipped[1].reason, "its note is missing");
+    }
+}
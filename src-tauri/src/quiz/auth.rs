@@ -42,6 +42,12 @@ impl QuizAuthService {
         }
     }
     
+    /// Check if a user is an instructor or an admin
+    pub async fn is_instructor(&self, user_id: Uuid) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let user = self.auth_service.get_user(user_id).await?;
+        Ok(user.has_role(Role::Admin) || user.has_role(Role::Instructor))
+    }
+    
     /// Check if a user has permission to perform an action on a quiz
     pub async fn check_quiz_permission(
         &self,
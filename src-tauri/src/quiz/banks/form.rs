@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use super::QuestionGroup;
+use crate::quiz::models::{AnswerType, Question};
+
+/// The questions of one attempt, in the order they are shown
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttemptForm {
+    /// Seed the form was generated from
+    pub seed: u64,
+
+    pub items: Vec<FormItem>,
+}
+
+/// A question on a form
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormItem {
+    pub question_id: String,
+
+    /// Bank and group the question was drawn through; `None` for the quiz's
+    /// own questions
+    pub bank_id: Option<Uuid>,
+    pub group_id: Option<Uuid>,
+
+    /// Order the choices are shown in; empty when they keep their order
+    pub choice_order: Vec<String>,
+}
+
+/// A question that can go on a form
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormCandidate {
+    pub question_id: String,
+
+    /// Choices that may be shuffled; empty when the order is fixed
+    pub choice_ids: Vec<String>,
+}
+
+impl FormCandidate {
+    /// True/false choices keep their order; other choice lists may shuffle
+    pub fn from_question(question: &Question) -> Self {
+        let choice_ids = match question.answer_type {
+            AnswerType::TrueFalse => Vec::new(),
+            _ => question.choices.iter().map(|choice| choice.id.to_string()).collect(),
+        };
+        Self { question_id: question.id.to_string(), choice_ids }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FormOptions {
+    pub shuffle_questions: bool,
+    pub shuffle_choices: bool,
+}
+
+impl AttemptForm {
+    /// Seed for an attempt's form, an FNV-1a hash of the attempt ID. The
+    /// draws made from it may differ between builds, so an attempt's form is
+    /// reproduced from the stored form, not regenerated from the seed.
+    pub fn seed_for_attempt(attempt_id: &str) -> u64 {
+        attempt_id.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Lay out a form: the quiz's own questions, each group's draw inserted
+    /// at the group's position, then the shuffles the options ask for.
+    ///
+    /// A question is drawn at most once even when several groups share a
+    /// bank or a bank repeats one of the quiz's questions; a group whose bank
+    /// runs short contributes what is left.
+    pub fn generate(
+        seed: u64,
+        fixed: &[FormCandidate],
+        groups: &[(QuestionGroup, Vec<FormCandidate>)],
+        options: FormOptions,
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut used: HashSet<&str> = fixed.iter().map(|candidate| candidate.question_id.as_str()).collect();
+        let mut entries: Vec<(&FormCandidate, Option<&QuestionGroup>)> =
+            fixed.iter().map(|candidate| (candidate, None)).collect();
+
+        let mut ordered: Vec<&(QuestionGroup, Vec<FormCandidate>)> = groups.iter().collect();
+        ordered.sort_by_key(|(group, _)| group.position);
+
+        let mut inserted = 0;
+        for (group, candidates) in ordered {
+            let mut available: Vec<&FormCandidate> = candidates.iter()
+                .filter(|candidate| !used.contains(candidate.question_id.as_str()))
+                .collect();
+            available.shuffle(&mut rng);
+            available.truncate(group.draw_count);
+
+            let at = (group.position + inserted).min(entries.len());
+            for (offset, candidate) in available.iter().copied().enumerate() {
+                used.insert(candidate.question_id.as_str());
+                entries.insert(at + offset, (candidate, Some(group)));
+            }
+            inserted += available.len();
+        }
+
+        if options.shuffle_questions {
+            entries.shuffle(&mut rng);
+        }
+
+        let items = entries.into_iter()
+            .map(|(candidate, group)| {
+                let mut choice_order = candidate.choice_ids.clone();
+                if options.shuffle_choices {
+                    choice_order.shuffle(&mut rng);
+                }
+                FormItem {
+                    question_id: candidate.question_id.clone(),
+                    bank_id: group.map(|group| group.bank_id),
+                    group_id: group.map(|group| group.id),
+                    choice_order,
+                }
+            })
+            .collect();
+
+        Self { seed, items }
+    }
+
+    pub fn item(&self, question_id: &str) -> Option<&FormItem> {
+        self.items.iter().find(|item| item.question_id == question_id)
+    }
+
+    pub fn contains(&self, question_id: &str) -> bool {
+        self.item(question_id).is_some()
+    }
+}
+
+impl FormItem {
+    /// Put `items` in the item's choice order. Items the order doesn't
+    /// mention, such as choices added after the form was made, go last.
+    pub fn order_choices<T>(&self, items: &mut [T], id: impl Fn(&T) -> String) {
+        if self.choice_order.is_empty() {
+            return;
+        }
+        items.sort_by_key(|item| {
+            let id = id(item);
+            self.choice_order.iter().position(|choice| *choice == id).unwrap_or(usize::MAX)
+        });
+    }
+}
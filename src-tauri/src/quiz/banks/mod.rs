@@ -0,0 +1,122 @@
+// Question banks and per-attempt forms
+//
+// A bank is a tagged set of questions that quizzes can share, visible to
+// others through its `QuizVisibility` like a quiz is. Quizzes draw from
+// banks through groups: each group puts `draw_count` random questions from
+// one bank at a position among the quiz's own questions.
+//
+// When an attempt starts, the draws, the question order and the choice
+// order are settled in an `AttemptForm` seeded from the attempt ID. The form
+// is stored with the attempt and synced with it, and grading, review and other
+// devices read it rather than drawing again, so all see the questions the
+// learner saw.
+
+mod form;
+mod storage;
+
+#[cfg(test)]
+mod tests;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::models::{Question, QuizVisibility};
+pub use form::{AttemptForm, FormCandidate, FormItem, FormOptions};
+pub use storage::BankStore;
+
+#[derive(Debug, Error)]
+pub enum BankError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Question bank not found: {0}")]
+    NotFound(Uuid),
+
+    #[error("Invalid stored data: {0}")]
+    Invalid(String),
+}
+
+/// A reusable set of questions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionBank {
+    pub id: Uuid,
+    pub title: String,
+    pub description: Option<String>,
+    pub author_id: Option<Uuid>,
+    pub visibility: QuizVisibility,
+    pub tags: Vec<String>,
+    pub questions: Vec<Question>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl QuestionBank {
+    pub fn new(title: String, author_id: Option<Uuid>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            title,
+            description: None,
+            author_id,
+            visibility: QuizVisibility::Private,
+            tags: Vec::new(),
+            questions: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Whether a user may draw from the bank, given the courses they belong to
+    pub fn is_visible_to(&self, user_id: Uuid, course_ids: &[Uuid]) -> bool {
+        if self.author_id == Some(user_id) {
+            return true;
+        }
+        match &self.visibility {
+            QuizVisibility::Public => true,
+            QuizVisibility::Private => false,
+            QuizVisibility::SharedWithUsers(users) => users.contains(&user_id),
+            QuizVisibility::Course(course_id) => course_ids.contains(course_id),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    pub fn question(&self, question_id: &str) -> Option<&Question> {
+        self.questions.iter().find(|question| question.id.to_string() == question_id)
+    }
+}
+
+/// Random questions from a bank, placed among a quiz's own questions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuestionGroup {
+    pub id: Uuid,
+    pub quiz_id: String,
+    pub bank_id: Uuid,
+    pub title: String,
+
+    /// Questions drawn for each attempt
+    pub draw_count: usize,
+
+    /// Number of the quiz's own questions that come before the group
+    pub position: usize,
+}
+
+impl QuestionGroup {
+    pub fn new(quiz_id: String, bank_id: Uuid, draw_count: usize, position: usize) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            quiz_id,
+            bank_id,
+            title: String::new(),
+            draw_count,
+            position,
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use super::{AttemptForm, BankError, QuestionBank, QuestionGroup};
+
+/// SQLite storage for banks, quiz groups and attempt forms
+#[derive(Debug, Clone)]
+pub struct BankStore {
+    pool: SqlitePool,
+}
+
+impl BankStore {
+    /// Create a store, ensuring the bank tables exist
+    pub async fn new(pool: SqlitePool) -> Result<Self, BankError> {
+        sqlx::query(include_str!("../../sql/quiz_bank_schema.sql"))
+            .execute(&pool)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    /// Insert or replace a bank
+    pub async fn save_bank(&self, bank: &QuestionBank) -> Result<(), BankError> {
+        sqlx::query(
+            "INSERT INTO question_banks (id, title, description, author_id, visibility, tags, questions, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT (id) DO UPDATE SET
+                title = excluded.title,
+                description = excluded.description,
+                author_id = excluded.author_id,
+                visibility = excluded.visibility,
+                tags = excluded.tags,
+                questions = excluded.questions,
+                updated_at = excluded.updated_at"
+        )
+        .bind(bank.id.to_string())
+        .bind(&bank.title)
+        .bind(&bank.description)
+        .bind(bank.author_id.map(|id| id.to_string()))
+        .bind(serde_json::to_string(&bank.visibility)?)
+        .bind(serde_json::to_string(&bank.tags)?)
+        .bind(serde_json::to_string(&bank.questions)?)
+        .bind(bank.created_at.to_rfc3339())
+        .bind(bank.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_bank(&self, bank_id: Uuid) -> Result<QuestionBank, BankError> {
+        let row = sqlx::query("SELECT * FROM question_banks WHERE id = ?")
+            .bind(bank_id.to_string())
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(BankError::NotFound(bank_id))?;
+
+        bank_from_row(&row)
+    }
+
+    /// Banks a user may draw from, optionally only those with a tag
+    pub async fn list_banks(&self, user_id: Uuid, course_ids: &[Uuid], tag: Option<&str>) -> Result<Vec<QuestionBank>, BankError> {
+        let rows = sqlx::query("SELECT * FROM question_banks ORDER BY title")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut banks = Vec::new();
+        for row in &rows {
+            let bank = bank_from_row(row)?;
+            if bank.is_visible_to(user_id, course_ids) && tag.is_none_or(|tag| bank.has_tag(tag)) {
+                banks.push(bank);
+            }
+        }
+        Ok(banks)
+    }
+
+    /// Delete a bank and the groups drawing from it
+    pub async fn delete_bank(&self, bank_id: Uuid) -> Result<(), BankError> {
+        sqlx::query("DELETE FROM quiz_question_groups WHERE bank_id = ?")
+            .bind(bank_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM question_banks WHERE id = ?")
+            .bind(bank_id.to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Insert or replace a quiz group; a group is never moved to another quiz
+    pub async fn save_group(&self, group: &QuestionGroup) -> Result<(), BankError> {
+        sqlx::query(
+            "INSERT INTO quiz_question_groups (id, quiz_id, bank_id, title, draw_count, position)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT (id) DO UPDATE SET
+                quiz_id = excluded.quiz_id,
+                bank_id = excluded.bank_id,
+                title = excluded.title,
+                draw_count = excluded.draw_count,
+                position = excluded.position
+             WHERE quiz_question_groups.quiz_id = excluded.quiz_id"
+        )
+        .bind(group.id.to_string())
+        .bind(&group.quiz_id)
+        .bind(group.bank_id.to_string())
+        .bind(&group.title)
+        .bind(group.draw_count as i64)
+        .bind(group.position as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete a group of a quiz
+    pub async fn delete_group(&self, quiz_id: &str, group_id: Uuid) -> Result<(), BankError> {
+        sqlx::query("DELETE FROM quiz_question_groups WHERE id = ? AND quiz_id = ?")
+            .bind(group_id.to_string())
+            .bind(quiz_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// A quiz's groups in position order
+    pub async fn groups_for_quiz(&self, quiz_id: &str) -> Result<Vec<QuestionGroup>, BankError> {
+        let rows = sqlx::query(
+            "SELECT id, quiz_id, bank_id, title, draw_count, position
+             FROM quiz_question_groups WHERE quiz_id = ? ORDER BY position, id"
+        )
+        .bind(quiz_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let id: String = row.get("id");
+                let bank_id: String = row.get("bank_id");
+                let draw_count: i64 = row.get("draw_count");
+                let position: i64 = row.get("position");
+                Ok(QuestionGroup {
+                    id: parse_uuid(&id)?,
+                    quiz_id: row.get("quiz_id"),
+                    bank_id: parse_uuid(&bank_id)?,
+                    title: row.get("title"),
+                    draw_count: draw_count.max(0) as usize,
+                    position: position.max(0) as usize,
+                })
+            })
+            .collect()
+    }
+
+    /// Store the form of an attempt. Forms never change once made, so a
+    /// second save for the same attempt keeps the first.
+    pub async fn save_form(&self, attempt_id: &str, form: &AttemptForm) -> Result<(), BankError> {
+        sqlx::query("INSERT OR IGNORE INTO quiz_attempt_forms (attempt_id, form, created_at) VALUES (?, ?, ?)")
+            .bind(attempt_id)
+            .bind(serde_json::to_string(form)?)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_form(&self, attempt_id: &str) -> Result<Option<AttemptForm>, BankError> {
+        let row = sqlx::query("SELECT form FROM quiz_attempt_forms WHERE attempt_id = ?")
+            .bind(attempt_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_str(row.get::<&str, _>("form"))?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn bank_from_row(row: &SqliteRow) -> Result<QuestionBank, BankError> {
+    let id: String = row.get("id");
+    let author_id: Option<String> = row.get("author_id");
+    let visibility: String = row.get("visibility");
+    let tags: String = row.get("tags");
+    let questions: String = row.get("questions");
+    let created_at: String = row.get("created_at");
+    let updated_at: String = row.get("updated_at");
+
+    Ok(QuestionBank {
+        id: parse_uuid(&id)?,
+        title: row.get("title"),
+        description: row.get("description"),
+        author_id: author_id.as_deref().map(parse_uuid).transpose()?,
+        visibility: serde_json::from_str(&visibility)?,
+        tags: serde_json::from_str(&tags)?,
+        questions: serde_json::from_str(&questions)?,
+        created_at: parse_time(&created_at)?,
+        updated_at: parse_time(&updated_at)?,
+    })
+}
+
+fn parse_uuid(value: &str) -> Result<Uuid, BankError> {
+    Uuid::parse_str(value).map_err(|_| BankError::Invalid(format!("invalid UUID {}", value)))
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, BankError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| BankError::Invalid(format!("invalid timestamp {}", value)))
+}
@@ -0,0 +1,205 @@
+use super::*;
+use crate::quiz::models::{AnswerType, Choice, QuestionContent};
+use sqlx::sqlite::SqlitePoolOptions;
+use std::collections::HashSet;
+
+fn candidate(id: &str, choices: &[&str]) -> FormCandidate {
+    FormCandidate {
+        question_id: id.to_string(),
+        choice_ids: choices.iter().map(|choice| choice.to_string()).collect(),
+    }
+}
+
+fn candidates(prefix: &str, count: usize) -> Vec<FormCandidate> {
+    (0..count).map(|i| candidate(&format!("{}{}", prefix, i), &["a", "b", "c", "d"])).collect()
+}
+
+fn question(text: &str) -> Question {
+    let content = QuestionContent {
+        text: text.to_string(),
+        rich_text: None,
+        image_url: None,
+        audio_url: None,
+        drag_drop_content: None,
+        hotspot_content: None,
+        drawing_content: None,
+        code_execution_content: None,
+        math_equation_content: None,
+        timeline_content: None,
+        diagram_labeling_content: None,
+        short_answer_content: None,
+    };
+    let mut question = Question::new(Uuid::nil(), content, AnswerType::MultipleChoice);
+    question.choices = ["yes", "no"].iter()
+        .map(|text| Choice {
+            id: Uuid::new_v4(),
+            text: text.to_string(),
+            rich_text: None,
+            image_url: None,
+            feedback: None,
+        })
+        .collect();
+    question
+}
+
+mod forms {
+    use super::*;
+
+    fn ids(form: &AttemptForm) -> Vec<&str> {
+        form.items.iter().map(|item| item.question_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_seed_is_stable() {
+        assert_eq!(AttemptForm::seed_for_attempt(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(AttemptForm::seed_for_attempt("attempt-1"), AttemptForm::seed_for_attempt("attempt-1"));
+        assert_ne!(AttemptForm::seed_for_attempt("attempt-1"), AttemptForm::seed_for_attempt("attempt-2"));
+    }
+
+    #[test]
+    fn test_draws_are_placed_and_seeded() {
+        let fixed = vec![candidate("intro", &[]), candidate("outro", &[])];
+        let group = QuestionGroup::new("quiz".to_string(), Uuid::new_v4(), 3, 1);
+        let groups = vec![(group.clone(), candidates("bank", 10))];
+
+        let form = AttemptForm::generate(7, &fixed, &groups, FormOptions::default());
+        assert_eq!(form.items.len(), 5);
+        assert_eq!(form.items[0].question_id, "intro");
+        assert_eq!(form.items[4].question_id, "outro");
+        assert!(form.items[1..4].iter().all(|item| item.group_id == Some(group.id) && item.bank_id == Some(group.bank_id)));
+        assert_eq!(form.items[0].group_id, None);
+
+        // The same seed gives the same form; other seeds draw differently
+        assert_eq!(form, AttemptForm::generate(7, &fixed, &groups, FormOptions::default()));
+        let draws: HashSet<Vec<String>> = (0..20)
+            .map(|seed| AttemptForm::generate(seed, &fixed, &groups, FormOptions::default()))
+            .map(|form| form.items.into_iter().map(|item| item.question_id).collect())
+            .collect();
+        assert!(draws.len() > 1);
+    }
+
+    #[test]
+    fn test_questions_are_drawn_once() {
+        let bank_id = Uuid::new_v4();
+        let shared = candidates("q", 4);
+        let groups = vec![
+            (QuestionGroup::new("quiz".to_string(), bank_id, 3, 0), shared.clone()),
+            (QuestionGroup::new("quiz".to_string(), bank_id, 3, 0), shared.clone()),
+        ];
+        let fixed = vec![candidate("q0", &[])];
+
+        let form = AttemptForm::generate(1, &fixed, &groups, FormOptions::default());
+        let unique: HashSet<&str> = ids(&form).into_iter().collect();
+        assert_eq!(form.items.len(), 4);
+        assert_eq!(unique.len(), 4);
+    }
+
+    #[test]
+    fn test_shuffles() {
+        let fixed = candidates("q", 12);
+        let options = FormOptions { shuffle_questions: true, shuffle_choices: true };
+        let form = AttemptForm::generate(3, &fixed, &[], options);
+
+        let mut shown = ids(&form);
+        assert_ne!(shown, fixed.iter().map(|c| c.question_id.as_str()).collect::<Vec<_>>());
+        shown.sort();
+        let mut expected: Vec<&str> = fixed.iter().map(|c| c.question_id.as_str()).collect();
+        expected.sort();
+        assert_eq!(shown, expected);
+
+        for item in &form.items {
+            let mut order = item.choice_order.clone();
+            order.sort();
+            assert_eq!(order, ["a", "b", "c", "d"]);
+        }
+        assert!(form.items.iter().any(|item| item.choice_order != ["a", "b", "c", "d"]));
+
+        // Fixed-order choices stay empty
+        let form = AttemptForm::generate(3, &[candidate("tf", &[])], &[], options);
+        assert!(form.items[0].choice_order.is_empty());
+    }
+
+    #[test]
+    fn test_order_choices() {
+        let item = FormItem {
+            question_id: "q".to_string(),
+            bank_id: None,
+            group_id: None,
+            choice_order: vec!["c".to_string(), "a".to_string(), "b".to_string()],
+        };
+        let mut choices = vec!["a", "b", "new", "c"];
+        item.order_choices(&mut choices, |choice| choice.to_string());
+        assert_eq!(choices, ["c", "a", "b", "new"]);
+    }
+
+    #[test]
+    fn test_candidate_from_question() {
+        let mut question = question("Is it?");
+        assert_eq!(FormCandidate::from_question(&question).choice_ids.len(), 2);
+        question.answer_type = AnswerType::TrueFalse;
+        assert!(FormCandidate::from_question(&question).choice_ids.is_empty());
+    }
+}
+
+mod banks {
+    use super::*;
+
+    #[test]
+    fn test_visibility() {
+        let author = Uuid::new_v4();
+        let student = Uuid::new_v4();
+        let course = Uuid::new_v4();
+        let mut bank = QuestionBank::new("Cells".to_string(), Some(author));
+
+        assert!(bank.is_visible_to(author, &[]));
+        assert!(!bank.is_visible_to(student, &[course]));
+        bank.visibility = QuizVisibility::SharedWithUsers(vec![student]);
+        assert!(bank.is_visible_to(student, &[]));
+        bank.visibility = QuizVisibility::Course(course);
+        assert!(bank.is_visible_to(student, &[course]));
+        assert!(!bank.is_visible_to(student, &[]));
+        bank.visibility = QuizVisibility::Public;
+        assert!(bank.is_visible_to(Uuid::new_v4(), &[]));
+    }
+
+    #[tokio::test]
+    async fn test_storage() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let store = BankStore::new(pool).await.unwrap();
+
+        let author = Uuid::new_v4();
+        let mut bank = QuestionBank::new("Cells".to_string(), Some(author));
+        bank.tags = vec!["Biology".to_string()];
+        bank.questions = vec![question("What is a ribosome?"), question("What is a cell wall?")];
+        store.save_bank(&bank).await.unwrap();
+
+        let loaded = store.get_bank(bank.id).await.unwrap();
+        assert_eq!(loaded.questions.len(), 2);
+        assert_eq!(loaded.question(&bank.questions[1].id.to_string()).unwrap().content.text, "What is a cell wall?");
+        assert_eq!(store.list_banks(author, &[], Some("biology")).await.unwrap().len(), 1);
+        assert!(store.list_banks(author, &[], Some("chemistry")).await.unwrap().is_empty());
+        assert!(store.list_banks(Uuid::new_v4(), &[], None).await.unwrap().is_empty());
+
+        let late = QuestionGroup::new("quiz-1".to_string(), bank.id, 1, 5);
+        let early = QuestionGroup::new("quiz-1".to_string(), bank.id, 2, 0);
+        store.save_group(&late).await.unwrap();
+        store.save_group(&early).await.unwrap();
+        assert_eq!(store.groups_for_quiz("quiz-1").await.unwrap(), vec![early.clone(), late.clone()]);
+
+        let groups = vec![(early, bank.questions.iter().map(FormCandidate::from_question).collect())];
+        let form = AttemptForm::generate(AttemptForm::seed_for_attempt("attempt"), &[], &groups, FormOptions::default());
+        store.save_form("attempt", &form).await.unwrap();
+        // A form is never replaced
+        store.save_form("attempt", &AttemptForm { seed: 0, items: Vec::new() }).await.unwrap();
+        assert_eq!(store.get_form("attempt").await.unwrap(), Some(form));
+        assert_eq!(store.get_form("other").await.unwrap(), None);
+
+        store.delete_bank(bank.id).await.unwrap();
+        assert!(matches!(store.get_bank(bank.id).await, Err(BankError::NotFound(_))));
+        assert!(store.groups_for_quiz("quiz-1").await.unwrap().is_empty());
+    }
+}
@@ -40,6 +40,7 @@ impl Cmi5Client {
         // Create a new HTTP client
         let mut headers = header::HeaderMap::new();
         headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json"));
+        headers.insert("X-Experience-API-Version", header::HeaderValue::from_static("1.0.3"));
         
         // Add authorization header if provided
         if let Some(token) = auth_token {
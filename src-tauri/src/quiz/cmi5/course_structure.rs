@@ -10,7 +10,10 @@ use std::io::Read;
 use uuid::Uuid;
 use zip::ZipArchive;
 use tracing::{info, error, debug};
-use crate::quiz::cmi5::models::{Cmi5Course, AssignableUnit, EntryMode, MoveOn, PassingScoreMethod, Objective};
+use xml::reader::{EventReader, XmlEvent};
+use crate::quiz::cmi5::models::{
+    Cmi5Course, Cmi5Block, CourseNode, AssignableUnit, EntryMode, LaunchMethod, MoveOn, PassingScoreMethod, Objective
+};
 
 /// Course structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,6 +85,10 @@ pub struct CourseAssignableUnit {
     /// Activity type
     #[serde(rename = "activityType", skip_serializing_if = "Option::is_none")]
     pub activity_type: Option<String>,
+    
+    /// Launch method
+    #[serde(rename = "launchMethod", skip_serializing_if = "Option::is_none")]
+    pub launch_method: Option<String>,
 }
 
 /// Parse a course structure from a cmi5 package
@@ -119,64 +126,161 @@ pub async fn parse_course_structure(package_path: &Path) -> Result<Cmi5Course> {
     cmi5_xml_file.read_to_string(&mut cmi5_xml_content)
         .map_err(|e| anyhow!("Failed to read cmi5.xml content: {}", e))?;
     
-    // Parse the XML
-    let course_structure: CourseStructure = serde_xml_rs::from_str(&cmi5_xml_content)
-        .map_err(|e| anyhow!("Failed to parse cmi5.xml: {}", e))?;
+    let course = parse_course_xml(&cmi5_xml_content)?;
     
-    // Convert to Cmi5Course
-    let assignable_units = course_structure.assignable_units.iter()
-        .map(|au| {
-            // Parse move on
-            let move_on = match au.move_on.as_str() {
-                "Completed" => MoveOn::Completed,
-                "Passed" => MoveOn::Passed,
-                "CompletedAndPassed" => MoveOn::CompletedAndPassed,
-                "CompletedOrPassed" => MoveOn::CompletedOrPassed,
-                "NotApplicable" => MoveOn::NotApplicable,
-                _ => MoveOn::NotApplicable,
-            };
-            
-            // Parse passing score method
-            let passing_score_method = au.passing_score_method.as_ref().map(|method| {
-                match method.as_str() {
-                    "percentage" => PassingScoreMethod::Percentage,
-                    "points" => PassingScoreMethod::Points,
-                    "scaled" => PassingScoreMethod::Scaled,
-                    _ => PassingScoreMethod::Scaled,
+    info!("Successfully parsed course structure: {}", course.title);
+    Ok(course)
+}
+
+/// Parse the course, blocks and AUs of a `cmi5.xml` document
+///
+/// Blocks may nest to any depth; the hierarchy is kept in `structure` while
+/// every AU is also listed in `assignable_units`, in document order.
+pub fn parse_course_xml(xml: &str) -> Result<Cmi5Course> {
+    let mut course = Cmi5Course {
+        id: String::new(),
+        title: String::new(),
+        description: None,
+        language: None,
+        version: None,
+        publisher: None,
+        assignable_units: Vec::new(),
+        structure: Vec::new(),
+        objectives: None,
+    };
+    
+    // Open elements, open blocks and the AU being read
+    let mut path: Vec<String> = Vec::new();
+    let mut blocks: Vec<Cmi5Block> = Vec::new();
+    let mut au: Option<AssignableUnit> = None;
+    let mut text = String::new();
+    
+    for event in EventReader::from_str(xml) {
+        match event.map_err(|e| anyhow!("Failed to parse cmi5.xml: {}", e))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let attr = |key: &str| attributes.iter()
+                    .find(|attribute| attribute.name.local_name == key)
+                    .map(|attribute| attribute.value.trim().to_string());
+                
+                match name.local_name.as_str() {
+                    "course" => course.id = attr("id").unwrap_or_default(),
+                    "block" => blocks.push(Cmi5Block {
+                        id: attr("id").ok_or_else(|| anyhow!("Block without id in cmi5.xml"))?,
+                        title: String::new(),
+                        description: None,
+                        children: Vec::new(),
+                    }),
+                    "au" => {
+                        let mastery_score = attr("masteryScore")
+                            .map(|score| score.parse::<f64>()
+                                .ok()
+                                .filter(|score| (0.0..=1.0).contains(score))
+                                .ok_or_else(|| anyhow!("Invalid masteryScore in cmi5.xml: {}", score)))
+                            .transpose()?;
+                        
+                        au = Some(AssignableUnit {
+                            id: attr("id").ok_or_else(|| anyhow!("AU without id in cmi5.xml"))?,
+                            title: String::new(),
+                            description: None,
+                            launch_url: String::new(),
+                            launch_parameters: None,
+                            entry_mode: EntryMode::Normal,
+                            move_on: parse_move_on(attr("moveOn").as_deref())?,
+                            mastery_score,
+                            passing_score_method: None,
+                            max_attempts: None,
+                            activity_type: attr("activityType"),
+                            launch_method: match attr("launchMethod").as_deref() {
+                                None | Some("AnyWindow") => LaunchMethod::AnyWindow,
+                                Some("OwnWindow") => LaunchMethod::OwnWindow,
+                                Some(other) => return Err(anyhow!("Invalid launchMethod in cmi5.xml: {}", other)),
+                            },
+                        });
+                    }
+                    _ => {}
                 }
-            });
-            
-            AssignableUnit {
-                id: au.id.clone(),
-                title: au.title.clone(),
-                description: au.description.clone(),
-                launch_url: au.url.clone(),
-                launch_parameters: au.launch_parameters.clone(),
-                entry_mode: EntryMode::Normal,
-                move_on,
-                mastery_score: au.mastery_score,
-                passing_score_method,
-                max_attempts: au.max_attempts,
-                activity_type: au.activity_type.clone(),
+                
+                path.push(name.local_name);
+                text.clear();
             }
-        })
-        .collect();
+            XmlEvent::Characters(chars) | XmlEvent::CData(chars) => text.push_str(&chars),
+            XmlEvent::EndElement { .. } => {
+                let name = path.pop().unwrap_or_default();
+                let parent = path.last().map(String::as_str).unwrap_or_default();
+                let owner = path.iter().rev().nth(1).map(String::as_str).unwrap_or_default();
+                let value = text.trim().to_string();
+                
+                match (name.as_str(), parent, owner) {
+                    // Only the first language of a title or description is kept
+                    ("langstring", "title" | "description", owner) => {
+                        let description = parent == "description";
+                        let target = match owner {
+                            "au" => au.as_mut().map(|au| if description { au.description.get_or_insert_with(String::new) } else { &mut au.title }),
+                            "block" => blocks.last_mut().map(|block| if description { block.description.get_or_insert_with(String::new) } else { &mut block.title }),
+                            "course" => Some(if description { course.description.get_or_insert_with(String::new) } else { &mut course.title }),
+                            _ => None,
+                        };
+                        if let Some(target) = target.filter(|target| target.is_empty()) {
+                            *target = value;
+                        }
+                    }
+                    ("url", "au", _) => {
+                        if let Some(au) = au.as_mut() {
+                            au.launch_url = value;
+                        }
+                    }
+                    ("launchParameters", "au", _) => {
+                        if let Some(au) = au.as_mut() {
+                            au.launch_parameters = Some(value).filter(|value| !value.is_empty());
+                        }
+                    }
+                    ("au", _, _) => {
+                        let au = au.take().ok_or_else(|| anyhow!("Unbalanced cmi5.xml"))?;
+                        let node = CourseNode::AssignableUnit(au.id.clone());
+                        match blocks.last_mut() {
+                            Some(block) => block.children.push(node),
+                            None => course.structure.push(node),
+                        }
+                        course.assignable_units.push(au);
+                    }
+                    ("block", _, _) => {
+                        let block = blocks.pop().ok_or_else(|| anyhow!("Unbalanced cmi5.xml"))?;
+                        match blocks.last_mut() {
+                            Some(parent) => parent.children.push(CourseNode::Block(block)),
+                            None => course.structure.push(CourseNode::Block(block)),
+                        }
+                    }
+                    _ => {}
+                }
+                
+                text.clear();
+            }
+            _ => {}
+        }
+    }
     
-    let course = Cmi5Course {
-        id: course_structure.id,
-        title: course_structure.title,
-        description: course_structure.description,
-        language: course_structure.language,
-        version: course_structure.version,
-        publisher: course_structure.publisher,
-        assignable_units,
-        objectives: None,
-    };
+    if course.id.is_empty() {
+        return Err(anyhow!("cmi5.xml has no course element"));
+    }
+    if course.assignable_units.is_empty() {
+        return Err(anyhow!("cmi5.xml contains no AUs"));
+    }
     
-    info!("Successfully parsed course structure: {}", course.title);
     Ok(course)
 }
 
+/// Parse the `moveOn` attribute of an AU, which defaults to `NotApplicable`
+fn parse_move_on(move_on: Option<&str>) -> Result<MoveOn> {
+    match move_on {
+        Some("Completed") => Ok(MoveOn::Completed),
+        Some("Passed") => Ok(MoveOn::Passed),
+        Some("CompletedAndPassed") => Ok(MoveOn::CompletedAndPassed),
+        Some("CompletedOrPassed") => Ok(MoveOn::CompletedOrPassed),
+        Some("NotApplicable") | None => Ok(MoveOn::NotApplicable),
+        Some(other) => Err(anyhow!("Invalid moveOn in cmi5.xml: {}", other)),
+    }
+}
+
 /// Extract a cmi5 package
 pub async fn extract_package(package_path: &Path, output_dir: &Path) -> Result<PathBuf> {
     info!("Extracting package from: {} to: {}", package_path.display(), output_dir.display());
@@ -264,6 +368,10 @@ pub async fn create_package(course: &Cmi5Course, output_path: &Path) -> Result<(
                 passing_score_method: passing_score_method.map(String::from),
                 max_attempts: au.max_attempts,
                 activity_type: au.activity_type.clone(),
+                launch_method: match au.launch_method {
+                    LaunchMethod::AnyWindow => None,
+                    LaunchMethod::OwnWindow => Some("OwnWindow".to_string()),
+                },
             }
         })
         .collect();
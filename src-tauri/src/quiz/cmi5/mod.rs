@@ -13,11 +13,15 @@ mod models;
 mod statements;
 mod course_structure;
 mod launch;
+mod satisfaction;
+#[cfg(test)]
+mod tests;
 
 pub use client::Cmi5Client;
 pub use models::{
     Cmi5State, Cmi5Context, Cmi5LaunchData, Cmi5LaunchParameters,
-    AssignableUnit, Cmi5Course, Cmi5Verb, Cmi5Result, Cmi5Score
+    AssignableUnit, Cmi5Block, Cmi5Course, CourseNode, Cmi5Verb, Cmi5Result, Cmi5Score,
+    LaunchMethod, MoveOn
 };
 pub use statements::{
     Cmi5Statement, Cmi5StatementBuilder, StatementType,
@@ -26,8 +30,9 @@ pub use statements::{
     create_terminated_statement, create_satisfied_statement,
     create_abandoned_statement, create_waived_statement
 };
-pub use course_structure::{CourseStructure, parse_course_structure};
+pub use course_structure::{CourseStructure, parse_course_structure, parse_course_xml};
 pub use launch::{LaunchService, LaunchParameters};
+pub use satisfaction::{AuProgress, Cmi5Registration, StatementRejected, check_statement, move_on_met};
 
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -36,7 +41,7 @@ use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 use std::collections::HashMap;
 use std::path::Path;
-use tracing::{info, error, warn};
+use tracing::{debug, info, error, warn};
 
 /// Main cmi5 service for the Ordo LMS
 pub struct Cmi5Service {
@@ -49,6 +54,9 @@ pub struct Cmi5Service {
     /// Course structures
     courses: Arc<Mutex<HashMap<String, Cmi5Course>>>,
     
+    /// Registrations by registration ID
+    registrations: Arc<Mutex<HashMap<String, Cmi5Registration>>>,
+    
     /// Launch service
     launch_service: Arc<LaunchService>,
 }
@@ -88,6 +96,23 @@ pub struct Cmi5Session {
     
     /// Session result
     pub result: Option<Cmi5Result>,
+    
+    /// Defined statements sent in the session, in order
+    #[serde(default)]
+    pub statements: Vec<StatementType>,
+}
+
+/// A launched assignable unit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cmi5Launch {
+    /// Session ID
+    pub session_id: String,
+    
+    /// Launch URL
+    pub url: String,
+    
+    /// How the AU must be presented
+    pub launch_method: LaunchMethod,
 }
 
 /// Launch mode for cmi5 content
@@ -116,6 +141,7 @@ impl Cmi5Service {
             client,
             sessions: Arc::new(Mutex::new(HashMap::new())),
             courses: Arc::new(Mutex::new(HashMap::new())),
+            registrations: Arc::new(Mutex::new(HashMap::new())),
             launch_service,
         })
     }
@@ -137,27 +163,64 @@ impl Cmi5Service {
     }
     
     /// Launch an assignable unit
+    ///
+    /// Launches of the same course by the same actor share one registration.
+    /// The AU's moveOn criteria and mastery score are passed to it through
+    /// the `LMS.LaunchData` state document, and the returned launch method
+    /// tells the caller whether the AU needs a window of its own.
     pub async fn launch_assignable_unit(
         &self,
         course_id: &str,
         au_id: &str,
         actor_id: &str,
         launch_mode: LaunchMode,
-    ) -> Result<String> {
+    ) -> Result<Cmi5Launch> {
         info!("Launching assignable unit: {} for actor: {}", au_id, actor_id);
         
-        // Get the course structure
-        let courses = self.courses.lock().await;
-        let course = courses.get(course_id)
-            .ok_or_else(|| anyhow!("Course not found: {}", course_id))?;
-        
-        // Find the assignable unit
-        let au = course.assignable_units.iter()
-            .find(|au| au.id == au_id)
-            .ok_or_else(|| anyhow!("Assignable unit not found: {}", au_id))?;
+        // Copy what the launch needs, so that no lock is held while the LRS
+        // is written to
+        let (au, registration_id, new_registration) = {
+            let courses = self.courses.lock().await;
+            let course = courses.get(course_id)
+                .ok_or_else(|| anyhow!("Course not found: {}", course_id))?;
+            
+            // Find the assignable unit
+            let au = course.assignable_units.iter()
+                .find(|au| au.id == au_id)
+                .ok_or_else(|| anyhow!("Assignable unit not found: {}", au_id))?
+                .clone();
+            
+            // Reuse the actor's registration for the course, or create one
+            let registrations = self.registrations.lock().await;
+            match find_registration(&registrations, actor_id, course_id) {
+                Some(registration_id) => (au, registration_id, None),
+                None => {
+                    let registration_id = Uuid::new_v4().to_string();
+                    let mut registration = Cmi5Registration::new(&registration_id, actor_id, course_id);
+                    
+                    // AUs without moveOn criteria are satisfied from the start
+                    let satisfied = registration.evaluate(course);
+                    (au, registration_id, Some((registration, satisfied)))
+                }
+            }
+        };
         
-        // Generate a registration ID
-        let registration_id = Uuid::new_v4().to_string();
+        let registration_id = match new_registration {
+            Some((registration, satisfied)) => {
+                self.send_satisfied(&registration, satisfied).await?;
+                
+                // A launch running meanwhile may have registered the actor first
+                let mut registrations = self.registrations.lock().await;
+                match find_registration(&registrations, actor_id, course_id) {
+                    Some(registration_id) => registration_id,
+                    None => {
+                        registrations.insert(registration_id.clone(), registration);
+                        registration_id
+                    }
+                }
+            }
+            None => registration_id,
+        };
         
         // Create launch parameters
         let launch_parameters = self.launch_service.create_launch_parameters(
@@ -175,18 +238,46 @@ impl Cmi5Service {
             actor_id: actor_id.to_string(),
             course_id: course_id.to_string(),
             au_id: au_id.to_string(),
-            registration_id,
+            registration_id: registration_id.clone(),
             launch_mode,
             launch_parameters: launch_parameters.clone(),
             state: Cmi5State::NotInitialized,
             start_time: chrono::Utc::now(),
             end_time: None,
             result: None,
+            statements: Vec::new(),
         };
         
+        // Provide the launch data the AU reads on startup
+        let mut launch_data = serde_json::json!({
+            "contextTemplate": {
+                "contextActivities": {
+                    "grouping": [{ "id": au.id }],
+                },
+                "extensions": {
+                    "https://w3id.org/xapi/cmi5/context/extensions/sessionid": session_id,
+                },
+            },
+            "launchMode": format!("{:?}", launch_mode),
+            "moveOn": au.move_on,
+        });
+        if let Some(mastery_score) = au.mastery_score {
+            launch_data["masteryScore"] = serde_json::json!(mastery_score);
+        }
+        if let Some(parameters) = &au.launch_parameters {
+            launch_data["launchParameters"] = serde_json::json!(parameters);
+        }
+        
+        self.client.set_state(
+            &launch_parameters.activity_id,
+            actor_id,
+            "LMS.LaunchData",
+            &launch_data,
+            Some(&registration_id),
+        ).await?;
+        
         // Store the session
-        let mut sessions = self.sessions.lock().await;
-        sessions.insert(session_id.clone(), session);
+        self.sessions.lock().await.insert(session_id.clone(), session);
         
         // Generate the launch URL
         let launch_url = self.launch_service.generate_launch_url(
@@ -195,40 +286,30 @@ impl Cmi5Service {
         )?;
         
         info!("Successfully created launch URL for session: {}", session_id);
-        Ok(launch_url)
+        Ok(Cmi5Launch {
+            session_id,
+            url: launch_url,
+            launch_method: au.launch_method,
+        })
     }
     
     /// Initialize a session
     pub async fn initialize_session(&self, session_id: &str) -> Result<()> {
         info!("Initializing session: {}", session_id);
         
-        // Get the session
-        let mut sessions = self.sessions.lock().await;
-        let session = sessions.get_mut(session_id)
-            .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
-        
-        // Check if the session is already initialized
-        if session.state != Cmi5State::NotInitialized {
-            return Err(anyhow!("Session already initialized"));
-        }
-        
-        // Create and send the initialized statement
-        let statement = create_initialized_statement(
-            &session.actor_id,
-            &session.au_id,
-            &session.registration_id,
-        );
-        
-        self.client.send_statement(&statement).await?;
-        
-        // Update the session state
-        session.state = Cmi5State::Initialized;
+        self.record_statement(session_id, StatementType::Initialized, None).await?;
         
         info!("Successfully initialized session: {}", session_id);
         Ok(())
     }
     
     /// Complete a session
+    ///
+    /// Completed, passed and failed are sent where they are allowed; those
+    /// the session does not need, such as completed for an AU completed in an
+    /// earlier session or any of them in Browse mode, are skipped. The session
+    /// is terminated in any case, after which the first statement rejected for
+    /// another reason, or the first failure to send one, is returned.
     pub async fn complete_session(
         &self,
         session_id: &str,
@@ -237,66 +318,151 @@ impl Cmi5Service {
     ) -> Result<()> {
         info!("Completing session: {}", session_id);
         
-        // Get the session
-        let mut sessions = self.sessions.lock().await;
-        let session = sessions.get_mut(session_id)
-            .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
-        
-        // Check if the session is initialized
-        if session.state != Cmi5State::Initialized && session.state != Cmi5State::InProgress {
-            return Err(anyhow!("Session not initialized or in progress"));
+        let mut outcome = vec![(StatementType::Completed, None)];
+        if let Some(success) = success {
+            let statement = if success { StatementType::Passed } else { StatementType::Failed };
+            outcome.push((statement, score.clone()));
         }
         
-        // Create and send the completed statement
-        let statement = create_completed_statement(
-            &session.actor_id,
-            &session.au_id,
-            &session.registration_id,
-        );
+        let mut first_error = None;
+        let mut judged = false;
+        for (statement_type, statement_score) in outcome {
+            match self.record_statement(session_id, statement_type, statement_score).await {
+                Ok(()) => judged |= statement_type != StatementType::Completed,
+                Err(e) if e.downcast_ref::<StatementRejected>().is_some_and(StatementRejected::is_redundant) => {
+                    debug!("Skipped {} statement in session {}: {}", statement_type.verb(), session_id, e);
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
         
-        self.client.send_statement(&statement).await?;
+        let terminated = self.record_statement(session_id, StatementType::Terminated, None).await;
         
-        // If success is provided, send the appropriate statement
-        if let Some(success) = success {
-            let statement = if success {
-                create_passed_statement(
-                    &session.actor_id,
-                    &session.au_id,
-                    &session.registration_id,
-                    score.clone(),
-                )
-            } else {
-                create_failed_statement(
-                    &session.actor_id,
-                    &session.au_id,
-                    &session.registration_id,
-                    score.clone(),
-                )
-            };
+        // Update the session result
+        {
+            let mut sessions = self.sessions.lock().await;
+            let session = sessions.get_mut(session_id)
+                .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
             
-            self.client.send_statement(&statement).await?;
+            session.result = Some(Cmi5Result {
+                score,
+                success: success.filter(|_| judged),
+                completion: Some(true),
+                duration: session.end_time.map(|end_time| end_time - session.start_time),
+            });
         }
         
-        // Create and send the terminated statement
-        let statement = create_terminated_statement(
-            &session.actor_id,
-            &session.au_id,
-            &session.registration_id,
-        );
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+        terminated?;
+        
+        info!("Successfully completed session: {}", session_id);
+        Ok(())
+    }
+    
+    /// Record a defined statement of a session
+    ///
+    /// The statement is checked against the session and registration before
+    /// it is sent, see [`check_statement`]. Once recorded, the moveOn criteria
+    /// of the course are evaluated and satisfied statements are sent for the
+    /// AUs, blocks and course that became satisfied. No lock is held while
+    /// statements are sent to the LRS.
+    pub async fn record_statement(
+        &self,
+        session_id: &str,
+        statement_type: StatementType,
+        score: Option<Cmi5Score>,
+    ) -> Result<()> {
+        let statement = {
+            let courses = self.courses.lock().await;
+            let sessions = self.sessions.lock().await;
+            let registrations = self.registrations.lock().await;
+            
+            let session = sessions.get(session_id)
+                .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
+            let course = courses.get(&session.course_id)
+                .ok_or_else(|| anyhow!("Course not found: {}", session.course_id))?;
+            let au = course.assignable_units.iter()
+                .find(|au| au.id == session.au_id)
+                .ok_or_else(|| anyhow!("Assignable unit not found: {}", session.au_id))?;
+            let registration = registrations.get(&session.registration_id)
+                .ok_or_else(|| anyhow!("Registration not found: {}", session.registration_id))?;
+            
+            if let Err(e) = check_statement(statement_type, session, registration, au, score.as_ref()) {
+                warn!("Rejected {} statement in session {}: {}", statement_type.verb(), session_id, e);
+                return Err(e.into());
+            }
+            
+            let (actor_id, au_id, registration_id) = (&session.actor_id, &session.au_id, &session.registration_id);
+            match statement_type {
+                StatementType::Initialized => create_initialized_statement(actor_id, au_id, registration_id),
+                StatementType::Completed => create_completed_statement(actor_id, au_id, registration_id),
+                StatementType::Passed => create_passed_statement(actor_id, au_id, registration_id, score),
+                StatementType::Failed => create_failed_statement(actor_id, au_id, registration_id, score),
+                StatementType::Terminated => create_terminated_statement(actor_id, au_id, registration_id),
+                StatementType::Satisfied | StatementType::Abandoned | StatementType::Waived => {
+                    return Err(anyhow!("{} statements are issued by the LMS only", statement_type.verb()));
+                }
+            }
+        };
         
         self.client.send_statement(&statement).await?;
         
-        // Update the session state and result
-        session.state = Cmi5State::Completed;
-        session.end_time = Some(chrono::Utc::now());
-        session.result = Some(Cmi5Result {
-            score,
-            success,
-            completion: Some(true),
-            duration: Some(session.end_time.unwrap() - session.start_time),
-        });
+        let (registration, satisfied) = {
+            let courses = self.courses.lock().await;
+            let mut sessions = self.sessions.lock().await;
+            let mut registrations = self.registrations.lock().await;
+            
+            let session = sessions.get_mut(session_id)
+                .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
+            let course = courses.get(&session.course_id)
+                .ok_or_else(|| anyhow!("Course not found: {}", session.course_id))?;
+            let registration = registrations.get_mut(&session.registration_id)
+                .ok_or_else(|| anyhow!("Registration not found: {}", session.registration_id))?;
+            
+            // Update the session state
+            session.statements.push(statement_type);
+            match statement_type {
+                StatementType::Initialized => session.state = Cmi5State::Initialized,
+                StatementType::Completed => session.state = Cmi5State::Completed,
+                StatementType::Passed => session.state = Cmi5State::Passed,
+                StatementType::Failed => session.state = Cmi5State::Failed,
+                StatementType::Terminated => {
+                    if session.state == Cmi5State::Initialized {
+                        session.state = Cmi5State::Terminated;
+                    }
+                    session.end_time = Some(chrono::Utc::now());
+                }
+                _ => {}
+            }
+            
+            registration.record(&session.au_id, statement_type);
+            let satisfied = registration.evaluate(course);
+            (registration.clone(), satisfied)
+        };
+        
+        self.send_satisfied(&registration, satisfied).await?;
+        
+        Ok(())
+    }
+    
+    /// Send satisfied statements for newly satisfied AUs, blocks and courses
+    async fn send_satisfied(&self, registration: &Cmi5Registration, satisfied: Vec<String>) -> Result<()> {
+        for activity_id in satisfied {
+            info!("Registration {} satisfied: {}", registration.id, activity_id);
+            
+            let statement = create_satisfied_statement(
+                &registration.actor_id,
+                &activity_id,
+                &registration.id,
+            );
+            
+            self.client.send_statement(&statement).await?;
+        }
         
-        info!("Successfully completed session: {}", session_id);
         Ok(())
     }
     
@@ -304,13 +470,18 @@ impl Cmi5Service {
     pub async fn abandon_session(&self, session_id: &str) -> Result<()> {
         info!("Abandoning session: {}", session_id);
         
-        // Get the session
-        let mut sessions = self.sessions.lock().await;
-        let session = sessions.get_mut(session_id)
+        // Get a copy of the session, so that no lock is held while the
+        // statement is sent
+        let session = self.sessions.lock().await
+            .get(session_id)
+            .cloned()
             .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
         
-        // Check if the session is initialized or in progress
-        if session.state != Cmi5State::Initialized && session.state != Cmi5State::InProgress {
+        // Check if the session is initialized and not yet ended
+        if !session.statements.contains(&StatementType::Initialized)
+            || session.statements.contains(&StatementType::Terminated)
+            || matches!(session.state, Cmi5State::Abandoned | Cmi5State::Waived)
+        {
             return Err(anyhow!("Session not initialized or in progress"));
         }
         
@@ -324,23 +495,28 @@ impl Cmi5Service {
         self.client.send_statement(&statement).await?;
         
         // Update the session state
-        session.state = Cmi5State::Abandoned;
-        session.end_time = Some(chrono::Utc::now());
+        if let Some(session) = self.sessions.lock().await.get_mut(session_id) {
+            session.state = Cmi5State::Abandoned;
+            session.end_time = Some(chrono::Utc::now());
+        }
         
         info!("Successfully abandoned session: {}", session_id);
         Ok(())
     }
     
     /// Waive a session
+    ///
+    /// Waiving satisfies the session's AU in its registration regardless of
+    /// the AU's moveOn criteria.
     pub async fn waive_session(&self, session_id: &str, reason: &str) -> Result<()> {
         info!("Waiving session: {}", session_id);
         
-        // Get the session
-        let mut sessions = self.sessions.lock().await;
-        let session = sessions.get_mut(session_id)
+        // Create the waived statement from a copy of the session, so that no
+        // lock is held while it is sent
+        let session = self.sessions.lock().await
+            .get(session_id)
+            .cloned()
             .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
-        
-        // Create and send the waived statement
         let statement = create_waived_statement(
             &session.actor_id,
             &session.au_id,
@@ -350,9 +526,30 @@ impl Cmi5Service {
         
         self.client.send_statement(&statement).await?;
         
-        // Update the session state
-        session.state = Cmi5State::Waived;
-        session.end_time = Some(chrono::Utc::now());
+        // Update the session state, and satisfy the AU and whatever contains it
+        let satisfaction = {
+            let courses = self.courses.lock().await;
+            let mut sessions = self.sessions.lock().await;
+            let mut registrations = self.registrations.lock().await;
+            
+            if let Some(session) = sessions.get_mut(session_id) {
+                session.state = Cmi5State::Waived;
+                session.end_time = Some(chrono::Utc::now());
+            }
+            
+            match (courses.get(&session.course_id), registrations.get_mut(&session.registration_id)) {
+                (Some(course), Some(registration)) => {
+                    registration.record(&session.au_id, StatementType::Waived);
+                    let satisfied = registration.evaluate(course);
+                    Some((registration.clone(), satisfied))
+                }
+                _ => None,
+            }
+        };
+        
+        if let Some((registration, satisfied)) = satisfaction {
+            self.send_satisfied(&registration, satisfied).await?;
+        }
         
         info!("Successfully waived session: {}", session_id);
         Ok(())
@@ -376,6 +573,16 @@ impl Cmi5Service {
         Ok(session.result.clone())
     }
     
+    /// Get an actor's registration for a course, if the course was launched
+    pub async fn get_registration(&self, actor_id: &str, course_id: &str) -> Result<Option<Cmi5Registration>> {
+        let registrations = self.registrations.lock().await;
+        let registration = registrations.values()
+            .find(|registration| registration.actor_id == actor_id && registration.course_id == course_id)
+            .cloned();
+        
+        Ok(registration)
+    }
+    
     /// Get all sessions for an actor
     pub async fn get_actor_sessions(&self, actor_id: &str) -> Result<Vec<Cmi5Session>> {
         let sessions = self.sessions.lock().await;
@@ -416,3 +623,10 @@ impl Cmi5Service {
         Ok(all_courses)
     }
 }
+
+/// ID of the actor's registration for the course, if there is one
+fn find_registration(registrations: &HashMap<String, Cmi5Registration>, actor_id: &str, course_id: &str) -> Option<String> {
+    registrations.values()
+        .find(|registration| registration.actor_id == actor_id && registration.course_id == course_id)
+        .map(|registration| registration.id.clone())
+}
@@ -191,6 +191,24 @@ pub struct AssignableUnit {
     /// Activity type
     #[serde(rename = "activityType", skip_serializing_if = "Option::is_none")]
     pub activity_type: Option<String>,
+    
+    /// Launch method
+    #[serde(rename = "launchMethod", default)]
+    pub launch_method: LaunchMethod,
+}
+
+/// Launch method
+///
+/// Whether the LMS may present the AU in a frame of its own window or must
+/// give the AU a window to itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LaunchMethod {
+    /// Any window, including a frame within the LMS
+    #[default]
+    AnyWindow,
+    
+    /// A new window owned by the AU
+    OwnWindow,
 }
 
 /// Entry mode
@@ -278,11 +296,59 @@ pub struct Cmi5Course {
     #[serde(rename = "assignableUnits")]
     pub assignable_units: Vec<AssignableUnit>,
     
+    /// Top level AUs and blocks in course structure order; when empty, all
+    /// assignable units sit directly below the course
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub structure: Vec<CourseNode>,
+    
     /// Objectives
     #[serde(skip_serializing_if = "Option::is_none")]
     pub objectives: Option<Vec<Objective>>,
 }
 
+/// Block grouping AUs and nested blocks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cmi5Block {
+    /// ID
+    pub id: String,
+    
+    /// Title
+    pub title: String,
+    
+    /// Description
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    
+    /// AUs and blocks in the block
+    pub children: Vec<CourseNode>,
+}
+
+/// Node of the course hierarchy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum CourseNode {
+    /// Assignable unit, by ID
+    #[serde(rename = "au")]
+    AssignableUnit(String),
+    
+    /// Block
+    #[serde(rename = "block")]
+    Block(Cmi5Block),
+}
+
+impl Cmi5Course {
+    /// Top level nodes of the course hierarchy
+    pub fn top_level(&self) -> Vec<CourseNode> {
+        if self.structure.is_empty() {
+            self.assignable_units.iter()
+                .map(|au| CourseNode::AssignableUnit(au.id.clone()))
+                .collect()
+        } else {
+            self.structure.clone()
+        }
+    }
+}
+
 /// Objective
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Objective {
@@ -0,0 +1,238 @@
+// cmi5 moveOn Evaluation
+//
+// This module checks the defined statements an AU sends against the rules of
+// the cmi5 specification and decides when AUs, blocks and the course are
+// satisfied. An AU is satisfied once its moveOn criteria are met within the
+// registration, or once it has been waived; a block or the course once all of
+// its children are. Satisfaction is never revoked.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+use super::models::{AssignableUnit, Cmi5Course, Cmi5Score, Cmi5State, CourseNode, MoveOn};
+use super::statements::StatementType;
+use super::{Cmi5Session, LaunchMode};
+
+/// Reasons a defined statement is rejected
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum StatementRejected {
+    #[error("The session has not been initialized")]
+    NotInitialized,
+
+    #[error("The session has already been initialized")]
+    AlreadyInitialized,
+
+    #[error("The session has ended")]
+    SessionEnded,
+
+    #[error("The AU has already been completed in this registration")]
+    AlreadyCompleted,
+
+    #[error("The AU has already been passed in this registration")]
+    AlreadyPassed,
+
+    #[error("The AU has already failed in this session")]
+    AlreadyFailed,
+
+    #[error("{0} statements are not allowed in {1:?} mode")]
+    LaunchMode(&'static str, LaunchMode),
+
+    #[error("A {0} statement requires a score when the AU has a mastery score")]
+    ScoreRequired(&'static str),
+
+    #[error("A scaled score of {score} contradicts {verb} with a mastery score of {mastery_score}")]
+    MasteryScore { verb: &'static str, score: f64, mastery_score: f64 },
+
+    #[error("{0} statements are issued by the LMS only")]
+    LmsOnly(&'static str),
+}
+
+impl StatementRejected {
+    /// Whether the statement is merely not needed in the session: the outcome
+    /// was already recorded, or the launch mode does not record outcomes
+    pub fn is_redundant(&self) -> bool {
+        matches!(
+            self,
+            StatementRejected::AlreadyCompleted
+                | StatementRejected::AlreadyPassed
+                | StatementRejected::AlreadyFailed
+                | StatementRejected::LaunchMode(..)
+        )
+    }
+}
+
+/// Outcome of an AU within a registration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuProgress {
+    /// A completed statement was recorded
+    pub completed: bool,
+
+    /// A passed statement was recorded
+    pub passed: bool,
+
+    /// The LMS waived the AU
+    pub waived: bool,
+}
+
+/// A learner's registration for a course
+///
+/// All sessions of the learner in the course share the registration, so
+/// moveOn criteria met in different sessions combine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cmi5Registration {
+    /// Registration ID
+    pub id: String,
+
+    /// Actor (learner) ID
+    pub actor_id: String,
+
+    /// Course ID
+    pub course_id: String,
+
+    /// Progress by AU ID
+    pub assignable_units: HashMap<String, AuProgress>,
+
+    /// IDs of the satisfied AUs, blocks and course
+    pub satisfied: HashSet<String>,
+}
+
+impl Cmi5Registration {
+    /// Create an empty registration
+    pub fn new(id: &str, actor_id: &str, course_id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            actor_id: actor_id.to_string(),
+            course_id: course_id.to_string(),
+            assignable_units: HashMap::new(),
+            satisfied: HashSet::new(),
+        }
+    }
+
+    /// Progress of an AU
+    pub fn progress(&self, au_id: &str) -> AuProgress {
+        self.assignable_units.get(au_id).cloned().unwrap_or_default()
+    }
+
+    /// Whether an AU, block or the course is satisfied
+    pub fn is_satisfied(&self, id: &str) -> bool {
+        self.satisfied.contains(id)
+    }
+
+    /// Record an accepted statement for an AU
+    pub fn record(&mut self, au_id: &str, statement: StatementType) {
+        let progress = self.assignable_units.entry(au_id.to_string()).or_default();
+        match statement {
+            StatementType::Completed => progress.completed = true,
+            StatementType::Passed => progress.passed = true,
+            StatementType::Waived => progress.waived = true,
+            _ => {}
+        }
+    }
+
+    /// Evaluate the course hierarchy and return the IDs that became
+    /// satisfied, AUs before the blocks containing them and the course last
+    pub fn evaluate(&mut self, course: &Cmi5Course) -> Vec<String> {
+        let mut newly_satisfied = Vec::new();
+
+        if self.evaluate_nodes(course, &course.top_level(), &mut newly_satisfied) {
+            self.satisfy(&course.id, &mut newly_satisfied);
+        }
+
+        newly_satisfied
+    }
+
+    fn evaluate_nodes(&mut self, course: &Cmi5Course, nodes: &[CourseNode], newly_satisfied: &mut Vec<String>) -> bool {
+        let mut all_satisfied = true;
+
+        for node in nodes {
+            let (id, satisfied) = match node {
+                CourseNode::AssignableUnit(au_id) => {
+                    let progress = self.progress(au_id);
+                    let satisfied = course.assignable_units.iter()
+                        .find(|au| &au.id == au_id)
+                        .map_or(false, |au| progress.waived || move_on_met(au.move_on, &progress));
+                    (au_id, satisfied)
+                }
+                CourseNode::Block(block) => (&block.id, self.evaluate_nodes(course, &block.children, newly_satisfied)),
+            };
+
+            if satisfied || self.is_satisfied(id) {
+                self.satisfy(id, newly_satisfied);
+            } else {
+                all_satisfied = false;
+            }
+        }
+
+        all_satisfied
+    }
+
+    fn satisfy(&mut self, id: &str, newly_satisfied: &mut Vec<String>) {
+        if self.satisfied.insert(id.to_string()) {
+            newly_satisfied.push(id.to_string());
+        }
+    }
+}
+
+/// Whether an AU's moveOn criteria are met
+pub fn move_on_met(move_on: MoveOn, progress: &AuProgress) -> bool {
+    match move_on {
+        MoveOn::Completed => progress.completed,
+        MoveOn::Passed => progress.passed,
+        MoveOn::CompletedAndPassed => progress.completed && progress.passed,
+        MoveOn::CompletedOrPassed => progress.completed || progress.passed,
+        MoveOn::NotApplicable => true,
+    }
+}
+
+/// Check a defined statement of an AU before it is sent
+///
+/// Statements must follow `initialized` and precede `terminated`, completion
+/// and passing are only recorded once per registration, and judgements must
+/// agree with the AU's mastery score. Browse and Review launches may not
+/// record completion or success at all.
+pub fn check_statement(
+    statement: StatementType,
+    session: &Cmi5Session,
+    registration: &Cmi5Registration,
+    au: &AssignableUnit,
+    score: Option<&Cmi5Score>,
+) -> Result<(), StatementRejected> {
+    let sent = |statement: StatementType| session.statements.contains(&statement);
+    let verb = statement.verb();
+
+    if sent(StatementType::Terminated) || matches!(session.state, Cmi5State::Abandoned | Cmi5State::Waived | Cmi5State::Terminated) {
+        return Err(StatementRejected::SessionEnded);
+    }
+
+    match statement {
+        StatementType::Initialized if !session.statements.is_empty() => return Err(StatementRejected::AlreadyInitialized),
+        StatementType::Initialized => return Ok(()),
+        StatementType::Satisfied | StatementType::Abandoned | StatementType::Waived => return Err(StatementRejected::LmsOnly(verb)),
+        _ if !sent(StatementType::Initialized) => return Err(StatementRejected::NotInitialized),
+        StatementType::Terminated => return Ok(()),
+        _ => {}
+    }
+
+    if session.launch_mode != LaunchMode::Normal {
+        return Err(StatementRejected::LaunchMode(verb, session.launch_mode));
+    }
+
+    let progress = registration.progress(&au.id);
+    match statement {
+        StatementType::Completed if progress.completed => return Err(StatementRejected::AlreadyCompleted),
+        StatementType::Passed | StatementType::Failed if progress.passed => return Err(StatementRejected::AlreadyPassed),
+        StatementType::Failed if sent(StatementType::Failed) => return Err(StatementRejected::AlreadyFailed),
+        _ => {}
+    }
+
+    if let (StatementType::Passed | StatementType::Failed, Some(mastery_score)) = (statement, au.mastery_score) {
+        let score = score.ok_or(StatementRejected::ScoreRequired(verb))?.scaled;
+        let passing = score >= mastery_score;
+        if passing != (statement == StatementType::Passed) {
+            return Err(StatementRejected::MasteryScore { verb, score, mastery_score });
+        }
+    }
+
+    Ok(())
+}
@@ -107,7 +107,7 @@ pub struct ObjectDefinition {
 }
 
 /// Statement type
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StatementType {
     /// Initialized
     Initialized,
@@ -134,6 +134,22 @@ pub enum StatementType {
     Waived,
 }
 
+impl StatementType {
+    /// Verb display name
+    pub fn verb(&self) -> &'static str {
+        match self {
+            StatementType::Initialized => "initialized",
+            StatementType::Completed => "completed",
+            StatementType::Passed => "passed",
+            StatementType::Failed => "failed",
+            StatementType::Terminated => "terminated",
+            StatementType::Satisfied => "satisfied",
+            StatementType::Abandoned => "abandoned",
+            StatementType::Waived => "waived",
+        }
+    }
+}
+
 /// cmi5 statement builder
 pub struct Cmi5StatementBuilder {
     /// Statement ID
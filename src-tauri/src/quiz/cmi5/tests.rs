@@ -1,10 +1,9 @@
-#[cfg(test)]
-mod tests {
+use super::*;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+mod service {
     use super::*;
-    use crate::quiz::cmi5::{Cmi5Service, LaunchService, LaunchMode};
-    use std::path::Path;
-    use std::sync::Arc;
-    use tokio::runtime::Runtime;
 
     #[test]
     fn test_cmi5_service_creation() {
@@ -91,3 +90,333 @@ mod tests {
         });
     }
 }
+
+const COURSE_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<courseStructure xmlns="https://w3id.org/xapi/profiles/cmi5/v1/CourseStructure.xsd">
+  <course id="https://example.com/courses/safety">
+    <title><langstring lang="en-US">Safety</langstring><langstring lang="de-DE">Sicherheit</langstring></title>
+    <description><langstring lang="en-US">Workplace safety</langstring></description>
+  </course>
+  <block id="https://example.com/courses/safety/basics">
+    <title><langstring lang="en-US">Basics</langstring></title>
+    <au id="https://example.com/courses/safety/intro" moveOn="Completed">
+      <title><langstring lang="en-US">Introduction</langstring></title>
+      <url>intro/index.html</url>
+    </au>
+    <au id="https://example.com/courses/safety/quiz" moveOn="Passed" masteryScore="0.8" launchMethod="OwnWindow">
+      <title><langstring lang="en-US">Quiz</langstring></title>
+      <url>quiz/index.html</url>
+      <launchParameters>{"questions":10}</launchParameters>
+    </au>
+  </block>
+  <au id="https://example.com/courses/safety/survey">
+    <title><langstring lang="en-US">Survey</langstring></title>
+    <url>survey/index.html</url>
+  </au>
+</courseStructure>"#;
+
+const INTRO: &str = "https://example.com/courses/safety/intro";
+const QUIZ: &str = "https://example.com/courses/safety/quiz";
+const SURVEY: &str = "https://example.com/courses/safety/survey";
+const BASICS: &str = "https://example.com/courses/safety/basics";
+const COURSE: &str = "https://example.com/courses/safety";
+
+mod course_structure {
+    use super::*;
+
+    #[test]
+    fn parses_blocks_and_au_attributes() {
+        let course = parse_course_xml(COURSE_XML).unwrap();
+
+        assert_eq!(course.id, COURSE);
+        assert_eq!(course.title, "Safety");
+        assert_eq!(course.description.as_deref(), Some("Workplace safety"));
+        assert_eq!(course.assignable_units.iter().map(|au| au.id.as_str()).collect::<Vec<_>>(), vec![INTRO, QUIZ, SURVEY]);
+
+        let quiz = &course.assignable_units[1];
+        assert_eq!(quiz.move_on, MoveOn::Passed);
+        assert_eq!(quiz.mastery_score, Some(0.8));
+        assert_eq!(quiz.launch_method, LaunchMethod::OwnWindow);
+        assert_eq!(quiz.launch_url, "quiz/index.html");
+        assert_eq!(quiz.launch_parameters.as_deref(), Some(r#"{"questions":10}"#));
+
+        let survey = &course.assignable_units[2];
+        assert_eq!(survey.move_on, MoveOn::NotApplicable);
+        assert_eq!(survey.launch_method, LaunchMethod::AnyWindow);
+
+        match course.structure.as_slice() {
+            [CourseNode::Block(block), CourseNode::AssignableUnit(au)] => {
+                assert_eq!(block.id, BASICS);
+                assert_eq!(block.title, "Basics");
+                assert!(matches!(block.children.as_slice(), [CourseNode::AssignableUnit(a), CourseNode::AssignableUnit(b)] if a == INTRO && b == QUIZ));
+                assert_eq!(au, SURVEY);
+            }
+            structure => panic!("unexpected structure: {:?}", structure),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_attributes() {
+        assert!(parse_course_xml(&COURSE_XML.replace(r#"masteryScore="0.8""#, r#"masteryScore="80""#)).is_err());
+        assert!(parse_course_xml(&COURSE_XML.replace(r#"moveOn="Completed""#, r#"moveOn="Finished""#)).is_err());
+        assert!(parse_course_xml(&COURSE_XML.replace("OwnWindow", "Popup")).is_err());
+    }
+}
+
+mod satisfaction {
+    use super::*;
+
+    fn session(launch_mode: LaunchMode, statements: &[StatementType]) -> Cmi5Session {
+        Cmi5Session {
+            id: "session-1".to_string(),
+            actor_id: "learner@example.com".to_string(),
+            course_id: COURSE.to_string(),
+            au_id: QUIZ.to_string(),
+            registration_id: "reg-1".to_string(),
+            launch_mode,
+            launch_parameters: Cmi5LaunchParameters {
+                endpoint: String::new(),
+                actor: String::new(),
+                registration: "reg-1".to_string(),
+                activity_id: QUIZ.to_string(),
+                auth_token: String::new(),
+            },
+            state: Cmi5State::Initialized,
+            start_time: chrono::Utc::now(),
+            end_time: None,
+            result: None,
+            statements: statements.to_vec(),
+        }
+    }
+
+    #[test]
+    fn rolls_up_move_on_to_blocks_and_course() {
+        let course = parse_course_xml(COURSE_XML).unwrap();
+        let mut registration = Cmi5Registration::new("reg-1", "learner@example.com", COURSE);
+
+        // The survey has no moveOn criteria
+        assert_eq!(registration.evaluate(&course), vec![SURVEY]);
+
+        registration.record(QUIZ, StatementType::Completed);
+        assert!(registration.evaluate(&course).is_empty());
+
+        registration.record(QUIZ, StatementType::Passed);
+        assert_eq!(registration.evaluate(&course), vec![QUIZ]);
+
+        registration.record(INTRO, StatementType::Completed);
+        assert_eq!(registration.evaluate(&course), vec![INTRO, BASICS, COURSE]);
+
+        // Satisfaction is only reported once
+        assert!(registration.evaluate(&course).is_empty());
+    }
+
+    #[test]
+    fn waived_aus_are_satisfied() {
+        let course = parse_course_xml(COURSE_XML).unwrap();
+        let mut registration = Cmi5Registration::new("reg-1", "learner@example.com", COURSE);
+        registration.evaluate(&course);
+
+        registration.record(QUIZ, StatementType::Waived);
+        registration.record(INTRO, StatementType::Waived);
+        assert_eq!(registration.evaluate(&course), vec![INTRO, QUIZ, BASICS, COURSE]);
+    }
+
+    #[test]
+    fn move_on_criteria() {
+        let completed = AuProgress { completed: true, ..Default::default() };
+        let passed = AuProgress { passed: true, ..Default::default() };
+
+        assert!(move_on_met(MoveOn::Completed, &completed));
+        assert!(!move_on_met(MoveOn::Passed, &completed));
+        assert!(!move_on_met(MoveOn::CompletedAndPassed, &passed));
+        assert!(move_on_met(MoveOn::CompletedOrPassed, &passed));
+        assert!(move_on_met(MoveOn::NotApplicable, &AuProgress::default()));
+    }
+
+    #[test]
+    fn rejects_out_of_order_and_duplicate_statements() {
+        use StatementType::*;
+
+        let course = parse_course_xml(COURSE_XML).unwrap();
+        let quiz = &course.assignable_units[1];
+        let mut registration = Cmi5Registration::new("reg-1", "learner@example.com", COURSE);
+        let passing = Cmi5Score::new(0.9, None, None, None);
+        let check = |statement, session: &Cmi5Session, registration: &Cmi5Registration| {
+            check_statement(statement, session, registration, quiz, Some(&passing))
+        };
+
+        assert_eq!(check(Completed, &session(LaunchMode::Normal, &[]), &registration), Err(StatementRejected::NotInitialized));
+        assert_eq!(check(Initialized, &session(LaunchMode::Normal, &[Initialized]), &registration), Err(StatementRejected::AlreadyInitialized));
+        assert_eq!(check(Completed, &session(LaunchMode::Normal, &[Initialized, Terminated]), &registration), Err(StatementRejected::SessionEnded));
+        assert_eq!(check(Satisfied, &session(LaunchMode::Normal, &[Initialized]), &registration), Err(StatementRejected::LmsOnly("satisfied")));
+        assert_eq!(check(Failed, &session(LaunchMode::Normal, &[Initialized, Failed]), &registration), Err(StatementRejected::AlreadyFailed));
+        assert_eq!(check(Passed, &session(LaunchMode::Normal, &[Initialized]), &registration), Ok(()));
+
+        // Completion and passing carry over between sessions of a registration
+        registration.record(QUIZ, Completed);
+        registration.record(QUIZ, Passed);
+        assert_eq!(check(Completed, &session(LaunchMode::Normal, &[Initialized]), &registration), Err(StatementRejected::AlreadyCompleted));
+        assert_eq!(check(Passed, &session(LaunchMode::Normal, &[Initialized]), &registration), Err(StatementRejected::AlreadyPassed));
+        assert_eq!(check(Terminated, &session(LaunchMode::Normal, &[Initialized]), &registration), Ok(()));
+
+        // Browse and Review launches only initialize and terminate
+        let fresh = Cmi5Registration::new("reg-2", "learner@example.com", COURSE);
+        assert_eq!(check(Completed, &session(LaunchMode::Browse, &[Initialized]), &fresh), Err(StatementRejected::LaunchMode("completed", LaunchMode::Browse)));
+        assert_eq!(check(Terminated, &session(LaunchMode::Review, &[Initialized]), &fresh), Ok(()));
+    }
+
+    #[test]
+    fn enforces_mastery_score() {
+        use StatementType::*;
+
+        let course = parse_course_xml(COURSE_XML).unwrap();
+        let quiz = &course.assignable_units[1];
+        let intro = &course.assignable_units[0];
+        let registration = Cmi5Registration::new("reg-1", "learner@example.com", COURSE);
+        let session = session(LaunchMode::Normal, &[Initialized]);
+        let low = Cmi5Score::new(0.5, None, None, None);
+        let high = Cmi5Score::new(0.8, None, None, None);
+
+        assert_eq!(check_statement(Passed, &session, &registration, quiz, None), Err(StatementRejected::ScoreRequired("passed")));
+        assert_eq!(
+            check_statement(Passed, &session, &registration, quiz, Some(&low)),
+            Err(StatementRejected::MasteryScore { verb: "passed", score: 0.5, mastery_score: 0.8 })
+        );
+        assert_eq!(
+            check_statement(Failed, &session, &registration, quiz, Some(&high)),
+            Err(StatementRejected::MasteryScore { verb: "failed", score: 0.8, mastery_score: 0.8 })
+        );
+        assert_eq!(check_statement(Passed, &session, &registration, quiz, Some(&high)), Ok(()));
+        assert_eq!(check_statement(Failed, &session, &registration, quiz, Some(&low)), Ok(()));
+
+        // Without a mastery score the AU judges on its own
+        assert_eq!(check_statement(Passed, &session, &registration, intro, None), Ok(()));
+    }
+}
+
+mod lms {
+    use super::*;
+    use crate::quiz::xapi::lrs::{LocalLrs, LrsCredentials, StatementQuery};
+    use axum::Router;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const LEARNER: &str = "learner@example.com";
+    const SATISFIED: &str = "https://w3id.org/xapi/adl/verbs/satisfied";
+
+    /// cmi5 service backed by the embedded LRS, with the test course loaded
+    async fn service() -> (Cmi5Service, LocalLrs) {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let lrs = LocalLrs::new(pool).await.unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/xapi", listener.local_addr().unwrap());
+        let app = Router::new().nest("/xapi", crate::routes::xapi::create_routes(lrs.clone(), LrsCredentials::new("local", "local")));
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let launch_service = Arc::new(LaunchService::new(&endpoint, &format!("{}/auth", endpoint)));
+        let service = Cmi5Service::new(&endpoint, Some("local:local"), launch_service).unwrap();
+        let course = parse_course_xml(COURSE_XML).unwrap();
+        service.courses.lock().await.insert(course.id.clone(), course);
+
+        (service, lrs)
+    }
+
+    async fn satisfied(lrs: &LocalLrs) -> Vec<String> {
+        let query = StatementQuery { verb: Some(SATISFIED.to_string()), ascending: true, ..Default::default() };
+        lrs.query_statements(&query).await.unwrap()
+            .statements
+            .iter()
+            .map(|statement| statement["object"]["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn satisfies_course_across_sessions() {
+        let (service, lrs) = service().await;
+
+        let launch = service.launch_assignable_unit(COURSE, QUIZ, LEARNER, LaunchMode::Normal).await.unwrap();
+        assert_eq!(launch.launch_method, LaunchMethod::OwnWindow);
+        assert_eq!(satisfied(&lrs).await, vec![SURVEY]);
+
+        // The AU fails, then passes in a second session of the registration
+        service.initialize_session(&launch.session_id).await.unwrap();
+        service.complete_session(&launch.session_id, Some(Cmi5Score::new(0.6, None, None, None)), Some(false)).await.unwrap();
+        assert_eq!(service.get_session_state(&launch.session_id).await.unwrap(), Cmi5State::Failed);
+
+        let retry = service.launch_assignable_unit(COURSE, QUIZ, LEARNER, LaunchMode::Normal).await.unwrap();
+        service.initialize_session(&retry.session_id).await.unwrap();
+        service.complete_session(&retry.session_id, Some(Cmi5Score::new(0.9, None, None, None)), Some(true)).await.unwrap();
+        assert_eq!(satisfied(&lrs).await, vec![SURVEY, QUIZ]);
+
+        let intro = service.launch_assignable_unit(COURSE, INTRO, LEARNER, LaunchMode::Normal).await.unwrap();
+        assert_eq!(intro.launch_method, LaunchMethod::AnyWindow);
+        service.initialize_session(&intro.session_id).await.unwrap();
+        service.complete_session(&intro.session_id, None, None).await.unwrap();
+        assert_eq!(satisfied(&lrs).await, vec![SURVEY, QUIZ, INTRO, BASICS, COURSE]);
+
+        let registration = service.get_registration(LEARNER, COURSE).await.unwrap().unwrap();
+        assert!(registration.is_satisfied(COURSE));
+        let sessions = service.get_actor_sessions(LEARNER).await.unwrap();
+        assert!(sessions.iter().all(|session| session.registration_id == registration.id));
+    }
+
+    #[tokio::test]
+    async fn rejects_statements_before_sending_them() {
+        let (service, lrs) = service().await;
+        let launch = service.launch_assignable_unit(COURSE, QUIZ, LEARNER, LaunchMode::Normal).await.unwrap();
+
+        let error = service.record_statement(&launch.session_id, StatementType::Completed, None).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<StatementRejected>(), Some(&StatementRejected::NotInitialized));
+
+        service.initialize_session(&launch.session_id).await.unwrap();
+        let low = Some(Cmi5Score::new(0.5, None, None, None));
+        let error = service.complete_session(&launch.session_id, low, Some(true)).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<StatementRejected>(), Some(StatementRejected::MasteryScore { .. })));
+
+        // The contradicting passed never reached the LRS, but the session was
+        // still terminated
+        let page = lrs.query_statements(&StatementQuery { activity: Some(QUIZ.to_string()), ..Default::default() }).await.unwrap();
+        assert_eq!(page.statements.len(), 3);
+        assert_eq!(service.get_session_state(&launch.session_id).await.unwrap(), Cmi5State::Completed);
+        let session = service.get_actor_sessions(LEARNER).await.unwrap().remove(0);
+        assert_eq!(session.statements.last(), Some(&StatementType::Terminated));
+        assert_eq!(session.result.unwrap().success, None);
+    }
+
+    #[tokio::test]
+    async fn completing_a_browse_session_only_terminates_it() {
+        let (service, lrs) = service().await;
+        let launch = service.launch_assignable_unit(COURSE, QUIZ, LEARNER, LaunchMode::Browse).await.unwrap();
+
+        service.initialize_session(&launch.session_id).await.unwrap();
+        service.complete_session(&launch.session_id, Some(Cmi5Score::new(0.9, None, None, None)), Some(true)).await.unwrap();
+
+        let session = service.get_actor_sessions(LEARNER).await.unwrap().remove(0);
+        assert_eq!(session.statements, vec![StatementType::Initialized, StatementType::Terminated]);
+        assert_eq!(session.state, Cmi5State::Terminated);
+        assert_eq!(satisfied(&lrs).await, vec![SURVEY]);
+    }
+
+    #[tokio::test]
+    async fn publishes_launch_data() {
+        let (service, _lrs) = service().await;
+        let launch = service.launch_assignable_unit(COURSE, QUIZ, LEARNER, LaunchMode::Normal).await.unwrap();
+        let registration = service.get_registration(LEARNER, COURSE).await.unwrap().unwrap();
+        let session = service.get_actor_sessions(LEARNER).await.unwrap().remove(0);
+
+        let launch_data = service.client
+            .get_state(&session.launch_parameters.activity_id, LEARNER, "LMS.LaunchData", Some(&registration.id))
+            .await
+            .unwrap();
+        assert_eq!(launch_data["moveOn"], "Passed");
+        assert_eq!(launch_data["masteryScore"], 0.8);
+        assert_eq!(launch_data["launchMode"], "Normal");
+        assert_eq!(launch_data["contextTemplate"]["extensions"]["https://w3id.org/xapi/cmi5/context/extensions/sessionid"], launch.session_id.as_str());
+    }
+}
@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use super::sandbox::{find_executable, Limits};
+use super::CodeRunnerError;
+
+/// Languages the runner can compile and execute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Python,
+    Rust,
+    JavaScript,
+}
+
+/// A program to start inside the sandbox and the host directories it needs
+#[derive(Debug, Clone)]
+pub(super) struct Invocation {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub read_only: Vec<PathBuf>,
+}
+
+impl Language {
+    /// Parse the language name stored on a question
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "python" | "python3" | "py" => Some(Language::Python),
+            "rust" | "rs" => Some(Language::Rust),
+            "javascript" | "js" | "node" | "nodejs" => Some(Language::JavaScript),
+            _ => None,
+        }
+    }
+
+    pub fn source_file(self) -> &'static str {
+        match self {
+            Language::Python => "main.py",
+            Language::Rust => "main.rs",
+            Language::JavaScript => "main.js",
+        }
+    }
+
+    /// Whether memory is limited through the address space rlimit. V8 reserves
+    /// far more address space than it uses, so Node gets a heap size instead.
+    pub fn limits_address_space(self) -> bool {
+        !matches!(self, Language::JavaScript)
+    }
+
+    /// The compile step, for compiled languages
+    pub(super) async fn compile(self) -> Result<Option<Invocation>, CodeRunnerError> {
+        match self {
+            Language::Rust => {
+                let sysroot = rust_sysroot().await?;
+                Ok(Some(Invocation {
+                    program: sysroot.join("bin").join("rustc"),
+                    args: ["--edition", "2021", "-O", "-o", "main", "main.rs"]
+                        .iter()
+                        .map(|arg| arg.to_string())
+                        .collect(),
+                    read_only: vec![sysroot],
+                }))
+            }
+            Language::Python | Language::JavaScript => Ok(None),
+        }
+    }
+
+    /// The command that runs a test case
+    pub(super) fn run(self, limits: &Limits) -> Result<Invocation, CodeRunnerError> {
+        match self {
+            Language::Python => {
+                let program = toolchain("python3")?;
+                Ok(Invocation {
+                    read_only: install_root(&program).into_iter().chain(Some(program.clone())).collect(),
+                    program,
+                    args: vec!["-I".to_string(), "-B".to_string(), self.source_file().to_string()],
+                })
+            }
+            Language::JavaScript => {
+                let program = toolchain("node")?;
+                let mut args = Vec::new();
+                if let Some(memory_kb) = limits.memory_kb {
+                    args.push(format!("--max-old-space-size={}", (memory_kb / 1024).max(16)));
+                }
+                args.push(self.source_file().to_string());
+                Ok(Invocation {
+                    read_only: install_root(&program).into_iter().chain(Some(program.clone())).collect(),
+                    program,
+                    args,
+                })
+            }
+            Language::Rust => Ok(Invocation {
+                program: PathBuf::from("./main"),
+                args: Vec::new(),
+                read_only: Vec::new(),
+            }),
+        }
+    }
+
+    /// Check imports against a question's allow list.
+    ///
+    /// This is a courtesy check that gives a clear message for disallowed
+    /// modules; the sandbox is what actually confines the program.
+    pub fn check_imports(self, code: &str, allowed: &[String]) -> Result<(), String> {
+        let modules: Vec<String> = match self {
+            Language::Python => {
+                let import = Regex::new(r"(?m)^\s*import\s+([\w.]+(?:\s+as\s+\w+)?(?:\s*,\s*[\w.]+(?:\s+as\s+\w+)?)*)").unwrap();
+                let from = Regex::new(r"(?m)^\s*from\s+([\w.]+)\s+import\b").unwrap();
+                import
+                    .captures_iter(code)
+                    .flat_map(|captures| {
+                        captures[1]
+                            .split(',')
+                            .filter_map(|name| name.split_whitespace().next().map(str::to_string))
+                            .collect::<Vec<_>>()
+                    })
+                    .chain(from.captures_iter(code).map(|captures| captures[1].to_string()))
+                    .filter(|name| !name.starts_with('.'))
+                    .collect()
+            }
+            Language::JavaScript => {
+                let pattern = Regex::new(r#"(?:require\s*\(\s*|\bimport\s*\(\s*|\bfrom\s+|^\s*import\s+)['"]([^'"]+)['"]"#).unwrap();
+                code.lines()
+                    .filter_map(|line| pattern.captures(line).map(|captures| captures[1].to_string()))
+                    .filter(|name| !name.starts_with('.'))
+                    .collect()
+            }
+            Language::Rust => {
+                let pattern = Regex::new(r"(?m)^\s*(?:pub\s+)?(?:use|extern\s+crate)\s+:*(\w+)").unwrap();
+                pattern
+                    .captures_iter(code)
+                    .map(|captures| captures[1].to_string())
+                    .filter(|name| !matches!(name.as_str(), "crate" | "self" | "super" | "std" | "core" | "alloc"))
+                    .collect()
+            }
+        };
+
+        for module in modules {
+            let root = module
+                .trim_start_matches("node:")
+                .split(['.', '/'])
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            let permitted = allowed.iter().any(|name| {
+                let name = name.trim();
+                name == module || name == root
+            });
+            if !permitted {
+                return Err(format!("Importing {} is not allowed for this question", module));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn toolchain(name: &str) -> Result<PathBuf, CodeRunnerError> {
+    find_executable(name).ok_or_else(|| CodeRunnerError::ToolchainMissing(name.to_string()))
+}
+
+async fn rust_sysroot() -> Result<PathBuf, CodeRunnerError> {
+    let rustc = toolchain("rustc")?;
+    let output = tokio::process::Command::new(rustc)
+        .args(["--print", "sysroot"])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(CodeRunnerError::ToolchainMissing("rustc".to_string()));
+    }
+    let sysroot = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    Ok(sysroot.canonicalize().unwrap_or(sysroot))
+}
+
+/// The installation prefix of a toolchain outside the system directories
+/// the sandbox always mounts, e.g. `/opt/node` for `/opt/node/bin/node`
+fn install_root(program: &Path) -> Option<PathBuf> {
+    const SYSTEM: [&str; 4] = ["/usr", "/bin", "/lib", "/lib64"];
+    let program = program.canonicalize().ok()?;
+    if SYSTEM.iter().any(|dir| program.starts_with(dir)) {
+        return None;
+    }
+    program.parent()?.parent().map(Path::to_path_buf)
+}
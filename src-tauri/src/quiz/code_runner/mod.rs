@@ -0,0 +1,368 @@
+// Sandboxed runner for CodeExecution questions
+//
+// Learner code is written to a scratch directory, compiled if the language
+// needs it, and run once per test case with the case's input on stdin.
+// Every process runs inside the OS sandbox with CPU time, memory, file size,
+// process count and output limits; see sandbox.rs for what the program can
+// reach.
+
+mod language;
+mod sandbox;
+
+#[cfg(test)]
+mod tests;
+
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use super::models::{CodeExecutionAnswer, CodeExecutionContent, CodeExecutionResult, ExecutionStatus};
+pub use language::Language;
+use language::Invocation;
+use sandbox::{Limits, Sandbox};
+
+#[derive(Debug, Error)]
+pub enum CodeRunnerError {
+    #[error("unsupported language: {0}")]
+    UnsupportedLanguage(String),
+
+    #[error("{0} is not installed")]
+    ToolchainMissing(String),
+
+    #[error("sandbox unavailable: {0}")]
+    SandboxUnavailable(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Limits used when a question does not set its own
+#[derive(Debug, Clone)]
+pub struct RunnerConfig {
+    pub time_limit_ms: u32,
+    pub memory_limit_kb: u32,
+    /// Cap on captured stdout and on stderr, each
+    pub output_limit_bytes: usize,
+    pub compile_time_limit_ms: u32,
+    /// Largest file a program may write, the compiled binary included
+    pub file_size_limit_bytes: u64,
+    /// Processes and threads a program may start; compilers and runtimes
+    /// such as the JVM need a few dozen threads
+    pub process_limit: u32,
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        Self {
+            time_limit_ms: 2_000,
+            memory_limit_kb: 256 * 1024,
+            output_limit_bytes: 64 * 1024,
+            compile_time_limit_ms: 30_000,
+            file_size_limit_bytes: 64 * 1024 * 1024,
+            process_limit: 128,
+        }
+    }
+}
+
+/// The outcome of one sandboxed process
+#[derive(Debug, Clone, PartialEq)]
+struct Execution {
+    stdout: String,
+    stderr: String,
+    exit_code: Option<i32>,
+    status: ExecutionStatus,
+    elapsed_ms: u32,
+}
+
+impl Execution {
+    fn succeeded(&self) -> bool {
+        self.status == ExecutionStatus::Finished && self.exit_code == Some(0)
+    }
+}
+
+pub struct CodeRunner {
+    config: RunnerConfig,
+}
+
+impl CodeRunner {
+    pub fn new(config: RunnerConfig) -> Self {
+        Self { config }
+    }
+
+    /// Run `code` against every test case of a question.
+    ///
+    /// Compile errors, disallowed imports and limit violations are reported
+    /// per test case rather than as errors; `Err` means the code could not be
+    /// run at all. Output of hidden test cases is withheld so learners can't
+    /// print their inputs.
+    pub async fn run(&self, content: &CodeExecutionContent, code: &str) -> Result<CodeExecutionAnswer, CodeRunnerError> {
+        let language = Language::from_name(&content.language)
+            .ok_or_else(|| CodeRunnerError::UnsupportedLanguage(content.language.clone()))?;
+
+        let answer = |execution_results| CodeExecutionAnswer {
+            code: code.to_string(),
+            language: content.language.clone(),
+            execution_results,
+        };
+
+        if let Some(allowed) = &content.allowed_imports {
+            if let Err(message) = language.check_imports(code, allowed) {
+                return Ok(answer(rejected(content, ExecutionStatus::CompileError, &message)));
+            }
+        }
+
+        let sandbox = Sandbox::detect()?;
+        let workdir = WorkDir::create()?;
+        tokio::fs::write(workdir.path().join(language.source_file()), code).await?;
+
+        if let Some(compiler) = language.compile().await? {
+            let limits = Limits {
+                cpu_ms: self.config.compile_time_limit_ms,
+                memory_kb: None,
+                file_size_bytes: self.config.file_size_limit_bytes,
+                limit_address_space: false,
+                processes: self.config.process_limit,
+            };
+            let compiled = self.execute(&sandbox, workdir.path(), &compiler, "", limits).await?;
+            if !compiled.succeeded() {
+                let message = match compiled.status {
+                    ExecutionStatus::TimeLimitExceeded => "Compilation timed out".to_string(),
+                    _ => compiled.stderr,
+                };
+                return Ok(answer(rejected(content, ExecutionStatus::CompileError, &message)));
+            }
+        }
+
+        let limits = Limits {
+            cpu_ms: content.time_limit_ms.unwrap_or(self.config.time_limit_ms),
+            memory_kb: Some(content.memory_limit_kb.unwrap_or(self.config.memory_limit_kb)),
+            file_size_bytes: self.config.file_size_limit_bytes,
+            limit_address_space: language.limits_address_space(),
+            processes: self.config.process_limit,
+        };
+        let program = language.run(&limits)?;
+
+        let mut results = Vec::with_capacity(content.test_cases.len());
+        for test_case in &content.test_cases {
+            let execution = self.execute(&sandbox, workdir.path(), &program, &test_case.input, limits).await?;
+            let passed = execution.succeeded()
+                && normalize_output(&execution.stdout) == normalize_output(&test_case.expected_output);
+            let error = match execution.status {
+                ExecutionStatus::Finished if execution.exit_code != Some(0) => {
+                    Some(match execution.exit_code {
+                        Some(code) => format!("Exited with status {}", code),
+                        None => "Terminated by a signal".to_string(),
+                    })
+                }
+                ExecutionStatus::Finished => None,
+                status => Some(status_message(status).to_string()),
+            };
+            let (output, stderr) = if test_case.is_hidden {
+                (String::new(), String::new())
+            } else {
+                (execution.stdout, execution.stderr)
+            };
+            results.push(CodeExecutionResult {
+                test_case_id: test_case.id.clone(),
+                output,
+                passed,
+                execution_time_ms: execution.elapsed_ms,
+                memory_used_kb: None,
+                error,
+                stderr,
+                exit_code: execution.exit_code,
+                status: execution.status,
+            });
+        }
+
+        Ok(answer(results))
+    }
+
+    async fn execute(
+        &self,
+        sandbox: &Sandbox,
+        workdir: &Path,
+        invocation: &Invocation,
+        input: &str,
+        limits: Limits,
+    ) -> Result<Execution, CodeRunnerError> {
+        let mut child = sandbox
+            .command(workdir, &invocation.read_only, &invocation.program, &invocation.args, limits)
+            .spawn()?;
+
+        // Either stream going over the cap stops the program straight away
+        let overflow = Arc::new(Notify::new());
+        let limit = self.config.output_limit_bytes;
+        let stdout = tokio::spawn(read_limited(child.stdout.take().expect("stdout is piped"), limit, overflow.clone()));
+        let stderr = tokio::spawn(read_limited(child.stderr.take().expect("stderr is piped"), limit, overflow.clone()));
+
+        // Programs that never read stdin would block a synchronous write, so
+        // the input is fed from its own task. Dropping stdin closes it.
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = input.as_bytes().to_vec();
+        let writer = tokio::spawn(async move {
+            let _ = stdin.write_all(&input).await;
+        });
+
+        let started = Instant::now();
+        let mut timed_out = false;
+        let status = tokio::select! {
+            status = child.wait() => Some(status?),
+            _ = tokio::time::sleep(Duration::from_millis(limits.wall_clock_ms())) => {
+                timed_out = true;
+                None
+            }
+            _ = overflow.notified() => None,
+        };
+        let elapsed_ms = started.elapsed().as_millis().min(u32::MAX as u128) as u32;
+        if status.is_none() {
+            let _ = child.kill().await;
+        }
+        writer.abort();
+
+        let (stdout, stdout_overflow) = stdout.await.unwrap_or_default();
+        let (stderr, stderr_overflow) = stderr.await.unwrap_or_default();
+        let stdout = String::from_utf8_lossy(&stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&stderr).into_owned();
+
+        let exit_code = status.and_then(|status| status.code());
+        // bwrap reports its own setup failures on stderr before the program starts
+        if exit_code == Some(1) && stderr.starts_with("bwrap:") {
+            return Err(CodeRunnerError::SandboxUnavailable(stderr.trim().to_string()));
+        }
+
+        let status = classify(status, timed_out, stdout_overflow || stderr_overflow, &stderr);
+        Ok(Execution { stdout, stderr, exit_code, status, elapsed_ms })
+    }
+}
+
+/// Work out how a process ended from its exit status and output
+fn classify(status: Option<ExitStatus>, timed_out: bool, overflowed: bool, stderr: &str) -> ExecutionStatus {
+    const OUT_OF_MEMORY: [&str; 4] = [
+        "MemoryError",
+        "memory allocation of",
+        "JavaScript heap out of memory",
+        "Cannot allocate memory",
+    ];
+
+    if overflowed {
+        return ExecutionStatus::OutputLimitExceeded;
+    }
+    if timed_out || status.and_then(signal).is_some_and(is_cpu_limit_signal) {
+        return ExecutionStatus::TimeLimitExceeded;
+    }
+    if status.is_some_and(|status| !status.success()) && OUT_OF_MEMORY.iter().any(|marker| stderr.contains(marker)) {
+        return ExecutionStatus::MemoryLimitExceeded;
+    }
+    ExecutionStatus::Finished
+}
+
+/// The signal that ended a process. bwrap passes on its program's death by
+/// signal N as exit status 128 + N.
+fn signal(status: ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return Some(signal);
+        }
+    }
+    status.code().filter(|code| *code > 128).map(|code| code - 128)
+}
+
+/// RLIMIT_CPU sends SIGXCPU at the soft limit and SIGKILL at the hard one
+#[cfg(unix)]
+fn is_cpu_limit_signal(signal: i32) -> bool {
+    signal == libc::SIGXCPU || signal == libc::SIGKILL
+}
+
+#[cfg(not(unix))]
+fn is_cpu_limit_signal(_signal: i32) -> bool {
+    false
+}
+
+fn status_message(status: ExecutionStatus) -> &'static str {
+    match status {
+        ExecutionStatus::Finished => "Finished",
+        ExecutionStatus::CompileError => "Compilation failed",
+        ExecutionStatus::TimeLimitExceeded => "Time limit exceeded",
+        ExecutionStatus::MemoryLimitExceeded => "Memory limit exceeded",
+        ExecutionStatus::OutputLimitExceeded => "Output limit exceeded",
+    }
+}
+
+/// Failed results for every test case when the program never ran
+fn rejected(content: &CodeExecutionContent, status: ExecutionStatus, message: &str) -> Vec<CodeExecutionResult> {
+    content
+        .test_cases
+        .iter()
+        .map(|test_case| CodeExecutionResult {
+            test_case_id: test_case.id.clone(),
+            output: String::new(),
+            passed: false,
+            execution_time_ms: 0,
+            memory_used_kb: None,
+            error: Some(message.to_string()),
+            stderr: String::new(),
+            exit_code: None,
+            status,
+        })
+        .collect()
+}
+
+/// Line endings and trailing whitespace don't count against an answer
+fn normalize_output(output: &str) -> String {
+    output
+        .replace("\r\n", "\n")
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end_matches('\n')
+        .to_string()
+}
+
+/// Read a stream up to `limit` bytes, signalling `overflow` if it has more
+async fn read_limited(mut reader: impl AsyncRead + Unpin, limit: usize, overflow: Arc<Notify>) -> (Vec<u8>, bool) {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        match reader.read(&mut chunk).await {
+            Ok(0) | Err(_) => return (buffer, false),
+            Ok(read) if buffer.len() + read > limit => {
+                let remaining = limit - buffer.len();
+                buffer.extend_from_slice(&chunk[..remaining]);
+                overflow.notify_one();
+                return (buffer, true);
+            }
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+        }
+    }
+}
+
+/// Scratch directory for one run, removed on drop
+struct WorkDir(PathBuf);
+
+impl WorkDir {
+    fn create() -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("ordo-code-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&path)?;
+        Ok(WorkDir(path))
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,205 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use tokio::process::Command;
+
+use super::CodeRunnerError;
+
+/// Where the working directory appears inside the sandbox
+pub(super) const SANDBOX_DIR: &str = "/sandbox";
+
+/// System directories mounted read-only so interpreters and compilers can
+/// load their shared libraries. Missing entries are skipped.
+const SYSTEM_DIRS: [&str; 8] = [
+    "/usr",
+    "/bin",
+    "/sbin",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/etc/alternatives",
+    "/etc/ld.so.cache",
+];
+
+/// Resource limits applied to one sandboxed process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Limits {
+    pub cpu_ms: u32,
+    pub memory_kb: Option<u32>,
+    pub file_size_bytes: u64,
+    /// Whether `memory_kb` is enforced through the address space rlimit
+    pub limit_address_space: bool,
+    /// Processes and threads the program may start, bwrap's own included
+    pub processes: u32,
+}
+
+impl Limits {
+    /// Wall-clock allowance. Programs that sleep or wait on input never hit
+    /// the CPU limit, so they are killed after twice that plus a second.
+    pub fn wall_clock_ms(&self) -> u64 {
+        self.cpu_ms as u64 * 2 + 1000
+    }
+}
+
+/// OS-level sandbox for learner code.
+///
+/// On Linux, programs run under bubblewrap (`bwrap`) in new user, PID,
+/// network, IPC, UTS and mount namespaces. They see read-only system
+/// directories and toolchains, their working directory at `/sandbox` and an
+/// empty `/tmp`; the new network namespace has no interfaces. Resource
+/// limits are set with setrlimit before bwrap starts and are inherited by
+/// the program.
+#[derive(Debug, Clone)]
+pub(super) struct Sandbox {
+    pub(super) bwrap: PathBuf,
+}
+
+impl Sandbox {
+    #[cfg(target_os = "linux")]
+    pub fn detect() -> Result<Self, CodeRunnerError> {
+        find_executable("bwrap")
+            .map(|bwrap| Sandbox { bwrap })
+            .ok_or_else(|| CodeRunnerError::SandboxUnavailable("bubblewrap (bwrap) is not installed".to_string()))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn detect() -> Result<Self, CodeRunnerError> {
+        Err(CodeRunnerError::SandboxUnavailable(
+            "code questions can only be run on Linux".to_string(),
+        ))
+    }
+
+    /// Arguments for bwrap that run `program` in `workdir`
+    pub fn arguments(&self, workdir: &Path, read_only: &[PathBuf], program: &Path, args: &[String]) -> Vec<OsString> {
+        let mut arguments: Vec<OsString> = [
+            "--unshare-all",
+            "--die-with-parent",
+            "--new-session",
+            "--cap-drop",
+            "ALL",
+        ]
+        .iter()
+        .map(OsString::from)
+        .collect();
+
+        let system = SYSTEM_DIRS.iter().map(PathBuf::from);
+        for path in system.chain(read_only.iter().cloned()) {
+            arguments.push("--ro-bind-try".into());
+            arguments.push(path.clone().into());
+            arguments.push(path.into());
+        }
+
+        for argument in ["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp", "--bind"] {
+            arguments.push(argument.into());
+        }
+        arguments.push(workdir.into());
+        arguments.push(SANDBOX_DIR.into());
+        arguments.push("--chdir".into());
+        arguments.push(SANDBOX_DIR.into());
+
+        arguments.push("--".into());
+        arguments.push(program.into());
+        arguments.extend(args.iter().map(OsString::from));
+        arguments
+    }
+
+    /// A command ready to spawn, with piped stdio, a clean environment and
+    /// the limits installed
+    pub fn command(&self, workdir: &Path, read_only: &[PathBuf], program: &Path, args: &[String], limits: Limits) -> Command {
+        let mut command = Command::new(&self.bwrap);
+        command
+            .args(self.arguments(workdir, read_only, program, args))
+            .env_clear()
+            .env("PATH", "/usr/local/bin:/usr/bin:/bin")
+            .env("HOME", SANDBOX_DIR)
+            .env("TMPDIR", "/tmp")
+            .env("LANG", "C.UTF-8")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        // RLIMIT_NPROC caps every task of the user, not only the program's,
+        // so it is set above the number already running. Counting them
+        // reads /proc, which has to happen before fork.
+        #[cfg(target_os = "linux")]
+        let user_tasks = user_tasks();
+        #[cfg(all(unix, not(target_os = "linux")))]
+        let user_tasks = 0;
+
+        #[cfg(unix)]
+        unsafe {
+            // setrlimit is async-signal-safe, so it may run between fork and exec
+            command.pre_exec(move || apply_limits(&limits, user_tasks));
+        }
+
+        command
+    }
+}
+
+#[cfg(unix)]
+fn apply_limits(limits: &Limits, user_tasks: u64) -> std::io::Result<()> {
+    fn check(result: libc::c_int) -> std::io::Result<()> {
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    }
+    let limit = |soft: u64, hard: u64| libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+
+    // SIGXCPU at the soft limit, SIGKILL a second later
+    let cpu_seconds = (limits.cpu_ms as u64).div_ceil(1000).max(1);
+    unsafe {
+        check(libc::setrlimit(libc::RLIMIT_CPU, &limit(cpu_seconds, cpu_seconds + 1)))?;
+        check(libc::setrlimit(libc::RLIMIT_FSIZE, &limit(limits.file_size_bytes, limits.file_size_bytes)))?;
+        check(libc::setrlimit(libc::RLIMIT_CORE, &limit(0, 0)))?;
+        let tasks = user_tasks + limits.processes as u64;
+        check(libc::setrlimit(libc::RLIMIT_NPROC, &limit(tasks, tasks)))?;
+        if let (Some(memory_kb), true) = (limits.memory_kb, limits.limit_address_space) {
+            let bytes = memory_kb as u64 * 1024;
+            check(libc::setrlimit(libc::RLIMIT_AS, &limit(bytes, bytes)))?;
+        }
+    }
+    Ok(())
+}
+
+/// Tasks (processes and threads) running under our real user ID
+#[cfg(target_os = "linux")]
+pub(super) fn user_tasks() -> u64 {
+    let uid = unsafe { libc::getuid() }.to_string();
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return 0;
+    };
+
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+        .filter_map(|entry| std::fs::read_to_string(entry.path().join("status")).ok())
+        .filter(|status| {
+            status.lines()
+                .find_map(|line| line.strip_prefix("Uid:"))
+                .and_then(|ids| ids.split_whitespace().next())
+                == Some(uid.as_str())
+        })
+        .map(|status| {
+            status.lines()
+                .find_map(|line| line.strip_prefix("Threads:"))
+                .and_then(|threads| threads.trim().parse::<u64>().ok())
+                .unwrap_or(1)
+        })
+        .sum()
+}
+
+/// Look an executable up on the host `PATH`
+pub(super) fn find_executable(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
@@ -0,0 +1,196 @@
+use super::*;
+use crate::quiz::models::CodeTestCase;
+
+fn content(language: &str, cases: &[(&str, &str, bool)]) -> CodeExecutionContent {
+    CodeExecutionContent {
+        language: language.to_string(),
+        initial_code: String::new(),
+        test_cases: cases
+            .iter()
+            .enumerate()
+            .map(|(index, (input, expected_output, is_hidden))| CodeTestCase {
+                id: index.to_string(),
+                input: input.to_string(),
+                expected_output: expected_output.to_string(),
+                is_hidden: *is_hidden,
+                description: None,
+            })
+            .collect(),
+        allowed_imports: None,
+        time_limit_ms: Some(1_000),
+        memory_limit_kb: None,
+    }
+}
+
+mod output {
+    use super::*;
+
+    #[test]
+    fn test_normalize_output() {
+        assert_eq!(normalize_output("1\r\n2  \r\n\n"), "1\n2");
+        assert_eq!(normalize_output("a b\t\nc"), normalize_output("a b\nc\n"));
+        assert_ne!(normalize_output(" 1"), normalize_output("1"));
+    }
+
+    #[tokio::test]
+    async fn test_read_limited() {
+        let overflow = Arc::new(Notify::new());
+        let (read, overflowed) = read_limited(&b"hello"[..], 16, overflow.clone()).await;
+        assert_eq!((read.as_slice(), overflowed), (&b"hello"[..], false));
+
+        let (read, overflowed) = read_limited(&b"hello world"[..], 5, overflow.clone()).await;
+        assert_eq!((read.as_slice(), overflowed), (&b"hello"[..], true));
+        // The overflow wakes the task waiting on the process
+        tokio::time::timeout(Duration::from_secs(1), overflow.notified()).await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_classify() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let exited = |code: i32| ExitStatus::from_raw(code << 8);
+        assert_eq!(classify(Some(exited(0)), false, false, ""), ExecutionStatus::Finished);
+        assert_eq!(classify(Some(exited(1)), false, false, "ValueError"), ExecutionStatus::Finished);
+        assert_eq!(classify(None, true, false, ""), ExecutionStatus::TimeLimitExceeded);
+        assert_eq!(classify(None, false, true, ""), ExecutionStatus::OutputLimitExceeded);
+        assert_eq!(
+            classify(Some(ExitStatus::from_raw(libc::SIGXCPU)), false, false, ""),
+            ExecutionStatus::TimeLimitExceeded
+        );
+        assert_eq!(
+            classify(Some(exited(128 + libc::SIGXCPU)), false, false, ""),
+            ExecutionStatus::TimeLimitExceeded
+        );
+        assert_eq!(
+            classify(Some(exited(1)), false, false, "Traceback...\nMemoryError"),
+            ExecutionStatus::MemoryLimitExceeded
+        );
+    }
+}
+
+mod languages {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Language::from_name("Python"), Some(Language::Python));
+        assert_eq!(Language::from_name("js"), Some(Language::JavaScript));
+        assert_eq!(Language::from_name("rust"), Some(Language::Rust));
+        assert_eq!(Language::from_name("cobol"), None);
+    }
+
+    #[test]
+    fn test_check_imports() {
+        let allowed = vec!["math".to_string(), "fs".to_string()];
+
+        assert!(Language::Python.check_imports("import math\nfrom math import sqrt\n", &allowed).is_ok());
+        assert!(Language::Python.check_imports("import math, os as o\n", &allowed).is_err());
+        assert!(Language::Python.check_imports("from os.path import join\n", &allowed).is_err());
+        assert!(Language::Python.check_imports("from . import helper\n", &allowed).is_ok());
+
+        assert!(Language::JavaScript.check_imports("const fs = require('node:fs');", &allowed).is_ok());
+        assert!(Language::JavaScript.check_imports("import http from \"http\";", &allowed).is_err());
+        assert!(Language::JavaScript.check_imports("const net = await import('net');", &allowed).is_err());
+
+        assert!(Language::Rust.check_imports("use std::io::Read;\nuse self::helpers::*;", &allowed).is_ok());
+        assert!(Language::Rust.check_imports("extern crate rand;", &allowed).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_import_is_rejected_without_running() {
+        let mut question = content("python", &[("", "", false), ("", "", true)]);
+        question.allowed_imports = Some(vec!["math".to_string()]);
+
+        let runner = CodeRunner::new(RunnerConfig::default());
+        let answer = runner.run(&question, "import socket\n").await.unwrap();
+        assert_eq!(answer.execution_results.len(), 2);
+        assert!(answer.execution_results.iter().all(|result| {
+            !result.passed && result.status == ExecutionStatus::CompileError
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_language() {
+        let runner = CodeRunner::new(RunnerConfig::default());
+        let result = runner.run(&content("cobol", &[]), "").await;
+        assert!(matches!(result, Err(CodeRunnerError::UnsupportedLanguage(_))));
+    }
+}
+
+mod sandboxing {
+    use super::*;
+
+    #[test]
+    fn test_arguments() {
+        let sandbox = Sandbox { bwrap: PathBuf::from("/usr/bin/bwrap") };
+        let arguments: Vec<String> = sandbox
+            .arguments(
+                Path::new("/tmp/work"),
+                &[PathBuf::from("/opt/node")],
+                Path::new("/opt/node/bin/node"),
+                &["main.js".to_string()],
+            )
+            .into_iter()
+            .map(|argument| argument.into_string().unwrap())
+            .collect();
+
+        assert!(arguments.contains(&"--unshare-all".to_string()));
+        assert!(arguments.windows(3).any(|window| window == ["--ro-bind-try", "/opt/node", "/opt/node"]));
+        assert!(arguments.windows(3).any(|window| window == ["--bind", "/tmp/work", SANDBOX_DIR]));
+        assert!(!arguments.iter().any(|argument| argument == "--share-net"));
+        assert_eq!(&arguments[arguments.len() - 3..], ["--", "/opt/node/bin/node", "main.js"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_user_tasks_counts_this_process() {
+        // The test harness runs at least this thread
+        assert!(sandbox::user_tasks() >= 1);
+    }
+
+    /// Runs real programs when bubblewrap and Python are installed; the
+    /// environment checks make it a no-op elsewhere.
+    #[tokio::test]
+    async fn test_python_in_sandbox() {
+        if Sandbox::detect().is_err() || sandbox::find_executable("python3").is_none() {
+            return;
+        }
+        let runner = CodeRunner::new(RunnerConfig::default());
+        let question = content("python", &[("2 3\n", "5\n", false), ("10 -4\n", "6", true)]);
+        let answer = match runner.run(&question, "a, b = map(int, input().split())\nprint(a + b)\n").await {
+            Ok(answer) => answer,
+            // Unprivileged user namespaces may be disabled on the host
+            Err(CodeRunnerError::SandboxUnavailable(_)) => return,
+            Err(error) => panic!("{}", error),
+        };
+        assert!(answer.execution_results.iter().all(|result| result.passed));
+        assert_eq!(answer.execution_results[0].output.trim(), "5");
+        assert_eq!(answer.execution_results[1].output, "");
+
+        let network = "import socket\nsocket.create_connection(('1.1.1.1', 80), timeout=1)\n";
+        let answer = runner.run(&question, network).await.unwrap();
+        assert!(answer.execution_results.iter().all(|result| !result.passed));
+        assert!(answer.execution_results[0].stderr.contains("Error"));
+
+        let files = "print(open('/etc/hostname').read())\n";
+        let answer = runner.run(&question, files).await.unwrap();
+        assert_ne!(answer.execution_results[0].exit_code, Some(0));
+
+        let busy = "while True:\n    pass\n";
+        let answer = runner.run(&question, busy).await.unwrap();
+        assert_eq!(answer.execution_results[0].status, ExecutionStatus::TimeLimitExceeded);
+
+        let chatty = "while True:\n    print('x' * 1000)\n";
+        let answer = runner.run(&question, chatty).await.unwrap();
+        assert_eq!(answer.execution_results[0].status, ExecutionStatus::OutputLimitExceeded);
+
+        let hungry = "data = bytearray(1024 * 1024 * 1024)\n";
+        let answer = runner.run(&question, hungry).await.unwrap();
+        assert_eq!(answer.execution_results[0].status, ExecutionStatus::MemoryLimitExceeded);
+
+        let fork_bomb = "import os, time\nwhile True:\n    if os.fork() == 0:\n        time.sleep(10)\n";
+        let answer = runner.run(&question, fork_bomb).await.unwrap();
+        assert!(answer.execution_results[0].stderr.contains("BlockingIOError"));
+    }
+}
@@ -1,14 +1,21 @@
-use super::models::{Quiz, Question, Answer, QuestionContent, AnswerType, StudyMode, QuizVisibility, FlashcardData};
+use super::models::{Quiz, Question, Answer, CodeExecutionAnswer, QuestionContent, AnswerType, StudyMode, QuizVisibility, FlashcardData};
 use super::collaboration::{CollaborationRole, QuizCollaborator, CollaborationInvitation, QuizComment};
 use super::session::QuizSession;
+use super::spaced_repetition::{DeckSchedulerSettings, FitResult};
 use super::storage::HybridQuizStore;
 use super::analytics::{TimePeriod, UserStudyStats, QuizAnalytics};
-use super::export::{ExportOptions, ExportFormat};
+use super::analytics::item_analysis::ItemAnalysis;
+use super::export::{ExportOptions, ExportFormat, QtiImportReport};
+use super::anki::AnkiImportReport;
+use super::exam::{ExamEvent, ExamState, TimeAccommodation};
+use super::code_runner::{CodeRunner, RunnerConfig};
 use super::QuizEngine;
 use tauri::{State, api::path};
 use uuid::Uuid;
 use std::sync::Arc;
 use std::path::PathBuf;
+use std::collections::HashMap;
+use chrono::Utc;
 
 #[tauri::command]
 pub async fn create_quiz(
@@ -218,6 +225,14 @@ pub async fn submit_answer(
             Answer::Choice(choice_id)
         },
         "text" => Answer::Text(answer_value),
+        // Code is run here rather than trusting results sent by the client
+        "code" => {
+            let question = quiz.questions.iter()
+                .find(|q| q.id == question_uuid)
+                .ok_or_else(|| "Question not found".to_string())?;
+            let answer = run_code(question, &answer_value).await?;
+            Answer::CodeExecution(answer)
+        },
         _ => return Err(format!("Unsupported answer type: {}", answer_type)),
     };
 
@@ -230,6 +245,38 @@ pub async fn submit_answer(
     Ok(result)
 }
 
+/// Run code against a question's test cases without submitting it
+#[tauri::command]
+pub async fn run_code_question(
+    quiz_id: String,
+    question_id: String,
+    code: String,
+    store: State<'_, Arc<HybridQuizStore>>,
+) -> Result<CodeExecutionAnswer, String> {
+    let quiz_uuid = Uuid::parse_str(&quiz_id).map_err(|e| e.to_string())?;
+    let question_uuid = Uuid::parse_str(&question_id).map_err(|e| e.to_string())?;
+
+    let quiz = store.get_quiz(quiz_uuid)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let question = quiz.questions.iter()
+        .find(|q| q.id == question_uuid)
+        .ok_or_else(|| "Question not found".to_string())?;
+
+    run_code(question, &code).await
+}
+
+async fn run_code(question: &Question, code: &str) -> Result<CodeExecutionAnswer, String> {
+    let content = question.content.code_execution_content.as_ref()
+        .ok_or_else(|| "Question has no code to run".to_string())?;
+
+    CodeRunner::new(RunnerConfig::default())
+        .run(content, code)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn complete_session(
     session_id: String,
@@ -318,6 +365,51 @@ pub async fn get_flashcard_stats(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn get_deck_scheduler_settings(
+    user_id: String,
+    deck_id: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<DeckSchedulerSettings, String> {
+    let user_uuid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let deck_uuid = Uuid::parse_str(&deck_id).map_err(|e| e.to_string())?;
+
+    engine.get_deck_scheduler_settings(user_uuid, deck_uuid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Choose SM-2 or FSRS for a deck, with FSRS's desired retention and weights
+#[tauri::command]
+pub async fn set_deck_scheduler_settings(
+    user_id: String,
+    deck_id: String,
+    settings: DeckSchedulerSettings,
+    engine: State<'_, QuizEngine>,
+) -> Result<(), String> {
+    let user_uuid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let deck_uuid = Uuid::parse_str(&deck_id).map_err(|e| e.to_string())?;
+
+    engine.set_deck_scheduler_settings(user_uuid, deck_uuid, settings)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Fit FSRS weights to the user's review log and use them for a deck
+#[tauri::command]
+pub async fn optimize_deck_scheduler(
+    user_id: String,
+    deck_id: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<FitResult, String> {
+    let user_uuid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    let deck_uuid = Uuid::parse_str(&deck_id).map_err(|e| e.to_string())?;
+
+    engine.optimize_deck_scheduler(user_uuid, deck_uuid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 // Analytics commands
 
 #[tauri::command]
@@ -362,6 +454,48 @@ pub async fn get_quiz_analytics(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn get_item_analysis(
+    quiz_id: String,
+    period: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<ItemAnalysis, String> {
+    let quiz_uuid = Uuid::parse_str(&quiz_id).map_err(|e| e.to_string())?;
+
+    let period = match period.as_str() {
+        "day" => TimePeriod::Day,
+        "week" => TimePeriod::Week,
+        "month" => TimePeriod::Month,
+        "year" => TimePeriod::Year,
+        _ => TimePeriod::AllTime,
+    };
+
+    engine.get_item_analysis(quiz_uuid, period)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_item_analysis_csv(
+    quiz_id: String,
+    period: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<String, String> {
+    let quiz_uuid = Uuid::parse_str(&quiz_id).map_err(|e| e.to_string())?;
+
+    let period = match period.as_str() {
+        "day" => TimePeriod::Day,
+        "week" => TimePeriod::Week,
+        "month" => TimePeriod::Month,
+        "year" => TimePeriod::Year,
+        _ => TimePeriod::AllTime,
+    };
+
+    engine.export_item_analysis_csv(quiz_uuid, period)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn generate_user_report(
     user_id: String,
@@ -678,6 +812,10 @@ pub async fn export_quiz(
         "markdown" => ExportFormat::Markdown,
         "anki" => ExportFormat::Anki,
         "quizlet" => ExportFormat::Quizlet,
+        "qti" | "qti21" => ExportFormat::Qti21,
+        "qti30" => ExportFormat::Qti30,
+        "gift" => ExportFormat::Gift,
+        "aiken" => ExportFormat::Aiken,
         _ => ExportFormat::Json,
     };
 
@@ -700,6 +838,10 @@ pub async fn export_quiz_to_file(
         "markdown" => ExportFormat::Markdown,
         "anki" => ExportFormat::Anki,
         "quizlet" => ExportFormat::Quizlet,
+        "qti" | "qti21" => ExportFormat::Qti21,
+        "qti30" => ExportFormat::Qti30,
+        "gift" => ExportFormat::Gift,
+        "aiken" => ExportFormat::Aiken,
         _ => ExportFormat::Json,
     };
 
@@ -710,6 +852,9 @@ pub async fn export_quiz_to_file(
         ExportFormat::Markdown => "md",
         ExportFormat::Anki => "txt",
         ExportFormat::Quizlet => "txt",
+        ExportFormat::Qti21 | ExportFormat::Qti30 => "zip",
+        ExportFormat::Gift => "gift",
+        ExportFormat::Aiken => "txt",
     };
 
     // Get the path to save the file
@@ -746,6 +891,10 @@ pub async fn export_quiz_with_options(
         "markdown" => ExportFormat::Markdown,
         "anki" => ExportFormat::Anki,
         "quizlet" => ExportFormat::Quizlet,
+        "qti" | "qti21" => ExportFormat::Qti21,
+        "qti30" => ExportFormat::Qti30,
+        "gift" => ExportFormat::Gift,
+        "aiken" => ExportFormat::Aiken,
         _ => ExportFormat::Json,
     };
 
@@ -762,6 +911,37 @@ pub async fn export_quiz_with_options(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn export_quiz_qti(
+    quiz_id: String,
+    format: String,
+    file_path: Option<String>,
+    engine: State<'_, QuizEngine>,
+) -> Result<serde_json::Value, String> {
+    let quiz_uuid = Uuid::parse_str(&quiz_id).map_err(|e| e.to_string())?;
+
+    let export_format = match format.as_str() {
+        "qti30" => ExportFormat::Qti30,
+        _ => ExportFormat::Qti21,
+    };
+
+    let path = if let Some(path) = file_path {
+        PathBuf::from(path)
+    } else {
+        let downloads_dir = path::download_dir().ok_or_else(|| "Could not find downloads directory".to_string())?;
+        downloads_dir.join(format!("quiz_{}.zip", quiz_id))
+    };
+
+    let package = engine.export_quiz_qti(quiz_uuid, export_format).await
+        .map_err(|e| e.to_string())?;
+    std::fs::write(&path, &package.data).map_err(|e| e.to_string())?;
+
+    Ok(serde_json::json!({
+        "path": path.to_string_lossy(),
+        "skipped": package.skipped,
+    }))
+}
+
 #[tauri::command]
 pub async fn import_quiz_from_file(
     file_path: String,
@@ -787,6 +967,10 @@ pub async fn import_quiz(
         "markdown" => ExportFormat::Markdown,
         "anki" => ExportFormat::Anki,
         "quizlet" => ExportFormat::Quizlet,
+        "qti" | "qti21" => ExportFormat::Qti21,
+        "qti30" => ExportFormat::Qti30,
+        "gift" => ExportFormat::Gift,
+        "aiken" => ExportFormat::Aiken,
         _ => ExportFormat::Json,
     };
 
@@ -794,4 +978,126 @@ pub async fn import_quiz(
         .map_err(|e| e.to_string())?;
 
     Ok(quiz_id.to_string())
-}
\ No newline at end of file
+}
+
+#[tauri::command]
+pub async fn import_quiz_qti(
+    data: Vec<u8>,
+    engine: State<'_, QuizEngine>,
+) -> Result<QtiImportReport, String> {
+    engine.import_quiz_qti(&data).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn import_anki_package(
+    data: Vec<u8>,
+    user_id: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<AnkiImportReport, String> {
+    let user_uuid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+
+    engine.import_anki_package(&data, user_uuid).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn start_timed_exam(
+    quiz_id: String,
+    user_id: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<ExamState, String> {
+    let quiz_uuid = Uuid::parse_str(&quiz_id).map_err(|e| e.to_string())?;
+    let user_uuid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+
+    engine.start_timed_exam(quiz_uuid, user_uuid).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn resume_timed_exam(
+    session_id: String,
+    user_id: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<ExamState, String> {
+    let session_uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
+    let user_uuid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+
+    engine.resume_timed_exam(session_uuid, user_uuid).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn resume_timed_exams(
+    user_id: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<Vec<ExamState>, String> {
+    let user_uuid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+
+    engine.resume_timed_exams(user_uuid).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn autosave_exam_answers(
+    session_id: String,
+    answers: HashMap<String, Answer>,
+    engine: State<'_, QuizEngine>,
+) -> Result<ExamState, String> {
+    let session_uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
+    let answers = answers.into_iter()
+        .map(|(question_id, answer)| Ok((Uuid::parse_str(&question_id).map_err(|e| e.to_string())?, answer)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    engine.autosave_exam_answers(session_uuid, answers).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn submit_timed_exam(
+    session_id: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<ExamState, String> {
+    let session_uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
+
+    engine.submit_timed_exam(session_uuid).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn grant_time_accommodation(
+    user_id: String,
+    quiz_id: Option<String>,
+    time_multiplier: f64,
+    extra_minutes: i64,
+    granted_by: String,
+    reason: Option<String>,
+    engine: State<'_, QuizEngine>,
+) -> Result<(), String> {
+    let granted_by = Uuid::parse_str(&granted_by).map_err(|e| e.to_string())?;
+    let quiz_id = quiz_id.map(|id| Uuid::parse_str(&id)).transpose().map_err(|e| e.to_string())?;
+
+    engine.grant_time_accommodation(granted_by, TimeAccommodation {
+        user_id: Uuid::parse_str(&user_id).map_err(|e| e.to_string())?,
+        quiz_id,
+        time_multiplier,
+        extra_minutes,
+        granted_by: Some(granted_by),
+        reason,
+        granted_at: Utc::now(),
+    }).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_exam_events(
+    session_id: String,
+    user_id: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<Vec<ExamEvent>, String> {
+    let session_uuid = Uuid::parse_str(&session_id).map_err(|e| e.to_string())?;
+    let user_uuid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+
+    engine.get_exam_events(session_uuid, user_uuid).await
+        .map_err(|e| e.to_string())
+}
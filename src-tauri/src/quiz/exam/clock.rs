@@ -0,0 +1,73 @@
+// The server's time, as the backend reads it
+//
+// Timed attempts trust the server's time over the device clock, so it must
+// not come from the frontend. The backend asks the configured server for its
+// time, read from the `Date` header of its response, at most once a minute,
+// and advances the last reading with the monotonic clock in between. Setting
+// the device clock does not move it. Until the server has been reached there
+// is no server time, and attempts fall back to the device clock.
+
+use chrono::{DateTime, Duration, Utc};
+use reqwest::header::DATE;
+use reqwest::Client;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::debug;
+
+/// How long a reading of the server's time is advanced before asking again
+pub const REFRESH_SECONDS: u64 = 60;
+
+/// How long to wait for the server
+const TIMEOUT_SECONDS: u64 = 5;
+
+pub struct ServerClock {
+    url: String,
+    client: Client,
+    /// Last time read from the server and when it was read
+    reading: Mutex<Option<(DateTime<Utc>, Instant)>>,
+}
+
+impl ServerClock {
+    pub fn new(url: &str) -> Self {
+        let client = Client::builder()
+            .timeout(std::time::Duration::from_secs(TIMEOUT_SECONDS))
+            .build()
+            .unwrap_or_default();
+        Self { url: url.to_string(), client, reading: Mutex::new(None) }
+    }
+
+    /// The server's time, when it has been reached
+    pub async fn now(&self) -> Option<DateTime<Utc>> {
+        let stale = self.reading.lock().unwrap()
+            .map_or(true, |(_, read_at)| read_at.elapsed().as_secs() >= REFRESH_SECONDS);
+        if stale {
+            match self.fetch().await {
+                Some(server_time) => self.record(server_time, Instant::now()),
+                None => debug!("Could not read the time of {}", self.url),
+            }
+        }
+        self.at(Instant::now())
+    }
+
+    async fn fetch(&self) -> Option<DateTime<Utc>> {
+        let response = self.client.head(&self.url).send().await.ok()?;
+        parse_date(response.headers().get(DATE)?.to_str().ok()?)
+    }
+
+    /// Remember a reading of the server's time
+    pub(super) fn record(&self, server_time: DateTime<Utc>, read_at: Instant) {
+        *self.reading.lock().unwrap() = Some((server_time, read_at));
+    }
+
+    /// The server's time at a monotonic instant, from the last reading
+    pub(super) fn at(&self, instant: Instant) -> Option<DateTime<Utc>> {
+        let (server_time, read_at) = (*self.reading.lock().unwrap())?;
+        let since = Duration::from_std(instant.saturating_duration_since(read_at)).ok()?;
+        Some(server_time + since)
+    }
+}
+
+/// Parse an HTTP date such as "Sun, 06 Nov 1994 08:49:37 GMT"
+pub fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value).ok().map(|time| time.with_timezone(&Utc))
+}
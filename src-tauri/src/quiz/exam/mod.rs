@@ -0,0 +1,524 @@
+// Timed exams
+//
+// A timed attempt is a quiz session with a timer. The timer counts the time
+// used at every checkpoint (see `timer`) rather than comparing the clock with
+// the start time, so closing the app or changing the system clock does not
+// stop it. The session is saved at every checkpoint, so a restarted app
+// resumes the attempt with the time that is left. Checkpoints happen when the
+// attempt is resumed, when answers are autosaved, when it is submitted and
+// every few seconds in the background, which submits attempts once their time
+// is up. Students can be granted extra time for one quiz or for all of them,
+// which also extends attempts that are still running. Only instructors who
+// may edit the quiz, or any quiz for accommodations covering all of them,
+// grant extra time. Everything that happens to an attempt is logged for
+// instructors.
+//
+// Checkpoints of an attempt are taken one at a time, each after reading the
+// server's time and then loading the attempt, so that a checkpoint of the
+// background loop never saves over answers or extra time saved meanwhile.
+
+pub mod clock;
+pub mod timer;
+mod store;
+#[cfg(test)]
+mod tests;
+
+pub use clock::ServerClock;
+pub use store::ExamStore;
+pub use timer::{Checkpoint, ExamTimer, TimeSource, CLOCK_DRIFT_MS};
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tracing::warn;
+use uuid::Uuid;
+
+use super::models::Answer;
+use super::session::QuizSession;
+use super::storage::{HybridQuizStore, StoreError};
+
+/// How often the app should autosave the answers of a running attempt
+pub const AUTOSAVE_INTERVAL_SECONDS: u64 = 15;
+
+/// How often running attempts are checked for running out of time
+pub const DEADLINE_CHECK_SECONDS: u64 = 5;
+
+#[derive(Debug, Error)]
+pub enum ExamError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Quiz store error: {0}")]
+    Store(#[from] StoreError),
+
+    #[error("Timed attempt not found: {0}")]
+    NotFound(Uuid),
+
+    #[error("Quiz {0} has no time limit")]
+    NotTimed(Uuid),
+
+    #[error("Timed attempt {0} has ended")]
+    Ended(Uuid),
+
+    #[error("Invalid accommodation: {0}")]
+    InvalidAccommodation(String),
+
+    #[error("Invalid answer: {0}")]
+    Answer(String),
+
+    #[error("Invalid stored data: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExamStatus {
+    InProgress,
+    /// Submitted by the student
+    Submitted,
+    /// Submitted when time ran out
+    Expired,
+}
+
+impl ExamStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExamStatus::InProgress => "in_progress",
+            ExamStatus::Submitted => "submitted",
+            ExamStatus::Expired => "expired",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "in_progress" => Some(ExamStatus::InProgress),
+            "submitted" => Some(ExamStatus::Submitted),
+            "expired" => Some(ExamStatus::Expired),
+            _ => None,
+        }
+    }
+}
+
+/// A timed attempt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExamAttempt {
+    /// ID of the attempt's quiz session
+    pub session_id: Uuid,
+    pub quiz_id: Uuid,
+    pub user_id: Uuid,
+    /// The quiz's time limit before accommodations
+    pub base_limit_ms: i64,
+    pub status: ExamStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExamEventKind {
+    Started,
+    /// First checkpoint after a restart
+    Resumed,
+    Autosaved,
+    /// The wall clock moved against the monotonic clock, by the device
+    /// sleeping or the clock being set
+    ClockChanged,
+    AccommodationGranted,
+    Submitted,
+    Expired,
+}
+
+impl ExamEventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExamEventKind::Started => "started",
+            ExamEventKind::Resumed => "resumed",
+            ExamEventKind::Autosaved => "autosaved",
+            ExamEventKind::ClockChanged => "clock_changed",
+            ExamEventKind::AccommodationGranted => "accommodation_granted",
+            ExamEventKind::Submitted => "submitted",
+            ExamEventKind::Expired => "expired",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [
+            ExamEventKind::Started,
+            ExamEventKind::Resumed,
+            ExamEventKind::Autosaved,
+            ExamEventKind::ClockChanged,
+            ExamEventKind::AccommodationGranted,
+            ExamEventKind::Submitted,
+            ExamEventKind::Expired,
+        ].into_iter().find(|kind| kind.as_str() == value)
+    }
+}
+
+/// Entry of an attempt's audit trail
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExamEvent {
+    pub session_id: Uuid,
+    pub kind: ExamEventKind,
+    /// Time used when it happened
+    pub elapsed_ms: i64,
+    pub detail: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Extra time granted to a student
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeAccommodation {
+    pub user_id: Uuid,
+    /// Quiz it applies to, or every quiz
+    pub quiz_id: Option<Uuid>,
+    /// Factor the time limit is multiplied by, such as 1.5 for time and a half
+    pub time_multiplier: f64,
+    /// Minutes added after multiplying
+    pub extra_minutes: i64,
+    pub granted_by: Option<Uuid>,
+    pub reason: Option<String>,
+    pub granted_at: DateTime<Utc>,
+}
+
+impl TimeAccommodation {
+    /// Time limit with the accommodation
+    pub fn apply(&self, limit_ms: i64) -> i64 {
+        (limit_ms as f64 * self.time_multiplier).round() as i64 + self.extra_minutes * 60_000
+    }
+
+    fn validate(&self) -> Result<(), ExamError> {
+        if !self.time_multiplier.is_finite() || self.time_multiplier < 1.0 {
+            return Err(ExamError::InvalidAccommodation("the time multiplier must be at least 1".to_string()));
+        }
+        if self.extra_minutes < 0 {
+            return Err(ExamError::InvalidAccommodation("extra minutes cannot be negative".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// A timed attempt as shown to the student
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExamState {
+    pub session: QuizSession,
+    pub status: ExamStatus,
+    /// Rounded up to whole seconds
+    pub remaining_seconds: i64,
+    /// When time runs out if the attempt keeps running
+    pub deadline: Option<DateTime<Utc>>,
+    pub autosave_interval_seconds: u64,
+}
+
+impl ExamState {
+    fn new(session: QuizSession, status: ExamStatus) -> Self {
+        let timer = session.timer.as_ref().filter(|_| status == ExamStatus::InProgress);
+        Self {
+            remaining_seconds: timer.map_or(0, |timer| (timer.remaining_ms() + 999) / 1000),
+            deadline: timer.map(ExamTimer::deadline),
+            session,
+            status,
+            autosave_interval_seconds: AUTOSAVE_INTERVAL_SECONDS,
+        }
+    }
+}
+
+/// Runs timed attempts
+pub struct ExamService {
+    store: ExamStore,
+    quizzes: Arc<HybridQuizStore>,
+    /// Monotonic time of each running attempt's last checkpoint. Attempts
+    /// have none after a restart until their next checkpoint.
+    clocks: Mutex<HashMap<Uuid, Instant>>,
+    /// Held while an attempt is loaded, changed and saved
+    locks: Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<()>>>>,
+    /// The server's time, when a server is configured
+    server_clock: Option<ServerClock>,
+}
+
+impl ExamService {
+    pub async fn new(pool: SqlitePool, quizzes: Arc<HybridQuizStore>) -> Result<Self, ExamError> {
+        Ok(Self {
+            store: ExamStore::new(pool).await?,
+            quizzes,
+            clocks: Mutex::new(HashMap::new()),
+            locks: Mutex::new(HashMap::new()),
+            server_clock: None,
+        })
+    }
+
+    /// Time attempts by the server's time whenever it can be reached
+    pub fn with_server_clock(self, server_clock: ServerClock) -> Self {
+        Self { server_clock: Some(server_clock), ..self }
+    }
+
+    async fn server_now(&self) -> Option<DateTime<Utc>> {
+        match &self.server_clock {
+            Some(server_clock) => server_clock.now().await,
+            None => None,
+        }
+    }
+
+    /// Wait until no other change to the attempt is in progress. Ended
+    /// attempts are no longer locked, as nothing changes them any more.
+    async fn lock_attempt(&self, session_id: Uuid) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self.locks.lock().unwrap().entry(session_id).or_default().clone();
+        lock.lock_owned().await
+    }
+
+    /// Start a timed attempt
+    pub async fn start(&self, quiz_id: Uuid, user_id: Uuid) -> Result<ExamState, ExamError> {
+        let quiz = self.quizzes.get_quiz(quiz_id).await?;
+        let minutes = quiz.settings.time_limit.filter(|minutes| *minutes > 0).ok_or(ExamError::NotTimed(quiz_id))?;
+        let base_limit_ms = minutes as i64 * 60_000;
+        let accommodation = self.store.accommodation_for(user_id, quiz_id).await?;
+        let limit_ms = accommodation.as_ref().map_or(base_limit_ms, |accommodation| accommodation.apply(base_limit_ms));
+
+        let mut session = QuizSession::with_quiz(&quiz, user_id);
+        session.timer = Some(ExamTimer::new(limit_ms, Utc::now(), self.server_now().await));
+        session.time_remaining = Some((limit_ms / 1000) as i32);
+        self.quizzes.store_session(&session).await?;
+        self.clocks.lock().unwrap().insert(session.id, Instant::now());
+
+        self.store.insert_attempt(&ExamAttempt {
+            session_id: session.id,
+            quiz_id,
+            user_id,
+            base_limit_ms,
+            status: ExamStatus::InProgress,
+            started_at: session.started_at,
+            finished_at: None,
+        }).await?;
+        let detail = match &accommodation {
+            Some(accommodation) => format!(
+                "{} minutes allowed, {} with the accommodation (x{} + {} minutes)",
+                minutes, limit_ms / 60_000, accommodation.time_multiplier, accommodation.extra_minutes
+            ),
+            None => format!("{} minutes allowed", minutes),
+        };
+        self.log(&session, ExamEventKind::Started, Some(detail)).await?;
+
+        Ok(ExamState::new(session, ExamStatus::InProgress))
+    }
+
+    /// The current state of an attempt, submitting it if time ran out
+    pub async fn resume(&self, session_id: Uuid) -> Result<ExamState, ExamError> {
+        let server_now = self.server_now().await;
+        let _lock = self.lock_attempt(session_id).await;
+        let attempt = self.store.get_attempt(session_id).await?;
+        let mut session = self.quizzes.get_session(session_id).await?;
+        let status = self.checkpoint(&attempt, &mut session, server_now).await?;
+        Ok(ExamState::new(session, status))
+    }
+
+    /// Resume a user's running attempts, as after a restart
+    pub async fn resume_all(&self, user_id: Uuid) -> Result<Vec<ExamState>, ExamError> {
+        let mut states = Vec::new();
+        for attempt in self.store.in_progress_attempts(Some(user_id)).await? {
+            states.push(self.resume(attempt.session_id).await?);
+        }
+        Ok(states)
+    }
+
+    /// Save answers of a running attempt. Answers arriving after time ran
+    /// out are refused.
+    pub async fn autosave(
+        &self,
+        session_id: Uuid,
+        answers: Vec<(Uuid, Answer)>,
+    ) -> Result<ExamState, ExamError> {
+        let server_now = self.server_now().await;
+        let _lock = self.lock_attempt(session_id).await;
+        let attempt = self.store.get_attempt(session_id).await?;
+        let mut session = self.quizzes.get_session(session_id).await?;
+        if self.checkpoint(&attempt, &mut session, server_now).await? != ExamStatus::InProgress {
+            return Err(ExamError::Ended(session_id));
+        }
+
+        let quiz = self.quizzes.get_quiz(attempt.quiz_id).await?;
+        let count = answers.len();
+        for (question_id, answer) in answers {
+            session.save_answer(question_id, answer, &quiz).map_err(ExamError::Answer)?;
+        }
+        self.quizzes.update_session(&session).await?;
+        self.log(&session, ExamEventKind::Autosaved, Some(format!("{} answers saved", count))).await?;
+
+        Ok(ExamState::new(session, ExamStatus::InProgress))
+    }
+
+    /// Submit an attempt. An attempt whose time ran out has already been
+    /// submitted and is returned as it is.
+    pub async fn submit(&self, session_id: Uuid) -> Result<ExamState, ExamError> {
+        let server_now = self.server_now().await;
+        let _lock = self.lock_attempt(session_id).await;
+        let attempt = self.store.get_attempt(session_id).await?;
+        let mut session = self.quizzes.get_session(session_id).await?;
+        let mut status = self.checkpoint(&attempt, &mut session, server_now).await?;
+        if status == ExamStatus::InProgress {
+            session.complete().map_err(ExamError::Answer)?;
+            status = ExamStatus::Submitted;
+            self.finish(&session, status).await?;
+        }
+        Ok(ExamState::new(session, status))
+    }
+
+    /// Grant a student extra time. Their running attempts it applies to are
+    /// extended unless their time already ran out.
+    pub async fn grant_accommodation(&self, accommodation: TimeAccommodation) -> Result<(), ExamError> {
+        accommodation.validate()?;
+        self.store.save_accommodation(&accommodation).await?;
+
+        let server_now = self.server_now().await;
+        for attempt in self.store.in_progress_attempts(Some(accommodation.user_id)).await? {
+            if accommodation.quiz_id.is_some_and(|quiz_id| quiz_id != attempt.quiz_id) {
+                continue;
+            }
+
+            let _lock = self.lock_attempt(attempt.session_id).await;
+            let attempt = self.store.get_attempt(attempt.session_id).await?;
+            let mut session = self.quizzes.get_session(attempt.session_id).await?;
+            if self.checkpoint(&attempt, &mut session, server_now).await? != ExamStatus::InProgress {
+                continue;
+            }
+
+            // A quiz's own accommodation wins over one for every quiz
+            let Some(effective) = self.store.accommodation_for(attempt.user_id, attempt.quiz_id).await? else {
+                continue;
+            };
+            let limit_ms = effective.apply(attempt.base_limit_ms);
+            if let Some(timer) = &mut session.timer {
+                timer.limit_ms = limit_ms;
+                session.time_remaining = Some(((timer.remaining_ms() + 999) / 1000) as i32);
+            }
+            self.quizzes.update_session(&session).await?;
+            let detail = format!(
+                "{} minutes allowed (x{} + {} minutes){}",
+                limit_ms / 60_000,
+                effective.time_multiplier,
+                effective.extra_minutes,
+                effective.reason.as_deref().map(|reason| format!(": {}", reason)).unwrap_or_default(),
+            );
+            self.log(&session, ExamEventKind::AccommodationGranted, Some(detail)).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn attempt(&self, session_id: Uuid) -> Result<ExamAttempt, ExamError> {
+        self.store.get_attempt(session_id).await
+    }
+
+    pub async fn accommodation_for(&self, user_id: Uuid, quiz_id: Uuid) -> Result<Option<TimeAccommodation>, ExamError> {
+        self.store.accommodation_for(user_id, quiz_id).await
+    }
+
+    /// Audit trail of an attempt, oldest first
+    pub async fn events(&self, session_id: Uuid) -> Result<Vec<ExamEvent>, ExamError> {
+        self.store.events(session_id).await
+    }
+
+    /// Checkpoint every running attempt, submitting those whose time ran
+    /// out. Returns how many were submitted.
+    pub async fn check_deadlines(&self) -> Result<usize, ExamError> {
+        let mut expired = 0;
+        let server_now = self.server_now().await;
+        for attempt in self.store.in_progress_attempts(None).await? {
+            let _lock = self.lock_attempt(attempt.session_id).await;
+            let attempt = self.store.get_attempt(attempt.session_id).await?;
+            let mut session = self.quizzes.get_session(attempt.session_id).await?;
+            if self.checkpoint(&attempt, &mut session, server_now).await? == ExamStatus::Expired {
+                expired += 1;
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Advance a running attempt's timer and save it, logging restarts and
+    /// clock changes. Submits the attempt if its time ran out. Called with
+    /// the attempt locked, and the server's time read before it was loaded.
+    async fn checkpoint(
+        &self,
+        attempt: &ExamAttempt,
+        session: &mut QuizSession,
+        server_now: Option<DateTime<Utc>>,
+    ) -> Result<ExamStatus, ExamError> {
+        if attempt.status != ExamStatus::InProgress {
+            return Ok(attempt.status);
+        }
+        // Completed as an ordinary session
+        if session.completed_at.is_some() {
+            self.finish(session, ExamStatus::Submitted).await?;
+            return Ok(ExamStatus::Submitted);
+        }
+
+        let now = Instant::now();
+        let monotonic = self.clocks.lock().unwrap()
+            .insert(session.id, now)
+            .map(|last| now.duration_since(last));
+        let Some(checkpoint) = session.update_time_remaining(monotonic, server_now) else {
+            return Err(ExamError::Invalid(format!("session {} has no timer", session.id)));
+        };
+
+        if monotonic.is_none() {
+            let source = match checkpoint.source {
+                TimeSource::Server => "the server's time",
+                _ => "the device clock",
+            };
+            let detail = format!("{} seconds counted since the last checkpoint by {}", checkpoint.added_ms / 1000, source);
+            self.log(session, ExamEventKind::Resumed, Some(detail)).await?;
+        }
+        if monotonic.is_none() && checkpoint.clock_drift_ms < 0 {
+            // Set back while the app was closed; the time closed is unknown
+            let detail = format!(
+                "the clock was {} seconds behind the last checkpoint after a restart; no time was counted",
+                -checkpoint.clock_drift_ms / 1000
+            );
+            self.log(session, ExamEventKind::ClockChanged, Some(detail)).await?;
+        } else if checkpoint.clock_drift_ms.abs() >= CLOCK_DRIFT_MS {
+            let detail = format!("the wall clock moved {} seconds against the monotonic clock", checkpoint.clock_drift_ms / 1000);
+            self.log(session, ExamEventKind::ClockChanged, Some(detail)).await?;
+        }
+
+        if session.completed_at.is_some() {
+            self.finish(session, ExamStatus::Expired).await?;
+            return Ok(ExamStatus::Expired);
+        }
+        self.quizzes.update_session(session).await?;
+        Ok(ExamStatus::InProgress)
+    }
+
+    async fn finish(&self, session: &QuizSession, status: ExamStatus) -> Result<(), ExamError> {
+        self.quizzes.update_session(session).await?;
+        self.store.finish_attempt(session.id, status, session.completed_at.unwrap_or_else(Utc::now)).await?;
+        self.clocks.lock().unwrap().remove(&session.id);
+        self.locks.lock().unwrap().remove(&session.id);
+
+        let kind = if status == ExamStatus::Expired { ExamEventKind::Expired } else { ExamEventKind::Submitted };
+        let detail = session.score.map(|score| format!("score {:.1}%", score));
+        self.log(session, kind, detail).await
+    }
+
+    async fn log(&self, session: &QuizSession, kind: ExamEventKind, detail: Option<String>) -> Result<(), ExamError> {
+        self.store.log_event(&ExamEvent {
+            session_id: session.id,
+            kind,
+            elapsed_ms: session.timer.as_ref().map_or(0, |timer| timer.elapsed_ms),
+            detail,
+            occurred_at: Utc::now(),
+        }).await
+    }
+}
+
+/// Check running attempts for running out of time every `interval`
+pub fn spawn_deadline_loop(service: Arc<ExamService>, interval: std::time::Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = service.check_deadlines().await {
+                warn!("Failed to check timed attempts: {}", e);
+            }
+        }
+    })
+}
@@ -0,0 +1,201 @@
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use super::{ExamAttempt, ExamError, ExamEvent, ExamEventKind, ExamStatus, TimeAccommodation};
+
+/// SQLite storage for timed attempts, their audit trail and accommodations
+#[derive(Debug, Clone)]
+pub struct ExamStore {
+    pool: SqlitePool,
+}
+
+impl ExamStore {
+    /// Create a store, ensuring the exam tables exist
+    pub async fn new(pool: SqlitePool) -> Result<Self, ExamError> {
+        sqlx::query(include_str!("../../sql/quiz_exam_schema.sql"))
+            .execute(&pool)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn insert_attempt(&self, attempt: &ExamAttempt) -> Result<(), ExamError> {
+        sqlx::query(
+            "INSERT INTO exam_attempts (session_id, quiz_id, user_id, base_limit_ms, status, started_at, finished_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(attempt.session_id.to_string())
+        .bind(attempt.quiz_id.to_string())
+        .bind(attempt.user_id.to_string())
+        .bind(attempt.base_limit_ms)
+        .bind(attempt.status.as_str())
+        .bind(attempt.started_at.to_rfc3339())
+        .bind(attempt.finished_at.map(|time| time.to_rfc3339()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_attempt(&self, session_id: Uuid) -> Result<ExamAttempt, ExamError> {
+        let row = sqlx::query("SELECT * FROM exam_attempts WHERE session_id = ?")
+            .bind(session_id.to_string())
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(ExamError::NotFound(session_id))?;
+
+        attempt_from_row(&row)
+    }
+
+    /// Attempts still running, of one user or of everyone
+    pub async fn in_progress_attempts(&self, user_id: Option<Uuid>) -> Result<Vec<ExamAttempt>, ExamError> {
+        let rows = sqlx::query(
+            "SELECT * FROM exam_attempts WHERE status = ? AND (? IS NULL OR user_id = ?) ORDER BY started_at"
+        )
+        .bind(ExamStatus::InProgress.as_str())
+        .bind(user_id.map(|id| id.to_string()))
+        .bind(user_id.map(|id| id.to_string()))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(attempt_from_row).collect()
+    }
+
+    pub async fn finish_attempt(&self, session_id: Uuid, status: ExamStatus, finished_at: DateTime<Utc>) -> Result<(), ExamError> {
+        sqlx::query("UPDATE exam_attempts SET status = ?, finished_at = ? WHERE session_id = ?")
+            .bind(status.as_str())
+            .bind(finished_at.to_rfc3339())
+            .bind(session_id.to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn log_event(&self, event: &ExamEvent) -> Result<(), ExamError> {
+        sqlx::query(
+            "INSERT INTO exam_events (session_id, kind, elapsed_ms, detail, occurred_at) VALUES (?, ?, ?, ?, ?)"
+        )
+        .bind(event.session_id.to_string())
+        .bind(event.kind.as_str())
+        .bind(event.elapsed_ms)
+        .bind(&event.detail)
+        .bind(event.occurred_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Audit trail of an attempt, oldest first
+    pub async fn events(&self, session_id: Uuid) -> Result<Vec<ExamEvent>, ExamError> {
+        let rows = sqlx::query("SELECT * FROM exam_events WHERE session_id = ? ORDER BY id")
+            .bind(session_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                let session_id: String = row.get("session_id");
+                let kind: String = row.get("kind");
+                let occurred_at: String = row.get("occurred_at");
+                Ok(ExamEvent {
+                    session_id: parse_uuid(&session_id)?,
+                    kind: ExamEventKind::parse(&kind)
+                        .ok_or_else(|| ExamError::Invalid(format!("unknown event kind {}", kind)))?,
+                    elapsed_ms: row.get("elapsed_ms"),
+                    detail: row.get("detail"),
+                    occurred_at: parse_time(&occurred_at)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Insert or replace a student's accommodation
+    pub async fn save_accommodation(&self, accommodation: &TimeAccommodation) -> Result<(), ExamError> {
+        sqlx::query(
+            "INSERT INTO exam_accommodations (user_id, quiz_id, time_multiplier, extra_minutes, granted_by, reason, granted_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT (user_id, quiz_id) DO UPDATE SET
+                time_multiplier = excluded.time_multiplier,
+                extra_minutes = excluded.extra_minutes,
+                granted_by = excluded.granted_by,
+                reason = excluded.reason,
+                granted_at = excluded.granted_at"
+        )
+        .bind(accommodation.user_id.to_string())
+        .bind(accommodation.quiz_id.map(|id| id.to_string()).unwrap_or_default())
+        .bind(accommodation.time_multiplier)
+        .bind(accommodation.extra_minutes)
+        .bind(accommodation.granted_by.map(|id| id.to_string()))
+        .bind(&accommodation.reason)
+        .bind(accommodation.granted_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The accommodation a student has for a quiz: one for the quiz itself,
+    /// failing that one for every quiz
+    pub async fn accommodation_for(&self, user_id: Uuid, quiz_id: Uuid) -> Result<Option<TimeAccommodation>, ExamError> {
+        let row = sqlx::query(
+            "SELECT * FROM exam_accommodations WHERE user_id = ? AND quiz_id IN (?, '') ORDER BY quiz_id DESC LIMIT 1"
+        )
+        .bind(user_id.to_string())
+        .bind(quiz_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(accommodation_from_row).transpose()
+    }
+}
+
+fn attempt_from_row(row: &SqliteRow) -> Result<ExamAttempt, ExamError> {
+    let session_id: String = row.get("session_id");
+    let quiz_id: String = row.get("quiz_id");
+    let user_id: String = row.get("user_id");
+    let status: String = row.get("status");
+    let started_at: String = row.get("started_at");
+    let finished_at: Option<String> = row.get("finished_at");
+
+    Ok(ExamAttempt {
+        session_id: parse_uuid(&session_id)?,
+        quiz_id: parse_uuid(&quiz_id)?,
+        user_id: parse_uuid(&user_id)?,
+        base_limit_ms: row.get("base_limit_ms"),
+        status: ExamStatus::parse(&status)
+            .ok_or_else(|| ExamError::Invalid(format!("unknown attempt status {}", status)))?,
+        started_at: parse_time(&started_at)?,
+        finished_at: finished_at.as_deref().map(parse_time).transpose()?,
+    })
+}
+
+fn accommodation_from_row(row: &SqliteRow) -> Result<TimeAccommodation, ExamError> {
+    let user_id: String = row.get("user_id");
+    let quiz_id: String = row.get("quiz_id");
+    let granted_by: Option<String> = row.get("granted_by");
+    let granted_at: String = row.get("granted_at");
+
+    Ok(TimeAccommodation {
+        user_id: parse_uuid(&user_id)?,
+        quiz_id: Some(quiz_id.as_str()).filter(|id| !id.is_empty()).map(parse_uuid).transpose()?,
+        time_multiplier: row.get("time_multiplier"),
+        extra_minutes: row.get("extra_minutes"),
+        granted_by: granted_by.as_deref().map(parse_uuid).transpose()?,
+        reason: row.get("reason"),
+        granted_at: parse_time(&granted_at)?,
+    })
+}
+
+fn parse_uuid(value: &str) -> Result<Uuid, ExamError> {
+    Uuid::parse_str(value).map_err(|_| ExamError::Invalid(format!("invalid UUID {}", value)))
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, ExamError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| ExamError::Invalid(format!("invalid timestamp {}", value)))
+}
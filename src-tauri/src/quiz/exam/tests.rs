@@ -0,0 +1,271 @@
+use super::*;
+use crate::quiz::models::{AnswerType, Question, QuestionContent, Quiz};
+use chrono::Duration;
+use sqlx::sqlite::SqlitePoolOptions;
+use std::time::Duration as StdDuration;
+
+fn time(seconds: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap()
+}
+
+fn accommodation(quiz_id: Option<Uuid>, time_multiplier: f64, extra_minutes: i64) -> TimeAccommodation {
+    TimeAccommodation {
+        user_id: Uuid::nil(),
+        quiz_id,
+        time_multiplier,
+        extra_minutes,
+        granted_by: None,
+        reason: Some("Extended time".to_string()),
+        granted_at: Utc::now(),
+    }
+}
+
+fn timed_quiz(minutes: i32) -> Quiz {
+    let mut quiz = Quiz::new("Midterm".to_string(), None);
+    quiz.settings.time_limit = Some(minutes);
+    let content = QuestionContent {
+        text: "2 + 2".to_string(),
+        rich_text: None,
+        image_url: None,
+        audio_url: None,
+        drag_drop_content: None,
+        hotspot_content: None,
+        drawing_content: None,
+        code_execution_content: None,
+        math_equation_content: None,
+        timeline_content: None,
+        diagram_labeling_content: None,
+        short_answer_content: None,
+    };
+    let mut question = Question::new(quiz.id, content, AnswerType::ShortAnswer);
+    question.set_correct_answer(Answer::Text("4".to_string()));
+    quiz.add_question(question);
+    quiz
+}
+
+mod timers {
+    use super::*;
+
+    #[test]
+    fn test_monotonic_clock_ignores_clock_set_back() {
+        let mut timer = ExamTimer::new(600_000, time(0), None);
+
+        let checkpoint = timer.checkpoint(Some(StdDuration::from_secs(120)), time(-3600), None);
+        assert_eq!(checkpoint.source, TimeSource::Monotonic);
+        assert_eq!(checkpoint.added_ms, 120_000);
+        assert_eq!(checkpoint.clock_drift_ms, -3_720_000);
+        assert_eq!(timer.remaining_ms(), 480_000);
+        assert_eq!(timer.checkpoint_at, time(-3600));
+    }
+
+    #[test]
+    fn test_sleep_counts() {
+        // The monotonic clock stops while the device sleeps; the wall clock does not
+        let mut timer = ExamTimer::new(600_000, time(0), None);
+        let checkpoint = timer.checkpoint(Some(StdDuration::from_secs(10)), time(300), None);
+        assert_eq!(checkpoint.added_ms, 300_000);
+        assert_eq!(checkpoint.clock_drift_ms, 290_000);
+    }
+
+    #[test]
+    fn test_restart_counts_time_closed() {
+        let mut timer = ExamTimer::new(600_000, time(0), None);
+        let checkpoint = timer.checkpoint(None, time(200), None);
+        assert_eq!((checkpoint.source, checkpoint.added_ms), (TimeSource::DeviceClock, 200_000));
+        assert_eq!(checkpoint.clock_drift_ms, 0);
+
+        // A clock set back before the restart stops the timer but never
+        // rewinds it, and is reported
+        let checkpoint = timer.checkpoint(None, time(-1000), None);
+        assert_eq!(checkpoint.added_ms, 0);
+        assert_eq!(checkpoint.clock_drift_ms, -1_200_000);
+        assert_eq!(timer.elapsed_ms, 200_000);
+    }
+
+    #[test]
+    fn test_server_time_is_trusted() {
+        let mut timer = ExamTimer::new(600_000, time(5000), Some(time(0)));
+        assert!(timer.checkpoint_trusted);
+
+        // The device clock is ignored when both checkpoints have the server's time
+        let checkpoint = timer.checkpoint(None, time(-5000), Some(time(450)));
+        assert_eq!((checkpoint.source, checkpoint.added_ms), (TimeSource::Server, 450_000));
+        assert_eq!(timer.deadline(), time(600));
+
+        // Without the server's time the device clock is compared with it
+        let checkpoint = timer.checkpoint(None, time(500), None);
+        assert_eq!((checkpoint.source, checkpoint.added_ms), (TimeSource::DeviceClock, 50_000));
+        assert!(!timer.checkpoint_trusted);
+
+        let checkpoint = timer.checkpoint(None, time(1000), Some(time(10_000)));
+        assert_eq!(checkpoint.source, TimeSource::DeviceClock);
+        assert_eq!(checkpoint.remaining_ms, 0);
+        assert!(timer.is_expired());
+    }
+}
+
+mod server_clock {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(clock::parse_date("Tue, 14 Nov 2023 22:13:20 GMT"), Some(time(0)));
+        assert_eq!(clock::parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn test_readings_advance_with_the_monotonic_clock() {
+        let server_clock = ServerClock::new("http://localhost:1");
+        let read_at = Instant::now();
+        assert_eq!(server_clock.at(read_at), None);
+
+        server_clock.record(time(0), read_at);
+        assert_eq!(server_clock.at(read_at + StdDuration::from_secs(90)), Some(time(90)));
+        // Instants before the reading do not rewind it
+        assert_eq!(server_clock.at(read_at), Some(time(0)));
+    }
+}
+
+mod sessions {
+    use super::*;
+
+    #[test]
+    fn test_session_expires() {
+        let quiz = timed_quiz(10);
+        let mut session = QuizSession::with_quiz(&quiz, Uuid::new_v4());
+        assert_eq!(session.timer.as_ref().unwrap().limit_ms, 600_000);
+
+        let checkpoint = session.update_time_remaining(Some(StdDuration::from_secs(61)), None).unwrap();
+        assert_eq!(checkpoint.source, TimeSource::Monotonic);
+        assert_eq!(session.time_remaining, Some(539));
+        assert!(session.completed_at.is_none());
+
+        session.save_answer(quiz.questions[0].id, Answer::Text("5".to_string()), &quiz).unwrap();
+        session.save_answer(quiz.questions[0].id, Answer::Text("4".to_string()), &quiz).unwrap();
+        assert_eq!(session.answers.len(), 1);
+        assert_eq!(session.answers[0].is_correct, Some(true));
+
+        session.update_time_remaining(Some(StdDuration::from_secs(600)), None).unwrap();
+        assert_eq!(session.time_remaining, Some(0));
+        assert_eq!(session.score, Some(100.0));
+        assert!(session.completed_at.is_some());
+
+        // Nothing more counts once the attempt is over
+        assert!(session.update_time_remaining(Some(StdDuration::from_secs(1)), None).is_none());
+        assert!(session.save_answer(quiz.questions[0].id, Answer::Text("4".to_string()), &quiz).is_err());
+    }
+
+    #[test]
+    fn test_sessions_without_timer() {
+        let mut untimed = QuizSession::with_quiz(&timed_quiz(10), Uuid::new_v4());
+        untimed.quiz_settings.time_limit = None;
+        untimed.timer = None;
+        assert!(untimed.update_time_remaining(None, None).is_none());
+
+        // Sessions saved before timers were kept count from their start
+        let mut old = QuizSession::with_quiz(&timed_quiz(10), Uuid::new_v4());
+        old.timer = None;
+        old.started_at = Utc::now() - Duration::minutes(4);
+        let checkpoint = old.update_time_remaining(None, None).unwrap();
+        assert_eq!(checkpoint.source, TimeSource::DeviceClock);
+        assert!((359..=360).contains(&old.time_remaining.unwrap()));
+    }
+}
+
+mod storage {
+    use super::*;
+
+    async fn store() -> ExamStore {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        ExamStore::new(pool).await.unwrap()
+    }
+
+    fn attempt(user_id: Uuid) -> ExamAttempt {
+        ExamAttempt {
+            session_id: Uuid::new_v4(),
+            quiz_id: Uuid::new_v4(),
+            user_id,
+            base_limit_ms: 600_000,
+            status: ExamStatus::InProgress,
+            started_at: time(0),
+            finished_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attempts() {
+        let store = store().await;
+        let user_id = Uuid::new_v4();
+        let running = attempt(user_id);
+        let other = attempt(Uuid::new_v4());
+        store.insert_attempt(&running).await.unwrap();
+        store.insert_attempt(&other).await.unwrap();
+
+        assert_eq!(store.get_attempt(running.session_id).await.unwrap(), running);
+        assert_eq!(store.in_progress_attempts(Some(user_id)).await.unwrap(), vec![running.clone()]);
+        assert_eq!(store.in_progress_attempts(None).await.unwrap().len(), 2);
+
+        store.finish_attempt(running.session_id, ExamStatus::Expired, time(600)).await.unwrap();
+        let finished = store.get_attempt(running.session_id).await.unwrap();
+        assert_eq!((finished.status, finished.finished_at), (ExamStatus::Expired, Some(time(600))));
+        assert!(store.in_progress_attempts(Some(user_id)).await.unwrap().is_empty());
+
+        assert!(matches!(store.get_attempt(Uuid::new_v4()).await, Err(ExamError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let store = store().await;
+        let session_id = Uuid::new_v4();
+        for (kind, elapsed_ms) in [(ExamEventKind::Started, 0), (ExamEventKind::ClockChanged, 5000), (ExamEventKind::Expired, 600_000)] {
+            store.log_event(&ExamEvent { session_id, kind, elapsed_ms, detail: None, occurred_at: time(elapsed_ms / 1000) })
+                .await.unwrap();
+        }
+
+        let events = store.events(session_id).await.unwrap();
+        assert_eq!(events.iter().map(|event| event.kind).collect::<Vec<_>>(), vec![
+            ExamEventKind::Started, ExamEventKind::ClockChanged, ExamEventKind::Expired,
+        ]);
+        assert_eq!(events[2].occurred_at, time(600));
+        assert!(store.events(Uuid::new_v4()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_accommodations() {
+        let store = store().await;
+        let quiz_id = Uuid::new_v4();
+        assert_eq!(store.accommodation_for(Uuid::nil(), quiz_id).await.unwrap(), None);
+
+        let everywhere = accommodation(None, 1.5, 0);
+        store.save_accommodation(&everywhere).await.unwrap();
+        assert_eq!(store.accommodation_for(Uuid::nil(), quiz_id).await.unwrap(), Some(everywhere.clone()));
+
+        // One for the quiz itself wins
+        let this_quiz = accommodation(Some(quiz_id), 2.0, 10);
+        store.save_accommodation(&this_quiz).await.unwrap();
+        assert_eq!(store.accommodation_for(Uuid::nil(), quiz_id).await.unwrap(), Some(this_quiz));
+        assert_eq!(store.accommodation_for(Uuid::nil(), Uuid::new_v4()).await.unwrap(), Some(everywhere.clone()));
+
+        // Granting again replaces it
+        let changed = accommodation(None, 1.25, 5);
+        store.save_accommodation(&changed).await.unwrap();
+        assert_eq!(store.accommodation_for(Uuid::nil(), Uuid::new_v4()).await.unwrap(), Some(changed));
+    }
+
+    #[test]
+    fn test_apply_and_validate() {
+        assert_eq!(accommodation(None, 1.5, 0).apply(600_000), 900_000);
+        assert_eq!(accommodation(None, 1.0, 10).apply(600_000), 1_200_000);
+        assert!(accommodation(None, 0.5, 0).validate().is_err());
+        assert!(accommodation(None, f64::NAN, 0).validate().is_err());
+        assert!(accommodation(None, 1.0, -1).validate().is_err());
+        assert!(accommodation(None, 1.0, 0).validate().is_ok());
+
+        for status in [ExamStatus::InProgress, ExamStatus::Submitted, ExamStatus::Expired] {
+            assert_eq!(ExamStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(ExamEventKind::parse(ExamEventKind::AccommodationGranted.as_str()), Some(ExamEventKind::AccommodationGranted));
+        assert_eq!(ExamEventKind::parse("paused"), None);
+    }
+}
@@ -0,0 +1,106 @@
+// Time used by a timed attempt
+//
+// The timer keeps the time used as of its last checkpoint and advances at
+// every checkpoint. While the app runs, it measures with the monotonic
+// clock, which setting the system clock does not move. The wall clock only
+// counts when it shows more time passed, as it does after the device slept.
+// After a restart there is no monotonic reading to go on. The time since the
+// last checkpoint is then taken from the server when both checkpoints have
+// its time, and from the device clock otherwise; the server's time is read
+// by the backend (see `clock`). Time never runs backwards, so turning the
+// clock back at most stops it until the next checkpoint. A clock found behind
+// the last checkpoint after a restart is reported, as it was set back while
+// the app was closed.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+/// Difference between the monotonic and the wall clock worth recording
+pub const CLOCK_DRIFT_MS: i64 = 60_000;
+
+/// Clock the time since the last checkpoint was measured with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeSource {
+    Monotonic,
+    Server,
+    DeviceClock,
+}
+
+/// Outcome of a checkpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Time counted since the previous checkpoint
+    pub added_ms: i64,
+    pub source: TimeSource,
+    /// Wall clock time minus monotonic time since the previous checkpoint,
+    /// when the monotonic clock was read. Otherwise how far the clock is
+    /// behind the previous checkpoint, as a negative number, or 0.
+    pub clock_drift_ms: i64,
+    pub remaining_ms: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExamTimer {
+    /// Time allowed, accommodations included
+    pub limit_ms: i64,
+    /// Time used as of the last checkpoint
+    pub elapsed_ms: i64,
+    /// Time of the last checkpoint
+    pub checkpoint_at: DateTime<Utc>,
+    /// Whether `checkpoint_at` is the server's time rather than the device's
+    pub checkpoint_trusted: bool,
+}
+
+impl ExamTimer {
+    pub fn new(limit_ms: i64, now: DateTime<Utc>, server_now: Option<DateTime<Utc>>) -> Self {
+        Self {
+            limit_ms,
+            elapsed_ms: 0,
+            checkpoint_at: server_now.unwrap_or(now),
+            checkpoint_trusted: server_now.is_some(),
+        }
+    }
+
+    /// Count the time since the last checkpoint. `monotonic` is the time
+    /// the monotonic clock measured since then, if it was running; `now` is
+    /// the device clock and `server_now` the server's, when known.
+    pub fn checkpoint(
+        &mut self,
+        monotonic: Option<std::time::Duration>,
+        now: DateTime<Utc>,
+        server_now: Option<DateTime<Utc>>,
+    ) -> Checkpoint {
+        let trusted = self.checkpoint_trusted && server_now.is_some();
+        let wall_ms = (if trusted { server_now.unwrap_or(now) } else { now } - self.checkpoint_at).num_milliseconds();
+
+        let (added_ms, source, clock_drift_ms) = match monotonic {
+            Some(monotonic) => {
+                let monotonic_ms = i64::try_from(monotonic.as_millis()).unwrap_or(i64::MAX);
+                (monotonic_ms.max(wall_ms), TimeSource::Monotonic, wall_ms - monotonic_ms)
+            }
+            None if trusted => (wall_ms, TimeSource::Server, wall_ms.min(0)),
+            None => (wall_ms, TimeSource::DeviceClock, wall_ms.min(0)),
+        };
+        let added_ms = added_ms.max(0);
+
+        self.elapsed_ms = self.elapsed_ms.saturating_add(added_ms);
+        self.checkpoint_at = server_now.unwrap_or(now);
+        self.checkpoint_trusted = server_now.is_some();
+
+        Checkpoint { added_ms, source, clock_drift_ms, remaining_ms: self.remaining_ms() }
+    }
+
+    pub fn remaining_ms(&self) -> i64 {
+        (self.limit_ms - self.elapsed_ms).max(0)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining_ms() == 0
+    }
+
+    /// When time runs out if the attempt keeps running, by the clock of the
+    /// last checkpoint
+    pub fn deadline(&self) -> DateTime<Utc> {
+        self.checkpoint_at + Duration::milliseconds(self.remaining_ms())
+    }
+}
@@ -1,5 +1,7 @@
 use super::models::{Quiz, Question, Answer, QuestionContent, AnswerType, StudyMode, QuizVisibility};
 use super::storage::HybridQuizStore;
+use super::qti::{self, QtiImport, QtiPackage, QtiVersion, SkippedQuestion};
+use super::text_formats::{self, TextExport};
 use uuid::Uuid;
 use std::sync::Arc;
 use std::error::Error;
@@ -22,6 +24,25 @@ pub enum ExportFormat {
     Anki,
     /// Quizlet-compatible format
     Quizlet,
+    /// IMS QTI 2.1 package
+    Qti21,
+    /// IMS QTI 3.0 package
+    Qti30,
+    /// Moodle GIFT text format
+    Gift,
+    /// Aiken text format (multiple choice only)
+    Aiken,
+}
+
+impl ExportFormat {
+    /// QTI version of the format, if it is a QTI package
+    pub fn qti_version(self) -> Option<QtiVersion> {
+        match self {
+            ExportFormat::Qti21 => Some(QtiVersion::V2_1),
+            ExportFormat::Qti30 => Some(QtiVersion::V3_0),
+            _ => None,
+        }
+    }
 }
 
 /// Quiz export options
@@ -80,6 +101,17 @@ pub struct QuizStatistics {
     pub question_difficulties: Vec<(Uuid, f32)>,
 }
 
+/// Outcome of importing a QTI package
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QtiImportReport {
+    /// ID of the imported quiz
+    pub quiz_id: Uuid,
+    /// Version of the package
+    pub version: QtiVersion,
+    /// Items that could not be imported
+    pub skipped: Vec<SkippedQuestion>,
+}
+
 /// Quiz import/export engine
 pub struct QuizExportEngine {
     store: Arc<HybridQuizStore>,
@@ -100,7 +132,7 @@ impl QuizExportEngine {
         let export_data = self.prepare_quiz_export(quiz_id, options).await?;
         
         let file = File::create(path)?;
-        let writer = BufWriter::new(file);
+        let mut writer = BufWriter::new(file);
         
         match options.format {
             ExportFormat::Json => {
@@ -118,6 +150,15 @@ impl QuizExportEngine {
             ExportFormat::Quizlet => {
                 self.export_to_quizlet(writer, &export_data)?;
             }
+            ExportFormat::Qti21 | ExportFormat::Qti30 => {
+                writer.write_all(&self.export_to_qti(&export_data)?.data)?;
+            }
+            ExportFormat::Gift => {
+                self.export_to_text(writer, text_formats::write_gift(&export_data.quiz))?;
+            }
+            ExportFormat::Aiken => {
+                self.export_to_text(writer, text_formats::write_aiken(&export_data.quiz))?;
+            }
         }
         
         Ok(())
@@ -132,7 +173,7 @@ impl QuizExportEngine {
         let export_data = self.prepare_quiz_export(quiz_id, options).await?;
         
         let mut buffer = Vec::new();
-        let writer = BufWriter::new(&mut buffer);
+        let mut writer = BufWriter::new(&mut buffer);
         
         match options.format {
             ExportFormat::Json => {
@@ -150,11 +191,31 @@ impl QuizExportEngine {
             ExportFormat::Quizlet => {
                 self.export_to_quizlet(writer, &export_data)?;
             }
+            ExportFormat::Qti21 | ExportFormat::Qti30 => {
+                writer.write_all(&self.export_to_qti(&export_data)?.data)?;
+            }
+            ExportFormat::Gift => {
+                self.export_to_text(writer, text_formats::write_gift(&export_data.quiz))?;
+            }
+            ExportFormat::Aiken => {
+                self.export_to_text(writer, text_formats::write_aiken(&export_data.quiz))?;
+            }
         }
         
         Ok(buffer)
     }
     
+    /// Export a quiz as a QTI package, reporting the questions that QTI
+    /// cannot represent
+    pub async fn export_quiz_qti(
+        &self,
+        quiz_id: Uuid,
+        options: ExportOptions,
+    ) -> Result<QtiPackage, Box<dyn Error + Send + Sync>> {
+        let export_data = self.prepare_quiz_export(quiz_id, options).await?;
+        self.export_to_qti(&export_data)
+    }
+    
     /// Import a quiz from a file
     pub async fn import_quiz_from_file(
         &self,
@@ -179,6 +240,16 @@ impl QuizExportEngine {
             "apkg" => {
                 self.import_from_anki(reader)?
             }
+            "gift" => {
+                let mut content = String::new();
+                reader.get_ref().read_to_string(&mut content)?;
+                self.import_from_gift(&content)?
+            }
+            "zip" => {
+                let mut data = Vec::new();
+                reader.get_ref().read_to_end(&mut data)?;
+                self.import_from_qti(&data)?.quiz
+            }
             "txt" => {
                 // Try to detect format
                 let mut content = String::new();
@@ -187,6 +258,10 @@ impl QuizExportEngine {
                 if content.contains("CLOZE") || content.contains("BASIC") {
                     // Likely Anki format
                     self.import_from_anki_text(&content)?
+                } else if text_formats::is_aiken(&content) {
+                    text_formats::read_aiken(&content)?
+                } else if text_formats::is_gift(&content) {
+                    self.import_from_gift(&content)?
                 } else if content.contains("\t") {
                     // Likely Quizlet format
                     self.import_from_quizlet_text(&content)?
@@ -231,6 +306,15 @@ impl QuizExportEngine {
             ExportFormat::Quizlet => {
                 self.import_from_quizlet(reader)?
             }
+            ExportFormat::Qti21 | ExportFormat::Qti30 => {
+                self.import_from_qti(data)?.quiz
+            }
+            ExportFormat::Gift => {
+                self.import_from_gift(std::str::from_utf8(data)?)?
+            }
+            ExportFormat::Aiken => {
+                text_formats::read_aiken(std::str::from_utf8(data)?)?
+            }
         };
         
         // Store the imported quiz
@@ -239,6 +323,23 @@ impl QuizExportEngine {
         Ok(quiz.id)
     }
     
+    /// Import a QTI package, reporting the items that could not be imported
+    pub async fn import_quiz_qti(
+        &self,
+        data: &[u8],
+    ) -> Result<QtiImportReport, Box<dyn Error + Send + Sync>> {
+        let import = self.import_from_qti(data)?;
+        
+        // Store the imported quiz
+        self.store.store_quiz(&import.quiz).await?;
+        
+        Ok(QtiImportReport {
+            quiz_id: import.quiz.id,
+            version: import.version,
+            skipped: import.skipped,
+        })
+    }
+    
     /// Prepare quiz export data
     async fn prepare_quiz_export(
         &self,
@@ -644,8 +745,8 @@ impl QuizExportEngine {
         &self,
         reader: R,
     ) -> Result<Quiz, Box<dyn Error + Send + Sync>> {
-        // For simplicity, we'll assume this is a text export from Anki
-        // In a real implementation, this would handle .apkg files
+        // Text exports only; `.apkg` packages are imported with their media
+        // and review history through `QuizEngine::import_anki_package`
         
         let mut content = String::new();
         BufReader::new(reader).read_to_string(&mut content)?;
@@ -775,4 +876,62 @@ impl QuizExportEngine {
         
         Ok(quiz)
     }
+    
+    /// Export to a QTI package
+    fn export_to_qti(
+        &self,
+        export_data: &QuizExport,
+    ) -> Result<QtiPackage, Box<dyn Error + Send + Sync>> {
+        let version = export_data.format.qti_version().unwrap_or(QtiVersion::V2_1);
+        let package = qti::write_package(&export_data.quiz, version)?;
+        
+        for skipped in &package.skipped {
+            tracing::warn!("Left question {} out of QTI export: {}", skipped.identifier, skipped.reason);
+        }
+        
+        Ok(package)
+    }
+    
+    /// Export to a plain text format
+    fn export_to_text<W: Write>(
+        &self,
+        mut writer: W,
+        export: TextExport,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for skipped in &export.skipped {
+            tracing::warn!("Left question {} out of text export: {}", skipped.identifier, skipped.reason);
+        }
+        
+        writer.write_all(export.text.as_bytes())?;
+        
+        Ok(())
+    }
+    
+    /// Import from a QTI package
+    fn import_from_qti(
+        &self,
+        data: &[u8],
+    ) -> Result<QtiImport, Box<dyn Error + Send + Sync>> {
+        let import = qti::read_package(data)?;
+        
+        for skipped in &import.skipped {
+            tracing::warn!("Skipped QTI item {}: {}", skipped.identifier, skipped.reason);
+        }
+        
+        Ok(import)
+    }
+    
+    /// Import from a GIFT document
+    fn import_from_gift(
+        &self,
+        content: &str,
+    ) -> Result<Quiz, Box<dyn Error + Send + Sync>> {
+        let import = text_formats::read_gift(content)?;
+        
+        for skipped in &import.skipped {
+            tracing::warn!("Skipped GIFT question at {}: {}", skipped.identifier, skipped.reason);
+        }
+        
+        Ok(import.quiz)
+    }
 }
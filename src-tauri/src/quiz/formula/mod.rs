@@ -0,0 +1,252 @@
+// Formula questions
+//
+// A formula question is a question template whose text refers to variables
+// in square brackets, such as "A car covers [d] km in [t] hours. What is its
+// average speed in km/h?". The author gives each variable a range and a
+// number of decimal places, and writes the correct answer as a formula of
+// the variables (`d / t`).
+//
+// Every attempt draws its own values the first time it is shown the question,
+// so learners sitting side by side see different ones. The values are stored
+// with the attempt and reused from then on, so the attempt keeps its numbers
+// after the template is edited or the random generator changes. Learners are
+// only shown the question text; the answer stays on the backend, where the
+// attempt's question is rebuilt as a short answer question with one
+// `Numeric` rule and graded like any other, within the author's tolerance.
+
+#[cfg(test)]
+mod tests;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::banks::AttemptForm;
+use super::math::{self, Expr, Formula, MathError};
+use super::models::{AcceptedAnswer, MatchRule};
+
+/// Most draws tried to find values for which the formula has a value
+const MAX_DRAWS: usize = 100;
+
+/// Most decimal places of values and answers
+const MAX_DECIMALS: u32 = 10;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum FormulaError {
+    #[error("Invalid formula: {0}")]
+    Formula(#[from] MathError),
+
+    #[error("The formula must be an expression, not an equation")]
+    Equation,
+
+    #[error("The formula uses undefined variable {0}")]
+    UndefinedVariable(String),
+
+    #[error("Invalid variable {name}: {message}")]
+    InvalidVariable { name: String, message: String },
+
+    #[error("Answers may have at most {0} decimal places")]
+    Precision(u32),
+
+    #[error("The formula has no value for any of the values drawn")]
+    NoValue,
+
+    #[error("Question template has no formula")]
+    NoFormula,
+}
+
+/// A variable of a formula question and the values it may take
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormulaVariable {
+    /// Name used in the formula and, in brackets, in the question text
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+
+    /// Decimal places of the values drawn
+    #[serde(default)]
+    pub decimals: u32,
+}
+
+/// How far an answer may be from the correct one
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value")]
+pub enum FormulaTolerance {
+    Absolute(f64),
+    /// A percentage of the correct answer
+    Percent(f64),
+}
+
+impl Default for FormulaTolerance {
+    fn default() -> Self {
+        FormulaTolerance::Absolute(0.0)
+    }
+}
+
+impl FormulaTolerance {
+    pub fn for_answer(self, answer: f64) -> f64 {
+        match self {
+            FormulaTolerance::Absolute(tolerance) => tolerance.abs(),
+            FormulaTolerance::Percent(percent) => (answer * percent / 100.0).abs(),
+        }
+    }
+}
+
+/// Variables and answer formula of a formula question
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormulaTemplate {
+    pub variables: Vec<FormulaVariable>,
+
+    /// The correct answer, in LaTeX or plain text
+    pub formula: String,
+
+    /// Decimal places the correct answer is rounded to
+    #[serde(default = "default_answer_decimals")]
+    pub answer_decimals: u32,
+
+    #[serde(default)]
+    pub tolerance: FormulaTolerance,
+}
+
+fn default_answer_decimals() -> u32 {
+    2
+}
+
+/// The values an attempt was given and the answer they lead to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormulaInstance {
+    pub seed: u64,
+    pub values: BTreeMap<String, f64>,
+    pub answer: f64,
+}
+
+/// What a learner is shown of a formula question: the text with the
+/// attempt's values, without the answer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormulaPrompt {
+    pub question_id: Uuid,
+    pub text: String,
+
+    /// Decimal places answers are expected to have
+    pub answer_decimals: u32,
+}
+
+/// Seed of the first draw of a question's values in an attempt
+pub fn seed_for(attempt_id: &str, question_id: Uuid) -> u64 {
+    AttemptForm::seed_for_attempt(&format!("{}/{}", attempt_id, question_id))
+}
+
+impl FormulaTemplate {
+    /// Check the variables and the formula, returning the parsed formula
+    pub fn validate(&self) -> Result<Expr, FormulaError> {
+        for variable in &self.variables {
+            variable.validate()?;
+        }
+        if self.answer_decimals > MAX_DECIMALS {
+            return Err(FormulaError::Precision(MAX_DECIMALS));
+        }
+
+        let expr = match math::parse(&self.formula)? {
+            Formula::Expression(expr) => expr,
+            Formula::Equation(..) => return Err(FormulaError::Equation),
+        };
+        // `e` is Euler's number unless it is a variable
+        if let Some(name) = expr.variables().into_iter()
+            .find(|name| name != "e" && !self.variables.iter().any(|variable| &variable.name == name))
+        {
+            return Err(FormulaError::UndefinedVariable(name));
+        }
+        Ok(expr)
+    }
+
+    /// Draw values from a seed. Values for which the formula has no value,
+    /// such as a zero divisor, are drawn again.
+    pub fn generate(&self, seed: u64) -> Result<FormulaInstance, FormulaError> {
+        let expr = self.validate()?;
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        for _ in 0..MAX_DRAWS {
+            let values: BTreeMap<String, f64> = self.variables.iter()
+                .map(|variable| (variable.name.clone(), variable.draw(&mut rng)))
+                .collect();
+            let lookup: HashMap<String, f64> = values.iter().map(|(name, value)| (name.clone(), *value)).collect();
+
+            if let Some(answer) = expr.evaluate(&lookup).filter(|answer| answer.is_finite()) {
+                return Ok(FormulaInstance { seed, values, answer: round(answer, self.answer_decimals) });
+            }
+        }
+        Err(FormulaError::NoValue)
+    }
+
+    /// Question text with each `[name]` replaced by its value
+    pub fn render(&self, text: &str, instance: &FormulaInstance) -> String {
+        self.variables.iter().fold(text.to_string(), |text, variable| {
+            match instance.values.get(&variable.name) {
+                Some(value) => text.replace(&format!("[{}]", variable.name), &format_value(*value, variable.decimals)),
+                None => text,
+            }
+        })
+    }
+
+    /// The correct answer as shown to learners
+    pub fn format_answer(&self, instance: &FormulaInstance) -> String {
+        format_value(instance.answer, self.answer_decimals)
+    }
+
+    /// Rule that accepts the answers to an instance. The answer is rounded,
+    /// so answers within half a unit of its last place are always accepted.
+    pub fn accepted_answer(&self, instance: &FormulaInstance) -> AcceptedAnswer {
+        let rounding = 0.5 * 10f64.powi(-(self.answer_decimals as i32));
+        AcceptedAnswer {
+            rule: MatchRule::Numeric {
+                value: instance.answer,
+                tolerance: self.tolerance.for_answer(instance.answer).max(rounding),
+            },
+            credit: 1.0,
+            feedback: None,
+        }
+    }
+}
+
+impl FormulaVariable {
+    fn validate(&self) -> Result<(), FormulaError> {
+        let invalid = |message: &str| FormulaError::InvalidVariable {
+            name: self.name.clone(),
+            message: message.to_string(),
+        };
+
+        // Names such as `mass` would be read as a product of letters
+        match math::parse_latex(&self.name) {
+            Ok(Formula::Expression(Expr::Variable(name))) if name == self.name => {}
+            _ => return Err(invalid("use a letter, optionally with a subscript such as v_0")),
+        }
+        if !self.min.is_finite() || !self.max.is_finite() || self.min > self.max {
+            return Err(invalid("the range must be finite with min at most max"));
+        }
+        if self.decimals > MAX_DECIMALS {
+            return Err(invalid(&format!("at most {} decimal places", MAX_DECIMALS)));
+        }
+        Ok(())
+    }
+
+    fn draw(&self, rng: &mut StdRng) -> f64 {
+        if self.min == self.max {
+            return self.min;
+        }
+        round(rng.random_range(self.min..=self.max), self.decimals).clamp(self.min, self.max)
+    }
+}
+
+fn round(value: f64, decimals: u32) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    (value * scale).round() / scale
+}
+
+fn format_value(value: f64, decimals: u32) -> String {
+    // Avoid showing "-0.00"
+    let value = if round(value, decimals) == 0.0 { 0.0 } else { value };
+    format!("{:.*}", decimals as usize, value)
+}
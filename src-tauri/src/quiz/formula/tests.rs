@@ -0,0 +1,220 @@
+use super::*;
+use crate::quiz::models::{Answer, AnswerType, WrongChoicePenalty};
+use crate::quiz::scoring::score_answer;
+use crate::quiz::templates::QuestionTemplate;
+use chrono::Utc;
+
+fn variable(name: &str, min: f64, max: f64, decimals: u32) -> FormulaVariable {
+    FormulaVariable { name: name.to_string(), min, max, decimals }
+}
+
+fn speed() -> FormulaTemplate {
+    FormulaTemplate {
+        variables: vec![variable("d", 10.0, 500.0, 0), variable("t", 0.5, 8.0, 1)],
+        formula: "\\frac{d}{t}".to_string(),
+        answer_decimals: 2,
+        tolerance: FormulaTolerance::Percent(1.0),
+    }
+}
+
+fn question_template(formula: Option<FormulaTemplate>) -> QuestionTemplate {
+    QuestionTemplate {
+        id: Uuid::new_v4(),
+        template_id: Uuid::new_v4(),
+        text: "A car covers [d] km in [t] hours. What is its average speed in km/h?".to_string(),
+        description: None,
+        answer_type: AnswerType::ShortAnswer,
+        placeholder_text: None,
+        example_answers: Vec::new(),
+        formula,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    }
+}
+
+mod templates {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(speed().validate().is_ok());
+
+        let mut template = speed();
+        template.formula = "d / t + v".to_string();
+        assert_eq!(template.validate(), Err(FormulaError::UndefinedVariable("v".to_string())));
+
+        template.formula = "d = t".to_string();
+        assert_eq!(template.validate(), Err(FormulaError::Equation));
+
+        template.formula = "d \\cdot e".to_string();
+        assert!(template.validate().is_ok());
+
+        let mut template = speed();
+        template.variables.push(variable("mass", 1.0, 2.0, 0));
+        assert!(matches!(template.validate(), Err(FormulaError::InvalidVariable { name, .. }) if name == "mass"));
+
+        let mut template = speed();
+        template.variables = vec![variable("v_0", 1.0, 2.0, 0), variable("d", 5.0, 1.0, 0), variable("t", 1.0, 2.0, 0)];
+        assert!(matches!(template.validate(), Err(FormulaError::InvalidVariable { name, .. }) if name == "d"));
+    }
+
+    #[test]
+    fn test_generate_is_seeded() {
+        let template = speed();
+        let instance = template.generate(seed_for("attempt-1", Uuid::nil())).unwrap();
+
+        assert_eq!(instance, template.generate(seed_for("attempt-1", Uuid::nil())).unwrap());
+        let others: Vec<FormulaInstance> = (2..10)
+            .map(|i| template.generate(seed_for(&format!("attempt-{}", i), Uuid::nil())).unwrap())
+            .collect();
+        assert!(others.iter().any(|other| other.values != instance.values));
+
+        // The same attempt gets other values for another question
+        assert_ne!(seed_for("attempt-1", Uuid::nil()), seed_for("attempt-1", Uuid::new_v4()));
+    }
+
+    #[test]
+    fn test_values_respect_range_and_decimals() {
+        let template = speed();
+        for seed in 0..200 {
+            let instance = template.generate(seed).unwrap();
+            let d = instance.values["d"];
+            let t = instance.values["t"];
+            assert!((10.0..=500.0).contains(&d) && d.fract() == 0.0);
+            assert!((0.5..=8.0).contains(&t));
+            assert!(((t * 10.0).round() - t * 10.0).abs() < 1e-9);
+            assert_eq!(instance.answer, (d / t * 100.0).round() / 100.0);
+        }
+    }
+
+    #[test]
+    fn test_undefined_values_are_drawn_again() {
+        let template = FormulaTemplate {
+            variables: vec![variable("a", 0.0, 1.0, 0)],
+            formula: "\\frac{1}{a}".to_string(),
+            answer_decimals: 2,
+            tolerance: FormulaTolerance::default(),
+        };
+        for seed in 0..20 {
+            assert_eq!(template.generate(seed).unwrap().answer, 1.0);
+        }
+
+        let template = FormulaTemplate {
+            variables: vec![variable("a", 0.0, 0.0, 0)],
+            ..template
+        };
+        assert_eq!(template.generate(0), Err(FormulaError::NoValue));
+    }
+
+    #[test]
+    fn test_render() {
+        let template = speed();
+        let instance = FormulaInstance {
+            seed: 0,
+            values: [("d".to_string(), 120.0), ("t".to_string(), 1.5)].into_iter().collect(),
+            answer: 80.0,
+        };
+        assert_eq!(
+            template.render("[d] km in [t] h, [d] again, [x] untouched", &instance),
+            "120 km in 1.5 h, 120 again, [x] untouched"
+        );
+        assert_eq!(template.format_answer(&instance), "80.00");
+    }
+
+    #[test]
+    fn test_tolerance() {
+        assert_eq!(FormulaTolerance::Absolute(-0.5).for_answer(10.0), 0.5);
+        assert_eq!(FormulaTolerance::Percent(2.0).for_answer(-50.0), 1.0);
+
+        let instance = FormulaInstance { seed: 0, values: BTreeMap::new(), answer: 3.14 };
+        let mut template = speed();
+        template.tolerance = FormulaTolerance::Absolute(0.0);
+        // Half a unit of the last decimal place is always allowed
+        assert_eq!(template.accepted_answer(&instance).rule, MatchRule::Numeric { value: 3.14, tolerance: 0.005 });
+
+        template.tolerance = FormulaTolerance::Absolute(0.1);
+        assert_eq!(template.accepted_answer(&instance).rule, MatchRule::Numeric { value: 3.14, tolerance: 0.1 });
+    }
+
+    #[test]
+    fn test_json_defaults() {
+        let template: FormulaTemplate = serde_json::from_str(
+            r#"{"variables": [{"name": "x", "min": 1, "max": 3}], "formula": "2x"}"#
+        ).unwrap();
+        assert_eq!(template.answer_decimals, 2);
+        assert_eq!(template.tolerance, FormulaTolerance::Absolute(0.0));
+        assert_eq!(template.variables[0].decimals, 0);
+
+        let tolerance: FormulaTolerance = serde_json::from_str(r#"{"kind": "Percent", "value": 5}"#).unwrap();
+        assert_eq!(tolerance, FormulaTolerance::Percent(5.0));
+    }
+}
+
+mod questions {
+    use super::*;
+
+    #[test]
+    fn test_formula_question() {
+        let template = question_template(Some(speed()));
+        let quiz_id = Uuid::new_v4();
+        let instance = template.formula_instance("attempt-1").unwrap();
+
+        let question = template.formula_question(quiz_id, &instance).unwrap();
+        assert_eq!(question.id, template.id);
+        assert_eq!(question.quiz_id, quiz_id);
+        assert_eq!(question.answer_type, AnswerType::ShortAnswer);
+        assert!(!question.content.text.contains('['));
+
+        // The first draw is reproducible for the attempt
+        assert_eq!(template.formula_instance("attempt-1").unwrap(), instance);
+
+        assert!(matches!(
+            question_template(None).formula_instance("attempt-1"),
+            Err(FormulaError::NoFormula)
+        ));
+        assert!(matches!(
+            question_template(None).formula_question(quiz_id, &instance),
+            Err(FormulaError::NoFormula)
+        ));
+    }
+
+    #[test]
+    fn test_prompt_leaves_out_the_answer() {
+        let template = question_template(Some(speed()));
+        let instance = FormulaInstance {
+            seed: 0,
+            values: [("d".to_string(), 120.0), ("t".to_string(), 1.5)].into_iter().collect(),
+            answer: 80.0,
+        };
+
+        let prompt = template.formula_prompt(&instance).unwrap();
+        assert_eq!(prompt.question_id, template.id);
+        assert_eq!(prompt.text, "A car covers 120 km in 1.5 hours. What is its average speed in km/h?");
+        assert_eq!(prompt.answer_decimals, 2);
+
+        let json = serde_json::to_value(&prompt).unwrap();
+        let mut fields: Vec<&String> = json.as_object().unwrap().keys().collect();
+        fields.sort();
+        assert_eq!(fields, ["answer_decimals", "question_id", "text"]);
+    }
+
+    #[test]
+    fn test_grading_uses_the_stored_values() {
+        let template = question_template(Some(speed()));
+        let instance = template.formula_instance("attempt-1").unwrap();
+        let question = template.formula_question(Uuid::new_v4(), &instance).unwrap();
+        let answer = instance.answer;
+
+        let score = |text: String| score_answer(&question, &Answer::Text(text), WrongChoicePenalty::default());
+        assert!(score(format!("{}", answer)).is_correct());
+        assert!(score(format!("{}", answer * 1.009)).is_correct());
+        assert!(!score(format!("{}", answer * 1.02)).is_correct());
+        assert!(!score("fast".to_string()).is_correct());
+
+        // Values stored with the attempt are graded as stored, whatever the
+        // template would draw now
+        let stored = FormulaInstance { seed: instance.seed, values: instance.values.clone(), answer: answer + 100.0 };
+        let question = template.formula_question(Uuid::new_v4(), &stored).unwrap();
+        assert!(score_answer(&question, &Answer::Text(format!("{}", answer + 100.0)), WrongChoicePenalty::default()).is_correct());
+    }
+}
@@ -0,0 +1,388 @@
+// LTI Advantage service clients
+//
+// Assignment and Grade Services (AGS) and Names and Role Provisioning
+// Services (NRPS) are plain REST APIs authorized with an OAuth 2 access token
+// that the tool obtains through the client-credentials grant, authenticating
+// with a JWT signed by its own key.
+//
+// References:
+// - IMS Security Framework 1.0, section 4.1 (client credentials with JWT)
+// - LTI Assignment and Grade Services 2.0
+// - LTI Names and Role Provisioning Services 2.0
+
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use jsonwebtoken::{Algorithm, Header};
+use reqwest::header::{HeaderMap, ACCEPT, CONTENT_TYPE, LINK};
+use serde::{Serialize, Deserialize};
+use tokio::sync::Mutex;
+use url::Url;
+use uuid::Uuid;
+
+use super::jwks::ToolKeyPair;
+use super::{LtiError, LtiPlatformConfig};
+
+/// AGS and NRPS OAuth scopes
+pub mod scopes {
+    pub const LINE_ITEM: &str = "https://purl.imsglobal.org/spec/lti-ags/scope/lineitem";
+    pub const LINE_ITEM_READONLY: &str = "https://purl.imsglobal.org/spec/lti-ags/scope/lineitem.readonly";
+    pub const RESULT_READONLY: &str = "https://purl.imsglobal.org/spec/lti-ags/scope/result.readonly";
+    pub const SCORE: &str = "https://purl.imsglobal.org/spec/lti-ags/scope/score";
+    pub const CONTEXT_MEMBERSHIP_READONLY: &str = "https://purl.imsglobal.org/spec/lti-nrps/scope/contextmembership.readonly";
+}
+
+const LINE_ITEM_MEDIA_TYPE: &str = "application/vnd.ims.lis.v2.lineitem+json";
+const LINE_ITEM_CONTAINER_MEDIA_TYPE: &str = "application/vnd.ims.lis.v2.lineitemcontainer+json";
+const SCORE_MEDIA_TYPE: &str = "application/vnd.ims.lis.v1.score+json";
+const MEMBERSHIP_CONTAINER_MEDIA_TYPE: &str = "application/vnd.ims.lti-nrps.v2.membershipcontainer+json";
+const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// Refresh tokens this long before the platform says they expire
+const TOKEN_EXPIRY_MARGIN_SECS: i64 = 30;
+
+/// AGS endpoint claim of an LTI 1.3 launch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgsEndpointClaim {
+    /// Scopes the platform granted
+    #[serde(default)]
+    pub scope: Vec<String>,
+
+    /// Line items container for the context
+    pub lineitems: Option<String>,
+
+    /// Line item bound to the launched resource link
+    pub lineitem: Option<String>,
+}
+
+/// NRPS claim of an LTI 1.3 launch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NrpsClaim {
+    /// Membership container for the context
+    pub context_memberships_url: String,
+
+    /// Supported service versions
+    #[serde(default)]
+    pub service_versions: Vec<String>,
+}
+
+/// AGS line item (a gradebook column)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineItem {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub score_maximum: f64,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_link_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_date_time: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_date_time: Option<DateTime<Utc>>,
+}
+
+/// AGS activity progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActivityProgress {
+    Initialized,
+    Started,
+    InProgress,
+    Submitted,
+    Completed,
+}
+
+/// AGS grading progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GradingProgress {
+    FullyGraded,
+    Pending,
+    PendingManual,
+    Failed,
+    NotReady,
+}
+
+/// AGS score publish payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Score {
+    pub user_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score_given: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score_maximum: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub timestamp: String,
+    pub activity_progress: ActivityProgress,
+    pub grading_progress: GradingProgress,
+}
+
+impl Score {
+    /// A final, fully graded score
+    pub fn completed(user_id: &str, score_given: f64, score_maximum: f64, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            score_given: Some(score_given),
+            score_maximum: Some(score_maximum),
+            comment: None,
+            timestamp: timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            activity_progress: ActivityProgress::Completed,
+            grading_progress: GradingProgress::FullyGraded,
+        }
+    }
+}
+
+/// NRPS context membership
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Member {
+    pub user_id: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    pub status: Option<String>,
+    pub name: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+    pub email: Option<String>,
+    pub picture: Option<String>,
+    pub lis_person_sourcedid: Option<String>,
+}
+
+/// NRPS context summary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MembershipContext {
+    pub id: String,
+    pub label: Option<String>,
+    pub title: Option<String>,
+}
+
+/// NRPS membership container page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MembershipContainer {
+    pub id: Option<String>,
+    pub context: Option<MembershipContext>,
+    #[serde(default)]
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<i64>,
+}
+
+struct CachedToken {
+    access_token: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Client for the LTI Advantage services of any number of platforms
+pub struct AdvantageClient {
+    /// HTTP client
+    http: reqwest::Client,
+
+    /// Access tokens keyed by platform and scope set
+    tokens: Mutex<HashMap<(Uuid, String), CachedToken>>,
+}
+
+impl AdvantageClient {
+    /// Create a new client
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get an access token for `scopes`, reusing a cached one when valid
+    pub async fn access_token(&self, platform: &LtiPlatformConfig, tool_key: &ToolKeyPair, scopes: &[&str]) -> Result<String, LtiError> {
+        let scope = scopes.join(" ");
+        let cache_key = (platform.id, scope.clone());
+
+        // The cache is not held across the token request, which may be slow
+        if let Some(cached) = self.tokens.lock().await.get(&cache_key) {
+            if cached.expires_at > Utc::now() {
+                return Ok(cached.access_token.clone());
+            }
+        }
+
+        let client_id = platform.client_id.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("client ID".to_string()))?;
+        let token_endpoint = platform.token_endpoint.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("token endpoint".to_string()))?;
+
+        let assertion = client_assertion(client_id, token_endpoint, tool_key)?;
+        let form = [
+            ("grant_type", "client_credentials"),
+            ("client_assertion_type", CLIENT_ASSERTION_TYPE),
+            ("client_assertion", assertion.as_str()),
+            ("scope", scope.as_str()),
+        ];
+
+        let response: TokenResponse = self.http.post(token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| LtiError::Service(format!("Token request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| LtiError::Service(format!("Invalid token response: {}", e)))?;
+
+        let lifetime = response.expires_in.unwrap_or(3600) - TOKEN_EXPIRY_MARGIN_SECS;
+        self.tokens.lock().await.insert(cache_key, CachedToken {
+            access_token: response.access_token.clone(),
+            expires_at: Utc::now() + Duration::seconds(lifetime.max(0)),
+        });
+
+        Ok(response.access_token)
+    }
+
+    /// Create a line item in a line items container
+    pub async fn create_line_item(&self, platform: &LtiPlatformConfig, tool_key: &ToolKeyPair, lineitems_url: &str, line_item: &LineItem) -> Result<LineItem, LtiError> {
+        let token = self.access_token(platform, tool_key, &[scopes::LINE_ITEM]).await?;
+
+        self.http.post(lineitems_url)
+            .bearer_auth(token)
+            .header(CONTENT_TYPE, LINE_ITEM_MEDIA_TYPE)
+            .header(ACCEPT, LINE_ITEM_MEDIA_TYPE)
+            .json(line_item)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| LtiError::Service(format!("Failed to create line item: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| LtiError::Service(format!("Invalid line item response: {}", e)))
+    }
+
+    /// List line items, optionally filtered by resource link or tag
+    pub async fn list_line_items(
+        &self,
+        platform: &LtiPlatformConfig,
+        tool_key: &ToolKeyPair,
+        lineitems_url: &str,
+        resource_link_id: Option<&str>,
+        tag: Option<&str>,
+    ) -> Result<Vec<LineItem>, LtiError> {
+        let token = self.access_token(platform, tool_key, &[scopes::LINE_ITEM_READONLY]).await?;
+
+        let mut url = Url::parse(lineitems_url)
+            .map_err(|e| LtiError::InvalidRequest(format!("Invalid line items URL: {}", e)))?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(resource_link_id) = resource_link_id {
+                query.append_pair("resource_link_id", resource_link_id);
+            }
+            if let Some(tag) = tag {
+                query.append_pair("tag", tag);
+            }
+        }
+
+        let mut line_items = Vec::new();
+        let mut next = Some(url.to_string());
+        while let Some(page_url) = next {
+            let response = self.http.get(&page_url)
+                .bearer_auth(&token)
+                .header(ACCEPT, LINE_ITEM_CONTAINER_MEDIA_TYPE)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| LtiError::Service(format!("Failed to list line items: {}", e)))?;
+
+            next = next_page_url(response.headers());
+            let page: Vec<LineItem> = response.json()
+                .await
+                .map_err(|e| LtiError::Service(format!("Invalid line items response: {}", e)))?;
+            line_items.extend(page);
+        }
+
+        Ok(line_items)
+    }
+
+    /// Publish a score to a line item
+    pub async fn publish_score(&self, platform: &LtiPlatformConfig, tool_key: &ToolKeyPair, lineitem_url: &str, score: &Score) -> Result<(), LtiError> {
+        let token = self.access_token(platform, tool_key, &[scopes::SCORE]).await?;
+
+        self.http.post(scores_url(lineitem_url)?)
+            .bearer_auth(token)
+            .header(CONTENT_TYPE, SCORE_MEDIA_TYPE)
+            .json(score)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| LtiError::Service(format!("Failed to publish score: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Fetch every member of a context, following `Link: rel="next"` pages
+    pub async fn fetch_memberships(&self, platform: &LtiPlatformConfig, tool_key: &ToolKeyPair, context_memberships_url: &str) -> Result<Vec<Member>, LtiError> {
+        let token = self.access_token(platform, tool_key, &[scopes::CONTEXT_MEMBERSHIP_READONLY]).await?;
+
+        let mut members = Vec::new();
+        let mut next = Some(context_memberships_url.to_string());
+        while let Some(page_url) = next {
+            let response = self.http.get(&page_url)
+                .bearer_auth(&token)
+                .header(ACCEPT, MEMBERSHIP_CONTAINER_MEDIA_TYPE)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| LtiError::Service(format!("Failed to fetch memberships: {}", e)))?;
+
+            next = next_page_url(response.headers());
+            let page: MembershipContainer = response.json()
+                .await
+                .map_err(|e| LtiError::Service(format!("Invalid membership response: {}", e)))?;
+            members.extend(page.members);
+        }
+
+        Ok(members)
+    }
+}
+
+/// Build the signed JWT the tool authenticates to the token endpoint with
+pub fn client_assertion(client_id: &str, token_endpoint: &str, tool_key: &ToolKeyPair) -> Result<String, LtiError> {
+    let now = Utc::now().timestamp();
+    let claims = serde_json::json!({
+        "iss": client_id,
+        "sub": client_id,
+        "aud": token_endpoint,
+        "iat": now,
+        "exp": now + 300,
+        "jti": Uuid::new_v4().to_string(),
+    });
+
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some(tool_key.kid.clone());
+
+    jsonwebtoken::encode(&header, &claims, &tool_key.encoding_key()?)
+        .map_err(|e| LtiError::Jwks(format!("Failed to sign client assertion: {}", e)))
+}
+
+/// Scores endpoint of a line item: `/scores` appended to its path
+pub fn scores_url(lineitem_url: &str) -> Result<String, LtiError> {
+    let mut url = Url::parse(lineitem_url)
+        .map_err(|e| LtiError::InvalidRequest(format!("Invalid line item URL: {}", e)))?;
+    let path = format!("{}/scores", url.path().trim_end_matches('/'));
+    url.set_path(&path);
+    Ok(url.to_string())
+}
+
+/// Extract the `rel="next"` target of a `Link` header
+fn next_page_url(headers: &HeaderMap) -> Option<String> {
+    headers.get_all(LINK).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find(|link| link.contains("rel=\"next\""))
+        .and_then(|link| {
+            let start = link.find('<')? + 1;
+            let end = link.find('>')?;
+            Some(link[start..end].to_string())
+        })
+}
@@ -0,0 +1,205 @@
+// JSON Web Key handling for LTI 1.3
+//
+// The tool signs its own JWTs (client assertions, deep linking responses)
+// with an RSA key pair published through its JWKS endpoint, and verifies
+// platform id_tokens against keys fetched from the platform's JWKS URL.
+//
+// References:
+// - IMS Security Framework 1.0, section 6 (Key Management)
+// - RFC 7517 (JSON Web Key)
+
+use std::collections::HashMap;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{DecodingKey, EncodingKey};
+use jsonwebtoken::jwk::JwkSet;
+use rsa::RsaPrivateKey;
+use rsa::pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding};
+use rsa::traits::PublicKeyParts;
+use serde_json::json;
+use sqlx::{Row, SqlitePool};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use super::LtiError;
+
+/// Default lifetime of a fetched platform key set
+pub const DEFAULT_JWKS_TTL_SECS: i64 = 3600;
+
+/// Minimum time between refetches triggered by an unknown `kid`
+const MIN_REFRESH_INTERVAL_SECS: i64 = 60;
+
+/// RSA key pair the tool signs its JWTs with
+#[derive(Clone)]
+pub struct ToolKeyPair {
+    /// Key ID published in the JWKS and set on signed JWT headers
+    pub kid: String,
+
+    /// PKCS#8 PEM-encoded private key
+    private_key_pem: String,
+
+    /// Public half as a JWK
+    public_jwk: serde_json::Value,
+}
+
+impl ToolKeyPair {
+    /// Generate a fresh 2048-bit RSA key pair
+    pub fn generate() -> Result<Self, LtiError> {
+        let private_key = RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 2048)
+            .map_err(|e| LtiError::Jwks(format!("Failed to generate RSA key: {}", e)))?;
+        let pem = private_key.to_pkcs8_pem(LineEnding::LF)
+            .map_err(|e| LtiError::Jwks(format!("Failed to encode RSA key: {}", e)))?;
+
+        Self::from_pem(&uuid::Uuid::new_v4().to_string(), &pem)
+    }
+
+    /// Load a key pair from a PKCS#8 PEM private key
+    pub fn from_pem(kid: &str, private_key_pem: &str) -> Result<Self, LtiError> {
+        let private_key = RsaPrivateKey::from_pkcs8_pem(private_key_pem)
+            .map_err(|e| LtiError::Jwks(format!("Invalid RSA private key: {}", e)))?;
+        let public_key = private_key.to_public_key();
+
+        let public_jwk = json!({
+            "kty": "RSA",
+            "alg": "RS256",
+            "use": "sig",
+            "kid": kid,
+            "n": URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+            "e": URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+        });
+
+        Ok(Self {
+            kid: kid.to_string(),
+            private_key_pem: private_key_pem.to_string(),
+            public_jwk,
+        })
+    }
+
+    /// Load the tool key from SQLite, generating and storing one on first use
+    pub async fn load_or_generate(pool: &SqlitePool) -> Result<Self, LtiError> {
+        sqlx::query(include_str!("../../sql/quiz_lti_schema.sql"))
+            .execute(pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        let row = sqlx::query("SELECT kid, private_key_pem FROM lti_tool_keys ORDER BY created_at DESC LIMIT 1")
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        if let Some(row) = row {
+            let kid: String = row.get("kid");
+            let pem: String = row.get("private_key_pem");
+            return Self::from_pem(&kid, &pem);
+        }
+
+        let key = Self::generate()?;
+        sqlx::query("INSERT INTO lti_tool_keys (kid, private_key_pem, created_at) VALUES (?, ?, ?)")
+            .bind(&key.kid)
+            .bind(&key.private_key_pem)
+            .bind(Utc::now().to_rfc3339())
+            .execute(pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        info!("Generated new LTI tool signing key {}", key.kid);
+        Ok(key)
+    }
+
+    /// Key used to sign tool JWTs
+    pub fn encoding_key(&self) -> Result<EncodingKey, LtiError> {
+        EncodingKey::from_rsa_pem(self.private_key_pem.as_bytes())
+            .map_err(|e| LtiError::Jwks(format!("Invalid RSA private key: {}", e)))
+    }
+
+    /// Public JWK for this key
+    pub fn public_jwk(&self) -> &serde_json::Value {
+        &self.public_jwk
+    }
+
+    /// JWKS document served from the tool's JWKS endpoint
+    pub fn jwks(&self) -> serde_json::Value {
+        json!({ "keys": [self.public_jwk] })
+    }
+}
+
+/// Cached platform key set
+struct CachedJwks {
+    keys: JwkSet,
+    fetched_at: DateTime<Utc>,
+}
+
+/// Cache of platform key sets keyed by JWKS URL
+pub struct JwksCache {
+    /// HTTP client
+    client: reqwest::Client,
+
+    /// How long a fetched key set is trusted
+    ttl: Duration,
+
+    /// Key sets by JWKS URL
+    entries: Mutex<HashMap<String, CachedJwks>>,
+}
+
+impl JwksCache {
+    /// Create a cache with the default TTL
+    pub fn new() -> Self {
+        Self::with_ttl(Duration::seconds(DEFAULT_JWKS_TTL_SECS))
+    }
+
+    /// Create a cache with a custom TTL
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Seed the cache with a key set, e.g. one configured statically
+    pub async fn insert(&self, jwks_url: &str, keys: JwkSet) {
+        let mut entries = self.entries.lock().await;
+        entries.insert(jwks_url.to_string(), CachedJwks { keys, fetched_at: Utc::now() });
+    }
+
+    /// Get the decoding key for `kid`, fetching the key set when it is
+    /// missing, stale, or does not contain the key (platform key rotation)
+    pub async fn decoding_key(&self, jwks_url: &str, kid: &str) -> Result<DecodingKey, LtiError> {
+        let mut entries = self.entries.lock().await;
+        let now = Utc::now();
+
+        if let Some(cached) = entries.get(jwks_url) {
+            let fresh = now - cached.fetched_at < self.ttl;
+            if fresh {
+                if let Some(jwk) = cached.keys.find(kid) {
+                    return DecodingKey::from_jwk(jwk)
+                        .map_err(|e| LtiError::Jwks(format!("Unusable JWK {}: {}", kid, e)));
+                }
+
+                // Avoid hammering the platform with tokens carrying bogus kids
+                if now - cached.fetched_at < Duration::seconds(MIN_REFRESH_INTERVAL_SECS) {
+                    return Err(LtiError::UnknownKey(kid.to_string()));
+                }
+            }
+        }
+
+        debug!("Fetching platform JWKS from {}", jwks_url);
+        let keys: JwkSet = self.client.get(jwks_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| LtiError::Jwks(format!("Failed to fetch JWKS: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| LtiError::Jwks(format!("Invalid JWKS document: {}", e)))?;
+
+        let key = keys.find(kid)
+            .ok_or_else(|| LtiError::UnknownKey(kid.to_string()))
+            .and_then(|jwk| DecodingKey::from_jwk(jwk)
+                .map_err(|e| LtiError::Jwks(format!("Unusable JWK {}: {}", kid, e))));
+
+        entries.insert(jwks_url.to_string(), CachedJwks { keys, fetched_at: now });
+
+        key
+    }
+}
@@ -0,0 +1,386 @@
+// LTI 1.3 / LTI Advantage launch handling
+//
+// A 1.3 launch is an OpenID Connect third-party initiated login:
+// 1. The platform calls the tool's login URL with `iss`, `login_hint`, ...
+// 2. The tool redirects to the platform's auth endpoint with a fresh
+//    `state` and `nonce`
+// 3. The platform form-posts a signed `id_token` and the `state` back to the
+//    tool's launch (redirect) URL
+//
+// References:
+// - IMS Security Framework 1.0, section 5.1 (OIDC third-party login)
+// - LTI Core 1.3, section 5 (message claims)
+
+use std::collections::HashMap;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+use sqlx::{Row, SqlitePool};
+use tokio::sync::Mutex;
+use url::Url;
+use uuid::Uuid;
+
+use super::LtiError;
+use super::advantage::{AgsEndpointClaim, NrpsClaim};
+
+/// LTI 1.3 claim URIs
+pub mod claims {
+    pub const MESSAGE_TYPE: &str = "https://purl.imsglobal.org/spec/lti/claim/message_type";
+    pub const VERSION: &str = "https://purl.imsglobal.org/spec/lti/claim/version";
+    pub const DEPLOYMENT_ID: &str = "https://purl.imsglobal.org/spec/lti/claim/deployment_id";
+    pub const TARGET_LINK_URI: &str = "https://purl.imsglobal.org/spec/lti/claim/target_link_uri";
+    pub const RESOURCE_LINK: &str = "https://purl.imsglobal.org/spec/lti/claim/resource_link";
+    pub const ROLES: &str = "https://purl.imsglobal.org/spec/lti/claim/roles";
+    pub const CONTEXT: &str = "https://purl.imsglobal.org/spec/lti/claim/context";
+    pub const CUSTOM: &str = "https://purl.imsglobal.org/spec/lti/claim/custom";
+    pub const LAUNCH_PRESENTATION: &str = "https://purl.imsglobal.org/spec/lti/claim/launch_presentation";
+    pub const AGS_ENDPOINT: &str = "https://purl.imsglobal.org/spec/lti-ags/claim/endpoint";
+    pub const NRPS: &str = "https://purl.imsglobal.org/spec/lti-nrps/claim/namesroleservice";
+}
+
+/// Message type of a resource link launch
+pub const RESOURCE_LINK_REQUEST: &str = "LtiResourceLinkRequest";
+
+/// How long a pending login may take before its state is rejected
+pub const LOGIN_STATE_TTL_SECS: i64 = 600;
+
+/// OIDC third-party initiated login request sent by the platform
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcLoginRequest {
+    /// Platform issuer
+    pub iss: String,
+
+    /// Opaque user hint, echoed back to the platform
+    pub login_hint: String,
+
+    /// Where the launch should end up
+    pub target_link_uri: String,
+
+    /// Opaque message hint, echoed back to the platform
+    pub lti_message_hint: Option<String>,
+
+    /// Client ID, sent by platforms that register several per issuer
+    pub client_id: Option<String>,
+
+    /// Deployment ID
+    pub lti_deployment_id: Option<String>,
+}
+
+/// Login started by the tool and awaiting the platform's id_token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingLogin {
+    /// Anti-CSRF state handed to the platform
+    pub state: String,
+
+    /// Nonce the id_token must carry
+    pub nonce: String,
+
+    /// Platform the login was started for
+    pub platform_id: Uuid,
+
+    /// Requested target link URI
+    pub target_link_uri: String,
+
+    /// Created at timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+impl PendingLogin {
+    /// Create a pending login with random state and nonce
+    pub fn new(platform_id: Uuid, target_link_uri: &str) -> Self {
+        Self {
+            state: Uuid::new_v4().simple().to_string(),
+            nonce: Uuid::new_v4().simple().to_string(),
+            platform_id,
+            target_link_uri: target_link_uri.to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Whether the login is too old to be completed
+    pub fn is_expired(&self) -> bool {
+        Utc::now() - self.created_at > Duration::seconds(LOGIN_STATE_TTL_SECS)
+    }
+}
+
+/// Store of pending OIDC logins
+#[async_trait]
+pub trait LoginStateStore: Send + Sync {
+    /// Remember a pending login
+    async fn save(&self, login: &PendingLogin) -> Result<(), LtiError>;
+
+    /// Remove and return the pending login for `state`; each state is usable once
+    async fn take(&self, state: &str) -> Result<Option<PendingLogin>, LtiError>;
+}
+
+/// In-memory login state store
+#[derive(Default)]
+pub struct InMemoryLoginStateStore {
+    logins: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl InMemoryLoginStateStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl LoginStateStore for InMemoryLoginStateStore {
+    async fn save(&self, login: &PendingLogin) -> Result<(), LtiError> {
+        let mut logins = self.logins.lock().await;
+        logins.retain(|_, pending| !pending.is_expired());
+        logins.insert(login.state.clone(), login.clone());
+        Ok(())
+    }
+
+    async fn take(&self, state: &str) -> Result<Option<PendingLogin>, LtiError> {
+        let mut logins = self.logins.lock().await;
+        Ok(logins.remove(state))
+    }
+}
+
+/// SQLite-backed login state store
+pub struct SqliteLoginStateStore {
+    pool: SqlitePool,
+}
+
+impl SqliteLoginStateStore {
+    /// Create a store, ensuring the LTI tables exist
+    pub async fn new(pool: SqlitePool) -> Result<Self, LtiError> {
+        sqlx::query(include_str!("../../sql/quiz_lti_schema.sql"))
+            .execute(&pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl LoginStateStore for SqliteLoginStateStore {
+    async fn save(&self, login: &PendingLogin) -> Result<(), LtiError> {
+        let cutoff = Utc::now() - Duration::seconds(LOGIN_STATE_TTL_SECS);
+        sqlx::query("DELETE FROM lti_login_states WHERE created_at < ?")
+            .bind(cutoff.to_rfc3339())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        sqlx::query(
+            "INSERT INTO lti_login_states (state, nonce, platform_id, target_link_uri, created_at) VALUES (?, ?, ?, ?, ?)"
+        )
+        .bind(&login.state)
+        .bind(&login.nonce)
+        .bind(login.platform_id.to_string())
+        .bind(&login.target_link_uri)
+        .bind(login.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn take(&self, state: &str) -> Result<Option<PendingLogin>, LtiError> {
+        let row = sqlx::query(
+            "DELETE FROM lti_login_states WHERE state = ? RETURNING nonce, platform_id, target_link_uri, created_at"
+        )
+        .bind(state)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let platform_id: String = row.get("platform_id");
+        let created_at: String = row.get("created_at");
+
+        Ok(Some(PendingLogin {
+            state: state.to_string(),
+            nonce: row.get("nonce"),
+            platform_id: Uuid::parse_str(&platform_id)
+                .map_err(|e| LtiError::Storage(e.to_string()))?,
+            target_link_uri: row.get("target_link_uri"),
+            created_at: DateTime::parse_from_rfc3339(&created_at)
+                .map_err(|e| LtiError::Storage(e.to_string()))?
+                .with_timezone(&Utc),
+        }))
+    }
+}
+
+/// `aud` claim, which may be a string or an array
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Audience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    /// Whether the audience includes `client_id`
+    pub fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::Single(aud) => aud == client_id,
+            Audience::Multiple(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+
+    /// Whether more than one audience is present
+    pub fn is_multiple(&self) -> bool {
+        matches!(self, Audience::Multiple(auds) if auds.len() > 1)
+    }
+}
+
+/// Resource link claim
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceLinkClaim {
+    pub id: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Context claim
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextClaim {
+    pub id: String,
+    pub label: Option<String>,
+    pub title: Option<String>,
+}
+
+/// Launch presentation claim
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LaunchPresentationClaim {
+    pub document_target: Option<String>,
+    pub return_url: Option<String>,
+    pub locale: Option<String>,
+}
+
+/// Claims of an LTI 1.3 launch id_token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LtiIdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: Audience,
+    pub exp: i64,
+    pub iat: i64,
+    pub nonce: String,
+    pub azp: Option<String>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/message_type")]
+    pub message_type: String,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/version")]
+    pub version: String,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/deployment_id")]
+    pub deployment_id: String,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/target_link_uri")]
+    pub target_link_uri: Option<String>,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/resource_link")]
+    pub resource_link: Option<ResourceLinkClaim>,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/roles", default)]
+    pub roles: Vec<String>,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/context")]
+    pub context: Option<ContextClaim>,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/custom", default)]
+    pub custom: HashMap<String, serde_json::Value>,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/launch_presentation")]
+    pub launch_presentation: Option<LaunchPresentationClaim>,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti-ags/claim/endpoint")]
+    pub ags_endpoint: Option<AgsEndpointClaim>,
+
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti-nrps/claim/namesroleservice")]
+    pub nrps: Option<NrpsClaim>,
+}
+
+impl LtiIdTokenClaims {
+    /// Check the LTI-specific claims that JWT validation does not cover
+    pub fn validate(&self, client_id: &str, deployment_id: Option<&str>, expected_nonce: &str) -> Result<(), LtiError> {
+        if self.nonce != expected_nonce {
+            return Err(LtiError::InvalidIdToken("nonce does not match login".to_string()));
+        }
+
+        // With several audiences the authorized party must be this tool
+        if self.aud.is_multiple() && self.azp.as_deref() != Some(client_id) {
+            return Err(LtiError::InvalidIdToken("azp does not match client_id".to_string()));
+        }
+
+        if self.version != "1.3.0" {
+            return Err(LtiError::InvalidClaim(format!("Unsupported LTI version: {}", self.version)));
+        }
+
+        if self.message_type != RESOURCE_LINK_REQUEST {
+            return Err(LtiError::InvalidClaim(format!("Unsupported message type: {}", self.message_type)));
+        }
+
+        if let Some(expected) = deployment_id {
+            if self.deployment_id != expected {
+                return Err(LtiError::InvalidClaim("Unknown deployment_id".to_string()));
+            }
+        }
+
+        if self.resource_link.is_none() {
+            return Err(LtiError::MissingParameter(claims::RESOURCE_LINK.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Custom claim values as strings, keyed like LTI 1.1 (`custom_` prefix)
+    pub fn custom_parameters(&self) -> HashMap<String, String> {
+        self.custom.iter()
+            .map(|(k, v)| {
+                let value = match v {
+                    serde_json::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (format!("custom_{}", k), value)
+            })
+            .collect()
+    }
+}
+
+/// Build the platform authentication request URL (step 2 of the login)
+pub fn build_auth_request_url(
+    auth_endpoint: &str,
+    client_id: &str,
+    redirect_uri: &str,
+    login: &PendingLogin,
+    login_hint: &str,
+    lti_message_hint: Option<&str>,
+) -> Result<String, LtiError> {
+    let mut url = Url::parse(auth_endpoint)
+        .map_err(|e| LtiError::InvalidRequest(format!("Invalid auth endpoint: {}", e)))?;
+
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("scope", "openid")
+            .append_pair("response_type", "id_token")
+            .append_pair("response_mode", "form_post")
+            .append_pair("prompt", "none")
+            .append_pair("client_id", client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("login_hint", login_hint)
+            .append_pair("state", &login.state)
+            .append_pair("nonce", &login.nonce);
+
+        if let Some(hint) = lti_message_hint {
+            query.append_pair("lti_message_hint", hint);
+        }
+    }
+
+    Ok(url.to_string())
+}
@@ -1,15 +1,92 @@
+pub mod oauth1;
+pub mod nonce_store;
+pub mod jwks;
+pub mod lti13;
+pub mod advantage;
+pub mod outcomes;
+pub mod platforms;
+pub mod roster;
+
+#[cfg(test)]
+mod tests;
+
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use std::collections::HashMap;
+use std::sync::Arc;
 use anyhow::{Result, anyhow};
-use oauth::Token;
-use oauth::post;
-use oauth::get;
-use oauth::Credentials;
+use thiserror::Error;
 use url::Url;
 use tracing::{debug, info, warn, error};
 
+use jsonwebtoken::{Algorithm, Validation};
+use nonce_store::{NonceStore, InMemoryNonceStore};
+use oauth1::SignatureMethod;
+use jwks::{JwksCache, ToolKeyPair};
+use lti13::{LoginStateStore, InMemoryLoginStateStore, LtiIdTokenClaims, OidcLoginRequest, PendingLogin};
+use advantage::{AdvantageClient, AgsEndpointClaim, LineItem, Member, NrpsClaim, Score};
+use outcomes::OutcomeBinding;
+use platforms::PlatformStore;
+
+/// Default allowed clock skew for `oauth_timestamp`, in seconds
+pub const DEFAULT_TIMESTAMP_WINDOW_SECS: i64 = 300;
+
+/// Error type for LTI launch validation
+#[derive(Debug, Error)]
+pub enum LtiError {
+    #[error("Platform not found: {0}")]
+    PlatformNotFound(Uuid),
+
+    #[error("Platform is not configured for {0}")]
+    WrongVersion(String),
+
+    #[error("Platform configuration is missing {0}")]
+    MissingConfiguration(String),
+
+    #[error("Missing required parameter: {0}")]
+    MissingParameter(String),
+
+    #[error("Invalid consumer key")]
+    InvalidConsumerKey,
+
+    #[error("Unsupported OAuth signature method: {0}")]
+    UnsupportedSignatureMethod(String),
+
+    #[error("Invalid OAuth signature")]
+    InvalidSignature,
+
+    #[error("OAuth nonce has already been used")]
+    NonceReplayed,
+
+    #[error("OAuth timestamp {timestamp} is outside the allowed window of {window_secs}s")]
+    ExpiredTimestamp { timestamp: i64, window_secs: i64 },
+
+    #[error("Unknown or expired login state")]
+    InvalidState,
+
+    #[error("Invalid id_token: {0}")]
+    InvalidIdToken(String),
+
+    #[error("Invalid LTI claim: {0}")]
+    InvalidClaim(String),
+
+    #[error("No platform key found for kid {0}")]
+    UnknownKey(String),
+
+    #[error("JWKS error: {0}")]
+    Jwks(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Platform service error: {0}")]
+    Service(String),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+}
+
 /// LTI version
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LtiVersion {
@@ -47,6 +124,10 @@ pub struct LtiPlatformConfig {
     /// Shared secret (for LTI 1.x)
     pub shared_secret: Option<String>,
     
+    /// Issuer (for LTI 1.3+, defaults to the platform URL)
+    #[serde(default)]
+    pub issuer: Option<String>,
+    
     /// Client ID (for LTI 1.3+)
     pub client_id: Option<String>,
     
@@ -114,6 +195,18 @@ pub struct LtiLaunchRequest {
     /// Custom parameters
     pub custom_parameters: HashMap<String, String>,
     
+    /// Basic Outcomes service (LTI 1.1)
+    #[serde(default)]
+    pub outcome_service: Option<LtiOutcomeService>,
+    
+    /// Assignment and Grade Services endpoint (LTI Advantage)
+    #[serde(default)]
+    pub ags_endpoint: Option<AgsEndpointClaim>,
+    
+    /// Names and Role Provisioning Services endpoint (LTI Advantage)
+    #[serde(default)]
+    pub nrps_endpoint: Option<NrpsClaim>,
+    
     /// Created at timestamp
     pub created_at: DateTime<Utc>,
 }
@@ -140,6 +233,84 @@ pub enum LtiRole {
     Other(String),
 }
 
+impl LtiPlatformConfig {
+    /// Whether the platform launches with LTI 1.3 or Advantage
+    pub fn is_lti_1_3(&self) -> bool {
+        matches!(self.version, LtiVersion::V1_3 | LtiVersion::Advantage)
+    }
+    
+    /// Issuer the platform signs id_tokens with
+    pub fn issuer(&self) -> &str {
+        self.issuer.as_deref().unwrap_or(&self.url)
+    }
+}
+
+impl LtiRole {
+    /// Parse the most privileged role from an LTI roles list
+    ///
+    /// Accepts LTI 1.1 short names ("Instructor") as well as LTI 1.3 role
+    /// URIs ("http://purl.imsglobal.org/vocab/lis/v2/membership#Instructor").
+    /// Sub-roles are told apart from their parent role: a teaching assistant
+    /// ("membership/Instructor#TeachingAssistant") is not an instructor.
+    pub fn from_roles(roles: &str) -> Self {
+        let parsed: Vec<LtiRole> = roles
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|role| !role.is_empty())
+            .map(Self::from_role)
+            .collect();
+
+        parsed
+            .iter()
+            .max_by_key(|role| role.rank())
+            .filter(|role| !matches!(role, LtiRole::Other(_)))
+            .cloned()
+            .unwrap_or_else(|| LtiRole::Other(roles.to_string()))
+    }
+
+    /// Parse a single role name or URI
+    fn from_role(role: &str) -> Self {
+        // The most specific part of a URI comes last, after '#' or '/'
+        let name = role.rsplit(|c| c == '#' || c == '/').next().unwrap_or(role);
+        match name {
+            "Instructor" => LtiRole::Instructor,
+            "Administrator" | "SysAdmin" => LtiRole::Administrator,
+            "ContentDeveloper" => LtiRole::ContentDeveloper,
+            "TeachingAssistant" => LtiRole::TeachingAssistant,
+            "Learner" | "Student" => LtiRole::Learner,
+            _ => LtiRole::Other(role.to_string()),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            LtiRole::Other(_) => 0,
+            LtiRole::Learner => 1,
+            LtiRole::TeachingAssistant => 2,
+            LtiRole::ContentDeveloper => 3,
+            LtiRole::Administrator => 4,
+            LtiRole::Instructor => 5,
+        }
+    }
+
+    /// Whether the role may create and edit quizzes
+    pub fn can_edit_content(&self) -> bool {
+        matches!(self, LtiRole::Instructor | LtiRole::Administrator | LtiRole::ContentDeveloper)
+    }
+
+    /// Whether the role may see and grade other users' attempts
+    ///
+    /// Teaching assistants grade but cannot edit quizzes or grant
+    /// accommodations.
+    pub fn can_grade(&self) -> bool {
+        matches!(self, LtiRole::Instructor | LtiRole::Administrator | LtiRole::TeachingAssistant)
+    }
+
+    /// Whether the role may change quiz settings for individual users
+    pub fn can_manage_attempts(&self) -> bool {
+        matches!(self, LtiRole::Instructor | LtiRole::Administrator)
+    }
+}
+
 /// LTI outcome service
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LtiOutcomeService {
@@ -189,14 +360,113 @@ pub enum LtiOutcomeStatus {
 pub struct LtiService {
     /// Platform configurations
     platforms: HashMap<Uuid, LtiPlatformConfig>,
+    
+    /// Seen OAuth nonces for LTI 1.x replay protection
+    nonce_store: Arc<dyn NonceStore>,
+    
+    /// Allowed clock skew for OAuth timestamps
+    timestamp_window: Duration,
+    
+    /// Pending LTI 1.3 OIDC logins
+    login_states: Arc<dyn LoginStateStore>,
+    
+    /// Cached platform key sets
+    jwks_cache: Arc<JwksCache>,
+    
+    /// Tool signing key (for LTI 1.3+)
+    tool_key: Option<ToolKeyPair>,
+    
+    /// Tool launch (OIDC redirect) URL registered with LTI 1.3 platforms
+    redirect_uri: Option<String>,
+    
+    /// LTI Advantage service client
+    advantage: AdvantageClient,
+    
+    /// HTTP client for LTI 1.1 outcome requests
+    http: reqwest::Client,
+}
+
+impl std::fmt::Debug for LtiService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LtiService")
+            .field("platforms", &self.platforms.len())
+            .field("timestamp_window", &self.timestamp_window)
+            .field("redirect_uri", &self.redirect_uri)
+            .finish_non_exhaustive()
+    }
 }
 
 impl LtiService {
-    /// Create a new LTI service
+    /// Create a new LTI service with in-memory nonce and login state stores
     pub fn new() -> Self {
+        Self::with_stores(
+            Arc::new(InMemoryNonceStore::new()),
+            Arc::new(InMemoryLoginStateStore::new()),
+        )
+    }
+    
+    /// Create a new LTI service backed by the given stores
+    pub fn with_stores(nonce_store: Arc<dyn NonceStore>, login_states: Arc<dyn LoginStateStore>) -> Self {
         Self {
             platforms: HashMap::new(),
+            nonce_store,
+            timestamp_window: Duration::seconds(DEFAULT_TIMESTAMP_WINDOW_SECS),
+            login_states,
+            jwks_cache: Arc::new(JwksCache::new()),
+            tool_key: None,
+            redirect_uri: None,
+            advantage: AdvantageClient::new(),
+            http: reqwest::Client::new(),
+        }
+    }
+    
+    /// Create the LTI service the app runs with: persisted nonces, login
+    /// states and tool key, and the registered platforms
+    ///
+    /// The tool key is needed for LTI Advantage grade passback and rosters
+    /// even before a launch URL is configured.
+    pub async fn configured(pool: &sqlx::SqlitePool, redirect_uri: Option<&str>) -> std::result::Result<Self, LtiError> {
+        let nonce_store = nonce_store::SqliteNonceStore::new(pool.clone()).await?;
+        let login_states = lti13::SqliteLoginStateStore::new(pool.clone()).await?;
+        let mut service = Self::with_stores(Arc::new(nonce_store), Arc::new(login_states));
+        
+        service.tool_key = Some(ToolKeyPair::load_or_generate(pool).await?);
+        service.redirect_uri = redirect_uri.map(|uri| uri.to_string());
+        
+        for platform in PlatformStore::new(pool.clone()).await?.list().await? {
+            service.add_platform(platform);
         }
+        
+        info!("LTI service configured with {} platform(s)", service.platforms.len());
+        Ok(service)
+    }
+    
+    /// Configure the tool side of LTI 1.3: signing key and launch URL
+    pub fn set_tool_config(&mut self, tool_key: ToolKeyPair, redirect_uri: &str) {
+        self.tool_key = Some(tool_key);
+        self.redirect_uri = Some(redirect_uri.to_string());
+    }
+    
+    /// Get the platform key cache
+    pub fn jwks_cache(&self) -> Arc<JwksCache> {
+        self.jwks_cache.clone()
+    }
+    
+    /// Get the tool signing key
+    pub fn tool_key(&self) -> Option<&ToolKeyPair> {
+        self.tool_key.as_ref()
+    }
+    
+    /// JWKS document for the tool's public JWKS endpoint
+    pub fn tool_jwks(&self) -> serde_json::Value {
+        self.tool_key.as_ref()
+            .map(|key| key.jwks())
+            .unwrap_or_else(|| serde_json::json!({ "keys": [] }))
+    }
+    
+    /// Set the allowed clock skew for OAuth timestamps
+    pub fn set_timestamp_window(&mut self, window: Duration) {
+        self.timestamp_window = window;
     }
     
     /// Add a platform configuration
@@ -215,63 +485,93 @@ impl LtiService {
     }
     
     /// Validate an LTI 1.x launch request
-    pub fn validate_launch_request(&self, platform_id: &Uuid, params: &HashMap<String, String>) -> Result<LtiLaunchRequest> {
+    ///
+    /// `http_method` and `launch_url` must be exactly what the platform
+    /// signed (the tool's launch URL as seen by the platform), and `params`
+    /// the decoded form body of the launch.
+    pub async fn validate_launch_request(
+        &self,
+        platform_id: &Uuid,
+        http_method: &str,
+        launch_url: &str,
+        params: &HashMap<String, String>,
+    ) -> std::result::Result<LtiLaunchRequest, LtiError> {
         let platform = self.get_platform(platform_id)
-            .ok_or_else(|| anyhow!("Platform not found"))?;
+            .ok_or(LtiError::PlatformNotFound(*platform_id))?;
         
         // Check if platform is LTI 1.x
         if platform.version != LtiVersion::V1_0 && platform.version != LtiVersion::V1_1 {
-            return Err(anyhow!("Platform is not LTI 1.x"));
+            return Err(LtiError::WrongVersion("LTI 1.x".to_string()));
         }
         
         // Check if platform has consumer key and shared secret
-        let consumer_key = platform.consumer_key.clone()
-            .ok_or_else(|| anyhow!("Platform does not have a consumer key"))?;
-        let shared_secret = platform.shared_secret.clone()
-            .ok_or_else(|| anyhow!("Platform does not have a shared secret"))?;
+        let consumer_key = platform.consumer_key.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("consumer key".to_string()))?;
+        let shared_secret = platform.shared_secret.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("shared secret".to_string()))?;
         
-        // Validate OAuth signature
-        let oauth_consumer_key = params.get("oauth_consumer_key")
-            .ok_or_else(|| anyhow!("Missing oauth_consumer_key parameter"))?;
+        let oauth_param = |name: &str| params.get(name)
+            .ok_or_else(|| LtiError::MissingParameter(name.to_string()));
         
-        if oauth_consumer_key != &consumer_key {
-            return Err(anyhow!("Invalid consumer key"));
+        if oauth_param("oauth_consumer_key")? != consumer_key {
+            return Err(LtiError::InvalidConsumerKey);
         }
         
-        // In a real implementation, we would validate the OAuth signature here
-        // For now, we'll just check if the required parameters are present
+        if let Some(version) = params.get("oauth_version") {
+            if version != "1.0" {
+                return Err(LtiError::InvalidRequest(format!("Unsupported oauth_version: {}", version)));
+            }
+        }
+        
+        let signature_method = SignatureMethod::from_param(oauth_param("oauth_signature_method")?)?;
+        let signature = oauth_param(oauth1::OAUTH_SIGNATURE)?;
+        let nonce = oauth_param("oauth_nonce")?;
+        let timestamp: i64 = oauth_param("oauth_timestamp")?
+            .parse()
+            .map_err(|_| LtiError::InvalidRequest("oauth_timestamp is not an integer".to_string()))?;
+        
+        // Verify the signature before touching the nonce store so forged
+        // requests cannot burn legitimate nonces
+        let pairs: Vec<(String, String)> = params.iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let base_string = oauth1::signature_base_string(http_method, launch_url, &pairs)?;
+        
+        if !oauth1::verify(signature_method, &base_string, shared_secret, "", signature) {
+            warn!("Rejected LTI launch for platform {} with invalid OAuth signature", platform_id);
+            debug!("Signature base string: {}", base_string);
+            return Err(LtiError::InvalidSignature);
+        }
+        
+        // Enforce the timestamp window in both directions
+        let now = Utc::now().timestamp();
+        let window_secs = self.timestamp_window.num_seconds();
+        if (now - timestamp).abs() > window_secs {
+            return Err(LtiError::ExpiredTimestamp { timestamp, window_secs });
+        }
+        
+        if !self.nonce_store.check_and_store(consumer_key, nonce, timestamp).await? {
+            warn!("Rejected replayed LTI launch nonce for platform {}", platform_id);
+            return Err(LtiError::NonceReplayed);
+        }
         
         // Check required parameters
         let user_id = params.get("user_id")
-            .ok_or_else(|| anyhow!("Missing user_id parameter"))?
+            .ok_or_else(|| LtiError::MissingParameter("user_id".to_string()))?
             .clone();
         
         let context_id = params.get("context_id")
-            .ok_or_else(|| anyhow!("Missing context_id parameter"))?
+            .ok_or_else(|| LtiError::MissingParameter("context_id".to_string()))?
             .clone();
         
         let resource_link_id = params.get("resource_link_id")
-            .ok_or_else(|| anyhow!("Missing resource_link_id parameter"))?
+            .ok_or_else(|| LtiError::MissingParameter("resource_link_id".to_string()))?
             .clone();
         
-        // Parse role
-        let role = if let Some(roles) = params.get("roles") {
-            if roles.contains("Instructor") {
-                LtiRole::Instructor
-            } else if roles.contains("Administrator") {
-                LtiRole::Administrator
-            } else if roles.contains("ContentDeveloper") {
-                LtiRole::ContentDeveloper
-            } else if roles.contains("TeachingAssistant") {
-                LtiRole::TeachingAssistant
-            } else if roles.contains("Learner") {
-                LtiRole::Learner
-            } else {
-                LtiRole::Other(roles.clone())
-            }
-        } else {
-            LtiRole::Learner // Default to learner if no role is specified
-        };
+        // Parse role, defaulting to learner if no role is specified
+        let role = params.get("roles")
+            .map(|roles| LtiRole::from_roles(roles))
+            .unwrap_or(LtiRole::Learner);
         
         // Extract optional parameters
         let context_title = params.get("context_title").cloned();
@@ -285,6 +585,15 @@ impl LtiService {
             .map(|(k, v)| (k.clone(), v.clone()))
             .collect();
         
+        // Outcomes are only possible when the platform sent both parts
+        let outcome_service = match (params.get("lis_outcome_service_url"), params.get("lis_result_sourcedid")) {
+            (Some(url), Some(source_id)) => Some(LtiOutcomeService {
+                url: url.clone(),
+                source_id: source_id.clone(),
+            }),
+            _ => None,
+        };
+        
         // Create launch request
         let launch_request = LtiLaunchRequest {
             id: Uuid::new_v4(),
@@ -298,95 +607,307 @@ impl LtiService {
             return_url,
             launch_presentation_document_target,
             custom_parameters,
+            outcome_service,
+            ags_endpoint: None,
+            nrps_endpoint: None,
             created_at: Utc::now(),
         };
         
         Ok(launch_request)
     }
     
-    /// Send an outcome to an LTI platform
-    pub fn send_outcome(&self, platform_id: &Uuid, outcome_service: &LtiOutcomeService, result: &LtiOutcomeResult) -> Result<()> {
+    /// Purge nonces that can no longer be replayed within the timestamp window
+    pub async fn purge_expired_nonces(&self) -> std::result::Result<u64, LtiError> {
+        self.nonce_store.purge_expired(Utc::now() - self.timestamp_window).await
+    }
+    
+    /// Send an outcome to an LTI 1.x platform through the Basic Outcomes service
+    pub async fn send_outcome(&self, platform_id: &Uuid, outcome_service: &LtiOutcomeService, result: &LtiOutcomeResult) -> std::result::Result<(), LtiError> {
         let platform = self.get_platform(platform_id)
-            .ok_or_else(|| anyhow!("Platform not found"))?;
+            .ok_or(LtiError::PlatformNotFound(*platform_id))?;
         
         // Check if platform is LTI 1.x
         if platform.version != LtiVersion::V1_0 && platform.version != LtiVersion::V1_1 {
-            return Err(anyhow!("Platform is not LTI 1.x"));
+            return Err(LtiError::WrongVersion("LTI 1.x".to_string()));
         }
         
         // Check if platform has consumer key and shared secret
-        let consumer_key = platform.consumer_key.clone()
-            .ok_or_else(|| anyhow!("Platform does not have a consumer key"))?;
-        let shared_secret = platform.shared_secret.clone()
-            .ok_or_else(|| anyhow!("Platform does not have a shared secret"))?;
-        
-        // Create OAuth credentials
-        let credentials = Credentials::new(consumer_key, shared_secret);
-        
-        // Create XML payload
-        let xml_payload = format!(
-            r#"<?xml version="1.0" encoding="UTF-8"?>
-            <imsx_POXEnvelopeRequest xmlns="http://www.imsglobal.org/services/ltiv1p1/xsd/imsoms_v1p0">
-              <imsx_POXHeader>
-                <imsx_POXRequestHeaderInfo>
-                  <imsx_version>V1.0</imsx_version>
-                  <imsx_messageIdentifier>{}</imsx_messageIdentifier>
-                </imsx_POXRequestHeaderInfo>
-              </imsx_POXHeader>
-              <imsx_POXBody>
-                <replaceResultRequest>
-                  <resultRecord>
-                    <sourcedGUID>
-                      <sourcedId>{}</sourcedId>
-                    </sourcedGUID>
-                    <result>
-                      <resultScore>
-                        <language>en</language>
-                        <textString>{}</textString>
-                      </resultScore>
-                    </result>
-                  </resultRecord>
-                </replaceResultRequest>
-              </imsx_POXBody>
-            </imsx_POXEnvelopeRequest>"#,
-            Uuid::new_v4(),
-            outcome_service.source_id,
-            result.score
-        );
-        
-        // In a real implementation, we would send the outcome to the platform
-        // For now, we'll just log the payload
-        info!("Sending outcome to platform {}: {}", platform_id, xml_payload);
+        let consumer_key = platform.consumer_key.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("consumer key".to_string()))?;
+        let shared_secret = platform.shared_secret.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("shared secret".to_string()))?;
+        
+        let body = outcomes::replace_result_request(&result.id.to_string(), &outcome_service.source_id, result.score);
+        let authorization = outcomes::sign_pox_request(&outcome_service.url, consumer_key, shared_secret, &body)?;
+        
+        debug!("Sending outcome to platform {}: {}", platform_id, body);
+        
+        let response = self.http.post(&outcome_service.url)
+            .header(reqwest::header::AUTHORIZATION, authorization)
+            .header(reqwest::header::CONTENT_TYPE, outcomes::POX_CONTENT_TYPE)
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| LtiError::Service(format!("Failed to send outcome: {}", e)))?;
         
+        let response_body = response.text()
+            .await
+            .map_err(|e| LtiError::Service(format!("Failed to read outcome response: {}", e)))?;
+        
+        outcomes::check_pox_response(&response_body)?;
+        
+        info!("Sent outcome {} for user {} to platform {}", result.score, result.user_id, platform_id);
         Ok(())
     }
     
+    /// Build the outcome binding for a quiz launched through LTI
+    pub fn outcome_binding_for_launch(&self, launch: &LtiLaunchRequest, quiz_id: &str, user_id: &str) -> Option<OutcomeBinding> {
+        let ags = launch.ags_endpoint.as_ref();
+        let binding = OutcomeBinding {
+            quiz_id: quiz_id.to_string(),
+            user_id: user_id.to_string(),
+            platform_id: launch.platform_id,
+            lti_user_id: launch.user_id.clone(),
+            outcome_url: launch.outcome_service.as_ref().map(|service| service.url.clone()),
+            source_id: launch.outcome_service.as_ref().map(|service| service.source_id.clone()),
+            lineitem_url: ags.and_then(|ags| ags.lineitem.clone()),
+            lineitems_url: ags.and_then(|ags| ags.lineitems.clone()),
+            resource_link_id: launch.resource_link_id.clone(),
+            updated_at: Utc::now(),
+        };
+        
+        let has_target = binding.outcome_url.is_some() || binding.lineitem_url.is_some() || binding.lineitems_url.is_some();
+        has_target.then_some(binding)
+    }
+    
+    /// Send a quiz score to wherever the binding says, returning the line item
+    /// URL if one had to be created
+    ///
+    /// `score` and `max_score` are in quiz points; LTI 1.1 receives the
+    /// normalized 0.0 - 1.0 value.
+    pub async fn publish_quiz_score(&self, binding: &OutcomeBinding, quiz_title: &str, score: f64, max_score: f64) -> std::result::Result<Option<String>, LtiError> {
+        let platform = self.get_platform(&binding.platform_id)
+            .ok_or(LtiError::PlatformNotFound(binding.platform_id))?;
+        
+        if !platform.is_lti_1_3() {
+            let (Some(url), Some(source_id)) = (&binding.outcome_url, &binding.source_id) else {
+                return Err(LtiError::MissingConfiguration("outcome service".to_string()));
+            };
+            
+            let result = LtiOutcomeResult {
+                id: Uuid::new_v4(),
+                user_id: binding.lti_user_id.clone(),
+                score: if max_score > 0.0 { (score / max_score) as f32 } else { 0.0 },
+                status: LtiOutcomeStatus::Pending,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            };
+            let service = LtiOutcomeService { url: url.clone(), source_id: source_id.clone() };
+            self.send_outcome(&platform.id, &service, &result).await?;
+            return Ok(None);
+        }
+        
+        let tool_key = self.tool_key.as_ref()
+            .ok_or_else(|| LtiError::MissingConfiguration("tool signing key".to_string()))?;
+        
+        // Without a coupled line item, find or create one for the resource link
+        let (lineitem_url, created) = match &binding.lineitem_url {
+            Some(url) => (url.clone(), false),
+            None => {
+                let lineitems_url = binding.lineitems_url.as_deref()
+                    .ok_or_else(|| LtiError::MissingConfiguration("AGS line items endpoint".to_string()))?;
+                let existing = self.advantage.list_line_items(platform, tool_key, lineitems_url, Some(&binding.resource_link_id), Some(&binding.quiz_id)).await?;
+                let (line_item, created) = match existing.into_iter().find(|item| item.id.is_some()) {
+                    Some(item) => (item, false),
+                    None => (self.advantage.create_line_item(platform, tool_key, lineitems_url, &LineItem {
+                        id: None,
+                        score_maximum: max_score,
+                        label: quiz_title.to_string(),
+                        resource_id: Some(binding.quiz_id.clone()),
+                        resource_link_id: Some(binding.resource_link_id.clone()),
+                        tag: Some(binding.quiz_id.clone()),
+                        start_date_time: None,
+                        end_date_time: None,
+                    }).await?, true),
+                };
+                let url = line_item.id
+                    .ok_or_else(|| LtiError::Service("Platform returned a line item without an id".to_string()))?;
+                (url, created)
+            }
+        };
+        
+        let score = Score::completed(&binding.lti_user_id, score, max_score, Utc::now());
+        self.advantage.publish_score(platform, tool_key, &lineitem_url, &score).await?;
+        
+        info!("Published AGS score for user {} to {}", binding.lti_user_id, lineitem_url);
+        Ok(created.then_some(lineitem_url))
+    }
+    
+    /// Fetch a course roster through Names and Role Provisioning Services
+    pub async fn fetch_roster(&self, platform_id: &Uuid, context_memberships_url: &str) -> std::result::Result<Vec<Member>, LtiError> {
+        let platform = self.get_platform(platform_id)
+            .ok_or(LtiError::PlatformNotFound(*platform_id))?;
+        
+        if !platform.is_lti_1_3() {
+            return Err(LtiError::WrongVersion("LTI 1.3".to_string()));
+        }
+        
+        let tool_key = self.tool_key.as_ref()
+            .ok_or_else(|| LtiError::MissingConfiguration("tool signing key".to_string()))?;
+        
+        self.advantage.fetch_memberships(platform, tool_key, context_memberships_url).await
+    }
+    
+    /// Find the LTI 1.3 platform registered for an issuer (and client ID)
+    pub fn find_lti_1_3_platform(&self, issuer: &str, client_id: Option<&str>) -> Option<&LtiPlatformConfig> {
+        self.platforms.values()
+            .filter(|platform| platform.is_lti_1_3() && platform.issuer() == issuer)
+            .find(|platform| match client_id {
+                Some(client_id) => platform.client_id.as_deref() == Some(client_id),
+                None => true,
+            })
+    }
+    
+    /// Handle an OIDC third-party initiated login from an LTI 1.3 platform
+    ///
+    /// Returns the platform authentication URL the browser must be
+    /// redirected to.
+    pub async fn handle_oidc_login(&self, request: &OidcLoginRequest) -> std::result::Result<String, LtiError> {
+        let platform = self.find_lti_1_3_platform(&request.iss, request.client_id.as_deref())
+            .ok_or_else(|| LtiError::InvalidRequest(format!("No LTI 1.3 platform registered for issuer {}", request.iss)))?;
+        
+        if let (Some(expected), Some(received)) = (platform.deployment_id.as_deref(), request.lti_deployment_id.as_deref()) {
+            if expected != received {
+                return Err(LtiError::InvalidClaim("Unknown deployment_id".to_string()));
+            }
+        }
+        
+        self.start_lti_1_3_login(
+            platform,
+            &request.target_link_uri,
+            &request.login_hint,
+            request.lti_message_hint.as_deref(),
+        ).await
+    }
+    
     /// Generate an LTI 1.3 launch URL
-    pub fn generate_lti_1_3_launch_url(&self, platform_id: &Uuid, resource_id: &str, user_id: &str) -> Result<String> {
+    ///
+    /// Starts a tool-initiated OIDC login for `user_id` and returns the
+    /// platform authentication URL; the platform answers with a launch for
+    /// `resource_id`.
+    pub async fn generate_lti_1_3_launch_url(&self, platform_id: &Uuid, resource_id: &str, user_id: &str) -> std::result::Result<String, LtiError> {
         let platform = self.get_platform(platform_id)
-            .ok_or_else(|| anyhow!("Platform not found"))?;
+            .ok_or(LtiError::PlatformNotFound(*platform_id))?;
         
         // Check if platform is LTI 1.3+
-        if platform.version != LtiVersion::V1_3 && platform.version != LtiVersion::Advantage {
-            return Err(anyhow!("Platform is not LTI 1.3+"));
+        if !platform.is_lti_1_3() {
+            return Err(LtiError::WrongVersion("LTI 1.3".to_string()));
         }
         
-        // Check if platform has required parameters
-        let client_id = platform.client_id.clone()
-            .ok_or_else(|| anyhow!("Platform does not have a client ID"))?;
-        let deployment_id = platform.deployment_id.clone()
-            .ok_or_else(|| anyhow!("Platform does not have a deployment ID"))?;
-        let auth_endpoint = platform.auth_endpoint.clone()
-            .ok_or_else(|| anyhow!("Platform does not have an authentication endpoint"))?;
+        let target_link_uri = self.redirect_uri.clone()
+            .ok_or_else(|| LtiError::MissingConfiguration("tool redirect URI".to_string()))?;
+        
+        self.start_lti_1_3_login(platform, &target_link_uri, user_id, Some(resource_id)).await
+    }
+    
+    async fn start_lti_1_3_login(
+        &self,
+        platform: &LtiPlatformConfig,
+        target_link_uri: &str,
+        login_hint: &str,
+        lti_message_hint: Option<&str>,
+    ) -> std::result::Result<String, LtiError> {
+        let client_id = platform.client_id.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("client ID".to_string()))?;
+        let auth_endpoint = platform.auth_endpoint.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("authentication endpoint".to_string()))?;
+        let redirect_uri = self.redirect_uri.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("tool redirect URI".to_string()))?;
+        
+        let login = PendingLogin::new(platform.id, target_link_uri);
+        self.login_states.save(&login).await?;
         
-        // In a real implementation, we would generate a JWT and create a launch URL
-        // For now, we'll just return a placeholder URL
-        let launch_url = format!(
-            "{}?client_id={}&lti_deployment_id={}&resource_id={}&user_id={}",
-            auth_endpoint, client_id, deployment_id, resource_id, user_id
-        );
+        lti13::build_auth_request_url(auth_endpoint, client_id, redirect_uri, &login, login_hint, lti_message_hint)
+    }
+    
+    /// Validate an LTI 1.3 launch (the id_token form-posted to the redirect URI)
+    pub async fn validate_lti_1_3_launch(&self, id_token: &str, state: &str) -> std::result::Result<LtiLaunchRequest, LtiError> {
+        let login = self.login_states.take(state).await?
+            .ok_or(LtiError::InvalidState)?;
+        
+        if login.is_expired() {
+            return Err(LtiError::InvalidState);
+        }
         
-        Ok(launch_url)
+        let platform = self.get_platform(&login.platform_id)
+            .ok_or(LtiError::PlatformNotFound(login.platform_id))?;
+        let client_id = platform.client_id.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("client ID".to_string()))?;
+        let jwks_endpoint = platform.jwks_endpoint.as_deref()
+            .ok_or_else(|| LtiError::MissingConfiguration("JWKS endpoint".to_string()))?;
+        
+        let header = jsonwebtoken::decode_header(id_token)
+            .map_err(|e| LtiError::InvalidIdToken(e.to_string()))?;
+        if header.alg != Algorithm::RS256 {
+            return Err(LtiError::InvalidIdToken(format!("Unsupported algorithm: {:?}", header.alg)));
+        }
+        let kid = header.kid
+            .ok_or_else(|| LtiError::InvalidIdToken("Missing kid header".to_string()))?;
+        
+        let key = self.jwks_cache.decoding_key(jwks_endpoint, &kid).await?;
+        
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&[client_id]);
+        validation.set_issuer(&[platform.issuer()]);
+        validation.set_required_spec_claims(&["exp", "iat", "iss", "aud", "sub"]);
+        validation.leeway = 60;
+        
+        let claims = jsonwebtoken::decode::<LtiIdTokenClaims>(id_token, &key, &validation)
+            .map_err(|e| LtiError::InvalidIdToken(e.to_string()))?
+            .claims;
+        
+        claims.validate(client_id, platform.deployment_id.as_deref(), &login.nonce)?;
+        
+        // The id_token itself must not be replayable either
+        if !self.nonce_store.check_and_store(platform.issuer(), &claims.nonce, claims.iat).await? {
+            return Err(LtiError::NonceReplayed);
+        }
+        
+        Ok(self.launch_request_from_claims(platform.id, claims))
+    }
+    
+    fn launch_request_from_claims(&self, platform_id: Uuid, claims: LtiIdTokenClaims) -> LtiLaunchRequest {
+        let custom_parameters = claims.custom_parameters();
+        let role = if claims.roles.is_empty() {
+            LtiRole::Learner
+        } else {
+            LtiRole::from_roles(&claims.roles.join(","))
+        };
+        let resource_link = claims.resource_link.unwrap_or_else(|| lti13::ResourceLinkClaim {
+            id: String::new(),
+            title: None,
+            description: None,
+        });
+        let presentation = claims.launch_presentation.unwrap_or_default();
+        
+        LtiLaunchRequest {
+            id: Uuid::new_v4(),
+            platform_id,
+            user_id: claims.sub,
+            role,
+            context_id: claims.context.as_ref().map(|c| c.id.clone()).unwrap_or_default(),
+            context_title: claims.context.and_then(|c| c.title),
+            resource_link_id: resource_link.id,
+            resource_link_title: resource_link.title,
+            return_url: presentation.return_url,
+            launch_presentation_document_target: presentation.document_target,
+            custom_parameters,
+            outcome_service: None,
+            ags_endpoint: claims.ags_endpoint,
+            nrps_endpoint: claims.nrps,
+            created_at: Utc::now(),
+        }
     }
 }
 
@@ -599,6 +1120,7 @@ pub fn create_platform_config<T: LtiPlatformProvider>(provider: &T) -> LtiPlatfo
         version: provider.get_version(),
         consumer_key: provider.get_consumer_key().map(|s| s.to_string()),
         shared_secret: provider.get_shared_secret().map(|s| s.to_string()),
+        issuer: None,
         client_id: provider.get_client_id().map(|s| s.to_string()),
         deployment_id: provider.get_deployment_id().map(|s| s.to_string()),
         public_jwk: None,
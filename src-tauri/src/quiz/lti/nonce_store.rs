@@ -0,0 +1,107 @@
+// OAuth nonce storage for LTI 1.x replay protection
+//
+// A nonce is only accepted once per consumer key. Nonces older than the
+// timestamp window can be purged because the timestamp check already rejects
+// any request that could reuse them.
+
+use std::collections::HashMap;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use tokio::sync::Mutex;
+
+use super::LtiError;
+
+/// Store of previously seen OAuth nonces
+#[async_trait]
+pub trait NonceStore: Send + Sync {
+    /// Record a nonce, returning `false` if it was already used by this consumer
+    async fn check_and_store(&self, consumer_key: &str, nonce: &str, timestamp: i64) -> Result<bool, LtiError>;
+
+    /// Drop nonces whose timestamp is older than `before`
+    async fn purge_expired(&self, before: DateTime<Utc>) -> Result<u64, LtiError>;
+}
+
+/// In-memory nonce store, used by the standalone quiz app and in tests
+#[derive(Default)]
+pub struct InMemoryNonceStore {
+    /// Seen nonces keyed by (consumer key, nonce)
+    nonces: Mutex<HashMap<(String, String), i64>>,
+}
+
+impl InMemoryNonceStore {
+    /// Create an empty nonce store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl NonceStore for InMemoryNonceStore {
+    async fn check_and_store(&self, consumer_key: &str, nonce: &str, timestamp: i64) -> Result<bool, LtiError> {
+        let mut nonces = self.nonces.lock().await;
+        let key = (consumer_key.to_string(), nonce.to_string());
+
+        if nonces.contains_key(&key) {
+            return Ok(false);
+        }
+
+        nonces.insert(key, timestamp);
+        Ok(true)
+    }
+
+    async fn purge_expired(&self, before: DateTime<Utc>) -> Result<u64, LtiError> {
+        let mut nonces = self.nonces.lock().await;
+        let cutoff = before.timestamp();
+        let initial = nonces.len();
+        nonces.retain(|_, timestamp| *timestamp >= cutoff);
+        Ok((initial - nonces.len()) as u64)
+    }
+}
+
+/// SQLite-backed nonce store so replay protection survives restarts
+pub struct SqliteNonceStore {
+    pool: SqlitePool,
+}
+
+impl SqliteNonceStore {
+    /// Create a nonce store, ensuring the `lti_oauth_nonces` table exists
+    pub async fn new(pool: SqlitePool) -> Result<Self, LtiError> {
+        sqlx::query(include_str!("../../sql/quiz_lti_schema.sql"))
+            .execute(&pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl NonceStore for SqliteNonceStore {
+    async fn check_and_store(&self, consumer_key: &str, nonce: &str, timestamp: i64) -> Result<bool, LtiError> {
+        // The primary key makes the insert the atomic check: a replayed nonce
+        // is ignored and affects no rows.
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO lti_oauth_nonces (consumer_key, nonce, timestamp, received_at) VALUES (?, ?, ?, ?)"
+        )
+        .bind(consumer_key)
+        .bind(nonce)
+        .bind(timestamp)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn purge_expired(&self, before: DateTime<Utc>) -> Result<u64, LtiError> {
+        let result = sqlx::query("DELETE FROM lti_oauth_nonces WHERE timestamp < ?")
+            .bind(before.timestamp())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+}
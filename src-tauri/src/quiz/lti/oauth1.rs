@@ -0,0 +1,218 @@
+// OAuth 1.0a message signing for LTI 1.x
+//
+// LTI 1.0/1.1 launches and outcome requests are signed with OAuth 1.0a using
+// the consumer key/shared secret pair and an empty token secret.
+//
+// References:
+// - RFC 5849 (The OAuth 1.0 Protocol), section 3.4
+// - IMS LTI 1.1 Implementation Guide, section 4 (Security Model)
+
+use std::collections::HashMap;
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::Sha256;
+use url::Url;
+
+use super::LtiError;
+
+/// Parameter carrying the signature itself; never part of the base string
+pub const OAUTH_SIGNATURE: &str = "oauth_signature";
+
+/// OAuth signature method
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureMethod {
+    /// HMAC-SHA1 (the LTI 1.x default)
+    HmacSha1,
+
+    /// HMAC-SHA256
+    HmacSha256,
+}
+
+impl SignatureMethod {
+    /// Parse the value of the `oauth_signature_method` parameter
+    pub fn from_param(value: &str) -> Result<Self, LtiError> {
+        match value {
+            "HMAC-SHA1" => Ok(SignatureMethod::HmacSha1),
+            "HMAC-SHA256" => Ok(SignatureMethod::HmacSha256),
+            other => Err(LtiError::UnsupportedSignatureMethod(other.to_string())),
+        }
+    }
+
+    /// Value used for the `oauth_signature_method` parameter
+    pub fn as_param(&self) -> &'static str {
+        match self {
+            SignatureMethod::HmacSha1 => "HMAC-SHA1",
+            SignatureMethod::HmacSha256 => "HMAC-SHA256",
+        }
+    }
+}
+
+/// Percent-encode a value as required by RFC 5849 section 3.6
+///
+/// Only the unreserved characters `ALPHA / DIGIT / "-" / "." / "_" / "~"` are
+/// left as-is; everything else is encoded from its UTF-8 bytes with uppercase
+/// hex digits.
+pub fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char);
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Build the base string URI (RFC 5849 section 3.4.1.2)
+///
+/// Scheme and host are lowercased, default ports are dropped and the query
+/// and fragment are removed. Returns the URI and the decoded query
+/// parameters, which take part in the parameter normalization.
+pub fn base_string_uri(url: &str) -> Result<(String, Vec<(String, String)>), LtiError> {
+    let parsed = Url::parse(url)
+        .map_err(|e| LtiError::InvalidRequest(format!("Invalid launch URL: {}", e)))?;
+
+    let host = parsed.host_str()
+        .ok_or_else(|| LtiError::InvalidRequest("Launch URL has no host".to_string()))?
+        .to_lowercase();
+
+    let scheme = parsed.scheme().to_lowercase();
+    let authority = match parsed.port() {
+        Some(port) if !((scheme == "http" && port == 80) || (scheme == "https" && port == 443)) => {
+            format!("{}:{}", host, port)
+        }
+        _ => host,
+    };
+
+    let query_params = parsed.query_pairs()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+
+    Ok((format!("{}://{}{}", scheme, authority, parsed.path()), query_params))
+}
+
+/// Normalize request parameters (RFC 5849 section 3.4.1.3.2)
+///
+/// `oauth_signature` is excluded; names and values are encoded and then
+/// sorted by name, with ties broken by value.
+pub fn normalize_parameters<'a, I>(params: I) -> String
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut encoded: Vec<(String, String)> = params.into_iter()
+        .filter(|(k, _)| *k != OAUTH_SIGNATURE)
+        .map(|(k, v)| (percent_encode(k), percent_encode(v)))
+        .collect();
+
+    encoded.sort();
+
+    encoded.iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Construct the signature base string (RFC 5849 section 3.4.1)
+///
+/// `params` are the decoded body/header parameters; any query parameters on
+/// `url` are merged in automatically.
+pub fn signature_base_string(method: &str, url: &str, params: &[(String, String)]) -> Result<String, LtiError> {
+    let (base_uri, query_params) = base_string_uri(url)?;
+
+    let normalized = normalize_parameters(
+        params.iter()
+            .chain(query_params.iter())
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+    );
+
+    Ok(format!(
+        "{}&{}&{}",
+        method.to_uppercase(),
+        percent_encode(&base_uri),
+        percent_encode(&normalized)
+    ))
+}
+
+/// Sign a base string, returning the base64-encoded signature
+pub fn sign(method: SignatureMethod, base_string: &str, consumer_secret: &str, token_secret: &str) -> String {
+    let key = format!("{}&{}", percent_encode(consumer_secret), percent_encode(token_secret));
+
+    let digest = match method {
+        SignatureMethod::HmacSha1 => {
+            let mut mac = Hmac::<Sha1>::new_from_slice(key.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(base_string.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        }
+        SignatureMethod::HmacSha256 => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(base_string.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        }
+    };
+
+    general_purpose::STANDARD.encode(digest)
+}
+
+/// Verify a base64-encoded signature in constant time
+pub fn verify(method: SignatureMethod, base_string: &str, consumer_secret: &str, token_secret: &str, signature: &str) -> bool {
+    let expected = sign(method, base_string, consumer_secret, token_secret);
+    constant_time_eq(expected.as_bytes(), signature.as_bytes())
+}
+
+/// Sign a full parameter set for an outgoing request
+///
+/// Fills in the `oauth_*` protocol parameters that are missing and adds
+/// `oauth_signature`. Used for outcome requests and by tests that need a
+/// correctly signed launch.
+pub fn sign_request(
+    method: SignatureMethod,
+    http_method: &str,
+    url: &str,
+    consumer_key: &str,
+    consumer_secret: &str,
+    params: &mut HashMap<String, String>,
+) -> Result<String, LtiError> {
+    params.entry("oauth_consumer_key".to_string()).or_insert_with(|| consumer_key.to_string());
+    params.entry("oauth_signature_method".to_string()).or_insert_with(|| method.as_param().to_string());
+    params.entry("oauth_timestamp".to_string()).or_insert_with(|| chrono::Utc::now().timestamp().to_string());
+    params.entry("oauth_nonce".to_string()).or_insert_with(|| uuid::Uuid::new_v4().simple().to_string());
+    params.entry("oauth_version".to_string()).or_insert_with(|| "1.0".to_string());
+    params.remove(OAUTH_SIGNATURE);
+
+    let pairs: Vec<(String, String)> = params.iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    let base_string = signature_base_string(http_method, url, &pairs)?;
+    let signature = sign(method, &base_string, consumer_secret, "");
+
+    params.insert(OAUTH_SIGNATURE.to_string(), signature.clone());
+
+    Ok(signature)
+}
+
+/// Build an `Authorization: OAuth ...` header value from signed parameters
+pub fn authorization_header(params: &HashMap<String, String>) -> String {
+    let mut oauth_params: Vec<(&String, &String)> = params.iter()
+        .filter(|(k, _)| k.starts_with("oauth_"))
+        .collect();
+    oauth_params.sort();
+
+    let fields = oauth_params.iter()
+        .map(|(k, v)| format!("{}=\"{}\"", percent_encode(k), percent_encode(v)))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!("OAuth {}", fields)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,234 @@
+// Grade passback for LTI launches
+//
+// LTI 1.1 uses the Basic Outcomes service: a POX (Plain Old XML) envelope
+// POSTed to `lis_outcome_service_url`, signed with OAuth 1.0a and an
+// `oauth_body_hash` of the XML. LTI 1.3 launches publish AGS scores instead.
+//
+// Every LTI launch of a quiz records an outcome binding so that a quiz
+// completed later (possibly after going offline) still knows where its
+// grade has to go.
+//
+// References:
+// - IMS LTI 1.1 Implementation Guide, section 6 (LTI Basic Outcomes Service)
+// - OAuth Request Body Hash 1.0
+
+use std::collections::HashMap;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use sha1::{Digest, Sha1};
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use super::oauth1::{self, SignatureMethod};
+use super::LtiError;
+
+/// Content type of Basic Outcomes requests
+pub const POX_CONTENT_TYPE: &str = "application/xml";
+
+/// Where the grade for a quiz attempt should be sent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutcomeBinding {
+    /// Local quiz ID
+    pub quiz_id: String,
+
+    /// Local user ID
+    pub user_id: String,
+
+    /// Platform the launch came from
+    pub platform_id: Uuid,
+
+    /// The platform's ID for the user (AGS `userId`)
+    pub lti_user_id: String,
+
+    /// Basic Outcomes service URL (LTI 1.1)
+    pub outcome_url: Option<String>,
+
+    /// Result sourcedId (LTI 1.1)
+    pub source_id: Option<String>,
+
+    /// AGS line item URL (LTI 1.3)
+    pub lineitem_url: Option<String>,
+
+    /// AGS line items container URL (LTI 1.3)
+    pub lineitems_url: Option<String>,
+
+    /// Resource link the launch was for
+    pub resource_link_id: String,
+
+    /// Updated at timestamp
+    pub updated_at: DateTime<Utc>,
+}
+
+/// SQLite store of outcome bindings
+#[derive(Debug)]
+pub struct OutcomeBindingStore {
+    pool: SqlitePool,
+}
+
+impl OutcomeBindingStore {
+    /// Create a store, ensuring the LTI tables exist
+    pub async fn new(pool: SqlitePool) -> Result<Self, LtiError> {
+        sqlx::query(include_str!("../../sql/quiz_lti_schema.sql"))
+            .execute(&pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(Self { pool })
+    }
+
+    /// Record (or refresh) the binding from the latest launch
+    pub async fn save(&self, binding: &OutcomeBinding) -> Result<(), LtiError> {
+        sqlx::query(
+            "INSERT INTO lti_outcome_bindings
+                (quiz_id, user_id, platform_id, lti_user_id, outcome_url, source_id, lineitem_url, lineitems_url, resource_link_id, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT (quiz_id, user_id) DO UPDATE SET
+                platform_id = excluded.platform_id,
+                lti_user_id = excluded.lti_user_id,
+                outcome_url = excluded.outcome_url,
+                source_id = excluded.source_id,
+                lineitem_url = excluded.lineitem_url,
+                lineitems_url = excluded.lineitems_url,
+                resource_link_id = excluded.resource_link_id,
+                updated_at = excluded.updated_at"
+        )
+        .bind(&binding.quiz_id)
+        .bind(&binding.user_id)
+        .bind(binding.platform_id.to_string())
+        .bind(&binding.lti_user_id)
+        .bind(&binding.outcome_url)
+        .bind(&binding.source_id)
+        .bind(&binding.lineitem_url)
+        .bind(&binding.lineitems_url)
+        .bind(&binding.resource_link_id)
+        .bind(binding.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Get the binding for a quiz and user, if the quiz was launched via LTI
+    pub async fn get(&self, quiz_id: &str, user_id: &str) -> Result<Option<OutcomeBinding>, LtiError> {
+        let row = sqlx::query(
+            "SELECT platform_id, lti_user_id, outcome_url, source_id, lineitem_url, lineitems_url, resource_link_id, updated_at
+             FROM lti_outcome_bindings WHERE quiz_id = ? AND user_id = ?"
+        )
+        .bind(quiz_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let platform_id: String = row.get("platform_id");
+        let updated_at: String = row.get("updated_at");
+
+        Ok(Some(OutcomeBinding {
+            quiz_id: quiz_id.to_string(),
+            user_id: user_id.to_string(),
+            platform_id: Uuid::parse_str(&platform_id)
+                .map_err(|e| LtiError::Storage(e.to_string()))?,
+            lti_user_id: row.get("lti_user_id"),
+            outcome_url: row.get("outcome_url"),
+            source_id: row.get("source_id"),
+            lineitem_url: row.get("lineitem_url"),
+            lineitems_url: row.get("lineitems_url"),
+            resource_link_id: row.get("resource_link_id"),
+            updated_at: DateTime::parse_from_rfc3339(&updated_at)
+                .map_err(|e| LtiError::Storage(e.to_string()))?
+                .with_timezone(&Utc),
+        }))
+    }
+
+    /// Record the line item created for a binding lacking one
+    pub async fn set_lineitem_url(&self, quiz_id: &str, user_id: &str, lineitem_url: &str) -> Result<(), LtiError> {
+        sqlx::query("UPDATE lti_outcome_bindings SET lineitem_url = ? WHERE quiz_id = ? AND user_id = ?")
+            .bind(lineitem_url)
+            .bind(quiz_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Build a Basic Outcomes `replaceResultRequest` envelope
+pub fn replace_result_request(message_id: &str, source_id: &str, score: f32) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<imsx_POXEnvelopeRequest xmlns="http://www.imsglobal.org/services/ltiv1p1/xsd/imsoms_v1p0">
+  <imsx_POXHeader>
+    <imsx_POXRequestHeaderInfo>
+      <imsx_version>V1.0</imsx_version>
+      <imsx_messageIdentifier>{}</imsx_messageIdentifier>
+    </imsx_POXRequestHeaderInfo>
+  </imsx_POXHeader>
+  <imsx_POXBody>
+    <replaceResultRequest>
+      <resultRecord>
+        <sourcedGUID>
+          <sourcedId>{}</sourcedId>
+        </sourcedGUID>
+        <result>
+          <resultScore>
+            <language>en</language>
+            <textString>{}</textString>
+          </resultScore>
+        </result>
+      </resultRecord>
+    </replaceResultRequest>
+  </imsx_POXBody>
+</imsx_POXEnvelopeRequest>"#,
+        xml_escape(message_id),
+        xml_escape(source_id),
+        score.clamp(0.0, 1.0)
+    )
+}
+
+/// Sign a POX body, returning the `Authorization` header value
+pub fn sign_pox_request(outcome_url: &str, consumer_key: &str, shared_secret: &str, body: &str) -> Result<String, LtiError> {
+    let body_hash = general_purpose::STANDARD.encode(Sha1::digest(body.as_bytes()));
+
+    let mut params = HashMap::new();
+    params.insert("oauth_body_hash".to_string(), body_hash);
+    oauth1::sign_request(SignatureMethod::HmacSha1, "POST", outcome_url, consumer_key, shared_secret, &mut params)?;
+
+    Ok(oauth1::authorization_header(&params))
+}
+
+/// Check the `imsx_codeMajor` of a Basic Outcomes response
+pub fn check_pox_response(body: &str) -> Result<(), LtiError> {
+    let code_major = extract_element(body, "imsx_codeMajor")
+        .ok_or_else(|| LtiError::Service("Outcome response has no imsx_codeMajor".to_string()))?;
+
+    if code_major.trim() == "success" {
+        Ok(())
+    } else {
+        let description = extract_element(body, "imsx_description").unwrap_or_default();
+        Err(LtiError::Service(format!("Outcome rejected ({}): {}", code_major.trim(), description.trim())))
+    }
+}
+
+fn extract_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(&xml[start..end])
+}
+
+fn xml_escape(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
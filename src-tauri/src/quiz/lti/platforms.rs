@@ -0,0 +1,74 @@
+// Registered LTI platforms
+//
+// Platforms are registered once by an administrator and must survive
+// restarts: launches, grade passback and roster imports all look the
+// platform up by ID. Each configuration is stored as JSON.
+
+use chrono::Utc;
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use super::{LtiError, LtiPlatformConfig};
+
+/// SQLite store of platform configurations
+#[derive(Debug)]
+pub struct PlatformStore {
+    pool: SqlitePool,
+}
+
+impl PlatformStore {
+    /// Create a store, ensuring the LTI tables exist
+    pub async fn new(pool: SqlitePool) -> Result<Self, LtiError> {
+        sqlx::query(include_str!("../../sql/quiz_lti_schema.sql"))
+            .execute(&pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(Self { pool })
+    }
+
+    /// Insert or replace a platform configuration
+    pub async fn save(&self, platform: &LtiPlatformConfig) -> Result<(), LtiError> {
+        let config = serde_json::to_string(platform)
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        sqlx::query(
+            "INSERT INTO lti_platforms (id, config, updated_at) VALUES (?, ?, ?)
+             ON CONFLICT (id) DO UPDATE SET config = excluded.config, updated_at = excluded.updated_at"
+        )
+        .bind(platform.id.to_string())
+        .bind(config)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// List every registered platform
+    pub async fn list(&self) -> Result<Vec<LtiPlatformConfig>, LtiError> {
+        let rows = sqlx::query("SELECT config FROM lti_platforms ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                let config: String = row.get("config");
+                serde_json::from_str(&config).map_err(|e| LtiError::Storage(e.to_string()))
+            })
+            .collect()
+    }
+
+    /// Remove a platform, returning whether it was registered
+    pub async fn remove(&self, id: &Uuid) -> Result<bool, LtiError> {
+        let result = sqlx::query("DELETE FROM lti_platforms WHERE id = ?")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
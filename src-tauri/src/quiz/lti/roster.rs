@@ -0,0 +1,127 @@
+// Course rosters imported through Names and Role Provisioning Services
+//
+// An import replaces the roster of one platform context: members present in
+// the NRPS response are upserted, members missing from it are kept but marked
+// `Deleted` so that their attempts still resolve to a name.
+
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use super::advantage::Member;
+use super::LtiError;
+
+/// NRPS status of members no longer in the platform's roster
+pub const STATUS_DELETED: &str = "Deleted";
+
+/// A roster member as stored locally
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RosterMember {
+    pub lti_user_id: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub roles: Vec<String>,
+    pub status: Option<String>,
+}
+
+/// SQLite store of imported rosters
+#[derive(Debug)]
+pub struct RosterStore {
+    pool: SqlitePool,
+}
+
+impl RosterStore {
+    /// Create a store, ensuring the LTI tables exist
+    pub async fn new(pool: SqlitePool) -> Result<Self, LtiError> {
+        sqlx::query(include_str!("../../sql/quiz_lti_schema.sql"))
+            .execute(&pool)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(Self { pool })
+    }
+
+    /// Replace the roster of a context, returning the number of members imported
+    pub async fn import(&self, platform_id: &Uuid, context_id: &str, members: &[Member]) -> Result<usize, LtiError> {
+        let imported_at = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin()
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        for member in members {
+            let name = member.name.clone().or_else(|| {
+                match (&member.given_name, &member.family_name) {
+                    (Some(given), Some(family)) => Some(format!("{} {}", given, family)),
+                    (Some(given), None) => Some(given.clone()),
+                    (None, Some(family)) => Some(family.clone()),
+                    (None, None) => None,
+                }
+            });
+
+            sqlx::query(
+                "INSERT INTO lti_roster_members (platform_id, context_id, lti_user_id, name, email, roles, status, imported_at)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                 ON CONFLICT (platform_id, context_id, lti_user_id) DO UPDATE SET
+                    name = excluded.name,
+                    email = excluded.email,
+                    roles = excluded.roles,
+                    status = excluded.status,
+                    imported_at = excluded.imported_at"
+            )
+            .bind(platform_id.to_string())
+            .bind(context_id)
+            .bind(&member.user_id)
+            .bind(name)
+            .bind(&member.email)
+            .bind(member.roles.join(","))
+            .bind(member.status.as_deref().unwrap_or("Active"))
+            .bind(&imported_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+        }
+
+        sqlx::query(
+            "UPDATE lti_roster_members SET status = ?
+             WHERE platform_id = ? AND context_id = ? AND imported_at <> ?"
+        )
+        .bind(STATUS_DELETED)
+        .bind(platform_id.to_string())
+        .bind(context_id)
+        .bind(&imported_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(members.len())
+    }
+
+    /// List the stored roster of a context
+    pub async fn list(&self, platform_id: &Uuid, context_id: &str) -> Result<Vec<RosterMember>, LtiError> {
+        let rows = sqlx::query(
+            "SELECT lti_user_id, name, email, roles, status FROM lti_roster_members
+             WHERE platform_id = ? AND context_id = ? ORDER BY lti_user_id"
+        )
+        .bind(platform_id.to_string())
+        .bind(context_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| LtiError::Storage(e.to_string()))?;
+
+        Ok(rows.into_iter().map(|row| {
+            let roles: String = row.get("roles");
+            RosterMember {
+                lti_user_id: row.get("lti_user_id"),
+                name: row.get("name"),
+                email: row.get("email"),
+                roles: roles.split(',').filter(|role| !role.is_empty()).map(|role| role.to_string()).collect(),
+                status: row.get("status"),
+            }
+        }).collect())
+    }
+}
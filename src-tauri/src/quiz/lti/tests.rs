@@ -0,0 +1,711 @@
+use super::*;
+use super::oauth1::{self, SignatureMethod};
+
+const LAUNCH_URL: &str = "https://quiz.example.com/lti/launch";
+const CONSUMER_KEY: &str = "ordo-consumer";
+const SHARED_SECRET: &str = "ordo-secret";
+
+fn lti_1_1_platform() -> LtiPlatformConfig {
+    LtiPlatformConfig {
+        id: Uuid::new_v4(),
+        name: "Test Platform".to_string(),
+        url: "https://lms.example.com".to_string(),
+        version: LtiVersion::V1_1,
+        consumer_key: Some(CONSUMER_KEY.to_string()),
+        shared_secret: Some(SHARED_SECRET.to_string()),
+        issuer: None,
+        client_id: None,
+        deployment_id: None,
+        public_jwk: None,
+        private_jwk: None,
+        auth_endpoint: None,
+        token_endpoint: None,
+        jwks_endpoint: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        custom_parameters: HashMap::new(),
+    }
+}
+
+fn launch_params() -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    params.insert("lti_message_type".to_string(), "basic-lti-launch-request".to_string());
+    params.insert("lti_version".to_string(), "LTI-1p0".to_string());
+    params.insert("user_id".to_string(), "292832126".to_string());
+    params.insert("roles".to_string(), "Instructor".to_string());
+    params.insert("context_id".to_string(), "456434513".to_string());
+    params.insert("resource_link_id".to_string(), "120988f929-274612".to_string());
+    params.insert("custom_quiz_id".to_string(), "quiz 1 & 2".to_string());
+    params
+}
+
+fn signed_launch(method: SignatureMethod, timestamp: Option<i64>) -> HashMap<String, String> {
+    let mut params = launch_params();
+    if let Some(timestamp) = timestamp {
+        params.insert("oauth_timestamp".to_string(), timestamp.to_string());
+    }
+    oauth1::sign_request(method, "POST", LAUNCH_URL, CONSUMER_KEY, SHARED_SECRET, &mut params).unwrap();
+    params
+}
+
+fn service_with_platform() -> (LtiService, Uuid) {
+    let mut service = LtiService::new();
+    let platform = lti_1_1_platform();
+    let id = platform.id;
+    service.add_platform(platform);
+    (service, id)
+}
+
+// OAuth Core 1.0 Appendix A.5, the signing example referenced by the IMS
+// LTI 1.1 implementation guide
+#[test]
+fn test_oauth_core_appendix_a_vector() {
+    let params = vec![
+        ("oauth_consumer_key".to_string(), "dpf43f3p2l4k3l03".to_string()),
+        ("oauth_token".to_string(), "nnch734d00sl2jdk".to_string()),
+        ("oauth_signature_method".to_string(), "HMAC-SHA1".to_string()),
+        ("oauth_timestamp".to_string(), "1191242096".to_string()),
+        ("oauth_nonce".to_string(), "kllo9940pd9333jh".to_string()),
+        ("oauth_version".to_string(), "1.0".to_string()),
+    ];
+
+    let base_string = oauth1::signature_base_string(
+        "GET",
+        "http://photos.example.net/photos?file=vacation.jpg&size=original",
+        &params,
+    ).unwrap();
+
+    assert_eq!(
+        base_string,
+        "GET&http%3A%2F%2Fphotos.example.net%2Fphotos&file%3Dvacation.jpg%26oauth_consumer_key%3Ddpf43f3p2l4k3l03%26oauth_nonce%3Dkllo9940pd9333jh%26oauth_signature_method%3DHMAC-SHA1%26oauth_timestamp%3D1191242096%26oauth_token%3Dnnch734d00sl2jdk%26oauth_version%3D1.0%26size%3Doriginal"
+    );
+
+    let signature = oauth1::sign(SignatureMethod::HmacSha1, &base_string, "kd94hf93k423kf44", "pfkkdhi9sl3r4s00");
+    assert_eq!(signature, "tR3+Ty81lMeYAr/Fid0kMTYa/WM=");
+}
+
+// RFC 5849 section 3.4.1.1 base string example (encoding, duplicate names,
+// default port and query merging)
+#[test]
+fn test_rfc5849_base_string() {
+    let params = vec![
+        ("oauth_consumer_key".to_string(), "9djdj82h48djs9d2".to_string()),
+        ("oauth_token".to_string(), "kkk9d7dh3k39sjv7".to_string()),
+        ("oauth_signature_method".to_string(), "HMAC-SHA1".to_string()),
+        ("oauth_timestamp".to_string(), "137131201".to_string()),
+        ("oauth_nonce".to_string(), "7d8f3e4a".to_string()),
+        ("oauth_signature".to_string(), "bYT5CMsGcbgUdFHObYMEfcx6bsw=".to_string()),
+        ("c2".to_string(), "".to_string()),
+        ("a3".to_string(), "2 q".to_string()),
+    ];
+
+    let base_string = oauth1::signature_base_string(
+        "post",
+        "HTTP://Example.com:80/request?b5=%3D%253D&a3=a&c%40=&a2=r%20b",
+        &params,
+    ).unwrap();
+
+    assert_eq!(
+        base_string,
+        "POST&http%3A%2F%2Fexample.com%2Frequest&a2%3Dr%2520b%26a3%3D2%2520q%26a3%3Da%26b5%3D%253D%25253D%26c%2540%3D%26c2%3D%26oauth_consumer_key%3D9djdj82h48djs9d2%26oauth_nonce%3D7d8f3e4a%26oauth_signature_method%3DHMAC-SHA1%26oauth_timestamp%3D137131201%26oauth_token%3Dkkk9d7dh3k39sjv7"
+    );
+}
+
+#[test]
+fn test_percent_encode() {
+    assert_eq!(oauth1::percent_encode("abcABC123-._~"), "abcABC123-._~");
+    assert_eq!(oauth1::percent_encode("a b&c=d"), "a%20b%26c%3Dd");
+    assert_eq!(oauth1::percent_encode("é"), "%C3%A9");
+}
+
+#[tokio::test]
+async fn test_valid_launch_hmac_sha1() {
+    let (service, platform_id) = service_with_platform();
+    let params = signed_launch(SignatureMethod::HmacSha1, None);
+
+    let launch = service.validate_launch_request(&platform_id, "POST", LAUNCH_URL, &params).await.unwrap();
+
+    assert_eq!(launch.user_id, "292832126");
+    assert_eq!(launch.role, LtiRole::Instructor);
+    assert_eq!(launch.custom_parameters.get("custom_quiz_id").map(String::as_str), Some("quiz 1 & 2"));
+}
+
+#[test]
+fn test_teaching_assistant_is_not_an_instructor() {
+    let role = LtiRole::from_roles("http://purl.imsglobal.org/vocab/lis/v2/membership/Instructor#TeachingAssistant");
+    assert_eq!(role, LtiRole::TeachingAssistant);
+    assert!(role.can_grade());
+    assert!(!role.can_edit_content());
+    assert!(!role.can_manage_attempts());
+
+    assert_eq!(LtiRole::from_roles("urn:lti:role:ims/lis/TeachingAssistant,Learner"), LtiRole::TeachingAssistant);
+}
+
+#[test]
+fn test_most_privileged_role_wins() {
+    let roles = "http://purl.imsglobal.org/vocab/lis/v2/membership#Learner,http://purl.imsglobal.org/vocab/lis/v2/membership#Instructor";
+    assert_eq!(LtiRole::from_roles(roles), LtiRole::Instructor);
+    assert_eq!(LtiRole::from_roles("Learner"), LtiRole::Learner);
+    assert_eq!(LtiRole::from_roles("Mentor"), LtiRole::Other("Mentor".to_string()));
+}
+
+#[tokio::test]
+async fn test_valid_launch_hmac_sha256() {
+    let (service, platform_id) = service_with_platform();
+    let params = signed_launch(SignatureMethod::HmacSha256, None);
+
+    assert!(service.validate_launch_request(&platform_id, "POST", LAUNCH_URL, &params).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_forged_launch_is_rejected() {
+    let (service, platform_id) = service_with_platform();
+    let mut params = signed_launch(SignatureMethod::HmacSha1, None);
+    params.insert("roles".to_string(), "Administrator".to_string());
+
+    let err = service.validate_launch_request(&platform_id, "POST", LAUNCH_URL, &params).await.unwrap_err();
+    assert!(matches!(err, LtiError::InvalidSignature));
+}
+
+#[tokio::test]
+async fn test_wrong_launch_url_is_rejected() {
+    let (service, platform_id) = service_with_platform();
+    let params = signed_launch(SignatureMethod::HmacSha1, None);
+
+    let err = service.validate_launch_request(&platform_id, "POST", "https://evil.example.com/lti/launch", &params).await.unwrap_err();
+    assert!(matches!(err, LtiError::InvalidSignature));
+}
+
+#[tokio::test]
+async fn test_replayed_nonce_is_rejected() {
+    let (service, platform_id) = service_with_platform();
+    let params = signed_launch(SignatureMethod::HmacSha1, None);
+
+    service.validate_launch_request(&platform_id, "POST", LAUNCH_URL, &params).await.unwrap();
+    let err = service.validate_launch_request(&platform_id, "POST", LAUNCH_URL, &params).await.unwrap_err();
+    assert!(matches!(err, LtiError::NonceReplayed));
+}
+
+#[tokio::test]
+async fn test_expired_timestamp_is_rejected() {
+    let (service, platform_id) = service_with_platform();
+    let stale = Utc::now().timestamp() - DEFAULT_TIMESTAMP_WINDOW_SECS - 60;
+    let params = signed_launch(SignatureMethod::HmacSha1, Some(stale));
+
+    let err = service.validate_launch_request(&platform_id, "POST", LAUNCH_URL, &params).await.unwrap_err();
+    assert!(matches!(err, LtiError::ExpiredTimestamp { .. }));
+}
+
+#[tokio::test]
+async fn test_unsupported_signature_method_is_rejected() {
+    let (service, platform_id) = service_with_platform();
+    let mut params = signed_launch(SignatureMethod::HmacSha1, None);
+    params.insert("oauth_signature_method".to_string(), "PLAINTEXT".to_string());
+
+    let err = service.validate_launch_request(&platform_id, "POST", LAUNCH_URL, &params).await.unwrap_err();
+    assert!(matches!(err, LtiError::UnsupportedSignatureMethod(_)));
+}
+
+mod lti_1_3 {
+    use super::*;
+    use super::super::jwks::ToolKeyPair;
+    use super::super::lti13::{claims, OidcLoginRequest};
+    use jsonwebtoken::{encode, Header};
+    use jsonwebtoken::jwk::JwkSet;
+    use once_cell::sync::Lazy;
+    use serde_json::json;
+
+    const ISSUER: &str = "https://canvas.instructure.com";
+    const CLIENT_ID: &str = "10000000000001";
+    const DEPLOYMENT_ID: &str = "1:deployment";
+    const JWKS_URL: &str = "https://canvas.example.edu/api/lti/security/jwks";
+    const REDIRECT_URI: &str = "https://quiz.example.com/lti/launch";
+
+    // RSA key generation is slow in debug builds; share one platform key
+    static PLATFORM_KEY: Lazy<ToolKeyPair> = Lazy::new(|| ToolKeyPair::generate().unwrap());
+
+    fn lti_1_3_platform() -> LtiPlatformConfig {
+        LtiPlatformConfig {
+            version: LtiVersion::V1_3,
+            consumer_key: None,
+            shared_secret: None,
+            issuer: Some(ISSUER.to_string()),
+            client_id: Some(CLIENT_ID.to_string()),
+            deployment_id: Some(DEPLOYMENT_ID.to_string()),
+            auth_endpoint: Some("https://canvas.example.edu/api/lti/authorize_redirect".to_string()),
+            token_endpoint: Some("https://canvas.example.edu/login/oauth2/token".to_string()),
+            jwks_endpoint: Some(JWKS_URL.to_string()),
+            ..lti_1_1_platform()
+        }
+    }
+
+    async fn service() -> LtiService {
+        let mut service = LtiService::new();
+        service.add_platform(lti_1_3_platform());
+        service.set_tool_config(PLATFORM_KEY.clone(), REDIRECT_URI);
+
+        let jwks: JwkSet = serde_json::from_value(PLATFORM_KEY.jwks()).unwrap();
+        service.jwks_cache().insert(JWKS_URL, jwks).await;
+        service
+    }
+
+    /// Run the OIDC login and return (state, nonce) from the redirect URL
+    async fn login(service: &LtiService) -> (String, String) {
+        let redirect = service.handle_oidc_login(&OidcLoginRequest {
+            iss: ISSUER.to_string(),
+            login_hint: "user-hint".to_string(),
+            target_link_uri: REDIRECT_URI.to_string(),
+            lti_message_hint: Some("message-hint".to_string()),
+            client_id: Some(CLIENT_ID.to_string()),
+            lti_deployment_id: Some(DEPLOYMENT_ID.to_string()),
+        }).await.unwrap();
+
+        let url = Url::parse(&redirect).unwrap();
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(query.get("response_mode").map(String::as_str), Some("form_post"));
+        assert_eq!(query.get("redirect_uri").map(String::as_str), Some(REDIRECT_URI));
+        assert_eq!(query.get("lti_message_hint").map(String::as_str), Some("message-hint"));
+
+        (query["state"].clone(), query["nonce"].clone())
+    }
+
+    fn id_token(nonce: &str, aud: &str) -> String {
+        let now = Utc::now().timestamp();
+        let claims = json!({
+            "iss": ISSUER,
+            "sub": "a6d5c443-1f51-4783-ba1a-7686ffe3b54a",
+            "aud": aud,
+            "exp": now + 300,
+            "iat": now,
+            "nonce": nonce,
+            "name": "Ms Jane Marie Doe",
+            (claims::MESSAGE_TYPE): "LtiResourceLinkRequest",
+            (claims::VERSION): "1.3.0",
+            (claims::DEPLOYMENT_ID): DEPLOYMENT_ID,
+            (claims::TARGET_LINK_URI): REDIRECT_URI,
+            (claims::RESOURCE_LINK): { "id": "200d101f-2c14-434a-a0f3-57c2a42369fd", "title": "Unit 3 Quiz" },
+            (claims::ROLES): ["http://purl.imsglobal.org/vocab/lis/v2/membership#Learner"],
+            (claims::CONTEXT): { "id": "c1d887f0-a1a3-4bca-ae25-c375edcc131a", "title": "Physics 101" },
+            (claims::CUSTOM): { "quiz_id": "42" },
+        });
+
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(PLATFORM_KEY.kid.clone());
+        encode(&header, &claims, &PLATFORM_KEY.encoding_key().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_lti_1_3_launch() {
+        let service = service().await;
+        let (state, nonce) = login(&service).await;
+
+        let launch = service.validate_lti_1_3_launch(&id_token(&nonce, CLIENT_ID), &state).await.unwrap();
+
+        assert_eq!(launch.user_id, "a6d5c443-1f51-4783-ba1a-7686ffe3b54a");
+        assert_eq!(launch.role, LtiRole::Learner);
+        assert_eq!(launch.context_title.as_deref(), Some("Physics 101"));
+        assert_eq!(launch.resource_link_title.as_deref(), Some("Unit 3 Quiz"));
+        assert_eq!(launch.custom_parameters.get("custom_quiz_id").map(String::as_str), Some("42"));
+    }
+
+    #[tokio::test]
+    async fn test_lti_1_3_state_is_single_use() {
+        let service = service().await;
+        let (state, nonce) = login(&service).await;
+        let token = id_token(&nonce, CLIENT_ID);
+
+        service.validate_lti_1_3_launch(&token, &state).await.unwrap();
+        let err = service.validate_lti_1_3_launch(&token, &state).await.unwrap_err();
+        assert!(matches!(err, LtiError::InvalidState));
+    }
+
+    #[tokio::test]
+    async fn test_lti_1_3_nonce_mismatch_is_rejected() {
+        let service = service().await;
+        let (state, _) = login(&service).await;
+
+        let err = service.validate_lti_1_3_launch(&id_token("other-nonce", CLIENT_ID), &state).await.unwrap_err();
+        assert!(matches!(err, LtiError::InvalidIdToken(_)));
+    }
+
+    #[tokio::test]
+    async fn test_lti_1_3_wrong_audience_is_rejected() {
+        let service = service().await;
+        let (state, nonce) = login(&service).await;
+
+        let err = service.validate_lti_1_3_launch(&id_token(&nonce, "another-tool"), &state).await.unwrap_err();
+        assert!(matches!(err, LtiError::InvalidIdToken(_)));
+    }
+
+    #[test]
+    fn test_tool_jwks_publishes_public_key_only() {
+        let jwks = PLATFORM_KEY.jwks();
+        let key = &jwks["keys"][0];
+
+        assert_eq!(key["kty"], "RSA");
+        assert_eq!(key["alg"], "RS256");
+        assert_eq!(key["kid"], PLATFORM_KEY.kid.as_str());
+        assert!(key.get("d").is_none());
+    }
+}
+
+// AGS, NRPS and Basic Outcomes against a mock platform on localhost
+mod advantage_services {
+    use super::*;
+    use super::super::advantage::{self, scopes};
+    use super::super::jwks::ToolKeyPair;
+    use super::super::outcomes::{self, OutcomeBinding};
+    use axum::extract::{Form, Path, Query, State};
+    use axum::http::{header, HeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use base64::{engine::general_purpose, Engine as _};
+    use once_cell::sync::Lazy;
+    use serde_json::{json, Value};
+    use sha1::{Digest, Sha1};
+    use std::sync::Mutex as StdMutex;
+
+    const ACCESS_TOKEN: &str = "mock-access-token";
+    const QUIZ_ID: &str = "quiz-42";
+    const RESOURCE_LINK_ID: &str = "200d101f-2c14-434a-a0f3-57c2a42369fd";
+
+    static TOOL_KEY: Lazy<ToolKeyPair> = Lazy::new(|| ToolKeyPair::generate().unwrap());
+
+    #[derive(Default)]
+    struct Recorded {
+        token_scopes: Vec<String>,
+        line_items: Vec<Value>,
+        scores: Vec<(String, Value)>,
+        outcomes: Vec<String>,
+    }
+
+    struct MockPlatform {
+        base_url: String,
+        recorded: StdMutex<Recorded>,
+    }
+
+    type Mock = Arc<MockPlatform>;
+
+    fn authorized(headers: &HeaderMap) -> bool {
+        headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok())
+            == Some(format!("Bearer {}", ACCESS_TOKEN).as_str())
+    }
+
+    async fn token(State(mock): State<Mock>, Form(form): Form<HashMap<String, String>>) -> impl IntoResponse {
+        if form.get("grant_type").map(String::as_str) != Some("client_credentials") || !form.contains_key("client_assertion") {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_request" })));
+        }
+        mock.recorded.lock().unwrap().token_scopes.push(form["scope"].clone());
+        (StatusCode::OK, Json(json!({ "access_token": ACCESS_TOKEN, "token_type": "Bearer", "expires_in": 3600 })))
+    }
+
+    async fn list_line_items(State(mock): State<Mock>, headers: HeaderMap, Query(query): Query<HashMap<String, String>>) -> impl IntoResponse {
+        if !authorized(&headers) {
+            return (StatusCode::UNAUTHORIZED, Json(json!([])));
+        }
+        let recorded = mock.recorded.lock().unwrap();
+        let items: Vec<Value> = recorded.line_items.iter()
+            .filter(|item| query.get("tag").map_or(true, |tag| item["tag"] == tag.as_str()))
+            .cloned()
+            .collect();
+        (StatusCode::OK, Json(Value::Array(items)))
+    }
+
+    async fn create_line_item(State(mock): State<Mock>, headers: HeaderMap, Json(mut item): Json<Value>) -> impl IntoResponse {
+        if !authorized(&headers) {
+            return (StatusCode::UNAUTHORIZED, Json(json!({})));
+        }
+        let mut recorded = mock.recorded.lock().unwrap();
+        item["id"] = json!(format!("{}/lineitems/{}", mock.base_url, recorded.line_items.len() + 1));
+        recorded.line_items.push(item.clone());
+        (StatusCode::CREATED, Json(item))
+    }
+
+    async fn post_score(State(mock): State<Mock>, headers: HeaderMap, Path(id): Path<String>, Json(score): Json<Value>) -> StatusCode {
+        if !authorized(&headers) {
+            return StatusCode::UNAUTHORIZED;
+        }
+        mock.recorded.lock().unwrap().scores.push((id, score));
+        StatusCode::NO_CONTENT
+    }
+
+    async fn memberships(State(mock): State<Mock>, headers: HeaderMap, Query(query): Query<HashMap<String, String>>) -> impl IntoResponse {
+        if !authorized(&headers) {
+            return (StatusCode::UNAUTHORIZED, HeaderMap::new(), Json(json!({})));
+        }
+
+        let mut response_headers = HeaderMap::new();
+        let members = if query.get("page").map(String::as_str) == Some("2") {
+            json!([{ "user_id": "learner-3", "roles": ["http://purl.imsglobal.org/vocab/lis/v2/membership#Learner"], "status": "Inactive" }])
+        } else {
+            let next = format!("<{}/memberships?page=2>; rel=\"next\"", mock.base_url);
+            response_headers.insert(header::LINK, next.parse().unwrap());
+            json!([
+                { "user_id": "instructor-1", "roles": ["http://purl.imsglobal.org/vocab/lis/v2/membership#Instructor"], "name": "Jane Doe" },
+                { "user_id": "learner-2", "roles": ["http://purl.imsglobal.org/vocab/lis/v2/membership#Learner"], "given_name": "John" },
+            ])
+        };
+
+        (StatusCode::OK, response_headers, Json(json!({ "id": "membership", "context": { "id": "ctx-1" }, "members": members })))
+    }
+
+    async fn pox_outcome(State(mock): State<Mock>, headers: HeaderMap, body: String) -> (StatusCode, String) {
+        let authorization = headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok()).unwrap_or_default();
+        let params: Vec<(String, String)> = authorization.trim_start_matches("OAuth ")
+            .split(", ")
+            .filter_map(|field| field.split_once('='))
+            .map(|(key, value)| {
+                let value = value.trim_matches('"');
+                let decoded = url::form_urlencoded::parse(format!("v={}", value).as_bytes())
+                    .next()
+                    .map(|(_, v)| v.into_owned())
+                    .unwrap_or_default();
+                (key.to_string(), decoded)
+            })
+            .collect();
+        let param = |name: &str| params.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone()).unwrap_or_default();
+
+        let url = format!("{}/outcomes", mock.base_url);
+        let signed: Vec<(String, String)> = params.iter().filter(|(k, _)| k != oauth1::OAUTH_SIGNATURE).cloned().collect();
+        let base_string = oauth1::signature_base_string("POST", &url, &signed).unwrap();
+        let body_hash = general_purpose::STANDARD.encode(Sha1::digest(body.as_bytes()));
+
+        let code_major = if param("oauth_body_hash") == body_hash
+            && oauth1::verify(SignatureMethod::HmacSha1, &base_string, SHARED_SECRET, "", &param(oauth1::OAUTH_SIGNATURE))
+        {
+            mock.recorded.lock().unwrap().outcomes.push(body);
+            "success"
+        } else {
+            "failure"
+        };
+
+        (StatusCode::OK, format!(
+            "<imsx_POXEnvelopeResponse><imsx_POXHeader><imsx_POXResponseHeaderInfo><imsx_statusInfo>\
+             <imsx_codeMajor>{}</imsx_codeMajor><imsx_description>checked</imsx_description>\
+             </imsx_statusInfo></imsx_POXResponseHeaderInfo></imsx_POXHeader></imsx_POXEnvelopeResponse>",
+            code_major
+        ))
+    }
+
+    async fn start_mock_platform() -> Mock {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mock = Arc::new(MockPlatform {
+            base_url: format!("http://{}", listener.local_addr().unwrap()),
+            recorded: StdMutex::new(Recorded::default()),
+        });
+
+        let app = Router::new()
+            .route("/token", post(token))
+            .route("/lineitems", get(list_line_items).post(create_line_item))
+            .route("/lineitems/:id/scores", post(post_score))
+            .route("/memberships", get(memberships))
+            .route("/outcomes", post(pox_outcome))
+            .with_state(mock.clone());
+
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        mock
+    }
+
+    fn service_for(mock: &Mock, version: LtiVersion) -> (LtiService, Uuid) {
+        let platform = LtiPlatformConfig {
+            version,
+            issuer: Some("https://lms.example.com".to_string()),
+            client_id: Some("tool-client".to_string()),
+            token_endpoint: Some(format!("{}/token", mock.base_url)),
+            ..lti_1_1_platform()
+        };
+        let id = platform.id;
+
+        let mut service = LtiService::new();
+        service.add_platform(platform);
+        service.set_tool_config(TOOL_KEY.clone(), "https://quiz.example.com/lti/launch");
+        (service, id)
+    }
+
+    fn binding(platform_id: Uuid) -> OutcomeBinding {
+        OutcomeBinding {
+            quiz_id: QUIZ_ID.to_string(),
+            user_id: "local-user".to_string(),
+            platform_id,
+            lti_user_id: "learner-2".to_string(),
+            outcome_url: None,
+            source_id: None,
+            lineitem_url: None,
+            lineitems_url: None,
+            resource_link_id: RESOURCE_LINK_ID.to_string(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_scores_url() {
+        assert_eq!(advantage::scores_url("https://lms.example.com/lineitems/7").unwrap(), "https://lms.example.com/lineitems/7/scores");
+        assert_eq!(advantage::scores_url("https://lms.example.com/lineitems/7/?type=x").unwrap(), "https://lms.example.com/lineitems/7/scores?type=x");
+    }
+
+    #[test]
+    fn test_check_pox_response() {
+        let ok = "<imsx_statusInfo><imsx_codeMajor>success</imsx_codeMajor></imsx_statusInfo>";
+        let rejected = "<imsx_codeMajor>failure</imsx_codeMajor><imsx_description>Unknown sourcedId</imsx_description>";
+
+        assert!(outcomes::check_pox_response(ok).is_ok());
+        let err = outcomes::check_pox_response(rejected).unwrap_err();
+        assert!(err.to_string().contains("Unknown sourcedId"));
+        assert!(outcomes::check_pox_response("<html></html>").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_publish_quiz_score_creates_line_item() {
+        let mock = start_mock_platform().await;
+        let (service, platform_id) = service_for(&mock, LtiVersion::V1_3);
+        let mut binding = binding(platform_id);
+        binding.lineitems_url = Some(format!("{}/lineitems", mock.base_url));
+
+        let created = service.publish_quiz_score(&binding, "Unit 3 Quiz", 8.0, 10.0).await.unwrap();
+        let lineitem_url = created.expect("a line item should have been created");
+        assert_eq!(lineitem_url, format!("{}/lineitems/1", mock.base_url));
+
+        // The coupled line item is used directly from then on
+        binding.lineitem_url = Some(lineitem_url);
+        assert_eq!(service.publish_quiz_score(&binding, "Unit 3 Quiz", 9.0, 10.0).await.unwrap(), None);
+
+        let recorded = mock.recorded.lock().unwrap();
+        assert_eq!(recorded.line_items.len(), 1);
+        assert_eq!(recorded.line_items[0]["label"], "Unit 3 Quiz");
+        assert_eq!(recorded.line_items[0]["tag"], QUIZ_ID);
+        assert_eq!(recorded.line_items[0]["resourceLinkId"], RESOURCE_LINK_ID);
+
+        assert_eq!(recorded.scores.len(), 2);
+        let (id, score) = &recorded.scores[1];
+        assert_eq!(id, "1");
+        assert_eq!(score["userId"], "learner-2");
+        assert_eq!(score["scoreGiven"], 9.0);
+        assert_eq!(score["scoreMaximum"], 10.0);
+        assert_eq!(score["activityProgress"], "Completed");
+        assert_eq!(score["gradingProgress"], "FullyGraded");
+
+        // One token per scope set; the score token is reused
+        assert_eq!(recorded.token_scopes, vec![scopes::LINE_ITEM_READONLY, scopes::LINE_ITEM, scopes::SCORE]);
+    }
+
+    #[tokio::test]
+    async fn test_publish_quiz_score_reuses_tagged_line_item() {
+        let mock = start_mock_platform().await;
+        mock.recorded.lock().unwrap().line_items.push(json!({
+            "id": format!("{}/lineitems/9", mock.base_url),
+            "scoreMaximum": 100.0,
+            "label": "Unit 3 Quiz",
+            "tag": QUIZ_ID,
+        }));
+        let (service, platform_id) = service_for(&mock, LtiVersion::V1_3);
+        let mut binding = binding(platform_id);
+        binding.lineitems_url = Some(format!("{}/lineitems", mock.base_url));
+
+        // Nothing was created, so there is nothing new to couple
+        let created = service.publish_quiz_score(&binding, "Unit 3 Quiz", 75.0, 100.0).await.unwrap();
+        assert_eq!(created, None);
+
+        let recorded = mock.recorded.lock().unwrap();
+        assert_eq!(recorded.line_items.len(), 1);
+        assert_eq!(recorded.scores.len(), 1);
+        assert_eq!(recorded.scores[0].0, "9");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_roster_follows_next_links() {
+        let mock = start_mock_platform().await;
+        let (service, platform_id) = service_for(&mock, LtiVersion::V1_3);
+
+        let members = service.fetch_roster(&platform_id, &format!("{}/memberships", mock.base_url)).await.unwrap();
+
+        let ids: Vec<&str> = members.iter().map(|member| member.user_id.as_str()).collect();
+        assert_eq!(ids, vec!["instructor-1", "learner-2", "learner-3"]);
+        assert_eq!(members[0].name.as_deref(), Some("Jane Doe"));
+        assert_eq!(members[2].status.as_deref(), Some("Inactive"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_roster_requires_lti_1_3() {
+        let mock = start_mock_platform().await;
+        let (service, platform_id) = service_for(&mock, LtiVersion::V1_1);
+
+        let err = service.fetch_roster(&platform_id, &format!("{}/memberships", mock.base_url)).await.unwrap_err();
+        assert!(matches!(err, LtiError::WrongVersion(_)));
+    }
+
+    #[tokio::test]
+    async fn test_publish_quiz_score_lti_1_1_sends_signed_pox() {
+        let mock = start_mock_platform().await;
+        let (service, platform_id) = service_for(&mock, LtiVersion::V1_1);
+        let mut binding = binding(platform_id);
+        binding.outcome_url = Some(format!("{}/outcomes", mock.base_url));
+        binding.source_id = Some("3124567<&>".to_string());
+
+        let created = service.publish_quiz_score(&binding, "Unit 3 Quiz", 80.0, 100.0).await.unwrap();
+        assert_eq!(created, None);
+
+        let recorded = mock.recorded.lock().unwrap();
+        assert_eq!(recorded.outcomes.len(), 1);
+        assert!(recorded.outcomes[0].contains("<sourcedId>3124567&lt;&amp;&gt;</sourcedId>"));
+        assert!(recorded.outcomes[0].contains("<textString>0.8</textString>"));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_pox_outcome_is_an_error() {
+        let mock = start_mock_platform().await;
+        let (mut service, platform_id) = service_for(&mock, LtiVersion::V1_1);
+        let mut platform = service.get_platform(&platform_id).unwrap().clone();
+        platform.shared_secret = Some("wrong-secret".to_string());
+        service.add_platform(platform);
+
+        let mut binding = binding(platform_id);
+        binding.outcome_url = Some(format!("{}/outcomes", mock.base_url));
+        binding.source_id = Some("3124567".to_string());
+
+        let err = service.publish_quiz_score(&binding, "Unit 3 Quiz", 80.0, 100.0).await.unwrap_err();
+        assert!(matches!(err, LtiError::Service(_)));
+        assert!(mock.recorded.lock().unwrap().outcomes.is_empty());
+    }
+
+    // The service the app runs with is rebuilt from the database: a platform
+    // registered before a restart still receives grades
+    #[tokio::test]
+    async fn test_grade_passback_with_configured_service() {
+        let mock = start_mock_platform().await;
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        let platform = LtiPlatformConfig {
+            version: LtiVersion::V1_3,
+            issuer: Some("https://lms.example.com".to_string()),
+            client_id: Some("tool-client".to_string()),
+            token_endpoint: Some(format!("{}/token", mock.base_url)),
+            ..lti_1_1_platform()
+        };
+        super::super::platforms::PlatformStore::new(pool.clone()).await.unwrap().save(&platform).await.unwrap();
+
+        let mut binding = binding(platform.id);
+        binding.lineitems_url = Some(format!("{}/lineitems", mock.base_url));
+        let bindings = outcomes::OutcomeBindingStore::new(pool.clone()).await.unwrap();
+        bindings.save(&binding).await.unwrap();
+
+        let service = LtiService::configured(&pool, None).await.unwrap();
+        assert!(service.get_platform(&platform.id).is_some());
+        assert!(service.tool_key().is_some());
+
+        let binding = bindings.get(QUIZ_ID, "local-user").await.unwrap().unwrap();
+        let created = service.publish_quiz_score(&binding, "Unit 3 Quiz", 6.0, 10.0).await.unwrap();
+        assert_eq!(created, Some(format!("{}/lineitems/1", mock.base_url)));
+
+        let recorded = mock.recorded.lock().unwrap();
+        assert_eq!(recorded.scores.len(), 1);
+        assert_eq!(recorded.scores[0].1["userId"], "learner-2");
+        assert_eq!(recorded.scores[0].1["scoreGiven"], 6.0);
+    }
+}
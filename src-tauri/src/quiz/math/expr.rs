@@ -0,0 +1,359 @@
+// Expression trees for math answers
+//
+// Differences are sums with a negated term and quotients are products with
+// a reciprocal, so sums, products and powers are all a canonical form has to
+// deal with.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::f64::consts::E;
+use std::fmt;
+
+/// Functions that may be applied to an expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sec,
+    Csc,
+    Cot,
+    Arcsin,
+    Arccos,
+    Arctan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Ln,
+    Log,
+    Abs,
+}
+
+impl Function {
+    /// Function with a name as written in LaTeX or MathML
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" | "tg" => Function::Tan,
+            "sec" => Function::Sec,
+            "csc" | "cosec" => Function::Csc,
+            "cot" | "cotan" => Function::Cot,
+            "arcsin" | "asin" => Function::Arcsin,
+            "arccos" | "acos" => Function::Arccos,
+            "arctan" | "atan" => Function::Arctan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "tanh" => Function::Tanh,
+            "exp" => Function::Exp,
+            "ln" => Function::Ln,
+            "log" => Function::Log,
+            "abs" => Function::Abs,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Tan => "tan",
+            Function::Sec => "sec",
+            Function::Csc => "csc",
+            Function::Cot => "cot",
+            Function::Arcsin => "arcsin",
+            Function::Arccos => "arccos",
+            Function::Arctan => "arctan",
+            Function::Sinh => "sinh",
+            Function::Cosh => "cosh",
+            Function::Tanh => "tanh",
+            Function::Exp => "exp",
+            Function::Ln => "ln",
+            Function::Log => "log",
+            Function::Abs => "abs",
+        }
+    }
+
+    /// The function `sin^{-1}` and friends stand for
+    pub fn inverse(self) -> Option<Self> {
+        match self {
+            Function::Sin => Some(Function::Arcsin),
+            Function::Cos => Some(Function::Arccos),
+            Function::Tan => Some(Function::Arctan),
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, x: f64) -> f64 {
+        match self {
+            Function::Sin => x.sin(),
+            Function::Cos => x.cos(),
+            Function::Tan => x.tan(),
+            Function::Sec => 1.0 / x.cos(),
+            Function::Csc => 1.0 / x.sin(),
+            Function::Cot => 1.0 / x.tan(),
+            Function::Arcsin => x.asin(),
+            Function::Arccos => x.acos(),
+            Function::Arctan => x.atan(),
+            Function::Sinh => x.sinh(),
+            Function::Cosh => x.cosh(),
+            Function::Tanh => x.tanh(),
+            Function::Exp => x.exp(),
+            Function::Ln => x.ln(),
+            Function::Log => x.log10(),
+            Function::Abs => x.abs(),
+        }
+    }
+}
+
+/// A parsed expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Variable(String),
+    Sum(Vec<Expr>),
+    Product(Vec<Expr>),
+    Power(Box<Expr>, Box<Expr>),
+    Function(Function, Box<Expr>),
+}
+
+/// A parsed answer: an expression, or an equation between two
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+    Expression(Expr),
+    Equation(Expr, Expr),
+}
+
+impl Expr {
+    pub fn negate(self) -> Self {
+        Expr::Product(vec![Expr::Number(-1.0), self])
+    }
+
+    pub fn reciprocal(self) -> Self {
+        Expr::Power(Box::new(self), Box::new(Expr::Number(-1.0)))
+    }
+
+    pub fn minus(self, other: Expr) -> Self {
+        Expr::Sum(vec![self, other.negate()])
+    }
+
+    pub fn power(self, exponent: Expr) -> Self {
+        Expr::Power(Box::new(self), Box::new(exponent))
+    }
+
+    pub fn apply(function: Function, argument: Expr) -> Self {
+        Expr::Function(function, Box::new(argument))
+    }
+
+    /// Names of the variables in the expression
+    pub fn variables(&self) -> BTreeSet<String> {
+        fn collect(expr: &Expr, names: &mut BTreeSet<String>) {
+            match expr {
+                Expr::Number(_) => {}
+                Expr::Variable(name) => {
+                    names.insert(name.clone());
+                }
+                Expr::Sum(terms) | Expr::Product(terms) => terms.iter().for_each(|term| collect(term, names)),
+                Expr::Power(base, exponent) => {
+                    collect(base, names);
+                    collect(exponent, names);
+                }
+                Expr::Function(_, argument) => collect(argument, names),
+            }
+        }
+
+        let mut names = BTreeSet::new();
+        collect(self, &mut names);
+        names
+    }
+
+    /// Value of the expression; `e` is Euler's number unless it is given a
+    /// value. Returns `None` when a variable has no value.
+    pub fn evaluate(&self, values: &HashMap<String, f64>) -> Option<f64> {
+        Some(match self {
+            Expr::Number(value) => *value,
+            Expr::Variable(name) => match values.get(name) {
+                Some(value) => *value,
+                None if name == "e" => E,
+                None => return None,
+            },
+            Expr::Sum(terms) => terms.iter().map(|term| term.evaluate(values)).sum::<Option<f64>>()?,
+            Expr::Product(factors) => factors.iter().map(|factor| factor.evaluate(values)).product::<Option<f64>>()?,
+            Expr::Power(base, exponent) => base.evaluate(values)?.powf(exponent.evaluate(values)?),
+            Expr::Function(function, argument) => function.apply(argument.evaluate(values)?),
+        })
+    }
+
+    /// Canonical form of the expression: nested sums and products are
+    /// flattened, like terms and factors are collected, numbers are folded
+    /// and operands are put in a fixed order. Products are not expanded, so
+    /// equal canonical forms mean equivalent expressions but not the other
+    /// way round.
+    pub fn simplify(&self) -> Expr {
+        match self {
+            Expr::Number(_) | Expr::Variable(_) => self.clone(),
+            Expr::Sum(terms) => simplify_sum(terms.iter().map(Expr::simplify).collect()),
+            Expr::Product(factors) => simplify_product(factors.iter().map(Expr::simplify).collect()),
+            Expr::Power(base, exponent) => simplify_power(base.simplify(), exponent.simplify()),
+            Expr::Function(function, argument) => match argument.simplify() {
+                Expr::Number(value) if function.apply(value).is_finite() => Expr::Number(function.apply(value)),
+                argument => Expr::apply(*function, argument),
+            },
+        }
+    }
+}
+
+fn simplify_sum(terms: Vec<Expr>) -> Expr {
+    let mut constant = 0.0;
+    let mut like_terms: BTreeMap<String, (Expr, f64)> = BTreeMap::new();
+
+    for term in flatten(terms, |expr| match expr {
+        Expr::Sum(terms) => Ok(terms),
+        other => Err(other),
+    }) {
+        if let Expr::Number(value) = term {
+            constant += value;
+            continue;
+        }
+        let (coefficient, rest) = split_coefficient(term);
+        like_terms.entry(rest.to_string())
+            .or_insert((rest, 0.0))
+            .1 += coefficient;
+    }
+
+    let mut terms: Vec<Expr> = like_terms.into_values()
+        .filter(|(_, coefficient)| *coefficient != 0.0)
+        .map(|(rest, coefficient)| with_coefficient(coefficient, rest))
+        .collect();
+    if constant != 0.0 || terms.is_empty() {
+        terms.push(Expr::Number(constant));
+    }
+
+    if terms.len() == 1 { terms.pop().unwrap() } else { Expr::Sum(terms) }
+}
+
+fn simplify_product(factors: Vec<Expr>) -> Expr {
+    let mut coefficient = 1.0;
+    let mut bases: BTreeMap<String, (Expr, f64)> = BTreeMap::new();
+
+    for factor in flatten(factors, |expr| match expr {
+        Expr::Product(factors) => Ok(factors),
+        other => Err(other),
+    }) {
+        let (base, exponent) = match factor {
+            Expr::Number(value) => {
+                coefficient *= value;
+                continue;
+            }
+            Expr::Power(base, exponent) => match *exponent {
+                Expr::Number(exponent) => (*base, exponent),
+                exponent => (Expr::Power(base, Box::new(exponent)), 1.0),
+            },
+            other => (other, 1.0),
+        };
+        bases.entry(base.to_string())
+            .or_insert((base, 0.0))
+            .1 += exponent;
+    }
+
+    if coefficient == 0.0 {
+        return Expr::Number(0.0);
+    }
+
+    let factors: Vec<Expr> = bases.into_values()
+        .filter(|(_, exponent)| *exponent != 0.0)
+        .map(|(base, exponent)| simplify_power(base, Expr::Number(exponent)))
+        .collect();
+
+    // Powers of numbers may have folded back into numbers
+    let (numbers, factors): (Vec<Expr>, Vec<Expr>) = factors.into_iter().partition(|factor| matches!(factor, Expr::Number(_)));
+    for number in numbers {
+        if let Expr::Number(value) = number {
+            coefficient *= value;
+        }
+    }
+
+    match factors.len() {
+        0 => Expr::Number(coefficient),
+        1 => with_coefficient(coefficient, factors.into_iter().next().unwrap()),
+        _ => with_coefficient(coefficient, Expr::Product(factors)),
+    }
+}
+
+fn simplify_power(base: Expr, exponent: Expr) -> Expr {
+    match (base, exponent) {
+        (Expr::Number(base), Expr::Number(exponent)) if base.powf(exponent).is_finite() => Expr::Number(base.powf(exponent)),
+        (_, Expr::Number(exponent)) if exponent == 0.0 => Expr::Number(1.0),
+        (base, Expr::Number(exponent)) if exponent == 1.0 => base,
+        (Expr::Number(base), _) if base == 1.0 => Expr::Number(1.0),
+        // (x^a)^n = x^(a n) holds for whole n
+        (Expr::Power(base, inner), Expr::Number(exponent)) if exponent.fract() == 0.0 => match *inner {
+            Expr::Number(inner) => simplify_power(*base, Expr::Number(inner * exponent)),
+            inner => Expr::Power(Box::new(Expr::Power(base, Box::new(inner))), Box::new(Expr::Number(exponent))),
+        },
+        // (x y)^n = x^n y^n holds for whole n
+        (Expr::Product(factors), Expr::Number(exponent)) if exponent.fract() == 0.0 => simplify_product(
+            factors.into_iter().map(|factor| simplify_power(factor, Expr::Number(exponent))).collect(),
+        ),
+        (base, exponent) => base.power(exponent),
+    }
+}
+
+/// Operands of nested sums or products as one list
+fn flatten(operands: Vec<Expr>, split: impl Fn(Expr) -> Result<Vec<Expr>, Expr> + Copy) -> Vec<Expr> {
+    let mut flat = Vec::new();
+    for operand in operands {
+        match split(operand) {
+            Ok(inner) => flat.extend(flatten(inner, split)),
+            Err(operand) => flat.push(operand),
+        }
+    }
+    flat
+}
+
+/// Numeric coefficient of a simplified term and the rest of the term
+fn split_coefficient(term: Expr) -> (f64, Expr) {
+    match term {
+        Expr::Product(mut factors) => match factors.first() {
+            Some(Expr::Number(coefficient)) => {
+                let coefficient = *coefficient;
+                factors.remove(0);
+                let rest = if factors.len() == 1 { factors.pop().unwrap() } else { Expr::Product(factors) };
+                (coefficient, rest)
+            }
+            _ => (1.0, Expr::Product(factors)),
+        },
+        other => (1.0, other),
+    }
+}
+
+fn with_coefficient(coefficient: f64, rest: Expr) -> Expr {
+    if coefficient == 1.0 {
+        return rest;
+    }
+    match rest {
+        Expr::Product(mut factors) => {
+            factors.insert(0, Expr::Number(coefficient));
+            Expr::Product(factors)
+        }
+        rest => Expr::Product(vec![Expr::Number(coefficient), rest]),
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |operands: &[Expr], separator: &str| operands.iter()
+            .map(|operand| operand.to_string())
+            .collect::<Vec<_>>()
+            .join(separator);
+
+        match self {
+            Expr::Number(value) => write!(f, "{}", value),
+            Expr::Variable(name) => write!(f, "{}", name),
+            Expr::Sum(terms) => write!(f, "({})", join(terms, " + ")),
+            Expr::Product(factors) => write!(f, "{}", join(factors, "*")),
+            Expr::Power(base, exponent) => write!(f, "({})^({})", base, exponent),
+            Expr::Function(function, argument) => write!(f, "{}({})", function.name(), argument),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+// MathML reader
+//
+// Presentation markup (`mrow`, `mfrac`, `msup`, ...) and content markup
+// (`apply`, `ci`, `cn`, ...) are both translated into parser tokens, with
+// every sub-expression in parentheses so the tree structure survives.
+// Annotations inside `semantics` are ignored in favour of the first child.
+
+use std::f64::consts::{E, PI};
+
+use xml::reader::{EventReader, XmlEvent};
+
+use super::expr::{Formula, Function};
+use super::parser::{lex_latex, parse_tokens, word_token, Token, TokenKind, MAX_NESTING};
+use super::MathError;
+
+#[derive(Debug, Clone, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    fn text(&self) -> String {
+        self.children.iter()
+            .map(|node| match node {
+                Node::Text(text) => text.clone(),
+                Node::Element(element) => element.text(),
+            })
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+}
+
+/// Parse a MathML document or fragment
+pub fn parse_mathml(source: &str) -> Result<Formula, MathError> {
+    let root = read_tree(source)?;
+    let mut tokens = Vec::new();
+    emit(&root, &mut tokens)?;
+    parse_tokens(tokens, 0)
+}
+
+fn read_tree(source: &str) -> Result<Element, MathError> {
+    let mut stack: Vec<Element> = vec![Element::default()];
+
+    for event in EventReader::from_str(source) {
+        match event.map_err(|e| MathError::MathMl(e.to_string()))? {
+            XmlEvent::StartElement { .. } if stack.len() > MAX_NESTING => {
+                return Err(MathError::MathMl("the document is nested too deeply".to_string()));
+            }
+            XmlEvent::StartElement { name, attributes, .. } => stack.push(Element {
+                name: name.local_name,
+                attributes: attributes.into_iter().map(|a| (a.name.local_name, a.value)).collect(),
+                children: Vec::new(),
+            }),
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().unwrap();
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(Node::Element(element));
+                }
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(current) = stack.last_mut() {
+                    current.children.push(Node::Text(text));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let document = stack.pop().unwrap_or_default();
+    document.elements().next().cloned()
+        .ok_or_else(|| MathError::MathMl("the document has no elements".to_string()))
+}
+
+fn push(tokens: &mut Vec<Token>, kind: TokenKind) {
+    tokens.push(Token::new(kind, 0));
+}
+
+/// Emit an element in parentheses
+fn group(element: &Element, tokens: &mut Vec<Token>) -> Result<(), MathError> {
+    push(tokens, TokenKind::Open('('));
+    emit(element, tokens)?;
+    push(tokens, TokenKind::Close(')'));
+    Ok(())
+}
+
+/// Emit elements joined by an operator, in parentheses
+fn join<'a>(elements: impl IntoIterator<Item = &'a Element>, operator: char, tokens: &mut Vec<Token>) -> Result<(), MathError> {
+    push(tokens, TokenKind::Open('('));
+    for (index, element) in elements.into_iter().enumerate() {
+        if index > 0 {
+            push(tokens, TokenKind::Operator(operator));
+        }
+        group(element, tokens)?;
+    }
+    push(tokens, TokenKind::Close(')'));
+    Ok(())
+}
+
+fn children<'a>(element: &'a Element, count: usize) -> Result<Vec<&'a Element>, MathError> {
+    let children: Vec<&Element> = element.elements().collect();
+    if children.len() == count {
+        Ok(children)
+    } else {
+        Err(MathError::MathMl(format!("<{}> needs {} children, not {}", element.name, count, children.len())))
+    }
+}
+
+fn emit(element: &Element, tokens: &mut Vec<Token>) -> Result<(), MathError> {
+    match element.name.as_str() {
+        "math" | "mrow" | "mstyle" | "mpadded" | "degree" | "logbase" => {
+            for child in element.elements() {
+                emit(child, tokens)?;
+            }
+        }
+        "semantics" => {
+            if let Some(first) = element.elements().next() {
+                emit(first, tokens)?;
+            }
+        }
+        "annotation" | "annotation-xml" | "mspace" | "mphantom" | "none" => {}
+
+        // Presentation markup
+        "mn" | "cn" => emit_number(element, tokens)?,
+        "mi" | "ci" => match element.text().as_str() {
+            "ⅇ" => push(tokens, TokenKind::Constant(E)),
+            text => {
+                if let Some(kind) = word_token(text) {
+                    push(tokens, kind);
+                }
+            }
+        },
+        "mo" => emit_operator(&element.text(), tokens)?,
+        "mtext" => tokens.extend(lex_latex(&element.text())?),
+        "mfrac" => {
+            let parts = children(element, 2)?;
+            push(tokens, TokenKind::Fraction);
+            group(parts[0], tokens)?;
+            group(parts[1], tokens)?;
+        }
+        "msqrt" => {
+            push(tokens, TokenKind::Root);
+            push(tokens, TokenKind::Open('('));
+            for child in element.elements() {
+                emit(child, tokens)?;
+            }
+            push(tokens, TokenKind::Close(')'));
+        }
+        "mroot" => {
+            let parts = children(element, 2)?;
+            push(tokens, TokenKind::Root);
+            push(tokens, TokenKind::Open('['));
+            emit(parts[1], tokens)?;
+            push(tokens, TokenKind::Close(']'));
+            group(parts[0], tokens)?;
+        }
+        "msup" => {
+            let parts = children(element, 2)?;
+            emit_base(parts[0], tokens)?;
+            push(tokens, TokenKind::Operator('^'));
+            group(parts[1], tokens)?;
+        }
+        "msub" => {
+            let parts = children(element, 2)?;
+            emit_base(parts[0], tokens)?;
+            emit_subscript(parts[1], tokens)?;
+        }
+        "msubsup" => {
+            let parts = children(element, 3)?;
+            emit_base(parts[0], tokens)?;
+            emit_subscript(parts[1], tokens)?;
+            push(tokens, TokenKind::Operator('^'));
+            group(parts[2], tokens)?;
+        }
+        "mfenced" => {
+            let open = element.get("open").unwrap_or("(");
+            let close = element.get("close").unwrap_or(")");
+            emit_operator(open, tokens)?;
+            for (index, child) in element.elements().enumerate() {
+                if index > 0 {
+                    push(tokens, TokenKind::Operator(','));
+                }
+                emit(child, tokens)?;
+            }
+            emit_operator(close, tokens)?;
+        }
+
+        // Content markup
+        "apply" => emit_apply(element, tokens)?,
+        "pi" => push(tokens, TokenKind::Constant(PI)),
+        "exponentiale" => push(tokens, TokenKind::Constant(E)),
+
+        other => return Err(MathError::MathMl(format!("unsupported element <{}>", other))),
+    }
+    Ok(())
+}
+
+/// Base of a superscript or subscript; functions such as `sin` stay bare so
+/// `sin²x` reads as a power of the sine
+fn emit_base(base: &Element, tokens: &mut Vec<Token>) -> Result<(), MathError> {
+    match base.name.as_str() {
+        "mi" | "ci" if matches!(word_token(&base.text()), Some(TokenKind::Function(_) | TokenKind::Identifier(_))) => emit(base, tokens),
+        _ => group(base, tokens),
+    }
+}
+
+fn emit_subscript(subscript: &Element, tokens: &mut Vec<Token>) -> Result<(), MathError> {
+    push(tokens, TokenKind::Operator('_'));
+    push(tokens, TokenKind::Open('{'));
+    emit(subscript, tokens)?;
+    push(tokens, TokenKind::Close('}'));
+    Ok(())
+}
+
+fn emit_number(element: &Element, tokens: &mut Vec<Token>) -> Result<(), MathError> {
+    let invalid = || MathError::MathMl(format!("invalid number '{}'", element.text()));
+
+    // `<cn type="rational">1<sep/>3</cn>` and `<cn type="e-notation">`
+    let parts: Vec<String> = element.children.iter()
+        .filter_map(|node| match node {
+            Node::Text(text) if !text.trim().is_empty() => Some(text.trim().to_string()),
+            _ => None,
+        })
+        .collect();
+    let number = |text: &str| text.parse::<f64>().map_err(|_| invalid());
+
+    let value = match (element.get("type"), parts.as_slice()) {
+        (Some("rational"), [numerator, denominator]) => number(numerator)? / number(denominator)?,
+        (Some("e-notation"), [mantissa, exponent]) => number(mantissa)? * 10f64.powf(number(exponent)?),
+        _ => number(&element.text())?,
+    };
+
+    // Negative numbers go in parentheses, so `x^-1` keeps its meaning
+    if value < 0.0 {
+        push(tokens, TokenKind::Open('('));
+        push(tokens, TokenKind::Operator('-'));
+        push(tokens, TokenKind::Number(-value));
+        push(tokens, TokenKind::Close(')'));
+    } else {
+        push(tokens, TokenKind::Number(value));
+    }
+    Ok(())
+}
+
+fn emit_operator(operator: &str, tokens: &mut Vec<Token>) -> Result<(), MathError> {
+    let kind = match operator.trim() {
+        // Function application and empty fences
+        "" | "\u{2061}" => return Ok(()),
+        "+" => TokenKind::Operator('+'),
+        "-" | "−" => TokenKind::Operator('-'),
+        "*" | "×" | "·" | "⋅" | "\u{2062}" => TokenKind::Operator('*'),
+        "/" | "÷" | "∕" => TokenKind::Operator('/'),
+        "=" => TokenKind::Operator('='),
+        "," => TokenKind::Operator(','),
+        "(" | "[" | "{" => TokenKind::Open(operator.trim().chars().next().unwrap()),
+        ")" | "]" | "}" => TokenKind::Close(operator.trim().chars().next().unwrap()),
+        "|" => TokenKind::Bar,
+        "√" => TokenKind::Root,
+        other => return Err(MathError::MathMl(format!("unsupported operator '{}'", other))),
+    };
+    push(tokens, kind);
+    Ok(())
+}
+
+/// `<apply>` with an operator element followed by its operands
+fn emit_apply(element: &Element, tokens: &mut Vec<Token>) -> Result<(), MathError> {
+    let mut elements = element.elements();
+    let operator = elements.next()
+        .ok_or_else(|| MathError::MathMl("<apply> needs an operator".to_string()))?;
+    let operands: Vec<&Element> = elements.collect();
+
+    // Qualifiers such as `<degree>` and `<logbase>`
+    let qualifier = |name: &str| operands.iter().find(|operand| operand.name == name).copied();
+    let arguments: Vec<&Element> = operands.iter()
+        .filter(|operand| !matches!(operand.name.as_str(), "degree" | "logbase"))
+        .copied()
+        .collect();
+
+    let name = operator.name.as_str();
+    match (name, arguments.as_slice()) {
+        ("plus", _) => join(arguments.iter().copied(), '+', tokens)?,
+        ("times", _) => join(arguments.iter().copied(), '*', tokens)?,
+        ("minus", [operand]) => {
+            push(tokens, TokenKind::Open('('));
+            push(tokens, TokenKind::Operator('-'));
+            group(operand, tokens)?;
+            push(tokens, TokenKind::Close(')'));
+        }
+        ("minus", [_, _]) => join(arguments.iter().copied(), '-', tokens)?,
+        ("divide", [_, _]) => join(arguments.iter().copied(), '/', tokens)?,
+        ("eq", [left, right]) => {
+            emit(left, tokens)?;
+            push(tokens, TokenKind::Operator('='));
+            emit(right, tokens)?;
+        }
+        ("power", [base, exponent]) => {
+            push(tokens, TokenKind::Open('('));
+            group(base, tokens)?;
+            push(tokens, TokenKind::Operator('^'));
+            group(exponent, tokens)?;
+            push(tokens, TokenKind::Close(')'));
+        }
+        ("root", [radicand]) => {
+            push(tokens, TokenKind::Root);
+            if let Some(degree) = qualifier("degree") {
+                push(tokens, TokenKind::Open('['));
+                emit(degree, tokens)?;
+                push(tokens, TokenKind::Close(']'));
+            }
+            group(radicand, tokens)?;
+        }
+        ("abs", [operand]) => {
+            push(tokens, TokenKind::Open('('));
+            push(tokens, TokenKind::Bar);
+            emit(operand, tokens)?;
+            push(tokens, TokenKind::Bar);
+            push(tokens, TokenKind::Close(')'));
+        }
+        (_, [operand]) => {
+            let function = Function::from_name(name)
+                .ok_or_else(|| MathError::MathMl(format!("unsupported operator <{}>", name)))?;
+            push(tokens, TokenKind::Open('('));
+            push(tokens, TokenKind::Function(function));
+            if let (Function::Log, Some(base)) = (function, qualifier("logbase")) {
+                push(tokens, TokenKind::Operator('_'));
+                group(base, tokens)?;
+            }
+            group(operand, tokens)?;
+            push(tokens, TokenKind::Close(')'));
+        }
+        _ => {
+            return Err(MathError::MathMl(format!("<{}> with {} operands is not supported", name, arguments.len())));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,385 @@
+// Grading of math answers
+//
+// Answers are LaTeX (or plain text such as `2x^2 + 1`) or MathML, and are
+// compared with the correct answer as follows:
+// - A correct answer that is a number, optionally with a unit, is graded
+//   numerically. The learner's unit is converted to the expected one, and
+//   `tolerance`, `precision` (decimal places) and `significant_figures` of
+//   the question's `MathEquationContent` decide how close the value must be.
+//   Missing units and the wrong number of significant figures cost part of
+//   the credit.
+// - Any other expression or equation is parsed into a tree. Answers whose
+//   canonical forms match are equivalent; otherwise both are evaluated at
+//   random points, drawn from `variables` where it lists a range or values.
+//   Equations are equivalent when one is a multiple of the other.
+// - `MathEquationType::Calculus` accepts answers that differ by a constant,
+//   and `C` is the constant of integration. Statistical and geometric
+//   answers are usually rounded, so they get a relative tolerance of 0.5%
+//   unless the question sets one.
+
+mod expr;
+mod mathml;
+mod parser;
+mod units;
+#[cfg(test)]
+mod tests;
+
+pub use expr::{Expr, Formula, Function};
+pub use mathml::parse_mathml;
+pub use units::{Quantity, Unit};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeSet, HashMap};
+use thiserror::Error;
+
+use super::models::{MathEquationContent, MathEquationType};
+
+/// Share of the credit lost for a missing unit or the wrong number of
+/// significant figures
+const FORMAT_PENALTY: f32 = 0.25;
+
+/// Relative tolerance for answers that should be exact
+const EXACT_TOLERANCE: f64 = 1e-9;
+
+/// Relative tolerance for statistical and geometric answers
+const ROUNDED_TOLERANCE: f64 = 5e-3;
+
+/// Points at which expressions must agree, and the most points tried to
+/// find them
+const SAMPLES: usize = 12;
+const MAX_ATTEMPTS: usize = 100;
+
+/// Range variables are drawn from when the question gives none
+const DEFAULT_RANGE: (f64, f64) = (-10.0, 10.0);
+
+/// Seed of the sampled points, so grading is repeatable
+const SEED: u64 = 0x5eed;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum MathError {
+    #[error("{message} (at {position})")]
+    Parse { position: usize, message: String },
+
+    #[error("Invalid MathML: {0}")]
+    MathMl(String),
+
+    #[error("Unknown unit: {0}")]
+    UnknownUnit(String),
+}
+
+impl MathError {
+    fn parse(position: usize, message: impl Into<String>) -> Self {
+        MathError::Parse { position, message: message.into() }
+    }
+}
+
+/// Result of grading a math answer
+#[derive(Debug, Clone, PartialEq)]
+pub struct MathGrade {
+    /// Fraction of the credit earned, from 0 to 1
+    pub credit: f32,
+    pub checks: Vec<Check>,
+}
+
+/// One aspect of a graded answer: its value, units or significant figures
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    pub feedback: Option<String>,
+}
+
+impl Check {
+    fn passed(name: &'static str) -> Self {
+        Self { name, passed: true, feedback: None }
+    }
+
+    fn failed(name: &'static str, feedback: Option<String>) -> Self {
+        Self { name, passed: false, feedback }
+    }
+}
+
+impl MathGrade {
+    fn wrong(name: &'static str, feedback: impl Into<String>) -> Self {
+        Self { credit: 0.0, checks: vec![Check::failed(name, Some(feedback.into()))] }
+    }
+
+    pub fn is_correct(&self) -> bool {
+        self.credit >= 1.0
+    }
+}
+
+/// Parse LaTeX or plain text
+pub fn parse_latex(source: &str) -> Result<Formula, MathError> {
+    parser::parse_tokens(parser::lex_latex(source)?, source.chars().count())
+}
+
+/// Parse LaTeX or MathML, told apart by a leading `<`
+pub fn parse(source: &str) -> Result<Formula, MathError> {
+    if source.trim_start().starts_with('<') {
+        parse_mathml(source)
+    } else {
+        parse_latex(source)
+    }
+}
+
+/// Grade an answer against the correct answer of a question. Fails only
+/// when the correct answer itself cannot be read.
+pub fn grade(expected: &str, answer: &str, content: Option<&MathEquationContent>) -> Result<MathGrade, MathError> {
+    let rules = Rules::new(content);
+
+    // A number without units may be answered with an expression that has
+    // variables, such as `\sin^2 x + \cos^2 x` for 1
+    if let Some(quantity) = Quantity::parse(expected) {
+        if quantity.unit.is_some() || Quantity::parse(answer).is_some() {
+            return Ok(grade_quantity(&quantity, answer, &rules));
+        }
+    }
+
+    let expected = parse(expected)?;
+    Ok(match parse(answer) {
+        Ok(answer) => grade_formula(&expected, &answer, &rules),
+        Err(error) => MathGrade::wrong("value", format!("The answer could not be read: {}", error)),
+    })
+}
+
+/// How closely an answer must match, from the question's settings
+struct Rules {
+    equation_type: MathEquationType,
+    tolerance: Option<f64>,
+    relative: f64,
+    significant_figures: Option<u32>,
+    ranges: HashMap<String, Vec<f64>>,
+}
+
+impl Rules {
+    fn new(content: Option<&MathEquationContent>) -> Self {
+        let equation_type = content.map_or(MathEquationType::Algebraic, |content| content.equation_type.clone());
+        let relative = match equation_type {
+            MathEquationType::Statistical | MathEquationType::Geometric => ROUNDED_TOLERANCE,
+            _ => EXACT_TOLERANCE,
+        };
+
+        // An answer to `precision` decimal places may be off by half a unit
+        // in the last place
+        let tolerance = content.and_then(|content| {
+            content.tolerance.or_else(|| content.precision.map(|places| 0.5 * 10f64.powi(-(places as i32))))
+        });
+
+        Self {
+            equation_type,
+            tolerance,
+            relative: if tolerance.is_some() { EXACT_TOLERANCE } else { relative },
+            significant_figures: content.and_then(|content| content.significant_figures),
+            ranges: content.and_then(|content| content.variables.clone()).unwrap_or_default(),
+        }
+    }
+
+    fn close(&self, a: f64, b: f64) -> bool {
+        self.close_at_scale(a, b, a.abs().max(b.abs()))
+    }
+
+    fn close_at_scale(&self, a: f64, b: f64, scale: f64) -> bool {
+        (a - b).abs() <= self.tolerance.unwrap_or(0.0) + self.relative * scale
+    }
+}
+
+fn grade_quantity(expected: &Quantity, answer: &str, rules: &Rules) -> MathGrade {
+    // Expressions without variables, such as `\frac{1}{4}`, are numbers too
+    let answer = match Quantity::parse(answer) {
+        Some(answer) => answer,
+        None => match parse(answer) {
+            Ok(Formula::Expression(expr)) => match expr.evaluate(&HashMap::new()) {
+                Some(value) => Quantity { value, mantissa: String::new(), unit: None },
+                None => return MathGrade::wrong("value", "The answer should be a number"),
+            },
+            Ok(Formula::Equation(..)) => return MathGrade::wrong("value", "The answer should be a number"),
+            Err(error) => return MathGrade::wrong("value", format!("The answer could not be read: {}", error)),
+        },
+    };
+
+    let mut checks = Vec::new();
+    let mut penalty = 0.0;
+    let mut value = answer.value;
+
+    if let Some((expected_unit, label)) = &expected.unit {
+        match &answer.unit {
+            Some((unit, given)) if !unit.is_compatible(expected_unit) => {
+                return MathGrade::wrong("units", format!("{} cannot be converted to {}", given, label));
+            }
+            Some((unit, _)) => {
+                value = expected_unit.from_base(unit.to_base(value));
+                checks.push(Check::passed("units"));
+            }
+            None => {
+                checks.push(Check::failed("units", Some(format!("Give the units ({})", label))));
+                penalty += FORMAT_PENALTY;
+            }
+        }
+    }
+
+    // Without a tolerance, a required number of significant figures means
+    // any value that rounds to the right one is right
+    let correct = match (rules.tolerance, rules.significant_figures) {
+        (None, Some(figures)) => rules.close(
+            units::round_to_significant(value, figures),
+            units::round_to_significant(expected.value, figures),
+        ),
+        _ => rules.close(value, expected.value),
+    };
+    if !correct {
+        checks.insert(0, Check::failed("value", None));
+        return MathGrade { credit: 0.0, checks };
+    }
+    checks.insert(0, Check::passed("value"));
+
+    if let Some(figures) = rules.significant_figures.filter(|_| !answer.mantissa.is_empty()) {
+        let (least, most) = units::significant_figures(&answer.mantissa);
+        if (least..=most).contains(&figures) {
+            checks.push(Check::passed("significant_figures"));
+        } else {
+            checks.push(Check::failed(
+                "significant_figures",
+                Some(format!("Give the answer to {} significant figures", figures)),
+            ));
+            penalty += FORMAT_PENALTY;
+        }
+    }
+
+    MathGrade { credit: 1.0 - penalty, checks }
+}
+
+fn grade_formula(expected: &Formula, answer: &Formula, rules: &Rules) -> MathGrade {
+    let equivalent = match (expected, answer) {
+        (Formula::Expression(expected), Formula::Expression(answer)) => equivalent_expressions(expected, answer, rules),
+        (Formula::Equation(left, right), Formula::Equation(answer_left, answer_right)) => equivalent_equations(
+            &left.clone().minus(right.clone()),
+            &answer_left.clone().minus(answer_right.clone()),
+            rules,
+        ),
+        (Formula::Equation(..), Formula::Expression(_)) => {
+            return MathGrade::wrong("value", "The answer should be an equation");
+        }
+        (Formula::Expression(_), Formula::Equation(..)) => {
+            return MathGrade::wrong("value", "The answer should be an expression, not an equation");
+        }
+    };
+
+    if equivalent {
+        MathGrade { credit: 1.0, checks: vec![Check::passed("value")] }
+    } else {
+        MathGrade { credit: 0.0, checks: vec![Check::failed("value", None)] }
+    }
+}
+
+fn equivalent_expressions(expected: &Expr, answer: &Expr, rules: &Rules) -> bool {
+    if expected.simplify() == answer.simplify() {
+        return true;
+    }
+
+    // Antiderivatives may differ by a constant
+    let up_to_constant = rules.equation_type == MathEquationType::Calculus
+        && expected.variables().iter().any(|name| name != "C" && name != "e");
+    let mut offset = None;
+    sample(&[expected, answer], rules, |values| {
+        let (expected, answer) = (values[0], values[1]);
+        let scale = expected.abs().max(answer.abs()).max(1.0);
+        if !up_to_constant {
+            return Some(rules.close(expected, answer));
+        }
+
+        let difference = expected - answer;
+        match offset {
+            None => {
+                offset = Some(difference);
+                Some(true)
+            }
+            Some(offset) => Some(rules.close_at_scale(difference, offset, scale)),
+        }
+    })
+}
+
+/// Equations given as `expected = 0` and `answer = 0` are equivalent when
+/// one side is a constant, non-zero multiple of the other
+fn equivalent_equations(expected: &Expr, answer: &Expr, rules: &Rules) -> bool {
+    let (expected_form, answer_form) = (expected.simplify(), answer.simplify());
+    if expected_form == answer_form || expected_form == answer_form.negate().simplify() {
+        return true;
+    }
+
+    let mut ratio = None;
+    sample(&[expected, answer], rules, |values| {
+        let (expected, answer) = (values[0], values[1]);
+        let scale = expected.abs().max(answer.abs());
+        if scale <= EXACT_TOLERANCE {
+            // Both sides hold at this point; says nothing about the ratio
+            return None;
+        }
+        if answer.abs() <= EXACT_TOLERANCE * scale || expected.abs() <= EXACT_TOLERANCE * scale {
+            return Some(false);
+        }
+
+        let current = expected / answer;
+        match ratio {
+            None => {
+                ratio = Some(current);
+                Some(true)
+            }
+            Some(ratio) => Some(rules.close_at_scale(current, ratio, ratio.abs().max(current.abs()))),
+        }
+    })
+}
+
+/// Evaluate expressions at random points and check each set of values.
+/// Points where every expression is undefined are skipped, as are points
+/// `check` returns `None` for; an expression that is undefined where
+/// another is defined fails. Returns whether enough points were checked and
+/// all passed.
+fn sample(exprs: &[&Expr], rules: &Rules, mut check: impl FnMut(&[f64]) -> Option<bool>) -> bool {
+    let variables: BTreeSet<String> = exprs.iter()
+        .flat_map(|expr| expr.variables())
+        .filter(|name| name != "e" || rules.ranges.contains_key(name))
+        .collect();
+    let calculus = rules.equation_type == MathEquationType::Calculus;
+
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut checked = 0;
+    let needed = if variables.is_empty() { 1 } else { SAMPLES };
+
+    for _ in 0..MAX_ATTEMPTS {
+        let point: HashMap<String, f64> = variables.iter()
+            .map(|name| {
+                let value = match rules.ranges.get(name).map(Vec::as_slice) {
+                    _ if calculus && name == "C" => 0.0,
+                    Some([low, high]) if low < high => rng.random_range(*low..*high),
+                    Some(values) if !values.is_empty() => values[rng.random_range(0..values.len())],
+                    _ => rng.random_range(DEFAULT_RANGE.0..DEFAULT_RANGE.1),
+                };
+                (name.clone(), value)
+            })
+            .collect();
+
+        let values: Vec<f64> = exprs.iter()
+            .map(|expr| expr.evaluate(&point).unwrap_or(f64::NAN))
+            .collect();
+        let defined = values.iter().filter(|value| value.is_finite()).count();
+        if defined == 0 {
+            continue;
+        }
+        if defined < values.len() {
+            return false;
+        }
+
+        match check(&values) {
+            Some(true) => checked += 1,
+            Some(false) => return false,
+            None => {}
+        }
+        if checked >= needed {
+            return true;
+        }
+    }
+
+    false
+}
@@ -0,0 +1,557 @@
+// Tokens and parser shared by the LaTeX and MathML readers
+//
+// LaTeX is lexed into tokens directly; MathML elements are translated into
+// the same tokens, so both end up in one recursive descent parser. Letters
+// are single-letter variables as in LaTeX, except for the names of functions
+// and `pi`, so `2xy` is a product and `sinx` is `sin(x)`.
+
+use std::f64::consts::PI;
+
+use super::expr::{Expr, Formula, Function};
+use super::MathError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum TokenKind {
+    Number(f64),
+    Identifier(String),
+    Constant(f64),
+    Function(Function),
+    /// One of `+ - * / ^ _ = ,`
+    Operator(char),
+    /// One of `( [ {`
+    Open(char),
+    /// One of `) ] }`
+    Close(char),
+    Bar,
+    Fraction,
+    Root,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Token {
+    pub kind: TokenKind,
+    /// Character offset in the source
+    pub position: usize,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, position: usize) -> Self {
+        Self { kind, position }
+    }
+}
+
+/// Deepest nesting of parentheses, fractions, roots and signs the parser
+/// follows; answers are short, and deeper input would only exhaust the stack
+pub(super) const MAX_NESTING: usize = 256;
+
+/// Names read as one word when they appear as letters
+const WORDS: &[&str] = &[
+    "arcsin", "arccos", "arctan", "cosec", "cotan", "sinh", "cosh", "tanh", "sqrt", "asin", "acos", "atan",
+    "sin", "cos", "tan", "sec", "csc", "cot", "exp", "log", "abs", "ln", "pi",
+];
+
+/// Greek letters, which are variables
+const GREEK: &[&str] = &[
+    "alpha", "beta", "gamma", "delta", "epsilon", "varepsilon", "zeta", "eta", "theta", "vartheta", "iota",
+    "kappa", "lambda", "mu", "nu", "xi", "rho", "sigma", "tau", "upsilon", "phi", "varphi", "chi", "psi",
+    "omega", "Gamma", "Delta", "Theta", "Lambda", "Xi", "Sigma", "Phi", "Psi", "Omega",
+];
+
+/// LaTeX commands that do not change the meaning of an expression
+const IGNORED_COMMANDS: &[&str] = &[
+    "left", "right", "big", "Big", "bigg", "Bigg", "bigl", "bigr", "Bigl", "Bigr", "displaystyle",
+    "textstyle", "quad", "qquad",
+];
+
+/// Lex LaTeX, or plain text with `*` and `^`, into tokens
+pub(super) fn lex_latex(source: &str) -> Result<Vec<Token>, MathError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let kind = match c {
+            _ if c.is_whitespace() || c == '$' => {
+                i += 1;
+                continue;
+            }
+            '0'..='9' | '.' => {
+                let (value, end) = lex_number(&chars, i)
+                    .ok_or_else(|| MathError::parse(i, format!("unexpected '{}'", c)))?;
+                i = end;
+                tokens.push(Token::new(TokenKind::Number(value), start));
+                continue;
+            }
+            '\\' => {
+                i += 1;
+                match chars.get(i) {
+                    Some(c) if c.is_ascii_alphabetic() => {}
+                    Some('{') => {
+                        i += 1;
+                        tokens.push(Token::new(TokenKind::Open('('), start));
+                        continue;
+                    }
+                    Some('}') => {
+                        i += 1;
+                        tokens.push(Token::new(TokenKind::Close(')'), start));
+                        continue;
+                    }
+                    Some('|') => {
+                        i += 1;
+                        tokens.push(Token::new(TokenKind::Bar, start));
+                        continue;
+                    }
+                    // Spacing such as `\,` and `\ `
+                    Some(_) => {
+                        i += 1;
+                        continue;
+                    }
+                    None => return Err(MathError::parse(start, "incomplete command")),
+                }
+
+                let name: String = chars[i..].iter().take_while(|c| c.is_ascii_alphabetic()).collect();
+                i += name.len();
+                match name.as_str() {
+                    _ if IGNORED_COMMANDS.contains(&name.as_str()) => {
+                        // `\left.` and `\right.` are empty delimiters
+                        if chars.get(i) == Some(&'.') {
+                            i += 1;
+                        }
+                        continue;
+                    }
+                    "cdot" | "times" | "ast" => TokenKind::Operator('*'),
+                    "div" => TokenKind::Operator('/'),
+                    "frac" | "dfrac" | "tfrac" => TokenKind::Fraction,
+                    "sqrt" => TokenKind::Root,
+                    "pi" => TokenKind::Constant(PI),
+                    "lvert" | "rvert" | "vert" | "mid" => TokenKind::Bar,
+                    "mathrm" | "text" | "textrm" | "mathit" | "operatorname" => {
+                        let (word, end) = read_group(&chars, i)
+                            .ok_or_else(|| MathError::parse(start, format!("\\{} needs an argument in braces", name)))?;
+                        i = end;
+                        match word_token(word.trim()) {
+                            Some(kind) => kind,
+                            None => continue,
+                        }
+                    }
+                    _ => match Function::from_name(&name) {
+                        Some(function) => TokenKind::Function(function),
+                        None if GREEK.contains(&name.as_str()) => TokenKind::Identifier(name),
+                        None => return Err(MathError::parse(start, format!("unsupported command \\{}", name))),
+                    },
+                }
+            }
+            _ if c.is_alphabetic() => {
+                let rest: String = chars[i..].iter().take_while(|c| c.is_ascii_alphabetic()).collect();
+                match WORDS.iter().find(|word| rest.starts_with(**word)) {
+                    Some(word) => {
+                        i += word.len();
+                        word_token(word).unwrap()
+                    }
+                    None => {
+                        i += 1;
+                        match c {
+                            'π' => TokenKind::Constant(PI),
+                            _ => TokenKind::Identifier(c.to_string()),
+                        }
+                    }
+                }
+            }
+            '√' => {
+                i += 1;
+                TokenKind::Root
+            }
+            '(' | '[' | '{' => {
+                i += 1;
+                TokenKind::Open(c)
+            }
+            ')' | ']' | '}' => {
+                i += 1;
+                TokenKind::Close(c)
+            }
+            '|' => {
+                i += 1;
+                TokenKind::Bar
+            }
+            '+' | '-' | '*' | '/' | '^' | '_' | '=' | ',' => {
+                i += 1;
+                TokenKind::Operator(c)
+            }
+            '−' => {
+                i += 1;
+                TokenKind::Operator('-')
+            }
+            '×' | '·' | '⋅' => {
+                i += 1;
+                TokenKind::Operator('*')
+            }
+            '÷' => {
+                i += 1;
+                TokenKind::Operator('/')
+            }
+            _ => return Err(MathError::parse(i, format!("unexpected '{}'", c))),
+        };
+        tokens.push(Token::new(kind, start));
+    }
+
+    Ok(tokens)
+}
+
+/// Token for a word written out, as in `\mathrm{sin}` or MathML `<mi>`
+pub(super) fn word_token(word: &str) -> Option<TokenKind> {
+    match word {
+        "" => None,
+        "pi" | "π" => Some(TokenKind::Constant(PI)),
+        "sqrt" => Some(TokenKind::Root),
+        _ => Some(match Function::from_name(word) {
+            Some(function) => TokenKind::Function(function),
+            None => TokenKind::Identifier(word.to_string()),
+        }),
+    }
+}
+
+/// A decimal number with an optional exponent written `e-3`
+fn lex_number(chars: &[char], start: usize) -> Option<(f64, usize)> {
+    let mut end = start;
+    let mut seen_point = false;
+    while let Some(&c) = chars.get(end) {
+        match c {
+            '0'..='9' => {}
+            '.' if !seen_point => seen_point = true,
+            _ => break,
+        }
+        end += 1;
+    }
+
+    // The exponent only counts when digits follow right away, so `2e` is
+    // still two times Euler's number
+    if matches!(chars.get(end), Some('e' | 'E')) {
+        let mut exponent_end = end + 1;
+        if matches!(chars.get(exponent_end), Some('+' | '-')) {
+            exponent_end += 1;
+        }
+        if chars.get(exponent_end).is_some_and(char::is_ascii_digit) {
+            while chars.get(exponent_end).is_some_and(char::is_ascii_digit) {
+                exponent_end += 1;
+            }
+            end = exponent_end;
+        }
+    }
+
+    let literal: String = chars[start..end].iter().collect();
+    literal.parse().ok().map(|value| (value, end))
+}
+
+/// Text of a `{...}` group after optional whitespace, and the index after it
+fn read_group(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut i = start;
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    if chars.get(i) != Some(&'{') {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut text = String::new();
+    for (offset, &c) in chars[i..].iter().enumerate() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return Some((text, i + offset + 1));
+        }
+        if offset > 0 {
+            text.push(c);
+        }
+    }
+    None
+}
+
+/// Parse tokens into a formula; `end` is the position reported for errors
+/// at the end of the input
+pub(super) fn parse_tokens(tokens: Vec<Token>, end: usize) -> Result<Formula, MathError> {
+    let mut parser = Parser { tokens, index: 0, end, bars: 0, depth: 0 };
+    if parser.tokens.is_empty() {
+        return Err(MathError::parse(0, "the answer is empty"));
+    }
+
+    let left = parser.expression()?;
+    let formula = if parser.eat(&TokenKind::Operator('=')) {
+        Formula::Equation(left, parser.expression()?)
+    } else {
+        Formula::Expression(left)
+    };
+
+    match parser.peek() {
+        None => Ok(formula),
+        Some(kind) => Err(parser.error(format!("unexpected {}", describe(kind)))),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+    end: usize,
+    /// Absolute value bars currently open
+    bars: usize,
+    /// Factors and primaries being parsed, each nesting level adding some
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.index).map(|token| &token.kind)
+    }
+
+    fn advance(&mut self) -> Option<TokenKind> {
+        let token = self.tokens.get(self.index)?.kind.clone();
+        self.index += 1;
+        Some(token)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek() == Some(kind) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<(), MathError> {
+        if self.eat(&kind) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {}", describe(&kind))))
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> MathError {
+        let position = self.tokens.get(self.index).map_or(self.end, |token| token.position);
+        MathError::parse(position, message)
+    }
+
+    /// Run `parse` one level deeper, failing past [`MAX_NESTING`]
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, MathError>) -> Result<T, MathError> {
+        if self.depth >= MAX_NESTING {
+            return Err(self.error("the answer is nested too deeply"));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Whether the next token starts an operand, for implied products
+    fn starts_operand(&self) -> bool {
+        match self.peek() {
+            Some(TokenKind::Bar) => self.bars == 0,
+            Some(kind) => matches!(
+                kind,
+                TokenKind::Number(_) | TokenKind::Identifier(_) | TokenKind::Constant(_) | TokenKind::Function(_)
+                    | TokenKind::Open(_) | TokenKind::Fraction | TokenKind::Root
+            ),
+            None => false,
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, MathError> {
+        let mut terms = vec![self.term()?];
+        loop {
+            if self.eat(&TokenKind::Operator('+')) {
+                terms.push(self.term()?);
+            } else if self.eat(&TokenKind::Operator('-')) {
+                terms.push(self.term()?.negate());
+            } else {
+                break;
+            }
+        }
+        Ok(if terms.len() == 1 { terms.pop().unwrap() } else { Expr::Sum(terms) })
+    }
+
+    fn term(&mut self) -> Result<Expr, MathError> {
+        let mut factors = vec![self.factor()?];
+        loop {
+            if self.eat(&TokenKind::Operator('*')) {
+                factors.push(self.factor()?);
+            } else if self.eat(&TokenKind::Operator('/')) {
+                factors.push(self.factor()?.reciprocal());
+            } else if self.starts_operand() {
+                factors.push(self.power()?);
+            } else {
+                break;
+            }
+        }
+        Ok(if factors.len() == 1 { factors.pop().unwrap() } else { Expr::Product(factors) })
+    }
+
+    fn factor(&mut self) -> Result<Expr, MathError> {
+        self.nested(|parser| {
+            if parser.eat(&TokenKind::Operator('-')) {
+                Ok(parser.factor()?.negate())
+            } else if parser.eat(&TokenKind::Operator('+')) {
+                parser.factor()
+            } else {
+                parser.power()
+            }
+        })
+    }
+
+    fn power(&mut self) -> Result<Expr, MathError> {
+        let base = self.primary()?;
+        if self.eat(&TokenKind::Operator('^')) {
+            Ok(base.power(self.factor()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, MathError> {
+        self.nested(Self::operand)
+    }
+
+    fn operand(&mut self) -> Result<Expr, MathError> {
+        let Some(kind) = self.advance() else {
+            return Err(self.error("the answer ends too early"));
+        };
+
+        match kind {
+            TokenKind::Number(value) | TokenKind::Constant(value) => Ok(Expr::Number(value)),
+            TokenKind::Identifier(name) => {
+                if self.eat(&TokenKind::Operator('_')) {
+                    Ok(Expr::Variable(format!("{}_{}", name, self.subscript()?)))
+                } else {
+                    Ok(Expr::Variable(name))
+                }
+            }
+            TokenKind::Open(open) => {
+                let inner = self.expression()?;
+                self.expect(TokenKind::Close(closing(open)))?;
+                Ok(inner)
+            }
+            TokenKind::Bar => {
+                self.bars += 1;
+                let inner = self.expression()?;
+                self.expect(TokenKind::Bar)?;
+                self.bars -= 1;
+                Ok(Expr::apply(Function::Abs, inner))
+            }
+            TokenKind::Fraction => {
+                let numerator = self.primary()?;
+                let denominator = self.primary()?;
+                Ok(Expr::Product(vec![numerator, denominator.reciprocal()]))
+            }
+            TokenKind::Root => {
+                let index = if self.eat(&TokenKind::Open('[')) {
+                    let index = self.expression()?;
+                    self.expect(TokenKind::Close(']'))?;
+                    index
+                } else {
+                    Expr::Number(2.0)
+                };
+                Ok(self.primary()?.power(index.reciprocal()))
+            }
+            TokenKind::Function(function) => self.function(function),
+            other => {
+                self.index -= 1;
+                Err(self.error(format!("unexpected {}", describe(&other))))
+            }
+        }
+    }
+
+    /// `sin x`, `\sin^2(x)`, `\sin^{-1} x` or `\log_2 x`
+    fn function(&mut self, mut function: Function) -> Result<Expr, MathError> {
+        let base = if function == Function::Log && self.eat(&TokenKind::Operator('_')) {
+            Some(self.primary()?)
+        } else {
+            None
+        };
+
+        let mut exponent = None;
+        if self.eat(&TokenKind::Operator('^')) {
+            match (self.factor()?.simplify(), function.inverse()) {
+                (Expr::Number(value), Some(inverse)) if value == -1.0 => function = inverse,
+                (power, _) => exponent = Some(power),
+            }
+        }
+
+        // Without parentheses the argument runs to the next operator or
+        // function, so `\sin 2x` is `sin(2x)`
+        let argument = if matches!(self.peek(), Some(TokenKind::Open(_))) {
+            self.primary()?
+        } else {
+            let mut factors = vec![self.power()?];
+            while self.starts_operand() && !matches!(self.peek(), Some(TokenKind::Function(_))) {
+                factors.push(self.power()?);
+            }
+            if factors.len() == 1 { factors.pop().unwrap() } else { Expr::Product(factors) }
+        };
+
+        let mut value = match base {
+            Some(base) => Expr::Product(vec![
+                Expr::apply(Function::Ln, argument),
+                Expr::apply(Function::Ln, base).reciprocal(),
+            ]),
+            None => Expr::apply(function, argument),
+        };
+        if let Some(exponent) = exponent {
+            value = value.power(exponent);
+        }
+        Ok(value)
+    }
+
+    /// Subscript of a variable, such as the `1` in `x_1` or `{max}` in `v_{max}`
+    fn subscript(&mut self) -> Result<String, MathError> {
+        let simple = |kind: &TokenKind| match kind {
+            TokenKind::Number(value) => Some(value.to_string()),
+            TokenKind::Identifier(name) => Some(name.clone()),
+            _ => None,
+        };
+
+        match self.advance() {
+            Some(TokenKind::Open(open)) => {
+                let mut text = String::new();
+                while let Some(part) = self.peek().and_then(simple) {
+                    text.push_str(&part);
+                    self.index += 1;
+                }
+                self.expect(TokenKind::Close(closing(open)))?;
+                Ok(text)
+            }
+            Some(kind) => match simple(&kind) {
+                Some(text) => Ok(text),
+                None => {
+                    self.index -= 1;
+                    Err(self.error("expected a subscript"))
+                }
+            },
+            None => Err(self.error("expected a subscript")),
+        }
+    }
+}
+
+fn closing(open: char) -> char {
+    match open {
+        '[' => ']',
+        '{' => '}',
+        _ => ')',
+    }
+}
+
+fn describe(kind: &TokenKind) -> String {
+    match kind {
+        TokenKind::Number(value) => format!("number {}", value),
+        TokenKind::Identifier(name) => format!("'{}'", name),
+        TokenKind::Constant(_) => "constant".to_string(),
+        TokenKind::Function(function) => format!("function {}", function.name()),
+        TokenKind::Operator(c) | TokenKind::Open(c) | TokenKind::Close(c) => format!("'{}'", c),
+        TokenKind::Bar => "'|'".to_string(),
+        TokenKind::Fraction => "fraction".to_string(),
+        TokenKind::Root => "root".to_string(),
+    }
+}
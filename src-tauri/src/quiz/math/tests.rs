@@ -0,0 +1,251 @@
+use super::*;
+
+fn content(equation_type: MathEquationType) -> MathEquationContent {
+    MathEquationContent {
+        equation_type,
+        variables: None,
+        precision: None,
+        display_mode: false,
+        tolerance: None,
+        significant_figures: None,
+    }
+}
+
+fn expression(source: &str) -> Expr {
+    match parse(source).unwrap() {
+        Formula::Expression(expr) => expr,
+        other => panic!("expected an expression, got {:?}", other),
+    }
+}
+
+fn value(source: &str) -> f64 {
+    expression(source).evaluate(&HashMap::new()).unwrap()
+}
+
+fn correct(expected: &str, answer: &str) -> bool {
+    grade(expected, answer, None).unwrap().is_correct()
+}
+
+mod parsing {
+    use super::*;
+
+    #[test]
+    fn test_latex() {
+        assert_eq!(value(r"\frac{1}{2} + 3^2"), 9.5);
+        assert_eq!(value(r"2 \cdot \sqrt{16}"), 8.0);
+        assert!((value(r"\sqrt[3]{27}") - 3.0).abs() < 1e-12);
+        assert_eq!(value(r"-2^2"), -4.0);
+        assert_eq!(value(r"2^{-1}"), 0.5);
+        assert_eq!(value(r"\left|-3\right|"), 3.0);
+        assert!((value(r"\log_{2} 8") - 3.0).abs() < 1e-12);
+        assert!((value(r"\sin^2 \frac{\pi}{4}") - 0.5).abs() < 1e-12);
+        assert!((value(r"\sin^{-1}(1)") - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert!((value("6.02e23") - 6.02e23).abs() < 1e9);
+    }
+
+    #[test]
+    fn test_implied_products() {
+        let point: HashMap<String, f64> = [("x".to_string(), 2.0), ("y".to_string(), 3.0)].into_iter().collect();
+        assert_eq!(expression("2xy").evaluate(&point), Some(12.0));
+        assert_eq!(expression("2(x+1)(y-1)").evaluate(&point), Some(12.0));
+        assert_eq!(expression(r"\sin 2x").simplify(), expression(r"\sin(2 x)").simplify());
+        assert_eq!(expression("x_1 + x_{max}").variables().into_iter().collect::<Vec<_>>(), vec!["x_1", "x_max"]);
+    }
+
+    #[test]
+    fn test_equations() {
+        assert!(matches!(parse("y = 2x + 1").unwrap(), Formula::Equation(..)));
+    }
+
+    #[test]
+    fn test_presentation_mathml() {
+        let source = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+            <mrow>
+                <mfrac><mn>1</mn><mn>2</mn></mfrac>
+                <mo>+</mo>
+                <msup><mi>x</mi><mn>2</mn></msup>
+                <mo>-</mo>
+                <msqrt><mn>9</mn></msqrt>
+            </mrow>
+        </math>"#;
+        let point: HashMap<String, f64> = [("x".to_string(), 3.0)].into_iter().collect();
+        assert_eq!(expression(source).evaluate(&point), Some(6.5));
+
+        let sine = "<math><msup><mi>sin</mi><mn>2</mn></msup><mi>x</mi><mo>+</mo><msup><mi>cos</mi><mn>2</mn></msup><mi>x</mi></math>";
+        assert!(correct("1", sine));
+    }
+
+    #[test]
+    fn test_content_mathml() {
+        let source = "<math><apply><plus/><apply><power/><ci>x</ci><cn>2</cn></apply><apply><minus/><cn>1</cn></apply></apply></math>";
+        assert!(correct("x^2 - 1", source));
+
+        let root = "<math><apply><root/><degree><cn>3</cn></degree><cn>8</cn></apply></math>";
+        assert!((value(root) - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(parse_latex("2 + "), Err(MathError::Parse { position: 4, .. })));
+        assert!(matches!(parse_latex(r"\foo x"), Err(MathError::Parse { position: 0, .. })));
+        assert!(matches!(parse_latex("(x + 1"), Err(MathError::Parse { .. })));
+        assert!(matches!(parse("<math><mtable/></math>"), Err(MathError::MathMl(_))));
+
+        let graded = grade("x + 1", "x +", None).unwrap();
+        assert_eq!(graded.credit, 0.0);
+        assert!(graded.checks[0].feedback.as_deref().unwrap().starts_with("The answer could not be read"));
+        assert!(grade("x +", "x", None).is_err());
+    }
+
+    #[test]
+    fn test_deep_nesting_is_an_error() {
+        let parentheses = format!("{}x{}", "(".repeat(10_000), ")".repeat(10_000));
+        assert!(matches!(parse_latex(&parentheses), Err(MathError::Parse { .. })));
+        assert!(matches!(parse_latex(&format!("{}x", "-".repeat(10_000))), Err(MathError::Parse { .. })));
+        assert!(matches!(parse_latex(&r"rac{1}".repeat(10_000)), Err(MathError::Parse { .. })));
+
+        let mathml = format!("<math>{}<ci>x</ci>{}</math>", "<mrow>".repeat(10_000), "</mrow>".repeat(10_000));
+        assert!(matches!(parse(&mathml), Err(MathError::MathMl(_))));
+
+        assert!(parse_latex(&format!("{}x{}", "(".repeat(20), ")".repeat(20))).is_ok());
+    }
+}
+
+mod equivalence {
+    use super::*;
+
+    #[test]
+    fn test_symbolic() {
+        assert_eq!(expression("x + x + 2y").simplify(), expression("2y + 2x").simplify());
+        assert_eq!(expression(r"\frac{x^3}{x}").simplify(), expression("x^2").simplify());
+        assert_eq!(expression("(2x)^2").simplify(), expression("4x^2").simplify());
+        assert_ne!(expression("x + 1").simplify(), expression("x + 2").simplify());
+    }
+
+    #[test]
+    fn test_sampled() {
+        assert!(correct("(x+1)^2", "x^2 + 2x + 1"));
+        assert!(correct(r"\sin^2(x) + \cos^2(x)", "1"));
+        assert!(correct(r"\frac{1}{x} + \frac{1}{y}", r"\frac{x + y}{xy}"));
+        assert!(correct("e^{2x}", r"(e^x)^2"));
+        assert!(!correct("(x+1)^2", "x^2 + 1"));
+        assert!(!correct("x", r"\sqrt{x^2}"));
+    }
+
+    #[test]
+    fn test_equations() {
+        assert!(correct("y = 2x + 1", "2x - y + 1 = 0"));
+        assert!(correct("y = 2x + 1", "2y = 4x + 2"));
+        assert!(!correct("y = 2x + 1", "y = 2x - 1"));
+
+        let graded = grade("y = 2x + 1", "2x + 1", None).unwrap();
+        assert_eq!(graded.checks[0].feedback.as_deref(), Some("The answer should be an equation"));
+    }
+
+    #[test]
+    fn test_variable_ranges() {
+        // sqrt(x^2) is x for positive x only
+        let mut positive = content(MathEquationType::Algebraic);
+        positive.variables = Some([("x".to_string(), vec![0.5, 100.0])].into_iter().collect());
+        assert!(grade("x", r"\sqrt{x^2}", Some(&positive)).unwrap().is_correct());
+    }
+
+    #[test]
+    fn test_calculus_constant() {
+        let calculus = content(MathEquationType::Calculus);
+        assert!(grade("x^3/3 + C", r"\frac{1}{3}x^3", Some(&calculus)).unwrap().is_correct());
+        assert!(grade("x^3/3 + C", r"\frac{x^3}{3} + 7", Some(&calculus)).unwrap().is_correct());
+        assert!(!grade("x^3/3 + C", "x^3", Some(&calculus)).unwrap().is_correct());
+        assert!(!grade("x^3/3 + C", "x^3/3", None).unwrap().is_correct());
+        assert!(!grade("5", "7", Some(&calculus)).unwrap().is_correct());
+    }
+}
+
+mod numbers {
+    use super::*;
+
+    #[test]
+    fn test_tolerance() {
+        let mut settings = content(MathEquationType::Custom);
+        settings.tolerance = Some(0.01);
+        assert!(grade("3.14", "3.145", Some(&settings)).unwrap().is_correct());
+        assert!(!grade("3.14", "3.16", Some(&settings)).unwrap().is_correct());
+
+        assert!(correct("0.25", r"\frac{1}{4}"));
+        assert!(!correct("0.333", r"\frac{1}{3}"));
+
+        settings.tolerance = None;
+        settings.precision = Some(2);
+        assert!(grade("0.333", r"\frac{1}{3}", Some(&settings)).unwrap().is_correct());
+    }
+
+    #[test]
+    fn test_rounded_types() {
+        let statistical = content(MathEquationType::Statistical);
+        assert!(grade("0.6827", "0.683", Some(&statistical)).unwrap().is_correct());
+        assert!(!grade("0.6827", "0.69", Some(&statistical)).unwrap().is_correct());
+    }
+
+    #[test]
+    fn test_significant_figures() {
+        let mut settings = content(MathEquationType::Custom);
+        settings.significant_figures = Some(3);
+
+        assert!(grade("9.80665", "9.81", Some(&settings)).unwrap().is_correct());
+        assert!(!grade("9.80665", "9.91", Some(&settings)).unwrap().is_correct());
+
+        let graded = grade("9.80665", "9.807", Some(&settings)).unwrap();
+        assert_eq!(graded.credit, 1.0 - FORMAT_PENALTY);
+        let check = graded.checks.iter().find(|check| check.name == "significant_figures").unwrap();
+        assert_eq!(check.feedback.as_deref(), Some("Give the answer to 3 significant figures"));
+
+        assert_eq!(units::significant_figures("0.00450"), (3, 3));
+        assert_eq!(units::significant_figures("1500"), (2, 4));
+        assert_eq!(units::round_to_significant(0.012345, 2), 0.012);
+    }
+
+    #[test]
+    fn test_scientific_notation() {
+        assert!(correct("3e8", r"3 \times 10^{8}"));
+        assert!(correct("3e8", "3*10^8"));
+        assert!(correct("6.02e23", "6.02E+23"));
+    }
+}
+
+mod quantities {
+    use super::*;
+
+    #[test]
+    fn test_conversion() {
+        assert!(correct("9.81 m/s^2", "981 cm/s^2"));
+        assert!(correct("1 kN", r"1000 \mathrm{kg\,m\,s^{-2}}"));
+        assert!(correct("36 km/h", "10 m/s"));
+        assert!(correct("1 J/(kg K)", "1 J/kg K"));
+        assert!(correct("25 °C", "298.15 K"));
+        assert!(correct(r"90^\circ", r"1.5707963267948966 rad"));
+        assert!(correct("2 mmol", "0.002 mol"));
+    }
+
+    #[test]
+    fn test_missing_and_wrong_units() {
+        let graded = grade("9.81 m/s^2", "9.81", None).unwrap();
+        assert_eq!(graded.credit, 1.0 - FORMAT_PENALTY);
+        let check = graded.checks.iter().find(|check| check.name == "units").unwrap();
+        assert_eq!(check.feedback.as_deref(), Some("Give the units (m/s^2)"));
+
+        let graded = grade("9.81 m/s^2", "9.81 m/s", None).unwrap();
+        assert_eq!(graded.credit, 0.0);
+        assert_eq!(graded.checks[0].feedback.as_deref(), Some("m/s cannot be converted to m/s^2"));
+    }
+
+    #[test]
+    fn test_parsing() {
+        assert!(Unit::parse("furlong").is_err());
+        assert!(Quantity::parse("2x").is_none());
+        assert!(Quantity::parse(r"3 \times 10").is_none());
+
+        let quantity = Quantity::parse("-1.50 \\mathrm{kJ/mol}").unwrap();
+        assert_eq!((quantity.value, quantity.mantissa.as_str()), (-1.5, "1.50"));
+        assert_eq!(quantity.unit.unwrap().1, "kJ/mol");
+    }
+}
@@ -0,0 +1,487 @@
+// Units and quantities for numeric answers
+//
+// A quantity is a decimal number, optionally in scientific notation,
+// followed by a unit such as `m/s^2`, `kg m s^{-2}`, `\mathrm{kJ/mol}` or
+// `°C`. Units are reduced to SI base units, so answers in any compatible
+// unit can be compared.
+
+use std::f64::consts::PI;
+
+use super::MathError;
+
+/// Exponents of metre, kilogram, second, ampere, kelvin, mole and candela
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dimension([i8; 7]);
+
+impl Dimension {
+    const NONE: Dimension = Dimension([0; 7]);
+
+    fn times(self, other: Dimension, exponent: i32) -> Dimension {
+        let mut result = self.0;
+        for (slot, value) in result.iter_mut().zip(other.0) {
+            *slot += value * exponent as i8;
+        }
+        Dimension(result)
+    }
+}
+
+/// A unit as a multiple of SI base units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    /// Size of the unit in base units
+    pub factor: f64,
+    /// Zero of the unit in base units, for degrees Celsius
+    pub offset: f64,
+    pub dimension: Dimension,
+}
+
+/// Named units: symbol, size in base units, dimension and whether SI
+/// prefixes apply
+const UNITS: &[(&str, f64, [i8; 7], bool)] = &[
+    ("m", 1.0, [1, 0, 0, 0, 0, 0, 0], true),
+    ("g", 1e-3, [0, 1, 0, 0, 0, 0, 0], true),
+    ("s", 1.0, [0, 0, 1, 0, 0, 0, 0], true),
+    ("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+    ("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+    ("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+    ("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+    ("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+    ("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+    ("Pa", 1.0, [-1, 1, -2, 0, 0, 0, 0], true),
+    ("J", 1.0, [2, 1, -2, 0, 0, 0, 0], true),
+    ("W", 1.0, [2, 1, -3, 0, 0, 0, 0], true),
+    ("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+    ("V", 1.0, [2, 1, -3, -1, 0, 0, 0], true),
+    ("Ω", 1.0, [2, 1, -3, -2, 0, 0, 0], true),
+    ("ohm", 1.0, [2, 1, -3, -2, 0, 0, 0], true),
+    ("F", 1.0, [-2, -1, 4, 2, 0, 0, 0], true),
+    ("T", 1.0, [0, 1, -2, -1, 0, 0, 0], true),
+    ("L", 1e-3, [3, 0, 0, 0, 0, 0, 0], true),
+    ("l", 1e-3, [3, 0, 0, 0, 0, 0, 0], true),
+    ("M", 1e3, [-3, 0, 0, 0, 0, 1, 0], false),
+    ("eV", 1.602_176_634e-19, [2, 1, -2, 0, 0, 0, 0], true),
+    ("cal", 4.184, [2, 1, -2, 0, 0, 0, 0], true),
+    ("bar", 1e5, [-1, 1, -2, 0, 0, 0, 0], true),
+    ("atm", 101_325.0, [-1, 1, -2, 0, 0, 0, 0], false),
+    ("min", 60.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("d", 86_400.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("rad", 1.0, [0; 7], true),
+    ("deg", PI / 180.0, [0; 7], false),
+    ("°", PI / 180.0, [0; 7], false),
+    ("%", 0.01, [0; 7], false),
+];
+
+const PREFIXES: &[(&str, f64)] = &[
+    ("da", 1e1),
+    ("Y", 1e24),
+    ("Z", 1e21),
+    ("E", 1e18),
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("μ", 1e-6),
+    ("µ", 1e-6),
+    ("u", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+    ("a", 1e-18),
+];
+
+impl Unit {
+    const ONE: Unit = Unit { factor: 1.0, offset: 0.0, dimension: Dimension::NONE };
+
+    /// Parse a unit; a `/` puts everything after it in the denominator, so
+    /// `J/kg K` is joules per kilogram kelvin
+    pub fn parse(text: &str) -> Result<Unit, MathError> {
+        let tokens = lex_unit(text)?;
+        let mut index = 0;
+        let unit = parse_product(&tokens, &mut index)?;
+        match tokens.get(index) {
+            None => Ok(unit),
+            Some(_) => Err(MathError::UnknownUnit(text.trim().to_string())),
+        }
+    }
+
+    /// Whether values in the two units can be converted
+    pub fn is_compatible(&self, other: &Unit) -> bool {
+        self.dimension == other.dimension
+    }
+
+    pub fn to_base(&self, value: f64) -> f64 {
+        value * self.factor + self.offset
+    }
+
+    pub fn from_base(&self, value: f64) -> f64 {
+        (value - self.offset) / self.factor
+    }
+
+    /// Product with another unit raised to a power; offsets only make sense
+    /// for a unit on its own, so they are dropped
+    fn times(self, other: Unit, exponent: i32) -> Unit {
+        Unit {
+            factor: self.factor * other.factor.powi(exponent),
+            offset: 0.0,
+            dimension: self.dimension.times(other.dimension, exponent),
+        }
+    }
+
+    fn named(symbol: &str) -> Option<Unit> {
+        let unit = |factor: f64, dimension: [i8; 7]| Unit { factor, offset: 0.0, dimension: Dimension(dimension) };
+
+        if matches!(symbol, "°C" | "degC" | "℃") {
+            return Some(Unit { factor: 1.0, offset: 273.15, dimension: Dimension([0, 0, 0, 0, 1, 0, 0]) });
+        }
+        if let Some((_, factor, dimension, _)) = UNITS.iter().find(|(name, ..)| *name == symbol) {
+            return Some(unit(*factor, *dimension));
+        }
+
+        PREFIXES.iter().find_map(|(prefix, scale)| {
+            let base = symbol.strip_prefix(prefix)?;
+            UNITS.iter()
+                .find(|(name, _, _, prefixed)| *prefixed && *name == base)
+                .map(|(_, factor, dimension, _)| unit(factor * scale, *dimension))
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum UnitToken {
+    Symbol(String),
+    Exponent(i32),
+    Divide,
+    Open,
+    Close,
+}
+
+fn lex_unit(text: &str) -> Result<Vec<UnitToken>, MathError> {
+    let unknown = || MathError::UnknownUnit(text.trim().to_string());
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut symbol = String::new();
+    let mut i = 0;
+
+    let flush = |symbol: &mut String, tokens: &mut Vec<UnitToken>| {
+        if !symbol.is_empty() {
+            tokens.push(UnitToken::Symbol(std::mem::take(symbol)));
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' => {
+                let name: String = chars[i + 1..].iter().take_while(|c| c.is_ascii_alphabetic()).collect();
+                i += 1 + name.len();
+                match name.as_str() {
+                    "mu" => symbol.push('μ'),
+                    "Omega" => symbol.push('Ω'),
+                    "circ" => symbol.push('°'),
+                    "mathrm" | "text" | "textrm" | "rm" | "mathit" | "operatorname" | "left" | "right" => {}
+                    "cdot" | "times" => flush(&mut symbol, &mut tokens),
+                    // `\%`, `\,` and other symbols or spacing
+                    "" => {
+                        if chars.get(i) == Some(&'%') {
+                            symbol.push('%');
+                        } else {
+                            flush(&mut symbol, &mut tokens);
+                        }
+                        i += 1;
+                    }
+                    _ => return Err(unknown()),
+                }
+                continue;
+            }
+            '^' => {
+                flush(&mut symbol, &mut tokens);
+                i += 1;
+                let (exponent, end) = lex_exponent(&chars, i).ok_or_else(unknown)?;
+                i = end;
+                match exponent {
+                    Some(exponent) => tokens.push(UnitToken::Exponent(exponent)),
+                    // `^\circ` is the degree sign
+                    None => symbol.push('°'),
+                }
+                continue;
+            }
+            '0'..='9' if !symbol.is_empty() => {
+                // `m2` for square metres
+                flush(&mut symbol, &mut tokens);
+                let digits: String = chars[i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+                i += digits.len();
+                tokens.push(UnitToken::Exponent(digits.parse().map_err(|_| unknown())?));
+                continue;
+            }
+            '/' => {
+                flush(&mut symbol, &mut tokens);
+                tokens.push(UnitToken::Divide);
+            }
+            '(' | '{' | '[' => {
+                flush(&mut symbol, &mut tokens);
+                tokens.push(UnitToken::Open);
+            }
+            ')' | '}' | ']' => {
+                flush(&mut symbol, &mut tokens);
+                tokens.push(UnitToken::Close);
+            }
+            _ if c.is_whitespace() || matches!(c, '*' | '·' | '⋅' | '.' | '~' | '$') => flush(&mut symbol, &mut tokens),
+            _ if c.is_alphabetic() || matches!(c, 'μ' | 'µ' | 'Ω' | '°' | '%' | '℃') => {
+                // `° C` is one symbol
+                if symbol.is_empty() && tokens.last() == Some(&UnitToken::Symbol("°".to_string())) && (c == 'C' || c == 'F') {
+                    tokens.pop();
+                    symbol.push('°');
+                }
+                symbol.push(c);
+            }
+            _ => return Err(unknown()),
+        }
+        i += 1;
+    }
+    flush(&mut symbol, &mut tokens);
+
+    Ok(tokens)
+}
+
+/// Exponent after `^`: a signed integer, optionally in braces, or `\circ`
+/// for degrees (returned as `None`)
+fn lex_exponent(chars: &[char], start: usize) -> Option<(Option<i32>, usize)> {
+    let mut i = start;
+    let braced = matches!(chars.get(i), Some('{' | '('));
+    if braced {
+        i += 1;
+    }
+
+    let rest: String = chars[i..].iter().collect();
+    let exponent = if let Some(after) = rest.strip_prefix("\\circ") {
+        i += rest.chars().count() - after.chars().count();
+        None
+    } else {
+        let mut text = String::new();
+        if let Some(&sign @ ('+' | '-' | '−')) = chars.get(i) {
+            text.push(if sign == '+' { '+' } else { '-' });
+            i += 1;
+        }
+        while let Some(digit) = chars.get(i).filter(|c| c.is_ascii_digit()) {
+            text.push(*digit);
+            i += 1;
+        }
+        Some(text.parse().ok()?)
+    };
+
+    if braced {
+        if !matches!(chars.get(i), Some('}' | ')')) {
+            return None;
+        }
+        i += 1;
+    }
+    Some((exponent, i))
+}
+
+fn parse_product(tokens: &[UnitToken], index: &mut usize) -> Result<Unit, MathError> {
+    let mut unit = Unit::ONE;
+    let mut sign = 1;
+    let mut factors = 0;
+
+    loop {
+        let factor = match tokens.get(*index) {
+            Some(UnitToken::Symbol(symbol)) => {
+                *index += 1;
+                Unit::named(symbol).ok_or_else(|| MathError::UnknownUnit(symbol.clone()))?
+            }
+            Some(UnitToken::Open) => {
+                *index += 1;
+                let inner = parse_product(tokens, index)?;
+                if tokens.get(*index) != Some(&UnitToken::Close) {
+                    return Err(MathError::UnknownUnit("unbalanced parentheses".to_string()));
+                }
+                *index += 1;
+                inner
+            }
+            Some(UnitToken::Divide) => {
+                *index += 1;
+                sign = -1;
+                continue;
+            }
+            _ => break,
+        };
+
+        let exponent = match tokens.get(*index) {
+            Some(UnitToken::Exponent(exponent)) => {
+                *index += 1;
+                *exponent
+            }
+            _ => 1,
+        };
+
+        // A lone unit such as °C keeps its offset
+        unit = if factors == 0 && sign * exponent == 1 && tokens.get(*index).is_none() {
+            factor
+        } else {
+            unit.times(factor, sign * exponent)
+        };
+        factors += 1;
+    }
+
+    if factors == 0 {
+        return Err(MathError::UnknownUnit("missing unit".to_string()));
+    }
+    Ok(unit)
+}
+
+/// A number with an optional unit, as written by the learner
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    /// Digits of the number as written, for counting significant figures
+    pub mantissa: String,
+    /// The unit and how it was written
+    pub unit: Option<(Unit, String)>,
+}
+
+impl Quantity {
+    /// Parse a number such as `-1.50`, `6.02e23` or `3 \times 10^{8}`,
+    /// followed by an optional unit; anything else is not a quantity
+    pub fn parse(text: &str) -> Option<Quantity> {
+        let text = text.trim().trim_matches('$').trim();
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+
+        let negative = matches!(chars.first(), Some('-' | '−'));
+        if negative || chars.first() == Some(&'+') {
+            i += 1;
+        }
+
+        let mantissa: String = chars[i..].iter().take_while(|c| c.is_ascii_digit() || **c == '.').collect();
+        if !mantissa.chars().any(|c| c.is_ascii_digit()) || mantissa.matches('.').count() > 1 {
+            return None;
+        }
+        i += mantissa.len();
+        let mut value: f64 = mantissa.parse().ok()?;
+
+        let (exponent, end) = scientific_exponent(&chars, i)?;
+        value *= 10f64.powi(exponent);
+        i = end;
+        if negative {
+            value = -value;
+        }
+
+        let rest: String = chars[i..].iter().collect();
+        let rest = rest.trim();
+        let unit = if rest.is_empty() {
+            None
+        } else {
+            Some((Unit::parse(rest).ok()?, unit_label(rest)))
+        };
+
+        Some(Quantity { value, mantissa, unit })
+    }
+}
+
+/// The power of ten after a mantissa, as `e5`, `\times 10^{5}`, `* 10^5` or
+/// `·10^5`, and the index after it. Returns `None` if the notation is started
+/// but not finished.
+fn scientific_exponent(chars: &[char], start: usize) -> Option<(i32, usize)> {
+    let signed = |i: &mut usize| -> Option<i32> {
+        let mut text = String::new();
+        if let Some(&sign @ ('+' | '-' | '−')) = chars.get(*i) {
+            text.push(if sign == '+' { '+' } else { '-' });
+            *i += 1;
+        }
+        while let Some(digit) = chars.get(*i).filter(|c| c.is_ascii_digit()) {
+            text.push(*digit);
+            *i += 1;
+        }
+        text.parse().ok()
+    };
+
+    // `e5`, only when digits follow so `2 eV` stays a unit
+    if matches!(chars.get(start), Some('e' | 'E')) {
+        let mut i = start + 1;
+        if matches!(chars.get(i), Some('+' | '-' | '−')) || chars.get(i).is_some_and(char::is_ascii_digit) {
+            if matches!(chars.get(i), Some('+' | '-' | '−')) && !chars.get(i + 1).is_some_and(char::is_ascii_digit) {
+                return Some((0, start));
+            }
+            let exponent = signed(&mut i)?;
+            return Some((exponent, i));
+        }
+        return Some((0, start));
+    }
+
+    let rest: String = chars[start..].iter().collect();
+    let trimmed = rest.trim_start();
+    let mut i = start + (rest.chars().count() - trimmed.chars().count());
+    let after_operator = ["\\times", "\\cdot", "×", "·", "⋅", "*"].iter()
+        .find_map(|operator| trimmed.strip_prefix(operator));
+    let Some(after_operator) = after_operator else {
+        return Some((0, start));
+    };
+    i += trimmed.chars().count() - after_operator.chars().count();
+
+    let after_space = after_operator.trim_start();
+    i += after_operator.chars().count() - after_space.chars().count();
+    if !after_space.starts_with("10") {
+        // A product with something other than a power of ten
+        return None;
+    }
+    i += 2;
+    if chars.get(i) != Some(&'^') {
+        return None;
+    }
+    i += 1;
+
+    let braced = chars.get(i) == Some(&'{');
+    if braced {
+        i += 1;
+    }
+    let exponent = signed(&mut i)?;
+    if braced {
+        if chars.get(i) != Some(&'}') {
+            return None;
+        }
+        i += 1;
+    }
+    Some((exponent, i))
+}
+
+/// Unit as shown in feedback, without LaTeX wrappers
+fn unit_label(text: &str) -> String {
+    let mut label = text.to_string();
+    for wrapper in ["\\mathrm", "\\textrm", "\\text", "\\rm", "\\,", "{", "}", "$"] {
+        label = label.replace(wrapper, "");
+    }
+    label.replace("\\mu", "μ").replace("\\Omega", "Ω").replace("^\\circ", "°").replace("\\circ", "°").trim().to_string()
+}
+
+/// Range of significant figures of a written number; trailing zeros of a
+/// whole number may or may not be significant
+pub fn significant_figures(mantissa: &str) -> (u32, u32) {
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    let significant = digits.trim_start_matches('0');
+    if significant.is_empty() {
+        return (1, 1);
+    }
+
+    let count = significant.len() as u32;
+    if mantissa.contains('.') {
+        (count, count)
+    } else {
+        let trailing_zeros = (significant.len() - significant.trim_end_matches('0').len()) as u32;
+        (count - trailing_zeros, count)
+    }
+}
+
+/// Round a value to a number of significant figures
+pub fn round_to_significant(value: f64, figures: u32) -> f64 {
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+    let scale = 10f64.powi(figures as i32 - 1 - value.abs().log10().floor() as i32);
+    (value * scale).round() / scale
+}
@@ -5,11 +5,20 @@ pub mod models;
 pub mod storage;
 pub mod commands;
 pub mod session;
+pub mod scoring;
+pub mod math;
+pub mod formula;
+pub mod code_runner;
+pub mod banks;
 pub mod sync;
 pub mod standalone;
 pub mod spaced_repetition;
 pub mod analytics;
 pub mod export;
+pub mod qti;
+pub mod anki;
+pub mod exam;
+pub mod text_formats;
 pub mod course_integration;
 pub mod auth;
 pub mod notification;
@@ -67,9 +76,12 @@ use crate::course::storage::CourseStore;
 use crate::auth::AuthService;
 use crate::notification::NotificationService;
 use session::QuizSession;
-use spaced_repetition::{SpacedRepetitionScheduler, FlashcardRating};
+use spaced_repetition::{SpacedRepetitionScheduler, FlashcardRating, DeckSchedulerSettings, FitResult};
 use analytics::{AnalyticsEngine, TimePeriod};
-use export::{QuizExportEngine, ExportOptions, ExportFormat};
+use export::{QuizExportEngine, ExportOptions, ExportFormat, QtiImportReport};
+use qti::QtiPackage;
+use anki::AnkiImportReport;
+use exam::{ExamError, ExamEvent, ExamService, ExamState, ServerClock, TimeAccommodation};
 use course_integration::CourseIntegrationService;
 use auth::{QuizAuthService, QuizAuthMiddleware};
 use notification::QuizNotificationService;
@@ -83,7 +95,7 @@ use asset_cache::{AssetCache, AssetCacheConfig, AssetMetadata, AssetType};
 use sync_manager::{SyncManager, SyncManagerConfig, SyncPriority, ConflictStrategy, SyncNotification};
 use lti::{LtiService, LtiPlatformConfig, LtiVersion, LtiLaunchRequest, LtiRole};
 use scorm::{ScormService, ScormPackageMetadata, ScormSession, ScormActivityState};
-use xapi::{XApiClient, XApiClientConfig, XApiStatement, XApiStatementBuilder};
+use xapi::{StatementQueue, XApiClient, XApiClientConfig, XApiStatement, XApiStatementBuilder};
 
 #[derive(Clone)]
 pub struct QuizEngine {
@@ -91,6 +103,7 @@ pub struct QuizEngine {
     scheduler: Arc<SpacedRepetitionScheduler>,
     analytics: Arc<AnalyticsEngine>,
     export_engine: Arc<QuizExportEngine>,
+    exam_service: Arc<ExamService>,
     session_queue: mpsc::UnboundedSender<QuizSession>,
     course_integration: Option<Arc<CourseIntegrationService>>,
     auth_service: Option<Arc<QuizAuthService>>,
@@ -110,6 +123,7 @@ pub struct QuizEngine {
     lti_service: Arc<Mutex<LtiService>>,
     scorm_service: Arc<ScormService>,
     xapi_client: Option<Arc<XApiClient>>,
+    xapi_queue: StatementQueue,
 }
 
 impl QuizEngine {
@@ -308,8 +322,12 @@ impl QuizEngine {
             });
         }
 
-        // Initialize LTI service
-        let lti_service = Arc::new(Mutex::new(LtiService::new()));
+        // Initialize LTI service with persisted nonces, login states, tool key
+        // and platforms, and the LTI 1.3 launch URL if one is configured
+        let redirect_uri = config.get_table("lti").ok()
+            .and_then(|lti_config| lti_config.get("redirect_uri").and_then(|v| v.as_str()).map(|s| s.to_string()));
+        let lti_service = LtiService::configured(store.get_sqlite_pool(), redirect_uri.as_deref()).await?;
+        let lti_service = Arc::new(Mutex::new(lti_service));
 
         // Initialize SCORM service
         let scorm_package_dir = config.data_dir.join("scorm/packages");
@@ -350,6 +368,25 @@ impl QuizEngine {
             None
         };
 
+        // Statements are queued locally and forwarded to the LRS in the
+        // background so that tracking keeps working offline. Without an LRS
+        // they stay queued, and are sent once one is configured
+        let xapi_queue = StatementQueue::new(store.get_sqlite_pool().clone()).await?;
+        if let Some(client) = &xapi_client {
+            xapi::queue::spawn_flush_loop(xapi_queue.clone(), client.clone(), std::time::Duration::from_secs(60));
+        }
+
+        // Timed attempts are submitted in the background once their time is up,
+        // and timed by the server's clock if one is configured
+        let mut exam_service = ExamService::new(store.get_sqlite_pool().clone(), store.clone()).await?;
+        if let Some(time_server) = config.get_table("exam").ok()
+            .and_then(|exam_config| exam_config.get("time_server").and_then(|v| v.as_str()).map(|s| s.to_string()))
+        {
+            exam_service = exam_service.with_server_clock(ServerClock::new(&time_server));
+        }
+        let exam_service = Arc::new(exam_service);
+        exam::spawn_deadline_loop(exam_service.clone(), std::time::Duration::from_secs(exam::DEADLINE_CHECK_SECONDS));
+
         // Start a background task to periodically clear expired cache entries
         let query_optimizer_clone = query_optimizer.clone();
         let asset_cache_clone = asset_cache.clone();
@@ -375,6 +412,7 @@ impl QuizEngine {
             scheduler,
             analytics,
             export_engine,
+            exam_service,
             session_queue: tx,
             course_integration,
             auth_service,
@@ -390,6 +428,7 @@ impl QuizEngine {
             lti_service,
             scorm_service,
             xapi_client,
+            xapi_queue,
         })
     }
 
@@ -438,6 +477,21 @@ impl QuizEngine {
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
     }
 
+    /// Get a user's scheduling settings for a deck
+    pub async fn get_deck_scheduler_settings(&self, user_id: uuid::Uuid, deck_id: uuid::Uuid) -> Result<DeckSchedulerSettings, Box<dyn std::error::Error + Send + Sync>> {
+        self.scheduler.get_deck_settings(user_id, deck_id).await
+    }
+
+    /// Choose the scheduling algorithm and settings of a deck
+    pub async fn set_deck_scheduler_settings(&self, user_id: uuid::Uuid, deck_id: uuid::Uuid, settings: DeckSchedulerSettings) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.scheduler.set_deck_settings(user_id, deck_id, &settings).await
+    }
+
+    /// Fit FSRS weights to a user's reviews for a deck
+    pub async fn optimize_deck_scheduler(&self, user_id: uuid::Uuid, deck_id: uuid::Uuid) -> Result<FitResult, Box<dyn std::error::Error + Send + Sync>> {
+        self.scheduler.optimize_deck(user_id, deck_id).await
+    }
+
     /// Get flashcard statistics for a user
     pub async fn get_flashcard_stats(&self, user_id: uuid::Uuid) -> Result<spaced_repetition::FlashcardStatistics, Box<dyn std::error::Error + Send + Sync>> {
         self.scheduler.get_user_statistics(user_id).await
@@ -458,6 +512,16 @@ impl QuizEngine {
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
     }
 
+    /// Get item analysis statistics of a quiz for revising its questions
+    pub async fn get_item_analysis(&self, quiz_id: uuid::Uuid, period: TimePeriod) -> Result<analytics::item_analysis::ItemAnalysis, Box<dyn std::error::Error + Send + Sync>> {
+        self.analytics.get_item_analysis(quiz_id, period).await
+    }
+
+    /// Export a quiz's item analysis as CSV
+    pub async fn export_item_analysis_csv(&self, quiz_id: uuid::Uuid, period: TimePeriod) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        self.analytics.export_item_analysis_csv(quiz_id, period).await
+    }
+
     /// Generate a PDF report for a user's study statistics
     pub async fn generate_user_report(&self, user_id: uuid::Uuid, period: TimePeriod) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
         self.analytics.generate_user_report(user_id, period).await
@@ -500,6 +564,16 @@ impl QuizEngine {
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
     }
 
+    /// Export a quiz as a QTI package, reporting the questions left out
+    pub async fn export_quiz_qti(&self, quiz_id: uuid::Uuid, format: ExportFormat) -> Result<QtiPackage, Box<dyn std::error::Error + Send + Sync>> {
+        let options = ExportOptions {
+            format,
+            ..Default::default()
+        };
+
+        self.export_engine.export_quiz_qti(quiz_id, options).await
+    }
+
     /// Import a quiz from a file
     pub async fn import_quiz_from_file(&self, path: &Path) -> Result<uuid::Uuid, Box<dyn std::error::Error + Send + Sync>> {
         self.export_engine.import_quiz_from_file(path).await
@@ -512,6 +586,108 @@ impl QuizEngine {
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
     }
 
+    /// Import a QTI package, reporting the items that could not be imported
+    pub async fn import_quiz_qti(&self, data: &[u8]) -> Result<QtiImportReport, Box<dyn std::error::Error + Send + Sync>> {
+        self.export_engine.import_quiz_qti(data).await
+    }
+
+    /// Import an Anki package for a user: a quiz per deck, the media into
+    /// the asset cache and the cards' scheduling and review history
+    pub async fn import_anki_package(&self, data: &[u8], user_id: uuid::Uuid) -> Result<AnkiImportReport, Box<dyn std::error::Error + Send + Sync>> {
+        let package = anki::read_package(data).await?;
+
+        let mut assets = HashMap::new();
+        for (filename, content) in &package.media {
+            let metadata = self.store_asset(content.clone(), filename, None, None).await?;
+            assets.insert(filename.clone(), metadata.id);
+        }
+
+        let import = anki::convert(&package, &assets, user_id, chrono::Utc::now());
+        for quiz in &import.quizzes {
+            self.store.store_quiz(quiz).await?;
+        }
+        for data in &import.flashcards {
+            self.store.store_flashcard_data(data).await?;
+        }
+        for review in &import.reviews {
+            self.store.store_flashcard_review(review).await?;
+        }
+
+        Ok(AnkiImportReport {
+            quiz_ids: import.quizzes.iter().map(|quiz| quiz.id).collect(),
+            cards: import.quizzes.iter().map(|quiz| quiz.questions.len()).sum(),
+            media: assets.len(),
+            reviews: import.reviews.len(),
+            skipped: import.skipped,
+        })
+    }
+
+    // Timed exam methods
+
+    /// Start a timed attempt at a quiz
+    pub async fn start_timed_exam(&self, quiz_id: uuid::Uuid, user_id: uuid::Uuid) -> Result<ExamState, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.exam_service.start(quiz_id, user_id).await?)
+    }
+
+    /// Get a timed attempt of the user with the time it has left; other
+    /// users' attempts are not found
+    pub async fn resume_timed_exam(&self, session_id: uuid::Uuid, user_id: uuid::Uuid) -> Result<ExamState, Box<dyn std::error::Error + Send + Sync>> {
+        if self.exam_service.attempt(session_id).await?.user_id != user_id {
+            return Err(ExamError::NotFound(session_id).into());
+        }
+        Ok(self.exam_service.resume(session_id).await?)
+    }
+
+    /// Get a user's timed attempts that are still running, as after a restart
+    pub async fn resume_timed_exams(&self, user_id: uuid::Uuid) -> Result<Vec<ExamState>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.exam_service.resume_all(user_id).await?)
+    }
+
+    /// Save the answers of a timed attempt without submitting it
+    pub async fn autosave_exam_answers(&self, session_id: uuid::Uuid, answers: Vec<(uuid::Uuid, models::Answer)>) -> Result<ExamState, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.exam_service.autosave(session_id, answers).await?)
+    }
+
+    /// Submit a timed attempt
+    pub async fn submit_timed_exam(&self, session_id: uuid::Uuid) -> Result<ExamState, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.exam_service.submit(session_id).await?)
+    }
+
+    /// Grant a student extra time, extending their running attempts. Extra
+    /// time for a quiz is granted by those who may edit it, and for every
+    /// quiz by instructors.
+    pub async fn grant_time_accommodation(&self, granted_by: uuid::Uuid, accommodation: TimeAccommodation) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Without the auth service nobody can be checked, so nobody may grant
+        let Some(auth) = &self.auth_service else {
+            return Err("Permission denied: the auth service is not available".into());
+        };
+        let allowed = match accommodation.quiz_id {
+            Some(quiz_id) => auth.check_quiz_permission(granted_by, quiz_id, auth::QuizPermission::Edit).await?,
+            None => auth.is_instructor(granted_by).await?,
+        };
+        if !allowed {
+            return Err("Permission denied: only instructors may grant extra time".into());
+        }
+
+        let accommodation = TimeAccommodation { granted_by: Some(granted_by), ..accommodation };
+        Ok(self.exam_service.grant_accommodation(accommodation).await?)
+    }
+
+    /// Audit trail of a timed attempt, for the student who took it and those
+    /// who may see the quiz's results
+    pub async fn get_exam_events(&self, session_id: uuid::Uuid, user_id: uuid::Uuid) -> Result<Vec<ExamEvent>, Box<dyn std::error::Error + Send + Sync>> {
+        let attempt = self.exam_service.attempt(session_id).await?;
+        let allowed = attempt.user_id == user_id || match &self.auth_service {
+            Some(auth) => auth.check_quiz_permission(user_id, attempt.quiz_id, auth::QuizPermission::ViewResults).await?,
+            None => false,
+        };
+        if !allowed {
+            return Err(ExamError::NotFound(session_id).into());
+        }
+
+        Ok(self.exam_service.events(session_id).await?)
+    }
+
     // Course Integration methods
 
     /// Add a quiz to a course
@@ -740,6 +916,31 @@ impl QuizEngine {
         }
     }
 
+    /// Check if a user may author and read question banks. Only instructors
+    /// may, and nobody without the auth service to check them.
+    pub async fn can_manage_question_banks(
+        &self,
+        user_id: uuid::Uuid,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.auth_service {
+            Some(auth) => auth.is_instructor(user_id).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Check if a user may change which bank questions a quiz draws. Those
+    /// who may edit the quiz may, and nobody without the auth service.
+    pub async fn can_manage_question_groups(
+        &self,
+        user_id: uuid::Uuid,
+        quiz_id: uuid::Uuid,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.auth_service {
+            Some(auth) => auth.check_quiz_permission(user_id, quiz_id, auth::QuizPermission::Edit).await,
+            None => Ok(false),
+        }
+    }
+
     /// Get the auth middleware
     pub fn get_auth_middleware(&self) -> Option<Arc<QuizAuthMiddleware>> {
         self.auth_middleware.clone()
@@ -1041,8 +1242,9 @@ impl QuizEngine {
 
     // LTI integration methods
 
-    /// Add an LTI platform configuration
+    /// Add an LTI platform configuration, keeping it across restarts
     pub async fn add_lti_platform(&self, config: LtiPlatformConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        lti::platforms::PlatformStore::new(self.store.get_sqlite_pool().clone()).await?.save(&config).await?;
         let mut lti_service = self.lti_service.lock().await;
         lti_service.add_platform(config);
         Ok(())
@@ -1056,14 +1258,38 @@ impl QuizEngine {
 
     /// Remove an LTI platform configuration
     pub async fn remove_lti_platform(&self, id: &Uuid) -> Result<Option<LtiPlatformConfig>, Box<dyn std::error::Error + Send + Sync>> {
+        lti::platforms::PlatformStore::new(self.store.get_sqlite_pool().clone()).await?.remove(id).await?;
         let mut lti_service = self.lti_service.lock().await;
         Ok(lti_service.remove_platform(id))
     }
 
+    /// Handle an LTI 1.3 OIDC login initiation, returning the platform redirect URL
+    pub async fn handle_lti_oidc_login(&self, request: &lti::lti13::OidcLoginRequest) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let lti_service = self.lti_service.lock().await;
+        lti_service.handle_oidc_login(request)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+    }
+
+    /// Validate an LTI 1.3 launch
+    pub async fn validate_lti_1_3_launch(&self, id_token: &str, state: &str) -> Result<LtiLaunchRequest, Box<dyn std::error::Error + Send + Sync>> {
+        let lti_service = self.lti_service.lock().await;
+        lti_service.validate_lti_1_3_launch(id_token, state)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+    }
+
+    /// Get the tool's public JWKS
+    pub async fn get_lti_tool_jwks(&self) -> serde_json::Value {
+        let lti_service = self.lti_service.lock().await;
+        lti_service.tool_jwks()
+    }
+
     /// Validate an LTI launch request
-    pub async fn validate_lti_launch_request(&self, platform_id: &Uuid, params: &HashMap<String, String>) -> Result<LtiLaunchRequest, Box<dyn std::error::Error + Send + Sync>> {
+    pub async fn validate_lti_launch_request(&self, platform_id: &Uuid, http_method: &str, launch_url: &str, params: &HashMap<String, String>) -> Result<LtiLaunchRequest, Box<dyn std::error::Error + Send + Sync>> {
         let lti_service = self.lti_service.lock().await;
-        lti_service.validate_launch_request(platform_id, params)
+        lti_service.validate_launch_request(platform_id, http_method, launch_url, params)
+            .await
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
     }
 
@@ -1073,6 +1299,7 @@ impl QuizEngine {
     pub async fn import_scorm_package(&self, package_path: &Path) -> Result<Uuid, Box<dyn std::error::Error + Send + Sync>> {
         let mut scorm_service = self.scorm_service.clone();
         scorm_service.import_package(package_path)
+            .await
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
     }
 
@@ -1090,6 +1317,7 @@ impl QuizEngine {
     pub async fn delete_scorm_package(&self, id: &Uuid) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut scorm_service = self.scorm_service.clone();
         scorm_service.delete_package(id)
+            .await
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
     }
 
@@ -1097,6 +1325,7 @@ impl QuizEngine {
     pub async fn create_scorm_session(&self, package_id: &Uuid, user_id: &Uuid) -> Result<Uuid, Box<dyn std::error::Error + Send + Sync>> {
         let mut scorm_service = self.scorm_service.clone();
         scorm_service.create_session(package_id, user_id)
+            .await
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
     }
 
@@ -1109,6 +1338,7 @@ impl QuizEngine {
     pub async fn update_scorm_session(&self, session: ScormSession) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut scorm_service = self.scorm_service.clone();
         scorm_service.update_session(session)
+            .await
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
     }
 
@@ -1120,14 +1350,18 @@ impl QuizEngine {
 
     // xAPI integration methods
 
-    /// Send an xAPI statement
+    /// Queue an xAPI statement for delivery to the LRS and return its ID; it
+    /// is kept until an LRS is configured
     pub async fn send_xapi_statement(&self, statement: &XApiStatement) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(client) = &self.xapi_client {
-            client.send_statement(statement).await
-                .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
-        } else {
-            Err(Box::new(std::io::Error::new(std::io::ErrorKind::NotFound, "xAPI client not configured")) as Box<dyn std::error::Error + Send + Sync>)
-        }
+        self.xapi_queue.enqueue(statement).await
+            .map(|id| id.to_string())
+            .map_err(|e| e.into())
+    }
+
+    /// Get the status of the outbound xAPI statement queue
+    pub async fn get_xapi_queue_status(&self) -> Result<xapi::XApiQueueStatus, Box<dyn std::error::Error + Send + Sync>> {
+        self.xapi_queue.status().await
+            .map_err(|e| e.into())
     }
 
     /// Get an xAPI statement by ID
@@ -1152,40 +1386,25 @@ impl QuizEngine {
 
     /// Track a quiz start event with xAPI
     pub async fn track_quiz_started(&self, user_id: &str, user_name: &str, user_email: &str, quiz_id: &str, quiz_name: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(client) = &self.xapi_client {
-            let statement = xapi::create_quiz_started_statement(user_id, user_name, user_email, quiz_id, quiz_name);
-            client.send_statement(&statement).await
-                .map(|_| ())
-                .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
-        } else {
-            // If xAPI is not configured, just return success
-            Ok(())
-        }
+        let statement = xapi::create_quiz_started_statement(user_id, user_name, user_email, quiz_id, quiz_name);
+        self.xapi_queue.enqueue(&statement).await
+            .map(|_| ())
+            .map_err(|e| e.into())
     }
 
     /// Track a quiz completion event with xAPI
     pub async fn track_quiz_completed(&self, user_id: &str, user_name: &str, user_email: &str, quiz_id: &str, quiz_name: &str, score: f32, max_score: f32, success: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(client) = &self.xapi_client {
-            let statement = xapi::create_quiz_completed_statement(user_id, user_name, user_email, quiz_id, quiz_name, score, max_score, success);
-            client.send_statement(&statement).await
-                .map(|_| ())
-                .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
-        } else {
-            // If xAPI is not configured, just return success
-            Ok(())
-        }
+        let statement = xapi::create_quiz_completed_statement(user_id, user_name, user_email, quiz_id, quiz_name, score, max_score, success);
+        self.xapi_queue.enqueue(&statement).await
+            .map(|_| ())
+            .map_err(|e| e.into())
     }
 
     /// Track a question answered event with xAPI
     pub async fn track_question_answered(&self, user_id: &str, user_name: &str, user_email: &str, quiz_id: &str, quiz_name: &str, question_id: &str, question_text: &str, response: &str, success: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(client) = &self.xapi_client {
-            let statement = xapi::create_question_answered_statement(user_id, user_name, user_email, quiz_id, quiz_name, question_id, question_text, response, success);
-            client.send_statement(&statement).await
-                .map(|_| ())
-                .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
-        } else {
-            // If xAPI is not configured, just return success
-            Ok(())
-        }
+        let statement = xapi::create_question_answered_statement(user_id, user_name, user_email, quiz_id, quiz_name, question_id, question_text, response, success);
+        self.xapi_queue.enqueue(&statement).await
+            .map(|_| ())
+            .map_err(|e| e.into())
     }
 }
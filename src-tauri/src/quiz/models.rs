@@ -3,6 +3,8 @@ use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 
+use super::scoring;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Quiz {
     pub id: Uuid,
@@ -43,6 +45,8 @@ pub struct QuestionContent {
     pub math_equation_content: Option<MathEquationContent>,
     pub timeline_content: Option<TimelineContent>,
     pub diagram_labeling_content: Option<DiagramLabelingContent>,
+    #[serde(default)]
+    pub short_answer_content: Option<ShortAnswerContent>,
 }
 
 impl QuestionContent {
@@ -76,11 +80,14 @@ pub struct Choice {
     pub text: String,
     pub rich_text: Option<String>,
     pub image_url: Option<String>,
+    #[serde(default)]
+    pub feedback: Option<String>, // Shown when this choice is picked
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Answer {
     Choice(Uuid),                      // ID of the selected choice
+    Choices(Vec<Uuid>),                // IDs of the selected choices (multi-select)
     Text(String),                      // Free text answer
     Matching(Vec<(Uuid, Uuid)>),       // Pairs of matching items
     Ordering(Vec<Uuid>),               // Ordered list of item IDs
@@ -101,6 +108,22 @@ pub struct QuizSettings {
     pub show_correct_answers: bool,
     pub passing_score: Option<f32>,
     pub study_mode: StudyMode,
+    #[serde(default)]
+    pub wrong_choice_penalty: WrongChoicePenalty,
+}
+
+/// How wrong picks count against multi-select and hotspot answers
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum WrongChoicePenalty {
+    /// Wrong picks cost nothing
+    None,
+    /// Each wrong pick cancels out one right pick
+    #[default]
+    PerChoice,
+    /// Each wrong pick costs a fixed fraction of the question's credit
+    Fixed(f32),
+    /// Only a fully right selection earns credit
+    AllOrNothing,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -129,6 +152,10 @@ pub struct FlashcardData {
     pub repetitions: i32,      // Number of successful repetitions
     pub due_date: DateTime<Utc>, // Next review date
     pub last_reviewed: DateTime<Utc>,
+    #[serde(default)]
+    pub stability: Option<f64>,  // FSRS stability in days, once scheduled with FSRS
+    #[serde(default)]
+    pub difficulty: Option<f64>, // FSRS difficulty from 1 to 10
 }
 
 // Quiz attempt/session data
@@ -196,6 +223,41 @@ pub enum DrawingTool {
     Text,
 }
 
+// Short Answer Question Content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShortAnswerContent {
+    /// Checked in order; the best credit among the matching rules counts
+    pub accepted_answers: Vec<AcceptedAnswer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptedAnswer {
+    pub rule: MatchRule,
+    /// Fraction of the question's credit this answer earns
+    #[serde(default = "full_credit")]
+    pub credit: f32,
+    pub feedback: Option<String>,
+}
+
+fn full_credit() -> f32 {
+    1.0
+}
+
+/// How a short answer is compared with an accepted answer
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum MatchRule {
+    /// Character for character, after trimming surrounding whitespace
+    Exact(String),
+    CaseInsensitive(String),
+    /// Ignores case, punctuation and runs of whitespace
+    Normalized(String),
+    /// Case-insensitive, within an edit distance of the text
+    Levenshtein { text: String, max_distance: usize },
+    /// Must match the whole answer
+    Regex(String),
+    Numeric { value: f64, tolerance: f64 },
+}
+
 // Code Execution Question Content
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CodeExecutionContent {
@@ -231,6 +293,24 @@ pub struct CodeExecutionResult {
     pub execution_time_ms: u32,
     pub memory_used_kb: Option<u32>,
     pub error: Option<String>,
+    #[serde(default)]
+    pub stderr: String,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub status: ExecutionStatus,
+}
+
+/// How a run of learner code ended
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ExecutionStatus {
+    /// The program ran to completion; `exit_code` says whether it succeeded
+    #[default]
+    Finished,
+    CompileError,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    OutputLimitExceeded,
 }
 
 // Math Equation Question Content
@@ -240,6 +320,10 @@ pub struct MathEquationContent {
     pub variables: Option<HashMap<String, Vec<f64>>>,
     pub precision: Option<u32>,
     pub display_mode: bool,
+    #[serde(default)]
+    pub tolerance: Option<f64>, // Accepted distance from a numeric answer
+    #[serde(default)]
+    pub significant_figures: Option<u32>, // Required significant figures of a numeric answer
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -328,6 +412,7 @@ impl Quiz {
                 show_correct_answers: true,
                 passing_score: None,
                 study_mode: StudyMode::MultipleChoice,
+                wrong_choice_penalty: WrongChoicePenalty::default(),
             },
             author_id,
             visibility: QuizVisibility::Private,
@@ -368,6 +453,7 @@ impl Question {
             text,
             rich_text: None,
             image_url: None,
+            feedback: None,
         };
         let id = choice.id;
         self.choices.push(choice);
@@ -378,35 +464,44 @@ impl Question {
         self.correct_answer = answer;
     }
 
-    pub fn check_answer(&self, answer: &Answer) -> bool {
-        match (&self.correct_answer, answer) {
-            (Answer::Choice(correct_id), Answer::Choice(answer_id)) => {
-                correct_id == answer_id
-            },
-            (Answer::Text(correct_text), Answer::Text(answer_text)) => {
-                // Simple case-insensitive comparison
-                // In a real implementation, this would use more sophisticated matching
-                correct_text.to_lowercase() == answer_text.to_lowercase()
-            },
-            (Answer::Matching(correct_pairs), Answer::Matching(answer_pairs)) => {
-                if correct_pairs.len() != answer_pairs.len() {
-                    return false;
-                }
-
-                // Check if all pairs match
-                correct_pairs.iter().all(|correct_pair| {
-                    answer_pairs.contains(correct_pair)
-                })
-            },
-            (Answer::Ordering(correct_order), Answer::Ordering(answer_order)) => {
-                if correct_order.len() != answer_order.len() {
-                    return false;
-                }
+    /// Move the alternates of a short answer question made before accepted
+    /// answers, which were listed as choices, into its short answer content.
+    /// They are matched ignoring case, as they were. Returns whether the
+    /// question changed.
+    pub fn migrate_short_answer_alternates(&mut self) -> bool {
+        let has_accepted_answers = self.content.short_answer_content.as_ref()
+            .is_some_and(|content| !content.accepted_answers.is_empty());
+        if self.answer_type != AnswerType::ShortAnswer || self.choices.is_empty() || has_accepted_answers {
+            return false;
+        }
+        let Answer::Text(correct) = &self.correct_answer else {
+            return false;
+        };
 
-                // Check if the order matches
-                correct_order == answer_order
-            },
-            _ => false, // Mismatched answer types
+        let accepted = |text: &str, feedback: Option<String>| AcceptedAnswer {
+            rule: MatchRule::CaseInsensitive(text.trim().to_string()),
+            credit: 1.0,
+            feedback,
+        };
+        let mut accepted_answers = vec![accepted(correct, None)];
+        for choice in self.choices.drain(..) {
+            if choice.text.trim().to_lowercase() == correct.trim().to_lowercase() {
+                accepted_answers[0].feedback = accepted_answers[0].feedback.take().or(choice.feedback);
+            } else {
+                accepted_answers.push(accepted(&choice.text, choice.feedback));
+            }
         }
+
+        self.content.short_answer_content = Some(ShortAnswerContent { accepted_answers });
+        true
+    }
+
+    pub fn check_answer(&self, answer: &Answer) -> bool {
+        scoring::score_answer(self, answer, WrongChoicePenalty::AllOrNothing).is_correct()
+    }
+
+    /// Score an answer for partial credit under the quiz's settings
+    pub fn score_answer(&self, answer: &Answer, settings: &QuizSettings) -> scoring::QuestionScore {
+        scoring::score_answer(self, answer, settings.wrong_choice_penalty)
     }
 }
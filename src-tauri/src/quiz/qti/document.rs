@@ -0,0 +1,245 @@
+// Minimal XML tree for QTI documents
+//
+// Element and attribute names are always held in QTI 2.1 vocabulary. Names
+// are translated to QTI 3.0 when rendering and back when parsing; XHTML
+// content elements such as `p` and `img` keep their names in both versions.
+
+use xml::reader::{EventReader, XmlEvent};
+
+use super::{QtiError, QtiVersion};
+
+/// XHTML elements allowed in item bodies, which QTI 3.0 does not prefix
+const XHTML_ELEMENTS: &[&str] = &[
+    "a", "abbr", "b", "big", "blockquote", "br", "caption", "cite", "code", "col", "colgroup", "dd", "dfn",
+    "div", "dl", "dt", "em", "h1", "h2", "h3", "h4", "h5", "h6", "hr", "i", "img", "kbd", "li", "object",
+    "ol", "p", "param", "pre", "q", "samp", "small", "span", "strong", "sub", "sup", "table", "tbody", "td",
+    "tfoot", "th", "thead", "tr", "tt", "ul", "var",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), ..Default::default() }
+    }
+
+    pub fn attr(mut self, name: &str, value: impl ToString) -> Self {
+        self.attributes.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn child(mut self, child: Element) -> Self {
+        self.children.push(Node::Element(child));
+        self
+    }
+
+    pub fn children(mut self, children: impl IntoIterator<Item = Element>) -> Self {
+        self.children.extend(children.into_iter().map(Node::Element));
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.children.push(Node::Text(text.to_string()));
+        self
+    }
+
+    /// Value of an attribute
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Child elements
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// First child element with a name
+    pub fn find(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.name == name)
+    }
+
+    /// Child elements with a name
+    pub fn find_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.elements().filter(move |element| element.name == name)
+    }
+
+    /// This element and all elements below it, depth first
+    pub fn descendants(&self) -> Vec<&Element> {
+        let mut descendants = vec![self];
+        for element in self.elements() {
+            descendants.extend(element.descendants());
+        }
+        descendants
+    }
+
+    /// Text below the element with whitespace collapsed, leaving out
+    /// elements for which `skip` returns true
+    pub fn text_content(&self, skip: &dyn Fn(&Element) -> bool) -> String {
+        fn collect(element: &Element, skip: &dyn Fn(&Element) -> bool, text: &mut String) {
+            for node in &element.children {
+                match node {
+                    Node::Text(value) => text.push_str(value),
+                    Node::Element(child) if !skip(child) => collect(child, skip, text),
+                    Node::Element(_) => {}
+                }
+            }
+        }
+
+        let mut text = String::new();
+        collect(self, skip, &mut text);
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Render as an XML document; QTI names are translated for `version`,
+    /// while documents outside QTI (the manifest) pass `None`
+    pub fn to_document(&self, version: Option<QtiVersion>) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        self.render(version, 0, &mut xml);
+        xml
+    }
+
+    fn render(&self, version: Option<QtiVersion>, depth: usize, xml: &mut String) {
+        let name = match version {
+            Some(QtiVersion::V3_0) => element_name_v3(&self.name),
+            _ => self.name.clone(),
+        };
+
+        xml.push('<');
+        xml.push_str(&name);
+        for (key, value) in &self.attributes {
+            let key = match version {
+                Some(QtiVersion::V3_0) if !key.contains(':') && key != "xmlns" => kebab_case(key),
+                _ => key.clone(),
+            };
+            xml.push_str(&format!(" {}=\"{}\"", key, escape(value)));
+        }
+
+        if self.children.is_empty() {
+            xml.push_str("/>");
+            return;
+        }
+        xml.push('>');
+
+        // Elements without text content are laid out one child per line
+        let block = self.children.iter().all(|node| matches!(node, Node::Element(_)));
+        for node in &self.children {
+            match node {
+                Node::Text(text) => xml.push_str(&escape(text)),
+                Node::Element(element) => {
+                    if block {
+                        xml.push('\n');
+                        xml.push_str(&"  ".repeat(depth + 1));
+                    }
+                    element.render(version, depth + 1, xml);
+                }
+            }
+        }
+        if block {
+            xml.push('\n');
+            xml.push_str(&"  ".repeat(depth));
+        }
+
+        xml.push_str(&format!("</{}>", name));
+    }
+}
+
+/// Parse a document, translating QTI 3.0 names to QTI 2.1 vocabulary
+pub(super) fn parse(path: &str, xml: &str) -> Result<Element, QtiError> {
+    let invalid = |message: String| QtiError::Xml(path.to_string(), message);
+    let mut stack = vec![Element::default()];
+
+    for event in EventReader::from_str(xml) {
+        match event.map_err(|e| invalid(e.to_string()))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let mut element = Element::new(&element_name_v2(&name.local_name));
+                element.attributes = attributes.into_iter()
+                    .map(|attribute| (camel_case(&attribute.name.local_name), attribute.value))
+                    .collect();
+                stack.push(element);
+            }
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().ok_or_else(|| invalid("unbalanced elements".to_string()))?;
+                stack.last_mut()
+                    .ok_or_else(|| invalid("unbalanced elements".to_string()))?
+                    .children
+                    .push(Node::Element(element));
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.children.push(Node::Text(text));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    stack.pop()
+        .and_then(|document| document.elements().next().cloned())
+        .ok_or_else(|| invalid("no root element".to_string()))
+}
+
+fn element_name_v3(name: &str) -> String {
+    if XHTML_ELEMENTS.contains(&name) {
+        name.to_string()
+    } else {
+        format!("qti-{}", kebab_case(name))
+    }
+}
+
+fn element_name_v2(name: &str) -> String {
+    match name.strip_prefix("qti-") {
+        Some(name) => camel_case(name),
+        None => name.to_string(),
+    }
+}
+
+/// `responseIdentifier` to `response-identifier`
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::with_capacity(name.len() + 4);
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            kebab.push('-');
+            kebab.push(c.to_ascii_lowercase());
+        } else {
+            kebab.push(c);
+        }
+    }
+    kebab
+}
+
+/// `response-identifier` to `responseIdentifier`
+fn camel_case(name: &str) -> String {
+    let mut parts = name.split('-');
+    let mut camel = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            camel.push(first.to_ascii_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,132 @@
+// IMS QTI 2.1 and 3.0 assessment packages
+//
+// Quizzes are exchanged as IMS content packages: a zip holding
+// `imsmanifest.xml`, an assessment test that lists the items in order and one
+// assessment item per question. Documents are built in QTI 2.1 vocabulary.
+// QTI 3.0 only renames things (`qti-` prefixed kebab-case elements and
+// kebab-case attributes), so the same trees are rendered for either version,
+// and 3.0 documents are read by translating their names back.
+//
+// Question types map to interactions as follows:
+// - MultipleChoice, TrueFalse: choiceInteraction, multiple cardinality for
+//   multi-select questions
+// - ShortAnswer: textEntryInteraction
+// - Essay: extendedTextInteraction
+// - Ordering: orderInteraction
+// - Matching: matchInteraction
+// - Hotspot: hotspotInteraction
+// - DragDrop, DiagramLabeling: graphicGapMatchInteraction
+// Questions that cannot be represented, such as drawing or code execution
+// questions, are left out and reported back to the caller.
+
+mod document;
+mod reader;
+mod writer;
+#[cfg(test)]
+mod tests;
+
+pub use reader::read_package;
+pub use writer::write_package;
+
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+
+use super::models::Quiz;
+
+/// Errors reading or writing a QTI package
+#[derive(Debug, Error)]
+pub enum QtiError {
+    #[error("Invalid package archive: {0}")]
+    Archive(#[from] zip::result::ZipError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid XML in {0}: {1}")]
+    Xml(String, String),
+
+    #[error("Invalid package: {0}")]
+    InvalidPackage(String),
+
+    #[error("Unsupported QTI version: {0}")]
+    UnsupportedVersion(String),
+}
+
+/// QTI version of a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QtiVersion {
+    V2_1,
+    V3_0,
+}
+
+impl QtiVersion {
+    fn namespace(self) -> &'static str {
+        match self {
+            QtiVersion::V2_1 => "http://www.imsglobal.org/xsd/imsqti_v2p1",
+            QtiVersion::V3_0 => "http://www.imsglobal.org/xsd/imsqtiasi_v3p0",
+        }
+    }
+
+    fn manifest_namespace(self) -> &'static str {
+        match self {
+            QtiVersion::V2_1 => "http://www.imsglobal.org/xsd/imscp_v1p1",
+            QtiVersion::V3_0 => "http://www.imsglobal.org/xsd/qti/qtiv3p0/imscp_v1p1",
+        }
+    }
+
+    fn item_resource_type(self) -> &'static str {
+        match self {
+            QtiVersion::V2_1 => "imsqti_item_xmlv2p1",
+            QtiVersion::V3_0 => "imsqti_item_xmlv3p0",
+        }
+    }
+
+    fn test_resource_type(self) -> &'static str {
+        match self {
+            QtiVersion::V2_1 => "imsqti_test_xmlv2p1",
+            QtiVersion::V3_0 => "imsqti_test_xmlv3p0",
+        }
+    }
+
+    fn match_correct_template(self) -> &'static str {
+        match self {
+            QtiVersion::V2_1 => "http://www.imsglobal.org/question/qti_v2p1/rptemplates/match_correct",
+            QtiVersion::V3_0 => "https://purl.imsglobal.org/spec/qti/v3p0/rptemplates/match_correct",
+        }
+    }
+}
+
+/// A question left out of an export or import
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedQuestion {
+    /// Item identifier
+    pub identifier: String,
+
+    /// Question text or item title
+    pub title: String,
+
+    /// Why the question could not be represented
+    pub reason: String,
+}
+
+/// A written QTI package
+#[derive(Debug, Clone)]
+pub struct QtiPackage {
+    /// Zip archive
+    pub data: Vec<u8>,
+
+    /// Questions that were left out
+    pub skipped: Vec<SkippedQuestion>,
+}
+
+/// A quiz read from a QTI package
+#[derive(Debug, Clone)]
+pub struct QtiImport {
+    pub quiz: Quiz,
+
+    /// Version of the package
+    pub version: QtiVersion,
+
+    /// Items that could not be imported
+    pub skipped: Vec<SkippedQuestion>,
+}
@@ -0,0 +1,334 @@
+// Reading quizzes from QTI packages
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use uuid::Uuid;
+use zip::ZipArchive;
+
+use super::document::{parse, Element};
+use super::{QtiError, QtiImport, QtiVersion, SkippedQuestion};
+use crate::quiz::models::{
+    Answer, AnswerType, Choice, DragDropContent, DragItem, DropZone, Hotspot, HotspotContent, Question,
+    QuestionContent, Quiz,
+};
+
+/// Read a quiz from a QTI 2.x or 3.0 package
+///
+/// Items are imported in the order of the package's assessment test, or in
+/// manifest order when there is none. Items that cannot be represented as a
+/// question are listed in [`QtiImport::skipped`].
+pub fn read_package(data: &[u8]) -> Result<QtiImport, QtiError> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+    let manifest = parse("imsmanifest.xml", &read_file(&mut archive, "imsmanifest.xml")?)?;
+
+    let resources: Vec<&Element> = manifest.find("resources")
+        .map(|resources| resources.find_all("resource").collect())
+        .unwrap_or_default();
+    let version = if resources.iter().any(|resource| resource_type(resource).ends_with("xmlv3p0")) {
+        QtiVersion::V3_0
+    } else if resources.iter().any(|resource| resource_type(resource).starts_with("imsqti_item_xmlv2p")) {
+        QtiVersion::V2_1
+    } else if let Some(resource) = resources.iter().find(|resource| resource_type(resource).starts_with("imsqti")) {
+        return Err(QtiError::UnsupportedVersion(resource_type(resource)));
+    } else {
+        return Err(QtiError::InvalidPackage("the manifest lists no QTI items".to_string()));
+    };
+
+    let mut item_paths: Vec<String> = resources.iter()
+        .filter(|resource| resource_type(resource).starts_with("imsqti_item_xml"))
+        .filter_map(|resource| resource.get("href").map(str::to_string))
+        .collect();
+
+    let mut title = "Imported QTI Quiz".to_string();
+    let test_path = resources.iter()
+        .find(|resource| resource_type(resource).starts_with("imsqti_test_xml"))
+        .and_then(|resource| resource.get("href"));
+    if let Some(test_path) = test_path {
+        let test = parse(test_path, &read_file(&mut archive, test_path)?)?;
+        if let Some(test_title) = test.get("title").filter(|test_title| !test_title.is_empty()) {
+            title = test_title.to_string();
+        }
+
+        let referenced: Vec<String> = test.descendants().into_iter()
+            .filter(|element| element.name == "assessmentItemRef")
+            .filter_map(|element| element.get("href"))
+            .map(|href| resolve(test_path, href))
+            .collect();
+        if !referenced.is_empty() {
+            item_paths = referenced;
+        }
+    }
+
+    let mut quiz = Quiz::new(title, None);
+    let mut skipped = Vec::new();
+
+    for path in item_paths {
+        let item = parse(&path, &read_file(&mut archive, &path)?)?;
+        match question_from_item(&item, quiz.id) {
+            Ok(question) => quiz.add_question(question),
+            Err(reason) => skipped.push(SkippedQuestion {
+                identifier: item.get("identifier").unwrap_or(path.as_str()).to_string(),
+                title: item.get("title").unwrap_or_default().to_string(),
+                reason,
+            }),
+        }
+    }
+
+    Ok(QtiImport { quiz, version, skipped })
+}
+
+fn resource_type(resource: &Element) -> String {
+    resource.get("type").unwrap_or_default().to_string()
+}
+
+fn read_file(archive: &mut ZipArchive<Cursor<&[u8]>>, path: &str) -> Result<String, QtiError> {
+    let mut file = archive.by_name(path)
+        .map_err(|_| QtiError::InvalidPackage(format!("{} is missing", path)))?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Resolve an href relative to the file that contains it
+fn resolve(base: &str, href: &str) -> String {
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop();
+    for part in href.split('/') {
+        match part {
+            "." | "" => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn string_id(identifier: &str, prefix: &str) -> String {
+    identifier.strip_prefix(prefix).unwrap_or(identifier).to_string()
+}
+
+/// Choice IDs by identifier, stable within an item
+///
+/// IDs are always new, even for identifiers we wrote ourselves, so that a
+/// package imported twice does not yield questions sharing choices.
+#[derive(Default)]
+struct ChoiceIds(HashMap<String, Uuid>);
+
+impl ChoiceIds {
+    fn get(&mut self, identifier: &str) -> Uuid {
+        *self.0.entry(identifier.to_string()).or_insert_with(Uuid::new_v4)
+    }
+
+    fn choice(&mut self, element: &Element) -> Choice {
+        Choice {
+            id: self.get(element.get("identifier").unwrap_or_default()),
+            text: element.text_content(&|_| false),
+            rich_text: None,
+            image_url: None,
+            feedback: None,
+        }
+    }
+}
+
+fn is_interaction(element: &Element) -> bool {
+    element.name.ends_with("Interaction")
+}
+
+/// `rect` coordinates as position and size
+fn parse_rect(element: &Element) -> Result<(f32, f32, f32, f32), String> {
+    if element.get("shape") != Some("rect") {
+        return Err("only rectangular hotspots are supported".to_string());
+    }
+    let coords: Vec<f32> = element.get("coords").unwrap_or_default()
+        .split(',')
+        .map(|value| value.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| "invalid hotspot coordinates".to_string())?;
+    match coords.as_slice() {
+        [left, top, right, bottom] => Ok((*left, *top, right - left, bottom - top)),
+        _ => Err("invalid hotspot coordinates".to_string()),
+    }
+}
+
+/// Split a `directedPair` value
+fn pair(value: &str) -> Result<(&str, &str), String> {
+    let mut parts = value.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(source), Some(target), None) => Ok((source, target)),
+        _ => Err(format!("invalid pair \"{}\"", value)),
+    }
+}
+
+fn question_from_item(item: &Element, quiz_id: Uuid) -> Result<Question, String> {
+    let body = item.find("itemBody").ok_or_else(|| "the item has no body".to_string())?;
+    let interactions: Vec<&Element> = body.descendants().into_iter().filter(|element| is_interaction(element)).collect();
+    let interaction = match interactions.as_slice() {
+        [interaction] => *interaction,
+        [] => return Err("the item has no interaction".to_string()),
+        _ => return Err("items with more than one interaction are not supported".to_string()),
+    };
+
+    let mut text = body.text_content(&is_interaction);
+    if let Some(prompt) = interaction.find("prompt") {
+        text = format!("{} {}", text, prompt.text_content(&|_| false)).trim().to_string();
+    }
+    let image_url = body.descendants().into_iter()
+        .find(|element| element.name == "img")
+        .and_then(|image| image.get("src"))
+        .map(str::to_string);
+
+    let response_identifier = interaction.get("responseIdentifier").unwrap_or_default();
+    let declaration = item.find_all("responseDeclaration")
+        .find(|declaration| declaration.get("identifier") == Some(response_identifier));
+    let correct: Vec<String> = declaration
+        .and_then(|declaration| declaration.find("correctResponse"))
+        .map(|correct| correct.find_all("value").map(|value| value.text_content(&|_| false)).collect())
+        .unwrap_or_default();
+
+    let content = QuestionContent {
+        text,
+        rich_text: None,
+        image_url,
+        audio_url: None,
+        drag_drop_content: None,
+        hotspot_content: None,
+        drawing_content: None,
+        code_execution_content: None,
+        math_equation_content: None,
+        timeline_content: None,
+        diagram_labeling_content: None,
+        short_answer_content: None,
+    };
+
+    let mut ids = ChoiceIds::default();
+    let mut question = match interaction.name.as_str() {
+        "choiceInteraction" => {
+            // 0 lets the candidate select any number of choices
+            let max_choices = interaction.get("maxChoices").and_then(|max| max.parse::<u32>().ok()).unwrap_or(1);
+            let choices: Vec<Choice> = interaction.find_all("simpleChoice").map(|choice| ids.choice(choice)).collect();
+            if correct.is_empty() {
+                return Err("the item has no correct response".to_string());
+            }
+
+            let mut question = if max_choices != 1 || correct.len() > 1 {
+                let mut question = Question::new(quiz_id, content, AnswerType::MultipleChoice);
+                question.correct_answer = Answer::Choices(correct.iter().map(|value| ids.get(value)).collect());
+                question
+            } else {
+                let true_false = choices.len() == 2 && choices.iter()
+                    .all(|choice| matches!(choice.text.to_lowercase().as_str(), "true" | "false"));
+                let mut question = Question::new(quiz_id, content, if true_false { AnswerType::TrueFalse } else { AnswerType::MultipleChoice });
+                question.correct_answer = Answer::Choice(ids.get(&correct[0]));
+                question
+            };
+            question.choices = choices;
+            question
+        }
+        "textEntryInteraction" | "extendedTextInteraction" => {
+            let answer_type = if interaction.name == "textEntryInteraction" { AnswerType::ShortAnswer } else { AnswerType::Essay };
+            let mut question = Question::new(quiz_id, content, answer_type);
+            question.correct_answer = Answer::Text(correct.first().cloned().unwrap_or_default());
+            question
+        }
+        "orderInteraction" => {
+            let mut question = Question::new(quiz_id, content, AnswerType::Ordering);
+            question.choices = interaction.find_all("simpleChoice").map(|choice| ids.choice(choice)).collect();
+            question.correct_answer = Answer::Ordering(correct.iter().map(|value| ids.get(value)).collect());
+            question
+        }
+        "matchInteraction" => {
+            let mut question = Question::new(quiz_id, content, AnswerType::Matching);
+            question.choices = interaction.find_all("simpleMatchSet")
+                .flat_map(|set| set.find_all("simpleAssociableChoice"))
+                .map(|choice| ids.choice(choice))
+                .collect();
+            let pairs = correct.iter()
+                .map(|value| pair(value).map(|(source, target)| (ids.get(source), ids.get(target))))
+                .collect::<Result<Vec<_>, _>>()?;
+            question.correct_answer = Answer::Matching(pairs);
+            question
+        }
+        "hotspotInteraction" => {
+            let image_url = interaction.find("object")
+                .and_then(|object| object.get("data"))
+                .ok_or_else(|| "the hotspot interaction has no image".to_string())?;
+
+            let mut hotspots = Vec::new();
+            let mut areas = Vec::new();
+            for choice in interaction.find_all("hotspotChoice") {
+                let identifier = choice.get("identifier").unwrap_or_default();
+                let (x, y, width, height) = parse_rect(choice)?;
+                if correct.iter().any(|value| value == identifier) {
+                    areas.push((x, y, width, height));
+                }
+                hotspots.push(Hotspot {
+                    id: string_id(identifier, "H-"),
+                    x,
+                    y,
+                    width,
+                    height,
+                    label: choice.get("hotspotLabel").map(str::to_string),
+                });
+            }
+
+            let mut question = Question::new(quiz_id, content, AnswerType::Hotspot);
+            question.content.hotspot_content = Some(HotspotContent { image_url: image_url.to_string(), hotspots });
+            question.correct_answer = Answer::Hotspot(areas);
+            question
+        }
+        "graphicGapMatchInteraction" => {
+            let background = interaction.find("object").and_then(|object| object.get("data")).map(str::to_string);
+
+            let mut drag_items = Vec::new();
+            for gap in interaction.elements().filter(|element| matches!(element.name.as_str(), "gapImg" | "gapText")) {
+                let image_url = gap.find("object").and_then(|object| object.get("data")).map(str::to_string);
+                let text = match &image_url {
+                    Some(_) => gap.get("objectLabel").unwrap_or_default().to_string(),
+                    None => gap.text_content(&|_| false),
+                };
+                drag_items.push(DragItem {
+                    id: string_id(gap.get("identifier").unwrap_or_default(), "D-"),
+                    text,
+                    rich_text: None,
+                    image_url,
+                });
+            }
+
+            let drop_zones = interaction.find_all("associableHotspot")
+                .map(|zone| {
+                    let (x, y, width, height) = parse_rect(zone)?;
+                    Ok(DropZone {
+                        id: string_id(zone.get("identifier").unwrap_or_default(), "Z-"),
+                        x,
+                        y,
+                        width,
+                        height,
+                        label: zone.get("hotspotLabel").map(str::to_string),
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+
+            let placements = correct.iter()
+                .map(|value| pair(value).map(|(item, zone)| (string_id(item, "D-"), string_id(zone, "Z-"))))
+                .collect::<Result<HashMap<_, _>, _>>()?;
+
+            let mut question = Question::new(quiz_id, content, AnswerType::DragDrop);
+            question.content.drag_drop_content = Some(DragDropContent {
+                background_image_url: background,
+                drag_items,
+                drop_zones,
+            });
+            question.correct_answer = Answer::DragDrop(placements);
+            question
+        }
+        other => return Err(format!("{} is not supported", other)),
+    };
+
+    question.explanation = item.find_all("modalFeedback")
+        .map(|feedback| feedback.text_content(&|_| false))
+        .find(|explanation| !explanation.is_empty());
+
+    Ok(question)
+}
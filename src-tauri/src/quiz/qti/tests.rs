@@ -0,0 +1,389 @@
+use super::*;
+use crate::quiz::models::{
+    Answer, AnswerType, DiagramLabel, DiagramLabelPosition, DiagramLabelingContent, DragDropContent, DragItem,
+    DropZone, Hotspot, HotspotContent, Question, QuestionContent,
+};
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use uuid::Uuid;
+
+fn content(text: &str) -> QuestionContent {
+    QuestionContent {
+        text: text.to_string(),
+        rich_text: None,
+        image_url: None,
+        audio_url: None,
+        drag_drop_content: None,
+        hotspot_content: None,
+        drawing_content: None,
+        code_execution_content: None,
+        math_equation_content: None,
+        timeline_content: None,
+        diagram_labeling_content: None,
+        short_answer_content: None,
+    }
+}
+
+fn sample_quiz() -> Quiz {
+    let mut quiz = Quiz::new("Geography".to_string(), None);
+
+    let mut question = Question::new(quiz.id, content("What is the capital of France?"), AnswerType::MultipleChoice);
+    question.add_choice("London".to_string());
+    let paris = question.add_choice("Paris".to_string());
+    question.correct_answer = Answer::Choice(paris);
+    question.explanation = Some("Paris has been the capital since 987.".to_string());
+    quiz.add_question(question);
+
+    let mut question = Question::new(quiz.id, content("The Nile is in Africa."), AnswerType::TrueFalse);
+    let yes = question.add_choice("True".to_string());
+    question.add_choice("False".to_string());
+    question.correct_answer = Answer::Choice(yes);
+    quiz.add_question(question);
+
+    let mut question = Question::new(quiz.id, content("Name the largest ocean."), AnswerType::ShortAnswer);
+    question.correct_answer = Answer::Text("Pacific".to_string());
+    quiz.add_question(question);
+
+    let mut question = Question::new(quiz.id, content("Describe plate tectonics."), AnswerType::Essay);
+    question.correct_answer = Answer::Text("Plates move on the mantle.".to_string());
+    quiz.add_question(question);
+
+    let mut question = Question::new(quiz.id, content("Order by size."), AnswerType::Ordering);
+    let small = question.add_choice("Andorra".to_string());
+    let large = question.add_choice("Russia".to_string());
+    question.correct_answer = Answer::Ordering(vec![large, small]);
+    quiz.add_question(question);
+
+    let mut question = Question::new(quiz.id, content("Match countries and capitals."), AnswerType::Matching);
+    let france = question.add_choice("France".to_string());
+    let spain = question.add_choice("Spain".to_string());
+    let paris = question.add_choice("Paris".to_string());
+    let madrid = question.add_choice("Madrid".to_string());
+    question.correct_answer = Answer::Matching(vec![(france, paris), (spain, madrid)]);
+    quiz.add_question(question);
+
+    let mut question = Question::new(quiz.id, content("Click on Australia."), AnswerType::Hotspot);
+    question.content.hotspot_content = Some(HotspotContent {
+        image_url: "world.png".to_string(),
+        hotspots: vec![
+            Hotspot { id: "australia".to_string(), x: 300.0, y: 200.0, width: 50.0, height: 40.0, label: Some("Australia".to_string()) },
+            Hotspot { id: "brazil".to_string(), x: 100.0, y: 150.0, width: 40.0, height: 40.0, label: None },
+        ],
+    });
+    question.correct_answer = Answer::Hotspot(vec![(300.0, 200.0, 50.0, 40.0)]);
+    quiz.add_question(question);
+
+    let mut question = Question::new(quiz.id, content("Place the flag."), AnswerType::DragDrop);
+    question.content.drag_drop_content = Some(DragDropContent {
+        background_image_url: Some("map.png".to_string()),
+        drag_items: vec![DragItem { id: "flag".to_string(), text: "Flag".to_string(), rich_text: None, image_url: Some("flag.png".to_string()) }],
+        drop_zones: vec![DropZone { id: "japan".to_string(), x: 10.0, y: 20.0, width: 30.0, height: 30.0, label: None }],
+    });
+    question.correct_answer = Answer::DragDrop(HashMap::from([("flag".to_string(), "japan".to_string())]));
+    quiz.add_question(question);
+
+    let mut question = Question::new(quiz.id, content("Which are islands?"), AnswerType::MultipleChoice);
+    let iceland = question.add_choice("Iceland".to_string());
+    question.add_choice("Austria".to_string());
+    let malta = question.add_choice("Malta".to_string());
+    question.correct_answer = Answer::Choices(vec![iceland, malta]);
+    question.explanation = Some("Austria is landlocked.".to_string());
+    quiz.add_question(question);
+
+    quiz
+}
+
+/// The correct answer with choice IDs replaced by the choices' positions, as
+/// imported choices get new IDs
+fn positional_answer(question: &Question) -> Answer {
+    let position = |id: &Uuid| {
+        let index = question.choices.iter().position(|choice| &choice.id == id).unwrap();
+        Uuid::from_u128(index as u128)
+    };
+    match &question.correct_answer {
+        Answer::Choice(id) => Answer::Choice(position(id)),
+        Answer::Choices(ids) => Answer::Choices(ids.iter().map(position).collect()),
+        Answer::Ordering(ids) => Answer::Ordering(ids.iter().map(position).collect()),
+        Answer::Matching(pairs) => Answer::Matching(pairs.iter().map(|(a, b)| (position(a), position(b))).collect()),
+        answer => answer.clone(),
+    }
+}
+
+fn diagram_question(quiz_id: Uuid) -> Question {
+    let mut question = Question::new(quiz_id, content("Label the volcano."), AnswerType::DiagramLabeling);
+    question.content.diagram_labeling_content = Some(DiagramLabelingContent {
+        diagram_image_url: "volcano.svg".to_string(),
+        labels: vec![DiagramLabel { id: "crater".to_string(), text: "Crater".to_string() }],
+        label_positions: vec![DiagramLabelPosition { id: "top".to_string(), x: 50.0, y: 0.0, width: 20.0, height: 10.0 }],
+    });
+    question.correct_answer = Answer::DiagramLabeling(HashMap::from([("crater".to_string(), "top".to_string())]));
+    question
+}
+
+fn read_entry(data: &[u8], name: &str) -> String {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).unwrap();
+    let mut content = String::new();
+    archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+    content
+}
+
+fn package(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in files {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+mod round_trip {
+    use super::*;
+
+    fn assert_round_trip(version: QtiVersion) {
+        let quiz = sample_quiz();
+        let package = write_package(&quiz, version).unwrap();
+        assert!(package.skipped.is_empty(), "{:?}", package.skipped);
+
+        let import = read_package(&package.data).unwrap();
+        assert_eq!(import.version, version);
+        assert!(import.skipped.is_empty(), "{:?}", import.skipped);
+        assert_eq!(import.quiz.title, "Geography");
+        assert_eq!(import.quiz.questions.len(), quiz.questions.len());
+
+        for (original, imported) in quiz.questions.iter().zip(&import.quiz.questions) {
+            assert_ne!(imported.id, original.id);
+            assert_eq!(imported.answer_type, original.answer_type);
+            assert_eq!(imported.content.text, original.content.text);
+            assert_eq!(positional_answer(imported), positional_answer(original));
+            assert_eq!(imported.explanation, original.explanation);
+
+            let texts = |question: &Question| question.choices.iter().map(|c| c.text.clone()).collect::<Vec<_>>();
+            assert_eq!(texts(imported), texts(original));
+        }
+
+        let hotspot = &import.quiz.questions[6].content.hotspot_content.as_ref().unwrap();
+        assert_eq!(hotspot.image_url, "world.png");
+        assert_eq!(hotspot.hotspots.len(), 2);
+        assert_eq!(hotspot.hotspots[0].label.as_deref(), Some("Australia"));
+
+        let drag_drop = import.quiz.questions[7].content.drag_drop_content.as_ref().unwrap();
+        assert_eq!(drag_drop.background_image_url.as_deref(), Some("map.png"));
+        assert_eq!(drag_drop.drag_items[0].image_url.as_deref(), Some("flag.png"));
+        assert_eq!(drag_drop.drop_zones[0].id, "japan");
+    }
+
+    #[test]
+    fn test_qti_2_1_round_trip() {
+        assert_round_trip(QtiVersion::V2_1);
+    }
+
+    #[test]
+    fn test_qti_3_0_round_trip() {
+        assert_round_trip(QtiVersion::V3_0);
+    }
+
+    #[test]
+    fn test_reimport_gets_new_ids() {
+        let quiz = sample_quiz();
+        let package = write_package(&quiz, QtiVersion::V2_1).unwrap();
+
+        let first = read_package(&package.data).unwrap().quiz;
+        let second = read_package(&package.data).unwrap().quiz;
+
+        for (first, second) in first.questions.iter().zip(&second.questions) {
+            assert_ne!(first.id, second.id);
+            for (a, b) in first.choices.iter().zip(&second.choices) {
+                assert_ne!(a.id, b.id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagram_labeling_imports_as_drag_drop() {
+        let mut quiz = Quiz::new("Volcanoes".to_string(), None);
+        quiz.add_question(diagram_question(quiz.id));
+
+        let package = write_package(&quiz, QtiVersion::V3_0).unwrap();
+        let import = read_package(&package.data).unwrap();
+
+        let question = &import.quiz.questions[0];
+        assert_eq!(question.answer_type, AnswerType::DragDrop);
+        assert_eq!(question.correct_answer, Answer::DragDrop(HashMap::from([("crater".to_string(), "top".to_string())])));
+        assert_eq!(question.content.drag_drop_content.as_ref().unwrap().drag_items[0].text, "Crater");
+    }
+}
+
+mod writer {
+    use super::*;
+
+    #[test]
+    fn test_package_layout() {
+        let quiz = sample_quiz();
+        let package = write_package(&quiz, QtiVersion::V2_1).unwrap();
+
+        let manifest = read_entry(&package.data, "imsmanifest.xml");
+        assert!(manifest.contains("imsqti_test_xmlv2p1"));
+        assert!(manifest.contains("imsqti_item_xmlv2p1"));
+
+        let test = read_entry(&package.data, "assessment.xml");
+        assert!(test.contains("<assessmentItemRef"));
+
+        let item = read_entry(&package.data, &format!("items/Q-{}.xml", quiz.questions[0].id));
+        assert!(item.contains("<choiceInteraction responseIdentifier=\"RESPONSE\""));
+        assert!(item.contains("<modalFeedback"));
+    }
+
+    #[test]
+    fn test_explanation_sets_feedback_outcome() {
+        let quiz = sample_quiz();
+        let package = write_package(&quiz, QtiVersion::V2_1).unwrap();
+
+        let item = read_entry(&package.data, &format!("items/Q-{}.xml", quiz.questions[0].id));
+        assert!(item.contains("<setOutcomeValue identifier=\"FEEDBACK\""));
+        assert!(item.contains("<setOutcomeValue identifier=\"SCORE\""));
+        assert!(!item.contains("template="));
+
+        // Without an explanation the standard template is enough
+        let item = read_entry(&package.data, &format!("items/Q-{}.xml", quiz.questions[1].id));
+        assert!(item.contains("template=\"http://www.imsglobal.org/question/qti_v2p1/rptemplates/match_correct\""));
+    }
+
+    #[test]
+    fn test_multi_select_choices() {
+        let quiz = sample_quiz();
+        let package = write_package(&quiz, QtiVersion::V2_1).unwrap();
+
+        let item = read_entry(&package.data, &format!("items/Q-{}.xml", quiz.questions[8].id));
+        assert!(item.contains("cardinality=\"multiple\""));
+        assert!(item.contains("maxChoices=\"0\""));
+        assert_eq!(item.matches("<value>").count(), 2);
+    }
+
+    #[test]
+    fn test_qti_3_0_names() {
+        let quiz = sample_quiz();
+        let package = write_package(&quiz, QtiVersion::V3_0).unwrap();
+
+        let manifest = read_entry(&package.data, "imsmanifest.xml");
+        assert!(manifest.contains("imsqti_item_xmlv3p0"));
+
+        let item = read_entry(&package.data, &format!("items/Q-{}.xml", quiz.questions[0].id));
+        assert!(item.contains("<qti-assessment-item"));
+        assert!(item.contains("<qti-choice-interaction response-identifier=\"RESPONSE\""));
+        assert!(item.contains("<qti-simple-choice"));
+        assert!(item.contains("<p>What is the capital of France?</p>"));
+        assert!(!item.contains("responseIdentifier"));
+    }
+
+    #[test]
+    fn test_unrepresentable_questions_are_skipped() {
+        let mut quiz = sample_quiz();
+        let drawing = Question::new(quiz.id, content("Draw a map."), AnswerType::Drawing);
+        let drawing_id = drawing.id;
+        quiz.add_question(drawing);
+
+        let package = write_package(&quiz, QtiVersion::V3_0).unwrap();
+
+        assert_eq!(package.skipped.len(), 1);
+        assert_eq!(package.skipped[0].identifier, format!("Q-{}", drawing_id));
+        assert_eq!(package.skipped[0].title, "Draw a map.");
+        assert_eq!(read_package(&package.data).unwrap().quiz.questions.len(), quiz.questions.len() - 1);
+    }
+
+    #[test]
+    fn test_text_labels_need_qti_3_0() {
+        let mut quiz = Quiz::new("Volcanoes".to_string(), None);
+        quiz.add_question(diagram_question(quiz.id));
+
+        assert_eq!(write_package(&quiz, QtiVersion::V2_1).unwrap().skipped.len(), 1);
+        assert!(write_package(&quiz, QtiVersion::V3_0).unwrap().skipped.is_empty());
+    }
+
+    #[test]
+    fn test_mismatched_answer_is_skipped() {
+        let mut quiz = Quiz::new("Broken".to_string(), None);
+        let mut question = Question::new(quiz.id, content("Pick one."), AnswerType::MultipleChoice);
+        question.correct_answer = Answer::Text("A".to_string());
+        quiz.add_question(question);
+
+        let package = write_package(&quiz, QtiVersion::V2_1).unwrap();
+        assert_eq!(package.skipped.len(), 1);
+    }
+}
+
+mod reader {
+    use super::*;
+
+    const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest xmlns="http://www.imsglobal.org/xsd/qti/qtiv3p0/imscp_v1p1" identifier="M-1">
+  <resources>
+    <resource identifier="capital" type="imsqti_item_xmlv3p0" href="capital.xml"/>
+    <resource identifier="colors" type="imsqti_item_xmlv3p0" href="colors.xml"/>
+  </resources>
+</manifest>"#;
+
+    const CAPITAL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<qti-assessment-item xmlns="http://www.imsglobal.org/xsd/imsqtiasi_v3p0" identifier="capital" title="Capital">
+  <qti-response-declaration identifier="RESPONSE" cardinality="single" base-type="identifier">
+    <qti-correct-response><qti-value>B</qti-value></qti-correct-response>
+  </qti-response-declaration>
+  <qti-item-body>
+    <qti-choice-interaction response-identifier="RESPONSE" max-choices="1">
+      <qti-prompt>What is the capital of <em>Italy</em>?</qti-prompt>
+      <qti-simple-choice identifier="A">Milan</qti-simple-choice>
+      <qti-simple-choice identifier="B">Rome</qti-simple-choice>
+    </qti-choice-interaction>
+  </qti-item-body>
+</qti-assessment-item>"#;
+
+    const COLORS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<qti-assessment-item xmlns="http://www.imsglobal.org/xsd/imsqtiasi_v3p0" identifier="colors" title="Colors">
+  <qti-response-declaration identifier="RESPONSE" cardinality="multiple" base-type="identifier">
+    <qti-correct-response><qti-value>A</qti-value><qti-value>B</qti-value></qti-correct-response>
+  </qti-response-declaration>
+  <qti-item-body>
+    <qti-choice-interaction response-identifier="RESPONSE" max-choices="0">
+      <qti-simple-choice identifier="A">Red</qti-simple-choice>
+      <qti-simple-choice identifier="B">Blue</qti-simple-choice>
+    </qti-choice-interaction>
+  </qti-item-body>
+</qti-assessment-item>"#;
+
+    #[test]
+    fn test_import_qti_3_0_package() {
+        let data = package(&[("imsmanifest.xml", MANIFEST), ("capital.xml", CAPITAL), ("colors.xml", COLORS)]);
+        let import = read_package(&data).unwrap();
+
+        assert_eq!(import.version, QtiVersion::V3_0);
+        assert_eq!(import.quiz.title, "Imported QTI Quiz");
+        assert_eq!(import.quiz.questions.len(), 2);
+        assert!(import.skipped.is_empty());
+
+        let question = &import.quiz.questions[0];
+        assert_eq!(question.answer_type, AnswerType::MultipleChoice);
+        assert_eq!(question.content.text, "What is the capital of Italy?");
+        assert_eq!(question.choices.len(), 2);
+        assert_eq!(question.correct_answer, Answer::Choice(question.choices[1].id));
+
+        let question = &import.quiz.questions[1];
+        assert_eq!(question.answer_type, AnswerType::MultipleChoice);
+        assert_eq!(question.correct_answer, Answer::Choices(vec![question.choices[0].id, question.choices[1].id]));
+    }
+
+    #[test]
+    fn test_qti_1_2_is_rejected() {
+        let manifest = r#"<manifest identifier="M-1"><resources>
+            <resource identifier="quiz" type="imsqti_xmlv1p2" href="quiz.xml"/>
+        </resources></manifest>"#;
+        let data = package(&[("imsmanifest.xml", manifest)]);
+
+        assert!(matches!(read_package(&data), Err(QtiError::UnsupportedVersion(_))));
+    }
+
+    #[test]
+    fn test_missing_manifest() {
+        let data = package(&[("capital.xml", CAPITAL)]);
+
+        assert!(matches!(read_package(&data), Err(QtiError::InvalidPackage(_))));
+    }
+}
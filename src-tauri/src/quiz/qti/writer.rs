@@ -0,0 +1,513 @@
+// Writing quizzes as QTI packages
+
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use super::document::Element;
+use super::{QtiError, QtiPackage, QtiVersion, SkippedQuestion};
+use crate::quiz::models::{Answer, AnswerType, Choice, Question, Quiz};
+
+/// Identifier of the response variable of every item
+const RESPONSE: &str = "RESPONSE";
+
+/// Write a quiz as a QTI package
+///
+/// Questions that QTI cannot represent are left out of the package and
+/// listed in [`QtiPackage::skipped`].
+pub fn write_package(quiz: &Quiz, version: QtiVersion) -> Result<QtiPackage, QtiError> {
+    let mut items = Vec::new();
+    let mut skipped = Vec::new();
+
+    for question in &quiz.questions {
+        let identifier = item_identifier(question);
+        match assessment_item(question, &identifier, version) {
+            Ok(item) => items.push((identifier, item)),
+            Err(reason) => skipped.push(SkippedQuestion {
+                identifier,
+                title: question.content.text.clone(),
+                reason,
+            }),
+        }
+    }
+
+    let test_identifier = format!("T-{}", quiz.id);
+    let test = Element::new("assessmentTest")
+        .attr("xmlns", version.namespace())
+        .attr("identifier", &test_identifier)
+        .attr("title", &quiz.title)
+        .child(Element::new("testPart")
+            .attr("identifier", "PART-1")
+            .attr("navigationMode", "nonlinear")
+            .attr("submissionMode", "simultaneous")
+            .child(Element::new("assessmentSection")
+                .attr("identifier", "SECTION-1")
+                .attr("title", &quiz.title)
+                .attr("visible", "true")
+                .children(items.iter().map(|(identifier, _)| {
+                    Element::new("assessmentItemRef")
+                        .attr("identifier", identifier)
+                        .attr("href", item_href(identifier))
+                }))));
+
+    let manifest = Element::new("manifest")
+        .attr("xmlns", version.manifest_namespace())
+        .attr("identifier", format!("M-{}", quiz.id))
+        .child(Element::new("metadata")
+            .child(Element::new("schema").text("QTI Package"))
+            .child(Element::new("schemaversion").text(match version {
+                QtiVersion::V2_1 => "2.1",
+                QtiVersion::V3_0 => "3.0.0",
+            })))
+        .child(Element::new("organizations"))
+        .child(Element::new("resources")
+            .child(Element::new("resource")
+                .attr("identifier", &test_identifier)
+                .attr("type", version.test_resource_type())
+                .attr("href", "assessment.xml")
+                .child(Element::new("file").attr("href", "assessment.xml"))
+                .children(items.iter().map(|(identifier, _)| {
+                    Element::new("dependency").attr("identifierref", identifier)
+                })))
+            .children(items.iter().map(|(identifier, _)| {
+                Element::new("resource")
+                    .attr("identifier", identifier)
+                    .attr("type", version.item_resource_type())
+                    .attr("href", item_href(identifier))
+                    .child(Element::new("file").attr("href", item_href(identifier)))
+            })));
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+
+    zip.start_file("imsmanifest.xml", options)?;
+    zip.write_all(manifest.to_document(None).as_bytes())?;
+    zip.start_file("assessment.xml", options)?;
+    zip.write_all(test.to_document(Some(version)).as_bytes())?;
+    for (identifier, item) in &items {
+        zip.start_file(item_href(identifier), options)?;
+        zip.write_all(item.to_document(Some(version)).as_bytes())?;
+    }
+
+    Ok(QtiPackage {
+        data: zip.finish()?.into_inner(),
+        skipped,
+    })
+}
+
+fn item_identifier(question: &Question) -> String {
+    format!("Q-{}", question.id)
+}
+
+fn item_href(identifier: &str) -> String {
+    format!("items/{}.xml", identifier)
+}
+
+fn choice_identifier(choice: &Choice) -> String {
+    format!("C-{}", choice.id)
+}
+
+/// QTI identifier for one of our string IDs, which may contain characters
+/// identifiers do not allow
+fn string_identifier(prefix: &str, id: &str) -> String {
+    let id: String = id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    format!("{}-{}", prefix, id)
+}
+
+/// Interaction of a question and the declaration of its response; inline
+/// interactions are placed within a paragraph
+struct Response {
+    interaction: Element,
+    cardinality: &'static str,
+    base_type: &'static str,
+    correct: Vec<String>,
+    inline: bool,
+}
+
+fn assessment_item(question: &Question, identifier: &str, version: QtiVersion) -> Result<Element, String> {
+    let response = match question.answer_type {
+        AnswerType::MultipleChoice | AnswerType::TrueFalse => choice(question)?,
+        AnswerType::ShortAnswer => text_entry(question)?,
+        AnswerType::Essay => extended_text(question)?,
+        AnswerType::Ordering => order(question)?,
+        AnswerType::Matching => matching(question)?,
+        AnswerType::Hotspot => hotspot(question)?,
+        AnswerType::DragDrop => drag_drop(question, version)?,
+        AnswerType::DiagramLabeling => diagram_labeling(question, version)?,
+        AnswerType::Drawing | AnswerType::CodeExecution | AnswerType::MathEquation | AnswerType::Timeline => {
+            return Err(format!("{:?} questions have no QTI interaction", question.answer_type));
+        }
+    };
+
+    let mut declaration = Element::new("responseDeclaration")
+        .attr("identifier", RESPONSE)
+        .attr("cardinality", response.cardinality)
+        .attr("baseType", response.base_type);
+    if !response.correct.is_empty() {
+        declaration = declaration.child(Element::new("correctResponse")
+            .children(response.correct.iter().map(|value| Element::new("value").text(value))));
+    }
+
+    let mut body = Element::new("itemBody").child(Element::new("p").text(&question.content.text));
+    if let Some(image_url) = &question.content.image_url {
+        body = body.child(Element::new("p").child(Element::new("img").attr("src", image_url).attr("alt", "")));
+    }
+    body = if response.inline {
+        body.child(Element::new("p").child(response.interaction))
+    } else {
+        body.child(response.interaction)
+    };
+
+    let mut item = Element::new("assessmentItem")
+        .attr("xmlns", version.namespace())
+        .attr("identifier", identifier)
+        .attr("title", &question.content.text)
+        .attr("adaptive", "false")
+        .attr("timeDependent", "false")
+        .child(declaration)
+        .child(Element::new("outcomeDeclaration")
+            .attr("identifier", "SCORE")
+            .attr("cardinality", "single")
+            .attr("baseType", "float"));
+
+    if question.explanation.is_some() {
+        item = item.child(Element::new("outcomeDeclaration")
+            .attr("identifier", "FEEDBACK")
+            .attr("cardinality", "single")
+            .attr("baseType", "identifier"));
+    }
+
+    item = item.child(body);
+
+    // Essays are marked by hand. The match_correct template only sets SCORE,
+    // so items with an explanation spell it out and set FEEDBACK as well
+    let scored = !response.correct.is_empty() && question.answer_type != AnswerType::Essay;
+    if question.explanation.is_some() {
+        item = item.child(response_processing(scored));
+    } else if scored {
+        item = item.child(Element::new("responseProcessing").attr("template", version.match_correct_template()));
+    }
+
+    if let Some(explanation) = &question.explanation {
+        item = item.child(Element::new("modalFeedback")
+            .attr("outcomeIdentifier", "FEEDBACK")
+            .attr("identifier", "EXPLANATION")
+            .attr("showHide", "show")
+            .text(explanation));
+    }
+
+    Ok(item)
+}
+
+/// Response processing equivalent to match_correct, if `scored`, that also
+/// shows the explanation
+fn response_processing(scored: bool) -> Element {
+    let set_score = |value: &str| Element::new("setOutcomeValue")
+        .attr("identifier", "SCORE")
+        .child(Element::new("baseValue").attr("baseType", "float").text(value));
+
+    let mut processing = Element::new("responseProcessing");
+    if scored {
+        processing = processing.child(Element::new("responseCondition")
+            .child(Element::new("responseIf")
+                .child(Element::new("match")
+                    .child(Element::new("variable").attr("identifier", RESPONSE))
+                    .child(Element::new("correct").attr("identifier", RESPONSE)))
+                .child(set_score("1")))
+            .child(Element::new("responseElse").child(set_score("0"))));
+    }
+    processing.child(Element::new("setOutcomeValue")
+        .attr("identifier", "FEEDBACK")
+        .child(Element::new("baseValue").attr("baseType", "identifier").text("EXPLANATION")))
+}
+
+fn mismatched_answer(question: &Question) -> String {
+    format!("the correct answer does not fit a {:?} question", question.answer_type)
+}
+
+fn simple_choices(question: &Question) -> Vec<Element> {
+    question.choices.iter()
+        .map(|choice| Element::new("simpleChoice").attr("identifier", choice_identifier(choice)).text(&choice.text))
+        .collect()
+}
+
+fn choice(question: &Question) -> Result<Response, String> {
+    // Multi-select questions let the candidate pick any number of choices
+    let (correct, cardinality, max_choices) = match &question.correct_answer {
+        Answer::Choice(correct) => (std::slice::from_ref(correct), "single", 1),
+        Answer::Choices(correct) if !correct.is_empty() => (correct.as_slice(), "multiple", 0),
+        _ => return Err(mismatched_answer(question)),
+    };
+    let correct = correct.iter()
+        .map(|id| question.choices.iter()
+            .find(|choice| &choice.id == id)
+            .map(choice_identifier)
+            .ok_or_else(|| "the correct choice is missing".to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Response {
+        interaction: Element::new("choiceInteraction")
+            .attr("responseIdentifier", RESPONSE)
+            .attr("shuffle", "false")
+            .attr("maxChoices", max_choices)
+            .children(simple_choices(question)),
+        cardinality,
+        base_type: "identifier",
+        correct,
+        inline: false,
+    })
+}
+
+fn text_entry(question: &Question) -> Result<Response, String> {
+    let Answer::Text(correct) = &question.correct_answer else {
+        return Err(mismatched_answer(question));
+    };
+
+    Ok(Response {
+        interaction: Element::new("textEntryInteraction")
+            .attr("responseIdentifier", RESPONSE)
+            .attr("expectedLength", correct.chars().count().max(10)),
+        cardinality: "single",
+        base_type: "string",
+        correct: vec![correct.clone()],
+        inline: true,
+    })
+}
+
+fn extended_text(question: &Question) -> Result<Response, String> {
+    // A model answer, if any, travels as the correct response
+    let model_answer = match &question.correct_answer {
+        Answer::Text(text) if !text.is_empty() => vec![text.clone()],
+        Answer::Text(_) => Vec::new(),
+        _ => return Err(mismatched_answer(question)),
+    };
+
+    Ok(Response {
+        interaction: Element::new("extendedTextInteraction").attr("responseIdentifier", RESPONSE),
+        cardinality: "single",
+        base_type: "string",
+        correct: model_answer,
+        inline: false,
+    })
+}
+
+fn order(question: &Question) -> Result<Response, String> {
+    let Answer::Ordering(order) = &question.correct_answer else {
+        return Err(mismatched_answer(question));
+    };
+    let correct = order.iter()
+        .map(|id| question.choices.iter()
+            .find(|choice| &choice.id == id)
+            .map(choice_identifier)
+            .ok_or_else(|| "the correct order refers to a missing choice".to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Response {
+        interaction: Element::new("orderInteraction")
+            .attr("responseIdentifier", RESPONSE)
+            .attr("shuffle", "true")
+            .children(simple_choices(question)),
+        cardinality: "ordered",
+        base_type: "identifier",
+        correct,
+        inline: false,
+    })
+}
+
+fn matching(question: &Question) -> Result<Response, String> {
+    let Answer::Matching(pairs) = &question.correct_answer else {
+        return Err(mismatched_answer(question));
+    };
+    if pairs.is_empty() {
+        return Err("the question has no matching pairs".to_string());
+    }
+
+    // Choices that start a pair are matched against all other choices
+    let count = |pick: fn(&(uuid::Uuid, uuid::Uuid)) -> uuid::Uuid, id: uuid::Uuid| {
+        pairs.iter().filter(|pair| pick(pair) == id).count()
+    };
+    let (sources, targets): (Vec<&Choice>, Vec<&Choice>) = question.choices.iter()
+        .partition(|choice| count(|pair| pair.0, choice.id) > 0);
+    let associable = |choice: &Choice, matches: usize| {
+        Element::new("simpleAssociableChoice")
+            .attr("identifier", choice_identifier(choice))
+            .attr("matchMax", matches.max(1))
+            .text(&choice.text)
+    };
+
+    let correct = pairs.iter()
+        .map(|(source, target)| {
+            let known = |id: &uuid::Uuid| question.choices.iter().any(|choice| &choice.id == id);
+            if known(source) && known(target) {
+                Ok(format!("C-{} C-{}", source, target))
+            } else {
+                Err("a matching pair refers to a missing choice".to_string())
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Response {
+        interaction: Element::new("matchInteraction")
+            .attr("responseIdentifier", RESPONSE)
+            .attr("shuffle", "true")
+            .attr("maxAssociations", pairs.len())
+            .child(Element::new("simpleMatchSet")
+                .children(sources.iter().map(|choice| associable(choice, count(|pair| pair.0, choice.id)))))
+            .child(Element::new("simpleMatchSet")
+                .children(targets.iter().map(|choice| associable(choice, count(|pair| pair.1, choice.id))))),
+        cardinality: "multiple",
+        base_type: "directedPair",
+        correct,
+        inline: false,
+    })
+}
+
+/// `rect` coordinates of a rectangle given as position and size
+fn rect_coords(x: f32, y: f32, width: f32, height: f32) -> String {
+    format!("{},{},{},{}", x, y, x + width, y + height)
+}
+
+/// Image object of a graphic interaction
+fn image_object(url: &str) -> Element {
+    let extension = url.rsplit('.').next().unwrap_or_default().to_ascii_lowercase();
+    let mime_type = match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        _ => "image/png",
+    };
+
+    Element::new("object").attr("data", url).attr("type", mime_type)
+}
+
+fn hotspot(question: &Question) -> Result<Response, String> {
+    let Answer::Hotspot(areas) = &question.correct_answer else {
+        return Err(mismatched_answer(question));
+    };
+    let content = question.content.hotspot_content.as_ref()
+        .ok_or_else(|| "the question has no hotspot image".to_string())?;
+
+    let mut choices = Vec::new();
+    let mut correct = Vec::new();
+    for hotspot in &content.hotspots {
+        let identifier = string_identifier("H", &hotspot.id);
+        let rect = (hotspot.x, hotspot.y, hotspot.width, hotspot.height);
+        if areas.iter().any(|area| *area == rect) {
+            correct.push(identifier.clone());
+        }
+
+        let mut choice = Element::new("hotspotChoice")
+            .attr("identifier", identifier)
+            .attr("shape", "rect")
+            .attr("coords", rect_coords(hotspot.x, hotspot.y, hotspot.width, hotspot.height));
+        if let Some(label) = &hotspot.label {
+            choice = choice.attr("hotspotLabel", label);
+        }
+        choices.push(choice);
+    }
+
+    if correct.len() != areas.len() {
+        return Err("a correct area is not one of the hotspots".to_string());
+    }
+
+    Ok(Response {
+        interaction: Element::new("hotspotInteraction")
+            .attr("responseIdentifier", RESPONSE)
+            .attr("maxChoices", correct.len().max(1))
+            .child(image_object(&content.image_url))
+            .children(choices),
+        cardinality: if correct.len() > 1 { "multiple" } else { "single" },
+        base_type: "identifier",
+        correct,
+        inline: false,
+    })
+}
+
+/// Draggable item of a graphic gap match; QTI 2.1 only drags images
+fn gap(identifier: String, text: &str, image_url: Option<&str>, version: QtiVersion) -> Result<Element, String> {
+    match (image_url, version) {
+        (Some(image_url), _) => Ok(Element::new("gapImg")
+            .attr("identifier", identifier)
+            .attr("matchMax", 1)
+            .attr("objectLabel", text)
+            .child(image_object(image_url))),
+        (None, QtiVersion::V3_0) => Ok(Element::new("gapText")
+            .attr("identifier", identifier)
+            .attr("matchMax", 1)
+            .text(text)),
+        (None, QtiVersion::V2_1) => Err(format!("text item \"{}\" can only be dragged onto an image in QTI 3.0", text)),
+    }
+}
+
+fn graphic_gap_match(background: &str, gaps: Vec<Element>, zones: Vec<Element>, mut correct: Vec<String>) -> Response {
+    correct.sort();
+
+    Response {
+        interaction: Element::new("graphicGapMatchInteraction")
+            .attr("responseIdentifier", RESPONSE)
+            .child(image_object(background))
+            .children(gaps)
+            .children(zones),
+        cardinality: "multiple",
+        base_type: "directedPair",
+        correct,
+        inline: false,
+    }
+}
+
+fn associable_hotspot(identifier: String, x: f32, y: f32, width: f32, height: f32, label: Option<&str>) -> Element {
+    let mut hotspot = Element::new("associableHotspot")
+        .attr("identifier", identifier)
+        .attr("shape", "rect")
+        .attr("coords", rect_coords(x, y, width, height))
+        .attr("matchMax", 1);
+    if let Some(label) = label {
+        hotspot = hotspot.attr("hotspotLabel", label);
+    }
+    hotspot
+}
+
+fn drag_drop(question: &Question, version: QtiVersion) -> Result<Response, String> {
+    let Answer::DragDrop(placements) = &question.correct_answer else {
+        return Err(mismatched_answer(question));
+    };
+    let content = question.content.drag_drop_content.as_ref()
+        .ok_or_else(|| "the question has no drag and drop content".to_string())?;
+    let background = content.background_image_url.as_deref()
+        .ok_or_else(|| "graphic gap match interactions need a background image".to_string())?;
+
+    let gaps = content.drag_items.iter()
+        .map(|item| gap(string_identifier("D", &item.id), &item.text, item.image_url.as_deref(), version))
+        .collect::<Result<Vec<_>, _>>()?;
+    let zones = content.drop_zones.iter()
+        .map(|zone| associable_hotspot(string_identifier("Z", &zone.id), zone.x, zone.y, zone.width, zone.height, zone.label.as_deref()))
+        .collect();
+    let correct = placements.iter()
+        .map(|(item, zone)| format!("{} {}", string_identifier("D", item), string_identifier("Z", zone)))
+        .collect();
+
+    Ok(graphic_gap_match(background, gaps, zones, correct))
+}
+
+fn diagram_labeling(question: &Question, version: QtiVersion) -> Result<Response, String> {
+    let Answer::DiagramLabeling(placements) = &question.correct_answer else {
+        return Err(mismatched_answer(question));
+    };
+    let content = question.content.diagram_labeling_content.as_ref()
+        .ok_or_else(|| "the question has no diagram".to_string())?;
+
+    let gaps = content.labels.iter()
+        .map(|label| gap(string_identifier("D", &label.id), &label.text, None, version))
+        .collect::<Result<Vec<_>, _>>()?;
+    let zones = content.label_positions.iter()
+        .map(|position| associable_hotspot(string_identifier("Z", &position.id), position.x, position.y, position.width, position.height, None))
+        .collect();
+    let correct = placements.iter()
+        .map(|(label, position)| format!("{} {}", string_identifier("D", label), string_identifier("Z", position)))
+        .collect();
+
+    Ok(graphic_gap_match(&content.diagram_image_url, gaps, zones, correct))
+}
@@ -0,0 +1,408 @@
+// Partial-credit scoring
+//
+// Every answer is scored as a fraction of the question's credit between 0 and
+// 1, together with the parts it was scored on: choices, pairs, positions,
+// drop zones or test cases. Multi-select and hotspot answers are docked for
+// wrong picks according to the quiz's `WrongChoicePenalty`. Essays and
+// drawings are left for manual review, and math answers are graded by the
+// `math` module. Short answers are checked against the question's accepted
+// answers, each with its own match rule, credit and feedback.
+
+mod short_answer;
+
+#[cfg(test)]
+mod tests;
+
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use super::math;
+use super::models::{Answer, AnswerType, Question, TimelineEvent, WrongChoicePenalty};
+
+/// Answers whose credit is this close to 1 count as correct
+const EPSILON: f32 = 1e-4;
+
+/// Score of an answer to one question
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuestionScore {
+    /// Fraction of the question's credit earned, from 0 to 1
+    pub credit: f32,
+
+    /// Parts of the answer and the credit each earned
+    pub parts: Vec<PartScore>,
+
+    /// The answer has to be graded by hand; `credit` is 0 until it is
+    pub needs_review: bool,
+}
+
+/// A scored part of an answer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartScore {
+    /// Choice ID, drop zone ID, test case ID or similar
+    pub id: String,
+
+    /// Credit of the part, from 0 to 1
+    pub credit: f32,
+
+    /// Feedback for the learner
+    pub feedback: Option<String>,
+}
+
+impl QuestionScore {
+    fn new(credit: f32, parts: Vec<PartScore>) -> Self {
+        Self { credit: credit.clamp(0.0, 1.0), parts, needs_review: false }
+    }
+
+    fn all_or_nothing(correct: bool, part: PartScore) -> Self {
+        Self::new(if correct { 1.0 } else { 0.0 }, vec![part])
+    }
+
+    fn review() -> Self {
+        Self { credit: 0.0, parts: Vec::new(), needs_review: true }
+    }
+
+    fn mismatched() -> Self {
+        Self::new(0.0, vec![PartScore::new("answer", 0.0, Some("The answer does not fit the question".to_string()))])
+    }
+
+    /// Whether the answer earned full credit
+    pub fn is_correct(&self) -> bool {
+        !self.needs_review && self.credit >= 1.0 - EPSILON
+    }
+}
+
+impl PartScore {
+    fn new(id: impl ToString, credit: f32, feedback: Option<String>) -> Self {
+        Self { id: id.to_string(), credit, feedback }
+    }
+}
+
+/// Score an answer to a question
+pub fn score_answer(question: &Question, answer: &Answer, penalty: WrongChoicePenalty) -> QuestionScore {
+    match question.answer_type {
+        AnswerType::Essay | AnswerType::Drawing => return QuestionScore::review(),
+        AnswerType::CodeExecution => return score_code(question, answer),
+        _ => {}
+    }
+
+    match (&question.correct_answer, answer) {
+        (Answer::Choice(correct), Answer::Choice(selected)) => score_choice(question, correct, selected),
+        (Answer::Choice(correct), Answer::Text(text)) => score_choice_text(question, correct, text),
+        (Answer::Choice(correct), Answer::Choices(selected)) => score_choices(question, &[*correct], selected, penalty),
+        (Answer::Choices(correct), Answer::Choice(selected)) => score_choices(question, correct, &[*selected], penalty),
+        (Answer::Choices(correct), Answer::Choices(selected)) => score_choices(question, correct, selected, penalty),
+        (Answer::Text(correct), Answer::Text(text)) => score_text(question, correct, text),
+        (Answer::Matching(correct), Answer::Matching(pairs)) => score_matching(question, correct, pairs),
+        (Answer::Ordering(correct), Answer::Ordering(order)) => score_ordering(correct, order),
+        (Answer::DragDrop(correct), Answer::DragDrop(placements)) => score_drop_zones(question, correct, placements),
+        (Answer::DiagramLabeling(correct), Answer::DiagramLabeling(placements)) => score_drop_zones(question, correct, placements),
+        (Answer::Hotspot(correct), Answer::Hotspot(clicks)) => score_hotspots(question, correct, clicks, penalty),
+        (Answer::MathEquation(correct), Answer::MathEquation(value)) => score_math(question, correct, value),
+        (Answer::Timeline(correct), Answer::Timeline(events)) => score_timeline(correct, events),
+        _ => QuestionScore::mismatched(),
+    }
+}
+
+fn choice_feedback(question: &Question, id: &Uuid) -> Option<String> {
+    question.choices.iter()
+        .find(|choice| &choice.id == id)
+        .and_then(|choice| choice.feedback.clone())
+}
+
+fn score_choice(question: &Question, correct: &Uuid, selected: &Uuid) -> QuestionScore {
+    QuestionScore::all_or_nothing(
+        correct == selected,
+        PartScore::new(selected, if correct == selected { 1.0 } else { 0.0 }, choice_feedback(question, selected)),
+    )
+}
+
+/// True/false answers may be given as the text of the choice
+fn score_choice_text(question: &Question, correct: &Uuid, text: &str) -> QuestionScore {
+    match question.choices.iter().find(|choice| choice.text.trim().eq_ignore_ascii_case(text.trim())) {
+        Some(choice) => score_choice(question, correct, &choice.id),
+        None => QuestionScore::mismatched(),
+    }
+}
+
+/// Credit for picking `right` of `total` correct options and `wrong` others
+fn selection_credit(right: usize, wrong: usize, total: usize, penalty: WrongChoicePenalty) -> f32 {
+    if total == 0 {
+        return if wrong == 0 { 1.0 } else { 0.0 };
+    }
+
+    let earned = right as f32 / total as f32;
+    let credit = match penalty {
+        WrongChoicePenalty::None => earned,
+        WrongChoicePenalty::PerChoice => (right as f32 - wrong as f32) / total as f32,
+        WrongChoicePenalty::Fixed(cost) => earned - cost * wrong as f32,
+        WrongChoicePenalty::AllOrNothing => if right == total && wrong == 0 { 1.0 } else { 0.0 },
+    };
+    credit.clamp(0.0, 1.0)
+}
+
+fn score_choices(question: &Question, correct: &[Uuid], selected: &[Uuid], penalty: WrongChoicePenalty) -> QuestionScore {
+    let correct: HashSet<&Uuid> = correct.iter().collect();
+    let mut seen = HashSet::new();
+    let selected: Vec<&Uuid> = selected.iter().filter(|id| seen.insert(**id)).collect();
+
+    let right = selected.iter().filter(|id| correct.contains(**id)).count();
+    let wrong = selected.len() - right;
+
+    // Correct choices in question order, then the wrong picks
+    let mut parts: Vec<PartScore> = question.choices.iter()
+        .filter(|choice| correct.contains(&choice.id))
+        .map(|choice| {
+            let picked = selected.contains(&&choice.id);
+            PartScore::new(choice.id, if picked { 1.0 } else { 0.0 }, if picked { choice.feedback.clone() } else { None })
+        })
+        .collect();
+    parts.extend(selected.iter()
+        .filter(|id| !correct.contains(**id))
+        .map(|id| PartScore::new(id, 0.0, choice_feedback(question, id))));
+
+    QuestionScore::new(selection_credit(right, wrong, correct.len(), penalty), parts)
+}
+
+/// Short answers with accepted answers are scored by their rules alone, so
+/// an exact or case-sensitive rule isn't undercut by the lenient default of
+/// matching the correct answer ignoring case
+fn score_text(question: &Question, correct: &str, text: &str) -> QuestionScore {
+    // Questions not yet migrated still list their alternates as choices
+    if question.answer_type == AnswerType::ShortAnswer && !question.choices.is_empty() {
+        let mut migrated = question.clone();
+        if migrated.migrate_short_answer_alternates() {
+            return score_text(&migrated, correct, text);
+        }
+    }
+
+    let accepted_answers = question.content.short_answer_content.as_ref()
+        .map(|content| content.accepted_answers.as_slice())
+        .filter(|accepted| !accepted.is_empty());
+    if let Some(accepted_answers) = accepted_answers {
+        return match short_answer::best_match(accepted_answers, text) {
+            Some(accepted) => {
+                let credit = accepted.credit.clamp(0.0, 1.0);
+                QuestionScore::new(credit, vec![PartScore::new("answer", credit, accepted.feedback.clone())])
+            }
+            None => QuestionScore::all_or_nothing(false, PartScore::new("answer", 0.0, None)),
+        };
+    }
+
+    let correct = correct.trim().to_lowercase() == text.trim().to_lowercase();
+    QuestionScore::all_or_nothing(correct, PartScore::new("answer", if correct { 1.0 } else { 0.0 }, None))
+}
+
+/// One part per correct pair; each left-hand item counts once
+fn score_matching(question: &Question, correct: &[(Uuid, Uuid)], pairs: &[(Uuid, Uuid)]) -> QuestionScore {
+    let given: HashMap<&Uuid, &Uuid> = pairs.iter().rev().map(|(source, target)| (source, target)).collect();
+
+    let parts: Vec<PartScore> = correct.iter()
+        .map(|(source, target)| {
+            let matched = given.get(source) == Some(&target);
+            PartScore::new(source, if matched { 1.0 } else { 0.0 }, if matched { None } else { choice_feedback(question, source) })
+        })
+        .collect();
+
+    let credit = if correct.is_empty() {
+        if pairs.is_empty() { 1.0 } else { 0.0 }
+    } else {
+        parts.iter().map(|part| part.credit).sum::<f32>() / correct.len() as f32
+    };
+    QuestionScore::new(credit, parts)
+}
+
+/// Fraction of the pairs of items in the correct order that the answer
+/// also puts in that order, in the manner of Kendall's tau; items missing
+/// from the answer break every pair they are part of
+pub fn ordering_credit<T: Eq + std::hash::Hash>(correct: &[T], order: &[T]) -> f32 {
+    let mut positions = HashMap::new();
+    for (position, item) in order.iter().enumerate() {
+        positions.entry(item).or_insert(position);
+    }
+
+    match correct.len() {
+        0 => return 1.0,
+        1 => return if positions.contains_key(&correct[0]) { 1.0 } else { 0.0 },
+        _ => {}
+    }
+
+    let mut concordant = 0;
+    for (i, first) in correct.iter().enumerate() {
+        for second in &correct[i + 1..] {
+            if let (Some(a), Some(b)) = (positions.get(first), positions.get(second)) {
+                if a < b {
+                    concordant += 1;
+                }
+            }
+        }
+    }
+
+    let pairs = correct.len() * (correct.len() - 1) / 2;
+    concordant as f32 / pairs as f32
+}
+
+fn score_ordering(correct: &[Uuid], order: &[Uuid]) -> QuestionScore {
+    let parts = correct.iter()
+        .enumerate()
+        .map(|(position, id)| PartScore::new(id, if order.get(position) == Some(id) { 1.0 } else { 0.0 }, None))
+        .collect();
+
+    QuestionScore::new(ordering_credit(correct, order), parts)
+}
+
+/// Events in date order; events on the same date keep their order
+fn chronological(events: &[TimelineEvent]) -> Vec<&str> {
+    let mut events: Vec<&TimelineEvent> = events.iter().collect();
+    events.sort_by_key(|event| event.date);
+    events.into_iter().map(|event| event.id.as_str()).collect()
+}
+
+fn score_timeline(correct: &[TimelineEvent], events: &[TimelineEvent]) -> QuestionScore {
+    let correct = chronological(correct);
+    let order = chronological(events);
+
+    let parts = correct.iter()
+        .enumerate()
+        .map(|(position, id)| PartScore::new(id, if order.get(position) == Some(id) { 1.0 } else { 0.0 }, None))
+        .collect();
+
+    QuestionScore::new(ordering_credit(&correct, &order), parts)
+}
+
+/// One part per drop zone that should receive an item
+fn score_drop_zones(question: &Question, correct: &HashMap<String, String>, placements: &HashMap<String, String>) -> QuestionScore {
+    let zone_label = |zone: &str| question.content.drag_drop_content.as_ref()
+        .and_then(|content| content.drop_zones.iter().find(|drop_zone| drop_zone.id == zone))
+        .and_then(|drop_zone| drop_zone.label.clone());
+
+    let mut expected: Vec<(&String, &String)> = correct.iter().collect();
+    expected.sort();
+
+    let parts: Vec<PartScore> = expected.iter()
+        .map(|(item, zone)| {
+            let placed = placements.get(*item) == Some(*zone);
+            PartScore::new(zone, if placed { 1.0 } else { 0.0 }, zone_label(zone))
+        })
+        .collect();
+
+    let credit = if expected.is_empty() {
+        if placements.is_empty() { 1.0 } else { 0.0 }
+    } else {
+        parts.iter().map(|part| part.credit).sum::<f32>() / expected.len() as f32
+    };
+    QuestionScore::new(credit, parts)
+}
+
+type Rect = (f32, f32, f32, f32);
+
+fn same_rect(a: &Rect, b: &Rect) -> bool {
+    (a.0 - b.0).abs() < EPSILON && (a.1 - b.1).abs() < EPSILON
+        && (a.2 - b.2).abs() < EPSILON && (a.3 - b.3).abs() < EPSILON
+}
+
+fn contains(rect: &Rect, x: f32, y: f32) -> bool {
+    x >= rect.0 && x <= rect.0 + rect.2 && y >= rect.1 && y <= rect.1 + rect.3
+}
+
+/// Clicks are hit-tested against the question's hotspots; the correct
+/// answer lists the areas of the hotspots to find
+fn score_hotspots(question: &Question, correct: &[Rect], clicks: &[Rect], penalty: WrongChoicePenalty) -> QuestionScore {
+    // Targets are the hotspots of the image, plus any correct area that is
+    // not one of them
+    let mut targets: Vec<(String, Rect, bool, Option<String>)> = question.content.hotspot_content.iter()
+        .flat_map(|content| &content.hotspots)
+        .map(|hotspot| {
+            let rect = (hotspot.x, hotspot.y, hotspot.width, hotspot.height);
+            let is_correct = correct.iter().any(|area| same_rect(area, &rect));
+            (hotspot.id.clone(), rect, is_correct, hotspot.label.clone())
+        })
+        .collect();
+    for (index, area) in correct.iter().enumerate() {
+        if !targets.iter().any(|(_, rect, _, _)| same_rect(area, rect)) {
+            targets.push((format!("area-{}", index + 1), *area, true, None));
+        }
+    }
+
+    // A click is the centre of the area the learner selected
+    let mut hit = vec![false; targets.len()];
+    let mut misses = 0;
+    for click in clicks {
+        let (x, y) = (click.0 + click.2 / 2.0, click.1 + click.3 / 2.0);
+        match targets.iter().position(|(_, rect, _, _)| contains(rect, x, y)) {
+            Some(index) => hit[index] = true,
+            None => misses += 1,
+        }
+    }
+
+    let total = targets.iter().filter(|(_, _, is_correct, _)| *is_correct).count();
+    let right = targets.iter().zip(&hit).filter(|((_, _, is_correct, _), hit)| *is_correct && **hit).count();
+    let wrong = targets.iter().zip(&hit).filter(|((_, _, is_correct, _), hit)| !*is_correct && **hit).count() + misses;
+
+    let mut parts: Vec<PartScore> = targets.iter()
+        .zip(&hit)
+        .filter(|((_, _, is_correct, _), hit)| *is_correct || **hit)
+        .map(|((id, _, is_correct, label), hit)| {
+            PartScore::new(id, if *is_correct && *hit { 1.0 } else { 0.0 }, label.clone())
+        })
+        .collect();
+    if misses > 0 {
+        parts.push(PartScore::new("missed", 0.0, Some(format!("{} click(s) hit no hotspot", misses))));
+    }
+
+    QuestionScore::new(selection_credit(right, wrong, total, penalty), parts)
+}
+
+/// Math answers are graded for equivalence by the math module; if the
+/// correct answer cannot be read, the answer is left for manual review
+fn score_math(question: &Question, correct: &str, value: &str) -> QuestionScore {
+    match math::grade(correct, value, question.content.math_equation_content.as_ref()) {
+        Ok(grade) => {
+            let parts = grade.checks.into_iter()
+                .map(|check| PartScore::new(check.name, if check.passed { 1.0 } else { 0.0 }, check.feedback))
+                .collect();
+            QuestionScore::new(grade.credit, parts)
+        }
+        Err(error) => {
+            tracing::warn!("Cannot read the correct answer of question {}: {}", question.id, error);
+            QuestionScore::review()
+        }
+    }
+}
+
+/// One part per test case of the question; hidden test cases give no
+/// feedback
+fn score_code(question: &Question, answer: &Answer) -> QuestionScore {
+    let Answer::CodeExecution(answer) = answer else {
+        return QuestionScore::mismatched();
+    };
+    if answer.execution_results.is_empty() {
+        return QuestionScore::review();
+    }
+
+    let test_cases = question.content.code_execution_content.as_ref()
+        .map(|content| content.test_cases.clone())
+        .unwrap_or_default();
+
+    let parts: Vec<PartScore> = if test_cases.is_empty() {
+        answer.execution_results.iter()
+            .map(|result| PartScore::new(&result.test_case_id, if result.passed { 1.0 } else { 0.0 }, result.error.clone()))
+            .collect()
+    } else {
+        test_cases.iter()
+            .map(|test_case| {
+                let result = answer.execution_results.iter().find(|result| result.test_case_id == test_case.id);
+                let passed = result.map_or(false, |result| result.passed);
+                let feedback = if test_case.is_hidden {
+                    None
+                } else {
+                    result.and_then(|result| result.error.clone()).or_else(|| test_case.description.clone())
+                };
+                PartScore::new(&test_case.id, if passed { 1.0 } else { 0.0 }, feedback)
+            })
+            .collect()
+    };
+
+    let credit = parts.iter().map(|part| part.credit).sum::<f32>() / parts.len() as f32;
+    QuestionScore::new(credit, parts)
+}
@@ -0,0 +1,132 @@
+use regex::Regex;
+use tracing::warn;
+
+use crate::quiz::models::{AcceptedAnswer, MatchRule};
+
+/// Whether a learner's text satisfies a rule
+pub fn matches(rule: &MatchRule, text: &str) -> bool {
+    let text = text.trim();
+    match rule {
+        MatchRule::Exact(expected) => text == expected.trim(),
+        MatchRule::CaseInsensitive(expected) => text.to_lowercase() == expected.trim().to_lowercase(),
+        MatchRule::Normalized(expected) => normalize(text) == normalize(expected),
+        MatchRule::Levenshtein { text: expected, max_distance } => {
+            levenshtein(&text.to_lowercase(), &expected.trim().to_lowercase()) <= *max_distance
+        }
+        MatchRule::Regex(pattern) => match Regex::new(&format!("^(?:{})$", pattern)) {
+            Ok(regex) => regex.is_match(text),
+            Err(e) => {
+                warn!("Invalid short answer pattern {:?}: {}", pattern, e);
+                false
+            }
+        },
+        MatchRule::Numeric { value, tolerance } => parse_number(text)
+            .is_some_and(|number| (number - value).abs() <= tolerance.abs() + f64::EPSILON * value.abs()),
+    }
+}
+
+/// The accepted answer with the most credit among those the text matches
+pub fn best_match<'a>(accepted: &'a [AcceptedAnswer], text: &str) -> Option<&'a AcceptedAnswer> {
+    accepted
+        .iter()
+        .filter(|answer| matches(&answer.rule, text))
+        .fold(None, |best: Option<&AcceptedAnswer>, answer| match best {
+            Some(best) if best.credit >= answer.credit => Some(best),
+            _ => Some(answer),
+        })
+}
+
+/// Lowercase, with punctuation dropped and whitespace collapsed
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_ascii_punctuation() && !matches!(c, '‘' | '’' | '“' | '”' | '–' | '—'))
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Edit distance in characters
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// A number, allowing a comma as the decimal separator and spaces,
+/// underscores, commas or points between digit groups
+///
+/// With both a comma and a point the last one is the decimal separator.
+/// A single comma followed by three digits, as in `1,000`, could be either
+/// and is not read as a number.
+fn parse_number(text: &str) -> Option<f64> {
+    let cleaned: String = text.chars().filter(|c| !c.is_whitespace() && *c != '_').collect();
+    let commas = cleaned.matches(',').count();
+    let points = cleaned.matches('.').count();
+
+    let (group, decimal) = match (commas, points) {
+        (0, 0) => return finite(cleaned.parse().ok()?),
+        (0, 1) => (None, Some('.')),
+        (1, 0) if digits_after(&cleaned, ',') == 3 => return None,
+        (1, 0) => (None, Some(',')),
+        (_, 0) => (Some(','), None),
+        (0, _) => (Some('.'), None),
+        _ if cleaned.rfind(',') > cleaned.rfind('.') => (Some('.'), Some(',')),
+        _ => (Some(','), Some('.')),
+    };
+
+    let (integer, fraction) = match decimal {
+        Some(decimal) => cleaned.rsplit_once(decimal)?,
+        None => (cleaned.as_str(), ""),
+    };
+    let integer = match group {
+        Some(group) => grouped_digits(integer, group)?,
+        None => integer.to_string(),
+    };
+    if fraction.contains([',', '.']) {
+        return None;
+    }
+
+    let number = if fraction.is_empty() { integer } else { format!("{}.{}", integer, fraction) };
+    finite(number.parse().ok()?)
+}
+
+fn finite(number: f64) -> Option<f64> {
+    Some(number).filter(|number| number.is_finite())
+}
+
+/// Digits after the last `separator`
+fn digits_after(text: &str, separator: char) -> usize {
+    text.rsplit(separator).next().map_or(0, |tail| tail.chars().take_while(char::is_ascii_digit).count())
+}
+
+/// The integer part without its group separators, if the groups after the
+/// first all have three digits
+fn grouped_digits(integer: &str, group: char) -> Option<String> {
+    let mut groups = integer.split(group);
+    let first = groups.next()?;
+    let digits = first.trim_start_matches(['+', '-']);
+    if digits.is_empty() || digits.len() > 3 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut result = first.to_string();
+    for group in groups {
+        if group.len() != 3 || !group.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        result.push_str(group);
+    }
+    Some(result)
+}
@@ -0,0 +1,471 @@
+use super::*;
+use crate::quiz::models::{
+    AcceptedAnswer, Choice, CodeExecutionAnswer, CodeExecutionContent, CodeExecutionResult, CodeTestCase,
+    DragDropContent, DropZone, ExecutionStatus, Hotspot, HotspotContent, MatchRule, QuestionContent,
+    ShortAnswerContent,
+};
+use chrono::{TimeZone, Utc};
+
+fn content(text: &str) -> QuestionContent {
+    QuestionContent {
+        text: text.to_string(),
+        rich_text: None,
+        image_url: None,
+        audio_url: None,
+        drag_drop_content: None,
+        hotspot_content: None,
+        drawing_content: None,
+        code_execution_content: None,
+        math_equation_content: None,
+        timeline_content: None,
+        diagram_labeling_content: None,
+        short_answer_content: None,
+    }
+}
+
+fn choice(text: &str, feedback: Option<&str>) -> Choice {
+    Choice {
+        id: Uuid::new_v4(),
+        text: text.to_string(),
+        rich_text: None,
+        image_url: None,
+        feedback: feedback.map(str::to_string),
+    }
+}
+
+fn question(answer_type: AnswerType, correct_answer: Answer) -> Question {
+    let mut question = Question::new(Uuid::new_v4(), content("Question"), answer_type);
+    question.correct_answer = correct_answer;
+    question
+}
+
+/// A multi-select question with four choices, the first two correct
+fn multi_select() -> (Question, Vec<Uuid>) {
+    let choices = vec![choice("a", None), choice("b", None), choice("c", Some("Not c")), choice("d", None)];
+    let ids: Vec<Uuid> = choices.iter().map(|choice| choice.id).collect();
+    let mut question = question(AnswerType::MultipleChoice, Answer::Choices(ids[..2].to_vec()));
+    question.choices = choices;
+    (question, ids)
+}
+
+fn assert_credit(score: &QuestionScore, credit: f32) {
+    assert!((score.credit - credit).abs() < 1e-4, "expected credit {}, got {}", credit, score.credit);
+}
+
+mod choices {
+    use super::*;
+
+    #[test]
+    fn test_single_choice() {
+        let right = choice("Paris", Some("Correct"));
+        let wrong = choice("London", Some("That is the capital of the UK"));
+        let mut question = question(AnswerType::MultipleChoice, Answer::Choice(right.id));
+        question.choices = vec![right.clone(), wrong.clone()];
+
+        assert!(score_answer(&question, &Answer::Choice(right.id), WrongChoicePenalty::PerChoice).is_correct());
+
+        let score = score_answer(&question, &Answer::Choice(wrong.id), WrongChoicePenalty::PerChoice);
+        assert_credit(&score, 0.0);
+        assert_eq!(score.parts[0].feedback.as_deref(), Some("That is the capital of the UK"));
+    }
+
+    #[test]
+    fn test_true_false_as_text() {
+        let yes = choice("True", None);
+        let no = choice("False", None);
+        let mut question = question(AnswerType::TrueFalse, Answer::Choice(yes.id));
+        question.choices = vec![yes, no];
+
+        assert!(question.check_answer(&Answer::Text("true".to_string())));
+        assert!(!question.check_answer(&Answer::Text("false".to_string())));
+    }
+
+    #[test]
+    fn test_penalties() {
+        let (question, ids) = multi_select();
+        let one_right_one_wrong = Answer::Choices(vec![ids[0], ids[2]]);
+
+        assert_credit(&score_answer(&question, &one_right_one_wrong, WrongChoicePenalty::None), 0.5);
+        assert_credit(&score_answer(&question, &one_right_one_wrong, WrongChoicePenalty::PerChoice), 0.0);
+        assert_credit(&score_answer(&question, &one_right_one_wrong, WrongChoicePenalty::Fixed(0.25)), 0.25);
+        assert_credit(&score_answer(&question, &one_right_one_wrong, WrongChoicePenalty::AllOrNothing), 0.0);
+
+        let one_right = Answer::Choices(vec![ids[0]]);
+        assert_credit(&score_answer(&question, &one_right, WrongChoicePenalty::PerChoice), 0.5);
+        assert_credit(&score_answer(&question, &one_right, WrongChoicePenalty::AllOrNothing), 0.0);
+
+        let everything = Answer::Choices(ids.clone());
+        assert_credit(&score_answer(&question, &everything, WrongChoicePenalty::PerChoice), 0.0);
+        assert_credit(&score_answer(&question, &everything, WrongChoicePenalty::Fixed(1.0)), 0.0);
+
+        let all_right = Answer::Choices(vec![ids[1], ids[0]]);
+        assert!(score_answer(&question, &all_right, WrongChoicePenalty::AllOrNothing).is_correct());
+    }
+
+    #[test]
+    fn test_parts_and_feedback() {
+        let (question, ids) = multi_select();
+        let score = score_answer(&question, &Answer::Choices(vec![ids[0], ids[2], ids[2]]), WrongChoicePenalty::None);
+
+        let parts: Vec<(&str, f32)> = score.parts.iter().map(|part| (part.id.as_str(), part.credit)).collect();
+        let (a, b, c) = (ids[0].to_string(), ids[1].to_string(), ids[2].to_string());
+        assert_eq!(parts, vec![(a.as_str(), 1.0), (b.as_str(), 0.0), (c.as_str(), 0.0)]);
+        assert_eq!(score.parts[2].feedback.as_deref(), Some("Not c"));
+    }
+
+    #[test]
+    fn test_quiz_settings_choose_the_penalty() {
+        let (question, ids) = multi_select();
+        let mut quiz = crate::quiz::models::Quiz::new("Quiz".to_string(), None);
+        quiz.settings.wrong_choice_penalty = WrongChoicePenalty::None;
+
+        let score = question.score_answer(&Answer::Choices(vec![ids[0], ids[3]]), &quiz.settings);
+        assert_credit(&score, 0.5);
+        assert!(!question.check_answer(&Answer::Choices(vec![ids[0], ids[3]])));
+    }
+}
+
+mod matching_and_ordering {
+    use super::*;
+
+    #[test]
+    fn test_matching_credit_per_pair() {
+        let pairs: Vec<(Uuid, Uuid)> = (0..4).map(|_| (Uuid::new_v4(), Uuid::new_v4())).collect();
+        let question = question(AnswerType::Matching, Answer::Matching(pairs.clone()));
+
+        let mut answer = pairs.clone();
+        answer.swap(0, 1);
+        answer[2].1 = pairs[3].1;
+        answer[3].1 = pairs[2].1;
+
+        assert_credit(&score_answer(&question, &Answer::Matching(answer), WrongChoicePenalty::PerChoice), 0.5);
+        assert!(question.check_answer(&Answer::Matching(pairs.into_iter().rev().collect())));
+    }
+
+    #[test]
+    fn test_ordering_credit() {
+        let items: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        assert_eq!(ordering_credit(&items, &items), 1.0);
+
+        let reversed: Vec<Uuid> = items.iter().rev().copied().collect();
+        assert_eq!(ordering_credit(&items, &reversed), 0.0);
+
+        // One adjacent swap breaks one of the six pairs
+        let swapped = vec![items[1], items[0], items[2], items[3]];
+        assert_credit(&QuestionScore::new(ordering_credit(&items, &swapped), Vec::new()), 5.0 / 6.0);
+
+        // A missing item breaks the three pairs it is part of
+        let missing = vec![items[0], items[1], items[2]];
+        assert_credit(&QuestionScore::new(ordering_credit(&items, &missing), Vec::new()), 0.5);
+    }
+
+    #[test]
+    fn test_ordering_question() {
+        let items: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let question = question(AnswerType::Ordering, Answer::Ordering(items.clone()));
+
+        let answer = Answer::Ordering(vec![items[0], items[2], items[1]]);
+        let score = score_answer(&question, &answer, WrongChoicePenalty::PerChoice);
+        assert_credit(&score, 2.0 / 3.0);
+        assert_eq!(score.parts.iter().map(|part| part.credit).collect::<Vec<_>>(), vec![1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_timeline_is_ordered_by_date() {
+        let event = |id: &str, year: i32| TimelineEvent {
+            id: id.to_string(),
+            title: id.to_string(),
+            description: None,
+            date: Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap(),
+            image_url: None,
+        };
+        let correct = vec![event("a", 1800), event("b", 1900), event("c", 2000)];
+        let question = question(AnswerType::Timeline, Answer::Timeline(correct));
+
+        let placed = vec![event("b", 1800), event("a", 1900), event("c", 2000)];
+        assert_credit(&score_answer(&question, &Answer::Timeline(placed), WrongChoicePenalty::PerChoice), 2.0 / 3.0);
+    }
+}
+
+mod placements {
+    use super::*;
+
+    fn zone(id: &str, label: &str) -> DropZone {
+        DropZone { id: id.to_string(), x: 0.0, y: 0.0, width: 10.0, height: 10.0, label: Some(label.to_string()) }
+    }
+
+    #[test]
+    fn test_drop_zone_credit() {
+        let correct: HashMap<String, String> = [("heart", "chest"), ("brain", "head"), ("liver", "belly")]
+            .iter()
+            .map(|(item, zone)| (item.to_string(), zone.to_string()))
+            .collect();
+        let mut question = question(AnswerType::DragDrop, Answer::DragDrop(correct.clone()));
+        question.content.drag_drop_content = Some(DragDropContent {
+            background_image_url: None,
+            drag_items: Vec::new(),
+            drop_zones: vec![zone("chest", "Chest"), zone("head", "Head"), zone("belly", "Belly")],
+        });
+
+        let mut placements = correct.clone();
+        placements.insert("liver".to_string(), "chest".to_string());
+
+        let score = score_answer(&question, &Answer::DragDrop(placements), WrongChoicePenalty::PerChoice);
+        assert_credit(&score, 2.0 / 3.0);
+        let belly = score.parts.iter().find(|part| part.id == "belly").unwrap();
+        assert_eq!((belly.credit, belly.feedback.as_deref()), (0.0, Some("Belly")));
+
+        assert!(question.check_answer(&Answer::DragDrop(correct)));
+    }
+
+    #[test]
+    fn test_diagram_labeling() {
+        let correct: HashMap<String, String> = [("label-1".to_string(), "part-1".to_string()), ("label-2".to_string(), "part-2".to_string())]
+            .into_iter()
+            .collect();
+        let question = question(AnswerType::DiagramLabeling, Answer::DiagramLabeling(correct));
+
+        let answer = [("label-1".to_string(), "part-1".to_string())].into_iter().collect();
+        assert_credit(&score_answer(&question, &Answer::DiagramLabeling(answer), WrongChoicePenalty::PerChoice), 0.5);
+    }
+}
+
+mod hotspots {
+    use super::*;
+
+    fn hotspot(id: &str, x: f32) -> Hotspot {
+        Hotspot { id: id.to_string(), x, y: 0.0, width: 10.0, height: 10.0, label: None }
+    }
+
+    /// Three hotspots side by side; the first two are the ones to find
+    fn hotspot_question() -> Question {
+        let mut question = question(
+            AnswerType::Hotspot,
+            Answer::Hotspot(vec![(0.0, 0.0, 10.0, 10.0), (20.0, 0.0, 10.0, 10.0)]),
+        );
+        question.content.hotspot_content = Some(HotspotContent {
+            image_url: "map.png".to_string(),
+            hotspots: vec![hotspot("north", 0.0), hotspot("south", 20.0), hotspot("east", 40.0)],
+        });
+        question
+    }
+
+    #[test]
+    fn test_clicks_are_hit_tested() {
+        let question = hotspot_question();
+
+        // Clicks anywhere inside the hotspots count
+        let answer = Answer::Hotspot(vec![(2.0, 2.0, 2.0, 2.0), (25.0, 5.0, 0.0, 0.0)]);
+        assert!(score_answer(&question, &answer, WrongChoicePenalty::AllOrNothing).is_correct());
+
+        let answer = Answer::Hotspot(vec![(2.0, 2.0, 2.0, 2.0)]);
+        assert_credit(&score_answer(&question, &answer, WrongChoicePenalty::PerChoice), 0.5);
+    }
+
+    #[test]
+    fn test_wrong_hotspots_and_misses_are_penalized() {
+        let question = hotspot_question();
+
+        let wrong_hotspot = Answer::Hotspot(vec![(5.0, 5.0, 0.0, 0.0), (25.0, 5.0, 0.0, 0.0), (45.0, 5.0, 0.0, 0.0)]);
+        assert_credit(&score_answer(&question, &wrong_hotspot, WrongChoicePenalty::PerChoice), 0.5);
+        assert_credit(&score_answer(&question, &wrong_hotspot, WrongChoicePenalty::None), 1.0);
+
+        let miss = Answer::Hotspot(vec![(5.0, 5.0, 0.0, 0.0), (100.0, 100.0, 0.0, 0.0)]);
+        let score = score_answer(&question, &miss, WrongChoicePenalty::Fixed(0.25));
+        assert_credit(&score, 0.25);
+        assert!(score.parts.iter().any(|part| part.id == "missed"));
+    }
+
+    #[test]
+    fn test_areas_without_hotspot_content() {
+        let question = question(AnswerType::Hotspot, Answer::Hotspot(vec![(0.0, 0.0, 10.0, 10.0)]));
+        assert!(question.check_answer(&Answer::Hotspot(vec![(4.0, 4.0, 2.0, 2.0)])));
+        assert!(!question.check_answer(&Answer::Hotspot(vec![(40.0, 40.0, 2.0, 2.0)])));
+    }
+}
+
+mod other_types {
+    use super::*;
+
+    #[test]
+    fn test_short_answer_alternates() {
+        let mut question = question(AnswerType::ShortAnswer, Answer::Text("Pacific".to_string()));
+        question.choices = vec![choice("Pacific Ocean", Some("Also accepted"))];
+
+        let score = score_answer(&question, &Answer::Text(" pacific ocean ".to_string()), WrongChoicePenalty::PerChoice);
+        assert!(score.is_correct());
+        assert_eq!(score.parts[0].feedback.as_deref(), Some("Also accepted"));
+        assert!(!question.check_answer(&Answer::Text("Atlantic".to_string())));
+    }
+
+    #[test]
+    fn test_short_answer_rules() {
+        let accepted = |rule: MatchRule, credit: f32, feedback: Option<&str>| AcceptedAnswer {
+            rule,
+            credit,
+            feedback: feedback.map(str::to_string),
+        };
+        let mut question = question(AnswerType::ShortAnswer, Answer::Text("Mitochondria".to_string()));
+        question.content.short_answer_content = Some(ShortAnswerContent {
+            accepted_answers: vec![
+                accepted(MatchRule::Exact("Mitochondria".to_string()), 1.0, None),
+                accepted(MatchRule::Normalized("the mitochondria".to_string()), 1.0, None),
+                accepted(MatchRule::Levenshtein { text: "mitochondria".to_string(), max_distance: 2 }, 0.5, Some("Check your spelling")),
+                accepted(MatchRule::Regex(r"(?i)mitochondri(on|um)".to_string()), 0.75, Some("Use the plural")),
+            ],
+        });
+        let score = |text: &str| score_answer(&question, &Answer::Text(text.to_string()), WrongChoicePenalty::PerChoice);
+
+        assert!(score(" Mitochondria ").is_correct());
+        assert!(score("The  mitochondria!").is_correct());
+        // Only the misspelling rule accepts a different case
+        assert_eq!(score("mitochondria").credit, 0.5);
+        assert_eq!(score("mitocondria").parts[0].feedback.as_deref(), Some("Check your spelling"));
+        assert_eq!(score("Mitochondrion").credit, 0.75);
+        assert_eq!(score("ribosome").credit, 0.0);
+        // The best of several matching rules counts
+        assert_eq!(score("mitochondrium").credit, 0.75);
+
+        // Accepted answers survive the JSON export, and older exports still load
+        let json = serde_json::to_value(&question).unwrap();
+        let restored: Question = serde_json::from_value(json.clone()).unwrap();
+        let rules = |question: &Question| -> Vec<MatchRule> {
+            question.content.short_answer_content.iter()
+                .flat_map(|content| content.accepted_answers.iter().map(|accepted| accepted.rule.clone()))
+                .collect()
+        };
+        assert_eq!(rules(&restored), rules(&question));
+        assert_eq!(restored.content.short_answer_content.unwrap().accepted_answers[2].credit, 0.5);
+
+        let mut legacy = json;
+        legacy["content"].as_object_mut().unwrap().remove("short_answer_content");
+        let legacy: Question = serde_json::from_value(legacy).unwrap();
+        assert!(legacy.content.short_answer_content.is_none());
+    }
+
+    #[test]
+    fn test_short_answer_matchers() {
+        use super::short_answer::matches;
+
+        assert!(matches(&MatchRule::CaseInsensitive("Paris".to_string()), "PARIS"));
+        assert!(!matches(&MatchRule::CaseInsensitive("Paris".to_string()), "Pari"));
+        assert!(matches(&MatchRule::Normalized("e.g., rock-and-roll".to_string()), "Eg rockandroll"));
+        assert!(matches(&MatchRule::Levenshtein { text: "kitten".to_string(), max_distance: 3 }, "sitting"));
+        assert!(!matches(&MatchRule::Levenshtein { text: "kitten".to_string(), max_distance: 2 }, "sitting"));
+        assert!(matches(&MatchRule::Regex(r"\d{4}".to_string()), "1066"));
+        assert!(!matches(&MatchRule::Regex(r"\d{4}".to_string()), "in 1066"));
+        assert!(!matches(&MatchRule::Regex("(".to_string()), "("));
+
+        let numeric = MatchRule::Numeric { value: 9.81, tolerance: 0.05 };
+        assert!(matches(&numeric, "9.8"));
+        assert!(matches(&numeric, "9,85"));
+        assert!(!matches(&numeric, "9.9"));
+        assert!(!matches(&numeric, "about 9.8"));
+        assert!(matches(&MatchRule::Numeric { value: 1_000_000.0, tolerance: 0.0 }, "1 000 000"));
+
+        // Thousands separators
+        let thousands = MatchRule::Numeric { value: 1_234_567.5, tolerance: 0.0 };
+        assert!(matches(&thousands, "1,234,567.5"));
+        assert!(matches(&thousands, "1.234.567,5"));
+        assert!(!matches(&thousands, "12,34,567.5"));
+        assert!(matches(&MatchRule::Numeric { value: 1_000_000.0, tolerance: 0.0 }, "1,000,000"));
+        // A lone comma before three digits could be either separator
+        assert!(!matches(&MatchRule::Numeric { value: 1000.0, tolerance: 0.0 }, "1,000"));
+        assert!(!matches(&MatchRule::Numeric { value: 1.0, tolerance: 0.0 }, "1,000"));
+        assert!(matches(&MatchRule::Numeric { value: 1000.0, tolerance: 0.0 }, "1,000.0"));
+    }
+
+    #[test]
+    fn test_short_answer_alternates_are_migrated() {
+        let mut question = question(AnswerType::ShortAnswer, Answer::Text("Pacific".to_string()));
+        question.choices = vec![choice("pacific", Some("Right")), choice("Pacific Ocean", Some("Also accepted"))];
+
+        assert!(question.migrate_short_answer_alternates());
+        assert!(question.choices.is_empty());
+        let accepted = &question.content.short_answer_content.as_ref().unwrap().accepted_answers;
+        assert_eq!(accepted.len(), 2);
+        assert_eq!(accepted[0].rule, MatchRule::CaseInsensitive("Pacific".to_string()));
+        assert_eq!(accepted[0].feedback.as_deref(), Some("Right"));
+        assert_eq!(accepted[1].rule, MatchRule::CaseInsensitive("Pacific Ocean".to_string()));
+
+        assert!(!question.migrate_short_answer_alternates());
+        assert!(question.check_answer(&Answer::Text("PACIFIC OCEAN".to_string())));
+    }
+
+    #[test]
+    fn test_math_equation_tolerance() {
+        let mut question = question(AnswerType::MathEquation, Answer::MathEquation("3.14".to_string()));
+        question.content.math_equation_content = Some(crate::quiz::models::MathEquationContent {
+            equation_type: crate::quiz::models::MathEquationType::Custom,
+            variables: None,
+            precision: None,
+            display_mode: false,
+            tolerance: Some(0.01),
+            significant_figures: None,
+        });
+
+        assert!(question.check_answer(&Answer::MathEquation("3.141".to_string())));
+        assert!(!question.check_answer(&Answer::MathEquation("3.2".to_string())));
+    }
+
+    #[test]
+    fn test_code_execution_test_cases() {
+        let test_case = |id: &str, is_hidden: bool| CodeTestCase {
+            id: id.to_string(),
+            input: String::new(),
+            expected_output: String::new(),
+            is_hidden,
+            description: Some(format!("Test {}", id)),
+        };
+        let result = |id: &str, passed: bool| CodeExecutionResult {
+            test_case_id: id.to_string(),
+            output: String::new(),
+            passed,
+            execution_time_ms: 1,
+            memory_used_kb: None,
+            error: None,
+            stderr: String::new(),
+            exit_code: Some(0),
+            status: ExecutionStatus::Finished,
+        };
+
+        let mut question = question(AnswerType::CodeExecution, Answer::Text(String::new()));
+        question.content.code_execution_content = Some(CodeExecutionContent {
+            language: "python".to_string(),
+            initial_code: String::new(),
+            test_cases: vec![test_case("1", false), test_case("2", false), test_case("3", true), test_case("4", true)],
+            allowed_imports: None,
+            time_limit_ms: None,
+            memory_limit_kb: None,
+        });
+
+        let mut answer = CodeExecutionAnswer {
+            code: "print(1)".to_string(),
+            language: "python".to_string(),
+            execution_results: Vec::new(),
+        };
+        assert!(score_answer(&question, &Answer::CodeExecution(answer.clone()), WrongChoicePenalty::PerChoice).needs_review);
+
+        answer.execution_results = vec![result("1", true), result("2", false), result("3", true)];
+        let score = score_answer(&question, &Answer::CodeExecution(answer), WrongChoicePenalty::PerChoice);
+        assert_credit(&score, 0.5);
+        assert_eq!(score.parts[1].feedback.as_deref(), Some("Test 2"));
+        assert_eq!(score.parts[2].feedback, None);
+    }
+
+    #[test]
+    fn test_essays_need_review() {
+        let question = question(AnswerType::Essay, Answer::Text(String::new()));
+        let score = score_answer(&question, &Answer::Text("An essay".to_string()), WrongChoicePenalty::PerChoice);
+        assert!(score.needs_review);
+        assert!(!score.is_correct());
+    }
+
+    #[test]
+    fn test_mismatched_answers_score_nothing() {
+        let question = question(AnswerType::Ordering, Answer::Ordering(vec![Uuid::new_v4()]));
+        let score = score_answer(&question, &Answer::Text("first".to_string()), WrongChoicePenalty::None);
+        assert_credit(&score, 0.0);
+        assert!(!score.needs_review);
+    }
+}
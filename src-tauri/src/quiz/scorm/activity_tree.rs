@@ -0,0 +1,1102 @@
+// SCORM 2004 activity tree and sequencing definition model
+//
+// The activity tree mirrors the manifest's organization: every `<item>` is an
+// activity, leaves are delivered to the learner and clusters group them. Each
+// activity carries its IMS Simple Sequencing definition (control modes,
+// sequencing rules, limit conditions, rollup rules and objectives) and the
+// tracking state the sequencing processes read and update.
+//
+// Process names and numbering in comments follow the SCORM 2004 4th Edition
+// Sequencing and Navigation (SN) book.
+
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+
+/// Sequencing rule and rollup rule condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConditionType {
+    Satisfied,
+    ObjectiveStatusKnown,
+    ObjectiveMeasureKnown,
+    ObjectiveMeasureGreaterThan,
+    ObjectiveMeasureLessThan,
+    Completed,
+    ActivityProgressKnown,
+    Attempted,
+    AttemptLimitExceeded,
+    TimeLimitExceeded,
+    OutsideAvailableTimeRange,
+    Always,
+}
+
+impl ConditionType {
+    /// Parse the `condition` attribute token
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "satisfied" => Some(Self::Satisfied),
+            "objectiveStatusKnown" => Some(Self::ObjectiveStatusKnown),
+            "objectiveMeasureKnown" => Some(Self::ObjectiveMeasureKnown),
+            "objectiveMeasureGreaterThan" => Some(Self::ObjectiveMeasureGreaterThan),
+            "objectiveMeasureLessThan" => Some(Self::ObjectiveMeasureLessThan),
+            "completed" => Some(Self::Completed),
+            "activityProgressKnown" => Some(Self::ActivityProgressKnown),
+            "attempted" => Some(Self::Attempted),
+            "attemptLimitExceeded" => Some(Self::AttemptLimitExceeded),
+            "timeLimitExceeded" => Some(Self::TimeLimitExceeded),
+            "outsideAvailableTimeRange" => Some(Self::OutsideAvailableTimeRange),
+            "always" => Some(Self::Always),
+            _ => None,
+        }
+    }
+}
+
+/// A single rule condition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleCondition {
+    /// Condition to evaluate
+    pub condition: ConditionType,
+
+    /// Objective the condition reads (primary objective if not set)
+    pub referenced_objective: Option<String>,
+
+    /// Threshold for the measure comparisons
+    pub measure_threshold: f64,
+
+    /// Whether the `not` operator applies
+    pub negate: bool,
+}
+
+/// How the conditions of a rule combine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConditionCombination {
+    All,
+    Any,
+}
+
+/// Sequencing rule action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleAction {
+    // Pre-condition actions
+    Skip,
+    Disabled,
+    HiddenFromChoice,
+    StopForwardTraversal,
+
+    // Exit condition action
+    Exit,
+
+    // Post-condition actions
+    ExitParent,
+    ExitAll,
+    Retry,
+    RetryAll,
+    Continue,
+    Previous,
+}
+
+impl RuleAction {
+    /// Parse the `action` attribute token
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "skip" => Some(Self::Skip),
+            "disabled" => Some(Self::Disabled),
+            "hiddenFromChoice" => Some(Self::HiddenFromChoice),
+            "stopForwardTraversal" => Some(Self::StopForwardTraversal),
+            "exit" => Some(Self::Exit),
+            "exitParent" => Some(Self::ExitParent),
+            "exitAll" => Some(Self::ExitAll),
+            "retry" => Some(Self::Retry),
+            "retryAll" => Some(Self::RetryAll),
+            "continue" => Some(Self::Continue),
+            "previous" => Some(Self::Previous),
+            _ => None,
+        }
+    }
+}
+
+/// Pre-condition, exit condition or post-condition rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SequencingRule {
+    pub combination: ConditionCombination,
+    pub conditions: Vec<RuleCondition>,
+    pub action: RuleAction,
+}
+
+/// Which children a rollup rule requires its conditions of
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ChildActivitySet {
+    All,
+    Any,
+    None,
+    AtLeastCount(u32),
+    AtLeastPercent(f64),
+}
+
+/// Rollup rule action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RollupAction {
+    Satisfied,
+    NotSatisfied,
+    Completed,
+    Incomplete,
+}
+
+impl RollupAction {
+    /// Parse the `action` attribute token
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "satisfied" => Some(Self::Satisfied),
+            "notSatisfied" => Some(Self::NotSatisfied),
+            "completed" => Some(Self::Completed),
+            "incomplete" => Some(Self::Incomplete),
+            _ => None,
+        }
+    }
+}
+
+/// Rollup rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RollupRule {
+    pub child_activity_set: ChildActivitySet,
+    pub combination: ConditionCombination,
+    pub conditions: Vec<RuleCondition>,
+    pub action: RollupAction,
+}
+
+/// When a child is included in its parent's rollup (`adlseq:rollupConsiderations`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RollupConsideration {
+    Always,
+    IfAttempted,
+    IfNotSkipped,
+    IfNotSuspended,
+}
+
+impl RollupConsideration {
+    /// Parse a `requiredFor*` attribute token
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "always" => Some(Self::Always),
+            "ifAttempted" => Some(Self::IfAttempted),
+            "ifNotSkipped" => Some(Self::IfNotSkipped),
+            "ifNotSuspended" => Some(Self::IfNotSuspended),
+            _ => None,
+        }
+    }
+}
+
+/// Sequencing control modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlMode {
+    pub choice: bool,
+    pub choice_exit: bool,
+    pub flow: bool,
+    pub forward_only: bool,
+    pub use_current_attempt_objective_info: bool,
+    pub use_current_attempt_progress_info: bool,
+}
+
+impl Default for ControlMode {
+    fn default() -> Self {
+        Self {
+            choice: true,
+            choice_exit: true,
+            flow: false,
+            forward_only: false,
+            use_current_attempt_objective_info: true,
+            use_current_attempt_progress_info: true,
+        }
+    }
+}
+
+/// Limit conditions
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct LimitConditions {
+    /// Maximum number of attempts on the activity
+    pub attempt_limit: Option<u32>,
+
+    /// Maximum duration of an attempt, in seconds
+    pub attempt_absolute_duration_limit: Option<f64>,
+}
+
+/// Rollup controls and considerations
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RollupControls {
+    pub rollup_objective_satisfied: bool,
+    pub rollup_progress_completion: bool,
+    pub objective_measure_weight: f64,
+    pub required_for_satisfied: RollupConsideration,
+    pub required_for_not_satisfied: RollupConsideration,
+    pub required_for_completed: RollupConsideration,
+    pub required_for_incomplete: RollupConsideration,
+    pub measure_satisfaction_if_active: bool,
+}
+
+impl Default for RollupControls {
+    fn default() -> Self {
+        Self {
+            rollup_objective_satisfied: true,
+            rollup_progress_completion: true,
+            objective_measure_weight: 1.0,
+            required_for_satisfied: RollupConsideration::Always,
+            required_for_not_satisfied: RollupConsideration::Always,
+            required_for_completed: RollupConsideration::Always,
+            required_for_incomplete: RollupConsideration::Always,
+            measure_satisfaction_if_active: true,
+        }
+    }
+}
+
+/// Delivery controls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeliveryControls {
+    pub tracked: bool,
+    pub completion_set_by_content: bool,
+    pub objective_set_by_content: bool,
+}
+
+impl Default for DeliveryControls {
+    fn default() -> Self {
+        Self {
+            tracked: true,
+            completion_set_by_content: false,
+            objective_set_by_content: false,
+        }
+    }
+}
+
+/// Mapping of a local objective to a shared global objective
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectiveMap {
+    pub target_id: String,
+    pub read_satisfied: bool,
+    pub read_normalized_measure: bool,
+    pub write_satisfied: bool,
+    pub write_normalized_measure: bool,
+}
+
+/// Objective of an activity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectiveDefinition {
+    /// Objective ID; empty for an unnamed primary objective
+    pub id: String,
+    pub primary: bool,
+    pub satisfied_by_measure: bool,
+    pub min_normalized_measure: f64,
+    pub maps: Vec<ObjectiveMap>,
+}
+
+impl ObjectiveDefinition {
+    /// The implicit primary objective of an activity that defines none
+    pub fn implicit_primary() -> Self {
+        Self {
+            id: String::new(),
+            primary: true,
+            satisfied_by_measure: false,
+            min_normalized_measure: 1.0,
+            maps: Vec::new(),
+        }
+    }
+}
+
+/// Complete sequencing definition of an activity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SequencingDefinition {
+    pub control_mode: ControlMode,
+    pub pre_condition_rules: Vec<SequencingRule>,
+    pub exit_condition_rules: Vec<SequencingRule>,
+    pub post_condition_rules: Vec<SequencingRule>,
+    pub limit_conditions: LimitConditions,
+    pub rollup_rules: Vec<RollupRule>,
+    pub rollup_controls: RollupControls,
+    pub objectives: Vec<ObjectiveDefinition>,
+    pub delivery_controls: DeliveryControls,
+}
+
+impl Default for SequencingDefinition {
+    fn default() -> Self {
+        Self {
+            control_mode: ControlMode::default(),
+            pre_condition_rules: Vec::new(),
+            exit_condition_rules: Vec::new(),
+            post_condition_rules: Vec::new(),
+            limit_conditions: LimitConditions::default(),
+            rollup_rules: Vec::new(),
+            rollup_controls: RollupControls::default(),
+            objectives: vec![ObjectiveDefinition::implicit_primary()],
+            delivery_controls: DeliveryControls::default(),
+        }
+    }
+}
+
+impl SequencingDefinition {
+    /// The primary objective
+    pub fn primary_objective(&self) -> &ObjectiveDefinition {
+        self.objectives.iter()
+            .find(|objective| objective.primary)
+            .expect("every sequencing definition has a primary objective")
+    }
+}
+
+/// Objective progress information
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ObjectiveState {
+    pub progress_status: bool,
+    pub satisfied_status: bool,
+    pub measure_status: bool,
+    pub normalized_measure: f64,
+}
+
+/// Tracking state of an activity
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ActivityState {
+    pub is_active: bool,
+    pub is_suspended: bool,
+    pub attempt_count: u32,
+    pub attempt_progress_status: bool,
+    pub attempt_completion_status: bool,
+
+    /// Duration of the current attempt, in seconds
+    pub attempt_absolute_duration: f64,
+
+    /// Local objective states keyed by objective ID
+    pub objectives: HashMap<String, ObjectiveState>,
+}
+
+/// Tracking data a SCO reported for its attempt
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct RuntimeData {
+    /// `cmi.completion_status`; `None` when unknown
+    pub completion_status: Option<bool>,
+
+    /// `cmi.success_status`; `None` when unknown
+    pub success_status: Option<bool>,
+
+    /// `cmi.score.scaled`
+    pub score_scaled: Option<f64>,
+
+    /// `cmi.session_time`, in seconds
+    pub session_time: f64,
+
+    /// Whether `cmi.exit` was `suspend`
+    pub suspend: bool,
+}
+
+impl RuntimeData {
+    /// Read the sequencing-relevant elements from SCORM 2004 runtime values
+    pub fn from_cmi(values: &HashMap<String, String>) -> Self {
+        let get = |key: &str| values.get(key).map(String::as_str);
+
+        Self {
+            completion_status: match get("cmi.completion_status") {
+                Some("completed") => Some(true),
+                Some("incomplete") | Some("not attempted") => Some(false),
+                _ => None,
+            },
+            success_status: match get("cmi.success_status") {
+                Some("passed") => Some(true),
+                Some("failed") => Some(false),
+                _ => None,
+            },
+            score_scaled: get("cmi.score.scaled").and_then(|value| value.parse().ok()),
+            session_time: get("cmi.session_time").and_then(parse_iso8601_duration).unwrap_or(0.0),
+            suspend: get("cmi.exit") == Some("suspend"),
+        }
+    }
+}
+
+/// An activity of the tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Activity {
+    /// Item identifier
+    pub id: String,
+    pub title: String,
+
+    /// Launch location of the resource, with parameters, for leaves
+    pub href: Option<String>,
+    pub is_visible: bool,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub sequencing: SequencingDefinition,
+    pub state: ActivityState,
+}
+
+impl Activity {
+    /// Create an activity with default sequencing
+    pub fn new(id: &str, title: &str, href: Option<String>) -> Self {
+        Self {
+            id: id.to_string(),
+            title: title.to_string(),
+            href,
+            is_visible: true,
+            parent: None,
+            children: Vec::new(),
+            sequencing: SequencingDefinition::default(),
+            state: ActivityState::default(),
+        }
+    }
+}
+
+/// Activity tree of an organization; the root is at index 0
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActivityTree {
+    activities: Vec<Activity>,
+
+    /// Shared global objectives keyed by target objective ID
+    global_objectives: HashMap<String, ObjectiveState>,
+}
+
+impl ActivityTree {
+    /// Create a tree from its root activity
+    pub fn new(root: Activity) -> Self {
+        Self {
+            activities: vec![Activity { parent: None, ..root }],
+            global_objectives: HashMap::new(),
+        }
+    }
+
+    /// Add an activity as the last child of `parent`, returning its index
+    pub fn add_child(&mut self, parent: usize, activity: Activity) -> usize {
+        let index = self.activities.len();
+        self.activities.push(Activity { parent: Some(parent), ..activity });
+        self.activities[parent].children.push(index);
+        index
+    }
+
+    /// Index of the root activity
+    pub fn root(&self) -> usize {
+        0
+    }
+
+    /// Number of activities
+    pub fn len(&self) -> usize {
+        self.activities.len()
+    }
+
+    /// Whether the tree is empty (never true for a constructed tree)
+    pub fn is_empty(&self) -> bool {
+        self.activities.is_empty()
+    }
+
+    /// Get an activity
+    pub fn get(&self, index: usize) -> &Activity {
+        &self.activities[index]
+    }
+
+    /// Get an activity mutably
+    pub fn get_mut(&mut self, index: usize) -> &mut Activity {
+        &mut self.activities[index]
+    }
+
+    /// Find an activity by identifier
+    pub fn find(&self, id: &str) -> Option<usize> {
+        self.activities.iter().position(|activity| activity.id == id)
+    }
+
+    /// Activities in tree (preorder) order
+    pub fn activities(&self) -> impl Iterator<Item = &Activity> {
+        self.preorder().into_iter().map(move |index| &self.activities[index])
+    }
+
+    /// Shared global objective states
+    pub fn global_objectives(&self) -> &HashMap<String, ObjectiveState> {
+        &self.global_objectives
+    }
+
+    /// Whether an activity is a leaf
+    pub fn is_leaf(&self, index: usize) -> bool {
+        self.activities[index].children.is_empty()
+    }
+
+    /// Parent of an activity
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        self.activities[index].parent
+    }
+
+    /// The activity and its ancestors, from the activity up to the root
+    pub fn path_to_root(&self, index: usize) -> Vec<usize> {
+        let mut path = vec![index];
+        let mut current = index;
+        while let Some(parent) = self.activities[current].parent {
+            path.push(parent);
+            current = parent;
+        }
+        path
+    }
+
+    /// The activity and its ancestors, from the root down to the activity
+    pub fn path_from_root(&self, index: usize) -> Vec<usize> {
+        let mut path = self.path_to_root(index);
+        path.reverse();
+        path
+    }
+
+    /// Whether `ancestor` is `index` or one of its ancestors
+    pub fn is_ancestor_or_self(&self, ancestor: usize, index: usize) -> bool {
+        self.path_to_root(index).contains(&ancestor)
+    }
+
+    /// Deepest activity that is an ancestor of (or equal to) both activities
+    pub fn common_ancestor(&self, a: usize, b: usize) -> usize {
+        let path_a = self.path_to_root(a);
+        self.path_to_root(b).into_iter()
+            .find(|index| path_a.contains(index))
+            .unwrap_or(self.root())
+    }
+
+    /// All activity indices in preorder
+    pub fn preorder(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.activities.len());
+        let mut stack = vec![self.root()];
+        while let Some(index) = stack.pop() {
+            order.push(index);
+            stack.extend(self.activities[index].children.iter().rev());
+        }
+        order
+    }
+
+    /// Position of an activity in preorder
+    pub fn preorder_position(&self, index: usize) -> usize {
+        self.preorder().iter().position(|&i| i == index).unwrap_or(0)
+    }
+
+    /// Whether the activity is the last leaf-or-cluster in preorder
+    pub fn is_last_in_tree(&self, index: usize) -> bool {
+        self.preorder().last() == Some(&index)
+    }
+
+    /// Sibling after an activity
+    pub fn next_sibling(&self, index: usize) -> Option<usize> {
+        let parent = self.activities[index].parent?;
+        let siblings = &self.activities[parent].children;
+        let position = siblings.iter().position(|&i| i == index)?;
+        siblings.get(position + 1).copied()
+    }
+
+    /// Sibling before an activity
+    pub fn previous_sibling(&self, index: usize) -> Option<usize> {
+        let parent = self.activities[index].parent?;
+        let siblings = &self.activities[parent].children;
+        let position = siblings.iter().position(|&i| i == index)?;
+        position.checked_sub(1).map(|p| siblings[p])
+    }
+
+    /// Control mode of an activity's parent
+    pub fn parent_control_mode(&self, index: usize) -> Option<ControlMode> {
+        self.parent(index).map(|parent| self.activities[parent].sequencing.control_mode)
+    }
+
+    // Objectives
+
+    fn objective_definition(&self, index: usize, objective_id: Option<&str>) -> Option<&ObjectiveDefinition> {
+        let sequencing = &self.activities[index].sequencing;
+        match objective_id {
+            Some(id) => sequencing.objectives.iter().find(|objective| objective.id == id),
+            None => Some(sequencing.primary_objective()),
+        }
+    }
+
+    /// Objective state as seen by the activity, applying global read maps
+    pub fn objective_state(&self, index: usize, objective_id: Option<&str>) -> ObjectiveState {
+        let Some(definition) = self.objective_definition(index, objective_id) else {
+            return ObjectiveState::default();
+        };
+
+        let mut state = self.activities[index].state.objectives
+            .get(&definition.id)
+            .copied()
+            .unwrap_or_default();
+
+        for map in &definition.maps {
+            let Some(global) = self.global_objectives.get(&map.target_id) else {
+                continue;
+            };
+            if map.read_satisfied && global.progress_status {
+                state.progress_status = true;
+                state.satisfied_status = global.satisfied_status;
+            }
+            if map.read_normalized_measure && global.measure_status {
+                state.measure_status = true;
+                state.normalized_measure = global.normalized_measure;
+            }
+        }
+
+        state
+    }
+
+    /// Primary objective state of an activity
+    pub fn primary_objective_state(&self, index: usize) -> ObjectiveState {
+        self.objective_state(index, None)
+    }
+
+    /// Update the local state of the primary objective and write it through
+    /// any global objective maps
+    pub fn set_primary_objective_state(&mut self, index: usize, state: ObjectiveState) {
+        let definition = self.activities[index].sequencing.primary_objective().clone();
+        self.activities[index].state.objectives.insert(definition.id.clone(), state);
+
+        for map in &definition.maps {
+            let global = self.global_objectives.entry(map.target_id.clone()).or_default();
+            if map.write_satisfied {
+                global.progress_status = state.progress_status;
+                global.satisfied_status = state.satisfied_status;
+            }
+            if map.write_normalized_measure {
+                global.measure_status = state.measure_status;
+                global.normalized_measure = state.normalized_measure;
+            }
+        }
+    }
+
+    /// Record the tracking data a SCO reported for the current attempt
+    pub fn apply_runtime_data(&mut self, index: usize, data: &RuntimeData) {
+        {
+            let state = &mut self.activities[index].state;
+            state.attempt_absolute_duration += data.session_time;
+            state.attempt_progress_status = data.completion_status.is_some();
+            state.attempt_completion_status = data.completion_status.unwrap_or(false);
+            state.is_suspended = data.suspend;
+        }
+
+        let objective = ObjectiveState {
+            progress_status: data.success_status.is_some(),
+            satisfied_status: data.success_status.unwrap_or(false),
+            measure_status: data.score_scaled.is_some(),
+            normalized_measure: data.score_scaled.unwrap_or(0.0),
+        };
+        self.set_primary_objective_state(index, objective);
+    }
+
+    // Rule evaluation
+
+    /// Evaluate one condition for an activity; `None` is "unknown"
+    pub fn evaluate_condition(&self, index: usize, condition: &RuleCondition) -> Option<bool> {
+        let activity = &self.activities[index];
+        let state = &activity.state;
+        let objective = || self.objective_state(index, condition.referenced_objective.as_deref());
+
+        let value = match condition.condition {
+            ConditionType::Satisfied => {
+                let objective = objective();
+                objective.progress_status.then_some(objective.satisfied_status)
+            }
+            ConditionType::ObjectiveStatusKnown => Some(objective().progress_status),
+            ConditionType::ObjectiveMeasureKnown => Some(objective().measure_status),
+            ConditionType::ObjectiveMeasureGreaterThan => {
+                let objective = objective();
+                objective.measure_status.then(|| objective.normalized_measure > condition.measure_threshold)
+            }
+            ConditionType::ObjectiveMeasureLessThan => {
+                let objective = objective();
+                objective.measure_status.then(|| objective.normalized_measure < condition.measure_threshold)
+            }
+            ConditionType::Completed => state.attempt_progress_status.then_some(state.attempt_completion_status),
+            ConditionType::ActivityProgressKnown => Some(state.attempt_count > 0 && state.attempt_progress_status),
+            ConditionType::Attempted => Some(state.attempt_count > 0),
+            ConditionType::AttemptLimitExceeded => Some(self.attempt_limit_exceeded(index)),
+            ConditionType::TimeLimitExceeded => Some(self.duration_limit_exceeded(index)),
+            // Availability windows are not part of the 4th Edition conformance
+            // requirements and are never authored by our vendors
+            ConditionType::OutsideAvailableTimeRange => Some(false),
+            ConditionType::Always => Some(true),
+        };
+
+        if condition.negate {
+            value.map(|value| !value)
+        } else {
+            value
+        }
+    }
+
+    /// Rule Check Subprocess (UP.2.1)
+    fn rule_applies(&self, index: usize, combination: ConditionCombination, conditions: &[RuleCondition]) -> bool {
+        let values = conditions.iter().map(|condition| self.evaluate_condition(index, condition));
+        combine(values, combination) == Some(true)
+    }
+
+    /// Sequencing Rules Check Process (UP.2): the action of the first rule
+    /// among `rules` whose action is in `actions` and whose conditions hold
+    pub fn check_rules(&self, index: usize, rules: &[SequencingRule], actions: &[RuleAction]) -> Option<RuleAction> {
+        rules.iter()
+            .filter(|rule| actions.contains(&rule.action))
+            .find(|rule| self.rule_applies(index, rule.combination, &rule.conditions))
+            .map(|rule| rule.action)
+    }
+
+    /// Evaluate an activity's pre-condition rules for the given actions
+    pub fn check_pre_conditions(&self, index: usize, actions: &[RuleAction]) -> Option<RuleAction> {
+        self.check_rules(index, &self.activities[index].sequencing.pre_condition_rules, actions)
+    }
+
+    /// Evaluate an activity's exit condition rules
+    pub fn check_exit_conditions(&self, index: usize) -> Option<RuleAction> {
+        self.check_rules(index, &self.activities[index].sequencing.exit_condition_rules, &[RuleAction::Exit])
+    }
+
+    /// Evaluate an activity's post-condition rules
+    pub fn check_post_conditions(&self, index: usize) -> Option<RuleAction> {
+        self.check_rules(index, &self.activities[index].sequencing.post_condition_rules, &[
+            RuleAction::ExitParent,
+            RuleAction::ExitAll,
+            RuleAction::Retry,
+            RuleAction::RetryAll,
+            RuleAction::Continue,
+            RuleAction::Previous,
+        ])
+    }
+
+    fn attempt_limit_exceeded(&self, index: usize) -> bool {
+        let activity = &self.activities[index];
+        match activity.sequencing.limit_conditions.attempt_limit {
+            Some(limit) => activity.state.attempt_count > 0 && activity.state.attempt_count >= limit,
+            None => false,
+        }
+    }
+
+    fn duration_limit_exceeded(&self, index: usize) -> bool {
+        let activity = &self.activities[index];
+        match activity.sequencing.limit_conditions.attempt_absolute_duration_limit {
+            Some(limit) => activity.state.attempt_count > 0 && activity.state.attempt_absolute_duration >= limit,
+            None => false,
+        }
+    }
+
+    /// Limit Conditions Check Process (UP.1)
+    pub fn limit_conditions_violated(&self, index: usize) -> bool {
+        let activity = &self.activities[index];
+        if !activity.sequencing.delivery_controls.tracked {
+            return false;
+        }
+        // Limits only apply to starting a new attempt
+        if activity.state.is_active || activity.state.is_suspended {
+            return false;
+        }
+        self.attempt_limit_exceeded(index) || self.duration_limit_exceeded(index)
+    }
+
+    /// Check Activity Process (UP.5): whether the activity may not be delivered
+    pub fn is_unavailable(&self, index: usize) -> bool {
+        self.check_pre_conditions(index, &[RuleAction::Disabled]).is_some() || self.limit_conditions_violated(index)
+    }
+
+    /// Whether a hidden-from-choice rule applies to the activity
+    pub fn is_hidden_from_choice(&self, index: usize) -> bool {
+        self.check_pre_conditions(index, &[RuleAction::HiddenFromChoice]).is_some()
+    }
+
+    // Attempts
+
+    /// Begin a new attempt on an activity (or resume a suspended one)
+    pub fn begin_attempt(&mut self, index: usize) {
+        let state = &mut self.activities[index].state;
+        if state.is_active {
+            return;
+        }
+
+        if state.is_suspended {
+            state.is_suspended = false;
+        } else {
+            state.attempt_count += 1;
+            state.attempt_progress_status = false;
+            state.attempt_completion_status = false;
+            state.attempt_absolute_duration = 0.0;
+            if self.activities[index].sequencing.control_mode.use_current_attempt_objective_info || self.is_leaf(index) {
+                self.activities[index].state.objectives.clear();
+            }
+        }
+
+        self.activities[index].state.is_active = true;
+    }
+
+    /// End Attempt Process (UP.4)
+    pub fn end_attempt(&mut self, index: usize) {
+        if self.is_leaf(index) {
+            let activity = &self.activities[index];
+            let controls = activity.sequencing.delivery_controls;
+            if controls.tracked && !activity.state.is_suspended {
+                if !controls.completion_set_by_content && !activity.state.attempt_progress_status {
+                    let state = &mut self.activities[index].state;
+                    state.attempt_progress_status = true;
+                    state.attempt_completion_status = true;
+                }
+
+                let objective = self.primary_objective_state(index);
+                if !controls.objective_set_by_content && !objective.progress_status {
+                    self.set_primary_objective_state(index, ObjectiveState {
+                        progress_status: true,
+                        satisfied_status: true,
+                        ..objective
+                    });
+                }
+            }
+        } else {
+            let suspended = self.activities[index].children.iter()
+                .any(|&child| self.activities[child].state.is_suspended);
+            self.activities[index].state.is_suspended = suspended;
+        }
+
+        self.activities[index].state.is_active = false;
+        self.overall_rollup(index);
+    }
+
+    // Rollup
+
+    /// Overall Rollup Process (RB.1.5)
+    pub fn overall_rollup(&mut self, index: usize) {
+        for activity in self.path_to_root(index) {
+            if self.is_leaf(activity) {
+                if self.activities[activity].sequencing.primary_objective().satisfied_by_measure {
+                    self.objective_rollup_using_measure(activity);
+                }
+                continue;
+            }
+
+            self.measure_rollup(activity);
+            self.objective_rollup(activity);
+            self.progress_rollup(activity);
+        }
+    }
+
+    /// Measure Rollup Process (RB.1.1)
+    fn measure_rollup(&mut self, index: usize) {
+        let mut total = 0.0;
+        let mut counted = 0.0;
+        let mut valid = false;
+
+        for &child in &self.activities[index].children {
+            let activity = &self.activities[child];
+            if !activity.sequencing.delivery_controls.tracked {
+                continue;
+            }
+            let weight = activity.sequencing.rollup_controls.objective_measure_weight;
+            counted += weight;
+
+            let objective = self.primary_objective_state(child);
+            if objective.measure_status {
+                total += objective.normalized_measure * weight;
+                valid = true;
+            }
+        }
+
+        let mut objective = self.activities[index].state.objectives
+            .get(&self.activities[index].sequencing.primary_objective().id)
+            .copied()
+            .unwrap_or_default();
+        if valid && counted > 0.0 {
+            objective.measure_status = true;
+            objective.normalized_measure = total / counted;
+        } else {
+            objective.measure_status = false;
+        }
+        self.set_primary_objective_state(index, objective);
+    }
+
+    /// Objective Rollup Using Measure Process (RB.1.2.a)
+    fn objective_rollup_using_measure(&mut self, index: usize) {
+        let activity = &self.activities[index];
+        if activity.state.is_active && !activity.sequencing.rollup_controls.measure_satisfaction_if_active {
+            return;
+        }
+
+        let min_measure = activity.sequencing.primary_objective().min_normalized_measure;
+        let mut objective = self.primary_objective_state(index);
+        if objective.measure_status {
+            objective.progress_status = true;
+            objective.satisfied_status = objective.normalized_measure >= min_measure;
+        } else {
+            objective.progress_status = false;
+        }
+        self.set_primary_objective_state(index, objective);
+    }
+
+    /// Objective Rollup Process (RB.1.2)
+    fn objective_rollup(&mut self, index: usize) {
+        if self.activities[index].sequencing.primary_objective().satisfied_by_measure {
+            self.objective_rollup_using_measure(index);
+            return;
+        }
+
+        let rules = self.rollup_rules(index, RollupAction::NotSatisfied, RollupAction::Satisfied);
+        let mut objective = self.primary_objective_state(index);
+        let mut changed = false;
+
+        // Not satisfied is evaluated first so that satisfied takes precedence
+        if self.rollup_rule_applies(index, &rules, RollupAction::NotSatisfied) {
+            objective.progress_status = true;
+            objective.satisfied_status = false;
+            changed = true;
+        }
+        if self.rollup_rule_applies(index, &rules, RollupAction::Satisfied) {
+            objective.progress_status = true;
+            objective.satisfied_status = true;
+            changed = true;
+        }
+
+        if changed {
+            self.set_primary_objective_state(index, objective);
+        }
+    }
+
+    /// Activity Progress Rollup Process (RB.1.3)
+    fn progress_rollup(&mut self, index: usize) {
+        let rules = self.rollup_rules(index, RollupAction::Incomplete, RollupAction::Completed);
+
+        if self.rollup_rule_applies(index, &rules, RollupAction::Incomplete) {
+            let state = &mut self.activities[index].state;
+            state.attempt_progress_status = true;
+            state.attempt_completion_status = false;
+        }
+        if self.rollup_rule_applies(index, &rules, RollupAction::Completed) {
+            let state = &mut self.activities[index].state;
+            state.attempt_progress_status = true;
+            state.attempt_completion_status = true;
+        }
+    }
+
+    /// The authored rollup rules for a pair of actions, or the default rules
+    /// if none are authored for either
+    fn rollup_rules(&self, index: usize, negative: RollupAction, positive: RollupAction) -> Vec<RollupRule> {
+        let authored: Vec<RollupRule> = self.activities[index].sequencing.rollup_rules.iter()
+            .filter(|rule| rule.action == negative || rule.action == positive)
+            .cloned()
+            .collect();
+        if !authored.is_empty() {
+            return authored;
+        }
+
+        let (negative_condition, positive_condition) = match positive {
+            RollupAction::Satisfied => (ConditionType::ObjectiveStatusKnown, ConditionType::Satisfied),
+            _ => (ConditionType::ActivityProgressKnown, ConditionType::Completed),
+        };
+        let rule = |condition, action| RollupRule {
+            child_activity_set: ChildActivitySet::All,
+            combination: ConditionCombination::Any,
+            conditions: vec![RuleCondition {
+                condition,
+                referenced_objective: None,
+                measure_threshold: 0.0,
+                negate: false,
+            }],
+            action,
+        };
+
+        vec![rule(negative_condition, negative), rule(positive_condition, positive)]
+    }
+
+    /// Rollup Rule Check Subprocess (RB.1.4)
+    fn rollup_rule_applies(&self, index: usize, rules: &[RollupRule], action: RollupAction) -> bool {
+        rules.iter().filter(|rule| rule.action == action).any(|rule| {
+            let values: Vec<Option<bool>> = self.activities[index].children.iter()
+                .copied()
+                .filter(|&child| self.contributes_to_rollup(child, action))
+                .map(|child| {
+                    let conditions = rule.conditions.iter().map(|condition| self.evaluate_condition(child, condition));
+                    combine(conditions, rule.combination)
+                })
+                .collect();
+
+            if values.is_empty() {
+                return false;
+            }
+
+            let satisfied = values.iter().filter(|value| **value == Some(true)).count();
+            match rule.child_activity_set {
+                ChildActivitySet::All => satisfied == values.len(),
+                ChildActivitySet::Any => satisfied > 0,
+                ChildActivitySet::None => values.iter().all(|value| *value == Some(false)),
+                ChildActivitySet::AtLeastCount(count) => satisfied >= count as usize,
+                ChildActivitySet::AtLeastPercent(percent) => satisfied as f64 / values.len() as f64 >= percent,
+            }
+        })
+    }
+
+    /// Check Child for Rollup Subprocess (RB.1.4.2)
+    fn contributes_to_rollup(&self, child: usize, action: RollupAction) -> bool {
+        let activity = &self.activities[child];
+        if !activity.sequencing.delivery_controls.tracked {
+            return false;
+        }
+
+        let controls = activity.sequencing.rollup_controls;
+        let (enabled, consideration) = match action {
+            RollupAction::Satisfied => (controls.rollup_objective_satisfied, controls.required_for_satisfied),
+            RollupAction::NotSatisfied => (controls.rollup_objective_satisfied, controls.required_for_not_satisfied),
+            RollupAction::Completed => (controls.rollup_progress_completion, controls.required_for_completed),
+            RollupAction::Incomplete => (controls.rollup_progress_completion, controls.required_for_incomplete),
+        };
+        if !enabled {
+            return false;
+        }
+
+        let state = &activity.state;
+        match consideration {
+            RollupConsideration::Always => true,
+            RollupConsideration::IfAttempted => state.attempt_count > 0,
+            RollupConsideration::IfNotSkipped => self.check_pre_conditions(child, &[RuleAction::Skip]).is_none(),
+            RollupConsideration::IfNotSuspended => state.attempt_count > 0 && !state.is_suspended,
+        }
+    }
+}
+
+/// Combine condition values with three-valued logic
+fn combine<I: Iterator<Item = Option<bool>>>(values: I, combination: ConditionCombination) -> Option<bool> {
+    let values: Vec<Option<bool>> = values.collect();
+    if values.is_empty() {
+        return None;
+    }
+
+    match combination {
+        ConditionCombination::All => {
+            if values.contains(&Some(false)) {
+                Some(false)
+            } else if values.contains(&None) {
+                None
+            } else {
+                Some(true)
+            }
+        }
+        ConditionCombination::Any => {
+            if values.contains(&Some(true)) {
+                Some(true)
+            } else if values.contains(&None) {
+                None
+            } else {
+                Some(false)
+            }
+        }
+    }
+}
+
+/// Parse an ISO 8601 duration (`P[nD]T[nH][nM][nS]`) into seconds
+pub fn parse_iso8601_duration(value: &str) -> Option<f64> {
+    let rest = value.trim().strip_prefix('P')?;
+    let (date, time) = match rest.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (rest, None),
+    };
+
+    let mut seconds = 0.0;
+    let mut parse_part = |part: &str, units: &[(char, f64)]| -> Option<()> {
+        let mut number = String::new();
+        for c in part.chars() {
+            if c.is_ascii_digit() || c == '.' {
+                number.push(c);
+            } else {
+                let (_, factor) = units.iter().find(|(unit, _)| *unit == c)?;
+                seconds += number.parse::<f64>().ok()? * factor;
+                number.clear();
+            }
+        }
+        number.is_empty().then_some(())
+    };
+
+    parse_part(date, &[('Y', 365.0 * 86400.0), ('M', 30.0 * 86400.0), ('W', 7.0 * 86400.0), ('D', 86400.0)])?;
+    if let Some(time) = time {
+        if time.is_empty() {
+            return None;
+        }
+        parse_part(time, &[('H', 3600.0), ('M', 60.0), ('S', 1.0)])?;
+    }
+
+    Some(seconds)
+}
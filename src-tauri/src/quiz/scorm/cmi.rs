@@ -0,0 +1,1061 @@
+// SCORM run-time data models
+//
+// `CmiDataModel` is the tracking data of one learner on one SCO together with
+// the state of the run-time API instance the SCO talks to. It implements the
+// SCORM 1.2 and SCORM 2004 4th Edition data models: element access rights,
+// data types and ranges, the ordering rules of the `cmi.objectives` and
+// `cmi.interactions` collections, and the error code every API call leaves
+// behind for `GetLastError`.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+
+use super::activity_tree::parse_iso8601_duration;
+use super::sequencing::NavigationRequest;
+use super::ScormVersion;
+
+/// Largest `cmi.suspend_data` accepted from SCORM 1.2 content (CMIString4096)
+pub const SUSPEND_DATA_LIMIT_1_2: usize = 4096;
+
+/// Largest `cmi.suspend_data` accepted from SCORM 2004 content (SPM 64000)
+pub const SUSPEND_DATA_LIMIT_2004: usize = 64000;
+
+static TIMESPAN_1_2: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\d{2,4}):(\d{2}):(\d{2}(?:\.\d{1,2})?)$").unwrap());
+static TIME_1_2: Lazy<Regex> = Lazy::new(|| Regex::new(r"^([01]\d|2[0-3]):[0-5]\d:[0-5]\d(\.\d{1,2})?$").unwrap());
+static TIME_2004: Lazy<Regex> = Lazy::new(|| Regex::new(
+    r"^\d{4}(-(0[1-9]|1[0-2])(-(0[1-9]|[12]\d|3[01])(T([01]\d|2[0-3])(:[0-5]\d(:[0-5]\d(\.\d{1,2})?)?)?(Z|[+-]([01]\d|2[0-3])(:[0-5]\d)?)?)?)?)?$"
+).unwrap());
+static DECIMAL: Lazy<Regex> = Lazy::new(|| Regex::new(r"^-?\d+(\.\d+)?$").unwrap());
+static REAL: Lazy<Regex> = Lazy::new(|| Regex::new(r"^-?(\d+(\.\d*)?|\.\d+)$").unwrap());
+static LANGUAGE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?i)([a-z]{2,3}|[ix])(-[a-z0-9]{1,8})*$").unwrap());
+static LOCALIZED: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\{lang=([^}]*)\}").unwrap());
+static JUMP: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\{target=[^}\s]+\}jump$").unwrap());
+
+/// Run-time API error; the numeric code depends on the SCORM version
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CmiError {
+    #[error("The API instance is already initialized")]
+    AlreadyInitialized,
+
+    #[error("The API instance has been terminated")]
+    ContentInstanceTerminated,
+
+    #[error("Terminate called before Initialize")]
+    TerminationBeforeInitialization,
+
+    #[error("Terminate called after Terminate")]
+    TerminationAfterTermination,
+
+    #[error("GetValue called before Initialize")]
+    RetrieveBeforeInitialization,
+
+    #[error("GetValue called after Terminate")]
+    RetrieveAfterTermination,
+
+    #[error("SetValue called before Initialize")]
+    StoreBeforeInitialization,
+
+    #[error("SetValue called after Terminate")]
+    StoreAfterTermination,
+
+    #[error("Commit called before Initialize")]
+    CommitBeforeInitialization,
+
+    #[error("Commit called after Terminate")]
+    CommitAfterTermination,
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Cannot get {0}")]
+    GetFailure(String),
+
+    #[error("Cannot set {0}")]
+    SetFailure(String),
+
+    #[error("{0} is not a data model element")]
+    UndefinedElement(String),
+
+    #[error("{0} has not been set")]
+    NotInitialized(String),
+
+    #[error("{0} is read only")]
+    ReadOnly(String),
+
+    #[error("{0} is a keyword and cannot be set")]
+    Keyword(String),
+
+    #[error("{0} is write only")]
+    WriteOnly(String),
+
+    #[error("{0} cannot have children")]
+    NoChildren(String),
+
+    #[error("{0} is not a collection and cannot have a count")]
+    NoCount(String),
+
+    #[error("Value does not match the data type of {0}")]
+    TypeMismatch(String),
+
+    #[error("Value is out of range for {0}")]
+    OutOfRange(String),
+
+    #[error("{0} requires the interaction type to be set first")]
+    DependencyNotEstablished(String),
+}
+
+impl CmiError {
+    /// Error code reported by `GetLastError`
+    pub fn code(&self, version: ScormVersion) -> u16 {
+        let (v1_2, v2004) = match self {
+            Self::AlreadyInitialized => (101, 103),
+            Self::ContentInstanceTerminated => (101, 104),
+            Self::TerminationBeforeInitialization => (301, 112),
+            Self::TerminationAfterTermination => (101, 113),
+            Self::RetrieveBeforeInitialization => (301, 122),
+            Self::RetrieveAfterTermination => (101, 123),
+            Self::StoreBeforeInitialization => (301, 132),
+            Self::StoreAfterTermination => (101, 133),
+            Self::CommitBeforeInitialization => (301, 142),
+            Self::CommitAfterTermination => (101, 143),
+            Self::InvalidArgument(_) => (201, 201),
+            Self::GetFailure(_) => (201, 301),
+            Self::SetFailure(_) => (201, 351),
+            Self::UndefinedElement(_) => (201, 401),
+            Self::NotInitialized(_) => (0, 403),
+            Self::ReadOnly(_) => (403, 404),
+            Self::Keyword(_) => (402, 404),
+            Self::WriteOnly(_) => (404, 405),
+            Self::NoChildren(_) => (202, 301),
+            Self::NoCount(_) => (203, 301),
+            Self::TypeMismatch(_) => (405, 406),
+            Self::OutOfRange(_) => (405, 407),
+            Self::DependencyNotEstablished(_) => (201, 408),
+        };
+
+        match version {
+            ScormVersion::V1_2 => v1_2,
+            ScormVersion::V2004_3RD | ScormVersion::V2004_4TH => v2004,
+        }
+    }
+}
+
+/// Text returned by `GetErrorString` for an error code
+pub fn error_string(version: ScormVersion, code: u16) -> &'static str {
+    match version {
+        ScormVersion::V1_2 => match code {
+            0 => "No error",
+            101 => "General exception",
+            201 => "Invalid argument error",
+            202 => "Element cannot have children",
+            203 => "Element not an array - cannot have count",
+            301 => "Not initialized",
+            401 => "Not implemented error",
+            402 => "Invalid set value, element is a keyword",
+            403 => "Element is read only",
+            404 => "Element is write only",
+            405 => "Incorrect data type",
+            _ => "",
+        },
+        ScormVersion::V2004_3RD | ScormVersion::V2004_4TH => match code {
+            0 => "No Error",
+            101 => "General Exception",
+            102 => "General Initialization Failure",
+            103 => "Already Initialized",
+            104 => "Content Instance Terminated",
+            111 => "General Termination Failure",
+            112 => "Termination Before Initialization",
+            113 => "Termination After Termination",
+            122 => "Retrieve Data Before Initialization",
+            123 => "Retrieve Data After Termination",
+            132 => "Store Data Before Initialization",
+            133 => "Store Data After Termination",
+            142 => "Commit Before Initialization",
+            143 => "Commit After Termination",
+            201 => "General Argument Error",
+            301 => "General Get Failure",
+            351 => "General Set Failure",
+            391 => "General Commit Failure",
+            401 => "Undefined Data Model Element",
+            402 => "Unimplemented Data Model Element",
+            403 => "Data Model Element Value Not Initialized",
+            404 => "Data Model Element Is Read Only",
+            405 => "Data Model Element Is Write Only",
+            406 => "Data Model Element Type Mismatch",
+            407 => "Data Model Element Value Out Of Range",
+            408 => "Data Model Dependency Not Established",
+            _ => "",
+        },
+    }
+}
+
+/// State of the run-time API instance of a SCO launch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiState {
+    NotInitialized,
+    Running,
+    Terminated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DataType {
+    /// `_children` and `_version`
+    Keyword(&'static str),
+    /// `_count` of a collection
+    Count,
+    CharacterString(usize),
+    /// 2004 localized string with an optional `{lang=..}` delimiter
+    LocalizedString(usize),
+    Language,
+    Identifier(usize),
+    /// SCORM 1.2 CMIDecimal
+    Decimal,
+    /// SCORM 1.2 score: blank or a decimal between 0 and 100
+    Score,
+    Integer(i64, i64),
+    Real(Option<f64>, Option<f64>),
+    Vocabulary(&'static [&'static str]),
+    Timespan,
+    Time,
+    InteractionResult,
+    /// 2004 learner response or correct response pattern, typed by the interaction
+    Response,
+    NavigationRequest,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ElementDef {
+    access: Access,
+    data_type: DataType,
+}
+
+const LESSON_STATUS_1_2: &[&str] = &["passed", "completed", "failed", "incomplete", "browsed"];
+const STATUS_1_2: &[&str] = &["passed", "completed", "failed", "incomplete", "browsed", "not attempted"];
+const COMPLETION_STATUS: &[&str] = &["completed", "incomplete", "not attempted", "unknown"];
+const SUCCESS_STATUS: &[&str] = &["passed", "failed", "unknown"];
+const CREDIT: &[&str] = &["credit", "no-credit"];
+const ENTRY: &[&str] = &["ab-initio", "resume", ""];
+const MODE: &[&str] = &["browse", "normal", "review"];
+const TIME_LIMIT_ACTION: &[&str] = &["exit,message", "exit,no message", "continue,message", "continue,no message"];
+const INTERACTION_TYPES_1_2: &[&str] = &["true-false", "choice", "fill-in", "matching", "performance", "sequencing", "likert", "numeric"];
+const INTERACTION_TYPES_2004: &[&str] = &[
+    "true-false", "choice", "fill-in", "long-fill-in", "matching", "performance", "sequencing", "likert", "numeric", "other",
+];
+
+/// Read-only values the LMS keeps when a SCORM 2004 SCO starts a new attempt
+const LMS_MANAGED_2004: &[&str] = &[
+    "cmi.learner_id",
+    "cmi.learner_name",
+    "cmi.credit",
+    "cmi.mode",
+    "cmi.launch_data",
+    "cmi.completion_threshold",
+    "cmi.scaled_passing_score",
+    "cmi.max_time_allowed",
+    "cmi.time_limit_action",
+    "cmi.learner_preference.audio_level",
+    "cmi.learner_preference.language",
+    "cmi.learner_preference.delivery_speed",
+    "cmi.learner_preference.audio_captioning",
+];
+
+fn element_def(version: ScormVersion, pattern: &str) -> Option<ElementDef> {
+    use Access::*;
+    use DataType::*;
+
+    let (access, data_type) = match version {
+        ScormVersion::V1_2 => match pattern {
+            "cmi._children" => (ReadOnly, Keyword("core,suspend_data,launch_data,comments,objectives,student_data,student_preference,interactions")),
+            "cmi.core._children" => (ReadOnly, Keyword("student_id,student_name,lesson_location,credit,lesson_status,entry,score,total_time,lesson_mode,exit,session_time")),
+            "cmi.core.student_id" => (ReadOnly, Identifier(255)),
+            "cmi.core.student_name" => (ReadOnly, CharacterString(255)),
+            "cmi.core.lesson_location" => (ReadWrite, CharacterString(255)),
+            "cmi.core.credit" => (ReadOnly, Vocabulary(CREDIT)),
+            "cmi.core.lesson_status" => (ReadWrite, Vocabulary(LESSON_STATUS_1_2)),
+            "cmi.core.entry" => (ReadOnly, Vocabulary(ENTRY)),
+            "cmi.core.score._children" => (ReadOnly, Keyword("raw,min,max")),
+            "cmi.core.score.raw" | "cmi.core.score.min" | "cmi.core.score.max" => (ReadWrite, Score),
+            "cmi.core.total_time" => (ReadOnly, Timespan),
+            "cmi.core.lesson_mode" => (ReadOnly, Vocabulary(MODE)),
+            "cmi.core.exit" => (WriteOnly, Vocabulary(&["time-out", "suspend", "logout", ""])),
+            "cmi.core.session_time" => (WriteOnly, Timespan),
+            "cmi.suspend_data" => (ReadWrite, CharacterString(SUSPEND_DATA_LIMIT_1_2)),
+            "cmi.launch_data" => (ReadOnly, CharacterString(4096)),
+            "cmi.comments" => (ReadWrite, CharacterString(4096)),
+            "cmi.comments_from_lms" => (ReadOnly, CharacterString(4096)),
+            "cmi.objectives._children" => (ReadOnly, Keyword("id,score,status")),
+            "cmi.objectives._count" => (ReadOnly, Count),
+            "cmi.objectives.n.id" => (ReadWrite, Identifier(255)),
+            "cmi.objectives.n.score._children" => (ReadOnly, Keyword("raw,min,max")),
+            "cmi.objectives.n.score.raw" | "cmi.objectives.n.score.min" | "cmi.objectives.n.score.max" => (ReadWrite, Score),
+            "cmi.objectives.n.status" => (ReadWrite, Vocabulary(STATUS_1_2)),
+            "cmi.student_data._children" => (ReadOnly, Keyword("mastery_score,max_time_allowed,time_limit_action")),
+            "cmi.student_data.mastery_score" => (ReadOnly, Score),
+            "cmi.student_data.max_time_allowed" => (ReadOnly, Timespan),
+            "cmi.student_data.time_limit_action" => (ReadOnly, Vocabulary(TIME_LIMIT_ACTION)),
+            "cmi.student_preference._children" => (ReadOnly, Keyword("audio,language,speed,text")),
+            "cmi.student_preference.audio" => (ReadWrite, Integer(-1, 100)),
+            "cmi.student_preference.language" => (ReadWrite, CharacterString(255)),
+            "cmi.student_preference.speed" => (ReadWrite, Integer(-100, 100)),
+            "cmi.student_preference.text" => (ReadWrite, Integer(-1, 1)),
+            "cmi.interactions._children" => (ReadOnly, Keyword("id,objectives,time,type,correct_responses,weighting,student_response,result,latency")),
+            "cmi.interactions._count" => (ReadOnly, Count),
+            "cmi.interactions.n.id" => (WriteOnly, Identifier(255)),
+            "cmi.interactions.n.objectives._count" => (ReadOnly, Count),
+            "cmi.interactions.n.objectives.n.id" => (WriteOnly, Identifier(255)),
+            "cmi.interactions.n.time" => (WriteOnly, Time),
+            "cmi.interactions.n.type" => (WriteOnly, Vocabulary(INTERACTION_TYPES_1_2)),
+            "cmi.interactions.n.correct_responses._count" => (ReadOnly, Count),
+            "cmi.interactions.n.correct_responses.n.pattern" => (WriteOnly, CharacterString(255)),
+            "cmi.interactions.n.weighting" => (WriteOnly, Decimal),
+            "cmi.interactions.n.student_response" => (WriteOnly, CharacterString(255)),
+            "cmi.interactions.n.result" => (WriteOnly, InteractionResult),
+            "cmi.interactions.n.latency" => (WriteOnly, Timespan),
+            _ => return None,
+        },
+        ScormVersion::V2004_3RD | ScormVersion::V2004_4TH => match pattern {
+            "cmi._version" => (ReadOnly, Keyword("1.0")),
+            "cmi.comments_from_learner._children" => (ReadOnly, Keyword("comment,location,timestamp")),
+            "cmi.comments_from_learner._count" => (ReadOnly, Count),
+            "cmi.comments_from_learner.n.comment" => (ReadWrite, LocalizedString(4000)),
+            "cmi.comments_from_learner.n.location" => (ReadWrite, CharacterString(250)),
+            "cmi.comments_from_learner.n.timestamp" => (ReadWrite, Time),
+            "cmi.comments_from_lms._children" => (ReadOnly, Keyword("comment,location,timestamp")),
+            "cmi.comments_from_lms._count" => (ReadOnly, Count),
+            "cmi.comments_from_lms.n.comment" => (ReadOnly, LocalizedString(4000)),
+            "cmi.comments_from_lms.n.location" => (ReadOnly, CharacterString(250)),
+            "cmi.comments_from_lms.n.timestamp" => (ReadOnly, Time),
+            "cmi.completion_status" => (ReadWrite, Vocabulary(COMPLETION_STATUS)),
+            "cmi.completion_threshold" => (ReadOnly, Real(Some(0.0), Some(1.0))),
+            "cmi.credit" => (ReadOnly, Vocabulary(CREDIT)),
+            "cmi.entry" => (ReadOnly, Vocabulary(ENTRY)),
+            "cmi.exit" => (WriteOnly, Vocabulary(&["time-out", "suspend", "logout", "normal", ""])),
+            "cmi.interactions._children" => (ReadOnly, Keyword("id,type,objectives,timestamp,correct_responses,weighting,learner_response,result,latency,description")),
+            "cmi.interactions._count" => (ReadOnly, Count),
+            "cmi.interactions.n.id" => (ReadWrite, Identifier(4000)),
+            "cmi.interactions.n.type" => (ReadWrite, Vocabulary(INTERACTION_TYPES_2004)),
+            "cmi.interactions.n.objectives._count" => (ReadOnly, Count),
+            "cmi.interactions.n.objectives.n.id" => (ReadWrite, Identifier(4000)),
+            "cmi.interactions.n.timestamp" => (ReadWrite, Time),
+            "cmi.interactions.n.correct_responses._count" => (ReadOnly, Count),
+            "cmi.interactions.n.correct_responses.n.pattern" => (ReadWrite, Response),
+            "cmi.interactions.n.weighting" => (ReadWrite, Real(None, None)),
+            "cmi.interactions.n.learner_response" => (ReadWrite, Response),
+            "cmi.interactions.n.result" => (ReadWrite, InteractionResult),
+            "cmi.interactions.n.latency" => (ReadWrite, Timespan),
+            "cmi.interactions.n.description" => (ReadWrite, LocalizedString(250)),
+            "cmi.launch_data" => (ReadOnly, CharacterString(4000)),
+            "cmi.learner_id" => (ReadOnly, Identifier(4000)),
+            "cmi.learner_name" => (ReadOnly, LocalizedString(250)),
+            "cmi.learner_preference._children" => (ReadOnly, Keyword("audio_level,language,delivery_speed,audio_captioning")),
+            "cmi.learner_preference.audio_level" => (ReadWrite, Real(Some(0.0), None)),
+            "cmi.learner_preference.language" => (ReadWrite, Language),
+            "cmi.learner_preference.delivery_speed" => (ReadWrite, Real(Some(0.0), None)),
+            "cmi.learner_preference.audio_captioning" => (ReadWrite, Vocabulary(&["-1", "0", "1"])),
+            "cmi.location" => (ReadWrite, CharacterString(1000)),
+            "cmi.max_time_allowed" => (ReadOnly, Timespan),
+            "cmi.mode" => (ReadOnly, Vocabulary(MODE)),
+            "cmi.objectives._children" => (ReadOnly, Keyword("id,score,success_status,completion_status,progress_measure,description")),
+            "cmi.objectives._count" => (ReadOnly, Count),
+            "cmi.objectives.n.id" => (ReadWrite, Identifier(4000)),
+            "cmi.objectives.n.score._children" => (ReadOnly, Keyword("scaled,raw,min,max")),
+            "cmi.objectives.n.score.scaled" => (ReadWrite, Real(Some(-1.0), Some(1.0))),
+            "cmi.objectives.n.score.raw" | "cmi.objectives.n.score.min" | "cmi.objectives.n.score.max" => (ReadWrite, Real(None, None)),
+            "cmi.objectives.n.success_status" => (ReadWrite, Vocabulary(SUCCESS_STATUS)),
+            "cmi.objectives.n.completion_status" => (ReadWrite, Vocabulary(COMPLETION_STATUS)),
+            "cmi.objectives.n.progress_measure" => (ReadWrite, Real(Some(0.0), Some(1.0))),
+            "cmi.objectives.n.description" => (ReadWrite, LocalizedString(250)),
+            "cmi.progress_measure" => (ReadWrite, Real(Some(0.0), Some(1.0))),
+            "cmi.scaled_passing_score" => (ReadOnly, Real(Some(-1.0), Some(1.0))),
+            "cmi.score._children" => (ReadOnly, Keyword("scaled,raw,min,max")),
+            "cmi.score.scaled" => (ReadWrite, Real(Some(-1.0), Some(1.0))),
+            "cmi.score.raw" | "cmi.score.min" | "cmi.score.max" => (ReadWrite, Real(None, None)),
+            "cmi.session_time" => (WriteOnly, Timespan),
+            "cmi.success_status" => (ReadWrite, Vocabulary(SUCCESS_STATUS)),
+            "cmi.suspend_data" => (ReadWrite, CharacterString(SUSPEND_DATA_LIMIT_2004)),
+            "cmi.time_limit_action" => (ReadOnly, Vocabulary(TIME_LIMIT_ACTION)),
+            "cmi.total_time" => (ReadOnly, Timespan),
+            "adl.nav.request" => (ReadWrite, NavigationRequest),
+            _ => return None,
+        },
+    };
+
+    Some(ElementDef { access, data_type })
+}
+
+/// The element a collection entry must be created with (SCORM 2004)
+fn creation_field(collection: &str) -> Option<&'static str> {
+    match collection.rsplit('.').next() {
+        Some("objectives") | Some("interactions") => Some("id"),
+        Some("correct_responses") => Some("pattern"),
+        _ => None,
+    }
+}
+
+/// An element name split into its pattern (indices replaced by `n`) and the
+/// collection entries it addresses
+struct ElementPath {
+    pattern: String,
+    /// (collection, index, field after the index) from outermost to innermost
+    entries: Vec<(String, usize, String)>,
+}
+
+impl ElementPath {
+    fn parse(element: &str) -> Self {
+        let segments: Vec<&str> = element.split('.').collect();
+        let mut pattern = Vec::with_capacity(segments.len());
+        let mut entries = Vec::new();
+
+        for (i, segment) in segments.iter().enumerate() {
+            let index = (!segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| segment.parse::<usize>().ok())
+                .flatten();
+
+            match index {
+                Some(index) => {
+                    let field = segments.get(i + 1).copied().unwrap_or_default();
+                    entries.push((segments[..i].join("."), index, field.to_string()));
+                    pattern.push("n");
+                }
+                None => pattern.push(segment),
+            }
+        }
+
+        Self { pattern: pattern.join("."), entries }
+    }
+}
+
+/// Format seconds as a SCORM 1.2 CMITimespan (`HHHH:MM:SS.SS`)
+fn format_timespan_1_2(seconds: f64) -> String {
+    let centiseconds = (seconds.max(0.0) * 100.0).round() as u64;
+    let hours = (centiseconds / 360_000).min(9999);
+    let minutes = (centiseconds / 6000) % 60;
+    let secs = (centiseconds / 100) % 60;
+
+    format!("{:04}:{:02}:{:02}.{:02}", hours, minutes, secs, centiseconds % 100)
+}
+
+/// Format seconds as a SCORM 2004 timeinterval (ISO 8601 duration)
+fn format_timespan_2004(seconds: f64) -> String {
+    let centiseconds = (seconds.max(0.0) * 100.0).round() as u64;
+    let hours = centiseconds / 360_000;
+    let minutes = (centiseconds / 6000) % 60;
+    let secs = (centiseconds % 6000) as f64 / 100.0;
+
+    format!("PT{}H{}M{}S", hours, minutes, secs)
+}
+
+/// Whether a value is a list of non-empty identifiers separated by `separator`
+fn is_identifier_list(value: &str, separator: &str) -> bool {
+    value.split(separator).all(|id| !id.is_empty() && !id.chars().any(char::is_whitespace))
+}
+
+/// Check a 2004 learner response or correct response pattern against the
+/// response format of an interaction type
+fn is_valid_response(interaction_type: &str, value: &str, is_pattern: bool) -> bool {
+    if value.chars().count() > 4000 {
+        return false;
+    }
+
+    match interaction_type {
+        "true-false" => value == "true" || value == "false",
+        "choice" => {
+            if value.is_empty() {
+                return true;
+            }
+            let ids: Vec<&str> = value.split("[,]").collect();
+            let mut unique = ids.clone();
+            unique.sort_unstable();
+            unique.dedup();
+            is_identifier_list(value, "[,]") && unique.len() == ids.len()
+        }
+        "likert" => !value.is_empty() && !value.chars().any(char::is_whitespace),
+        "sequencing" => is_identifier_list(value, "[,]"),
+        "matching" => value.split("[,]").all(|pair| {
+            let parts: Vec<&str> = pair.split("[.]").collect();
+            parts.len() == 2 && parts.iter().all(|part| !part.is_empty() && !part.chars().any(char::is_whitespace))
+        }),
+        "numeric" if is_pattern => {
+            let bounds: Vec<&str> = value.split("[:]").collect();
+            bounds.len() <= 2 && bounds.iter().all(|bound| bound.is_empty() || REAL.is_match(bound))
+        }
+        "numeric" => REAL.is_match(value),
+        _ => true,
+    }
+}
+
+/// Tracking data and API state of one learner on one SCO
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CmiDataModel {
+    version: ScormVersion,
+
+    api_state: ApiState,
+
+    /// Element values keyed by the full element name
+    values: HashMap<String, String>,
+
+    /// Entry counts of collections keyed by the full collection name
+    counts: HashMap<String, usize>,
+
+    /// Error left by the last API call
+    #[serde(skip)]
+    last_error: Option<CmiError>,
+}
+
+impl CmiDataModel {
+    /// Create the data model of a learner's first launch of a SCO
+    pub fn new(version: ScormVersion, learner_id: &str, learner_name: &str) -> Self {
+        let defaults: &[(&str, &str)] = match version {
+            ScormVersion::V1_2 => &[
+                ("cmi.core.student_id", learner_id),
+                ("cmi.core.student_name", learner_name),
+                ("cmi.core.credit", "credit"),
+                ("cmi.core.lesson_status", "not attempted"),
+                ("cmi.core.entry", "ab-initio"),
+                ("cmi.core.total_time", "0000:00:00.00"),
+                ("cmi.core.lesson_mode", "normal"),
+                ("cmi.student_preference.audio", "0"),
+                ("cmi.student_preference.speed", "0"),
+                ("cmi.student_preference.text", "0"),
+            ],
+            ScormVersion::V2004_3RD | ScormVersion::V2004_4TH => &[
+                ("cmi.learner_id", learner_id),
+                ("cmi.learner_name", learner_name),
+                ("cmi.credit", "credit"),
+                ("cmi.mode", "normal"),
+                ("cmi.entry", "ab-initio"),
+                ("cmi.completion_status", "unknown"),
+                ("cmi.success_status", "unknown"),
+                ("cmi.total_time", "PT0H0M0S"),
+                ("cmi.learner_preference.audio_level", "1"),
+                ("cmi.learner_preference.language", ""),
+                ("cmi.learner_preference.delivery_speed", "1"),
+                ("cmi.learner_preference.audio_captioning", "0"),
+                ("adl.nav.request", "_none_"),
+            ],
+        };
+
+        Self {
+            version,
+            api_state: ApiState::NotInitialized,
+            values: defaults.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            counts: HashMap::new(),
+            last_error: None,
+        }
+    }
+
+    /// SCORM version of the data model
+    pub fn version(&self) -> ScormVersion {
+        self.version
+    }
+
+    /// State of the API instance
+    pub fn api_state(&self) -> ApiState {
+        self.api_state
+    }
+
+    /// Stored value of an element, without access checks
+    pub fn get(&self, element: &str) -> Option<&str> {
+        self.values.get(element).map(String::as_str)
+    }
+
+    /// All element values, with the SCORM 2004 completion and success status
+    /// evaluated against the completion threshold and scaled passing score
+    pub fn values(&self) -> HashMap<String, String> {
+        let mut values = self.values.clone();
+        for element in ["cmi.completion_status", "cmi.success_status"] {
+            if let Some(value) = self.effective_value(element) {
+                values.insert(element.to_string(), value);
+            }
+        }
+        values
+    }
+
+    /// Set an LMS-managed value such as `cmi.launch_data` or
+    /// `cmi.student_data.mastery_score`, bypassing access checks
+    pub fn set_lms_value(&mut self, element: &str, value: &str) {
+        self.values.insert(element.to_string(), value.to_string());
+    }
+
+    /// Accumulated `total_time` in seconds
+    pub fn total_time_seconds(&self) -> f64 {
+        self.values.get(self.element("cmi.core.total_time", "cmi.total_time"))
+            .and_then(|value| self.parse_timespan(value))
+            .unwrap_or(0.0)
+    }
+
+    /// Create the data model of the same learner's first launch of another
+    /// SCO of the package
+    pub fn for_another_sco(&self) -> Self {
+        let value = |v1_2, v2004| self.values.get(self.element(v1_2, v2004)).map(String::as_str).unwrap_or("");
+        Self::new(
+            self.version,
+            value("cmi.core.student_id", "cmi.learner_id"),
+            value("cmi.core.student_name", "cmi.learner_name"),
+        )
+    }
+
+    /// Prepare a new API instance for the next launch of the SCO. A suspended
+    /// session is resumed; otherwise a SCORM 2004 SCO starts a new attempt
+    /// with fresh data.
+    pub fn relaunch(&mut self) {
+        match self.api_state {
+            ApiState::NotInitialized => return,
+            // The content went away without terminating
+            ApiState::Running => self.end_session(),
+            ApiState::Terminated => {}
+        }
+
+        let suspended = self.values.get(self.element("cmi.core.exit", "cmi.exit"))
+            .is_some_and(|exit| exit == "suspend");
+
+        if !suspended && self.is_2004() {
+            let mut fresh = Self::new(self.version, "", "");
+            for element in LMS_MANAGED_2004 {
+                if let Some(value) = self.values.remove(*element) {
+                    fresh.values.insert(element.to_string(), value);
+                }
+            }
+            *self = fresh;
+        }
+
+        let entry = match (suspended, self.is_2004()) {
+            (true, _) => "resume",
+            (false, true) => "ab-initio",
+            (false, false) => "",
+        };
+        self.values.insert(self.element("cmi.core.entry", "cmi.entry").to_string(), entry.to_string());
+        self.values.remove(self.element("cmi.core.exit", "cmi.exit"));
+        self.values.remove(self.element("cmi.core.session_time", "cmi.session_time"));
+        if self.is_2004() {
+            self.values.insert("adl.nav.request".to_string(), "_none_".to_string());
+        }
+
+        self.api_state = ApiState::NotInitialized;
+        self.last_error = None;
+    }
+
+    /// `Initialize` / `LMSInitialize`
+    pub fn initialize(&mut self, parameter: &str) -> bool {
+        let result = self.try_initialize(parameter);
+        self.record(result).is_some()
+    }
+
+    /// `Terminate` / `LMSFinish`
+    pub fn terminate(&mut self, parameter: &str) -> bool {
+        let result = self.try_terminate(parameter);
+        self.record(result).is_some()
+    }
+
+    /// `Commit` / `LMSCommit`
+    pub fn commit(&mut self, parameter: &str) -> bool {
+        let result = self.try_commit(parameter);
+        self.record(result).is_some()
+    }
+
+    /// `GetValue` / `LMSGetValue`
+    pub fn get_value(&mut self, element: &str) -> String {
+        let result = self.try_get_value(element);
+        self.record(result).unwrap_or_default()
+    }
+
+    /// `SetValue` / `LMSSetValue`
+    pub fn set_value(&mut self, element: &str, value: &str) -> bool {
+        let result = self.try_set_value(element, value);
+        self.record(result).is_some()
+    }
+
+    /// `GetLastError` / `LMSGetLastError`
+    pub fn last_error(&self) -> u16 {
+        self.last_error.as_ref().map_or(0, |error| error.code(self.version))
+    }
+
+    /// `GetErrorString` / `LMSGetErrorString`
+    pub fn error_string(&self, code: &str) -> String {
+        code.parse()
+            .map(|code| error_string(self.version, code).to_string())
+            .unwrap_or_default()
+    }
+
+    /// `GetDiagnostic` / `LMSGetDiagnostic`; details the last error when
+    /// asked about it
+    pub fn diagnostic(&self, code: &str) -> String {
+        match &self.last_error {
+            Some(error) if code.is_empty() || code.parse::<u16>().ok() == Some(error.code(self.version)) => error.to_string(),
+            _ => self.error_string(code),
+        }
+    }
+
+    fn is_2004(&self) -> bool {
+        self.version != ScormVersion::V1_2
+    }
+
+    /// Pick the name of an element that moved between versions
+    fn element(&self, v1_2: &'static str, v2004: &'static str) -> &'static str {
+        if self.is_2004() { v2004 } else { v1_2 }
+    }
+
+    fn record<T>(&mut self, result: Result<T, CmiError>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.last_error = None;
+                Some(value)
+            }
+            Err(error) => {
+                self.last_error = Some(error);
+                None
+            }
+        }
+    }
+
+    fn count(&self, collection: &str) -> usize {
+        self.counts.get(collection).copied().unwrap_or(0)
+    }
+
+    fn parse_timespan(&self, value: &str) -> Option<f64> {
+        if self.is_2004() {
+            return parse_iso8601_duration(value);
+        }
+
+        let captures = TIMESPAN_1_2.captures(value)?;
+        let hours: f64 = captures[1].parse().ok()?;
+        let minutes: f64 = captures[2].parse().ok()?;
+        let seconds: f64 = captures[3].parse().ok()?;
+        Some(hours * 3600.0 + minutes * 60.0 + seconds)
+    }
+
+    fn number(&self, element: &str) -> Option<f64> {
+        self.values.get(element).and_then(|value| value.parse().ok())
+    }
+
+    /// Value of an element as reported to content; SCORM 2004 statuses are
+    /// evaluated by the LMS when a threshold or passing score is defined
+    fn effective_value(&self, element: &str) -> Option<String> {
+        if self.is_2004() {
+            match element {
+                "cmi.completion_status" => {
+                    if let Some(threshold) = self.number("cmi.completion_threshold") {
+                        let status = match self.number("cmi.progress_measure") {
+                            Some(progress) if progress >= threshold => "completed",
+                            Some(_) => "incomplete",
+                            None => "unknown",
+                        };
+                        return Some(status.to_string());
+                    }
+                }
+                "cmi.success_status" => {
+                    if let Some(passing_score) = self.number("cmi.scaled_passing_score") {
+                        let status = match self.number("cmi.score.scaled") {
+                            Some(score) if score >= passing_score => "passed",
+                            Some(_) => "failed",
+                            None => "unknown",
+                        };
+                        return Some(status.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.values.get(element).cloned()
+    }
+
+    /// Add the session time to the total time and apply the LMS's end of
+    /// session rules
+    fn end_session(&mut self) {
+        let session_time = self.values.get(self.element("cmi.core.session_time", "cmi.session_time"))
+            .and_then(|value| self.parse_timespan(value))
+            .unwrap_or(0.0);
+        let total_time = self.total_time_seconds() + session_time;
+        let total_time = if self.is_2004() {
+            format_timespan_2004(total_time)
+        } else {
+            format_timespan_1_2(total_time)
+        };
+        self.values.insert(self.element("cmi.core.total_time", "cmi.total_time").to_string(), total_time);
+
+        if !self.is_2004() {
+            let mastery_score = self.number("cmi.student_data.mastery_score");
+            let raw_score = self.number("cmi.core.score.raw");
+            let credit = self.values.get("cmi.core.credit").is_some_and(|credit| credit == "credit");
+
+            if let (true, Some(mastery_score), Some(raw_score)) = (credit, mastery_score, raw_score) {
+                let status = if raw_score >= mastery_score { "passed" } else { "failed" };
+                self.values.insert("cmi.core.lesson_status".to_string(), status.to_string());
+            } else if self.values.get("cmi.core.lesson_status").is_some_and(|status| status == "not attempted") {
+                self.values.insert("cmi.core.lesson_status".to_string(), "completed".to_string());
+            }
+        }
+
+        self.api_state = ApiState::Terminated;
+    }
+
+    fn try_initialize(&mut self, parameter: &str) -> Result<(), CmiError> {
+        if !parameter.is_empty() {
+            return Err(CmiError::InvalidArgument(parameter.to_string()));
+        }
+
+        match self.api_state {
+            ApiState::NotInitialized => {
+                self.api_state = ApiState::Running;
+                Ok(())
+            }
+            ApiState::Running => Err(CmiError::AlreadyInitialized),
+            ApiState::Terminated => Err(CmiError::ContentInstanceTerminated),
+        }
+    }
+
+    fn try_terminate(&mut self, parameter: &str) -> Result<(), CmiError> {
+        if !parameter.is_empty() {
+            return Err(CmiError::InvalidArgument(parameter.to_string()));
+        }
+
+        match self.api_state {
+            ApiState::NotInitialized => Err(CmiError::TerminationBeforeInitialization),
+            ApiState::Terminated => Err(CmiError::TerminationAfterTermination),
+            ApiState::Running => {
+                self.end_session();
+                Ok(())
+            }
+        }
+    }
+
+    fn try_commit(&self, parameter: &str) -> Result<(), CmiError> {
+        if !parameter.is_empty() {
+            return Err(CmiError::InvalidArgument(parameter.to_string()));
+        }
+
+        match self.api_state {
+            ApiState::NotInitialized => Err(CmiError::CommitBeforeInitialization),
+            ApiState::Terminated => Err(CmiError::CommitAfterTermination),
+            ApiState::Running => Ok(()),
+        }
+    }
+
+    fn lookup(&self, path: &ElementPath, element: &str) -> Result<ElementDef, CmiError> {
+        // Answered by the sequencer; the data model only defines the element
+        if self.is_2004() && element.starts_with("adl.nav.request_valid.") {
+            return Ok(ElementDef { access: Access::ReadOnly, data_type: DataType::Keyword("unknown") });
+        }
+
+        if let Some(def) = element_def(self.version, &path.pattern) {
+            return Ok(def);
+        }
+
+        if let Some(parent) = path.pattern.strip_suffix("._children") {
+            if element_def(self.version, parent).is_some() {
+                return Err(CmiError::NoChildren(element.to_string()));
+            }
+        }
+        if let Some(parent) = path.pattern.strip_suffix("._count") {
+            if element_def(self.version, parent).is_some() {
+                return Err(CmiError::NoCount(element.to_string()));
+            }
+        }
+
+        Err(CmiError::UndefinedElement(element.to_string()))
+    }
+
+    /// Check the collection indices of an element; returns the collection a
+    /// set creates a new entry in
+    fn check_indices(&self, path: &ElementPath, element: &str, for_set: bool) -> Result<Option<String>, CmiError> {
+        for (depth, (collection, index, field)) in path.entries.iter().enumerate() {
+            let count = self.count(collection);
+            if *index < count {
+                continue;
+            }
+
+            if !for_set {
+                return Err(CmiError::GetFailure(element.to_string()));
+            }
+
+            let innermost = depth + 1 == path.entries.len();
+            if *index > count || !innermost {
+                return Err(CmiError::SetFailure(element.to_string()));
+            }
+
+            if self.is_2004() && creation_field(collection).is_some_and(|required| required != field) {
+                return Err(CmiError::SetFailure(element.to_string()));
+            }
+
+            return Ok(Some(collection.clone()));
+        }
+
+        Ok(None)
+    }
+
+    fn try_get_value(&self, element: &str) -> Result<String, CmiError> {
+        match self.api_state {
+            ApiState::NotInitialized => return Err(CmiError::RetrieveBeforeInitialization),
+            ApiState::Terminated => return Err(CmiError::RetrieveAfterTermination),
+            ApiState::Running => {}
+        }
+
+        if element.is_empty() {
+            return Err(CmiError::GetFailure("an empty element name".to_string()));
+        }
+
+        let path = ElementPath::parse(element);
+        let def = self.lookup(&path, element)?;
+
+        match def.data_type {
+            DataType::Keyword(value) => return Ok(value.to_string()),
+            DataType::Count => {
+                self.check_indices(&path, element, false)?;
+                let collection = element.trim_end_matches("._count");
+                return Ok(self.count(collection).to_string());
+            }
+            _ => {}
+        }
+
+        if def.access == Access::WriteOnly {
+            return Err(CmiError::WriteOnly(element.to_string()));
+        }
+
+        self.check_indices(&path, element, false)?;
+
+        match self.effective_value(element) {
+            Some(value) => Ok(value),
+            None if self.is_2004() => Err(CmiError::NotInitialized(element.to_string())),
+            None => Ok(String::new()),
+        }
+    }
+
+    fn try_set_value(&mut self, element: &str, value: &str) -> Result<(), CmiError> {
+        match self.api_state {
+            ApiState::NotInitialized => return Err(CmiError::StoreBeforeInitialization),
+            ApiState::Terminated => return Err(CmiError::StoreAfterTermination),
+            ApiState::Running => {}
+        }
+
+        if element.is_empty() {
+            return Err(CmiError::SetFailure("an empty element name".to_string()));
+        }
+
+        let path = ElementPath::parse(element);
+        let def = self.lookup(&path, element)?;
+
+        if let DataType::Keyword(_) | DataType::Count = def.data_type {
+            return Err(CmiError::Keyword(element.to_string()));
+        }
+        if def.access == Access::ReadOnly {
+            return Err(CmiError::ReadOnly(element.to_string()));
+        }
+
+        let new_entry = self.check_indices(&path, element, true)?;
+        self.validate(&path, element, def.data_type, value)?;
+        if self.is_2004() {
+            self.check_identifier(&path, element, value)?;
+        }
+
+        self.values.insert(element.to_string(), value.to_string());
+        if let Some(collection) = new_entry {
+            *self.counts.entry(collection).or_insert(0) += 1;
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self, path: &ElementPath, element: &str, data_type: DataType, value: &str) -> Result<(), CmiError> {
+        let mismatch = || CmiError::TypeMismatch(element.to_string());
+        let out_of_range = || CmiError::OutOfRange(element.to_string());
+
+        let valid = match data_type {
+            DataType::Keyword(_) | DataType::Count => false,
+            DataType::CharacterString(max) => value.chars().count() <= max,
+            DataType::LocalizedString(max) => match LOCALIZED.captures(value) {
+                Some(captures) => {
+                    let language = &captures[1];
+                    (language.is_empty() || LANGUAGE.is_match(language))
+                        && value[captures[0].len()..].chars().count() <= max
+                }
+                None => value.chars().count() <= max,
+            },
+            DataType::Language => value.is_empty() || LANGUAGE.is_match(value),
+            DataType::Identifier(max) => {
+                !value.is_empty() && value.chars().count() <= max && !value.chars().any(char::is_whitespace)
+            }
+            DataType::Decimal => DECIMAL.is_match(value),
+            DataType::Score => {
+                if !value.is_empty() && !DECIMAL.is_match(value) {
+                    return Err(mismatch());
+                }
+                if value.parse::<f64>().is_ok_and(|score| !(0.0..=100.0).contains(&score)) {
+                    return Err(out_of_range());
+                }
+                true
+            }
+            DataType::Integer(min, max) => {
+                let number: i64 = value.parse().map_err(|_| mismatch())?;
+                if number < min || number > max {
+                    return Err(out_of_range());
+                }
+                true
+            }
+            DataType::Real(min, max) => {
+                if !REAL.is_match(value) {
+                    return Err(mismatch());
+                }
+                let number: f64 = value.parse().map_err(|_| mismatch())?;
+                if min.is_some_and(|min| number < min) || max.is_some_and(|max| number > max) {
+                    return Err(out_of_range());
+                }
+                true
+            }
+            DataType::Vocabulary(words) => words.contains(&value),
+            DataType::Timespan => self.parse_timespan(value).is_some(),
+            DataType::Time if self.is_2004() => TIME_2004.is_match(value),
+            DataType::Time => TIME_1_2.is_match(value),
+            DataType::InteractionResult if self.is_2004() => {
+                ["correct", "incorrect", "unanticipated", "neutral"].contains(&value) || REAL.is_match(value)
+            }
+            DataType::InteractionResult => {
+                ["correct", "wrong", "unanticipated", "neutral"].contains(&value) || DECIMAL.is_match(value)
+            }
+            DataType::Response => {
+                let (_, interaction, _) = &path.entries[0];
+                let interaction_type = self.values.get(&format!("cmi.interactions.{}.type", interaction))
+                    .ok_or_else(|| CmiError::DependencyNotEstablished(element.to_string()))?;
+                is_valid_response(interaction_type, value, element.ends_with(".pattern"))
+            }
+            DataType::NavigationRequest => {
+                value == "_none_" || NavigationRequest::parse(value).is_some() || JUMP.is_match(value)
+            }
+        };
+
+        if valid { Ok(()) } else { Err(mismatch()) }
+    }
+
+    /// Objective identifiers are unique within their collection and cannot
+    /// change once set
+    fn check_identifier(&self, path: &ElementPath, element: &str, value: &str) -> Result<(), CmiError> {
+        if path.pattern != "cmi.objectives.n.id" && path.pattern != "cmi.interactions.n.objectives.n.id" {
+            return Ok(());
+        }
+
+        if self.values.get(element).is_some_and(|current| current != value) {
+            return Err(CmiError::SetFailure(element.to_string()));
+        }
+
+        let (collection, index, _) = path.entries.last().expect("objective identifiers are collection entries");
+        let duplicate = (0..self.count(collection))
+            .filter(|other| other != index)
+            .any(|other| self.values.get(&format!("{}.{}.id", collection, other)).is_some_and(|id| id == value));
+        if duplicate {
+            return Err(CmiError::SetFailure(element.to_string()));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+// Activity tree construction from `imsmanifest.xml`
+//
+// The default organization becomes the root activity and its nested
+// `<item>` elements the rest of the tree. `<imsss:sequencing>` elements,
+// including ones referencing the manifest's `<imsss:sequencingCollection>`
+// via `IDRef`, are applied on top of the IMS SS defaults.
+
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use xml::reader::{EventReader, XmlEvent};
+
+use super::activity_tree::{
+    Activity, ActivityTree, ChildActivitySet, ConditionCombination, ConditionType, ObjectiveDefinition,
+    ObjectiveMap, RollupAction, RollupConsideration, RollupRule, RuleAction, RuleCondition,
+    SequencingDefinition, SequencingRule, parse_iso8601_duration,
+};
+
+/// Minimal element tree of the manifest, keyed by local (unprefixed) names
+#[derive(Debug, Clone, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn bool_attr(&self, name: &str, default: bool) -> bool {
+        match self.attr(name) {
+            Some(value) => value.trim() == "true",
+            None => default,
+        }
+    }
+
+    fn f64_attr(&self, name: &str, default: f64) -> f64 {
+        self.attr(name).and_then(|value| value.trim().parse().ok()).unwrap_or(default)
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+fn parse_document(xml: &str) -> Result<Element> {
+    let mut stack: Vec<Element> = vec![Element::default()];
+
+    for event in EventReader::from_str(xml) {
+        match event.map_err(|e| anyhow!("Invalid manifest XML: {}", e))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes.into_iter()
+                        .map(|attribute| (attribute.name.local_name, attribute.value))
+                        .collect(),
+                    children: Vec::new(),
+                    text: String::new(),
+                });
+            }
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().ok_or_else(|| anyhow!("Unbalanced manifest XML"))?;
+                stack.last_mut()
+                    .ok_or_else(|| anyhow!("Unbalanced manifest XML"))?
+                    .children
+                    .push(element);
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    stack.pop()
+        .and_then(|document| document.children.into_iter().next())
+        .ok_or_else(|| anyhow!("Manifest has no root element"))
+}
+
+/// Build the activity tree of a manifest's default organization
+pub fn parse_activity_tree(manifest: &str) -> Result<ActivityTree> {
+    let root = parse_document(manifest)?;
+    if root.name != "manifest" {
+        return Err(anyhow!("Root element is <{}>, expected <manifest>", root.name));
+    }
+
+    let resources = parse_resources(&root);
+    let collection: HashMap<&str, &Element> = root.child("sequencingCollection")
+        .map(|collection| collection.children_named("sequencing")
+            .filter_map(|sequencing| sequencing.attr("ID").map(|id| (id, sequencing)))
+            .collect())
+        .unwrap_or_default();
+
+    let organizations = root.child("organizations")
+        .ok_or_else(|| anyhow!("Manifest has no <organizations>"))?;
+    let organization = organizations.attr("default")
+        .and_then(|default| organizations.children_named("organization").find(|org| org.attr("identifier") == Some(default)))
+        .or_else(|| organizations.child("organization"))
+        .ok_or_else(|| anyhow!("Manifest has no <organization>"))?;
+
+    let mut root_activity = Activity::new(
+        organization.attr("identifier").unwrap_or("organization"),
+        &title_of(organization),
+        None,
+    );
+    root_activity.sequencing = parse_sequencing(organization.child("sequencing"), &collection)?;
+
+    let mut tree = ActivityTree::new(root_activity);
+    let root_index = tree.root();
+    for item in organization.children_named("item") {
+        add_item(&mut tree, root_index, item, &resources, &collection)?;
+    }
+
+    Ok(tree)
+}
+
+fn add_item(
+    tree: &mut ActivityTree,
+    parent: usize,
+    item: &Element,
+    resources: &HashMap<String, String>,
+    collection: &HashMap<&str, &Element>,
+) -> Result<()> {
+    let identifier = item.attr("identifier")
+        .ok_or_else(|| anyhow!("<item> without an identifier"))?;
+
+    let href = item.attr("identifierref")
+        .map(|resource| resources.get(resource)
+            .cloned()
+            .ok_or_else(|| anyhow!("Item {} references unknown resource {}", identifier, resource)))
+        .transpose()?
+        .map(|href| with_parameters(&href, item.attr("parameters")));
+
+    let mut activity = Activity::new(identifier, &title_of(item), href);
+    activity.is_visible = item.bool_attr("isvisible", true);
+    activity.sequencing = parse_sequencing(item.child("sequencing"), collection)?;
+
+    let index = tree.add_child(parent, activity);
+    for child in item.children_named("item") {
+        add_item(tree, index, child, resources, collection)?;
+    }
+
+    Ok(())
+}
+
+fn title_of(element: &Element) -> String {
+    element.child("title").map(|title| title.text.trim().to_string()).unwrap_or_default()
+}
+
+/// Resource identifier to launch location, resolving `xml:base`
+fn parse_resources(root: &Element) -> HashMap<String, String> {
+    let Some(resources) = root.child("resources") else {
+        return HashMap::new();
+    };
+    let base = resources.attr("base").unwrap_or("");
+
+    resources.children_named("resource")
+        .filter_map(|resource| {
+            let identifier = resource.attr("identifier")?;
+            let href = resource.attr("href")?;
+            let resource_base = resource.attr("base").unwrap_or("");
+            Some((identifier.to_string(), format!("{}{}{}", base, resource_base, href)))
+        })
+        .collect()
+}
+
+/// Append an item's `parameters` to its resource href (CAM 3.4.1.10)
+fn with_parameters(href: &str, parameters: Option<&str>) -> String {
+    let Some(parameters) = parameters.map(str::trim).filter(|p| !p.is_empty()) else {
+        return href.to_string();
+    };
+
+    let parameters = parameters.trim_start_matches(['?', '&']);
+    if parameters.starts_with('#') {
+        if href.contains('#') {
+            href.to_string()
+        } else {
+            format!("{}{}", href, parameters)
+        }
+    } else if href.contains('?') {
+        format!("{}&{}", href, parameters)
+    } else {
+        format!("{}?{}", href, parameters)
+    }
+}
+
+fn parse_sequencing(element: Option<&Element>, collection: &HashMap<&str, &Element>) -> Result<SequencingDefinition> {
+    let mut definition = SequencingDefinition::default();
+    let Some(element) = element else {
+        return Ok(definition);
+    };
+
+    if let Some(id_ref) = element.attr("IDRef") {
+        let shared = collection.get(id_ref)
+            .ok_or_else(|| anyhow!("Sequencing references unknown collection entry {}", id_ref))?;
+        apply_sequencing(&mut definition, shared)?;
+    }
+    apply_sequencing(&mut definition, element)?;
+
+    Ok(definition)
+}
+
+/// Override the parts of a definition an `<imsss:sequencing>` element specifies
+fn apply_sequencing(definition: &mut SequencingDefinition, element: &Element) -> Result<()> {
+    if let Some(control_mode) = element.child("controlMode") {
+        let mode = &mut definition.control_mode;
+        mode.choice = control_mode.bool_attr("choice", true);
+        mode.choice_exit = control_mode.bool_attr("choiceExit", true);
+        mode.flow = control_mode.bool_attr("flow", false);
+        mode.forward_only = control_mode.bool_attr("forwardOnly", false);
+        mode.use_current_attempt_objective_info = control_mode.bool_attr("useCurrentAttemptObjectiveInfo", true);
+        mode.use_current_attempt_progress_info = control_mode.bool_attr("useCurrentAttemptProgressInfo", true);
+    }
+
+    if let Some(rules) = element.child("sequencingRules") {
+        definition.pre_condition_rules = parse_rules(rules, "preConditionRule")?;
+        definition.exit_condition_rules = parse_rules(rules, "exitConditionRule")?;
+        definition.post_condition_rules = parse_rules(rules, "postConditionRule")?;
+    }
+
+    if let Some(limits) = element.child("limitConditions") {
+        definition.limit_conditions.attempt_limit = limits.attr("attemptLimit")
+            .and_then(|limit| limit.trim().parse().ok())
+            .filter(|limit| *limit > 0);
+        definition.limit_conditions.attempt_absolute_duration_limit = limits.attr("attemptAbsoluteDurationLimit")
+            .and_then(parse_iso8601_duration);
+    }
+
+    if let Some(rollup) = element.child("rollupRules") {
+        let controls = &mut definition.rollup_controls;
+        controls.rollup_objective_satisfied = rollup.bool_attr("rollupObjectiveSatisfied", true);
+        controls.rollup_progress_completion = rollup.bool_attr("rollupProgressCompletion", true);
+        controls.objective_measure_weight = rollup.f64_attr("objectiveMeasureWeight", 1.0);
+
+        definition.rollup_rules = rollup.children_named("rollupRule")
+            .map(parse_rollup_rule)
+            .collect::<Result<_>>()?;
+    }
+
+    if let Some(considerations) = element.child("rollupConsiderations") {
+        let controls = &mut definition.rollup_controls;
+        let consideration = |name: &str| considerations.attr(name)
+            .and_then(RollupConsideration::from_token)
+            .unwrap_or(RollupConsideration::Always);
+        controls.required_for_satisfied = consideration("requiredForSatisfied");
+        controls.required_for_not_satisfied = consideration("requiredForNotSatisfied");
+        controls.required_for_completed = consideration("requiredForCompleted");
+        controls.required_for_incomplete = consideration("requiredForIncomplete");
+        controls.measure_satisfaction_if_active = considerations.bool_attr("measureSatisfactionIfActive", true);
+    }
+
+    if let Some(objectives) = element.child("objectives") {
+        definition.objectives = parse_objectives(objectives);
+    }
+
+    if let Some(delivery) = element.child("deliveryControls") {
+        let controls = &mut definition.delivery_controls;
+        controls.tracked = delivery.bool_attr("tracked", true);
+        controls.completion_set_by_content = delivery.bool_attr("completionSetByContent", false);
+        controls.objective_set_by_content = delivery.bool_attr("objectiveSetByContent", false);
+    }
+
+    Ok(())
+}
+
+fn parse_rules(rules: &Element, name: &str) -> Result<Vec<SequencingRule>> {
+    rules.children_named(name).map(|rule| -> Result<SequencingRule> {
+        let action = rule.child("ruleAction")
+            .and_then(|action| action.attr("action"))
+            .ok_or_else(|| anyhow!("<{}> without a rule action", name))?;
+        let action = RuleAction::from_token(action)
+            .ok_or_else(|| anyhow!("Unknown sequencing rule action {}", action))?;
+
+        let conditions = rule.child("ruleConditions");
+        let combination = parse_combination(conditions, ConditionCombination::All);
+        let conditions = conditions
+            .map(|conditions| conditions.children_named("ruleCondition").map(parse_condition).collect::<Result<Vec<_>>>())
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(SequencingRule { combination, conditions, action })
+    }).collect()
+}
+
+fn parse_rollup_rule(rule: &Element) -> Result<RollupRule> {
+    let action = rule.child("rollupAction")
+        .and_then(|action| action.attr("action"))
+        .ok_or_else(|| anyhow!("<rollupRule> without a rollup action"))?;
+    let action = RollupAction::from_token(action)
+        .ok_or_else(|| anyhow!("Unknown rollup action {}", action))?;
+
+    let child_activity_set = match rule.attr("childActivitySet").unwrap_or("all") {
+        "all" => ChildActivitySet::All,
+        "any" => ChildActivitySet::Any,
+        "none" => ChildActivitySet::None,
+        "atLeastCount" => ChildActivitySet::AtLeastCount(rule.attr("minimumCount").and_then(|count| count.parse().ok()).unwrap_or(0)),
+        "atLeastPercent" => ChildActivitySet::AtLeastPercent(rule.f64_attr("minimumPercent", 0.0)),
+        other => return Err(anyhow!("Unknown child activity set {}", other)),
+    };
+
+    let conditions = rule.child("rollupConditions");
+    let combination = parse_combination(conditions, ConditionCombination::Any);
+    let conditions = conditions
+        .map(|conditions| conditions.children_named("rollupCondition").map(parse_condition).collect::<Result<Vec<_>>>())
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(RollupRule { child_activity_set, combination, conditions, action })
+}
+
+fn parse_combination(conditions: Option<&Element>, default: ConditionCombination) -> ConditionCombination {
+    match conditions.and_then(|conditions| conditions.attr("conditionCombination")) {
+        Some("all") => ConditionCombination::All,
+        Some("any") => ConditionCombination::Any,
+        _ => default,
+    }
+}
+
+fn parse_condition(condition: &Element) -> Result<RuleCondition> {
+    let token = condition.attr("condition")
+        .ok_or_else(|| anyhow!("<{}> without a condition", condition.name))?;
+
+    Ok(RuleCondition {
+        condition: ConditionType::from_token(token)
+            .ok_or_else(|| anyhow!("Unknown rule condition {}", token))?,
+        referenced_objective: condition.attr("referencedObjective").map(|id| id.to_string()),
+        measure_threshold: condition.f64_attr("measureThreshold", 0.0),
+        negate: condition.attr("operator") == Some("not"),
+    })
+}
+
+fn parse_objectives(objectives: &Element) -> Vec<ObjectiveDefinition> {
+    let parse = |objective: &Element, primary: bool| ObjectiveDefinition {
+        id: objective.attr("objectiveID").unwrap_or("").to_string(),
+        primary,
+        satisfied_by_measure: objective.bool_attr("satisfiedByMeasure", false),
+        min_normalized_measure: objective.child("minNormalizedMeasure")
+            .and_then(|measure| measure.text.trim().parse().ok())
+            .unwrap_or(1.0),
+        maps: objective.children_named("mapInfo")
+            .filter_map(|map| Some(ObjectiveMap {
+                target_id: map.attr("targetObjectiveID")?.to_string(),
+                read_satisfied: map.bool_attr("readSatisfiedStatus", true),
+                read_normalized_measure: map.bool_attr("readNormalizedMeasure", true),
+                write_satisfied: map.bool_attr("writeSatisfiedStatus", false),
+                write_normalized_measure: map.bool_attr("writeNormalizedMeasure", false),
+            }))
+            .collect(),
+    };
+
+    let mut definitions: Vec<ObjectiveDefinition> = objectives.child("primaryObjective")
+        .map(|primary| parse(primary, true))
+        .into_iter()
+        .chain(objectives.children_named("objective").map(|objective| parse(objective, false)))
+        .collect();
+
+    if !definitions.iter().any(|objective| objective.primary) {
+        definitions.insert(0, ObjectiveDefinition::implicit_primary());
+    }
+
+    definitions
+}
@@ -1,12 +1,27 @@
+pub mod activity_tree;
+pub mod cmi;
+pub mod manifest;
+pub mod sequencing;
+pub mod store;
+
+#[cfg(test)]
+mod tests;
+
+pub use activity_tree::{Activity, ActivityTree, RuntimeData};
+pub use cmi::{ApiState, CmiDataModel, CmiError};
+pub use sequencing::{NavigationRequest, SequencingError, SequencingOutcome, SequencingSession};
+pub use store::ScormStore;
+
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use anyhow::{Result, anyhow};
+use sqlx::SqlitePool;
 use zip::ZipArchive;
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{Read, Seek, Write};
 use tracing::{debug, info, warn, error};
 use xml::reader::{EventReader, XmlEvent};
 
@@ -183,8 +198,17 @@ pub struct ScormSession {
     /// Session interactions
     pub interactions: Vec<ScormInteraction>,
     
-    /// Session data
-    pub data: HashMap<String, String>,
+    /// Run-time tracking data of the SCO currently delivered
+    pub cmi: CmiDataModel,
+    
+    /// Activity the current tracking data belongs to, once sequencing has
+    /// delivered one
+    #[serde(default)]
+    pub activity_id: Option<String>,
+    
+    /// Tracking data of the other SCOs delivered in the session, by activity ID
+    #[serde(default)]
+    pub activity_cmi: HashMap<String, CmiDataModel>,
     
     /// Created at timestamp
     pub created_at: DateTime<Utc>,
@@ -196,6 +220,66 @@ pub struct ScormSession {
     pub completed_at: Option<DateTime<Utc>>,
 }
 
+impl ScormSession {
+    /// Switch the run-time API to the SCO of an activity, keeping the tracking
+    /// data of the SCO being left so that it resumes from it
+    fn deliver(&mut self, activity_id: &str) {
+        match self.activity_id.as_deref() {
+            // The data created with the session is that of the first SCO
+            None => self.activity_id = Some(activity_id.to_string()),
+            Some(current) if current == activity_id => {},
+            Some(current) => {
+                let next = self.activity_cmi.remove(activity_id)
+                    .unwrap_or_else(|| self.cmi.for_another_sco());
+                let previous = std::mem::replace(&mut self.cmi, next);
+                self.activity_cmi.insert(current.to_string(), previous);
+                self.activity_id = Some(activity_id.to_string());
+            },
+        }
+        self.cmi.relaunch();
+    }
+    
+    /// Update the session summary from the tracking data
+    fn refresh_from_cmi(&mut self) {
+        let values = self.cmi.values();
+        let get = |key: &str| values.get(key).map(String::as_str);
+        let score = |key: &str| get(key).and_then(|value| value.parse::<f32>().ok());
+        
+        match self.cmi.version() {
+            ScormVersion::V1_2 => {
+                self.state = match get("cmi.core.lesson_status") {
+                    Some("passed") => ScormActivityState::Passed,
+                    Some("failed") => ScormActivityState::Failed,
+                    Some("completed") => ScormActivityState::Completed,
+                    Some("incomplete") => ScormActivityState::Incomplete,
+                    Some("browsed") => ScormActivityState::Attempted,
+                    Some("not attempted") if self.cmi.api_state() == ApiState::NotInitialized => ScormActivityState::NotAttempted,
+                    Some("not attempted") => ScormActivityState::Attempted,
+                    _ => ScormActivityState::Unknown,
+                };
+                self.score = score("cmi.core.score.raw");
+                self.max_score = score("cmi.core.score.max");
+                self.min_score = score("cmi.core.score.min");
+                self.total_time = get("cmi.core.total_time").map(str::to_string);
+            },
+            ScormVersion::V2004_3RD | ScormVersion::V2004_4TH => {
+                self.state = match (get("cmi.success_status"), get("cmi.completion_status")) {
+                    (Some("passed"), _) => ScormActivityState::Passed,
+                    (Some("failed"), _) => ScormActivityState::Failed,
+                    (_, Some("completed")) => ScormActivityState::Completed,
+                    (_, Some("incomplete")) => ScormActivityState::Incomplete,
+                    _ if self.cmi.api_state() == ApiState::NotInitialized => self.state,
+                    _ => ScormActivityState::Attempted,
+                };
+                self.score = score("cmi.score.raw");
+                self.max_score = score("cmi.score.max");
+                self.min_score = score("cmi.score.min");
+                self.total_time = get("cmi.total_time").map(str::to_string);
+            },
+        }
+    }
+}
+
 /// SCORM service
 pub struct ScormService {
     /// Package storage directory
@@ -206,6 +290,15 @@ pub struct ScormService {
     
     /// Sessions
     sessions: HashMap<Uuid, ScormSession>,
+    
+    /// Activity trees of SCORM 2004 packages
+    activity_trees: HashMap<Uuid, ActivityTree>,
+    
+    /// Sequencing state of sessions on SCORM 2004 packages
+    sequencing_sessions: HashMap<Uuid, SequencingSession>,
+    
+    /// Persistent storage of packages and sessions
+    store: Option<ScormStore>,
 }
 
 impl ScormService {
@@ -220,11 +313,55 @@ impl ScormService {
             package_dir,
             packages: HashMap::new(),
             sessions: HashMap::new(),
+            activity_trees: HashMap::new(),
+            sequencing_sessions: HashMap::new(),
+            store: None,
         })
     }
     
+    /// Persist packages and sessions to SQLite, loading those stored earlier
+    pub async fn with_store(mut self, pool: SqlitePool) -> Result<Self> {
+        let store = ScormStore::new(pool).await?;
+        
+        for package in store.load_packages().await? {
+            if package.version != ScormVersion::V1_2 {
+                let manifest_path = self.package_dir.join(package.id.to_string()).join("imsmanifest.xml");
+                let activity_tree = fs::read_to_string(&manifest_path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|manifest_content| manifest::parse_activity_tree(&manifest_content));
+                match activity_tree {
+                    Ok(activity_tree) => {
+                        self.activity_trees.insert(package.id, activity_tree);
+                    },
+                    Err(e) => warn!("Failed to rebuild the activity tree of SCORM package {}: {}", package.id, e),
+                }
+            }
+            self.packages.insert(package.id, package);
+        }
+        
+        for (session, sequencing) in store.load_sessions().await? {
+            if let Some(sequencing) = sequencing {
+                self.sequencing_sessions.insert(session.id, sequencing);
+            }
+            self.sessions.insert(session.id, session);
+        }
+        
+        info!("Loaded {} SCORM packages and {} sessions", self.packages.len(), self.sessions.len());
+        self.store = Some(store);
+        Ok(self)
+    }
+    
+    /// Write a session and its sequencing state to the store
+    async fn persist_session(&self, session_id: &Uuid) -> Result<()> {
+        let (Some(store), Some(session)) = (&self.store, self.sessions.get(session_id)) else {
+            return Ok(());
+        };
+        
+        store.save_session(session, self.sequencing_sessions.get(session_id)).await
+    }
+    
     /// Import a SCORM package
-    pub fn import_package(&mut self, package_path: &Path) -> Result<Uuid> {
+    pub async fn import_package(&mut self, package_path: &Path) -> Result<Uuid> {
         // Open the package file
         let file = File::open(package_path)?;
         
@@ -237,13 +374,18 @@ impl ScormService {
         }
         
         // Extract the manifest file
-        let mut manifest_file = archive.by_name("imsmanifest.xml")?;
         let mut manifest_content = String::new();
-        manifest_file.read_to_string(&mut manifest_content)?;
+        archive.by_name("imsmanifest.xml")?.read_to_string(&mut manifest_content)?;
         
         // Parse the manifest file
         let metadata = self.parse_manifest(&manifest_content)?;
         
+        // SCORM 2004 content is sequenced over the organization's activity tree
+        let activity_tree = match metadata.version {
+            ScormVersion::V1_2 => None,
+            ScormVersion::V2004_3RD | ScormVersion::V2004_4TH => Some(manifest::parse_activity_tree(&manifest_content)?),
+        };
+        
         // Create a directory for the package
         let package_dir = self.package_dir.join(metadata.id.to_string());
         fs::create_dir_all(&package_dir)?;
@@ -268,7 +410,13 @@ impl ScormService {
         }
         
         // Store the package metadata
+        if let Some(store) = &self.store {
+            store.save_package(&metadata).await?;
+        }
         self.packages.insert(metadata.id, metadata.clone());
+        if let Some(activity_tree) = activity_tree {
+            self.activity_trees.insert(metadata.id, activity_tree);
+        }
         
         Ok(metadata.id)
     }
@@ -359,7 +507,7 @@ impl ScormService {
     }
     
     /// Delete a package
-    pub fn delete_package(&mut self, id: &Uuid) -> Result<()> {
+    pub async fn delete_package(&mut self, id: &Uuid) -> Result<()> {
         // Remove the package from the map
         if self.packages.remove(id).is_none() {
             return Err(anyhow!("Package not found"));
@@ -371,18 +519,25 @@ impl ScormService {
             fs::remove_dir_all(package_dir)?;
         }
         
+        if let Some(store) = &self.store {
+            store.delete_package(id).await?;
+        }
+        
         // Remove any sessions for this package
         self.sessions.retain(|_, session| session.package_id != *id);
+        self.activity_trees.remove(id);
+        let sessions = &self.sessions;
+        self.sequencing_sessions.retain(|session_id, _| sessions.contains_key(session_id));
         
         Ok(())
     }
     
     /// Create a new session
-    pub fn create_session(&mut self, package_id: &Uuid, user_id: &Uuid) -> Result<Uuid> {
+    pub async fn create_session(&mut self, package_id: &Uuid, user_id: &Uuid) -> Result<Uuid> {
         // Check if the package exists
-        if !self.packages.contains_key(package_id) {
-            return Err(anyhow!("Package not found"));
-        }
+        let version = self.packages.get(package_id)
+            .map(|package| package.version)
+            .ok_or_else(|| anyhow!("Package not found"))?;
         
         // Create a new session
         let session = ScormSession {
@@ -395,7 +550,9 @@ impl ScormService {
             min_score: None,
             total_time: None,
             interactions: Vec::new(),
-            data: HashMap::new(),
+            cmi: CmiDataModel::new(version, &user_id.to_string(), ""),
+            activity_id: None,
+            activity_cmi: HashMap::new(),
             created_at: Utc::now(),
             updated_at: Utc::now(),
             completed_at: None,
@@ -404,6 +561,32 @@ impl ScormService {
         // Store the session
         let session_id = session.id;
         self.sessions.insert(session_id, session);
+        if let Some(activity_tree) = self.activity_trees.get(package_id) {
+            self.sequencing_sessions.insert(session_id, SequencingSession::new(activity_tree.clone()));
+        }
+        self.persist_session(&session_id).await?;
+        
+        Ok(session_id)
+    }
+    
+    /// Launch a package for a user, resuming their latest unfinished session
+    /// if there is one
+    pub async fn launch_session(&mut self, package_id: &Uuid, user_id: &Uuid) -> Result<Uuid> {
+        let resumable = self.sessions.values()
+            .filter(|session| session.package_id == *package_id && session.user_id == *user_id)
+            .filter(|session| session.completed_at.is_none())
+            .max_by_key(|session| session.updated_at)
+            .map(|session| session.id);
+        
+        let Some(session_id) = resumable else {
+            return self.create_session(package_id, user_id).await;
+        };
+        
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            session.cmi.relaunch();
+            session.updated_at = Utc::now();
+        }
+        self.persist_session(&session_id).await?;
         
         Ok(session_id)
     }
@@ -428,28 +611,113 @@ impl ScormService {
     }
     
     /// Update a session
-    pub fn update_session(&mut self, session: ScormSession) -> Result<()> {
+    pub async fn update_session(&mut self, session: ScormSession) -> Result<()> {
         // Check if the session exists
         if !self.sessions.contains_key(&session.id) {
             return Err(anyhow!("Session not found"));
         }
         
         // Update the session
-        self.sessions.insert(session.id, session);
+        let session_id = session.id;
+        self.sessions.insert(session_id, session);
+        self.persist_session(&session_id).await?;
         
         Ok(())
     }
     
     /// Delete a session
-    pub fn delete_session(&mut self, id: &Uuid) -> Result<()> {
+    pub async fn delete_session(&mut self, id: &Uuid) -> Result<()> {
         // Remove the session from the map
         if self.sessions.remove(id).is_none() {
             return Err(anyhow!("Session not found"));
         }
+        self.sequencing_sessions.remove(id);
+        if let Some(store) = &self.store {
+            store.delete_session(id).await?;
+        }
         
         Ok(())
     }
     
+    /// Get the activity tree of a SCORM 2004 package
+    pub fn get_activity_tree(&self, package_id: &Uuid) -> Option<&ActivityTree> {
+        self.activity_trees.get(package_id)
+    }
+    
+    /// Get the sequencing state of a session on a SCORM 2004 package
+    pub fn get_sequencing_session(&self, session_id: &Uuid) -> Option<&SequencingSession> {
+        self.sequencing_sessions.get(session_id)
+    }
+    
+    /// Process a navigation request for a session
+    pub async fn navigate(&mut self, session_id: &Uuid, request: NavigationRequest) -> Result<SequencingOutcome> {
+        let sequencing = self.sequencing_sessions.get_mut(session_id)
+            .ok_or_else(|| anyhow!("Session is not sequenced"))?;
+        
+        let outcome = sequencing.process_navigation_request(request)
+            .map_err(|e| anyhow!("Navigation request not allowed: {} ({})", e, e.code()))?;
+        
+        // The delivered SCO gets a fresh API instance over its own data
+        if let (SequencingOutcome::Deliver(activity_id), Some(session)) = (&outcome, self.sessions.get_mut(session_id)) {
+            session.deliver(activity_id);
+        }
+        self.persist_session(session_id).await?;
+        
+        Ok(outcome)
+    }
+    
+    /// Get the launch URL of an activity of a package
+    pub fn get_activity_launch_url(&self, package_id: &Uuid, activity_id: &str) -> Result<String> {
+        let tree = self.get_activity_tree(package_id)
+            .ok_or_else(|| anyhow!("Package has no activity tree"))?;
+        let activity = tree.find(activity_id)
+            .map(|index| tree.get(index))
+            .ok_or_else(|| anyhow!("Activity not found"))?;
+        let href = activity.href.as_ref()
+            .ok_or_else(|| anyhow!("Activity {} has no content", activity_id))?;
+        
+        Ok(format!("/scorm/{}/{}", package_id, href))
+    }
+    
+    /// Answer an `adl.nav.request_valid.*` query
+    fn navigation_request_valid(&self, session_id: &Uuid, request: &str) -> &'static str {
+        let Some(sequencing) = self.sequencing_sessions.get(session_id) else {
+            return "unknown";
+        };
+        
+        let request = match request {
+            "continue" => Some(NavigationRequest::Continue),
+            "previous" => Some(NavigationRequest::Previous),
+            other => other.strip_prefix("choice.").and_then(|choice| NavigationRequest::parse(&format!("{}choice", choice))),
+        };
+        
+        match request {
+            Some(request) if sequencing.is_request_valid(&request) => "true",
+            Some(_) => "false",
+            None => "unknown",
+        }
+    }
+    
+    /// Feed a terminating SCO's tracking data to sequencing and process the
+    /// navigation request it left in `adl.nav.request`
+    fn terminate_sequenced_attempt(&mut self, session: &mut ScormSession) {
+        let Some(sequencing) = self.sequencing_sessions.get_mut(&session.id) else {
+            return;
+        };
+        
+        sequencing.report_runtime_data(&RuntimeData::from_cmi(&session.cmi.values()));
+        
+        let request = session.cmi.get("adl.nav.request")
+            .and_then(NavigationRequest::parse);
+        if let Some(request) = request {
+            match sequencing.process_navigation_request(request) {
+                Ok(SequencingOutcome::Deliver(activity_id)) => session.deliver(&activity_id),
+                Ok(_) => {},
+                Err(e) => warn!("Navigation request of session {} rejected: {} ({})", session.id, e, e.code()),
+            }
+        }
+    }
+    
     /// Get the launch URL for a package
     pub fn get_launch_url(&self, package_id: &Uuid) -> Result<String> {
         // Get the package
@@ -462,111 +730,59 @@ impl ScormService {
         Ok(launch_url)
     }
     
-    /// Handle a SCORM API call
-    pub fn handle_api_call(&mut self, session_id: &Uuid, function: &str, args: &[&str]) -> Result<String> {
+    /// Handle a SCORM API call; both the SCORM 1.2 (`LMSGetValue`, ...) and
+    /// SCORM 2004 (`GetValue`, ...) function names are accepted
+    pub async fn handle_api_call(&mut self, session_id: &Uuid, function: &str, args: &[&str]) -> Result<String> {
         // Get the session
         let mut session = self.get_session(session_id)
             .ok_or_else(|| anyhow!("Session not found"))?
             .clone();
         
+        let arg = |index: usize| args.get(index).copied().unwrap_or("");
+        let function = function.strip_prefix("LMS").unwrap_or(function);
+        
         // Handle the API call
         let result = match function {
-            "Initialize" => {
-                session.state = ScormActivityState::Attempted;
-                session.updated_at = Utc::now();
-                "true"
-            },
-            "Terminate" => {
-                if session.state == ScormActivityState::Completed || session.state == ScormActivityState::Passed || session.state == ScormActivityState::Failed {
-                    session.completed_at = Some(Utc::now());
+            "Initialize" => session.cmi.initialize(arg(0)).to_string(),
+            "Terminate" | "Finish" => {
+                let terminated = session.cmi.terminate(arg(0));
+                if terminated {
+                    session.refresh_from_cmi();
+                    if matches!(session.state, ScormActivityState::Completed | ScormActivityState::Passed | ScormActivityState::Failed) {
+                        session.completed_at = Some(Utc::now());
+                    }
+                    self.terminate_sequenced_attempt(&mut session);
                 }
-                session.updated_at = Utc::now();
-                "true"
+                terminated.to_string()
             },
             "GetValue" => {
-                if args.len() != 1 {
-                    return Err(anyhow!("Invalid number of arguments for GetValue"));
-                }
-                
-                let key = args[0];
-                
-                // Handle special keys
-                match key {
-                    "cmi.core.score.raw" => session.score.map(|s| s.to_string()).unwrap_or_default(),
-                    "cmi.core.score.max" => session.max_score.map(|s| s.to_string()).unwrap_or_default(),
-                    "cmi.core.score.min" => session.min_score.map(|s| s.to_string()).unwrap_or_default(),
-                    "cmi.core.lesson_status" => match session.state {
-                        ScormActivityState::NotAttempted => "not attempted",
-                        ScormActivityState::Attempted => "attempted",
-                        ScormActivityState::Completed => "completed",
-                        ScormActivityState::Incomplete => "incomplete",
-                        ScormActivityState::Passed => "passed",
-                        ScormActivityState::Failed => "failed",
-                        ScormActivityState::Unknown => "unknown",
-                    },
-                    "cmi.core.total_time" => session.total_time.clone().unwrap_or_default(),
-                    _ => session.data.get(key).cloned().unwrap_or_default(),
-                }
-            },
-            "SetValue" => {
-                if args.len() != 2 {
-                    return Err(anyhow!("Invalid number of arguments for SetValue"));
-                }
-                
-                let key = args[0];
-                let value = args[1];
-                
-                // Handle special keys
-                match key {
-                    "cmi.core.score.raw" => {
-                        if let Ok(score) = value.parse::<f32>() {
-                            session.score = Some(score);
-                        }
-                    },
-                    "cmi.core.score.max" => {
-                        if let Ok(max_score) = value.parse::<f32>() {
-                            session.max_score = Some(max_score);
-                        }
-                    },
-                    "cmi.core.score.min" => {
-                        if let Ok(min_score) = value.parse::<f32>() {
-                            session.min_score = Some(min_score);
-                        }
-                    },
-                    "cmi.core.lesson_status" => {
-                        session.state = match value {
-                            "not attempted" => ScormActivityState::NotAttempted,
-                            "attempted" => ScormActivityState::Attempted,
-                            "completed" => ScormActivityState::Completed,
-                            "incomplete" => ScormActivityState::Incomplete,
-                            "passed" => ScormActivityState::Passed,
-                            "failed" => ScormActivityState::Failed,
-                            _ => ScormActivityState::Unknown,
-                        };
-                    },
-                    "cmi.core.total_time" => {
-                        session.total_time = Some(value.to_string());
-                    },
-                    _ => {
-                        session.data.insert(key.to_string(), value.to_string());
-                    },
+                let element = arg(0);
+                let value = session.cmi.get_value(element);
+                match element.strip_prefix("adl.nav.request_valid.") {
+                    Some(request) if session.cmi.last_error() == 0 => self.navigation_request_valid(session_id, request).to_string(),
+                    _ => value,
                 }
-                
-                session.updated_at = Utc::now();
-                "true"
-            },
-            "Commit" => {
-                session.updated_at = Utc::now();
-                "true"
             },
-            "GetLastError" => "0", // No error
-            "GetErrorString" => "",
-            "GetDiagnostic" => "",
+            "SetValue" => session.cmi.set_value(arg(0), arg(1)).to_string(),
+            "Commit" => session.cmi.commit(arg(0)).to_string(),
+            "GetLastError" => session.cmi.last_error().to_string(),
+            "GetErrorString" => session.cmi.error_string(arg(0)),
+            "GetDiagnostic" => session.cmi.diagnostic(arg(0)),
             _ => return Err(anyhow!("Unknown API function: {}", function)),
-        }.to_string();
+        };
         
-        // Update the session
-        self.update_session(session)?;
+        // The summary of a terminated session is taken before sequencing may
+        // start a new attempt
+        if !matches!(function, "Terminate" | "Finish") {
+            session.refresh_from_cmi();
+        }
+        session.updated_at = Utc::now();
+        self.sessions.insert(session.id, session);
+        
+        // Tracking data is persisted when content commits it or ends its session
+        if matches!(function, "Initialize" | "Terminate" | "Finish" | "Commit") {
+            self.persist_session(session_id).await?;
+        }
         
         Ok(result)
     }
@@ -0,0 +1,767 @@
+// SCORM 2004 sequencing and navigation
+//
+// `SequencingSession` runs the Overall Sequencing Process (OP.1) over an
+// activity tree: a navigation request is validated, turned into termination
+// and sequencing requests, and the resulting delivery request identifies the
+// next SCO to launch. Errors carry the SN book exception codes so they can be
+// reported to content through `adl.nav.request_valid`.
+
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+
+use super::activity_tree::{ActivityTree, RuleAction, RuntimeData};
+
+/// Sequencing exception
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SequencingError {
+    #[error("Sequencing session has already begun")]
+    SessionAlreadyBegun,
+
+    #[error("Sequencing session has not begun")]
+    SessionNotBegun,
+
+    #[error("No suspended activity to resume")]
+    NoSuspendedActivity,
+
+    #[error("Flow navigation is not allowed")]
+    FlowNotAllowed,
+
+    #[error("Backward navigation is not allowed in a forward-only cluster")]
+    ForwardOnly,
+
+    #[error("Exiting the active activity by choice is not allowed")]
+    ChoiceExitNotAllowed,
+
+    #[error("Choice navigation is not allowed")]
+    ChoiceNotAllowed,
+
+    #[error("Activity {0} does not exist")]
+    UnknownActivity(String),
+
+    #[error("Current activity is not active")]
+    ActivityNotActive,
+
+    #[error("Current activity is already terminated")]
+    AlreadyTerminated,
+
+    #[error("Activity is hidden from choice")]
+    HiddenFromChoice,
+
+    #[error("A stop forward traversal rule prevents the choice")]
+    StopForwardTraversal,
+
+    #[error("Reached the beginning of the activity tree")]
+    BeginningOfTree,
+
+    #[error("No activity available to deliver")]
+    NothingToDeliver,
+
+    #[error("Activity is disabled or its limit conditions are violated")]
+    ActivityUnavailable,
+
+    #[error("Only leaf activities can be delivered")]
+    NotALeaf,
+
+    #[error("Exit parent requested on the root activity")]
+    NoParent,
+}
+
+impl SequencingError {
+    /// SN book exception code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::SessionAlreadyBegun => "NB.2.1-1",
+            Self::SessionNotBegun => "NB.2.1-2",
+            Self::NoSuspendedActivity => "NB.2.1-3",
+            Self::FlowNotAllowed => "NB.2.1-4",
+            Self::ForwardOnly => "NB.2.1-5",
+            Self::ChoiceExitNotAllowed => "NB.2.1-8",
+            Self::ChoiceNotAllowed => "NB.2.1-10",
+            Self::UnknownActivity(_) => "NB.2.1-11",
+            Self::ActivityNotActive => "NB.2.1-12",
+            Self::AlreadyTerminated => "TB.2.3-2",
+            Self::NoParent => "TB.2.3-4",
+            Self::HiddenFromChoice => "SB.2.9-3",
+            Self::StopForwardTraversal => "SB.2.4-1",
+            Self::BeginningOfTree => "SB.2.1-3",
+            Self::NothingToDeliver => "SB.2.9-9",
+            Self::ActivityUnavailable => "DB.1.1-3",
+            Self::NotALeaf => "DB.1.1-2",
+        }
+    }
+}
+
+/// Navigation request from the LMS UI or from content (`adl.nav.request`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NavigationRequest {
+    Start,
+    ResumeAll,
+    Continue,
+    Previous,
+    Choice(String),
+    Exit,
+    ExitAll,
+    SuspendAll,
+    Abandon,
+    AbandonAll,
+}
+
+impl NavigationRequest {
+    /// Parse an `adl.nav.request` value; `_none_` and unknown values yield `None`
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Some(rest) = value.strip_prefix("{target=") {
+            let (target, request) = rest.split_once('}')?;
+            return (request == "choice").then(|| Self::Choice(target.to_string()));
+        }
+
+        match value {
+            "start" => Some(Self::Start),
+            "resumeAll" => Some(Self::ResumeAll),
+            "continue" => Some(Self::Continue),
+            "previous" => Some(Self::Previous),
+            "exit" => Some(Self::Exit),
+            "exitAll" => Some(Self::ExitAll),
+            "suspendAll" => Some(Self::SuspendAll),
+            "abandon" => Some(Self::Abandon),
+            "abandonAll" => Some(Self::AbandonAll),
+            _ => None,
+        }
+    }
+}
+
+/// Result of processing a navigation request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SequencingOutcome {
+    /// Launch the identified activity
+    Deliver(String),
+
+    /// The sequencing session is over
+    EndSession,
+
+    /// Nothing to launch; the learner picks from the table of contents
+    Idle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TerminationRequest {
+    Exit,
+    ExitAll,
+    SuspendAll,
+    Abandon,
+    AbandonAll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SequencingRequest {
+    Start,
+    ResumeAll,
+    Continue,
+    Previous,
+    Choice(usize),
+    Retry,
+    Exit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Forward,
+    Backward,
+}
+
+/// Why a flow traversal found no activity
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FlowFailure {
+    EndOfTree,
+    Error(SequencingError),
+}
+
+impl From<SequencingError> for FlowFailure {
+    fn from(error: SequencingError) -> Self {
+        Self::Error(error)
+    }
+}
+
+/// Sequencing state of one learner's attempt on a package
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SequencingSession {
+    tree: ActivityTree,
+    current: Option<usize>,
+    suspended: Option<usize>,
+    ended: bool,
+}
+
+impl SequencingSession {
+    /// Begin sequencing a fresh copy of a package's activity tree
+    pub fn new(tree: ActivityTree) -> Self {
+        Self {
+            tree,
+            current: None,
+            suspended: None,
+            ended: false,
+        }
+    }
+
+    /// The activity tree with its tracking state
+    pub fn tree(&self) -> &ActivityTree {
+        &self.tree
+    }
+
+    /// Identifier of the current activity
+    pub fn current_activity(&self) -> Option<&str> {
+        self.current.map(|index| self.tree.get(index).id.as_str())
+    }
+
+    /// Identifier of the suspended activity, if the session was suspended
+    pub fn suspended_activity(&self) -> Option<&str> {
+        self.suspended.map(|index| self.tree.get(index).id.as_str())
+    }
+
+    /// Whether the sequencing session has ended
+    pub fn is_ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Record what the current SCO reported before it terminated
+    pub fn report_runtime_data(&mut self, data: &RuntimeData) {
+        if let Some(current) = self.current {
+            self.tree.apply_runtime_data(current, data);
+        }
+    }
+
+    /// Whether a navigation request would succeed, without performing it
+    pub fn is_request_valid(&self, request: &NavigationRequest) -> bool {
+        self.clone().process_navigation_request(request.clone()).is_ok()
+    }
+
+    /// Activities the learner may currently select from the table of contents
+    pub fn available_choices(&self) -> Vec<String> {
+        self.tree.preorder().into_iter()
+            .filter(|&index| self.tree.get(index).is_visible)
+            .filter(|&index| self.is_request_valid(&NavigationRequest::Choice(self.tree.get(index).id.clone())))
+            .map(|index| self.tree.get(index).id.clone())
+            .collect()
+    }
+
+    /// Overall Sequencing Process (OP.1)
+    pub fn process_navigation_request(&mut self, request: NavigationRequest) -> Result<SequencingOutcome, SequencingError> {
+        let (termination, mut sequencing) = self.navigation_request(&request)?;
+
+        if let Some(termination) = termination {
+            if let Some(override_request) = self.termination_request(termination)? {
+                sequencing = Some(override_request);
+            }
+        }
+
+        let Some(sequencing) = sequencing else {
+            return Ok(SequencingOutcome::Idle);
+        };
+
+        match self.sequencing_request(sequencing)? {
+            Some(target) => self.deliver(target),
+            None if self.ended => Ok(SequencingOutcome::EndSession),
+            None => Ok(SequencingOutcome::Idle),
+        }
+    }
+
+    /// Navigation Request Process (NB.2.1)
+    fn navigation_request(&self, request: &NavigationRequest) -> Result<(Option<TerminationRequest>, Option<SequencingRequest>), SequencingError> {
+        let current_is_active = self.current.is_some_and(|current| self.tree.get(current).state.is_active);
+        let exit_if_active = || current_is_active.then_some(TerminationRequest::Exit);
+
+        match request {
+            NavigationRequest::Start => match self.current {
+                None => Ok((None, Some(SequencingRequest::Start))),
+                Some(_) => Err(SequencingError::SessionAlreadyBegun),
+            },
+            NavigationRequest::ResumeAll => match (self.current, self.suspended) {
+                (None, Some(_)) => Ok((None, Some(SequencingRequest::ResumeAll))),
+                (None, None) => Err(SequencingError::NoSuspendedActivity),
+                (Some(_), _) => Err(SequencingError::SessionAlreadyBegun),
+            },
+            NavigationRequest::Continue => {
+                let current = self.current.ok_or(SequencingError::SessionNotBegun)?;
+                match self.tree.parent_control_mode(current) {
+                    Some(mode) if mode.flow => Ok((exit_if_active(), Some(SequencingRequest::Continue))),
+                    _ => Err(SequencingError::FlowNotAllowed),
+                }
+            }
+            NavigationRequest::Previous => {
+                let current = self.current.ok_or(SequencingError::SessionNotBegun)?;
+                match self.tree.parent_control_mode(current) {
+                    Some(mode) if mode.flow && mode.forward_only => Err(SequencingError::ForwardOnly),
+                    Some(mode) if mode.flow => Ok((exit_if_active(), Some(SequencingRequest::Previous))),
+                    _ => Err(SequencingError::FlowNotAllowed),
+                }
+            }
+            NavigationRequest::Choice(target_id) => {
+                let target = self.tree.find(target_id)
+                    .ok_or_else(|| SequencingError::UnknownActivity(target_id.clone()))?;
+
+                let choice_allowed = self.tree.parent_control_mode(target).map_or(true, |mode| mode.choice);
+                if !choice_allowed {
+                    return Err(SequencingError::ChoiceNotAllowed);
+                }
+
+                let Some(current) = self.current else {
+                    return Ok((None, Some(SequencingRequest::Choice(target))));
+                };
+
+                // Every active activity being left must allow choice exit
+                let common = self.tree.common_ancestor(current, target);
+                for activity in self.tree.path_to_root(current) {
+                    if activity == common {
+                        break;
+                    }
+                    let state = &self.tree.get(activity).state;
+                    if state.is_active && !self.tree.get(activity).sequencing.control_mode.choice_exit {
+                        return Err(SequencingError::ChoiceExitNotAllowed);
+                    }
+                }
+
+                Ok((exit_if_active(), Some(SequencingRequest::Choice(target))))
+            }
+            NavigationRequest::Exit | NavigationRequest::Abandon => {
+                self.current.ok_or(SequencingError::SessionNotBegun)?;
+                if !current_is_active {
+                    return Err(SequencingError::ActivityNotActive);
+                }
+                let termination = if *request == NavigationRequest::Exit {
+                    TerminationRequest::Exit
+                } else {
+                    TerminationRequest::Abandon
+                };
+                Ok((Some(termination), Some(SequencingRequest::Exit)))
+            }
+            NavigationRequest::ExitAll | NavigationRequest::SuspendAll | NavigationRequest::AbandonAll => {
+                self.current.ok_or(SequencingError::SessionNotBegun)?;
+                let termination = match request {
+                    NavigationRequest::ExitAll => TerminationRequest::ExitAll,
+                    NavigationRequest::SuspendAll => TerminationRequest::SuspendAll,
+                    _ => TerminationRequest::AbandonAll,
+                };
+                Ok((Some(termination), Some(SequencingRequest::Exit)))
+            }
+        }
+    }
+
+    /// Termination Request Process (TB.2.3); may override the sequencing request
+    fn termination_request(&mut self, request: TerminationRequest) -> Result<Option<SequencingRequest>, SequencingError> {
+        let current = self.current.ok_or(SequencingError::SessionNotBegun)?;
+        let root = self.tree.root();
+
+        match request {
+            TerminationRequest::Exit => {
+                if !self.tree.get(current).state.is_active {
+                    return Err(SequencingError::AlreadyTerminated);
+                }
+                self.tree.end_attempt(current);
+                self.exit_action_rules();
+
+                loop {
+                    let current = self.current.unwrap_or(root);
+                    match self.tree.check_post_conditions(current) {
+                        Some(RuleAction::ExitAll) => return self.termination_request(TerminationRequest::ExitAll),
+                        Some(RuleAction::ExitParent) => {
+                            let parent = self.tree.parent(current).ok_or(SequencingError::NoParent)?;
+                            self.current = Some(parent);
+                            self.tree.end_attempt(parent);
+                        }
+                        Some(RuleAction::RetryAll) => {
+                            self.termination_request(TerminationRequest::ExitAll)?;
+                            return Ok(Some(SequencingRequest::Retry));
+                        }
+                        action => {
+                            let override_request = match action {
+                                Some(RuleAction::Retry) => Some(SequencingRequest::Retry),
+                                Some(RuleAction::Continue) => Some(SequencingRequest::Continue),
+                                Some(RuleAction::Previous) => Some(SequencingRequest::Previous),
+                                _ => None,
+                            };
+                            if current == root && override_request != Some(SequencingRequest::Retry) {
+                                return Ok(Some(SequencingRequest::Exit));
+                            }
+                            return Ok(override_request);
+                        }
+                    }
+                }
+            }
+            TerminationRequest::ExitAll => {
+                if self.tree.get(current).state.is_active {
+                    self.tree.end_attempt(current);
+                }
+                self.terminate_descendent_attempts(root);
+                self.tree.end_attempt(root);
+                self.current = Some(root);
+                Ok(Some(SequencingRequest::Exit))
+            }
+            TerminationRequest::SuspendAll => {
+                let state = &self.tree.get(current).state;
+                if !state.is_active && !state.is_suspended {
+                    return Err(SequencingError::AlreadyTerminated);
+                }
+                self.tree.overall_rollup(current);
+                self.suspended = Some(current);
+                for activity in self.tree.path_to_root(current) {
+                    let state = &mut self.tree.get_mut(activity).state;
+                    state.is_active = false;
+                    state.is_suspended = true;
+                }
+                self.current = Some(root);
+                Ok(Some(SequencingRequest::Exit))
+            }
+            TerminationRequest::Abandon => {
+                self.tree.get_mut(current).state.is_active = false;
+                Ok(None)
+            }
+            TerminationRequest::AbandonAll => {
+                for activity in self.tree.path_to_root(current) {
+                    self.tree.get_mut(activity).state.is_active = false;
+                }
+                self.current = Some(root);
+                Ok(Some(SequencingRequest::Exit))
+            }
+        }
+    }
+
+    /// Sequencing Exit Action Rules Subprocess (TB.2.1)
+    fn exit_action_rules(&mut self) {
+        let Some(current) = self.current else {
+            return;
+        };
+
+        let mut ancestors = self.tree.path_from_root(current);
+        ancestors.pop();
+
+        if let Some(exit_target) = ancestors.into_iter().find(|&activity| self.tree.check_exit_conditions(activity).is_some()) {
+            self.terminate_descendent_attempts(exit_target);
+            self.tree.end_attempt(exit_target);
+            self.current = Some(exit_target);
+        }
+    }
+
+    /// Terminate Descendent Attempts Process (UP.3): end the attempts between
+    /// the current activity and `activity`, exclusive of both
+    fn terminate_descendent_attempts(&mut self, activity: usize) {
+        let Some(current) = self.current else {
+            return;
+        };
+
+        let common = self.tree.common_ancestor(current, activity);
+        for index in self.tree.path_to_root(current) {
+            if index == common {
+                break;
+            }
+            if index != current && self.tree.get(index).state.is_active {
+                self.tree.end_attempt(index);
+            }
+        }
+    }
+
+    /// Sequencing Request Process (SB.2.12); returns the activity to deliver
+    fn sequencing_request(&mut self, request: SequencingRequest) -> Result<Option<usize>, SequencingError> {
+        let root = self.tree.root();
+
+        match request {
+            SequencingRequest::Start => {
+                if self.current.is_some() {
+                    return Err(SequencingError::SessionAlreadyBegun);
+                }
+                if self.tree.is_leaf(root) {
+                    return Ok(Some(root));
+                }
+                self.flow_or_end(root, Direction::Forward, true)
+            }
+            SequencingRequest::ResumeAll => {
+                if self.current.is_some() {
+                    return Err(SequencingError::SessionAlreadyBegun);
+                }
+                self.suspended.map(Some).ok_or(SequencingError::NoSuspendedActivity)
+            }
+            SequencingRequest::Exit => {
+                let current = self.current.ok_or(SequencingError::SessionNotBegun)?;
+                if self.tree.get(current).state.is_active {
+                    return Err(SequencingError::ActivityNotActive);
+                }
+                if current == root {
+                    self.end_session();
+                }
+                Ok(None)
+            }
+            SequencingRequest::Retry => {
+                let current = self.current.ok_or(SequencingError::SessionNotBegun)?;
+                let state = &self.tree.get(current).state;
+                if state.is_active || state.is_suspended {
+                    return Err(SequencingError::ActivityNotActive);
+                }
+                if self.tree.is_leaf(current) {
+                    return Ok(Some(current));
+                }
+                match self.flow(current, Direction::Forward, true) {
+                    Ok(activity) => Ok(Some(activity)),
+                    Err(_) => Err(SequencingError::NothingToDeliver),
+                }
+            }
+            SequencingRequest::Continue => {
+                let current = self.current.ok_or(SequencingError::SessionNotBegun)?;
+                if !self.tree.parent_control_mode(current).is_some_and(|mode| mode.flow) {
+                    return Err(SequencingError::FlowNotAllowed);
+                }
+                self.flow_or_end(current, Direction::Forward, false)
+            }
+            SequencingRequest::Previous => {
+                let current = self.current.ok_or(SequencingError::SessionNotBegun)?;
+                if !self.tree.parent_control_mode(current).is_some_and(|mode| mode.flow) {
+                    return Err(SequencingError::FlowNotAllowed);
+                }
+                match self.flow(current, Direction::Backward, false) {
+                    Ok(activity) => Ok(Some(activity)),
+                    Err(FlowFailure::EndOfTree) => Err(SequencingError::BeginningOfTree),
+                    Err(FlowFailure::Error(error)) => Err(error),
+                }
+            }
+            SequencingRequest::Choice(target) => self.choice_request(target).map(Some),
+        }
+    }
+
+    /// Run a forward flow; walking off the end of the tree ends the session
+    fn flow_or_end(&mut self, from: usize, direction: Direction, consider_children: bool) -> Result<Option<usize>, SequencingError> {
+        match self.flow(from, direction, consider_children) {
+            Ok(activity) => Ok(Some(activity)),
+            Err(FlowFailure::EndOfTree) => {
+                self.end_session();
+                Ok(None)
+            }
+            Err(FlowFailure::Error(error)) => Err(error),
+        }
+    }
+
+    /// End the sequencing session, ending any attempts still active; the
+    /// tracking state carries over to the next session
+    fn end_session(&mut self) {
+        if let Some(current) = self.current.take() {
+            for activity in self.tree.path_to_root(current) {
+                if self.tree.get(activity).state.is_active {
+                    self.tree.end_attempt(activity);
+                }
+            }
+        }
+        self.ended = true;
+    }
+
+    /// Choice Sequencing Request Process (SB.2.9)
+    fn choice_request(&mut self, target: usize) -> Result<usize, SequencingError> {
+        let root = self.tree.root();
+
+        for activity in self.tree.path_from_root(target) {
+            if activity != root && self.tree.is_hidden_from_choice(activity) {
+                return Err(SequencingError::HiddenFromChoice);
+            }
+        }
+        if !self.tree.parent_control_mode(target).map_or(true, |mode| mode.choice) {
+            return Err(SequencingError::ChoiceNotAllowed);
+        }
+
+        let common = match self.current {
+            Some(current) if current != target => {
+                let common = self.tree.common_ancestor(current, target);
+                let siblings = self.tree.parent(current).is_some() && self.tree.parent(current) == self.tree.parent(target);
+
+                if siblings {
+                    // Traverse the siblings between current and target
+                    let parent = self.tree.parent(current).unwrap_or(root);
+                    let children = self.tree.get(parent).children.clone();
+                    let from = children.iter().position(|&child| child == current).unwrap_or(0);
+                    let to = children.iter().position(|&child| child == target).unwrap_or(0);
+                    if from < to {
+                        for &activity in &children[from..to] {
+                            self.choice_traversal(activity, Direction::Forward)?;
+                        }
+                    } else {
+                        self.choice_traversal(target, Direction::Backward)?;
+                    }
+                } else if self.tree.preorder_position(target) > self.tree.preorder_position(current) {
+                    // Moving forward into another branch: the path from the
+                    // common ancestor down to the target must permit it
+                    let path = self.tree.path_from_root(target);
+                    let start = path.iter().position(|&activity| activity == common).unwrap_or(0);
+                    for &activity in &path[start..path.len() - 1] {
+                        self.choice_traversal(activity, Direction::Forward)?;
+                    }
+                } else if !self.tree.is_ancestor_or_self(target, current) {
+                    self.choice_traversal(target, Direction::Backward)?;
+                }
+
+                common
+            }
+            Some(current) => current,
+            None => root,
+        };
+
+        if self.tree.is_leaf(target) {
+            return Ok(target);
+        }
+
+        match self.flow(target, Direction::Forward, true) {
+            Ok(activity) => Ok(activity),
+            Err(_) => {
+                self.terminate_descendent_attempts(common);
+                self.current = Some(target);
+                Err(SequencingError::NothingToDeliver)
+            }
+        }
+    }
+
+    /// Choice Activity Traversal Subprocess (SB.2.4)
+    fn choice_traversal(&self, activity: usize, direction: Direction) -> Result<(), SequencingError> {
+        match direction {
+            Direction::Forward => {
+                if self.tree.check_pre_conditions(activity, &[RuleAction::StopForwardTraversal]).is_some() {
+                    return Err(SequencingError::StopForwardTraversal);
+                }
+            }
+            Direction::Backward => {
+                if self.tree.parent_control_mode(activity).is_some_and(|mode| mode.forward_only) {
+                    return Err(SequencingError::ForwardOnly);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Flow Subprocess (SB.2.3)
+    fn flow(&self, from: usize, direction: Direction, consider_children: bool) -> Result<usize, FlowFailure> {
+        let (candidate, direction) = self.flow_tree_traversal(from, direction, consider_children, None)?;
+        self.flow_activity_traversal(candidate, direction, None)
+    }
+
+    /// Flow Tree Traversal Subprocess (SB.2.1); returns the next activity and
+    /// the direction traversal continues in
+    fn flow_tree_traversal(
+        &self,
+        activity: usize,
+        direction: Direction,
+        consider_children: bool,
+        previous_direction: Option<Direction>,
+    ) -> Result<(usize, Direction), FlowFailure> {
+        let root = self.tree.root();
+
+        // Having flowed forward through a forward-only cluster entered
+        // backwards, leave it backwards from its first child
+        let (activity, direction) = match self.tree.parent(activity) {
+            Some(parent) if previous_direction == Some(Direction::Backward) && self.tree.next_sibling(activity).is_none() => {
+                (self.tree.get(parent).children[0], Direction::Backward)
+            }
+            _ => (activity, direction),
+        };
+
+        match direction {
+            Direction::Forward => {
+                if self.tree.is_last_in_tree(activity) || (activity == root && !consider_children) {
+                    return Err(FlowFailure::EndOfTree);
+                }
+
+                if self.tree.is_leaf(activity) || !consider_children {
+                    match self.tree.next_sibling(activity) {
+                        Some(next) => Ok((next, direction)),
+                        None => {
+                            let parent = self.tree.parent(activity).ok_or(FlowFailure::EndOfTree)?;
+                            self.flow_tree_traversal(parent, direction, false, None)
+                        }
+                    }
+                } else {
+                    let first = *self.tree.get(activity).children.first()
+                        .ok_or(FlowFailure::Error(SequencingError::NothingToDeliver))?;
+                    Ok((first, direction))
+                }
+            }
+            Direction::Backward => {
+                if activity == root {
+                    return Err(FlowFailure::EndOfTree);
+                }
+
+                if self.tree.is_leaf(activity) || !consider_children {
+                    match self.tree.previous_sibling(activity) {
+                        Some(previous) => Ok((previous, direction)),
+                        None => {
+                            let parent = self.tree.parent(activity).ok_or(FlowFailure::EndOfTree)?;
+                            self.flow_tree_traversal(parent, direction, false, None)
+                        }
+                    }
+                } else {
+                    let children = &self.tree.get(activity).children;
+                    if children.is_empty() {
+                        return Err(FlowFailure::Error(SequencingError::NothingToDeliver));
+                    }
+                    if self.tree.get(activity).sequencing.control_mode.forward_only {
+                        Ok((children[0], Direction::Forward))
+                    } else {
+                        Ok((children[children.len() - 1], Direction::Backward))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Flow Activity Traversal Subprocess (SB.2.2)
+    fn flow_activity_traversal(&self, activity: usize, direction: Direction, previous_direction: Option<Direction>) -> Result<usize, FlowFailure> {
+        if !self.tree.parent_control_mode(activity).is_some_and(|mode| mode.flow) {
+            return Err(SequencingError::FlowNotAllowed.into());
+        }
+
+        if self.tree.check_pre_conditions(activity, &[RuleAction::Skip]).is_some() {
+            let (next, next_direction) = self.flow_tree_traversal(activity, direction, false, previous_direction)?;
+            let previous = if previous_direction == Some(Direction::Backward) && next_direction == Direction::Backward {
+                None
+            } else {
+                previous_direction
+            };
+            return self.flow_activity_traversal(next, next_direction, previous);
+        }
+
+        if self.tree.is_unavailable(activity) {
+            return Err(SequencingError::ActivityUnavailable.into());
+        }
+
+        if self.tree.is_leaf(activity) {
+            return Ok(activity);
+        }
+
+        let (next, next_direction) = self.flow_tree_traversal(activity, direction, true, None)?;
+        if direction == Direction::Backward && next_direction == Direction::Forward {
+            self.flow_activity_traversal(next, Direction::Forward, Some(Direction::Backward))
+        } else {
+            self.flow_activity_traversal(next, direction, None)
+        }
+    }
+
+    /// Delivery Request Process (DB.1.1) and Content Delivery Environment
+    /// Process (DB.2)
+    fn deliver(&mut self, target: usize) -> Result<SequencingOutcome, SequencingError> {
+        if !self.tree.is_leaf(target) {
+            return Err(SequencingError::NotALeaf);
+        }
+
+        let path = self.tree.path_from_root(target);
+        if path.iter().any(|&activity| self.tree.is_unavailable(activity)) {
+            return Err(SequencingError::ActivityUnavailable);
+        }
+
+        if let Some(current) = self.current {
+            if current != target {
+                let common = self.tree.common_ancestor(current, target);
+                self.terminate_descendent_attempts(common);
+            }
+        }
+
+        for activity in path {
+            self.tree.begin_attempt(activity);
+        }
+
+        self.current = Some(target);
+        self.suspended = None;
+        self.ended = false;
+
+        Ok(SequencingOutcome::Deliver(self.tree.get(target).id.clone()))
+    }
+}
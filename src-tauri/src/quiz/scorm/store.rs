@@ -0,0 +1,123 @@
+// SQLite persistence of SCORM packages and sessions
+//
+// Package metadata and sessions are stored as JSON documents so that imported
+// packages and in-progress attempts survive a restart. Activity trees are not
+// stored; they are rebuilt from the extracted manifest when packages load.
+
+use anyhow::Result;
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use super::sequencing::SequencingSession;
+use super::{ScormPackageMetadata, ScormSession};
+
+/// SQLite store of SCORM packages and sessions
+#[derive(Debug, Clone)]
+pub struct ScormStore {
+    pool: SqlitePool,
+}
+
+impl ScormStore {
+    /// Create a store, ensuring the SCORM tables exist
+    pub async fn new(pool: SqlitePool) -> Result<Self> {
+        sqlx::query(include_str!("../../sql/quiz_scorm_schema.sql"))
+            .execute(&pool)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    /// Insert or replace a package
+    pub async fn save_package(&self, package: &ScormPackageMetadata) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO scorm_packages (id, metadata, created_at) VALUES (?, ?, ?)
+             ON CONFLICT (id) DO UPDATE SET metadata = excluded.metadata"
+        )
+        .bind(package.id.to_string())
+        .bind(serde_json::to_string(package)?)
+        .bind(package.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete a package and its sessions
+    pub async fn delete_package(&self, id: &Uuid) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM scorm_sessions WHERE package_id = ?")
+            .bind(id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM scorm_packages WHERE id = ?")
+            .bind(id.to_string())
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Load all packages
+    pub async fn load_packages(&self) -> Result<Vec<ScormPackageMetadata>> {
+        let rows = sqlx::query("SELECT metadata FROM scorm_packages ORDER BY created_at")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| Ok(serde_json::from_str(row.get("metadata"))?))
+            .collect()
+    }
+
+    /// Insert or replace a session with its sequencing state
+    pub async fn save_session(&self, session: &ScormSession, sequencing: Option<&SequencingSession>) -> Result<()> {
+        let sequencing = sequencing.map(serde_json::to_string).transpose()?;
+
+        sqlx::query(
+            "INSERT INTO scorm_sessions (id, package_id, user_id, data, sequencing, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT (id) DO UPDATE SET
+                data = excluded.data,
+                sequencing = excluded.sequencing,
+                updated_at = excluded.updated_at"
+        )
+        .bind(session.id.to_string())
+        .bind(session.package_id.to_string())
+        .bind(session.user_id.to_string())
+        .bind(serde_json::to_string(session)?)
+        .bind(sequencing)
+        .bind(session.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete a session
+    pub async fn delete_session(&self, id: &Uuid) -> Result<()> {
+        sqlx::query("DELETE FROM scorm_sessions WHERE id = ?")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Load all sessions with their sequencing state
+    pub async fn load_sessions(&self) -> Result<Vec<(ScormSession, Option<SequencingSession>)>> {
+        let rows = sqlx::query("SELECT data, sequencing FROM scorm_sessions ORDER BY updated_at")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let session = serde_json::from_str(row.get("data"))?;
+                let sequencing = row.get::<Option<String>, _>("sequencing")
+                    .map(|sequencing| serde_json::from_str(&sequencing))
+                    .transpose()?;
+                Ok((session, sequencing))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,685 @@
+use super::activity_tree::parse_iso8601_duration;
+use super::manifest::parse_activity_tree;
+use super::*;
+
+// A pretest that can test out of chapter 1, a forward-only chapter and a
+// posttest with two attempts that is retried until passed
+const COURSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest identifier="com.example.course" version="1"
+    xmlns="http://www.imsglobal.org/xsd/imscp_v1p1"
+    xmlns:adlcp="http://www.adlnet.org/xsd/adlcp_v1p3"
+    xmlns:imsss="http://www.imsglobal.org/xsd/imsss">
+  <metadata>
+    <schema>ADL SCORM</schema>
+    <schemaversion>2004 4th Edition</schemaversion>
+  </metadata>
+  <organizations default="org">
+    <organization identifier="org">
+      <title>Safety Course</title>
+      <item identifier="pretest" identifierref="r_pretest">
+        <title>Pretest</title>
+        <imsss:sequencing>
+          <imsss:rollupRules rollupObjectiveSatisfied="false" rollupProgressCompletion="false"/>
+          <imsss:objectives>
+            <imsss:primaryObjective objectiveID="pretest_obj">
+              <imsss:mapInfo targetObjectiveID="mastery" writeSatisfiedStatus="true"/>
+            </imsss:primaryObjective>
+          </imsss:objectives>
+        </imsss:sequencing>
+      </item>
+      <item identifier="chapter1">
+        <title>Chapter 1</title>
+        <item identifier="lesson1" identifierref="r_lesson1" parameters="?page=1">
+          <title>Lesson 1</title>
+          <imsss:sequencing IDRef="lesson_rules"/>
+        </item>
+        <item identifier="lesson2" identifierref="r_lesson2">
+          <title>Lesson 2</title>
+          <imsss:sequencing IDRef="lesson_rules"/>
+        </item>
+        <imsss:sequencing>
+          <imsss:controlMode flow="true" forwardOnly="true" choiceExit="false"/>
+          <imsss:sequencingRules>
+            <imsss:preConditionRule>
+              <imsss:ruleConditions>
+                <imsss:ruleCondition referencedObjective="mastered" condition="satisfied"/>
+              </imsss:ruleConditions>
+              <imsss:ruleAction action="skip"/>
+            </imsss:preConditionRule>
+          </imsss:sequencingRules>
+          <imsss:objectives>
+            <imsss:primaryObjective objectiveID="chapter1_obj"/>
+            <imsss:objective objectiveID="mastered">
+              <imsss:mapInfo targetObjectiveID="mastery"/>
+            </imsss:objective>
+          </imsss:objectives>
+        </imsss:sequencing>
+      </item>
+      <item identifier="posttest" identifierref="r_posttest">
+        <title>Posttest</title>
+        <imsss:sequencing>
+          <imsss:sequencingRules>
+            <imsss:postConditionRule>
+              <imsss:ruleConditions>
+                <imsss:ruleCondition operator="not" condition="satisfied"/>
+              </imsss:ruleConditions>
+              <imsss:ruleAction action="retry"/>
+            </imsss:postConditionRule>
+          </imsss:sequencingRules>
+          <imsss:limitConditions attemptLimit="2"/>
+        </imsss:sequencing>
+      </item>
+      <imsss:sequencing>
+        <imsss:controlMode choice="true" flow="true"/>
+      </imsss:sequencing>
+    </organization>
+  </organizations>
+  <resources xml:base="content/">
+    <resource identifier="r_pretest" type="webcontent" adlcp:scormType="sco" href="pretest.html"/>
+    <resource identifier="r_lesson1" type="webcontent" adlcp:scormType="sco" href="lesson1.html"/>
+    <resource identifier="r_lesson2" type="webcontent" adlcp:scormType="sco" href="lesson2.html"/>
+    <resource identifier="r_posttest" type="webcontent" adlcp:scormType="sco" href="posttest.html"/>
+  </resources>
+  <imsss:sequencingCollection>
+    <imsss:sequencing ID="lesson_rules">
+      <imsss:limitConditions attemptLimit="3"/>
+    </imsss:sequencing>
+  </imsss:sequencingCollection>
+</manifest>"#;
+
+// Two SCOs whose weighted score decides the course's satisfaction
+const MEASURED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest identifier="com.example.measured" xmlns="http://www.imsglobal.org/xsd/imscp_v1p1" xmlns:imsss="http://www.imsglobal.org/xsd/imsss">
+  <organizations default="org">
+    <organization identifier="org">
+      <title>Measured</title>
+      <item identifier="a" identifierref="r_a">
+        <title>A</title>
+      </item>
+      <item identifier="b" identifierref="r_b">
+        <title>B</title>
+        <imsss:sequencing>
+          <imsss:rollupRules objectiveMeasureWeight="3"/>
+        </imsss:sequencing>
+      </item>
+      <imsss:sequencing>
+        <imsss:controlMode flow="true"/>
+        <imsss:objectives>
+          <imsss:primaryObjective satisfiedByMeasure="true">
+            <imsss:minNormalizedMeasure>0.6</imsss:minNormalizedMeasure>
+          </imsss:primaryObjective>
+        </imsss:objectives>
+      </imsss:sequencing>
+    </organization>
+  </organizations>
+  <resources>
+    <resource identifier="r_a" type="webcontent" href="a.html"/>
+    <resource identifier="r_b" type="webcontent" href="b.html"/>
+  </resources>
+</manifest>"#;
+
+fn course() -> SequencingSession {
+    SequencingSession::new(parse_activity_tree(COURSE).unwrap())
+}
+
+fn deliver(id: &str) -> Result<SequencingOutcome, SequencingError> {
+    Ok(SequencingOutcome::Deliver(id.to_string()))
+}
+
+fn report(session: &mut SequencingSession, passed: Option<bool>, score: Option<f64>) {
+    session.report_runtime_data(&RuntimeData {
+        completion_status: Some(true),
+        success_status: passed,
+        score_scaled: score,
+        session_time: 60.0,
+        suspend: false,
+    });
+}
+
+#[test]
+fn test_parse_activity_tree() {
+    let tree = parse_activity_tree(COURSE).unwrap();
+    let ids: Vec<&str> = tree.activities().map(|activity| activity.id.as_str()).collect();
+    assert_eq!(ids, vec!["org", "pretest", "chapter1", "lesson1", "lesson2", "posttest"]);
+
+    let root = tree.get(tree.root());
+    assert_eq!(root.title, "Safety Course");
+    assert!(root.sequencing.control_mode.flow);
+
+    let lesson1 = tree.get(tree.find("lesson1").unwrap());
+    assert_eq!(lesson1.href.as_deref(), Some("content/lesson1.html?page=1"));
+    assert_eq!(lesson1.sequencing.limit_conditions.attempt_limit, Some(3));
+
+    let chapter1 = tree.get(tree.find("chapter1").unwrap());
+    assert!(chapter1.href.is_none());
+    assert!(chapter1.sequencing.control_mode.forward_only);
+    assert!(!chapter1.sequencing.control_mode.choice_exit);
+    assert_eq!(chapter1.sequencing.pre_condition_rules.len(), 1);
+    assert_eq!(chapter1.sequencing.primary_objective().id, "chapter1_obj");
+    assert_eq!(chapter1.sequencing.objectives[1].maps[0].target_id, "mastery");
+    assert!(chapter1.sequencing.objectives[1].maps[0].read_satisfied);
+
+    let posttest = tree.get(tree.find("posttest").unwrap());
+    assert_eq!(posttest.sequencing.post_condition_rules[0].action, activity_tree::RuleAction::Retry);
+    assert!(posttest.sequencing.post_condition_rules[0].conditions[0].negate);
+}
+
+#[test]
+fn test_continue_and_previous_flow() {
+    let mut session = course();
+
+    assert_eq!(session.process_navigation_request(NavigationRequest::Start), deliver("pretest"));
+    report(&mut session, Some(false), None);
+    assert_eq!(session.process_navigation_request(NavigationRequest::Continue), deliver("lesson1"));
+    assert_eq!(session.process_navigation_request(NavigationRequest::Continue), deliver("lesson2"));
+
+    // Chapter 1 is forward only
+    assert_eq!(session.process_navigation_request(NavigationRequest::Previous), Err(SequencingError::ForwardOnly));
+
+    assert_eq!(session.process_navigation_request(NavigationRequest::Continue), deliver("posttest"));
+}
+
+#[test]
+fn test_previous_into_forward_only_cluster() {
+    let mut session = course();
+    session.process_navigation_request(NavigationRequest::Start).unwrap();
+    session.process_navigation_request(NavigationRequest::Continue).unwrap();
+    session.process_navigation_request(NavigationRequest::Continue).unwrap();
+    session.process_navigation_request(NavigationRequest::Continue).unwrap();
+
+    // Backing into a forward-only cluster enters at its first child
+    assert_eq!(session.process_navigation_request(NavigationRequest::Previous), deliver("lesson1"));
+}
+
+#[test]
+fn test_pretest_mastery_skips_chapter() {
+    let mut session = course();
+
+    session.process_navigation_request(NavigationRequest::Start).unwrap();
+    report(&mut session, Some(true), None);
+
+    assert_eq!(session.process_navigation_request(NavigationRequest::Continue), deliver("posttest"));
+    assert_eq!(session.tree().get(session.tree().find("lesson1").unwrap()).state.attempt_count, 0);
+}
+
+#[test]
+fn test_post_condition_retry_until_attempt_limit() {
+    let mut session = course();
+    session.process_navigation_request(NavigationRequest::Start).unwrap();
+    session.process_navigation_request(NavigationRequest::Choice("posttest".to_string())).unwrap();
+
+    report(&mut session, Some(false), None);
+    assert_eq!(session.process_navigation_request(NavigationRequest::Continue), deliver("posttest"));
+    let posttest = session.tree().find("posttest").unwrap();
+    assert_eq!(session.tree().get(posttest).state.attempt_count, 2);
+
+    report(&mut session, Some(false), None);
+    assert_eq!(session.process_navigation_request(NavigationRequest::Continue), Err(SequencingError::ActivityUnavailable));
+}
+
+#[test]
+fn test_rollup_to_root() {
+    let mut session = course();
+
+    session.process_navigation_request(NavigationRequest::Start).unwrap();
+    report(&mut session, Some(false), None);
+    session.process_navigation_request(NavigationRequest::Continue).unwrap();
+    session.process_navigation_request(NavigationRequest::Continue).unwrap();
+    session.process_navigation_request(NavigationRequest::Continue).unwrap();
+    report(&mut session, Some(true), Some(0.9));
+
+    assert_eq!(session.process_navigation_request(NavigationRequest::Continue), Ok(SequencingOutcome::EndSession));
+    assert!(session.is_ended());
+
+    let tree = session.tree();
+    let chapter1 = tree.find("chapter1").unwrap();
+    assert!(tree.primary_objective_state(chapter1).satisfied_status);
+    assert!(tree.get(chapter1).state.attempt_completion_status);
+
+    // The failed pretest does not count towards the course
+    let root = tree.primary_objective_state(tree.root());
+    assert!(root.progress_status);
+    assert!(root.satisfied_status);
+    assert!(tree.get(tree.root()).state.attempt_completion_status);
+}
+
+#[test]
+fn test_measure_rollup() {
+    let mut session = SequencingSession::new(parse_activity_tree(MEASURED).unwrap());
+
+    assert_eq!(session.process_navigation_request(NavigationRequest::Start), deliver("a"));
+    report(&mut session, None, Some(0.2));
+    assert_eq!(session.process_navigation_request(NavigationRequest::Continue), deliver("b"));
+    report(&mut session, None, Some(0.8));
+    assert_eq!(session.process_navigation_request(NavigationRequest::Continue), Ok(SequencingOutcome::EndSession));
+
+    // (0.2 * 1 + 0.8 * 3) / 4 = 0.65
+    let root = session.tree().primary_objective_state(session.tree().root());
+    assert!(root.measure_status);
+    assert!((root.normalized_measure - 0.65).abs() < 1e-9);
+    assert!(root.satisfied_status);
+}
+
+#[test]
+fn test_choice_navigation() {
+    let mut session = course();
+
+    assert_eq!(session.process_navigation_request(NavigationRequest::Start), deliver("pretest"));
+    assert_eq!(session.process_navigation_request(NavigationRequest::Choice("lesson2".to_string())), deliver("lesson2"));
+
+    // Chapter 1 may not be left by choice while active, nor entered backwards
+    assert_eq!(
+        session.process_navigation_request(NavigationRequest::Choice("posttest".to_string())),
+        Err(SequencingError::ChoiceExitNotAllowed)
+    );
+    assert_eq!(
+        session.process_navigation_request(NavigationRequest::Choice("lesson1".to_string())),
+        Err(SequencingError::ForwardOnly)
+    );
+    assert_eq!(
+        session.process_navigation_request(NavigationRequest::Choice("missing".to_string())),
+        Err(SequencingError::UnknownActivity("missing".to_string()))
+    );
+}
+
+// Each SCO of a multi-SCO package keeps its own tracking data
+#[test]
+fn test_delivered_scos_keep_their_own_data() {
+    let mut session = ScormSession {
+        id: Uuid::new_v4(),
+        package_id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        state: ScormActivityState::NotAttempted,
+        score: None,
+        max_score: None,
+        min_score: None,
+        total_time: None,
+        interactions: Vec::new(),
+        cmi: CmiDataModel::new(ScormVersion::V2004_4TH, "learner-1", "Ada Lovelace"),
+        activity_id: None,
+        activity_cmi: HashMap::new(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        completed_at: None,
+    };
+
+    session.deliver("lesson1");
+    assert!(session.cmi.initialize(""));
+    assert!(session.cmi.set_value("cmi.suspend_data", "lesson1 page 3"));
+    assert!(session.cmi.set_value("cmi.exit", "suspend"));
+    assert!(session.cmi.terminate(""));
+
+    session.deliver("lesson2");
+    assert!(session.cmi.initialize(""));
+    assert_eq!(session.cmi.get_value("cmi.suspend_data"), "");
+    assert_eq!(session.cmi.get_value("cmi.entry"), "ab-initio");
+    assert_eq!(session.cmi.get_value("cmi.learner_name"), "Ada Lovelace");
+    assert!(session.cmi.set_value("cmi.completion_status", "completed"));
+    assert!(session.cmi.terminate(""));
+
+    session.deliver("lesson1");
+    assert!(session.cmi.initialize(""));
+    assert_eq!(session.cmi.get_value("cmi.entry"), "resume");
+    assert_eq!(session.cmi.get_value("cmi.suspend_data"), "lesson1 page 3");
+    assert_eq!(session.activity_cmi["lesson2"].get("cmi.completion_status"), Some("completed"));
+}
+
+#[test]
+fn test_choice_disabled_by_parent() {
+    let mut tree = parse_activity_tree(COURSE).unwrap();
+    let root = tree.root();
+    tree.get_mut(root).sequencing.control_mode.choice = false;
+    let mut session = SequencingSession::new(tree);
+
+    assert_eq!(
+        session.process_navigation_request(NavigationRequest::Choice("posttest".to_string())),
+        Err(SequencingError::ChoiceNotAllowed)
+    );
+    assert!(!session.available_choices().contains(&"posttest".to_string()));
+    assert!(session.available_choices().contains(&"lesson1".to_string()));
+}
+
+#[test]
+fn test_suspend_all_and_resume_all() {
+    let mut session = course();
+    session.process_navigation_request(NavigationRequest::Start).unwrap();
+    session.process_navigation_request(NavigationRequest::Continue).unwrap();
+
+    assert_eq!(session.process_navigation_request(NavigationRequest::SuspendAll), Ok(SequencingOutcome::EndSession));
+    assert_eq!(session.suspended_activity(), Some("lesson1"));
+    assert_eq!(session.current_activity(), None);
+
+    assert_eq!(session.process_navigation_request(NavigationRequest::ResumeAll), deliver("lesson1"));
+    let lesson1 = session.tree().find("lesson1").unwrap();
+    assert_eq!(session.tree().get(lesson1).state.attempt_count, 1);
+}
+
+#[test]
+fn test_exit_all_ends_session() {
+    let mut session = course();
+
+    assert_eq!(session.process_navigation_request(NavigationRequest::Continue), Err(SequencingError::SessionNotBegun));
+    session.process_navigation_request(NavigationRequest::Start).unwrap();
+    assert_eq!(session.process_navigation_request(NavigationRequest::ExitAll), Ok(SequencingOutcome::EndSession));
+    assert!(!session.tree().get(session.tree().root()).state.is_active);
+    assert_eq!(session.process_navigation_request(NavigationRequest::ResumeAll), Err(SequencingError::NoSuspendedActivity));
+}
+
+#[test]
+fn test_request_validity_does_not_change_state() {
+    let mut session = course();
+    session.process_navigation_request(NavigationRequest::Start).unwrap();
+
+    assert!(session.is_request_valid(&NavigationRequest::Continue));
+    assert!(!session.is_request_valid(&NavigationRequest::Previous));
+    assert_eq!(session.current_activity(), Some("pretest"));
+}
+
+#[test]
+fn test_parse_navigation_request() {
+    assert_eq!(NavigationRequest::parse("continue"), Some(NavigationRequest::Continue));
+    assert_eq!(NavigationRequest::parse("exitAll"), Some(NavigationRequest::ExitAll));
+    assert_eq!(NavigationRequest::parse("{target=lesson2}choice"), Some(NavigationRequest::Choice("lesson2".to_string())));
+    assert_eq!(NavigationRequest::parse("{target=lesson2}jump"), None);
+    assert_eq!(NavigationRequest::parse("_none_"), None);
+}
+
+#[test]
+fn test_parse_iso8601_duration() {
+    assert_eq!(parse_iso8601_duration("PT1H30M5.5S"), Some(5405.5));
+    assert_eq!(parse_iso8601_duration("P1DT1S"), Some(86401.0));
+    assert_eq!(parse_iso8601_duration("PT"), None);
+    assert_eq!(parse_iso8601_duration("1H"), None);
+}
+
+const SCORM_1_2_MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest identifier="com.example.scorm12" version="1.0"
+    xmlns="http://www.imsproject.org/xsd/imscp_rootv1p1p2"
+    xmlns:adlcp="http://www.adlnet.org/xsd/adlcp_rootv1p2">
+  <organizations default="org">
+    <organization identifier="org">
+      <title>Fire Safety</title>
+      <item identifier="item" identifierref="sco">
+        <title>Fire Safety</title>
+      </item>
+    </organization>
+  </organizations>
+  <resources>
+    <resource identifier="sco" type="webcontent" adlcp:scormtype="sco" href="index.html"/>
+  </resources>
+</manifest>"#;
+
+fn running_model(version: ScormVersion) -> CmiDataModel {
+    let mut cmi = CmiDataModel::new(version, "learner-1", "Ada Lovelace");
+    assert!(cmi.initialize(""));
+    cmi
+}
+
+#[test]
+fn test_cmi_1_2_element_access() {
+    let mut cmi = running_model(ScormVersion::V1_2);
+
+    assert_eq!(cmi.get_value("cmi.core.student_id"), "learner-1");
+    assert_eq!(cmi.get_value("cmi.core.lesson_status"), "not attempted");
+    assert_eq!(cmi.get_value("cmi.core.lesson_location"), "");
+    assert_eq!(cmi.last_error(), 0);
+    assert_eq!(cmi.get_value("cmi.core.score._children"), "raw,min,max");
+
+    assert!(cmi.set_value("cmi.core.lesson_status", "incomplete"));
+    assert!(!cmi.set_value("cmi.core.lesson_status", "not attempted"));
+    assert_eq!(cmi.last_error(), 405);
+    assert!(!cmi.set_value("cmi.core.score.raw", "101"));
+    assert_eq!(cmi.last_error(), 405);
+    assert!(cmi.set_value("cmi.core.score.raw", ""));
+
+    assert!(!cmi.set_value("cmi.core.student_id", "someone-else"));
+    assert_eq!(cmi.last_error(), 403);
+    assert!(!cmi.set_value("cmi.core._children", "x"));
+    assert_eq!(cmi.last_error(), 402);
+    assert_eq!(cmi.get_value("cmi.core.exit"), "");
+    assert_eq!(cmi.last_error(), 404);
+    assert_eq!(cmi.get_value("cmi.core.student_id._children"), "");
+    assert_eq!(cmi.last_error(), 202);
+    assert_eq!(cmi.get_value("cmi.core.student_id._count"), "");
+    assert_eq!(cmi.last_error(), 203);
+    assert_eq!(cmi.get_value("cmi.core.favourite_colour"), "");
+    assert_eq!(cmi.last_error(), 201);
+
+    assert_eq!(cmi.error_string("201"), "Invalid argument error");
+    assert_eq!(cmi.diagnostic(""), "cmi.core.favourite_colour is not a data model element");
+
+    assert!(!cmi.set_value("cmi.student_preference.speed", "150"));
+    assert!(cmi.set_value("cmi.student_preference.speed", "-50"));
+    assert!(cmi.set_value("cmi.interactions.0.id", "q1"));
+    assert!(cmi.set_value("cmi.interactions.0.time", "13:05:59"));
+    assert!(!cmi.set_value("cmi.interactions.0.time", "25:00:00"));
+    assert!(!cmi.set_value("cmi.interactions.2.id", "q3"));
+    assert_eq!(cmi.last_error(), 201);
+    assert_eq!(cmi.get_value("cmi.interactions._count"), "1");
+}
+
+#[test]
+fn test_cmi_api_state_errors() {
+    let mut cmi = CmiDataModel::new(ScormVersion::V2004_4TH, "learner-1", "Ada Lovelace");
+
+    assert_eq!(cmi.get_value("cmi.learner_id"), "");
+    assert_eq!(cmi.last_error(), 122);
+    assert!(!cmi.terminate(""));
+    assert_eq!(cmi.last_error(), 112);
+    assert!(!cmi.initialize("unexpected"));
+    assert_eq!(cmi.last_error(), 201);
+
+    assert!(cmi.initialize(""));
+    assert!(!cmi.initialize(""));
+    assert_eq!(cmi.last_error(), 103);
+    assert!(cmi.commit(""));
+    assert!(cmi.terminate(""));
+
+    assert_eq!(cmi.get_value("cmi.learner_id"), "");
+    assert_eq!(cmi.last_error(), 123);
+    assert!(!cmi.set_value("cmi.location", "page-2"));
+    assert_eq!(cmi.last_error(), 133);
+    assert!(!cmi.commit(""));
+    assert_eq!(cmi.last_error(), 143);
+    assert!(!cmi.initialize(""));
+    assert_eq!(cmi.last_error(), 104);
+    assert_eq!(cmi.error_string("104"), "Content Instance Terminated");
+
+    let mut cmi = CmiDataModel::new(ScormVersion::V1_2, "learner-1", "Ada Lovelace");
+    assert_eq!(cmi.get_value("cmi.core.student_id"), "");
+    assert_eq!(cmi.last_error(), 301);
+}
+
+#[test]
+fn test_cmi_2004_collections() {
+    let mut cmi = running_model(ScormVersion::V2004_4TH);
+
+    // Entries are created in order, starting with their identifier
+    assert!(!cmi.set_value("cmi.interactions.0.type", "choice"));
+    assert_eq!(cmi.last_error(), 351);
+    assert!(cmi.set_value("cmi.interactions.0.id", "urn:example:q1"));
+    assert!(!cmi.set_value("cmi.interactions.2.id", "urn:example:q3"));
+    assert_eq!(cmi.last_error(), 351);
+    assert_eq!(cmi.get_value("cmi.interactions._count"), "1");
+
+    // Responses are typed by the interaction
+    assert!(!cmi.set_value("cmi.interactions.0.learner_response", "a[,]b"));
+    assert_eq!(cmi.last_error(), 408);
+    assert!(cmi.set_value("cmi.interactions.0.type", "choice"));
+    assert!(cmi.set_value("cmi.interactions.0.learner_response", "a[,]b"));
+    assert!(!cmi.set_value("cmi.interactions.0.learner_response", "a[,]a"));
+    assert_eq!(cmi.last_error(), 406);
+    assert!(cmi.set_value("cmi.interactions.0.correct_responses.0.pattern", "a[,]b"));
+    assert_eq!(cmi.get_value("cmi.interactions.0.correct_responses._count"), "1");
+    assert!(cmi.set_value("cmi.interactions.0.result", "incorrect"));
+    assert!(!cmi.set_value("cmi.interactions.0.result", "wrong"));
+    assert!(cmi.set_value("cmi.interactions.0.latency", "PT12.5S"));
+
+    // Objective identifiers are unique and fixed
+    assert!(cmi.set_value("cmi.objectives.0.id", "obj-1"));
+    assert!(!cmi.set_value("cmi.objectives.1.id", "obj-1"));
+    assert_eq!(cmi.last_error(), 351);
+    assert!(!cmi.set_value("cmi.objectives.0.id", "obj-2"));
+    assert!(cmi.set_value("cmi.objectives.0.score.scaled", "0.5"));
+    assert!(!cmi.set_value("cmi.objectives.0.score.scaled", "1.5"));
+    assert_eq!(cmi.last_error(), 407);
+    assert!(!cmi.set_value("cmi.objectives.0.score.scaled", "half"));
+    assert_eq!(cmi.last_error(), 406);
+
+    assert_eq!(cmi.get_value("cmi.objectives.5.id"), "");
+    assert_eq!(cmi.last_error(), 301);
+    assert_eq!(cmi.get_value("cmi.objectives.0.description"), "");
+    assert_eq!(cmi.last_error(), 403);
+    assert_eq!(cmi.get_value("cmi.location"), "");
+    assert_eq!(cmi.last_error(), 403);
+    assert!(!cmi.set_value("cmi._version", "2.0"));
+    assert_eq!(cmi.last_error(), 404);
+    assert_eq!(cmi.get_value("cmi.session_time"), "");
+    assert_eq!(cmi.last_error(), 405);
+
+    assert!(cmi.set_value("cmi.comments_from_learner.0.comment", "{lang=en-GB}Too long"));
+    assert!(!cmi.set_value("cmi.comments_from_learner.0.timestamp", "2026-13-01"));
+    assert!(cmi.set_value("cmi.comments_from_learner.0.timestamp", "2026-10-17T09:30:00Z"));
+    assert!(cmi.set_value("adl.nav.request", "{target=lesson2}choice"));
+    assert!(!cmi.set_value("adl.nav.request", "sideways"));
+    assert!(!cmi.set_value("adl.nav.request_valid.continue", "true"));
+    assert_eq!(cmi.last_error(), 404);
+}
+
+#[test]
+fn test_cmi_suspend_data_limits() {
+    let mut cmi = running_model(ScormVersion::V1_2);
+    assert!(cmi.set_value("cmi.suspend_data", &"x".repeat(cmi::SUSPEND_DATA_LIMIT_1_2)));
+    assert!(!cmi.set_value("cmi.suspend_data", &"x".repeat(cmi::SUSPEND_DATA_LIMIT_1_2 + 1)));
+    assert_eq!(cmi.last_error(), 405);
+
+    let mut cmi = running_model(ScormVersion::V2004_4TH);
+    assert!(cmi.set_value("cmi.suspend_data", &"x".repeat(cmi::SUSPEND_DATA_LIMIT_2004)));
+    assert!(!cmi.set_value("cmi.suspend_data", &"x".repeat(cmi::SUSPEND_DATA_LIMIT_2004 + 1)));
+    assert_eq!(cmi.last_error(), 406);
+    assert_eq!(cmi.get_value("cmi.suspend_data").len(), cmi::SUSPEND_DATA_LIMIT_2004);
+}
+
+#[test]
+fn test_cmi_session_time_accumulates() {
+    let mut cmi = running_model(ScormVersion::V1_2);
+    assert!(cmi.set_value("cmi.core.session_time", "0000:30:00"));
+    assert!(!cmi.set_value("cmi.core.session_time", "PT30M"));
+    assert!(cmi.terminate(""));
+
+    cmi.relaunch();
+    assert!(cmi.initialize(""));
+    assert_eq!(cmi.get_value("cmi.core.entry"), "");
+    assert_eq!(cmi.get_value("cmi.core.total_time"), "0000:30:00.00");
+    assert!(cmi.set_value("cmi.core.session_time", "00:45:30.5"));
+    assert!(cmi.terminate(""));
+    assert_eq!(cmi.get("cmi.core.total_time"), Some("0001:15:30.50"));
+
+    let mut cmi = running_model(ScormVersion::V2004_4TH);
+    assert!(cmi.set_value("cmi.session_time", "PT1H30M"));
+    assert!(cmi.set_value("cmi.location", "page-7"));
+    assert!(cmi.set_value("cmi.exit", "suspend"));
+    assert!(cmi.terminate(""));
+
+    // A suspended attempt resumes with its data
+    cmi.relaunch();
+    assert!(cmi.initialize(""));
+    assert_eq!(cmi.get_value("cmi.entry"), "resume");
+    assert_eq!(cmi.get_value("cmi.location"), "page-7");
+    assert!(cmi.set_value("cmi.session_time", "PT45M"));
+    assert!(cmi.terminate(""));
+    assert_eq!(cmi.get("cmi.total_time"), Some("PT2H15M0S"));
+    assert_eq!(cmi.total_time_seconds(), 8100.0);
+
+    // Any other exit starts a new attempt
+    cmi.relaunch();
+    assert!(cmi.initialize(""));
+    assert_eq!(cmi.get_value("cmi.entry"), "ab-initio");
+    assert_eq!(cmi.get_value("cmi.learner_id"), "learner-1");
+    assert_eq!(cmi.get_value("cmi.location"), "");
+    assert_eq!(cmi.last_error(), 403);
+}
+
+#[test]
+fn test_cmi_lms_evaluated_status() {
+    let mut cmi = CmiDataModel::new(ScormVersion::V2004_4TH, "learner-1", "Ada Lovelace");
+    cmi.set_lms_value("cmi.completion_threshold", "0.8");
+    cmi.set_lms_value("cmi.scaled_passing_score", "0.7");
+    assert!(cmi.initialize(""));
+
+    assert_eq!(cmi.get_value("cmi.completion_status"), "unknown");
+    assert!(cmi.set_value("cmi.completion_status", "completed"));
+    assert!(cmi.set_value("cmi.progress_measure", "0.5"));
+    assert_eq!(cmi.get_value("cmi.completion_status"), "incomplete");
+    assert!(cmi.set_value("cmi.progress_measure", "0.9"));
+    assert_eq!(cmi.get_value("cmi.completion_status"), "completed");
+
+    assert_eq!(cmi.get_value("cmi.success_status"), "unknown");
+    assert!(cmi.set_value("cmi.score.scaled", "0.75"));
+    assert_eq!(cmi.get_value("cmi.success_status"), "passed");
+
+    let mut cmi = CmiDataModel::new(ScormVersion::V1_2, "learner-1", "Ada Lovelace");
+    cmi.set_lms_value("cmi.student_data.mastery_score", "80");
+    assert!(cmi.initialize(""));
+    assert!(cmi.set_value("cmi.core.score.raw", "85"));
+    assert!(cmi.terminate(""));
+    assert_eq!(cmi.get("cmi.core.lesson_status"), Some("passed"));
+}
+
+fn write_package(dir: &Path, manifest: &str) -> PathBuf {
+    let path = dir.join("package.zip");
+    let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    zip.start_file("imsmanifest.xml", options).unwrap();
+    zip.write_all(manifest.as_bytes()).unwrap();
+    zip.start_file("index.html", options).unwrap();
+    zip.write_all(b"<html></html>").unwrap();
+    zip.finish().unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_sessions_resume_after_restart() {
+    let dir = std::env::temp_dir().join(format!("ordo-scorm-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let package_path = write_package(&dir, SCORM_1_2_MANIFEST);
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    let user_id = Uuid::new_v4();
+
+    let mut service = ScormService::new(dir.join("packages")).unwrap()
+        .with_store(pool.clone())
+        .await
+        .unwrap();
+    let package_id = service.import_package(&package_path).await.unwrap();
+    let session_id = service.launch_session(&package_id, &user_id).await.unwrap();
+
+    assert_eq!(service.handle_api_call(&session_id, "LMSInitialize", &[""]).await.unwrap(), "true");
+    service.handle_api_call(&session_id, "LMSSetValue", &["cmi.suspend_data", "page=4"]).await.unwrap();
+    service.handle_api_call(&session_id, "LMSSetValue", &["cmi.core.lesson_status", "incomplete"]).await.unwrap();
+    service.handle_api_call(&session_id, "LMSSetValue", &["cmi.core.exit", "suspend"]).await.unwrap();
+    service.handle_api_call(&session_id, "LMSSetValue", &["cmi.core.session_time", "00:10:00"]).await.unwrap();
+    assert_eq!(service.handle_api_call(&session_id, "LMSSetValue", &["cmi.core.credit", "no-credit"]).await.unwrap(), "false");
+    assert_eq!(service.handle_api_call(&session_id, "LMSGetLastError", &[]).await.unwrap(), "403");
+    assert_eq!(service.handle_api_call(&session_id, "LMSFinish", &[""]).await.unwrap(), "true");
+    drop(service);
+
+    // A new service instance sees the package and resumes the session
+    let mut service = ScormService::new(dir.join("packages")).unwrap()
+        .with_store(pool)
+        .await
+        .unwrap();
+    assert!(service.get_package(&package_id).is_some());
+    assert_eq!(service.get_session(&session_id).unwrap().state, ScormActivityState::Incomplete);
+    assert_eq!(service.launch_session(&package_id, &user_id).await.unwrap(), session_id);
+
+    service.handle_api_call(&session_id, "LMSInitialize", &[""]).await.unwrap();
+    assert_eq!(service.handle_api_call(&session_id, "LMSGetValue", &["cmi.core.entry"]).await.unwrap(), "resume");
+    assert_eq!(service.handle_api_call(&session_id, "LMSGetValue", &["cmi.suspend_data"]).await.unwrap(), "page=4");
+    assert_eq!(service.handle_api_call(&session_id, "LMSGetValue", &["cmi.core.total_time"]).await.unwrap(), "0000:10:00.00");
+
+    fs::remove_dir_all(dir).unwrap();
+}
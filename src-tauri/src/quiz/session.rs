@@ -1,4 +1,5 @@
-use super::models::{Quiz, Question, Answer, QuizSettings, StudyMode};
+use super::models::{Quiz, Question, Answer, QuizSettings, StudyMode, WrongChoicePenalty};
+use super::exam::timer::{Checkpoint, ExamTimer};
 use chrono::{DateTime, Utc, Duration};
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
@@ -17,6 +18,9 @@ pub struct QuizSession {
     pub time_remaining: Option<i32>, // in seconds
     pub quiz_settings: QuizSettings,
     pub question_order: Vec<usize>, // Shuffled indices if shuffle_questions is true
+    /// Time used by a timed attempt
+    #[serde(default)]
+    pub timer: Option<ExamTimer>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +29,8 @@ pub struct SessionAnswer {
     pub answer: Answer,
     pub timestamp: DateTime<Utc>,
     pub is_correct: Option<bool>,
+    #[serde(default)]
+    pub credit: Option<f32>, // Fraction of the question's credit, None until graded
     pub time_spent: i32, // in seconds
 }
 
@@ -47,8 +53,10 @@ impl QuizSession {
                 show_correct_answers: true,
                 passing_score: None,
                 study_mode: StudyMode::MultipleChoice,
+                wrong_choice_penalty: WrongChoicePenalty::default(),
             },
             question_order: Vec::new(),
+            timer: None,
         }
     }
 
@@ -65,6 +73,7 @@ impl QuizSession {
             time_remaining: quiz.settings.time_limit.map(|mins| mins * 60),
             quiz_settings: quiz.settings.clone(),
             question_order: (0..quiz.questions.len()).collect(),
+            timer: quiz.settings.time_limit.map(|mins| ExamTimer::new(mins as i64 * 60_000, Utc::now(), None)),
         };
 
         // Shuffle questions if needed
@@ -86,14 +95,16 @@ impl QuizSession {
         let timestamp = Utc::now();
         let time_spent = (timestamp - self.started_at).num_seconds() as i32;
 
-        // Check if the answer is correct
-        let is_correct = question.check_answer(&answer);
+        // Score the answer; essays and drawings wait for manual grading
+        let score = question.score_answer(&answer, &self.quiz_settings);
+        let is_correct = score.is_correct();
 
         self.answers.push(SessionAnswer {
             question_id,
             answer,
             timestamp,
-            is_correct: Some(is_correct),
+            is_correct: (!score.needs_review).then_some(is_correct),
+            credit: (!score.needs_review).then_some(score.credit),
             time_spent,
         });
 
@@ -115,11 +126,13 @@ impl QuizSession {
             return Ok(0.0);
         }
 
-        let correct_answers = self.answers.iter()
-            .filter(|a| a.is_correct.unwrap_or(false))
-            .count() as f32;
+        // Partial credit counts; answers from before it existed count as
+        // fully right or wrong
+        let earned: f32 = self.answers.iter()
+            .map(|a| a.credit.unwrap_or(if a.is_correct.unwrap_or(false) { 1.0 } else { 0.0 }))
+            .sum();
 
-        let score = (correct_answers / total_questions) * 100.0;
+        let score = (earned / total_questions) * 100.0;
         self.score = Some(score);
 
         Ok(score)
@@ -141,22 +154,64 @@ impl QuizSession {
         (self.current_question_index as f32 + 1.0) / (self.question_order.len() as f32)
     }
 
-    pub fn update_time_remaining(&mut self) -> Option<i32> {
-        if let Some(time_limit) = self.quiz_settings.time_limit {
-            let elapsed = (Utc::now() - self.started_at).num_seconds() as i32;
-            let time_limit_seconds = time_limit * 60;
-            let remaining = time_limit_seconds - elapsed;
+    /// Save the answer to a question of a timed attempt, replacing any
+    /// answer saved before. Unlike `submit_answer`, the attempt stays on
+    /// the current question.
+    pub fn save_answer(&mut self, question_id: Uuid, answer: Answer, quiz: &Quiz) -> Result<(), String> {
+        if self.completed_at.is_some() {
+            return Err("The attempt has ended".to_string());
+        }
 
-            self.time_remaining = Some(remaining.max(0));
+        let question = quiz.questions.iter()
+            .find(|q| q.id == question_id)
+            .ok_or_else(|| format!("Question not found: {}", question_id))?;
 
-            // Auto-complete if time is up
-            if remaining <= 0 && self.completed_at.is_none() {
-                let _ = self.complete();
-            }
+        let timestamp = Utc::now();
+        let score = question.score_answer(&answer, &self.quiz_settings);
+        let saved = SessionAnswer {
+            question_id,
+            answer,
+            timestamp,
+            is_correct: (!score.needs_review).then_some(score.is_correct()),
+            credit: (!score.needs_review).then_some(score.credit),
+            time_spent: (timestamp - self.started_at).num_seconds() as i32,
+        };
 
-            self.time_remaining
-        } else {
-            None
+        match self.answers.iter_mut().find(|a| a.question_id == question_id) {
+            Some(existing) => *existing = saved,
+            None => self.answers.push(saved),
         }
+        Ok(())
     }
-}
\ No newline at end of file
+
+    /// Count the time used since the last checkpoint and complete the
+    /// attempt once time is up. `monotonic` is the time the monotonic clock
+    /// measured since the last checkpoint, if it has been running since;
+    /// `server_now` is the server's time, when known. Returns `None` for
+    /// untimed or completed sessions.
+    pub fn update_time_remaining(
+        &mut self,
+        monotonic: Option<std::time::Duration>,
+        server_now: Option<DateTime<Utc>>,
+    ) -> Option<Checkpoint> {
+        if self.completed_at.is_some() {
+            return None;
+        }
+
+        // Sessions from before timers were kept count from their start
+        if self.timer.is_none() {
+            let limit = self.quiz_settings.time_limit?;
+            self.timer = Some(ExamTimer::new(limit as i64 * 60_000, self.started_at, None));
+        }
+        let timer = self.timer.as_mut()?;
+
+        let checkpoint = timer.checkpoint(monotonic, Utc::now(), server_now);
+        self.time_remaining = Some(Duration::milliseconds(checkpoint.remaining_ms + 999).num_seconds() as i32);
+
+        if checkpoint.remaining_ms == 0 {
+            let _ = self.complete();
+        }
+
+        Some(checkpoint)
+    }
+}
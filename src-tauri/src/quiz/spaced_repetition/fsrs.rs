@@ -0,0 +1,209 @@
+// FSRS (Free Spaced Repetition Scheduler), version 4.5
+//
+// Each card has a memory state: its stability, the number of days after
+// which recall drops to 90%, and its difficulty, from 1 to 10. The chance
+// of recalling a card, its retrievability, decays with the days since the
+// last review along a power forgetting curve. A card is due when its
+// retrievability falls to the deck's desired retention, and every review
+// updates the memory state from the grade and the retrievability at the
+// time of the review. The 17 weights of the model default to values fitted
+// to a large body of Anki reviews; `optimizer` fits them to a user's own.
+
+use serde::{Serialize, Deserialize};
+
+use super::FlashcardRating;
+
+/// Exponent of the forgetting curve
+const DECAY: f64 = -0.5;
+
+/// Chosen so that retrievability is 90% after `stability` days
+const FACTOR: f64 = 19.0 / 81.0;
+
+pub const MIN_DIFFICULTY: f64 = 1.0;
+pub const MAX_DIFFICULTY: f64 = 10.0;
+
+/// Stability is kept within these bounds, in days
+pub const MIN_STABILITY: f64 = 0.01;
+pub const MAX_STABILITY: f64 = 36500.0;
+
+/// Longest interval the scheduler gives, in days
+pub const MAX_INTERVAL: i32 = 36500;
+
+/// Weights fitted to the FSRS benchmark data
+pub const DEFAULT_WEIGHTS: [f64; 17] = [
+    0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461, 2.1072, 0.0793,
+    0.3246, 1.587, 0.2272, 2.8755,
+];
+
+/// Range each weight is kept in, by the FSRS reference optimizer
+pub const WEIGHT_BOUNDS: [(f64, f64); 17] = [
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (1.0, 10.0),
+    (0.1, 5.0),
+    (0.1, 5.0),
+    (0.0, 0.75),
+    (0.0, 4.0),
+    (0.0, 0.8),
+    (0.01, 3.0),
+    (0.5, 5.0),
+    (0.01, 0.2),
+    (0.01, 0.9),
+    (0.01, 3.0),
+    (0.0, 1.0),
+    (1.0, 6.0),
+];
+
+/// FSRS grade of a review
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Grade {
+    Again = 1,
+    Hard = 2,
+    Good = 3,
+    Easy = 4,
+}
+
+impl From<FlashcardRating> for Grade {
+    fn from(rating: FlashcardRating) -> Self {
+        match rating {
+            FlashcardRating::Blackout | FlashcardRating::Familiar => Grade::Again,
+            FlashcardRating::Difficult => Grade::Hard,
+            FlashcardRating::Hesitation => Grade::Good,
+            FlashcardRating::Perfect => Grade::Easy,
+        }
+    }
+}
+
+impl Grade {
+    pub fn is_recall(self) -> bool {
+        self != Grade::Again
+    }
+
+    fn value(self) -> f64 {
+        self as i32 as f64
+    }
+}
+
+/// Memory state of a card
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MemoryState {
+    /// Days after which retrievability falls to 90%
+    pub stability: f64,
+    pub difficulty: f64,
+}
+
+impl MemoryState {
+    /// State of a card scheduled with SM-2 until now. SM-2 intervals aim at
+    /// about 90% recall, so the interval stands in for the stability, and
+    /// the default ease factor of 2.5 maps to a middling difficulty of 5,
+    /// the lowest of 1.3 to the hardest.
+    pub fn from_sm2(ease_factor: f32, interval: i32) -> Self {
+        let difficulty = 5.0 + (2.5 - ease_factor as f64) * 5.0 / 1.2;
+        Self {
+            stability: (interval as f64).clamp(MIN_STABILITY, MAX_STABILITY),
+            difficulty: difficulty.clamp(MIN_DIFFICULTY, MAX_DIFFICULTY),
+        }
+    }
+}
+
+/// FSRS model with a set of weights and a target retention
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fsrs {
+    pub weights: [f64; 17],
+    pub desired_retention: f64,
+}
+
+impl Default for Fsrs {
+    fn default() -> Self {
+        Self { weights: DEFAULT_WEIGHTS, desired_retention: 0.9 }
+    }
+}
+
+impl Fsrs {
+    /// Model with given weights. Weights of the wrong length are ignored.
+    pub fn new(weights: Option<&[f64]>, desired_retention: f64) -> Self {
+        let weights = weights
+            .and_then(|weights| <[f64; 17]>::try_from(weights).ok())
+            .unwrap_or(DEFAULT_WEIGHTS);
+        Self { weights, desired_retention: desired_retention.clamp(0.7, 0.99) }
+    }
+
+    /// Chance of recalling a card `elapsed_days` after its last review
+    pub fn retrievability(stability: f64, elapsed_days: f64) -> f64 {
+        (1.0 + FACTOR * elapsed_days.max(0.0) / stability).powf(DECAY)
+    }
+
+    /// Days until retrievability falls to the desired retention
+    pub fn next_interval(&self, stability: f64) -> i32 {
+        let days = stability / FACTOR * (self.desired_retention.powf(1.0 / DECAY) - 1.0);
+        (days.round() as i32).clamp(1, MAX_INTERVAL)
+    }
+
+    /// State after the first review of a new card
+    pub fn initial_state(&self, grade: Grade) -> MemoryState {
+        MemoryState {
+            stability: self.initial_stability(grade),
+            difficulty: self.initial_difficulty(grade),
+        }
+    }
+
+    /// State after reviewing a card `elapsed_days` after its last review
+    pub fn next_state(&self, state: MemoryState, elapsed_days: f64, grade: Grade) -> MemoryState {
+        let retrievability = Self::retrievability(state.stability, elapsed_days);
+        let stability = if grade.is_recall() {
+            self.recall_stability(state, retrievability, grade)
+        } else {
+            self.forget_stability(state, retrievability)
+        };
+
+        MemoryState {
+            stability: stability.clamp(MIN_STABILITY, MAX_STABILITY),
+            difficulty: self.next_difficulty(state.difficulty, grade),
+        }
+    }
+
+    fn initial_stability(&self, grade: Grade) -> f64 {
+        self.weights[grade as usize - 1].max(MIN_STABILITY)
+    }
+
+    fn initial_difficulty(&self, grade: Grade) -> f64 {
+        let w = &self.weights;
+        (w[4] - (grade.value() - 3.0) * w[5]).clamp(MIN_DIFFICULTY, MAX_DIFFICULTY)
+    }
+
+    /// Difficulty moves with the grade and reverts towards that of a new
+    /// card graded Good
+    fn next_difficulty(&self, difficulty: f64, grade: Grade) -> f64 {
+        let w = &self.weights;
+        let moved = difficulty - w[6] * (grade.value() - 3.0);
+        let reverted = w[7] * self.initial_difficulty(Grade::Good) + (1.0 - w[7]) * moved;
+        reverted.clamp(MIN_DIFFICULTY, MAX_DIFFICULTY)
+    }
+
+    fn recall_stability(&self, state: MemoryState, retrievability: f64, grade: Grade) -> f64 {
+        let w = &self.weights;
+        let hard_penalty = if grade == Grade::Hard { w[15] } else { 1.0 };
+        let easy_bonus = if grade == Grade::Easy { w[16] } else { 1.0 };
+
+        state.stability
+            * (w[8].exp()
+                * (11.0 - state.difficulty)
+                * state.stability.powf(-w[9])
+                * ((w[10] * (1.0 - retrievability)).exp() - 1.0)
+                * hard_penalty
+                * easy_bonus
+                + 1.0)
+    }
+
+    fn forget_stability(&self, state: MemoryState, retrievability: f64) -> f64 {
+        let w = &self.weights;
+        let stability = w[11]
+            * state.difficulty.powf(-w[12])
+            * ((state.stability + 1.0).powf(w[13]) - 1.0)
+            * (w[14] * (1.0 - retrievability)).exp();
+        // Forgetting never makes a card more stable
+        stability.min(state.stability)
+    }
+}
@@ -1,3 +1,18 @@
+// Spaced repetition of flashcards
+//
+// Each user picks the scheduling algorithm per deck, a deck being the quiz
+// a card's question belongs to. SM-2 is the default. FSRS tracks each
+// card's stability and difficulty, schedules reviews at the deck's desired
+// retention and can fit its weights to the user's review log. Cards reviewed
+// with SM-2 before their deck switched to FSRS take their first memory state
+// from their SM-2 interval and ease factor.
+
+pub mod fsrs;
+pub mod optimizer;
+
+#[cfg(test)]
+mod tests;
+
 use super::models::{FlashcardData, Quiz, Question, Answer};
 use super::session::QuizSession;
 use super::storage::HybridQuizStore;
@@ -7,6 +22,9 @@ use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 use std::error::Error;
 
+pub use fsrs::{Fsrs, Grade, MemoryState};
+pub use optimizer::FitResult;
+
 /// Original SM-2 parameters structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SM2Parameters {
@@ -22,7 +40,7 @@ pub struct SpacedRepetitionScheduler {
 }
 
 /// Rating provided by the user for a flashcard
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FlashcardRating {
     /// Rating 1: Complete blackout, wrong response
     Blackout = 1,
@@ -52,6 +70,93 @@ impl From<i32> for FlashcardRating {
     }
 }
 
+/// Algorithm that schedules a deck's cards
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SchedulingAlgorithm {
+    #[default]
+    Sm2,
+    Fsrs,
+}
+
+/// A user's scheduling settings for a deck
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeckSchedulerSettings {
+    pub algorithm: SchedulingAlgorithm,
+
+    /// Chance of recall at which FSRS schedules reviews
+    #[serde(default = "default_desired_retention")]
+    pub desired_retention: f64,
+
+    /// FSRS weights fitted to the user's reviews; the defaults when unset
+    #[serde(default)]
+    pub weights: Option<Vec<f64>>,
+}
+
+fn default_desired_retention() -> f64 {
+    0.9
+}
+
+impl Default for DeckSchedulerSettings {
+    fn default() -> Self {
+        Self {
+            algorithm: SchedulingAlgorithm::default(),
+            desired_retention: default_desired_retention(),
+            weights: None,
+        }
+    }
+}
+
+impl DeckSchedulerSettings {
+    pub fn fsrs(&self) -> Fsrs {
+        Fsrs::new(self.weights.as_deref(), self.desired_retention)
+    }
+}
+
+/// One review of a card, as kept for fitting FSRS weights
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewLogEntry {
+    pub question_id: Uuid,
+    pub user_id: Uuid,
+    pub deck_id: Uuid,
+    pub rating: FlashcardRating,
+    pub reviewed_at: DateTime<Utc>,
+}
+
+/// FSRS memory state of a card. Cards only reviewed with SM-2 so far get
+/// one from their SM-2 data; cards never reviewed have none.
+pub fn memory_state(data: &FlashcardData) -> Option<MemoryState> {
+    match (data.stability, data.difficulty) {
+        (Some(stability), Some(difficulty)) => Some(MemoryState { stability, difficulty }),
+        _ if data.repetitions > 0 || data.interval > 0 => Some(MemoryState::from_sm2(data.ease_factor, data.interval)),
+        _ => None,
+    }
+}
+
+/// Chance that the user recalls a card now
+pub fn retrievability(data: &FlashcardData, now: DateTime<Utc>) -> Option<f64> {
+    memory_state(data).map(|state| Fsrs::retrievability(state.stability, days_between(data.last_reviewed, now)))
+}
+
+/// Update a card with FSRS after a review at `now`
+pub fn update_fsrs(fsrs: &Fsrs, data: &mut FlashcardData, rating: FlashcardRating, now: DateTime<Utc>) {
+    let grade = Grade::from(rating);
+    let state = match memory_state(data) {
+        Some(state) => fsrs.next_state(state, days_between(data.last_reviewed, now), grade),
+        None => fsrs.initial_state(grade),
+    };
+
+    data.stability = Some(state.stability);
+    data.difficulty = Some(state.difficulty);
+    data.repetitions = if grade.is_recall() { data.repetitions + 1 } else { 0 };
+    data.interval = fsrs.next_interval(state.stability);
+    data.last_reviewed = now;
+    data.due_date = now + Duration::days(data.interval as i64);
+}
+
+fn days_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_seconds().max(0) as f64 / 86_400.0
+}
+
 impl SpacedRepetitionScheduler {
     pub fn new(store: Arc<HybridQuizStore>) -> Self {
         Self { store }
@@ -76,12 +181,17 @@ impl SpacedRepetitionScheduler {
         Ok(updated_data.due_date)
     }
 
+    /// SM-2 easiness factor after a review of quality `performance`, 0 to 5:
+    /// EF' = EF + (0.1 - (5 - q) * (0.08 + (5 - q) * 0.02)), at least 1.3
+    fn sm2_easiness_factor(easiness_factor: f32, performance: f32) -> f32 {
+        (easiness_factor + (0.1 - (5.0 - performance) * (0.08 + (5.0 - performance) * 0.02))).max(1.3)
+    }
+
     /// Legacy calculation method for backward compatibility
-    fn calculate_sm2(&self, mut params: SM2Parameters, performance: u8) -> SM2Parameters {
+    fn calculate_sm2(mut params: SM2Parameters, performance: u8) -> SM2Parameters {
         let performance = performance.clamp(0, 5) as f32;
 
-        params.easiness_factor = params.easiness_factor.max(1.3)
-            * (0.1 - (5.0 - performance) * (0.08 + (5.0 - performance) * 0.02));
+        params.easiness_factor = Self::sm2_easiness_factor(params.easiness_factor, performance);
 
         if performance < 3.0 {
             params.repetitions = 0;
@@ -99,6 +209,7 @@ impl SpacedRepetitionScheduler {
     }
 
     /// Process a flashcard rating and update the spaced repetition data
+    /// with the algorithm the user chose for the card's deck
     pub async fn process_rating(
         &self,
         question_id: Uuid,
@@ -118,21 +229,94 @@ impl SpacedRepetitionScheduler {
                     repetitions: 0,   // Number of successful repetitions
                     due_date: Utc::now(), // Due immediately
                     last_reviewed: Utc::now(),
+                    stability: None,
+                    difficulty: None,
                 }
             }
         };
 
+        // Cards belong to the deck of their question
+        let deck_id = self.store.get_question(question_id).await?.quiz_id;
+        let settings = self.get_deck_settings(user_id, deck_id).await?;
+        let now = Utc::now();
+
         // Update flashcard data based on rating
-        self.update_flashcard_data(&mut flashcard_data, rating);
+        match settings.algorithm {
+            SchedulingAlgorithm::Sm2 => Self::update_flashcard_data(&mut flashcard_data, rating),
+            SchedulingAlgorithm::Fsrs => update_fsrs(&settings.fsrs(), &mut flashcard_data, rating, now),
+        }
 
         // Store updated flashcard data
         self.store.store_flashcard_data(&flashcard_data).await?;
 
+        // Log the review whatever the algorithm, so the weights can be fitted
+        // when the deck moves to FSRS
+        self.store.store_flashcard_review(&ReviewLogEntry {
+            question_id,
+            user_id,
+            deck_id,
+            rating,
+            reviewed_at: now,
+        }).await?;
+
         Ok(flashcard_data)
     }
 
+    /// A user's scheduling settings for a deck, SM-2 unless chosen otherwise
+    pub async fn get_deck_settings(
+        &self,
+        user_id: Uuid,
+        deck_id: Uuid,
+    ) -> Result<DeckSchedulerSettings, Box<dyn Error + Send + Sync>> {
+        Ok(self.store.get_deck_scheduler_settings(user_id, deck_id).await?.unwrap_or_default())
+    }
+
+    /// Choose the algorithm and FSRS settings of a deck. Cards switch
+    /// algorithm at their next review.
+    pub async fn set_deck_settings(
+        &self,
+        user_id: Uuid,
+        deck_id: Uuid,
+        settings: &DeckSchedulerSettings,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !(0.7..=0.99).contains(&settings.desired_retention) {
+            return Err("Desired retention must be between 0.7 and 0.99".into());
+        }
+        if let Some(weights) = &settings.weights {
+            let in_bounds = weights.len() == fsrs::DEFAULT_WEIGHTS.len()
+                && weights.iter().zip(fsrs::WEIGHT_BOUNDS.iter()).all(|(w, (low, high))| (*low..=*high).contains(w));
+            if !in_bounds {
+                return Err("FSRS needs 17 weights within their bounds".into());
+            }
+        }
+
+        self.store.store_deck_scheduler_settings(user_id, deck_id, settings).await?;
+        Ok(())
+    }
+
+    /// Fit FSRS weights to the user's review log and use them for a deck.
+    /// Memory is personal rather than per deck, so all of the user's reviews
+    /// are used.
+    pub async fn optimize_deck(
+        &self,
+        user_id: Uuid,
+        deck_id: Uuid,
+    ) -> Result<FitResult, Box<dyn Error + Send + Sync>> {
+        let mut settings = self.get_deck_settings(user_id, deck_id).await?;
+        let reviews = self.store.get_flashcard_reviews(user_id).await?;
+        let initial = settings.fsrs().weights;
+
+        let result = tokio::task::spawn_blocking(move || optimizer::fit(&reviews, &initial)).await?
+            .ok_or_else(|| format!("At least {} reviews after the first of each card are needed", optimizer::MIN_REVIEWS))?;
+
+        settings.weights = Some(result.weights.clone());
+        self.store.store_deck_scheduler_settings(user_id, deck_id, &settings).await?;
+
+        Ok(result)
+    }
+
     /// Update flashcard data using the SM-2 algorithm with enhancements
-    fn update_flashcard_data(&self, data: &mut FlashcardData, rating: FlashcardRating) {
+    fn update_flashcard_data(data: &mut FlashcardData, rating: FlashcardRating) {
         // Convert rating to numeric value
         let rating_value = rating as i32;
 
@@ -169,11 +353,8 @@ impl SpacedRepetitionScheduler {
             };
         }
 
-        // Update ease factor based on rating
-        // This is the standard SM-2 formula with a slight modification
-        // to make it more responsive to user performance
-        let ease_factor_delta = 0.1 - (5 - rating_value) as f32 * 0.08;
-        data.ease_factor = (data.ease_factor + ease_factor_delta).max(1.3);
+        // Update ease factor based on rating, with the standard SM-2 formula
+        data.ease_factor = Self::sm2_easiness_factor(data.ease_factor, rating_value as f32);
 
         // Calculate next due date
         // Add jitter to prevent cards from clumping together
@@ -304,11 +485,16 @@ impl SpacedRepetitionScheduler {
         }
 
         // Calculate retention rate (percentage of cards that were answered correctly)
-        // This requires looking at review history, which we'll implement in a future version
-        // For now, we'll estimate based on ease factors
+        // Cards scheduled with FSRS know their retrievability; for the others
+        // we estimate it based on ease factors
         if !all_flashcards.is_empty() {
             let retention_sum: f32 = all_flashcards.iter()
                 .map(|card| {
+                    if card.stability.is_some() {
+                        if let Some(retrievability) = retrievability(card, now) {
+                            return retrievability as f32;
+                        }
+                    }
                     // Convert ease factor to estimated retention rate
                     // Ease factor 2.5 (default) corresponds to about 85% retention
                     let base_retention = 0.85;
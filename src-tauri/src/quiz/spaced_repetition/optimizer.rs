@@ -0,0 +1,142 @@
+// Fitting FSRS weights to a user's review log
+//
+// Each card's reviews are replayed through the model. Before every review
+// after the first, the model predicts the chance of recall, and the fit
+// minimises the log loss of those predictions against whether the card was
+// actually recalled. The loss is minimised with Adam on numerical gradients,
+// keeping each weight within its bounds. Reviews on the same day as the
+// previous one say little about long-term memory and are skipped, as the
+// FSRS reference optimizer does.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use super::fsrs::{Fsrs, Grade, MemoryState, WEIGHT_BOUNDS};
+use super::ReviewLogEntry;
+
+/// Fewest predicted reviews worth fitting to
+pub const MIN_REVIEWS: usize = 50;
+
+const STEPS: usize = 200;
+const LEARNING_RATE: f64 = 0.04;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+/// Step of the numerical gradient
+const DELTA: f64 = 1e-4;
+
+/// Stop once a step improves the loss by less than this
+const TOLERANCE: f64 = 1e-7;
+
+/// Weights fitted to a review log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FitResult {
+    pub weights: Vec<f64>,
+
+    /// Reviews whose recall the model predicted
+    pub reviews: usize,
+
+    /// Mean log loss with the initial and the fitted weights
+    pub loss_before: f64,
+    pub loss_after: f64,
+}
+
+/// Reviews of one card as days since the previous review and grade
+type History = Vec<(f64, Grade)>;
+
+/// Fit weights to a review log, starting from `initial`. Returns `None`
+/// when the log has fewer than `MIN_REVIEWS` reviews to predict. The
+/// initial weights are kept unless the fitted ones predict better.
+pub fn fit(reviews: &[ReviewLogEntry], initial: &[f64; 17]) -> Option<FitResult> {
+    let histories = histories(reviews);
+    let count: usize = histories.iter().map(|history| history.len() - 1).sum();
+    if count < MIN_REVIEWS {
+        return None;
+    }
+
+    let loss_before = loss(&histories, initial, count);
+    let mut weights = *initial;
+    let mut current = loss_before;
+    let mut m = [0.0; 17];
+    let mut v = [0.0; 17];
+
+    for step in 1..=STEPS {
+        let gradient = gradient(&histories, &weights, count);
+        for (i, &(low, high)) in WEIGHT_BOUNDS.iter().enumerate() {
+            m[i] = BETA1 * m[i] + (1.0 - BETA1) * gradient[i];
+            v[i] = BETA2 * v[i] + (1.0 - BETA2) * gradient[i] * gradient[i];
+            let m_hat = m[i] / (1.0 - BETA1.powi(step as i32));
+            let v_hat = v[i] / (1.0 - BETA2.powi(step as i32));
+            weights[i] = (weights[i] - LEARNING_RATE * m_hat / (v_hat.sqrt() + EPSILON)).clamp(low, high);
+        }
+
+        let next = loss(&histories, &weights, count);
+        if (current - next).abs() < TOLERANCE {
+            current = next;
+            break;
+        }
+        current = next;
+    }
+
+    let (weights, loss_after) = if current < loss_before { (weights, current) } else { (*initial, loss_before) };
+    Some(FitResult { weights: weights.to_vec(), reviews: count, loss_before, loss_after })
+}
+
+/// Review histories of the cards in a log, leaving out same-day reviews.
+/// The first review of each card has no prediction.
+fn histories(reviews: &[ReviewLogEntry]) -> Vec<History> {
+    // Ordered by card, so the loss is summed in the same order every time
+    let mut by_card: BTreeMap<Uuid, Vec<&ReviewLogEntry>> = BTreeMap::new();
+    for review in reviews {
+        by_card.entry(review.question_id).or_default().push(review);
+    }
+
+    by_card.into_values()
+        .map(|mut card| {
+            card.sort_by_key(|review| review.reviewed_at);
+            let mut history = vec![(0.0, Grade::from(card[0].rating))];
+            let mut last = card[0].reviewed_at;
+            for review in &card[1..] {
+                let days = (review.reviewed_at - last).num_seconds() as f64 / 86_400.0;
+                if days >= 1.0 {
+                    history.push((days, Grade::from(review.rating)));
+                    last = review.reviewed_at;
+                }
+            }
+            history
+        })
+        .filter(|history| history.len() > 1)
+        .collect()
+}
+
+/// Mean log loss of the model's recall predictions
+fn loss(histories: &[History], weights: &[f64; 17], count: usize) -> f64 {
+    let model = Fsrs { weights: *weights, ..Fsrs::default() };
+    let mut total = 0.0;
+
+    for history in histories {
+        let mut state: MemoryState = model.initial_state(history[0].1);
+        for &(days, grade) in &history[1..] {
+            let predicted = Fsrs::retrievability(state.stability, days).clamp(1e-6, 1.0 - 1e-6);
+            total -= if grade.is_recall() { predicted.ln() } else { (1.0 - predicted).ln() };
+            state = model.next_state(state, days, grade);
+        }
+    }
+    total / count as f64
+}
+
+fn gradient(histories: &[History], weights: &[f64; 17], count: usize) -> [f64; 17] {
+    let mut gradient = [0.0; 17];
+    for (i, &(low, high)) in WEIGHT_BOUNDS.iter().enumerate() {
+        let mut up = *weights;
+        let mut down = *weights;
+        up[i] = (weights[i] + DELTA).min(high);
+        down[i] = (weights[i] - DELTA).max(low);
+        if up[i] > down[i] {
+            gradient[i] = (loss(histories, &up, count) - loss(histories, &down, count)) / (up[i] - down[i]);
+        }
+    }
+    gradient
+}
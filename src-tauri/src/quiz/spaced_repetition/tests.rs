@@ -0,0 +1,283 @@
+use super::*;
+use super::fsrs::{DEFAULT_WEIGHTS, MAX_DIFFICULTY, MIN_DIFFICULTY};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+fn card(ease_factor: f32, interval: i32, repetitions: i32) -> FlashcardData {
+    let now = Utc::now();
+    FlashcardData {
+        question_id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        ease_factor,
+        interval,
+        repetitions,
+        due_date: now,
+        last_reviewed: now,
+        stability: None,
+        difficulty: None,
+    }
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-6
+}
+
+mod sm2 {
+    use super::*;
+
+    fn ease_after(easiness_factor: f32, performance: u8) -> f32 {
+        let params = SM2Parameters { easiness_factor, interval: 6, repetitions: 2 };
+        SpacedRepetitionScheduler::calculate_sm2(params, performance).easiness_factor
+    }
+
+    #[test]
+    fn test_easiness_factor_is_adjusted_not_scaled() {
+        assert!((ease_after(2.5, 5) - 2.6).abs() < 1e-5);
+        assert!((ease_after(2.5, 4) - 2.5).abs() < 1e-5);
+        assert!((ease_after(2.5, 3) - 2.36).abs() < 1e-5);
+        assert!((ease_after(2.5, 0) - 1.7).abs() < 1e-5);
+        assert_eq!(ease_after(1.4, 1), 1.3);
+    }
+
+    #[test]
+    fn test_reviews_adjust_easiness_like_legacy_calculation() {
+        for rating in 1..=5 {
+            let mut data = card(2.5, 6, 2);
+            SpacedRepetitionScheduler::update_flashcard_data(&mut data, FlashcardRating::from(rating));
+            assert!((data.ease_factor - ease_after(2.5, rating as u8)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_intervals() {
+        let params = SM2Parameters { easiness_factor: 2.5, interval: 6, repetitions: 2 };
+        let next = SpacedRepetitionScheduler::calculate_sm2(params.clone(), 5);
+        assert_eq!(next.repetitions, 3);
+        assert_eq!(next.interval, 15);
+
+        let lapsed = SpacedRepetitionScheduler::calculate_sm2(params, 2);
+        assert_eq!((lapsed.repetitions, lapsed.interval), (0, 1));
+    }
+}
+
+mod model {
+    use super::*;
+
+    #[test]
+    fn test_retrievability_and_intervals() {
+        assert_eq!(Fsrs::retrievability(10.0, 0.0), 1.0);
+        assert!(close(Fsrs::retrievability(10.0, 10.0), 0.9));
+        assert!(Fsrs::retrievability(10.0, 30.0) < Fsrs::retrievability(10.0, 20.0));
+
+        let fsrs = Fsrs::default();
+        assert_eq!(fsrs.next_interval(10.0), 10);
+        assert_eq!(fsrs.next_interval(0.1), 1);
+        let eager = Fsrs::new(None, 0.95);
+        assert!(eager.next_interval(10.0) < 10);
+        let relaxed = Fsrs::new(None, 0.8);
+        assert!(relaxed.next_interval(10.0) > 10);
+    }
+
+    #[test]
+    fn test_initial_state() {
+        let fsrs = Fsrs::default();
+        assert_eq!(fsrs.initial_state(Grade::Again).stability, DEFAULT_WEIGHTS[0]);
+        assert_eq!(fsrs.initial_state(Grade::Easy).stability, DEFAULT_WEIGHTS[3]);
+        assert!(close(fsrs.initial_state(Grade::Good).difficulty, DEFAULT_WEIGHTS[4]));
+        assert!(fsrs.initial_state(Grade::Again).difficulty > fsrs.initial_state(Grade::Easy).difficulty);
+    }
+
+    #[test]
+    fn test_next_state() {
+        let fsrs = Fsrs::default();
+        let state = MemoryState { stability: 10.0, difficulty: 5.0 };
+
+        let good = fsrs.next_state(state, 10.0, Grade::Good);
+        let hard = fsrs.next_state(state, 10.0, Grade::Hard);
+        let easy = fsrs.next_state(state, 10.0, Grade::Easy);
+        let again = fsrs.next_state(state, 10.0, Grade::Again);
+        assert!(hard.stability > state.stability);
+        assert!(hard.stability < good.stability && good.stability < easy.stability);
+        assert!(again.stability < state.stability);
+        assert!(again.difficulty > state.difficulty && easy.difficulty < state.difficulty);
+
+        // Recalling a card later, when it was harder to recall, makes it more stable
+        assert!(fsrs.next_state(state, 20.0, Grade::Good).stability > good.stability);
+
+        let hardest = MemoryState { stability: 1.0, difficulty: MAX_DIFFICULTY };
+        assert_eq!(fsrs.next_state(hardest, 1.0, Grade::Again).difficulty, MAX_DIFFICULTY);
+        let easiest = MemoryState { stability: 1.0, difficulty: MIN_DIFFICULTY };
+        assert!(fsrs.next_state(easiest, 1.0, Grade::Easy).difficulty >= MIN_DIFFICULTY);
+    }
+
+    #[test]
+    fn test_grades() {
+        assert_eq!(Grade::from(FlashcardRating::Blackout), Grade::Again);
+        assert_eq!(Grade::from(FlashcardRating::Familiar), Grade::Again);
+        assert_eq!(Grade::from(FlashcardRating::Difficult), Grade::Hard);
+        assert_eq!(Grade::from(FlashcardRating::Hesitation), Grade::Good);
+        assert_eq!(Grade::from(FlashcardRating::Perfect), Grade::Easy);
+    }
+
+    #[test]
+    fn test_weights() {
+        assert_eq!(Fsrs::new(Some(&[1.0; 3]), 0.9).weights, DEFAULT_WEIGHTS);
+        assert_eq!(Fsrs::new(Some(&[1.0; 17]), 0.9).weights, [1.0; 17]);
+        assert_eq!(Fsrs::new(None, 0.5).desired_retention, 0.7);
+    }
+}
+
+mod cards {
+    use super::*;
+
+    #[test]
+    fn test_sm2_cards_migrate() {
+        assert_eq!(memory_state(&card(2.5, 0, 0)), None);
+
+        let state = memory_state(&card(2.5, 20, 4)).unwrap();
+        assert_eq!(state.stability, 20.0);
+        assert!(close(state.difficulty, 5.0));
+        assert_eq!(memory_state(&card(1.3, 3, 1)).unwrap().difficulty, MAX_DIFFICULTY);
+        assert!(memory_state(&card(3.0, 3, 1)).unwrap().difficulty < 5.0);
+
+        let mut fsrs_card = card(2.5, 20, 4);
+        fsrs_card.stability = Some(42.0);
+        fsrs_card.difficulty = Some(3.0);
+        assert_eq!(memory_state(&fsrs_card), Some(MemoryState { stability: 42.0, difficulty: 3.0 }));
+    }
+
+    #[test]
+    fn test_old_card_json_deserializes() {
+        let mut value = serde_json::to_value(card(2.5, 6, 2)).unwrap();
+        let object = value.as_object_mut().unwrap();
+        object.remove("stability");
+        object.remove("difficulty");
+
+        let data: FlashcardData = serde_json::from_value(value).unwrap();
+        assert_eq!((data.stability, data.difficulty), (None, None));
+    }
+
+    #[test]
+    fn test_update_fsrs() {
+        let fsrs = Fsrs::default();
+        let mut data = card(2.5, 0, 0);
+        let now = data.last_reviewed;
+
+        update_fsrs(&fsrs, &mut data, FlashcardRating::Hesitation, now);
+        assert_eq!(data.stability, Some(DEFAULT_WEIGHTS[2]));
+        assert_eq!(data.repetitions, 1);
+        assert_eq!(data.interval, fsrs.next_interval(DEFAULT_WEIGHTS[2]));
+        assert_eq!(data.due_date, now + Duration::days(data.interval as i64));
+
+        // A migrated SM-2 card builds on its interval
+        let mut migrated = card(2.5, 30, 5);
+        migrated.last_reviewed = now - Duration::days(30);
+        update_fsrs(&fsrs, &mut migrated, FlashcardRating::Perfect, now);
+        assert!(migrated.stability.unwrap() > 30.0);
+        assert_eq!(migrated.ease_factor, 2.5);
+
+        update_fsrs(&fsrs, &mut migrated, FlashcardRating::Blackout, now + Duration::days(100));
+        assert_eq!(migrated.repetitions, 0);
+        assert!(migrated.interval < 30);
+    }
+
+    #[test]
+    fn test_retrievability() {
+        let now = Utc::now();
+        let mut data = card(2.5, 10, 2);
+        data.stability = Some(10.0);
+        data.difficulty = Some(5.0);
+        data.last_reviewed = now - Duration::days(10);
+        assert!(close(retrievability(&data, now).unwrap(), 0.9));
+        assert_eq!(retrievability(&card(2.5, 0, 0), now), None);
+    }
+
+    #[test]
+    fn test_settings_json() {
+        let settings: DeckSchedulerSettings = serde_json::from_str(r#"{"algorithm": "Fsrs"}"#).unwrap();
+        assert_eq!(settings.algorithm, SchedulingAlgorithm::Fsrs);
+        assert_eq!(settings.desired_retention, 0.9);
+        assert_eq!(settings.fsrs(), Fsrs::default());
+        assert_eq!(DeckSchedulerSettings::default().algorithm, SchedulingAlgorithm::Sm2);
+    }
+}
+
+mod fitting {
+    use super::*;
+
+    /// Reviews of a learner who forgets faster than the default weights
+    /// expect, scheduled as the default model would
+    fn forgetful_log(cards: usize, reviews: usize) -> Vec<ReviewLogEntry> {
+        let mut true_weights = DEFAULT_WEIGHTS;
+        for weight in &mut true_weights[..4] {
+            *weight *= 0.3;
+        }
+        true_weights[8] = 1.0;
+        let learner = Fsrs { weights: true_weights, ..Fsrs::default() };
+        let scheduler = Fsrs::default();
+
+        let mut rng = StdRng::seed_from_u64(17);
+        let user_id = Uuid::new_v4();
+        let deck_id = Uuid::new_v4();
+        let start = Utc::now() - Duration::days(3650);
+        let mut log = Vec::new();
+
+        for _ in 0..cards {
+            let question_id = Uuid::new_v4();
+            let mut time = start;
+            let mut state = learner.initial_state(Grade::Good);
+            let mut scheduled = scheduler.initial_state(Grade::Good);
+            log.push(ReviewLogEntry { question_id, user_id, deck_id, rating: FlashcardRating::Hesitation, reviewed_at: time });
+
+            for _ in 1..reviews {
+                let days = scheduler.next_interval(scheduled.stability);
+                time += Duration::days(days as i64);
+                let recalled = rng.random_bool(Fsrs::retrievability(state.stability, days as f64));
+                let (grade, rating) = if recalled {
+                    (Grade::Good, FlashcardRating::Hesitation)
+                } else {
+                    (Grade::Again, FlashcardRating::Blackout)
+                };
+                state = learner.next_state(state, days as f64, grade);
+                scheduled = scheduler.next_state(scheduled, days as f64, grade);
+                log.push(ReviewLogEntry { question_id, user_id, deck_id, rating, reviewed_at: time });
+            }
+        }
+        log
+    }
+
+    #[test]
+    fn test_too_few_reviews() {
+        assert_eq!(optimizer::fit(&forgetful_log(5, 3), &DEFAULT_WEIGHTS), None);
+    }
+
+    #[test]
+    fn test_same_day_reviews_are_skipped() {
+        let mut log = forgetful_log(30, 2);
+        assert_eq!(optimizer::fit(&log, &DEFAULT_WEIGHTS), None);
+
+        // A second review on the first day adds nothing to predict
+        for review in log.clone() {
+            log.push(ReviewLogEntry { reviewed_at: review.reviewed_at + Duration::hours(1), ..review });
+        }
+        assert_eq!(optimizer::fit(&log, &DEFAULT_WEIGHTS), None);
+    }
+
+    #[test]
+    fn test_fit_improves_predictions() {
+        let log = forgetful_log(60, 6);
+        let result = optimizer::fit(&log, &DEFAULT_WEIGHTS).unwrap();
+
+        assert_eq!(result.reviews, 300);
+        assert!(result.loss_after < result.loss_before);
+        assert_eq!(result.weights.len(), 17);
+        assert!(result.weights.iter().zip(fsrs::WEIGHT_BOUNDS.iter()).all(|(w, (low, high))| (*low..=*high).contains(w)));
+        // The learner forgets faster, so the fitted first-review stability is lower
+        assert!(result.weights[2] < DEFAULT_WEIGHTS[2]);
+
+        // Fitting is deterministic whatever order the log is in
+        let mut reversed = log.clone();
+        reversed.reverse();
+        assert_eq!(optimizer::fit(&reversed, &DEFAULT_WEIGHTS), Some(result));
+    }
+}
@@ -8,6 +8,7 @@ use chrono::{DateTime, Utc};
 
 use super::models::{Quiz, Question, Answer, QuizSettings, StudyMode, QuizVisibility, FlashcardData};
 use super::session::QuizSession;
+use super::spaced_repetition::{DeckSchedulerSettings, FlashcardRating, ReviewLogEntry};
 use crate::core::config::Config;
 
 // Custom error type for the hybrid store
@@ -127,6 +128,12 @@ impl HybridQuizStore {
                 .await
                 .map_err(|e| StoreError::Sqlx(e))?;
 
+            // Add the FSRS columns to flashcard data made before FSRS
+            migrate_flashcard_data(&sqlite).await?;
+
+            // Add the wrong choice penalty to settings made before multi-select
+            migrate_quiz_settings(&sqlite).await?;
+
             // Initialize quiz templates schema
             sqlx::query(include_str!("../sql/quiz_templates_schema.sql"))
                 .execute(&sqlite)
@@ -182,8 +189,8 @@ impl HybridQuizStore {
         // Store quiz settings
         sqlx::query!(
             r#"
-            INSERT INTO quiz_settings (quiz_id, shuffle_questions, time_limit, allow_retries, show_correct_answers, passing_score, study_mode)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO quiz_settings (quiz_id, shuffle_questions, time_limit, allow_retries, show_correct_answers, passing_score, study_mode, wrong_choice_penalty)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
             "#,
             quiz.id.to_string(),
             quiz.settings.shuffle_questions,
@@ -191,7 +198,8 @@ impl HybridQuizStore {
             quiz.settings.allow_retries,
             quiz.settings.show_correct_answers,
             quiz.settings.passing_score,
-            serde_json::to_string(&quiz.settings.study_mode)?
+            serde_json::to_string(&quiz.settings.study_mode)?,
+            serde_json::to_string(&quiz.settings.wrong_choice_penalty)?
         )
         .execute(&self.sqlite)
         .await?;
@@ -217,14 +225,15 @@ impl HybridQuizStore {
             for choice in &question.choices {
                 sqlx::query!(
                     r#"
-                    INSERT INTO choices (id, question_id, text, rich_text, image_url)
-                    VALUES (?, ?, ?, ?, ?)
+                    INSERT INTO choices (id, question_id, text, rich_text, image_url, feedback)
+                    VALUES (?, ?, ?, ?, ?, ?)
                     "#,
                     choice.id.to_string(),
                     question.id.to_string(),
                     choice.text,
                     choice.rich_text,
-                    choice.image_url
+                    choice.image_url,
+                    choice.feedback
                 )
                 .execute(&self.sqlite)
                 .await?;
@@ -248,7 +257,11 @@ impl HybridQuizStore {
         let table = read_txn.open_table(quiz_table)?;
 
         if let Ok(encrypted_data) = table.get(quiz_id.to_string().as_str()) {
-            return self.encryption.decrypt_quiz(encrypted_data.value());
+            let mut quiz = self.encryption.decrypt_quiz(encrypted_data.value())?;
+            for question in &mut quiz.questions {
+                question.migrate_short_answer_alternates();
+            }
+            return Ok(quiz);
         }
 
         // Fall back to SQLite if not in Redb
@@ -265,7 +278,7 @@ impl HybridQuizStore {
 
         let settings_row = sqlx::query!(
             r#"
-            SELECT shuffle_questions, time_limit, allow_retries, show_correct_answers, passing_score, study_mode
+            SELECT shuffle_questions, time_limit, allow_retries, show_correct_answers, passing_score, study_mode, wrong_choice_penalty
             FROM quiz_settings
             WHERE quiz_id = ?
             "#,
@@ -293,7 +306,7 @@ impl HybridQuizStore {
 
             let choices = sqlx::query!(
                 r#"
-                SELECT id, text, rich_text, image_url
+                SELECT id, text, rich_text, image_url, feedback
                 FROM choices
                 WHERE question_id = ?
                 "#,
@@ -315,10 +328,11 @@ impl HybridQuizStore {
                     text: c.text,
                     rich_text: c.rich_text,
                     image_url: c.image_url,
+                    feedback: c.feedback,
                 });
             }
 
-            quiz_questions.push(super::models::Question {
+            let mut question = super::models::Question {
                 id: question_id,
                 quiz_id,
                 content,
@@ -326,13 +340,16 @@ impl HybridQuizStore {
                 choices: question_choices,
                 correct_answer,
                 explanation: q.explanation,
-            });
+            };
+            question.migrate_short_answer_alternates();
+            quiz_questions.push(question);
         }
 
         let visibility: super::models::QuizVisibility = serde_json::from_str(&quiz_row.visibility)?;
         let tags: Vec<String> = serde_json::from_str(&quiz_row.tags)?;
         let study_mode: super::models::StudyMode = serde_json::from_str(&quiz_row.study_mode)?;
         let settings_study_mode: super::models::StudyMode = serde_json::from_str(&settings_row.study_mode)?;
+        let wrong_choice_penalty: super::models::WrongChoicePenalty = serde_json::from_str(&settings_row.wrong_choice_penalty)?;
 
         let quiz = super::models::Quiz {
             id: quiz_id,
@@ -350,6 +367,7 @@ impl HybridQuizStore {
                 show_correct_answers: settings_row.show_correct_answers != 0,
                 passing_score: if settings_row.passing_score > 0.0 { Some(settings_row.passing_score) } else { None },
                 study_mode: settings_study_mode,
+                wrong_choice_penalty,
             },
             author_id: quiz_row.author_id.map(|id| Uuid::parse_str(&id)
                 .map_err(|_| StoreError::Other(format!("Invalid UUID: {}", id)))).transpose()?,
@@ -465,7 +483,8 @@ impl HybridQuizStore {
             sqlx::query!(
                 r#"
                 UPDATE flashcard_data
-                SET ease_factor = ?, interval = ?, repetitions = ?, due_date = ?, last_reviewed = ?
+                SET ease_factor = ?, interval = ?, repetitions = ?, due_date = ?, last_reviewed = ?,
+                    stability = ?, difficulty = ?
                 WHERE question_id = ? AND user_id = ?
                 "#,
                 data.ease_factor,
@@ -473,6 +492,8 @@ impl HybridQuizStore {
                 data.repetitions,
                 data.due_date,
                 data.last_reviewed,
+                data.stability,
+                data.difficulty,
                 data.question_id.to_string(),
                 data.user_id.to_string()
             )
@@ -483,8 +504,8 @@ impl HybridQuizStore {
             sqlx::query!(
                 r#"
                 INSERT INTO flashcard_data
-                (question_id, user_id, ease_factor, interval, repetitions, due_date, last_reviewed)
-                VALUES (?, ?, ?, ?, ?, ?, ?)
+                (question_id, user_id, ease_factor, interval, repetitions, due_date, last_reviewed, stability, difficulty)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
                 "#,
                 data.question_id.to_string(),
                 data.user_id.to_string(),
@@ -492,7 +513,9 @@ impl HybridQuizStore {
                 data.interval,
                 data.repetitions,
                 data.due_date,
-                data.last_reviewed
+                data.last_reviewed,
+                data.stability,
+                data.difficulty
             )
             .execute(&self.sqlite)
             .await?;
@@ -531,7 +554,7 @@ impl HybridQuizStore {
         // Fall back to SQLite
         let row = sqlx::query!(
             r#"
-            SELECT question_id, user_id, ease_factor, interval, repetitions, due_date, last_reviewed
+            SELECT question_id, user_id, ease_factor, interval, repetitions, due_date, last_reviewed, stability, difficulty
             FROM flashcard_data
             WHERE question_id = ? AND user_id = ?
             "#,
@@ -551,6 +574,8 @@ impl HybridQuizStore {
                 .map_err(|_| StoreError::Other("Invalid due_date datetime".to_string()))?,
             last_reviewed: row.last_reviewed.parse::<DateTime<Utc>>()
                 .map_err(|_| StoreError::Other("Invalid last_reviewed datetime".to_string()))?,
+            stability: row.stability,
+            difficulty: row.difficulty,
         };
 
         // Cache in Redb for faster access next time
@@ -573,7 +598,7 @@ impl HybridQuizStore {
         // In a real implementation, you'd need to specify the user ID
         let row = sqlx::query!(
             r#"
-            SELECT question_id, user_id, ease_factor, interval, repetitions, due_date, last_reviewed
+            SELECT question_id, user_id, ease_factor, interval, repetitions, due_date, last_reviewed, stability, difficulty
             FROM flashcard_data
             WHERE question_id = ?
             LIMIT 1
@@ -596,6 +621,8 @@ impl HybridQuizStore {
                 .map_err(|_| StoreError::Other("Invalid due_date datetime".to_string()))?,
             last_reviewed: row.last_reviewed.parse::<DateTime<Utc>>()
                 .map_err(|_| StoreError::Other("Invalid last_reviewed datetime".to_string()))?,
+            stability: row.stability,
+            difficulty: row.difficulty,
         };
 
         Ok(flashcard_data)
@@ -605,7 +632,7 @@ impl HybridQuizStore {
     pub async fn get_due_flashcards(&self, user_id: Uuid, now: DateTime<Utc>, limit: usize) -> Result<Vec<FlashcardData>> {
         let rows = sqlx::query!(
             r#"
-            SELECT question_id, user_id, ease_factor, interval, repetitions, due_date, last_reviewed
+            SELECT question_id, user_id, ease_factor, interval, repetitions, due_date, last_reviewed, stability, difficulty
             FROM flashcard_data
             WHERE user_id = ? AND due_date <= ?
             ORDER BY due_date ASC
@@ -634,6 +661,8 @@ impl HybridQuizStore {
                     .map_err(|_| StoreError::Other("Invalid due_date datetime".to_string()))?,
                 last_reviewed: row.last_reviewed.parse::<DateTime<Utc>>()
                     .map_err(|_| StoreError::Other("Invalid last_reviewed datetime".to_string()))?,
+                stability: row.stability,
+                difficulty: row.difficulty,
             });
         }
 
@@ -644,7 +673,7 @@ impl HybridQuizStore {
     pub async fn get_all_flashcard_data(&self, user_id: Uuid) -> Result<Vec<FlashcardData>> {
         let rows = sqlx::query!(
             r#"
-            SELECT question_id, user_id, ease_factor, interval, repetitions, due_date, last_reviewed
+            SELECT question_id, user_id, ease_factor, interval, repetitions, due_date, last_reviewed, stability, difficulty
             FROM flashcard_data
             WHERE user_id = ?
             "#,
@@ -669,12 +698,88 @@ impl HybridQuizStore {
                     .map_err(|_| StoreError::Other("Invalid due_date datetime".to_string()))?,
                 last_reviewed: row.last_reviewed.parse::<DateTime<Utc>>()
                     .map_err(|_| StoreError::Other("Invalid last_reviewed datetime".to_string()))?,
+                stability: row.stability,
+                difficulty: row.difficulty,
             });
         }
 
         Ok(flashcards)
     }
 
+    /// Append a review to the flashcard review log
+    pub async fn store_flashcard_review(&self, review: &ReviewLogEntry) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO flashcard_reviews (question_id, user_id, deck_id, rating, reviewed_at) VALUES (?, ?, ?, ?, ?)"
+        )
+        .bind(review.question_id.to_string())
+        .bind(review.user_id.to_string())
+        .bind(review.deck_id.to_string())
+        .bind(review.rating as i32)
+        .bind(review.reviewed_at.to_rfc3339())
+        .execute(&self.sqlite)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get a user's flashcard reviews in the order they were made
+    pub async fn get_flashcard_reviews(&self, user_id: Uuid) -> Result<Vec<ReviewLogEntry>> {
+        let rows = sqlx::query(
+            "SELECT question_id, deck_id, rating, reviewed_at FROM flashcard_reviews WHERE user_id = ? ORDER BY id"
+        )
+        .bind(user_id.to_string())
+        .fetch_all(&self.sqlite)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let question_id: String = row.get("question_id");
+                let deck_id: String = row.get("deck_id");
+                let reviewed_at: String = row.get("reviewed_at");
+                Ok(ReviewLogEntry {
+                    question_id: Uuid::parse_str(&question_id)
+                        .map_err(|_| StoreError::Other(format!("Invalid UUID: {}", question_id)))?,
+                    user_id,
+                    deck_id: Uuid::parse_str(&deck_id)
+                        .map_err(|_| StoreError::Other(format!("Invalid UUID: {}", deck_id)))?,
+                    rating: FlashcardRating::from(row.get::<i32, _>("rating")),
+                    reviewed_at: reviewed_at.parse::<DateTime<Utc>>()
+                        .map_err(|_| StoreError::Other("Invalid reviewed_at datetime".to_string()))?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get a user's scheduling settings for a deck, if they chose any
+    pub async fn get_deck_scheduler_settings(&self, user_id: Uuid, deck_id: Uuid) -> Result<Option<DeckSchedulerSettings>> {
+        let row = sqlx::query("SELECT settings FROM flashcard_deck_settings WHERE user_id = ? AND deck_id = ?")
+            .bind(user_id.to_string())
+            .bind(deck_id.to_string())
+            .fetch_optional(&self.sqlite)
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_str(row.get::<&str, _>("settings"))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a user's scheduling settings for a deck
+    pub async fn store_deck_scheduler_settings(&self, user_id: Uuid, deck_id: Uuid, settings: &DeckSchedulerSettings) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO flashcard_deck_settings (user_id, deck_id, settings, updated_at) VALUES (?, ?, ?, ?)
+             ON CONFLICT (user_id, deck_id) DO UPDATE SET settings = excluded.settings, updated_at = excluded.updated_at"
+        )
+        .bind(user_id.to_string())
+        .bind(deck_id.to_string())
+        .bind(serde_json::to_string(settings)?)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.sqlite)
+        .await?;
+
+        Ok(())
+    }
+
     /// Set the spaced repetition scheduler
     pub fn set_spaced_repetition_scheduler(&mut self, scheduler: Arc<super::spaced_repetition::SpacedRepetitionScheduler>) {
         self.spaced_repetition_scheduler = Some(scheduler);
@@ -703,7 +808,7 @@ impl HybridQuizStore {
 
         let choices = sqlx::query!(
             r#"
-            SELECT id, text, rich_text, image_url
+            SELECT id, text, rich_text, image_url, feedback
             FROM choices
             WHERE question_id = ?
             "#,
@@ -725,10 +830,11 @@ impl HybridQuizStore {
                 text: c.text,
                 rich_text: c.rich_text,
                 image_url: c.image_url,
+                feedback: c.feedback,
             });
         }
 
-        Ok(Question {
+        let mut question = Question {
             id: question_id,
             quiz_id,
             content,
@@ -736,7 +842,9 @@ impl HybridQuizStore {
             choices: question_choices,
             correct_answer,
             explanation: q.explanation,
-        })
+        };
+        question.migrate_short_answer_alternates();
+        Ok(question)
     }
 
     // Analytics-related methods
@@ -897,4 +1005,44 @@ impl HybridQuizStore {
 
         Ok(dates)
     }
-}
\ No newline at end of file
+}
+
+/// Add the `wrong_choice_penalty` column to a `quiz_settings` table created
+/// before multi-select questions. Existing quizzes get the default penalty.
+async fn migrate_quiz_settings(sqlite: &SqlitePool) -> Result<()> {
+    let columns: Vec<String> = sqlx::query("PRAGMA table_info(quiz_settings)")
+        .fetch_all(sqlite)
+        .await?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect();
+
+    if !columns.iter().any(|name| name == "wrong_choice_penalty") {
+        sqlx::query("ALTER TABLE quiz_settings ADD COLUMN wrong_choice_penalty TEXT NOT NULL DEFAULT '\"PerChoice\"'")
+            .execute(sqlite)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Add the FSRS memory state columns to a `flashcard_data` table created
+/// before FSRS. Existing cards keep them empty until their first FSRS review.
+async fn migrate_flashcard_data(sqlite: &SqlitePool) -> Result<()> {
+    let columns: Vec<String> = sqlx::query("PRAGMA table_info(flashcard_data)")
+        .fetch_all(sqlite)
+        .await?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect();
+
+    for column in ["stability", "difficulty"] {
+        if !columns.iter().any(|name| name == column) {
+            sqlx::query(&format!("ALTER TABLE flashcard_data ADD COLUMN {} REAL", column))
+                .execute(sqlite)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,9 +1,17 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use tauri::{State, Window};
 use uuid::Uuid;
 
 use crate::app_state::AppState;
+use crate::quiz::banks::{AttemptForm, FormCandidate, FormOptions};
+use crate::quiz::models::{Answer, AnswerType, Question};
+use crate::quiz::xapi::{self, XApiAccount, XApiActor, XApiStatement};
+use crate::sync::operations::OperationType;
+
+/// Home page of the accounts learners are identified by in xAPI statements
+const XAPI_ACCOUNT_HOME_PAGE: &str = "http://example.com";
 
 /// Represents a quiz attempt in progress
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +25,9 @@ pub struct QuizAttempt {
     pub score: Option<f64>,
     pub answers: Vec<QuestionAnswer>,
     pub time_spent: i64, // in seconds
+    /// Questions drawn for this attempt, in the order they were shown
+    #[serde(default)]
+    pub form: Option<AttemptForm>,
 }
 
 /// Represents the status of a quiz attempt
@@ -103,6 +114,9 @@ pub async fn start_quiz(
     let attempt_id = Uuid::new_v4().to_string();
     let now = chrono::Utc::now();
     
+    // Draw this attempt's questions and fix their order
+    let (form, questions) = build_form(&state, &quiz_id, &attempt_id, questions, quiz.shuffle_questions).await?;
+    
     let attempt = QuizAttempt {
         id: attempt_id,
         quiz_id: quiz_id.clone(),
@@ -113,6 +127,7 @@ pub async fn start_quiz(
         score: None,
         answers: vec![],
         time_spent: 0,
+        form: Some(form),
     };
     
     // Save attempt to database
@@ -133,6 +148,14 @@ pub async fn start_quiz(
         Err(e) => return Err(format!("Failed to save attempt: {}", e)),
     };
     
+    if let (Some(banks), Some(form)) = (&state.question_banks, &attempt.form) {
+        if let Err(e) = banks.save_form(&attempt.id, form).await {
+            return Err(format!("Failed to save attempt form: {}", e));
+        }
+    }
+    
+    sync_attempt(&state, &attempt, OperationType::Create).await;
+    
     // Create quiz taking state
     let quiz_state = QuizTakingState {
         quiz_id: quiz_id.clone(),
@@ -148,6 +171,10 @@ pub async fn start_quiz(
         time_remaining: quiz.time_limit as i64,
     };
     
+    track_xapi(&state, &quiz_state.attempt.user_id, xapi::create_quiz_started_statement(
+        "", "", "", &quiz_state.quiz_id, &quiz_state.quiz_title,
+    )).await;
+    
     // Start timer
     let window_clone = window.clone();
     let time_limit = quiz.time_limit as i64;
@@ -198,7 +225,17 @@ pub async fn submit_answer(
         return Err("No more questions".to_string());
     }
     
-    let current_question = &quiz_state.questions[quiz_state.current_question_index];
+    let question_id = quiz_state.questions[quiz_state.current_question_index].id.clone();
+    
+    // Grade against the questions stored for the attempt rather than the
+    // copy the client sent back
+    let quiz_id = get_attempt_quiz_id(&state, &quiz_state.attempt.id).await?;
+    let form = get_stored_form(&state, &quiz_state.attempt.id).await?;
+    let questions = get_attempt_questions(&state, &quiz_id, form.as_ref()).await?;
+    let current_question = match questions.into_iter().find(|question| question.id == question_id) {
+        Some(question) => question,
+        None => return Err("Question is not part of this attempt".to_string()),
+    };
     
     // Check if answer is correct
     let is_correct = if let Some(answer_id) = &answer_id {
@@ -240,6 +277,15 @@ pub async fn submit_answer(
         Err(e) => return Err(format!("Failed to save answer: {}", e)),
     };
     
+    let response = current_question.options.iter()
+        .find(|option| Some(&option.id) == answer.answer_id.as_ref())
+        .map(|option| option.option_text.clone())
+        .unwrap_or_default();
+    track_xapi(&state, &quiz_state.attempt.user_id, xapi::create_question_answered_statement(
+        "", "", "", &quiz_state.quiz_id, &quiz_state.quiz_title,
+        &current_question.id, &current_question.question_text, &response, is_correct,
+    )).await;
+    
     // Move to next question
     quiz_state.current_question_index += 1;
     
@@ -270,10 +316,36 @@ pub async fn complete_quiz(
 ) -> Result<QuizAttempt, String> {
     let now = chrono::Utc::now();
     
+    // Score the answers recorded for the questions stored with the attempt,
+    // not the client's copy of either. The last answer to a question counts.
+    let quiz_id = get_attempt_quiz_id(&state, &quiz_state.attempt.id).await?;
+    let form = get_stored_form(&state, &quiz_state.attempt.id).await?;
+    let question_ids: Vec<String> = match &form {
+        Some(form) => form.items.iter().map(|item| item.question_id.clone()).collect(),
+        None => get_quiz_questions_with_options(&state, &quiz_id).await?
+            .into_iter()
+            .map(|question| question.id)
+            .collect(),
+    };
+    
+    let answers = match sqlx::query!(
+        "SELECT question_id, is_correct FROM quiz_attempt_answers WHERE attempt_id = ? ORDER BY created_at ASC",
+        quiz_state.attempt.id
+    )
+    .fetch_all(&state.db_pool)
+    .await {
+        Ok(rows) => rows,
+        Err(e) => return Err(format!("Failed to fetch answers: {}", e)),
+    };
+    let mut graded = HashMap::new();
+    for answer in answers {
+        graded.insert(answer.question_id, answer.is_correct.unwrap_or(false));
+    }
+    
     // Calculate score
-    let total_questions = quiz_state.questions.len() as f64;
-    let correct_answers = quiz_state.attempt.answers.iter()
-        .filter(|answer| answer.is_correct.unwrap_or(false))
+    let total_questions = question_ids.len() as f64;
+    let correct_answers = question_ids.iter()
+        .filter(|question_id| graded.get(*question_id).copied().unwrap_or(false))
         .count() as f64;
     
     let score = if total_questions > 0.0 {
@@ -283,6 +355,7 @@ pub async fn complete_quiz(
     };
     
     // Update attempt
+    quiz_state.attempt.form = form;
     quiz_state.attempt.end_time = Some(now);
     quiz_state.attempt.status = AttemptStatus::Completed;
     quiz_state.attempt.score = Some(score);
@@ -304,9 +377,278 @@ pub async fn complete_quiz(
         Err(e) => return Err(format!("Failed to update attempt: {}", e)),
     };
     
+    sync_attempt(&state, &quiz_state.attempt, OperationType::Update).await;
+    
+    track_xapi(&state, &quiz_state.attempt.user_id, xapi::create_quiz_completed_statement(
+        "", "", "", &quiz_state.quiz_id, &quiz_state.quiz_title,
+        score as f32, 100.0, score >= quiz_state.passing_score,
+    )).await;
+    
+    // Send the grade back to the LMS if the quiz was launched via LTI. A
+    // failure here must not lose the completed attempt, so it is only logged.
+    if let Err(e) = send_lti_outcome(&state, &quiz_state, score).await {
+        tracing::warn!("Failed to send LTI outcome for attempt {}: {}", quiz_state.attempt.id, e);
+    }
+    
     Ok(quiz_state.attempt)
 }
 
+/// Queue an attempt, with the form it was given, for sync to the learner's
+/// other devices
+///
+/// Like tracking, syncing must not get in the way of taking the quiz, so
+/// failures are only logged.
+async fn sync_attempt(state: &State<'_, AppState>, attempt: &QuizAttempt, operation_type: OperationType) {
+    let engine = match state.get_sync_engine() {
+        Ok(engine) => engine,
+        Err(e) => {
+            tracing::warn!("Not syncing attempt {}: {}", attempt.id, e);
+            return;
+        }
+    };
+    let user_id = match attempt.user_id.parse::<i64>() {
+        Ok(user_id) => user_id,
+        Err(_) => {
+            tracing::warn!("Not syncing attempt {}: user {} has no numeric ID", attempt.id, attempt.user_id);
+            return;
+        }
+    };
+    let payload = match serde_json::to_value(attempt) {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::warn!("Failed to serialize attempt {} for sync: {}", attempt.id, e);
+            return;
+        }
+    };
+    
+    if let Err(e) = engine.queue_operation(user_id, operation_type, "quiz_attempt", Some(&attempt.id), payload).await {
+        tracing::warn!("Failed to queue attempt {} for sync: {}", attempt.id, e);
+    }
+}
+
+/// Queue an xAPI statement about a learner's attempt
+///
+/// Learners are identified by their local account. Tracking must not get in
+/// the way of taking the quiz, so failures are only logged.
+async fn track_xapi(state: &State<'_, AppState>, user_id: &str, mut statement: XApiStatement) {
+    statement.actor = XApiActor {
+        name: None,
+        mbox: None,
+        mbox_sha1sum: None,
+        openid: None,
+        account: Some(XApiAccount {
+            home_page: XAPI_ACCOUNT_HOME_PAGE.to_string(),
+            name: user_id.to_string(),
+        }),
+        object_type: Some("Agent".to_string()),
+    };
+    
+    if let Err(e) = state.track_xapi(&statement).await {
+        tracing::warn!("Failed to track xAPI statement for user {}: {}", user_id, e);
+    }
+}
+
+/// Publish a completed attempt's score to the platform that launched the quiz
+async fn send_lti_outcome(
+    state: &State<'_, AppState>,
+    quiz_state: &QuizTakingState,
+    score: f64,
+) -> Result<(), String> {
+    let (Some(lti_service), Some(bindings)) = (state.lti_service.clone(), state.lti_outcome_bindings.clone()) else {
+        return Ok(());
+    };
+    
+    let binding = match bindings.get(&quiz_state.quiz_id, &quiz_state.attempt.user_id).await {
+        Ok(Some(binding)) => binding,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    };
+    
+    let lti_service = lti_service.lock().await;
+    let created_lineitem = lti_service.publish_quiz_score(&binding, &quiz_state.quiz_title, score, 100.0)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    if let Some(lineitem_url) = created_lineitem {
+        bindings.set_lineitem_url(&binding.quiz_id, &binding.user_id, &lineitem_url)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    
+    Ok(())
+}
+
+/// Generate the form for a new attempt, returning it with the questions on
+/// it in form order.
+///
+/// The quiz's shuffle setting covers both questions and choices.
+async fn build_form(
+    state: &State<'_, AppState>,
+    quiz_id: &str,
+    attempt_id: &str,
+    questions: Vec<QuizQuestion>,
+    shuffle: bool,
+) -> Result<(AttemptForm, Vec<QuizQuestion>), String> {
+    let fixed: Vec<FormCandidate> = questions.iter().map(form_candidate).collect();
+    
+    let mut groups = Vec::new();
+    let mut pool = questions;
+    if let Some(banks) = &state.question_banks {
+        let quiz_groups = match banks.groups_for_quiz(quiz_id).await {
+            Ok(groups) => groups,
+            Err(e) => return Err(format!("Failed to fetch question groups: {}", e)),
+        };
+        
+        for group in quiz_groups {
+            let bank = match banks.get_bank(group.bank_id).await {
+                Ok(bank) => bank,
+                Err(e) => return Err(format!("Failed to fetch question bank: {}", e)),
+            };
+            let candidates = bank.questions.iter().map(FormCandidate::from_question).collect();
+            pool.extend(bank.questions.iter().map(|question| bank_question(question, quiz_id)));
+            groups.push((group, candidates));
+        }
+    }
+    
+    let options = FormOptions { shuffle_questions: shuffle, shuffle_choices: shuffle };
+    let form = AttemptForm::generate(AttemptForm::seed_for_attempt(attempt_id), &fixed, &groups, options);
+    let questions = apply_form(&form, pool);
+    
+    Ok((form, questions))
+}
+
+/// The quiz an attempt was started for
+async fn get_attempt_quiz_id(state: &State<'_, AppState>, attempt_id: &str) -> Result<String, String> {
+    match sqlx::query!(
+        "SELECT quiz_id FROM quiz_attempts WHERE id = ?",
+        attempt_id
+    )
+    .fetch_one(&state.db_pool)
+    .await {
+        Ok(row) => Ok(row.quiz_id.to_string()),
+        Err(e) => Err(format!("Failed to fetch attempt: {}", e)),
+    }
+}
+
+/// The form stored for an attempt when it started, if any
+async fn get_stored_form(state: &State<'_, AppState>, attempt_id: &str) -> Result<Option<AttemptForm>, String> {
+    match &state.question_banks {
+        Some(banks) => match banks.get_form(attempt_id).await {
+            Ok(form) => Ok(form),
+            Err(e) => Err(format!("Failed to fetch attempt form: {}", e)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// The questions an attempt was given, falling back to the quiz's current
+/// questions for attempts made before forms were stored
+async fn get_attempt_questions(
+    state: &State<'_, AppState>,
+    quiz_id: &str,
+    form: Option<&AttemptForm>,
+) -> Result<Vec<QuizQuestion>, String> {
+    match form {
+        Some(form) => get_form_questions(state, quiz_id, form).await,
+        None => get_quiz_questions_with_options(state, quiz_id).await,
+    }
+}
+
+/// The questions of a stored form, for reviewing its attempt
+async fn get_form_questions(
+    state: &State<'_, AppState>,
+    quiz_id: &str,
+    form: &AttemptForm,
+) -> Result<Vec<QuizQuestion>, String> {
+    let mut pool = get_quiz_questions_with_options(state, quiz_id).await?;
+    
+    let mut bank_ids: Vec<Uuid> = form.items.iter().filter_map(|item| item.bank_id).collect();
+    bank_ids.sort();
+    bank_ids.dedup();
+    if let Some(banks) = &state.question_banks {
+        for bank_id in bank_ids {
+            match banks.get_bank(bank_id).await {
+                Ok(bank) => pool.extend(bank.questions.iter().map(|question| bank_question(question, quiz_id))),
+                Err(e) => tracing::warn!("Failed to fetch question bank {} for review: {}", bank_id, e),
+            }
+        }
+    }
+    
+    Ok(apply_form(form, pool))
+}
+
+/// Questions in form order, with their options in the form's choice order.
+/// Questions deleted since the form was made are left out.
+fn apply_form(form: &AttemptForm, pool: Vec<QuizQuestion>) -> Vec<QuizQuestion> {
+    form.items.iter()
+        .filter_map(|item| {
+            let mut question = pool.iter().find(|question| question.id == item.question_id)?.clone();
+            item.order_choices(&mut question.options, |option| option.id.clone());
+            for (position, option) in question.options.iter_mut().enumerate() {
+                option.position = position as i32;
+            }
+            Some(question)
+        })
+        .enumerate()
+        .map(|(position, mut question)| {
+            question.position = position as i32 + 1;
+            question
+        })
+        .collect()
+}
+
+fn form_candidate(question: &QuizQuestion) -> FormCandidate {
+    let choice_ids = if question.question_type == "true_false" {
+        Vec::new()
+    } else {
+        question.options.iter().map(|option| option.id.clone()).collect()
+    };
+    FormCandidate { question_id: question.id.clone(), choice_ids }
+}
+
+/// A bank question in the shape quiz taking works with
+fn bank_question(question: &Question, quiz_id: &str) -> QuizQuestion {
+    let correct = match &question.correct_answer {
+        Answer::Choice(id) => vec![*id],
+        Answer::Choices(ids) => ids.clone(),
+        _ => Vec::new(),
+    };
+    let question_type = match question.answer_type {
+        AnswerType::MultipleChoice => "multiple_choice",
+        AnswerType::TrueFalse => "true_false",
+        AnswerType::ShortAnswer => "short_answer",
+        AnswerType::Essay => "essay",
+        AnswerType::Matching => "matching",
+        AnswerType::Ordering => "ordering",
+        AnswerType::DragDrop => "drag_drop",
+        AnswerType::Hotspot => "hotspot",
+        AnswerType::Drawing => "drawing",
+        AnswerType::CodeExecution => "code_execution",
+        AnswerType::MathEquation => "math_equation",
+        AnswerType::Timeline => "timeline",
+        AnswerType::DiagramLabeling => "diagram_labeling",
+    };
+    
+    QuizQuestion {
+        id: question.id.to_string(),
+        quiz_id: quiz_id.to_string(),
+        question_text: question.content.text.clone(),
+        question_type: question_type.to_string(),
+        points: 1,
+        position: 0,
+        options: question.choices.iter()
+            .enumerate()
+            .map(|(position, choice)| AnswerOption {
+                id: choice.id.to_string(),
+                question_id: question.id.to_string(),
+                option_text: choice.text.clone(),
+                is_correct: correct.contains(&choice.id),
+                position: position as i32,
+            })
+            .collect(),
+    }
+}
+
 /// Get quiz questions with answer options
 async fn get_quiz_questions_with_options(
     state: &State<'_, AppState>,
@@ -400,31 +742,16 @@ pub async fn get_quiz_results(
         Err(e) => return Err(format!("Failed to fetch answers: {}", e)),
     };
     
-    // Get questions
-    let questions = match sqlx::query!(
-        "SELECT * FROM questions WHERE quiz_id = ? ORDER BY position ASC",
-        attempt.quiz_id
-    )
-    .fetch_all(&state.db_pool)
-    .await {
-        Ok(rows) => rows,
-        Err(e) => return Err(format!("Failed to fetch questions: {}", e)),
-    };
+    // Get the questions the attempt was given
+    let form = get_stored_form(&state, &attempt_id).await?;
+    let quiz_id = attempt.quiz_id.to_string();
+    let questions = get_attempt_questions(&state, &quiz_id, form.as_ref()).await?;
     
     // Build question details
     let mut question_details = Vec::new();
     
-    for question in questions {
-        // Get options for this question
-        let options = match sqlx::query!(
-            "SELECT * FROM answers WHERE question_id = ? ORDER BY position ASC",
-            question.id
-        )
-        .fetch_all(&state.db_pool)
-        .await {
-            Ok(rows) => rows,
-            Err(e) => return Err(format!("Failed to fetch answer options: {}", e)),
-        };
+    for question in &questions {
+        let options = &question.options;
         
         // Find user's answer for this question
         let user_answer = answers.iter().find(|a| a.question_id == question.id);
@@ -446,6 +773,10 @@ pub async fn get_quiz_results(
                     "isCorrect": a.is_correct
                 })
             }),
+            "options": options.iter().map(|o| serde_json::json!({
+                "id": o.id,
+                "text": o.option_text
+            })).collect::<Vec<_>>(),
             "correctAnswer": correct_option.map(|o| {
                 serde_json::json!({
                     "id": o.id,
@@ -1,6 +1,7 @@
 use super::QuizEngine;
 use super::templates::{QuizTemplate, TemplateCategory, TemplateRating};
 use super::models::{StudyMode, QuizVisibility, AnswerType};
+use super::formula::FormulaTemplate;
 use uuid::Uuid;
 use tauri::State;
 use serde_json;
@@ -146,6 +147,64 @@ pub async fn add_question_template(
     serde_json::to_value(question_template).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn add_formula_question_template(
+    template_id: String,
+    text: String,
+    description: Option<String>,
+    formula: FormulaTemplate,
+    engine: State<'_, QuizEngine>,
+) -> Result<serde_json::Value, String> {
+    let template_uuid = Uuid::parse_str(&template_id).map_err(|e| e.to_string())?;
+    
+    let question_template = engine.add_formula_question_template(template_uuid, text, description, formula)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    serde_json::to_value(question_template).map_err(|e| e.to_string())
+}
+
+/// Show a formula question to an attempt, without its answer. The same
+/// attempt always gets the same values.
+#[tauri::command]
+pub async fn generate_formula_question(
+    template_id: String,
+    question_template_id: String,
+    attempt_id: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<serde_json::Value, String> {
+    let template_uuid = Uuid::parse_str(&template_id).map_err(|e| e.to_string())?;
+    let question_template_uuid = Uuid::parse_str(&question_template_id).map_err(|e| e.to_string())?;
+    
+    let prompt = engine.generate_formula_question(template_uuid, question_template_uuid, &attempt_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    serde_json::to_value(prompt).map_err(|e| e.to_string())
+}
+
+/// Grade an attempt's answer to a formula question against the values it
+/// was shown
+#[tauri::command]
+pub async fn grade_formula_answer(
+    template_id: String,
+    question_template_id: String,
+    quiz_id: String,
+    attempt_id: String,
+    answer: String,
+    engine: State<'_, QuizEngine>,
+) -> Result<serde_json::Value, String> {
+    let template_uuid = Uuid::parse_str(&template_id).map_err(|e| e.to_string())?;
+    let question_template_uuid = Uuid::parse_str(&question_template_id).map_err(|e| e.to_string())?;
+    let quiz_uuid = Uuid::parse_str(&quiz_id).map_err(|e| e.to_string())?;
+    
+    let score = engine.grade_formula_answer(template_uuid, question_template_uuid, quiz_uuid, &attempt_id, answer)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    serde_json::to_value(score).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn update_template(
     template_id: String,
@@ -1,6 +1,8 @@
 use super::QuizEngine;
 use super::templates::{QuizTemplate, QuestionTemplate, TemplateCategory, TemplateRating};
 use super::models::{Quiz, StudyMode, QuizVisibility, AnswerType};
+use super::formula::{FormulaPrompt, FormulaTemplate};
+use super::scoring::QuestionScore;
 use uuid::Uuid;
 use std::error::Error;
 
@@ -88,6 +90,51 @@ impl QuizEngine {
         }
     }
     
+    /// Add a formula question to a quiz template
+    pub async fn add_formula_question_template(
+        &self,
+        template_id: Uuid,
+        text: String,
+        description: Option<String>,
+        formula: FormulaTemplate,
+    ) -> Result<QuestionTemplate, Box<dyn Error + Send + Sync>> {
+        if let Some(template_service) = &self.template_service {
+            template_service.add_formula_question_template(template_id, text, description, formula).await
+        } else {
+            Err("Template service is not available".into())
+        }
+    }
+    
+    /// Show a formula question to an attempt
+    pub async fn generate_formula_question(
+        &self,
+        template_id: Uuid,
+        question_template_id: Uuid,
+        attempt_id: &str,
+    ) -> Result<FormulaPrompt, Box<dyn Error + Send + Sync>> {
+        if let Some(template_service) = &self.template_service {
+            template_service.generate_formula_question(template_id, question_template_id, attempt_id).await
+        } else {
+            Err("Template service is not available".into())
+        }
+    }
+    
+    /// Grade an attempt's answer to a formula question
+    pub async fn grade_formula_answer(
+        &self,
+        template_id: Uuid,
+        question_template_id: Uuid,
+        quiz_id: Uuid,
+        attempt_id: &str,
+        answer: String,
+    ) -> Result<QuestionScore, Box<dyn Error + Send + Sync>> {
+        if let Some(template_service) = &self.template_service {
+            template_service.grade_formula_answer(template_id, question_template_id, quiz_id, attempt_id, answer).await
+        } else {
+            Err("Template service is not available".into())
+        }
+    }
+    
     /// Update a quiz template
     pub async fn update_template(
         &self,
@@ -1,5 +1,7 @@
-use super::models::{Quiz, Question, Answer, AnswerType, StudyMode, QuizVisibility};
+use super::models::{Quiz, Question, Answer, AnswerType, StudyMode, QuizVisibility, QuestionContent, ShortAnswerContent, WrongChoicePenalty};
 use super::storage::HybridQuizStore;
+use super::formula::{self, FormulaError, FormulaInstance, FormulaPrompt, FormulaTemplate};
+use super::scoring::{self, QuestionScore};
 use uuid::Uuid;
 use std::sync::Arc;
 use std::error::Error;
@@ -49,10 +51,60 @@ pub struct QuestionTemplate {
     pub answer_type: AnswerType,
     pub placeholder_text: Option<String>,
     pub example_answers: Vec<String>,
+    /// Variables and answer formula of a formula question
+    #[serde(default)]
+    pub formula: Option<FormulaTemplate>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl QuestionTemplate {
+    /// Draw the values of a formula question for an attempt
+    pub fn formula_instance(&self, attempt_id: &str) -> Result<FormulaInstance, FormulaError> {
+        let template = self.formula.as_ref().ok_or(FormulaError::NoFormula)?;
+        template.generate(formula::seed_for(attempt_id, self.id))
+    }
+
+    /// What a learner is shown of a formula question with an attempt's values
+    pub fn formula_prompt(&self, instance: &FormulaInstance) -> Result<FormulaPrompt, FormulaError> {
+        let template = self.formula.as_ref().ok_or(FormulaError::NoFormula)?;
+        Ok(FormulaPrompt {
+            question_id: self.id,
+            text: template.render(&self.text, instance),
+            answer_decimals: template.answer_decimals,
+        })
+    }
+
+    /// The question of a formula question with an attempt's values, answer
+    /// included, for grading. The question keeps the template's ID, so
+    /// answers from any attempt can be matched to it.
+    pub fn formula_question(&self, quiz_id: Uuid, instance: &FormulaInstance) -> Result<Question, FormulaError> {
+        let template = self.formula.as_ref().ok_or(FormulaError::NoFormula)?;
+        let content = QuestionContent {
+            text: template.render(&self.text, instance),
+            rich_text: None,
+            image_url: None,
+            audio_url: None,
+            drag_drop_content: None,
+            hotspot_content: None,
+            drawing_content: None,
+            code_execution_content: None,
+            math_equation_content: None,
+            timeline_content: None,
+            diagram_labeling_content: None,
+            short_answer_content: Some(ShortAnswerContent {
+                accepted_answers: vec![template.accepted_answer(instance)],
+            }),
+        };
+        let mut question = Question::new(quiz_id, content, AnswerType::ShortAnswer);
+        question.id = self.id;
+        question.set_correct_answer(Answer::Text(template.format_answer(instance)));
+        question.explanation = self.description.clone();
+
+        Ok(question)
+    }
+}
+
 /// Template rating model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TemplateRating {
@@ -157,6 +209,7 @@ impl TemplateService {
                 answer_type: question.answer_type.clone(),
                 placeholder_text: None,
                 example_answers: question.answers.iter().map(|a| a.text.clone()).collect(),
+                formula: None,
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
             };
@@ -192,6 +245,7 @@ impl TemplateService {
             answer_type,
             placeholder_text,
             example_answers,
+            formula: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
@@ -206,6 +260,96 @@ impl TemplateService {
         Ok(question_template)
     }
 
+    /// Add a formula question to a quiz template. Placeholders such as
+    /// `[d]` in the text are filled with each attempt's values.
+    pub async fn add_formula_question_template(
+        &self,
+        template_id: Uuid,
+        text: String,
+        description: Option<String>,
+        formula: FormulaTemplate,
+    ) -> Result<QuestionTemplate, Box<dyn Error + Send + Sync>> {
+        // Reject formulas that could not be generated before storing them
+        formula.generate(0)?;
+
+        let mut template = self.get_template(template_id).await?;
+
+        let question_template = QuestionTemplate {
+            id: Uuid::new_v4(),
+            template_id,
+            text,
+            description,
+            answer_type: AnswerType::ShortAnswer,
+            placeholder_text: None,
+            example_answers: Vec::new(),
+            formula: Some(formula),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        template.question_templates.push(question_template.clone());
+        template.updated_at = Utc::now();
+
+        self.store_template(&template).await?;
+
+        Ok(question_template)
+    }
+
+    /// Show a formula question of a template to an attempt. The values are
+    /// drawn the first time and stored with the attempt.
+    pub async fn generate_formula_question(
+        &self,
+        template_id: Uuid,
+        question_template_id: Uuid,
+        attempt_id: &str,
+    ) -> Result<FormulaPrompt, Box<dyn Error + Send + Sync>> {
+        let question_template = self.get_question_template(template_id, question_template_id).await?;
+
+        let instance = match self.get_formula_instance(attempt_id, question_template_id).await? {
+            Some(instance) => instance,
+            None => {
+                let instance = question_template.formula_instance(attempt_id)?;
+                // Another request may have drawn values first; theirs are kept
+                self.store_formula_instance(attempt_id, question_template_id, &instance).await?;
+                self.get_formula_instance(attempt_id, question_template_id).await?
+                    .ok_or("Formula values were not stored")?
+            }
+        };
+
+        Ok(question_template.formula_prompt(&instance)?)
+    }
+
+    /// Grade an attempt's answer to a formula question against the values
+    /// it was shown
+    pub async fn grade_formula_answer(
+        &self,
+        template_id: Uuid,
+        question_template_id: Uuid,
+        quiz_id: Uuid,
+        attempt_id: &str,
+        answer: String,
+    ) -> Result<QuestionScore, Box<dyn Error + Send + Sync>> {
+        let question_template = self.get_question_template(template_id, question_template_id).await?;
+
+        let instance = self.get_formula_instance(attempt_id, question_template_id).await?
+            .ok_or("The question has not been shown to this attempt")?;
+        let question = question_template.formula_question(quiz_id, &instance)?;
+
+        Ok(scoring::score_answer(&question, &Answer::Text(answer), WrongChoicePenalty::default()))
+    }
+
+    async fn get_question_template(
+        &self,
+        template_id: Uuid,
+        question_template_id: Uuid,
+    ) -> Result<QuestionTemplate, Box<dyn Error + Send + Sync>> {
+        let template = self.get_template(template_id).await?;
+
+        template.question_templates.into_iter()
+            .find(|question_template| question_template.id == question_template_id)
+            .ok_or_else(|| "Question template not found".into())
+    }
+
     /// Update a quiz template
     pub async fn update_template(
         &self,
@@ -274,6 +418,17 @@ impl TemplateService {
         .execute(&self.db_pool)
         .await?;
 
+        // Delete the formulas of its question templates
+        sqlx::query!(
+            r#"
+            DELETE FROM quiz_question_formulas
+            WHERE question_template_id IN (SELECT id FROM quiz_question_templates WHERE template_id = ?)
+            "#,
+            template_id.to_string()
+        )
+        .execute(&self.db_pool)
+        .await?;
+
         // Delete the question templates
         sqlx::query!(
             r#"
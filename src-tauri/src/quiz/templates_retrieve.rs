@@ -3,6 +3,7 @@ use super::models::{Quiz, Question, Answer, AnswerType};
 use uuid::Uuid;
 use std::error::Error;
 use chrono::{DateTime, Utc};
+use sqlx::Row;
 
 impl TemplateService {
     /// Get a quiz template by ID
@@ -108,6 +109,16 @@ impl TemplateService {
             // Parse the answer type
             let answer_type = row.answer_type.parse()?;
             
+            // Get the formula of a formula question
+            let formula = match sqlx::query("SELECT formula FROM quiz_question_formulas WHERE question_template_id = ?")
+                .bind(&row.id)
+                .fetch_optional(&self.db_pool)
+                .await?
+            {
+                Some(formula_row) => Some(serde_json::from_str(formula_row.get::<&str, _>("formula"))?),
+                None => None,
+            };
+            
             // Create the question template
             let question_template = QuestionTemplate {
                 id: Uuid::parse_str(&row.id)?,
@@ -117,6 +128,7 @@ impl TemplateService {
                 answer_type,
                 placeholder_text: row.placeholder_text,
                 example_answers,
+                formula,
                 created_at: row.created_at.parse::<DateTime<Utc>>()?,
                 updated_at: row.updated_at.parse::<DateTime<Utc>>()?,
             };
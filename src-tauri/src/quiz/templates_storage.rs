@@ -1,4 +1,6 @@
 use super::templates::{TemplateService, QuizTemplate, TemplateCategory};
+use super::formula::FormulaInstance;
+use sqlx::Row;
 use uuid::Uuid;
 use std::error::Error;
 use chrono::Utc;
@@ -153,8 +155,60 @@ impl TemplateService {
                 .execute(&self.db_pool)
                 .await?;
             }
+            
+            // Store the formula of a formula question
+            if let Some(formula) = &question_template.formula {
+                sqlx::query(
+                    "INSERT INTO quiz_question_formulas (question_template_id, formula) VALUES (?, ?)
+                     ON CONFLICT (question_template_id) DO UPDATE SET formula = excluded.formula"
+                )
+                .bind(question_template.id.to_string())
+                .bind(serde_json::to_string(formula)?)
+                .execute(&self.db_pool)
+                .await?;
+            }
         }
         
         Ok(())
     }
+    
+    /// Store the values of a formula question drawn for an attempt, unless
+    /// the attempt already has values for the question
+    pub async fn store_formula_instance(
+        &self,
+        attempt_id: &str,
+        question_template_id: Uuid,
+        instance: &FormulaInstance,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO quiz_formula_instances (attempt_id, question_template_id, instance, created_at)
+             VALUES (?, ?, ?, ?)"
+        )
+        .bind(attempt_id)
+        .bind(question_template_id.to_string())
+        .bind(serde_json::to_string(instance)?)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.db_pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// The values of a formula question drawn for an attempt
+    pub async fn get_formula_instance(
+        &self,
+        attempt_id: &str,
+        question_template_id: Uuid,
+    ) -> Result<Option<FormulaInstance>, Box<dyn Error + Send + Sync>> {
+        let row = sqlx::query("SELECT instance FROM quiz_formula_instances WHERE attempt_id = ? AND question_template_id = ?")
+            .bind(attempt_id)
+            .bind(question_template_id.to_string())
+            .fetch_optional(&self.db_pool)
+            .await?;
+        
+        match row {
+            Some(row) => Ok(Some(serde_json::from_str(row.get::<&str, _>("instance"))?)),
+            None => Ok(None),
+        }
+    }
 }
@@ -43,6 +43,7 @@ mod tests {
             text: "Paris".to_string(),
             rich_text: None,
             image_url: Some("https://example.com/paris.jpg".to_string()),
+            feedback: None,
         };
         
         // In a real implementation, Choice would have an alt_text field
@@ -89,18 +89,21 @@ mod tests {
                 text: "Paris".to_string(),
                 rich_text: None,
                 image_url: None,
+                feedback: None,
             },
             crate::quiz::models::Choice {
                 id: choice2_id,
                 text: "London".to_string(),
                 rich_text: None,
                 image_url: None,
+                feedback: None,
             },
             crate::quiz::models::Choice {
                 id: choice3_id,
                 text: "Berlin".to_string(),
                 rich_text: None,
                 image_url: None,
+                feedback: None,
             },
         ];
         
@@ -51,18 +51,21 @@ mod tests {
                 text: "Paris".to_string(),
                 rich_text: None,
                 image_url: None,
+                feedback: None,
             },
             crate::quiz::models::Choice {
                 id: Uuid::new_v4(),
                 text: "London".to_string(),
                 rich_text: None,
                 image_url: None,
+                feedback: None,
             },
             crate::quiz::models::Choice {
                 id: Uuid::new_v4(),
                 text: "Berlin".to_string(),
                 rich_text: None,
                 image_url: None,
+                feedback: None,
             },
         ];
         
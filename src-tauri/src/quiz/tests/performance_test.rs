@@ -48,18 +48,21 @@ mod tests {
                         text: format!("Choice 1 for question {}", j),
                         rich_text: None,
                         image_url: None,
+                        feedback: None,
                     },
                     crate::quiz::models::Choice {
                         id: choice2_id,
                         text: format!("Choice 2 for question {}", j),
                         rich_text: None,
                         image_url: None,
+                        feedback: None,
                     },
                     crate::quiz::models::Choice {
                         id: choice3_id,
                         text: format!("Choice 3 for question {}", j),
                         rich_text: None,
                         image_url: None,
+                        feedback: None,
                     },
                 ];
                 
@@ -218,18 +221,21 @@ mod tests {
                     text: format!("Choice 1 for question {}", i),
                     rich_text: None,
                     image_url: None,
+                    feedback: None,
                 },
                 crate::quiz::models::Choice {
                     id: choice2_id,
                     text: format!("Choice 2 for question {}", i),
                     rich_text: None,
                     image_url: None,
+                    feedback: None,
                 },
                 crate::quiz::models::Choice {
                     id: choice3_id,
                     text: format!("Choice 3 for question {}", i),
                     rich_text: None,
                     image_url: None,
+                    feedback: None,
                 },
             ];
             
@@ -0,0 +1,147 @@
+// Aiken format
+//
+// Each question is a line of text, followed by options labelled `A.` or
+// `A)` and an `ANSWER:` line naming the correct option.
+
+use super::{choice, content, is_true_false, skipped, ParseError, TextExport};
+use crate::quiz::models::{Answer, AnswerType, Choice, Question, Quiz};
+
+const ANSWER: &str = "ANSWER:";
+
+/// A question being read
+struct Pending {
+    line: usize,
+    text: String,
+    options: Vec<(char, Choice)>,
+}
+
+/// Read a quiz from an Aiken document
+pub fn read_aiken(source: &str) -> Result<Quiz, ParseError> {
+    let mut quiz = Quiz::new("Imported from Aiken".to_string(), None);
+    let mut pending: Option<Pending> = None;
+
+    for (index, raw) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let line = raw.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let column = raw.chars().take_while(|c| c.is_whitespace()).count() + 1;
+
+        if let Some(rest) = line.strip_prefix(ANSWER) {
+            let question = pending.take()
+                .ok_or_else(|| ParseError::new(line_number, column, "ANSWER line without a question"))?;
+            if question.options.is_empty() {
+                return Err(ParseError::new(line_number, column, "the question has no options"));
+            }
+
+            let answer_column = column + ANSWER.len() + rest.chars().take_while(|c| c.is_whitespace()).count();
+            let mut letters = rest.trim().chars();
+            let letter = match (letters.next(), letters.next()) {
+                (Some(letter), None) => letter.to_ascii_uppercase(),
+                _ => return Err(ParseError::new(line_number, answer_column, "expected a single option letter")),
+            };
+            let correct = question.options.iter()
+                .find(|(option, _)| *option == letter)
+                .map(|(_, choice)| choice.id)
+                .ok_or_else(|| ParseError::new(line_number, answer_column, format!("there is no option {}", letter)))?;
+
+            let choices: Vec<Choice> = question.options.into_iter().map(|(_, choice)| choice).collect();
+            let answer_type = if is_true_false(&choices) { AnswerType::TrueFalse } else { AnswerType::MultipleChoice };
+            let mut question = Question::new(quiz.id, content(question.text), answer_type);
+            question.choices = choices;
+            question.correct_answer = Answer::Choice(correct);
+            quiz.add_question(question);
+            continue;
+        }
+
+        let Some(question) = pending.as_mut() else {
+            pending = Some(Pending { line: line_number, text: line.to_string(), options: Vec::new() });
+            continue;
+        };
+
+        match option(line) {
+            Some((letter, text)) => {
+                if question.options.iter().any(|(option, _)| *option == letter) {
+                    return Err(ParseError::new(line_number, column, format!("option {} is listed twice", letter)));
+                }
+                question.options.push((letter, choice(text.to_string(), None)));
+            }
+            None if question.options.is_empty() => {
+                question.text.push('\n');
+                question.text.push_str(line);
+            }
+            None => return Err(ParseError::new(line_number, column, "expected an option or an ANSWER line")),
+        }
+    }
+
+    match pending {
+        Some(question) => Err(ParseError::new(question.line, 1, "the question has no ANSWER line")),
+        None => Ok(quiz),
+    }
+}
+
+/// Whether a document looks like Aiken
+pub fn is_aiken(source: &str) -> bool {
+    source.lines().any(|line| line.trim_start().starts_with(ANSWER))
+}
+
+/// Write a quiz as an Aiken document; only multiple choice and true/false
+/// questions can be written
+pub fn write_aiken(quiz: &Quiz) -> TextExport {
+    let mut text = String::new();
+    let mut left_out = Vec::new();
+
+    for question in &quiz.questions {
+        match question_to_aiken(question) {
+            Ok(aiken) => {
+                text.push_str(&aiken);
+                text.push('\n');
+            }
+            Err(reason) => left_out.push(skipped(question, reason)),
+        }
+    }
+
+    TextExport { text, skipped: left_out }
+}
+
+/// `A. text` or `A) text`
+fn option(line: &str) -> Option<(char, &str)> {
+    let mut chars = line.chars();
+    let letter = chars.next().filter(char::is_ascii_uppercase)?;
+    chars.next().filter(|separator| matches!(separator, '.' | ')'))?;
+
+    let text = chars.as_str();
+    if !text.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some((letter, text.trim()))
+}
+
+fn question_to_aiken(question: &Question) -> Result<String, String> {
+    if !matches!(question.answer_type, AnswerType::MultipleChoice | AnswerType::TrueFalse) {
+        return Err(format!("Aiken only holds multiple choice questions, not {:?}", question.answer_type));
+    }
+    let Answer::Choice(correct) = &question.correct_answer else {
+        return Err(format!("the correct answer does not fit a {:?} question", question.answer_type));
+    };
+    if question.choices.len() > 26 {
+        return Err("Aiken questions have at most 26 options".to_string());
+    }
+
+    let single_line = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let letters = ('A'..='Z').zip(&question.choices);
+
+    let mut aiken = format!("{}\n", single_line(&question.content.text));
+    let mut answer = None;
+    for (letter, choice) in letters {
+        aiken.push_str(&format!("{}. {}\n", letter, single_line(&choice.text)));
+        if &choice.id == correct {
+            answer = Some(letter);
+        }
+    }
+
+    let answer = answer.ok_or_else(|| "the correct choice is missing".to_string())?;
+    aiken.push_str(&format!("{} {}\n", ANSWER, answer));
+    Ok(aiken)
+}
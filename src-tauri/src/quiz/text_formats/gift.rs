@@ -0,0 +1,760 @@
+// GIFT format
+//
+// A GIFT document is a list of questions separated by blank lines. Each
+// question is text with an answer block in braces, optionally preceded by a
+// `::title::` and a `[format]`; `//` lines are comments and `$CATEGORY:` lines
+// set the category of the questions that follow. Special characters in text
+// are escaped with a backslash.
+//
+// Multiple choice questions with several correct answers, given by several
+// `=` answers or by positive weights as Moodle writes them, are multi-select
+// questions. Questions that are valid GIFT but that we cannot represent, such
+// as a correct answer alongside answers worth part of the credit, are
+// skipped and reported rather than failing the whole document.
+
+use uuid::Uuid;
+
+use super::{choice, content, is_true_false, skipped, ParseError, TextExport, TextImport};
+use crate::quiz::models::{
+    AcceptedAnswer, Answer, AnswerType, Choice, MatchRule, MathEquationContent, MathEquationType, Question,
+    QuestionContent, Quiz, ShortAnswerContent,
+};
+use crate::quiz::qti::SkippedQuestion;
+
+/// Characters that must be escaped in GIFT text
+const SPECIAL: &[char] = &['~', '=', '#', '{', '}', ':', '\\'];
+
+/// Question text in place of an answer block in the middle of the text
+const BLANK: &str = "_____";
+
+/// A character of the document and its position
+#[derive(Debug, Clone, Copy)]
+struct Char {
+    c: char,
+    line: usize,
+    column: usize,
+}
+
+impl Char {
+    fn error(self, message: impl Into<String>) -> ParseError {
+        ParseError::new(self.line, self.column, message)
+    }
+
+    fn unsupported(self, message: impl Into<String>) -> Rejected {
+        Rejected::Unsupported(self.error(message))
+    }
+}
+
+/// Why a question was not read
+enum Rejected {
+    /// The document is malformed
+    Invalid(ParseError),
+    /// The question is valid GIFT we cannot represent; it is skipped
+    Unsupported(ParseError),
+}
+
+impl From<ParseError> for Rejected {
+    fn from(error: ParseError) -> Self {
+        Rejected::Invalid(error)
+    }
+}
+
+/// An answer of an answer block
+struct GiftAnswer<'a> {
+    marker: Char,
+    weight: Option<f64>,
+    text: &'a [Char],
+    feedback: Option<String>,
+}
+
+impl GiftAnswer<'_> {
+    /// Fraction of the credit the answer is worth
+    fn credit(&self) -> f32 {
+        match self.weight {
+            None if self.marker.c == '=' => 1.0,
+            None => 0.0,
+            Some(weight) => (weight / 100.0).clamp(0.0, 1.0) as f32,
+        }
+    }
+
+    /// Whether the answer is correct; answers worth part of the credit are
+    /// not supported
+    fn is_correct(&self) -> Result<bool, Rejected> {
+        match self.weight {
+            None => Ok(self.marker.c == '='),
+            Some(weight) if weight == 100.0 => Ok(true),
+            Some(weight) if weight <= 0.0 => Ok(false),
+            Some(weight) => Err(self.marker.unsupported(format!("partial credit weights such as {}% are not supported", weight))),
+        }
+    }
+}
+
+/// Read a quiz from a GIFT document
+///
+/// Descriptions (text without an answer block) are left out, and questions
+/// we cannot represent are listed in [`TextImport::skipped`].
+pub fn read_gift(source: &str) -> Result<TextImport, ParseError> {
+    let mut quiz = Quiz::new("Imported from GIFT".to_string(), None);
+    let mut skipped = Vec::new();
+
+    for block in blocks(source) {
+        let mut block = trim(&block);
+
+        if text(block).starts_with("$CATEGORY:") {
+            let end = find_unescaped(block, |c| c == '\n').unwrap_or(block.len());
+            let category = category_name(text(&block[..end])["$CATEGORY:".len()..].trim());
+            if !category.is_empty() && !quiz.tags.contains(&category) {
+                quiz.tags.push(category);
+            }
+            block = trim(&block[end..]);
+        }
+
+        if block.is_empty() {
+            continue;
+        }
+
+        match parse_question(block, quiz.id) {
+            Ok(Some(question)) => quiz.add_question(question),
+            Ok(None) => {}
+            Err(Rejected::Unsupported(error)) => skipped.push(SkippedQuestion {
+                identifier: format!("line {}", block[0].line),
+                title: question_title(block),
+                reason: error.to_string(),
+            }),
+            Err(Rejected::Invalid(error)) => return Err(error),
+        }
+    }
+
+    Ok(TextImport { quiz, skipped })
+}
+
+/// Title of a question, or its first line when it has none
+fn question_title(block: &[Char]) -> String {
+    if text(block).starts_with("::") {
+        if let Some(end) = find_sequence(&block[2..], "::") {
+            return unescape(&block[2..2 + end]).trim().to_string();
+        }
+    }
+    let end = find_unescaped(block, |c| c == '{' || c == '\n').unwrap_or(block.len());
+    unescape(&block[..end]).trim().to_string()
+}
+
+/// Whether a document looks like GIFT
+pub fn is_gift(source: &str) -> bool {
+    source.lines().any(|line| {
+        let line = line.trim();
+        line.starts_with("$CATEGORY:")
+            || (line.contains('{') && (line.contains('=') || line.contains('~') || line.contains('}')))
+    })
+}
+
+/// Write a quiz as a GIFT document
+pub fn write_gift(quiz: &Quiz) -> TextExport {
+    let mut text = String::new();
+    let mut left_out = Vec::new();
+
+    for question in &quiz.questions {
+        match question_to_gift(question) {
+            Ok(gift) => {
+                text.push_str(&gift);
+                text.push_str("\n\n");
+            }
+            Err(reason) => left_out.push(skipped(question, reason)),
+        }
+    }
+
+    TextExport { text, skipped: left_out }
+}
+
+/// Split a document into blocks of characters at blank lines, dropping
+/// comment lines
+fn blocks(source: &str) -> Vec<Vec<Char>> {
+    let mut blocks = Vec::new();
+    let mut block = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("//") {
+            continue;
+        }
+        if trimmed.is_empty() {
+            if !block.is_empty() {
+                blocks.push(std::mem::take(&mut block));
+            }
+            continue;
+        }
+
+        let mut column = 0;
+        for c in line.chars() {
+            column += 1;
+            block.push(Char { c, line: index + 1, column });
+        }
+        block.push(Char { c: '\n', line: index + 1, column: column + 1 });
+    }
+
+    if !block.is_empty() {
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Category path without the `$course$/`-style context prefix
+fn category_name(path: &str) -> String {
+    let path = match path.strip_prefix('$').and_then(|rest| rest.split_once("$/")) {
+        Some((_, path)) => path,
+        None => path,
+    };
+    path.trim().to_string()
+}
+
+fn parse_question(block: &[Char], quiz_id: Uuid) -> Result<Option<Question>, Rejected> {
+    let mut span = block;
+
+    if text(span).starts_with("::") {
+        let end = find_sequence(&span[2..], "::")
+            .ok_or_else(|| span[0].error("the question title is not closed with ::"))?;
+        span = trim(&span[2 + end + 2..]);
+    }
+
+    let mut html = false;
+    if span.first().map(|ch| ch.c) == Some('[') {
+        if let Some(end) = find_unescaped(span, |c| c == ']') {
+            match text(&span[1..end]).as_str() {
+                "html" => html = true,
+                "moodle" | "plain" | "markdown" => {}
+                format => return Err(span[0].error(format!("unknown text format [{}]", format)).into()),
+            }
+            span = trim(&span[end + 1..]);
+        }
+    }
+
+    let Some(open) = find_unescaped(span, |c| c == '{') else {
+        return Ok(None);
+    };
+    let close = find_unescaped(&span[open + 1..], |c| c == '}')
+        .map(|index| open + 1 + index)
+        .ok_or_else(|| span[open].error("the answer block is not closed with }"))?;
+    if let Some(index) = find_unescaped(&span[close + 1..], |c| c == '{') {
+        return Err(span[close + 1 + index].error("a question can only have one answer block").into());
+    }
+
+    let before = unescape(&span[..open]).trim().to_string();
+    let after = unescape(&span[close + 1..]).trim().to_string();
+    let question_text = if after.is_empty() { before } else { format!("{} {} {}", before, BLANK, after) };
+
+    let mut body = &span[open + 1..close];
+    let mut explanation = None;
+    if let Some(index) = find_sequence(body, "####") {
+        explanation = Some(unescape(&body[index + 4..]).trim().to_string()).filter(|text| !text.is_empty());
+        body = &body[..index];
+    }
+
+    let mut content = content(question_text);
+    if html {
+        content.rich_text = Some(content.text.clone());
+    }
+
+    let body = trim(body);
+    let mut question = if body.is_empty() {
+        Question::new(quiz_id, content, AnswerType::Essay)
+    } else if body[0].c == '#' {
+        numeric(quiz_id, content, &body[1..], span[open])?
+    } else if let Some(question) = true_false(quiz_id, content.clone(), body) {
+        question
+    } else {
+        let answers = parse_answers(body)?;
+        let matching = answers.iter().all(|answer| answer.marker.c == '=' && find_sequence(answer.text, "->").is_some());
+        if matching {
+            matching_question(quiz_id, content, &answers)?
+        } else if answers.iter().any(|answer| answer.marker.c == '~') {
+            multiple_choice(quiz_id, content, &answers, span[open])?
+        } else {
+            short_answer(quiz_id, content, &answers)
+        }
+    };
+
+    question.explanation = explanation;
+    Ok(Some(question))
+}
+
+fn parse_answers(body: &[Char]) -> Result<Vec<GiftAnswer<'_>>, ParseError> {
+    let mut answers = Vec::new();
+    let mut rest = trim(body);
+
+    while let Some(&marker) = rest.first() {
+        if marker.c != '=' && marker.c != '~' {
+            return Err(marker.error("expected an answer starting with = or ~"));
+        }
+        let end = find_unescaped(&rest[1..], |c| c == '=' || c == '~')
+            .map(|index| index + 1)
+            .unwrap_or(rest.len());
+        answers.push(parse_answer(marker, &rest[1..end])?);
+        rest = trim(&rest[end..]);
+    }
+
+    Ok(answers)
+}
+
+fn parse_answer(marker: Char, span: &[Char]) -> Result<GiftAnswer<'_>, ParseError> {
+    let mut span = trim(span);
+
+    let mut weight = None;
+    if span.first().map(|ch| ch.c) == Some('%') {
+        let end = find_unescaped(&span[1..], |c| c == '%')
+            .map(|index| index + 1)
+            .ok_or_else(|| span[0].error("the answer weight is not closed with %"))?;
+        let value = text(&span[1..end]);
+        weight = Some(value.trim().parse::<f64>()
+            .map_err(|_| span[0].error(format!("invalid answer weight {}", value)))?);
+        span = trim(&span[end + 1..]);
+    }
+
+    let (text, feedback) = split_feedback(span);
+    let text = trim(text);
+    if text.is_empty() {
+        return Err(marker.error("the answer is empty"));
+    }
+
+    Ok(GiftAnswer { marker, weight, text, feedback })
+}
+
+/// Split an answer at its `#feedback`
+fn split_feedback(span: &[Char]) -> (&[Char], Option<String>) {
+    match find_unescaped(span, |c| c == '#') {
+        Some(index) => (&span[..index], non_empty(&span[index + 1..])),
+        None => (span, None),
+    }
+}
+
+fn true_false(quiz_id: Uuid, content: QuestionContent, body: &[Char]) -> Option<Question> {
+    let (value, rest) = match find_unescaped(body, |c| c == '#') {
+        Some(index) => (&body[..index], Some(&body[index + 1..])),
+        None => (body, None),
+    };
+    let value = match text(trim(value)).to_uppercase().as_str() {
+        "T" | "TRUE" => true,
+        "F" | "FALSE" => false,
+        _ => return None,
+    };
+
+    // The first feedback is shown for the wrong answer, the second for the right one
+    let (wrong_feedback, right_feedback) = match rest {
+        Some(rest) => {
+            let (wrong, right) = split_feedback(rest);
+            (non_empty(wrong), right)
+        }
+        None => (None, None),
+    };
+    let (true_feedback, false_feedback) = if value { (right_feedback, wrong_feedback) } else { (wrong_feedback, right_feedback) };
+
+    let mut question = Question::new(quiz_id, content, AnswerType::TrueFalse);
+    let true_choice = choice("True".to_string(), true_feedback);
+    let false_choice = choice("False".to_string(), false_feedback);
+    question.correct_answer = Answer::Choice(if value { true_choice.id } else { false_choice.id });
+    question.choices = vec![true_choice, false_choice];
+    Some(question)
+}
+
+fn multiple_choice(
+    quiz_id: Uuid,
+    content: QuestionContent,
+    answers: &[GiftAnswer],
+    open: Char,
+) -> Result<Question, Rejected> {
+    // Weights of multi-select answers split the credit among them
+    let partial = answers.iter().find(|answer| answer.credit() > 0.0 && answer.credit() < 1.0);
+    if let Some(partial) = partial {
+        if answers.iter().any(|answer| answer.credit() >= 1.0) {
+            return Err(partial.marker.unsupported("partial credit weights next to a fully correct answer are not supported"));
+        }
+    }
+
+    let mut choices = Vec::new();
+    let mut correct = Vec::new();
+    for answer in answers {
+        let choice = choice(unescape(answer.text), answer.feedback.clone());
+        if answer.credit() > 0.0 {
+            correct.push(choice.id);
+        }
+        choices.push(choice);
+    }
+
+    let mut question = match correct.as_slice() {
+        [] => return Err(open.error("the question has no correct answer").into()),
+        [correct] => {
+            let answer_type = if is_true_false(&choices) { AnswerType::TrueFalse } else { AnswerType::MultipleChoice };
+            let mut question = Question::new(quiz_id, content, answer_type);
+            question.correct_answer = Answer::Choice(*correct);
+            question
+        }
+        _ => {
+            let mut question = Question::new(quiz_id, content, AnswerType::MultipleChoice);
+            question.correct_answer = Answer::Choices(correct);
+            question
+        }
+    };
+    question.choices = choices;
+    Ok(question)
+}
+
+/// Short answers are matched ignoring case, as in Moodle; the best answer
+/// is the correct one
+fn short_answer(quiz_id: Uuid, content: QuestionContent, answers: &[GiftAnswer]) -> Question {
+    let accepted_answers: Vec<AcceptedAnswer> = answers.iter()
+        .map(|answer| AcceptedAnswer {
+            rule: MatchRule::CaseInsensitive(unescape(answer.text).trim().to_string()),
+            credit: answer.credit(),
+            feedback: answer.feedback.clone(),
+        })
+        .collect();
+    let best = accepted_answers.iter()
+        .fold(&accepted_answers[0], |best, accepted| if accepted.credit > best.credit { accepted } else { best });
+
+    let mut question = Question::new(quiz_id, content, AnswerType::ShortAnswer);
+    question.correct_answer = Answer::Text(rule_text(&best.rule).unwrap_or_default().to_string());
+    question.content.short_answer_content = Some(ShortAnswerContent { accepted_answers });
+    question
+}
+
+/// Text of a rule GIFT can express
+fn rule_text(rule: &MatchRule) -> Option<&str> {
+    match rule {
+        MatchRule::Exact(text) | MatchRule::CaseInsensitive(text) | MatchRule::Normalized(text) => Some(text),
+        _ => None,
+    }
+}
+
+fn matching_question(
+    quiz_id: Uuid,
+    content: QuestionContent,
+    answers: &[GiftAnswer],
+) -> Result<Question, ParseError> {
+    let mut sources = Vec::new();
+    let mut targets: Vec<Choice> = Vec::new();
+    let mut pairs = Vec::new();
+
+    for answer in answers {
+        let arrow = find_sequence(answer.text, "->").unwrap_or_default();
+        let source = unescape(&answer.text[..arrow]).trim().to_string();
+        let target = unescape(&answer.text[arrow + 2..]).trim().to_string();
+        if target.is_empty() {
+            return Err(answer.marker.error("the match has no right-hand side"));
+        }
+
+        let target_id = match targets.iter().find(|choice| choice.text == target) {
+            Some(choice) => choice.id,
+            None => {
+                let choice = choice(target, None);
+                let id = choice.id;
+                targets.push(choice);
+                id
+            }
+        };
+
+        // A match without a left-hand side only adds a distractor
+        if !source.is_empty() {
+            let source = choice(source, None);
+            pairs.push((source.id, target_id));
+            sources.push(source);
+        }
+    }
+
+    let mut question = Question::new(quiz_id, content, AnswerType::Matching);
+    question.choices = sources.into_iter().chain(targets).collect();
+    question.correct_answer = Answer::Matching(pairs);
+    Ok(question)
+}
+
+fn numeric(
+    quiz_id: Uuid,
+    mut content: QuestionContent,
+    body: &[Char],
+    open: Char,
+) -> Result<Question, Rejected> {
+    let body = trim(body);
+
+    let (spec, feedback) = if matches!(body.first().map(|ch| ch.c), Some('=' | '~')) {
+        let answers = parse_answers(body)?;
+        let mut correct = None;
+        for answer in &answers {
+            if answer.is_correct()? {
+                if correct.is_some() {
+                    return Err(answer.marker.unsupported("numeric questions with more than one correct answer are not supported"));
+                }
+                correct = Some(answer);
+            } else {
+                return Err(answer.marker.unsupported("wrong numeric answers are not supported"));
+            }
+        }
+        let correct = correct.ok_or_else(|| open.error("the question has no correct answer"))?;
+        (correct.text, correct.feedback.clone())
+    } else {
+        let (spec, feedback) = split_feedback(body);
+        (trim(spec), feedback)
+    };
+
+    let position = spec.first().copied().unwrap_or(open);
+    let (value, tolerance) = parse_number(&unescape(spec))
+        .ok_or_else(|| position.error(format!("invalid numeric answer {}", text(spec))))?;
+
+    content.math_equation_content = Some(MathEquationContent {
+        equation_type: MathEquationType::Custom,
+        variables: None,
+        precision: None,
+        display_mode: false,
+        tolerance: Some(tolerance),
+        significant_figures: None,
+    });
+
+    let mut question = Question::new(quiz_id, content, AnswerType::MathEquation);
+    question.correct_answer = Answer::MathEquation(value.to_string());
+    question.choices.push(choice(value.to_string(), feedback));
+    Ok(question)
+}
+
+/// `value`, `value:tolerance` or `min..max` as a value and tolerance
+fn parse_number(spec: &str) -> Option<(f64, f64)> {
+    let spec = spec.trim();
+    if let Some((min, max)) = spec.split_once("..") {
+        let (min, max) = (min.trim().parse::<f64>().ok()?, max.trim().parse::<f64>().ok()?);
+        if min > max {
+            return None;
+        }
+        Some(((min + max) / 2.0, (max - min) / 2.0))
+    } else if let Some((value, tolerance)) = spec.split_once(':') {
+        Some((value.trim().parse().ok()?, tolerance.trim().parse::<f64>().ok()?.abs()))
+    } else {
+        Some((spec.parse().ok()?, 0.0))
+    }
+}
+
+fn question_to_gift(question: &Question) -> Result<String, String> {
+    let mismatched = || format!("the correct answer does not fit a {:?} question", question.answer_type);
+
+    let answers = match question.answer_type {
+        AnswerType::MultipleChoice | AnswerType::TrueFalse => {
+            let correct = match &question.correct_answer {
+                Answer::Choice(correct) => correct,
+                Answer::Choices(correct) if question.answer_type == AnswerType::MultipleChoice => {
+                    return finish(question, multiple_answers(question, correct)?);
+                }
+                _ => return Err(mismatched()),
+            };
+            let correct_choice = question.choices.iter()
+                .find(|choice| &choice.id == correct)
+                .ok_or_else(|| "the correct choice is missing".to_string())?;
+
+            if question.answer_type == AnswerType::TrueFalse && is_true_false(&question.choices) {
+                let value = correct_choice.text.trim().eq_ignore_ascii_case("true");
+                let wrong = question.choices.iter().find(|choice| &choice.id != correct).and_then(|choice| choice.feedback.as_deref());
+                let right = correct_choice.feedback.as_deref();
+
+                let mut block = (if value { "TRUE" } else { "FALSE" }).to_string();
+                if wrong.is_some() || right.is_some() {
+                    block.push_str(&format!("#{}", escape(wrong.unwrap_or_default())));
+                }
+                if let Some(right) = right {
+                    block.push_str(&format!("#{}", escape(right)));
+                }
+                block
+            } else {
+                let lines: Vec<String> = question.choices.iter()
+                    .map(|choice| format!("\t{}{}", if &choice.id == correct { '=' } else { '~' }, answer_text(choice)))
+                    .collect();
+                format!("\n{}\n", lines.join("\n"))
+            }
+        }
+        AnswerType::ShortAnswer => {
+            let Answer::Text(correct) = &question.correct_answer else {
+                return Err(mismatched());
+            };
+            let accepted_answers = question.content.short_answer_content.as_ref()
+                .map(|content| content.accepted_answers.as_slice())
+                .filter(|accepted| !accepted.is_empty());
+            if let Some(accepted_answers) = accepted_answers {
+                let answers = accepted_answers.iter()
+                    .map(|accepted| {
+                        let text = rule_text(&accepted.rule)
+                            .ok_or_else(|| "only plain text accepted answers can be written as GIFT".to_string())?;
+                        let mut answer = if accepted.credit >= 1.0 {
+                            format!("={}", escape(text))
+                        } else {
+                            format!("=%{}%{}", (accepted.credit.max(0.0) * 100.0).round(), escape(text))
+                        };
+                        if let Some(feedback) = &accepted.feedback {
+                            answer.push_str(&format!("#{}", escape(feedback)));
+                        }
+                        Ok(answer)
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                return finish(question, answers.join(" "));
+            }
+
+            let mut answers = Vec::new();
+            if !question.choices.iter().any(|choice| choice.text.eq_ignore_ascii_case(correct)) {
+                answers.push(format!("={}", escape(correct)));
+            }
+            answers.extend(question.choices.iter().map(|choice| format!("={}", answer_text(choice))));
+            answers.join(" ")
+        }
+        AnswerType::Essay => String::new(),
+        AnswerType::Matching => {
+            let Answer::Matching(pairs) = &question.correct_answer else {
+                return Err(mismatched());
+            };
+            let text_of = |id: &Uuid| question.choices.iter()
+                .find(|choice| &choice.id == id)
+                .map(|choice| escape(&choice.text))
+                .ok_or_else(|| "a matching pair refers to a missing choice".to_string());
+
+            let mut lines = pairs.iter()
+                .map(|(source, target)| Ok(format!("\t={} -> {}", text_of(source)?, text_of(target)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            lines.extend(question.choices.iter()
+                .filter(|choice| !pairs.iter().any(|(source, target)| source == &choice.id || target == &choice.id))
+                .map(|choice| format!("\t= -> {}", escape(&choice.text))));
+            format!("\n{}\n", lines.join("\n"))
+        }
+        AnswerType::MathEquation => {
+            let Answer::MathEquation(value) = &question.correct_answer else {
+                return Err(mismatched());
+            };
+            let value: f64 = value.trim().parse()
+                .map_err(|_| "only numeric answers can be written as GIFT".to_string())?;
+            let tolerance = question.content.math_equation_content.as_ref()
+                .and_then(|content| content.tolerance)
+                .unwrap_or_default();
+
+            let mut block = if tolerance > 0.0 { format!("#{}:{}", value, tolerance) } else { format!("#{}", value) };
+            if let Some(feedback) = question.choices.first().and_then(|choice| choice.feedback.as_deref()) {
+                block.push_str(&format!("#{}", escape(feedback)));
+            }
+            block
+        }
+        _ => return Err(format!("{:?} questions have no GIFT equivalent", question.answer_type)),
+    };
+
+    finish(question, answers)
+}
+
+/// Answers of a multi-select question, as Moodle writes them: the correct
+/// ones share the credit
+fn multiple_answers(question: &Question, correct: &[Uuid]) -> Result<String, String> {
+    if correct.is_empty() || !correct.iter().all(|id| question.choices.iter().any(|choice| &choice.id == id)) {
+        return Err("a correct choice is missing".to_string());
+    }
+
+    let weight = ((100.0 / correct.len() as f64) * 100_000.0).round() / 100_000.0;
+    let lines: Vec<String> = question.choices.iter()
+        .map(|choice| if correct.contains(&choice.id) {
+            format!("\t~%{}%{}", weight, answer_text(choice))
+        } else {
+            format!("\t~{}", answer_text(choice))
+        })
+        .collect();
+    Ok(format!("\n{}\n", lines.join("\n")))
+}
+
+/// A question with its answer block
+fn finish(question: &Question, answers: String) -> Result<String, String> {
+    let explanation = question.explanation.as_deref()
+        .map(|explanation| format!("####{}", escape(explanation)))
+        .unwrap_or_default();
+    let block = format!("{{{}{}}}", answers, explanation);
+
+    let gift = match question.content.text.split_once(BLANK) {
+        Some((before, after)) => format!("{} {} {}", escape(before.trim()), block, escape(after.trim())),
+        None => format!("{} {}", escape(&question.content.text), block),
+    };
+    Ok(gift.trim().to_string())
+}
+
+/// Text and feedback of a choice
+fn answer_text(choice: &Choice) -> String {
+    match &choice.feedback {
+        Some(feedback) => format!("{}#{}", escape(&choice.text), escape(feedback)),
+        None => escape(&choice.text),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            c if SPECIAL.contains(&c) => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(span: &[Char]) -> String {
+    let mut text = String::with_capacity(span.len());
+    let mut chars = span.iter().map(|ch| ch.c);
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some(escaped) => text.push(escaped),
+            None => text.push('\\'),
+        }
+    }
+    text
+}
+
+/// Raw text of a span
+fn text(span: &[Char]) -> String {
+    span.iter().map(|ch| ch.c).collect()
+}
+
+fn non_empty(span: &[Char]) -> Option<String> {
+    Some(unescape(span).trim().to_string()).filter(|text| !text.is_empty())
+}
+
+fn trim(span: &[Char]) -> &[Char] {
+    let start = span.iter().position(|ch| !ch.c.is_whitespace()).unwrap_or(span.len());
+    let end = span.iter().rposition(|ch| !ch.c.is_whitespace()).map_or(start, |index| index + 1);
+    &span[start..end.max(start)]
+}
+
+/// Position of the first unescaped character matching `predicate`
+fn find_unescaped(span: &[Char], predicate: impl Fn(char) -> bool) -> Option<usize> {
+    let mut escaped = false;
+    for (index, ch) in span.iter().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if ch.c == '\\' {
+            escaped = true;
+        } else if predicate(ch.c) {
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// Position of the first unescaped occurrence of `pattern`
+fn find_sequence(span: &[Char], pattern: &str) -> Option<usize> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut escaped = false;
+    for index in 0..span.len() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        if span[index].c == '\\' {
+            escaped = true;
+            continue;
+        }
+        if span[index..].len() >= pattern.len() && span[index..].iter().zip(&pattern).all(|(ch, c)| ch.c == *c) {
+            return Some(index);
+        }
+    }
+    None
+}
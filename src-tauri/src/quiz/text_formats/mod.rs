@@ -0,0 +1,105 @@
+// Plain text question formats
+//
+// GIFT and Aiken are the plain text formats Moodle uses for question banks,
+// and what many instructors author in. Aiken only holds multiple choice
+// questions; GIFT also covers true/false, short answer, matching, numeric
+// and essay questions, per-answer feedback and categories.
+//
+// Answers map to our models as follows:
+// - Per-answer feedback is kept on the `Choice`.
+// - Multi-select questions are written as Moodle multiple answer questions,
+//   the correct answers sharing the credit.
+// - Short answers are accepted answers of the question's short answer
+//   content, matched ignoring case; partial credit weights are kept.
+// - Numeric answers become MathEquation questions; ranges are stored as
+//   their midpoint with a tolerance.
+// - Categories are added to the quiz tags, since questions have no category.
+
+mod aiken;
+mod gift;
+#[cfg(test)]
+mod tests;
+
+pub use aiken::{is_aiken, read_aiken, write_aiken};
+pub use gift::{is_gift, read_gift, write_gift};
+
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::models::{Choice, QuestionContent};
+use super::qti::SkippedQuestion;
+
+/// Error in a GIFT or Aiken document, positioned at a 1-based line and column
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("Line {line}, column {column}: {message}")]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self { line, column, message: message.into() }
+    }
+}
+
+/// A quiz read from a text format
+#[derive(Debug, Clone)]
+pub struct TextImport {
+    pub quiz: super::models::Quiz,
+
+    /// Questions the quiz cannot represent
+    pub skipped: Vec<SkippedQuestion>,
+}
+
+/// A quiz written in a text format
+#[derive(Debug, Clone)]
+pub struct TextExport {
+    pub text: String,
+
+    /// Questions the format cannot represent
+    pub skipped: Vec<SkippedQuestion>,
+}
+
+fn content(text: String) -> QuestionContent {
+    QuestionContent {
+        text,
+        rich_text: None,
+        image_url: None,
+        audio_url: None,
+        drag_drop_content: None,
+        hotspot_content: None,
+        drawing_content: None,
+        code_execution_content: None,
+        math_equation_content: None,
+        timeline_content: None,
+        diagram_labeling_content: None,
+        short_answer_content: None,
+    }
+}
+
+fn choice(text: String, feedback: Option<String>) -> Choice {
+    Choice {
+        id: Uuid::new_v4(),
+        text,
+        rich_text: None,
+        image_url: None,
+        feedback,
+    }
+}
+
+/// Whether two choices read "True" and "False"
+fn is_true_false(choices: &[Choice]) -> bool {
+    let mut texts: Vec<String> = choices.iter().map(|choice| choice.text.trim().to_lowercase()).collect();
+    texts.sort();
+    texts == ["false", "true"]
+}
+
+fn skipped(question: &super::models::Question, reason: String) -> SkippedQuestion {
+    SkippedQuestion {
+        identifier: question.id.to_string(),
+        title: question.content.text.clone(),
+        reason,
+    }
+}
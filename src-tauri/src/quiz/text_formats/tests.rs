@@ -0,0 +1,265 @@
+use super::*;
+use crate::quiz::models::{Answer, AnswerType, MatchRule, Question, Quiz};
+
+fn correct_choice(question: &Question) -> &Choice {
+    let Answer::Choice(id) = &question.correct_answer else {
+        panic!("expected a choice answer, got {:?}", question.correct_answer);
+    };
+    question.choices.iter().find(|choice| &choice.id == id).unwrap()
+}
+
+mod gift {
+    use super::*;
+
+    const DOCUMENT: &str = r#"// Geography questions
+$CATEGORY: $course$/top/Geography
+
+::Capital::What is the capital of France? {
+    =Paris#Correct!
+    ~London#That is the capital of the UK.
+    ~Berlin
+####Paris has been the capital since 987.}
+
+The Nile is in Africa. {T#It is, though.#Well done.}
+
+Name the largest ocean. {=Pacific =Pacific Ocean#Also accepted}
+
+Match the countries and capitals. {
+    =France -> Paris
+    =Spain -> Madrid
+    = -> Lisbon
+}
+
+What is 2 \+ 2 \= ? {#4}
+
+Pick a number between 1 and 10. {#1..10#Any of them}
+
+Pi to two decimals. {#=3.14:0.005#Close enough}
+
+Mozart was born in {~Vienna =Salzburg ~Prague} in 1756.
+
+Describe the water cycle. {}
+
+This line is only a description.
+"#;
+
+    #[test]
+    fn test_read_document() {
+        let quiz = read_gift(DOCUMENT).unwrap().quiz;
+
+        assert_eq!(quiz.tags, vec!["top/Geography".to_string()]);
+        assert_eq!(quiz.questions.len(), 9);
+
+        let capital = &quiz.questions[0];
+        assert_eq!(capital.answer_type, AnswerType::MultipleChoice);
+        assert_eq!(capital.content.text, "What is the capital of France?");
+        assert_eq!(capital.choices.len(), 3);
+        assert_eq!(correct_choice(capital).text, "Paris");
+        assert_eq!(capital.choices[0].feedback.as_deref(), Some("Correct!"));
+        assert_eq!(capital.choices[2].feedback, None);
+        assert_eq!(capital.explanation.as_deref(), Some("Paris has been the capital since 987."));
+
+        let nile = &quiz.questions[1];
+        assert_eq!(nile.answer_type, AnswerType::TrueFalse);
+        assert_eq!(correct_choice(nile).text, "True");
+        assert_eq!(correct_choice(nile).feedback.as_deref(), Some("Well done."));
+        assert_eq!(nile.choices[1].feedback.as_deref(), Some("It is, though."));
+
+        let ocean = &quiz.questions[2];
+        assert_eq!(ocean.answer_type, AnswerType::ShortAnswer);
+        assert_eq!(ocean.correct_answer, Answer::Text("Pacific".to_string()));
+        assert!(ocean.check_answer(&Answer::Text("pacific ocean".to_string())));
+        assert!(ocean.choices.is_empty());
+        let accepted = &ocean.content.short_answer_content.as_ref().unwrap().accepted_answers;
+        assert_eq!(accepted.len(), 2);
+        assert_eq!(accepted[1].rule, MatchRule::CaseInsensitive("Pacific Ocean".to_string()));
+        assert_eq!(accepted[1].feedback.as_deref(), Some("Also accepted"));
+
+        let matching = &quiz.questions[3];
+        assert_eq!(matching.answer_type, AnswerType::Matching);
+        assert_eq!(matching.choices.len(), 5);
+        let Answer::Matching(pairs) = &matching.correct_answer else { panic!() };
+        assert_eq!(pairs.len(), 2);
+        assert!(matching.choices.iter().any(|choice| choice.text == "Lisbon"));
+
+        let sum = &quiz.questions[4];
+        assert_eq!(sum.answer_type, AnswerType::MathEquation);
+        assert_eq!(sum.content.text, "What is 2 + 2 = ?");
+        assert_eq!(sum.correct_answer, Answer::MathEquation("4".to_string()));
+
+        let range = &quiz.questions[5];
+        assert_eq!(range.correct_answer, Answer::MathEquation("5.5".to_string()));
+        assert_eq!(range.content.math_equation_content.as_ref().unwrap().tolerance, Some(4.5));
+        assert_eq!(range.choices[0].feedback.as_deref(), Some("Any of them"));
+
+        let pi = &quiz.questions[6];
+        assert_eq!(pi.correct_answer, Answer::MathEquation("3.14".to_string()));
+        assert_eq!(pi.content.math_equation_content.as_ref().unwrap().tolerance, Some(0.005));
+
+        let mozart = &quiz.questions[7];
+        assert_eq!(mozart.content.text, "Mozart was born in _____ in 1756.");
+        assert_eq!(correct_choice(mozart).text, "Salzburg");
+
+        assert_eq!(quiz.questions[8].answer_type, AnswerType::Essay);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let quiz = read_gift(DOCUMENT).unwrap().quiz;
+        let export = write_gift(&quiz);
+        assert!(export.skipped.is_empty());
+
+        let reread = read_gift(&export.text).unwrap().quiz;
+        assert_eq!(reread.questions.len(), quiz.questions.len());
+        for (original, copy) in quiz.questions.iter().zip(&reread.questions) {
+            assert_eq!(copy.answer_type, original.answer_type);
+            assert_eq!(copy.content.text, original.content.text);
+            assert_eq!(copy.explanation, original.explanation);
+
+            let accepted = |question: &Question| question.content.short_answer_content.as_ref()
+                .map(|content| content.accepted_answers.iter()
+                    .map(|accepted| (accepted.rule.clone(), accepted.credit, accepted.feedback.clone()))
+                    .collect::<Vec<_>>());
+            assert_eq!(accepted(copy), accepted(original));
+
+            let texts = |question: &Question| question.choices.iter()
+                .map(|choice| (choice.text.clone(), choice.feedback.clone()))
+                .collect::<Vec<_>>();
+            let mut original_texts = texts(original);
+            let mut copy_texts = texts(copy);
+            original_texts.sort();
+            copy_texts.sort();
+            assert_eq!(copy_texts, original_texts);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_questions_are_skipped() {
+        let mut quiz = Quiz::new("Drawing".to_string(), None);
+        quiz.add_question(Question::new(quiz.id, content("Draw a cat.".to_string()), AnswerType::Drawing));
+
+        let export = write_gift(&quiz);
+        assert!(export.text.is_empty());
+        assert_eq!(export.skipped.len(), 1);
+    }
+
+    #[test]
+    fn test_errors_have_positions() {
+        let error = read_gift("Question one {=a ~b}\n\nQuestion two {\n  =a\n  ~b\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 14));
+
+        let error = read_gift("How many? {#lots}").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 13));
+        assert!(error.to_string().starts_with("Line 1, column 13:"));
+    }
+
+    #[test]
+    fn test_unsupported_questions_are_reported() {
+        let document = "First {=a ~b}\n\n::Partial:: Pick one {\n  =a\n  ~%50%b\n}\n\nLast {=a ~b}";
+        let import = read_gift(document).unwrap();
+
+        assert_eq!(import.quiz.questions.len(), 2);
+        assert_eq!(import.skipped.len(), 1);
+        assert_eq!(import.skipped[0].identifier, "line 3");
+        assert_eq!(import.skipped[0].title, "Partial");
+        assert!(import.skipped[0].reason.starts_with("Line 5, column 3:"));
+    }
+
+    #[test]
+    fn test_multi_select() {
+        let document = "Pick two {=a =b ~c}\n\nPick the primes {~%50%2 ~%50%3 ~%-100%4}";
+        let import = read_gift(document).unwrap();
+        assert!(import.skipped.is_empty());
+
+        for question in &import.quiz.questions {
+            assert_eq!(question.answer_type, AnswerType::MultipleChoice);
+            assert_eq!(question.correct_answer, Answer::Choices(vec![question.choices[0].id, question.choices[1].id]));
+        }
+
+        let export = write_gift(&import.quiz);
+        assert!(export.text.contains("~%50%2"));
+        let reread = read_gift(&export.text).unwrap().quiz;
+        let question = &reread.questions[0];
+        assert_eq!(question.correct_answer, Answer::Choices(vec![question.choices[0].id, question.choices[1].id]));
+    }
+
+    #[test]
+    fn test_short_answer_partial_credit() {
+        let quiz = read_gift("Capital of Australia? {=Canberra =%50%Sydney#Its largest city}").unwrap().quiz;
+        let question = &quiz.questions[0];
+
+        assert_eq!(question.correct_answer, Answer::Text("Canberra".to_string()));
+        let score = question.score_answer(&Answer::Text("sydney".to_string()), &quiz.settings);
+        assert_eq!(score.credit, 0.5);
+
+        let export = write_gift(&quiz);
+        assert!(export.text.contains("=%50%Sydney#Its largest city"));
+    }
+}
+
+mod aiken {
+    use super::*;
+
+    const DOCUMENT: &str = "What is the capital of France?
+A. London
+B. Paris
+C) Berlin
+ANSWER: B
+
+The Nile is in Africa.
+A. True
+B. False
+ANSWER: A
+";
+
+    #[test]
+    fn test_read_document() {
+        let quiz = read_aiken(DOCUMENT).unwrap();
+        assert_eq!(quiz.questions.len(), 2);
+
+        let capital = &quiz.questions[0];
+        assert_eq!(capital.answer_type, AnswerType::MultipleChoice);
+        assert_eq!(capital.choices.len(), 3);
+        assert_eq!(capital.choices[2].text, "Berlin");
+        assert_eq!(correct_choice(capital).text, "Paris");
+
+        assert_eq!(quiz.questions[1].answer_type, AnswerType::TrueFalse);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let quiz = read_aiken(DOCUMENT).unwrap();
+        let export = write_aiken(&quiz);
+        assert!(export.skipped.is_empty());
+
+        let reread = read_aiken(&export.text).unwrap();
+        assert_eq!(reread.questions.len(), 2);
+        assert_eq!(correct_choice(&reread.questions[0]).text, "Paris");
+        assert!(is_aiken(&export.text));
+    }
+
+    #[test]
+    fn test_only_multiple_choice_is_written() {
+        let mut quiz = read_aiken(DOCUMENT).unwrap();
+        quiz.add_question(Question::new(quiz.id, content("Describe Paris.".to_string()), AnswerType::Essay));
+
+        let export = write_aiken(&quiz);
+        assert_eq!(export.skipped.len(), 1);
+        assert_eq!(read_aiken(&export.text).unwrap().questions.len(), 2);
+    }
+
+    #[test]
+    fn test_errors_have_positions() {
+        let error = read_aiken("Question\nA. one\nB. two\nANSWER: C\n").unwrap_err();
+        assert_eq!((error.line, error.column), (4, 9));
+
+        let error = read_aiken("Question\nA. one\nnot an option\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 1));
+
+        let error = read_aiken("Question\nA. one\n").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 1));
+
+        let error = read_aiken("  ANSWER: A\n").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 3));
+    }
+}
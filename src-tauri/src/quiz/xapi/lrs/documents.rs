@@ -0,0 +1,328 @@
+// State, Activity Profile and Agent Profile resources of the embedded LRS
+//
+// The three document resources only differ in how documents are keyed, so
+// they share one table. Every document carries an ETag derived from its
+// content, which clients use for optimistic concurrency through If-Match and
+// If-None-Match.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use sqlx::Row;
+use uuid::Uuid;
+
+use super::{parse_agent, timestamp, LocalLrs, LrsError};
+
+/// Document resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    State,
+    ActivityProfile,
+    AgentProfile,
+}
+
+impl DocumentKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DocumentKind::State => "state",
+            DocumentKind::ActivityProfile => "activity_profile",
+            DocumentKind::AgentProfile => "agent_profile",
+        }
+    }
+}
+
+/// The set of documents that IDs are unique within
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentScope {
+    kind: DocumentKind,
+    activity_id: String,
+    agent: String,
+    registration: String,
+}
+
+impl DocumentScope {
+    /// States of an agent in an activity, optionally for one registration
+    pub fn state(activity_id: &str, agent: &str, registration: Option<&str>) -> Result<Self, LrsError> {
+        let registration = registration
+            .map(|registration| {
+                Uuid::parse_str(registration)
+                    .map(|registration| registration.to_string())
+                    .map_err(|_| LrsError::InvalidParameter("registration must be a UUID".to_string()))
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Self {
+            kind: DocumentKind::State,
+            activity_id: activity_id.to_string(),
+            agent: parse_agent(agent)?,
+            registration,
+        })
+    }
+
+    /// Profiles of an activity
+    pub fn activity_profile(activity_id: &str) -> Self {
+        Self {
+            kind: DocumentKind::ActivityProfile,
+            activity_id: activity_id.to_string(),
+            agent: String::new(),
+            registration: String::new(),
+        }
+    }
+
+    /// Profiles of an agent
+    pub fn agent_profile(agent: &str) -> Result<Self, LrsError> {
+        Ok(Self {
+            kind: DocumentKind::AgentProfile,
+            activity_id: String::new(),
+            agent: parse_agent(agent)?,
+            registration: String::new(),
+        })
+    }
+}
+
+/// A stored document
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub content: Vec<u8>,
+    pub content_type: String,
+    pub etag: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Concurrency headers of a write request
+#[derive(Debug, Clone, Default)]
+pub struct Preconditions {
+    /// ETags of which the current document must have one, or `*`
+    pub if_match: Option<String>,
+
+    /// ETags of which the current document must have none; `*` requires
+    /// that no document exists
+    pub if_none_match: Option<String>,
+}
+
+impl Preconditions {
+    fn is_empty(&self) -> bool {
+        self.if_match.is_none() && self.if_none_match.is_none()
+    }
+
+    /// Check the headers against the ETag of the current document, if any
+    fn check(&self, current_etag: Option<&str>) -> Result<(), LrsError> {
+        if let Some(if_match) = &self.if_match {
+            if !current_etag.map_or(false, |etag| etag_matches(if_match, etag)) {
+                return Err(LrsError::PreconditionFailed);
+            }
+        }
+        if let Some(if_none_match) = &self.if_none_match {
+            if current_etag.map_or(false, |etag| etag_matches(if_none_match, etag)) {
+                return Err(LrsError::PreconditionFailed);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl LocalLrs {
+    /// Get a document
+    pub async fn document(&self, scope: &DocumentScope, document_id: &str) -> Result<Option<Document>, LrsError> {
+        let row = sqlx::query(
+            "SELECT content, content_type, etag, updated_at FROM lrs_documents
+             WHERE kind = ? AND activity_id = ? AND agent = ? AND registration = ? AND document_id = ?"
+        )
+        .bind(scope.kind.as_str())
+        .bind(&scope.activity_id)
+        .bind(&scope.agent)
+        .bind(&scope.registration)
+        .bind(document_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| Document {
+            content: row.get("content"),
+            content_type: row.get("content_type"),
+            etag: row.get("etag"),
+            updated_at: DateTime::parse_from_rfc3339(row.get("updated_at"))
+                .map(|time| time.with_timezone(&Utc))
+                .unwrap_or_default(),
+        }))
+    }
+
+    /// IDs of the documents in a scope, optionally only those updated after `since`
+    pub async fn document_ids(&self, scope: &DocumentScope, since: Option<DateTime<Utc>>) -> Result<Vec<String>, LrsError> {
+        let ids = sqlx::query_scalar(
+            "SELECT document_id FROM lrs_documents
+             WHERE kind = ? AND activity_id = ? AND agent = ? AND registration = ? AND updated_at > ?
+             ORDER BY document_id"
+        )
+        .bind(scope.kind.as_str())
+        .bind(&scope.activity_id)
+        .bind(&scope.agent)
+        .bind(&scope.registration)
+        .bind(since.map(timestamp).unwrap_or_default())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Store or replace a document and return its new ETag
+    ///
+    /// Replacing an existing profile requires a concurrency header, as
+    /// required by the xAPI specification; states may be replaced freely.
+    pub async fn put_document(
+        &self,
+        scope: &DocumentScope,
+        document_id: &str,
+        content: Vec<u8>,
+        content_type: &str,
+        preconditions: &Preconditions,
+    ) -> Result<String, LrsError> {
+        self.write_document(scope, document_id, content, content_type, preconditions, false).await
+    }
+
+    /// Merge a JSON object into a document and return its new ETag
+    ///
+    /// Properties of the posted object replace those of the stored one. A
+    /// document that does not exist yet is created.
+    pub async fn post_document(
+        &self,
+        scope: &DocumentScope,
+        document_id: &str,
+        content: Vec<u8>,
+        content_type: &str,
+        preconditions: &Preconditions,
+    ) -> Result<String, LrsError> {
+        self.write_document(scope, document_id, content, content_type, preconditions, true).await
+    }
+
+    /// Delete a document
+    pub async fn delete_document(&self, scope: &DocumentScope, document_id: &str, preconditions: &Preconditions) -> Result<(), LrsError> {
+        let current = self.document(scope, document_id).await?;
+        preconditions.check(current.as_ref().map(|document| document.etag.as_str()))?;
+
+        sqlx::query(
+            "DELETE FROM lrs_documents
+             WHERE kind = ? AND activity_id = ? AND agent = ? AND registration = ? AND document_id = ?"
+        )
+        .bind(scope.kind.as_str())
+        .bind(&scope.activity_id)
+        .bind(&scope.agent)
+        .bind(&scope.registration)
+        .bind(document_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete all documents in a scope; only supported for states
+    pub async fn delete_documents(&self, scope: &DocumentScope) -> Result<(), LrsError> {
+        if scope.kind != DocumentKind::State {
+            return Err(LrsError::InvalidParameter("a profile ID is required".to_string()));
+        }
+
+        sqlx::query("DELETE FROM lrs_documents WHERE kind = ? AND activity_id = ? AND agent = ? AND registration = ?")
+            .bind(scope.kind.as_str())
+            .bind(&scope.activity_id)
+            .bind(&scope.agent)
+            .bind(&scope.registration)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn write_document(
+        &self,
+        scope: &DocumentScope,
+        document_id: &str,
+        mut content: Vec<u8>,
+        content_type: &str,
+        preconditions: &Preconditions,
+        merge: bool,
+    ) -> Result<String, LrsError> {
+        if document_id.is_empty() {
+            return Err(LrsError::InvalidParameter("a document ID is required".to_string()));
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        let current: Option<(Vec<u8>, String, String)> = sqlx::query_as(
+            "SELECT content, content_type, etag FROM lrs_documents
+             WHERE kind = ? AND activity_id = ? AND agent = ? AND registration = ? AND document_id = ?"
+        )
+        .bind(scope.kind.as_str())
+        .bind(&scope.activity_id)
+        .bind(&scope.agent)
+        .bind(&scope.registration)
+        .bind(document_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        preconditions.check(current.as_ref().map(|(_, _, etag)| etag.as_str()))?;
+        if !merge && scope.kind != DocumentKind::State && current.is_some() && preconditions.is_empty() {
+            return Err(LrsError::ConcurrencyRequired);
+        }
+
+        let mut content_type = content_type.to_string();
+        if let (true, Some((current_content, current_type, _))) = (merge, &current) {
+            content = merge_json(current_content, current_type, &content, &content_type)?;
+            content_type = "application/json".to_string();
+        }
+
+        let etag = etag(&content);
+        sqlx::query(
+            "INSERT INTO lrs_documents (kind, activity_id, agent, registration, document_id, content, content_type, etag, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT (kind, activity_id, agent, registration, document_id) DO UPDATE SET
+                content = excluded.content,
+                content_type = excluded.content_type,
+                etag = excluded.etag,
+                updated_at = excluded.updated_at"
+        )
+        .bind(scope.kind.as_str())
+        .bind(&scope.activity_id)
+        .bind(&scope.agent)
+        .bind(&scope.registration)
+        .bind(document_id)
+        .bind(content)
+        .bind(content_type)
+        .bind(&etag)
+        .bind(timestamp(Utc::now()))
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(etag)
+    }
+}
+
+/// Merge the properties of a posted JSON object into a stored one
+fn merge_json(current: &[u8], current_type: &str, posted: &[u8], posted_type: &str) -> Result<Vec<u8>, LrsError> {
+    let object = |content: &[u8], content_type: &str| -> Result<serde_json::Map<String, Value>, LrsError> {
+        if !content_type.starts_with("application/json") {
+            return Err(LrsError::InvalidDocument("only JSON documents can be merged".to_string()));
+        }
+        match serde_json::from_slice(content) {
+            Ok(Value::Object(object)) => Ok(object),
+            _ => Err(LrsError::InvalidDocument("only JSON objects can be merged".to_string())),
+        }
+    };
+
+    let mut merged = object(current, current_type)?;
+    merged.extend(object(posted, posted_type)?);
+    Ok(serde_json::to_vec(&merged)?)
+}
+
+/// ETag of document content
+fn etag(content: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(Sha1::digest(content)))
+}
+
+/// Whether an If-Match or If-None-Match header matches an ETag
+fn etag_matches(header: &str, etag: &str) -> bool {
+    header.split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/").trim_matches('"'))
+        .any(|tag| tag == "*" || tag == etag.trim_matches('"'))
+}
@@ -0,0 +1,159 @@
+// Embedded Learning Record Store
+//
+// Implements the xAPI 1.0.3 Statements, State, Activity Profile and Agent
+// Profile resources on top of SQLite so that xAPI and cmi5 content can be
+// tracked without a network connection. Statements are stored exactly as
+// accepted and never updated; voiding only flags the voided statement. The
+// HTTP binding lives in `routes::xapi`, and only serves clients presenting
+// the LRS's Basic credentials, as the server may be reachable from the LAN.
+
+mod documents;
+mod statements;
+
+pub use documents::{Document, DocumentKind, DocumentScope, Preconditions};
+pub use statements::{StatementPage, StatementQuery};
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use thiserror::Error;
+
+/// xAPI version implemented by the LRS
+pub const XAPI_VERSION: &str = "1.0.3";
+
+/// Verb of statements that void another statement
+pub const VOIDED_VERB: &str = "http://adlnet.gov/expapi/verbs/voided";
+
+/// Header carrying the xAPI version of requests and responses
+pub const VERSION_HEADER: &str = "X-Experience-API-Version";
+
+/// Errors returned by the LRS
+#[derive(Debug, Error)]
+pub enum LrsError {
+    #[error("Invalid statement: {0}")]
+    InvalidStatement(String),
+
+    #[error("Invalid parameter: {0}")]
+    InvalidParameter(String),
+
+    #[error("Invalid document: {0}")]
+    InvalidDocument(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("The document was modified or created concurrently")]
+    PreconditionFailed,
+
+    #[error("The document exists; an If-Match or If-None-Match header is required")]
+    ConcurrencyRequired,
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] sqlx::Error),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// Basic credentials clients of the LRS authenticate with
+#[derive(Debug, Clone)]
+pub struct LrsCredentials {
+    key: String,
+    secret: String,
+}
+
+impl LrsCredentials {
+    pub fn new(key: &str, secret: &str) -> Self {
+        Self { key: key.to_string(), secret: secret.to_string() }
+    }
+
+    /// Credentials set with `LRS_KEY` and `LRS_SECRET`, or random ones only
+    /// valid until the application exits
+    pub fn from_env() -> Self {
+        match (std::env::var("LRS_KEY"), std::env::var("LRS_SECRET")) {
+            (Ok(key), Ok(secret)) => Self { key, secret },
+            _ => Self {
+                key: "ordo".to_string(),
+                secret: hex::encode(rand::random::<[u8; 32]>()),
+            },
+        }
+    }
+
+    /// `key:secret`, as encoded in a Basic authorization header
+    pub fn basic(&self) -> String {
+        format!("{}:{}", self.key, self.secret)
+    }
+
+    /// Whether the value of an Authorization header carries these credentials
+    pub fn authorize(&self, authorization: &str) -> bool {
+        let Some(encoded) = authorization.strip_prefix("Basic ") else {
+            return false;
+        };
+        let Ok(decoded) = general_purpose::STANDARD.decode(encoded.trim()) else {
+            return false;
+        };
+
+        // Digests are compared, so the time taken tells nothing of the secret
+        Sha256::digest(&decoded) == Sha256::digest(self.basic().as_bytes())
+    }
+}
+
+/// SQLite backed Learning Record Store
+#[derive(Debug, Clone)]
+pub struct LocalLrs {
+    pool: SqlitePool,
+}
+
+impl LocalLrs {
+    /// Create an LRS, ensuring its tables exist
+    pub async fn new(pool: SqlitePool) -> Result<Self, LrsError> {
+        sqlx::query(include_str!("../../../sql/quiz_lrs_schema.sql"))
+            .execute(&pool)
+            .await?;
+
+        Ok(Self { pool })
+    }
+}
+
+/// Inverse functional identifier of an agent or identified group
+///
+/// Agents and groups are the same actor exactly when their IFIs match.
+pub fn agent_ifi(agent: &Value) -> Option<String> {
+    let field = |name: &str| agent.get(name).and_then(Value::as_str);
+
+    if let Some(mbox) = field("mbox") {
+        Some(format!("mbox:{}", mbox))
+    } else if let Some(sha1sum) = field("mbox_sha1sum") {
+        Some(format!("mbox_sha1sum:{}", sha1sum))
+    } else if let Some(openid) = field("openid") {
+        Some(format!("openid:{}", openid))
+    } else {
+        let account = agent.get("account")?;
+        let home_page = account.get("homePage").and_then(Value::as_str)?;
+        let name = account.get("name").and_then(Value::as_str)?;
+        Some(format!("account:{}|{}", home_page, name))
+    }
+}
+
+/// IFI of an agent given as a JSON request parameter
+pub fn parse_agent(agent: &str) -> Result<String, LrsError> {
+    let value: Value = serde_json::from_str(agent)
+        .map_err(|e| LrsError::InvalidParameter(format!("agent is not valid JSON: {}", e)))?;
+
+    agent_ifi(&value)
+        .ok_or_else(|| LrsError::InvalidParameter("agent has no inverse functional identifier".to_string()))
+}
+
+/// Parse a timestamp request parameter
+pub fn parse_timestamp(name: &str, value: &str) -> Result<DateTime<Utc>, LrsError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| LrsError::InvalidParameter(format!("{} is not an ISO 8601 timestamp", name)))
+}
+
+/// Timestamps are stored with a fixed precision so that they order as text
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
@@ -0,0 +1,639 @@
+// Statements resource of the embedded LRS
+//
+// Statements are validated, completed with the properties the LRS is
+// responsible for (id, stored, authority, version, timestamp) and stored as
+// JSON. The agents, activities, verb and registration each statement is about
+// are extracted into separate columns and tables for querying.
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
+use sqlx::{QueryBuilder, Row, Sqlite, Transaction};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use super::{agent_ifi, parse_agent, parse_timestamp, timestamp, LocalLrs, LrsError, VOIDED_VERB};
+
+/// Largest number of statements returned in one page
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Version recorded on statements that do not declare one
+const DEFAULT_STATEMENT_VERSION: &str = "1.0.0";
+
+/// Context activity lists of a statement context
+const CONTEXT_ACTIVITY_LISTS: [&str; 4] = ["parent", "grouping", "category", "other"];
+
+/// Statement filters, following the xAPI `GET statements` parameters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatementQuery {
+    /// Agent or identified group as JSON, matched against actor and object
+    pub agent: Option<String>,
+
+    /// Verb ID
+    pub verb: Option<String>,
+
+    /// Activity ID, matched against the object
+    pub activity: Option<String>,
+
+    /// Registration in the statement context
+    pub registration: Option<Uuid>,
+
+    /// Also match the activity against context and sub-statement activities
+    pub related_activities: bool,
+
+    /// Also match the agent against authority, instructor, team and sub-statement agents
+    pub related_agents: bool,
+
+    /// Only statements stored after this time
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only statements stored at or before this time
+    pub until: Option<DateTime<Utc>>,
+
+    /// Page size; 0 returns as many statements as the LRS allows
+    pub limit: u32,
+
+    /// Return the oldest statements first
+    pub ascending: bool,
+
+    /// Continue after this position; set on the query of the `more` link
+    pub cursor: Option<i64>,
+}
+
+impl StatementQuery {
+    /// Parse query parameters, rejecting unknown ones
+    ///
+    /// `format` and `attachments` are accepted but statements are always
+    /// returned exactly as stored and without attachments.
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self, LrsError> {
+        let mut query = Self::default();
+
+        for (name, value) in params {
+            match name.as_str() {
+                "agent" => {
+                    parse_agent(value)?;
+                    query.agent = Some(value.clone());
+                }
+                "verb" => query.verb = Some(value.clone()),
+                "activity" => query.activity = Some(value.clone()),
+                "registration" => {
+                    query.registration = Some(Uuid::parse_str(value)
+                        .map_err(|_| LrsError::InvalidParameter("registration must be a UUID".to_string()))?);
+                }
+                "related_activities" => query.related_activities = parse_bool(name, value)?,
+                "related_agents" => query.related_agents = parse_bool(name, value)?,
+                "since" => query.since = Some(parse_timestamp(name, value)?),
+                "until" => query.until = Some(parse_timestamp(name, value)?),
+                "limit" => {
+                    query.limit = value.parse()
+                        .map_err(|_| LrsError::InvalidParameter("limit must be a non-negative integer".to_string()))?;
+                }
+                "ascending" => query.ascending = parse_bool(name, value)?,
+                "cursor" => {
+                    query.cursor = Some(value.parse()
+                        .map_err(|_| LrsError::InvalidParameter("cursor is invalid".to_string()))?);
+                }
+                "format" | "attachments" => {}
+                _ => return Err(LrsError::InvalidParameter(format!("unknown parameter {}", name))),
+            }
+        }
+
+        Ok(query)
+    }
+
+    /// Query parameters reproducing this query
+    pub fn to_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = Vec::new();
+
+        if let Some(agent) = &self.agent {
+            params.push(("agent", agent.clone()));
+        }
+        if let Some(verb) = &self.verb {
+            params.push(("verb", verb.clone()));
+        }
+        if let Some(activity) = &self.activity {
+            params.push(("activity", activity.clone()));
+        }
+        if let Some(registration) = self.registration {
+            params.push(("registration", registration.to_string()));
+        }
+        if self.related_activities {
+            params.push(("related_activities", "true".to_string()));
+        }
+        if self.related_agents {
+            params.push(("related_agents", "true".to_string()));
+        }
+        if let Some(since) = self.since {
+            params.push(("since", timestamp(since)));
+        }
+        if let Some(until) = self.until {
+            params.push(("until", timestamp(until)));
+        }
+        if self.limit > 0 {
+            params.push(("limit", self.limit.to_string()));
+        }
+        if self.ascending {
+            params.push(("ascending", "true".to_string()));
+        }
+        if let Some(cursor) = self.cursor {
+            params.push(("cursor", cursor.to_string()));
+        }
+
+        params
+    }
+}
+
+/// One page of query results
+#[derive(Debug, Clone)]
+pub struct StatementPage {
+    /// Statements in the page
+    pub statements: Vec<Value>,
+
+    /// Query of the next page, if there is one
+    pub more: Option<StatementQuery>,
+}
+
+/// Values extracted from a statement for filtering
+struct Indexed {
+    id: String,
+    verb_id: String,
+    registration: Option<String>,
+    statement_ref: Option<String>,
+    agents: Vec<(String, bool)>,
+    activities: Vec<(String, bool)>,
+}
+
+impl LocalLrs {
+    /// Store a batch of statements and return their IDs
+    ///
+    /// Statements without an ID are assigned one. The batch is stored
+    /// atomically; resending a statement that is already stored is accepted,
+    /// but reusing its ID for a different statement is a conflict.
+    pub async fn store_statements(&self, statements: Vec<Value>) -> Result<Vec<String>, LrsError> {
+        let stored = timestamp(Utc::now());
+        let mut ids = HashSet::new();
+        let mut prepared = Vec::with_capacity(statements.len());
+
+        for statement in statements {
+            let has_timestamp = statement.get("timestamp").map_or(false, |timestamp| !timestamp.is_null());
+            let (statement, indexed) = prepare(statement, &stored)?;
+            if !ids.insert(indexed.id.clone()) {
+                return Err(invalid(format!("statement {} appears more than once", indexed.id)));
+            }
+            prepared.push((statement, indexed, has_timestamp));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        for (statement, indexed, has_timestamp) in &prepared {
+            insert_statement(&mut tx, statement, indexed, *has_timestamp).await?;
+        }
+        tx.commit().await?;
+
+        Ok(prepared.into_iter().map(|(_, indexed, _)| indexed.id).collect())
+    }
+
+    /// Store a statement under the ID given by the request
+    pub async fn put_statement(&self, statement_id: &str, mut statement: Value) -> Result<(), LrsError> {
+        let statement_id = Uuid::parse_str(statement_id)
+            .map_err(|_| LrsError::InvalidParameter("statementId must be a UUID".to_string()))?;
+
+        if let Some(object) = statement.as_object_mut() {
+            match object.get("id").and_then(Value::as_str).map(Uuid::parse_str) {
+                Some(Ok(id)) if id != statement_id => {
+                    return Err(invalid("the statement ID differs from statementId"));
+                }
+                Some(_) => {}
+                None => {
+                    object.insert("id".to_string(), json!(statement_id.to_string()));
+                }
+            }
+        }
+
+        self.store_statements(vec![statement]).await.map(|_| ())
+    }
+
+    /// Get a statement by ID
+    ///
+    /// Voided statements are only returned when `voided` is set, and then only
+    /// voided statements are.
+    pub async fn statement(&self, statement_id: &str, voided: bool) -> Result<Option<Value>, LrsError> {
+        let statement_id = Uuid::parse_str(statement_id)
+            .map_err(|_| LrsError::InvalidParameter("statement ID must be a UUID".to_string()))?;
+
+        let statement: Option<String> = sqlx::query_scalar("SELECT statement FROM lrs_statements WHERE id = ? AND voided = ?")
+            .bind(statement_id.to_string())
+            .bind(voided)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(statement.map(|statement| serde_json::from_str(&statement)).transpose()?)
+    }
+
+    /// Query statements, newest first unless the query is ascending
+    ///
+    /// Voided statements are left out. A statement that refers to another one
+    /// through a StatementRef, such as a voiding statement, matches the agent,
+    /// verb, activity and registration filters when the statement it refers
+    /// to does.
+    pub async fn query_statements(&self, query: &StatementQuery) -> Result<StatementPage, LrsError> {
+        let limit = if query.limit == 0 { MAX_PAGE_SIZE } else { query.limit.min(MAX_PAGE_SIZE) };
+        let agent = query.agent.as_deref().map(parse_agent).transpose()?;
+
+        let mut sql = QueryBuilder::<Sqlite>::new("SELECT s.seq, s.statement FROM lrs_statements s WHERE s.voided = 0");
+        if agent.is_some() || query.verb.is_some() || query.activity.is_some() || query.registration.is_some() {
+            sql.push(" AND (");
+            push_filters(&mut sql, "s", query, agent.as_deref());
+            sql.push(" OR EXISTS (SELECT 1 FROM lrs_statements t WHERE t.id = s.statement_ref AND ");
+            push_filters(&mut sql, "t", query, agent.as_deref());
+            sql.push("))");
+        }
+        if let Some(since) = query.since {
+            sql.push(" AND s.stored > ").push_bind(timestamp(since));
+        }
+        if let Some(until) = query.until {
+            sql.push(" AND s.stored <= ").push_bind(timestamp(until));
+        }
+        if let Some(cursor) = query.cursor {
+            sql.push(if query.ascending { " AND s.seq > " } else { " AND s.seq < " }).push_bind(cursor);
+        }
+        sql.push(if query.ascending { " ORDER BY s.seq ASC" } else { " ORDER BY s.seq DESC" });
+        sql.push(" LIMIT ").push_bind(i64::from(limit) + 1);
+
+        let rows = sql.build().fetch_all(&self.pool).await?;
+        let has_more = rows.len() > limit as usize;
+
+        let mut statements = Vec::with_capacity(rows.len().min(limit as usize));
+        let mut cursor = None;
+        for row in rows.into_iter().take(limit as usize) {
+            cursor = Some(row.get::<i64, _>("seq"));
+            statements.push(serde_json::from_str(row.get("statement"))?);
+        }
+
+        let more = if has_more {
+            Some(StatementQuery { cursor, ..query.clone() })
+        } else {
+            None
+        };
+
+        Ok(StatementPage { statements, more })
+    }
+}
+
+/// Filters of a statement query on the statement aliased as `alias`
+fn push_filters(sql: &mut QueryBuilder<'_, Sqlite>, alias: &str, query: &StatementQuery, agent: Option<&str>) {
+    sql.push("(1 = 1");
+
+    if let Some(verb) = &query.verb {
+        sql.push(format!(" AND {}.verb_id = ", alias)).push_bind(verb.clone());
+    }
+    if let Some(registration) = query.registration {
+        sql.push(format!(" AND {}.registration = ", alias)).push_bind(registration.to_string());
+    }
+    if let Some(agent) = agent {
+        sql.push(format!(" AND EXISTS (SELECT 1 FROM lrs_statement_agents g WHERE g.seq = {}.seq AND g.ifi = ", alias))
+            .push_bind(agent.to_string());
+        if !query.related_agents {
+            sql.push(" AND g.related = 0");
+        }
+        sql.push(")");
+    }
+    if let Some(activity) = &query.activity {
+        sql.push(format!(" AND EXISTS (SELECT 1 FROM lrs_statement_activities a WHERE a.seq = {}.seq AND a.activity_id = ", alias))
+            .push_bind(activity.clone());
+        if !query.related_activities {
+            sql.push(" AND a.related = 0");
+        }
+        sql.push(")");
+    }
+
+    sql.push(")");
+}
+
+async fn insert_statement(tx: &mut Transaction<'_, Sqlite>, statement: &Value, indexed: &Indexed, has_timestamp: bool) -> Result<(), LrsError> {
+    let existing: Option<String> = sqlx::query_scalar("SELECT statement FROM lrs_statements WHERE id = ?")
+        .bind(&indexed.id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    if let Some(existing) = existing {
+        let existing: Value = serde_json::from_str(&existing)?;
+        return if same_statement(&existing, statement, has_timestamp) {
+            Ok(())
+        } else {
+            Err(LrsError::Conflict(format!("statement {} is already stored with different content", indexed.id)))
+        };
+    }
+
+    let voiding = indexed.verb_id == VOIDED_VERB;
+    if let (true, Some(target)) = (voiding, &indexed.statement_ref) {
+        let target_verb: Option<String> = sqlx::query_scalar("SELECT verb_id FROM lrs_statements WHERE id = ?")
+            .bind(target)
+            .fetch_optional(&mut **tx)
+            .await?;
+        if target_verb.as_deref() == Some(VOIDED_VERB) {
+            return Err(invalid("a voiding statement cannot be voided"));
+        }
+
+        sqlx::query("UPDATE lrs_statements SET voided = 1 WHERE id = ?")
+            .bind(target)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    // The statement may have been voided before it arrived
+    let voided = !voiding && sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM lrs_statements WHERE verb_id = ? AND statement_ref = ?)"
+    )
+    .bind(VOIDED_VERB)
+    .bind(&indexed.id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let seq = sqlx::query(
+        "INSERT INTO lrs_statements (id, statement, verb_id, registration, statement_ref, voided, stored)
+         VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&indexed.id)
+    .bind(serde_json::to_string(statement)?)
+    .bind(&indexed.verb_id)
+    .bind(&indexed.registration)
+    .bind(&indexed.statement_ref)
+    .bind(voided)
+    .bind(statement["stored"].as_str())
+    .execute(&mut **tx)
+    .await?
+    .last_insert_rowid();
+
+    for (ifi, related) in &indexed.agents {
+        sqlx::query("INSERT INTO lrs_statement_agents (seq, ifi, related) VALUES (?, ?, ?)")
+            .bind(seq)
+            .bind(ifi)
+            .bind(related)
+            .execute(&mut **tx)
+            .await?;
+    }
+    for (activity_id, related) in &indexed.activities {
+        sqlx::query("INSERT INTO lrs_statement_activities (seq, activity_id, related) VALUES (?, ?, ?)")
+            .bind(seq)
+            .bind(activity_id)
+            .bind(related)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Validate a statement and complete it with the properties set by the LRS
+fn prepare(mut statement: Value, stored: &str) -> Result<(Value, Indexed), LrsError> {
+    strip_nulls(&mut statement);
+    let map = statement.as_object_mut()
+        .ok_or_else(|| invalid("a statement must be a JSON object"))?;
+
+    let id = match map.get("id") {
+        Some(id) => id.as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(|| invalid("id must be a UUID"))?,
+        None => Uuid::new_v4(),
+    };
+    map.insert("id".to_string(), json!(id.to_string()));
+
+    validate_agent(map.get("actor").ok_or_else(|| invalid("actor is required"))?, "actor")?;
+    let verb_id = validate_verb(map)?;
+    let object = map.get("object").ok_or_else(|| invalid("object is required"))?;
+    validate_object(object, false)?;
+    if verb_id == VOIDED_VERB && object_type(object) != "StatementRef" {
+        return Err(invalid("a voiding statement must refer to a StatementRef"));
+    }
+
+    if let Some(authority) = map.get("authority") {
+        validate_agent(authority, "authority")?;
+    }
+    if let Some(timestamp) = map.get("timestamp") {
+        timestamp.as_str()
+            .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+            .ok_or_else(|| invalid("timestamp must be an ISO 8601 timestamp"))?;
+    }
+    match map.get("version") {
+        None => {
+            map.insert("version".to_string(), json!(DEFAULT_STATEMENT_VERSION));
+        }
+        Some(version) if version.as_str().map_or(false, |version| version.starts_with("1.0")) => {}
+        Some(_) => return Err(invalid("unsupported statement version")),
+    }
+
+    map.entry("timestamp").or_insert_with(|| json!(stored));
+    map.entry("authority").or_insert_with(local_authority);
+    map.insert("stored".to_string(), json!(stored));
+
+    let indexed = index(&statement, id.to_string(), verb_id)?;
+    Ok((statement, indexed))
+}
+
+/// Extract what a statement is about
+fn index(statement: &Value, id: String, verb_id: String) -> Result<Indexed, LrsError> {
+    let registration = statement.pointer("/context/registration")
+        .map(|registration| {
+            registration.as_str()
+                .and_then(|registration| Uuid::parse_str(registration).ok())
+                .map(|registration| registration.to_string())
+                .ok_or_else(|| invalid("context.registration must be a UUID"))
+        })
+        .transpose()?;
+
+    let mut indexed = Indexed {
+        id,
+        verb_id,
+        registration,
+        statement_ref: None,
+        agents: Vec::new(),
+        activities: Vec::new(),
+    };
+
+    push_agent(&mut indexed, &statement["actor"], false);
+    push_agent(&mut indexed, &statement["authority"], true);
+    push_context(&mut indexed, statement);
+
+    let object = &statement["object"];
+    match object_type(object) {
+        "Activity" => push_activity(&mut indexed, object, false),
+        "Agent" | "Group" => push_agent(&mut indexed, object, false),
+        "StatementRef" => {
+            indexed.statement_ref = object["id"].as_str()
+                .and_then(|id| Uuid::parse_str(id).ok())
+                .map(|id| id.to_string());
+        }
+        "SubStatement" => {
+            push_agent(&mut indexed, &object["actor"], true);
+            push_context(&mut indexed, object);
+            let sub_object = &object["object"];
+            match object_type(sub_object) {
+                "Activity" => push_activity(&mut indexed, sub_object, true),
+                "Agent" | "Group" => push_agent(&mut indexed, sub_object, true),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+
+    Ok(indexed)
+}
+
+fn push_agent(indexed: &mut Indexed, agent: &Value, related: bool) {
+    if let Some(ifi) = agent_ifi(agent) {
+        indexed.agents.push((ifi, related));
+    }
+}
+
+fn push_activity(indexed: &mut Indexed, activity: &Value, related: bool) {
+    if let Some(id) = activity["id"].as_str() {
+        indexed.activities.push((id.to_string(), related));
+    }
+}
+
+/// Instructor, team and context activities are all related
+fn push_context(indexed: &mut Indexed, statement: &Value) {
+    let context = &statement["context"];
+    push_agent(indexed, &context["instructor"], true);
+    push_agent(indexed, &context["team"], true);
+
+    for list in CONTEXT_ACTIVITY_LISTS {
+        match &context["contextActivities"][list] {
+            Value::Array(activities) => {
+                for activity in activities {
+                    push_activity(indexed, activity, true);
+                }
+            }
+            activity => push_activity(indexed, activity, true),
+        }
+    }
+}
+
+fn validate_agent(agent: &Value, property: &str) -> Result<(), LrsError> {
+    let object = agent.as_object()
+        .ok_or_else(|| invalid(format!("{} must be an object", property)))?;
+
+    match object.get("objectType").and_then(Value::as_str).unwrap_or("Agent") {
+        "Agent" => {
+            if agent_ifi(agent).is_none() {
+                return Err(invalid(format!("{} has no inverse functional identifier", property)));
+            }
+        }
+        "Group" => {
+            match object.get("member") {
+                Some(Value::Array(members)) => {
+                    for member in members {
+                        if member.get("objectType").and_then(Value::as_str) == Some("Group") {
+                            return Err(invalid(format!("{} contains a nested group", property)));
+                        }
+                        validate_agent(member, property)?;
+                    }
+                }
+                Some(_) => return Err(invalid(format!("{}.member must be an array", property))),
+                None if agent_ifi(agent).is_none() => {
+                    return Err(invalid(format!("{} is an anonymous group without members", property)));
+                }
+                None => {}
+            }
+        }
+        other => return Err(invalid(format!("{} has unsupported objectType {}", property, other))),
+    }
+
+    Ok(())
+}
+
+fn validate_verb(statement: &Map<String, Value>) -> Result<String, LrsError> {
+    statement.get("verb")
+        .and_then(|verb| verb.get("id"))
+        .and_then(Value::as_str)
+        .filter(|id| is_iri(id))
+        .map(str::to_string)
+        .ok_or_else(|| invalid("verb.id must be an IRI"))
+}
+
+fn validate_object(object: &Value, in_sub_statement: bool) -> Result<(), LrsError> {
+    let map = object.as_object()
+        .ok_or_else(|| invalid("object must be an object"))?;
+    let id = map.get("id").and_then(Value::as_str);
+
+    match object_type(object) {
+        "Activity" => {
+            if !id.map_or(false, is_iri) {
+                return Err(invalid("activity id must be an IRI"));
+            }
+        }
+        "Agent" | "Group" => validate_agent(object, "object")?,
+        "StatementRef" => {
+            if id.and_then(|id| Uuid::parse_str(id).ok()).is_none() {
+                return Err(invalid("StatementRef id must be a UUID"));
+            }
+        }
+        "SubStatement" if !in_sub_statement => {
+            if let Some(property) = ["id", "stored", "version", "authority"].into_iter().find(|property| map.contains_key(*property)) {
+                return Err(invalid(format!("a SubStatement must not have {}", property)));
+            }
+            validate_agent(map.get("actor").ok_or_else(|| invalid("SubStatement actor is required"))?, "SubStatement actor")?;
+            validate_verb(map)?;
+            validate_object(map.get("object").ok_or_else(|| invalid("SubStatement object is required"))?, true)?;
+        }
+        "SubStatement" => return Err(invalid("a SubStatement must not contain a SubStatement")),
+        other => return Err(invalid(format!("unsupported objectType {}", other))),
+    }
+
+    Ok(())
+}
+
+/// Whether a resent statement is the one already stored, ignoring the
+/// properties set by the LRS
+fn same_statement(stored: &Value, received: &Value, compare_timestamp: bool) -> bool {
+    let comparable = |statement: &Value| {
+        let mut statement = statement.clone();
+        if let Some(map) = statement.as_object_mut() {
+            for property in ["stored", "authority", "version"] {
+                map.remove(property);
+            }
+            if !compare_timestamp {
+                map.remove("timestamp");
+            }
+        }
+        statement
+    };
+
+    comparable(stored) == comparable(received)
+}
+
+/// Null properties are treated as absent
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+fn object_type(object: &Value) -> &str {
+    object.get("objectType").and_then(Value::as_str).unwrap_or("Activity")
+}
+
+fn is_iri(value: &str) -> bool {
+    value.split_once(':').map_or(false, |(scheme, rest)| !scheme.is_empty() && !rest.is_empty())
+}
+
+/// Authority of statements that arrive without one
+fn local_authority() -> Value {
+    json!({
+        "objectType": "Agent",
+        "name": "Ordo LRS",
+        "account": { "homePage": "http://localhost", "name": "ordo-lrs" }
+    })
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, LrsError> {
+    value.parse()
+        .map_err(|_| LrsError::InvalidParameter(format!("{} must be true or false", name)))
+}
+
+fn invalid(message: impl Into<String>) -> LrsError {
+    LrsError::InvalidStatement(message.into())
+}
@@ -6,6 +6,15 @@ use anyhow::{Result, anyhow};
 use reqwest::Client;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use tracing::{debug, info, warn, error};
+use thiserror::Error;
+
+pub mod lrs;
+pub mod queue;
+
+#[cfg(test)]
+mod tests;
+
+pub use queue::{FlushReport, StatementQueue, XApiQueueStatus};
 
 /// xAPI statement
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -425,6 +434,85 @@ impl XApiClient {
         
         Ok(statements)
     }
+
+    /// Send a batch of statements to the LRS in a single request
+    pub async fn post_statements(&self, statements: &[XApiStatement]) -> Result<Delivery, XApiSendError> {
+        let response = self.client.post(format!("{}/statements", self.endpoint))
+            .headers(self.write_headers()?)
+            .json(statements)
+            .send()
+            .await
+            .map_err(|e| XApiSendError::Transient(e.to_string()))?;
+
+        Self::delivery(response).await
+    }
+
+    /// Store a single statement under its own ID
+    pub async fn put_statement(&self, statement: &XApiStatement) -> Result<Delivery, XApiSendError> {
+        let statement_id = statement.id.as_deref()
+            .ok_or_else(|| XApiSendError::Rejected("Statement has no ID".to_string()))?;
+
+        let response = self.client.put(format!("{}/statements", self.endpoint))
+            .query(&[("statementId", statement_id)])
+            .headers(self.write_headers()?)
+            .json(statement)
+            .send()
+            .await
+            .map_err(|e| XApiSendError::Transient(e.to_string()))?;
+
+        Self::delivery(response).await
+    }
+
+    /// Headers for requests that write statements
+    fn write_headers(&self) -> Result<HeaderMap, XApiSendError> {
+        let mut headers = HeaderMap::new();
+        let invalid = |e: reqwest::header::InvalidHeaderValue| XApiSendError::Transient(format!("Invalid LRS credentials or version: {}", e));
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&self.auth_header).map_err(invalid)?);
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert("X-Experience-API-Version", HeaderValue::from_str(&self.version).map_err(invalid)?);
+        Ok(headers)
+    }
+
+    /// Classify the LRS response to a statement write
+    async fn delivery(response: reqwest::Response) -> Result<Delivery, XApiSendError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Delivery::Stored);
+        }
+        if status == reqwest::StatusCode::CONFLICT {
+            return Ok(Delivery::Conflict);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let message = format!("{} {}", status, body.trim()).trim().to_string();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            Err(XApiSendError::Transient(message))
+        } else {
+            Err(XApiSendError::Rejected(message))
+        }
+    }
+}
+
+/// Result of writing statements the LRS did not refuse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// The statements were stored
+    Stored,
+
+    /// The LRS already holds a statement with the same ID
+    Conflict,
+}
+
+/// Error writing statements to the LRS
+#[derive(Debug, Error)]
+pub enum XApiSendError {
+    /// The LRS could not be reached or asked to retry later
+    #[error("LRS unavailable: {0}")]
+    Transient(String),
+
+    /// The LRS refused the statements; sending them again will not help
+    #[error("LRS rejected statements: {0}")]
+    Rejected(String),
 }
 
 /// xAPI statement builder
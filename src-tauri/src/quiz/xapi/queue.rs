@@ -0,0 +1,382 @@
+// Store-and-forward queue of xAPI statements
+//
+// Statements are written to SQLite with their final UUID before anything is
+// sent, so tracking keeps working offline and a statement resent after a lost
+// response is recognised by the LRS as a duplicate rather than stored twice.
+// A background loop forwards due statements in batches and backs off while the
+// LRS is unreachable.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use super::{Delivery, XApiClient, XApiSendError, XApiStatement};
+
+/// Maximum number of statements sent in one request
+pub const FLUSH_BATCH_SIZE: i64 = 50;
+
+/// Delay before the first retry; doubled on every further failure
+const RETRY_BASE_SECONDS: i64 = 5;
+
+/// Upper bound of the retry delay
+const RETRY_MAX_SECONDS: i64 = 30 * 60;
+
+/// Counts of what happened to the statements of one flush
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlushReport {
+    /// Statements stored by the LRS
+    pub sent: usize,
+
+    /// Statements the LRS already held
+    pub duplicates: usize,
+
+    /// Statements the LRS refused; they are kept but not retried
+    pub rejected: usize,
+
+    /// Statements left for a later attempt
+    pub deferred: usize,
+}
+
+/// Queue status shown to the user
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct XApiQueueStatus {
+    /// Statements waiting to be sent
+    pub pending: i64,
+
+    /// Statements delivered to the LRS
+    pub sent: i64,
+
+    /// Statements the LRS refused
+    pub rejected: i64,
+
+    /// When the oldest pending statement was queued
+    pub oldest_pending_at: Option<DateTime<Utc>>,
+
+    /// When the next pending statement becomes due
+    pub next_attempt_at: Option<DateTime<Utc>>,
+
+    /// When statements were last sent or attempted
+    pub last_attempt_at: Option<DateTime<Utc>>,
+
+    /// Most recent error of a statement that was not delivered
+    pub last_error: Option<String>,
+}
+
+/// A statement taken from the queue for sending
+struct QueuedStatement {
+    id: Uuid,
+    statement: XApiStatement,
+    attempts: i64,
+}
+
+/// Durable queue of outbound xAPI statements
+#[derive(Debug, Clone)]
+pub struct StatementQueue {
+    pool: SqlitePool,
+    wake: Arc<Notify>,
+}
+
+impl StatementQueue {
+    /// Create a queue, ensuring the queue table exists
+    pub async fn new(pool: SqlitePool) -> Result<Self> {
+        sqlx::query(include_str!("../../sql/quiz_xapi_schema.sql"))
+            .execute(&pool)
+            .await?;
+
+        Ok(Self {
+            pool,
+            wake: Arc::new(Notify::new()),
+        })
+    }
+
+    /// Queue a statement for delivery
+    ///
+    /// A statement without an ID is given a new UUID, which is returned.
+    /// Queueing a statement whose ID is already queued has no effect.
+    pub async fn enqueue(&self, statement: &XApiStatement) -> Result<Uuid> {
+        let mut statement = statement.clone();
+        let id = match statement.id.as_deref() {
+            Some(id) => Uuid::parse_str(id)?,
+            None => Uuid::new_v4(),
+        };
+        statement.id = Some(id.to_string());
+        statement.timestamp.get_or_insert_with(Utc::now);
+        statement.stored = None;
+
+        let now = timestamp(Utc::now());
+        sqlx::query(
+            "INSERT INTO xapi_statement_queue (id, statement, status, attempts, next_attempt_at, queued_at)
+             VALUES (?, ?, 'pending', 0, ?, ?)
+             ON CONFLICT (id) DO NOTHING"
+        )
+        .bind(id.to_string())
+        .bind(serde_json::to_string(&statement)?)
+        .bind(&now)
+        .bind(&now)
+        .execute(&self.pool)
+        .await?;
+
+        self.wake.notify_one();
+        Ok(id)
+    }
+
+    /// Send every due statement to the LRS
+    ///
+    /// Statements go out in batches. When the LRS refuses a batch or reports a
+    /// conflict, its statements are sent one by one so that a single bad or
+    /// already stored statement does not hold back the others. Flushing stops
+    /// at the first transient failure; the remaining statements are retried
+    /// with exponential backoff.
+    pub async fn flush(&self, client: &XApiClient) -> Result<FlushReport> {
+        let mut report = FlushReport::default();
+
+        loop {
+            let batch = self.due(Utc::now(), FLUSH_BATCH_SIZE).await?;
+            if batch.is_empty() {
+                break;
+            }
+
+            let statements: Vec<XApiStatement> = batch.iter().map(|queued| queued.statement.clone()).collect();
+            match client.post_statements(&statements).await {
+                Ok(Delivery::Stored) => {
+                    self.mark_sent(&batch).await?;
+                    report.sent += batch.len();
+                }
+                Ok(Delivery::Conflict) | Err(XApiSendError::Rejected(_)) if batch.len() > 1 => {
+                    if !self.send_individually(client, &batch, &mut report).await? {
+                        break;
+                    }
+                }
+                Ok(Delivery::Conflict) => {
+                    self.mark_sent(&batch).await?;
+                    report.duplicates += batch.len();
+                }
+                Err(XApiSendError::Rejected(e)) => {
+                    self.mark_rejected(&batch, &e).await?;
+                    report.rejected += batch.len();
+                }
+                Err(XApiSendError::Transient(e)) => {
+                    self.mark_retry(&batch, &e).await?;
+                    report.deferred += batch.len();
+                    break;
+                }
+            }
+        }
+
+        if report != FlushReport::default() {
+            debug!("Flushed xAPI statement queue: {:?}", report);
+        }
+        Ok(report)
+    }
+
+    /// Current queue status
+    pub async fn status(&self) -> Result<XApiQueueStatus> {
+        let mut status = XApiQueueStatus::default();
+
+        let counts = sqlx::query("SELECT status, COUNT(*) AS count FROM xapi_statement_queue GROUP BY status")
+            .fetch_all(&self.pool)
+            .await?;
+        for row in counts {
+            let count: i64 = row.get("count");
+            match row.get::<String, _>("status").as_str() {
+                "pending" => status.pending = count,
+                "sent" => status.sent = count,
+                "rejected" => status.rejected = count,
+                _ => {}
+            }
+        }
+
+        let row = sqlx::query(
+            "SELECT
+                (SELECT MIN(queued_at) FROM xapi_statement_queue WHERE status = 'pending') AS oldest_pending_at,
+                (SELECT MIN(next_attempt_at) FROM xapi_statement_queue WHERE status = 'pending') AS next_attempt_at,
+                (SELECT MAX(last_attempt_at) FROM xapi_statement_queue) AS last_attempt_at,
+                (SELECT last_error FROM xapi_statement_queue
+                    WHERE status != 'sent' AND last_error IS NOT NULL
+                    ORDER BY last_attempt_at DESC LIMIT 1) AS last_error"
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        status.oldest_pending_at = parse_timestamp(row.get("oldest_pending_at"))?;
+        status.next_attempt_at = parse_timestamp(row.get("next_attempt_at"))?;
+        status.last_attempt_at = parse_timestamp(row.get("last_attempt_at"))?;
+        status.last_error = row.get("last_error");
+
+        Ok(status)
+    }
+
+    /// Wake the flush loop, e.g. after connectivity returned
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Send the statements of a refused batch one by one
+    ///
+    /// Returns false if a transient failure deferred the rest of the batch.
+    async fn send_individually(&self, client: &XApiClient, batch: &[QueuedStatement], report: &mut FlushReport) -> Result<bool> {
+        for (index, queued) in batch.iter().enumerate() {
+            let single = std::slice::from_ref(queued);
+            match client.put_statement(&queued.statement).await {
+                Ok(Delivery::Stored) => {
+                    self.mark_sent(single).await?;
+                    report.sent += 1;
+                }
+                Ok(Delivery::Conflict) => {
+                    self.mark_sent(single).await?;
+                    report.duplicates += 1;
+                }
+                Err(XApiSendError::Rejected(e)) => {
+                    warn!("LRS rejected xAPI statement {}: {}", queued.id, e);
+                    self.mark_rejected(single, &e).await?;
+                    report.rejected += 1;
+                }
+                Err(XApiSendError::Transient(e)) => {
+                    let remaining = &batch[index..];
+                    self.mark_retry(remaining, &e).await?;
+                    report.deferred += remaining.len();
+                    return Ok(false);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Pending statements due at `now`, oldest first
+    async fn due(&self, now: DateTime<Utc>, limit: i64) -> Result<Vec<QueuedStatement>> {
+        let rows = sqlx::query(
+            "SELECT id, statement, attempts FROM xapi_statement_queue
+             WHERE status = 'pending' AND next_attempt_at <= ?
+             ORDER BY queued_at, id
+             LIMIT ?"
+        )
+        .bind(timestamp(now))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(QueuedStatement {
+                    id: Uuid::parse_str(row.get("id"))?,
+                    statement: serde_json::from_str(row.get("statement"))?,
+                    attempts: row.get("attempts"),
+                })
+            })
+            .collect()
+    }
+
+    async fn mark_sent(&self, statements: &[QueuedStatement]) -> Result<()> {
+        let now = timestamp(Utc::now());
+        let mut tx = self.pool.begin().await?;
+
+        for queued in statements {
+            sqlx::query(
+                "UPDATE xapi_statement_queue
+                 SET status = 'sent', attempts = attempts + 1, last_attempt_at = ?, sent_at = ?, last_error = NULL
+                 WHERE id = ?"
+            )
+            .bind(&now)
+            .bind(&now)
+            .bind(queued.id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn mark_rejected(&self, statements: &[QueuedStatement], error: &str) -> Result<()> {
+        let now = timestamp(Utc::now());
+        let mut tx = self.pool.begin().await?;
+
+        for queued in statements {
+            sqlx::query(
+                "UPDATE xapi_statement_queue
+                 SET status = 'rejected', attempts = attempts + 1, last_attempt_at = ?, last_error = ?
+                 WHERE id = ?"
+            )
+            .bind(&now)
+            .bind(error)
+            .bind(queued.id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn mark_retry(&self, statements: &[QueuedStatement], error: &str) -> Result<()> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        for queued in statements {
+            let attempts = queued.attempts + 1;
+            sqlx::query(
+                "UPDATE xapi_statement_queue
+                 SET attempts = ?, next_attempt_at = ?, last_attempt_at = ?, last_error = ?
+                 WHERE id = ?"
+            )
+            .bind(attempts)
+            .bind(timestamp(now + retry_delay(attempts)))
+            .bind(timestamp(now))
+            .bind(error)
+            .bind(queued.id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Forward queued statements to the LRS in the background
+///
+/// The queue is flushed every `interval` and whenever a statement is queued.
+pub fn spawn_flush_loop(queue: StatementQueue, client: Arc<XApiClient>, interval: std::time::Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = queue.wake.notified() => {}
+            }
+
+            match queue.flush(&client).await {
+                Ok(report) if report.deferred > 0 => {
+                    debug!("LRS unavailable, {} xAPI statements deferred", report.deferred);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to flush xAPI statement queue: {}", e),
+            }
+        }
+    })
+}
+
+/// Delay before retry number `attempts`
+fn retry_delay(attempts: i64) -> Duration {
+    let exponent = (attempts - 1).clamp(0, 20) as u32;
+    let seconds = RETRY_BASE_SECONDS.saturating_mul(1 << exponent).min(RETRY_MAX_SECONDS);
+    Duration::seconds(seconds)
+}
+
+/// Timestamps are stored with a fixed precision so that they order as text
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn parse_timestamp(value: Option<String>) -> Result<Option<DateTime<Utc>>> {
+    value
+        .map(|value| Ok(DateTime::parse_from_rfc3339(&value)?.with_timezone(&Utc)))
+        .transpose()
+}
@@ -0,0 +1,471 @@
+use super::*;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::{Json, Router};
+use serde_json::Value;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use std::sync::{Arc, Mutex as StdMutex};
+
+/// Minimal LRS that stores statements by ID
+#[derive(Default)]
+struct MockLrs {
+    online: bool,
+    stored: HashMap<String, Value>,
+    requests: usize,
+}
+
+type Lrs = Arc<StdMutex<MockLrs>>;
+
+fn check(lrs: &MockLrs, statements: &[Value]) -> Result<(), StatusCode> {
+    if !lrs.online {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    if statements.iter().any(|statement| statement["verb"]["id"].as_str().unwrap_or("").is_empty()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if statements.iter().any(|statement| lrs.stored.contains_key(statement["id"].as_str().unwrap())) {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(())
+}
+
+async fn post_statements(State(lrs): State<Lrs>, Json(statements): Json<Vec<Value>>) -> Result<Json<Vec<String>>, StatusCode> {
+    let mut lrs = lrs.lock().unwrap();
+    lrs.requests += 1;
+    check(&lrs, &statements)?;
+
+    let ids = statements.iter().map(|statement| statement["id"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+    for (id, statement) in ids.iter().zip(statements) {
+        lrs.stored.insert(id.clone(), statement);
+    }
+    Ok(Json(ids))
+}
+
+async fn put_statement(State(lrs): State<Lrs>, Query(params): Query<HashMap<String, String>>, Json(statement): Json<Value>) -> StatusCode {
+    let mut lrs = lrs.lock().unwrap();
+    lrs.requests += 1;
+    if let Err(status) = check(&lrs, std::slice::from_ref(&statement)) {
+        return status;
+    }
+
+    lrs.stored.insert(params["statementId"].clone(), statement);
+    StatusCode::NO_CONTENT
+}
+
+async fn start_mock_lrs() -> (Lrs, XApiClient) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let lrs: Lrs = Arc::new(StdMutex::new(MockLrs { online: true, ..Default::default() }));
+
+    let app = Router::new()
+        .route("/statements", post(post_statements).put(put_statement))
+        .with_state(lrs.clone());
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let client = XApiClient::new(XApiClientConfig {
+        endpoint,
+        username: "lrs".to_string(),
+        password: "secret".to_string(),
+        version: "1.0.3".to_string(),
+    }).unwrap();
+    (lrs, client)
+}
+
+async fn memory_pool() -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap()
+}
+
+fn started(quiz_id: &str) -> XApiStatement {
+    create_quiz_started_statement("user-1", "Learner", "learner@example.com", quiz_id, "Quiz")
+}
+
+/// Make deferred statements due again without waiting for the backoff
+async fn expire_backoff(pool: &SqlitePool) {
+    sqlx::query("UPDATE xapi_statement_queue SET next_attempt_at = '1970-01-01T00:00:00.000000Z'")
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_enqueue_assigns_id_once() {
+    let pool = memory_pool().await;
+    let queue = StatementQueue::new(pool.clone()).await.unwrap();
+
+    let id = queue.enqueue(&started("quiz-1")).await.unwrap();
+
+    let mut statement = started("quiz-2");
+    statement.id = Some(id.to_string());
+    assert_eq!(queue.enqueue(&statement).await.unwrap(), id);
+
+    statement.id = Some("not-a-uuid".to_string());
+    assert!(queue.enqueue(&statement).await.is_err());
+
+    let stored: String = sqlx::query_scalar("SELECT statement FROM xapi_statement_queue")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let stored: XApiStatement = serde_json::from_str(&stored).unwrap();
+    assert_eq!(stored.id, Some(id.to_string()));
+    assert_eq!(stored.object.id, "http://example.com/quizzes/quiz-1");
+
+    let status = queue.status().await.unwrap();
+    assert_eq!(status.pending, 1);
+    assert!(status.oldest_pending_at.is_some());
+    assert!(status.last_attempt_at.is_none());
+}
+
+#[tokio::test]
+async fn test_flush_defers_while_offline() {
+    let pool = memory_pool().await;
+    let queue = StatementQueue::new(pool.clone()).await.unwrap();
+    let (lrs, client) = start_mock_lrs().await;
+    lrs.lock().unwrap().online = false;
+
+    let first = queue.enqueue(&started("quiz-1")).await.unwrap();
+    let second = queue.enqueue(&started("quiz-2")).await.unwrap();
+
+    let report = queue.flush(&client).await.unwrap();
+    assert_eq!(report, FlushReport { deferred: 2, ..Default::default() });
+
+    let status = queue.status().await.unwrap();
+    assert_eq!(status.pending, 2);
+    assert!(status.next_attempt_at.unwrap() > Utc::now());
+    assert!(status.last_error.unwrap().contains("503"));
+
+    // Nothing is due until the backoff has passed
+    lrs.lock().unwrap().online = true;
+    assert_eq!(queue.flush(&client).await.unwrap(), FlushReport::default());
+    assert_eq!(lrs.lock().unwrap().requests, 1);
+
+    expire_backoff(&pool).await;
+    let report = queue.flush(&client).await.unwrap();
+    assert_eq!(report, FlushReport { sent: 2, ..Default::default() });
+
+    {
+        let lrs = lrs.lock().unwrap();
+        assert_eq!(lrs.requests, 2);
+        assert!(lrs.stored.contains_key(&first.to_string()));
+        assert!(lrs.stored.contains_key(&second.to_string()));
+    }
+
+    let status = queue.status().await.unwrap();
+    assert_eq!((status.pending, status.sent), (0, 2));
+    assert!(status.last_error.is_none());
+}
+
+#[tokio::test]
+async fn test_flush_dedupes_conflicts_and_isolates_rejections() {
+    let pool = memory_pool().await;
+    let queue = StatementQueue::new(pool.clone()).await.unwrap();
+    let (lrs, client) = start_mock_lrs().await;
+
+    // A statement whose earlier delivery succeeded but whose response was lost
+    let delivered = queue.enqueue(&started("quiz-1")).await.unwrap();
+    let fresh = queue.enqueue(&started("quiz-2")).await.unwrap();
+    let mut invalid = started("quiz-3");
+    invalid.verb.id = String::new();
+    let invalid = queue.enqueue(&invalid).await.unwrap();
+    lrs.lock().unwrap().stored.insert(delivered.to_string(), Value::Null);
+
+    let report = queue.flush(&client).await.unwrap();
+    assert_eq!(report, FlushReport { sent: 1, duplicates: 1, rejected: 1, deferred: 0 });
+
+    {
+        let lrs = lrs.lock().unwrap();
+        assert_eq!(lrs.stored[&delivered.to_string()], Value::Null);
+        assert!(lrs.stored.contains_key(&fresh.to_string()));
+        assert!(!lrs.stored.contains_key(&invalid.to_string()));
+    }
+
+    let status = queue.status().await.unwrap();
+    assert_eq!((status.pending, status.sent, status.rejected), (0, 2, 1));
+    assert!(status.last_error.unwrap().contains("400"));
+
+    // Rejected statements are not retried
+    assert_eq!(queue.flush(&client).await.unwrap(), FlushReport::default());
+}
+
+mod local_lrs {
+    use super::*;
+    use super::super::lrs::{DocumentScope, LocalLrs, LrsCredentials, LrsError, Preconditions, StatementQuery, VOIDED_VERB};
+    use crate::quiz::cmi5::Cmi5Client;
+    use serde_json::json;
+
+    const LEARNER: &str = r#"{"mbox":"mailto:learner@example.com"}"#;
+    const QUIZ: &str = "http://example.com/quizzes/quiz-1";
+
+    async fn lrs() -> LocalLrs {
+        LocalLrs::new(memory_pool().await).await.unwrap()
+    }
+
+    fn statement(email: &str, verb: &str, activity: &str) -> Value {
+        json!({
+            "actor": { "objectType": "Agent", "mbox": format!("mailto:{}", email) },
+            "verb": { "id": format!("http://adlnet.gov/expapi/verbs/{}", verb), "display": { "en-US": verb } },
+            "object": { "id": activity },
+        })
+    }
+
+    fn voiding(target: &str) -> Value {
+        json!({
+            "actor": { "mbox": "mailto:teacher@example.com" },
+            "verb": { "id": VOIDED_VERB },
+            "object": { "objectType": "StatementRef", "id": target },
+        })
+    }
+
+    fn ids(statements: &[Value]) -> Vec<&str> {
+        statements.iter().map(|statement| statement["id"].as_str().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_statements_are_immutable() {
+        let lrs = lrs().await;
+        let id = Uuid::new_v4().to_string();
+
+        let mut first = statement("learner@example.com", "attempted", QUIZ);
+        first["id"] = json!(id);
+        assert_eq!(lrs.store_statements(vec![first.clone()]).await.unwrap(), vec![id.clone()]);
+
+        let stored = lrs.statement(&id, false).await.unwrap().unwrap();
+        assert!(stored["stored"].is_string());
+        assert_eq!(stored["version"], "1.0.0");
+        assert_eq!(stored["timestamp"], stored["stored"]);
+        assert!(stored["authority"]["account"].is_object());
+
+        // Resending the same statement is accepted and changes nothing
+        lrs.put_statement(&id, first.clone()).await.unwrap();
+        assert_eq!(lrs.statement(&id, false).await.unwrap().unwrap(), stored);
+
+        let mut changed = first;
+        changed["verb"]["id"] = json!("http://adlnet.gov/expapi/verbs/passed");
+        assert!(matches!(lrs.store_statements(vec![changed]).await, Err(LrsError::Conflict(_))));
+
+        let invalid = json!({ "actor": { "name": "No identifier" }, "verb": { "id": "x:y" }, "object": { "id": QUIZ } });
+        let batch = vec![statement("other@example.com", "attempted", QUIZ), invalid];
+        assert!(matches!(lrs.store_statements(batch).await, Err(LrsError::InvalidStatement(_))));
+        assert_eq!(lrs.query_statements(&StatementQuery::default()).await.unwrap().statements.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_voiding() {
+        let lrs = lrs().await;
+        let ids_stored = lrs.store_statements(vec![
+            statement("learner@example.com", "attempted", QUIZ),
+            statement("learner@example.com", "passed", QUIZ),
+        ]).await.unwrap();
+        let (attempted, passed) = (&ids_stored[0], &ids_stored[1]);
+
+        let voiding_id = lrs.store_statements(vec![voiding(passed)]).await.unwrap().remove(0);
+
+        assert!(lrs.statement(passed, false).await.unwrap().is_none());
+        assert!(lrs.statement(passed, true).await.unwrap().is_some());
+        assert!(lrs.statement(attempted, true).await.unwrap().is_none());
+
+        // The voiding statement matches filters through the voided statement
+        let query = StatementQuery { agent: Some(LEARNER.to_string()), ..Default::default() };
+        let page = lrs.query_statements(&query).await.unwrap();
+        assert_eq!(ids(&page.statements), vec![voiding_id.as_str(), attempted.as_str()]);
+
+        assert!(matches!(
+            lrs.store_statements(vec![voiding(&voiding_id)]).await,
+            Err(LrsError::InvalidStatement(_))
+        ));
+
+        // A statement voided before it arrives is stored voided
+        let late = Uuid::new_v4().to_string();
+        lrs.store_statements(vec![voiding(&late)]).await.unwrap();
+        lrs.put_statement(&late, statement("learner@example.com", "failed", QUIZ)).await.unwrap();
+        assert!(lrs.statement(&late, true).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_query_filters_and_paging() {
+        let lrs = lrs().await;
+        let mut with_context = statement("other@example.com", "answered", "http://example.com/questions/q1");
+        with_context["context"] = json!({
+            "registration": "6b2f1d0c-2a6e-4b6f-9e84-4a1c0cfd2a61",
+            "instructor": { "mbox": "mailto:learner@example.com" },
+            "contextActivities": { "parent": [{ "id": QUIZ }] },
+        });
+
+        let first = lrs.store_statements(vec![
+            statement("learner@example.com", "attempted", QUIZ),
+            statement("learner@example.com", "answered", "http://example.com/questions/q1"),
+        ]).await.unwrap();
+        let between = Utc::now();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let second = lrs.store_statements(vec![
+            with_context,
+            statement("other@example.com", "attempted", QUIZ),
+        ]).await.unwrap();
+
+        let query = |query: StatementQuery| {
+            let lrs = lrs.clone();
+            async move { lrs.query_statements(&query).await.unwrap().statements }
+        };
+
+        let learner = query(StatementQuery { agent: Some(LEARNER.to_string()), ..Default::default() }).await;
+        assert_eq!(ids(&learner), vec![first[1].as_str(), first[0].as_str()]);
+        let related = query(StatementQuery { agent: Some(LEARNER.to_string()), related_agents: true, ..Default::default() }).await;
+        assert_eq!(related.len(), 3);
+
+        let quiz = query(StatementQuery { activity: Some(QUIZ.to_string()), ..Default::default() }).await;
+        assert_eq!(ids(&quiz), vec![second[1].as_str(), first[0].as_str()]);
+        let quiz_related = query(StatementQuery { activity: Some(QUIZ.to_string()), related_activities: true, ..Default::default() }).await;
+        assert_eq!(quiz_related.len(), 3);
+
+        let answered = query(StatementQuery { verb: Some("http://adlnet.gov/expapi/verbs/answered".to_string()), ..Default::default() }).await;
+        assert_eq!(answered.len(), 2);
+        let registration = query(StatementQuery { registration: Some(Uuid::parse_str("6b2f1d0c-2a6e-4b6f-9e84-4a1c0cfd2a61").unwrap()), ..Default::default() }).await;
+        assert_eq!(ids(&registration), vec![second[0].as_str()]);
+
+        assert_eq!(query(StatementQuery { since: Some(between), ..Default::default() }).await.len(), 2);
+        assert_eq!(query(StatementQuery { until: Some(between), ..Default::default() }).await.len(), 2);
+
+        // Page through everything oldest first, two at a time
+        let mut page = lrs.query_statements(&StatementQuery { limit: 2, ascending: true, ..Default::default() }).await.unwrap();
+        let mut seen = ids(&page.statements).into_iter().map(str::to_string).collect::<Vec<_>>();
+        while let Some(more) = page.more {
+            page = lrs.query_statements(&more).await.unwrap();
+            seen.extend(ids(&page.statements).into_iter().map(str::to_string));
+        }
+        assert_eq!(seen, [first, second].concat());
+    }
+
+    #[test]
+    fn test_query_params() {
+        let params: HashMap<String, String> = [
+            ("agent", LEARNER),
+            ("verb", "http://adlnet.gov/expapi/verbs/passed"),
+            ("related_activities", "true"),
+            ("since", "2024-01-01T00:00:00Z"),
+            ("limit", "10"),
+        ].into_iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+
+        let query = StatementQuery::from_params(&params).unwrap();
+        assert!(query.related_activities);
+        assert_eq!(query.limit, 10);
+
+        let round_trip: HashMap<String, String> = query.to_params().into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+        assert_eq!(StatementQuery::from_params(&round_trip).unwrap(), query);
+
+        let unknown: HashMap<String, String> = [("actor".to_string(), LEARNER.to_string())].into_iter().collect();
+        assert!(matches!(StatementQuery::from_params(&unknown), Err(LrsError::InvalidParameter(_))));
+    }
+
+    #[tokio::test]
+    async fn test_documents_and_etags() {
+        let lrs = lrs().await;
+        let state = DocumentScope::state(QUIZ, LEARNER, None).unwrap();
+        let none = Preconditions::default();
+
+        let etag = lrs.put_document(&state, "bookmark", br#"{"page":1}"#.to_vec(), "application/json", &none).await.unwrap();
+        let document = lrs.document(&state, "bookmark").await.unwrap().unwrap();
+        assert_eq!(document.etag, etag);
+        assert_eq!(document.content, br#"{"page":1}"#);
+
+        // States may be replaced without concurrency headers, but not with a stale ETag
+        let etag = lrs.put_document(&state, "bookmark", br#"{"page":2}"#.to_vec(), "application/json", &none).await.unwrap();
+        let stale = Preconditions { if_match: Some("\"0000\"".to_string()), if_none_match: None };
+        assert!(matches!(
+            lrs.put_document(&state, "bookmark", b"{}".to_vec(), "application/json", &stale).await,
+            Err(LrsError::PreconditionFailed)
+        ));
+
+        // POST merges JSON objects
+        let current = Preconditions { if_match: Some(etag), if_none_match: None };
+        lrs.post_document(&state, "bookmark", br#"{"score":3}"#.to_vec(), "application/json", &current).await.unwrap();
+        let merged: Value = serde_json::from_slice(&lrs.document(&state, "bookmark").await.unwrap().unwrap().content).unwrap();
+        assert_eq!(merged, json!({ "page": 2, "score": 3 }));
+
+        lrs.put_document(&state, "suspend", b"raw".to_vec(), "text/plain", &none).await.unwrap();
+        assert!(matches!(
+            lrs.post_document(&state, "suspend", b"{}".to_vec(), "application/json", &none).await,
+            Err(LrsError::InvalidDocument(_))
+        ));
+        assert_eq!(lrs.document_ids(&state, None).await.unwrap(), vec!["bookmark", "suspend"]);
+
+        // Registrations keep separate states
+        let registered = DocumentScope::state(QUIZ, LEARNER, Some("6b2f1d0c-2a6e-4b6f-9e84-4a1c0cfd2a61")).unwrap();
+        assert!(lrs.document(&registered, "bookmark").await.unwrap().is_none());
+
+        lrs.delete_documents(&state).await.unwrap();
+        assert!(lrs.document_ids(&state, None).await.unwrap().is_empty());
+
+        // Profiles require a concurrency header to replace an existing document
+        let profile = DocumentScope::agent_profile(LEARNER).unwrap();
+        let create = Preconditions { if_match: None, if_none_match: Some("*".to_string()) };
+        let etag = lrs.put_document(&profile, "preferences", b"{}".to_vec(), "application/json", &create).await.unwrap();
+        assert!(matches!(
+            lrs.put_document(&profile, "preferences", b"{}".to_vec(), "application/json", &create).await,
+            Err(LrsError::PreconditionFailed)
+        ));
+        assert!(matches!(
+            lrs.put_document(&profile, "preferences", b"{}".to_vec(), "application/json", &none).await,
+            Err(LrsError::ConcurrencyRequired)
+        ));
+        let current = Preconditions { if_match: Some(etag), if_none_match: None };
+        lrs.put_document(&profile, "preferences", br#"{"lang":"en"}"#.to_vec(), "application/json", &current).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http_resources() {
+        let lrs = lrs().await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/xapi", listener.local_addr().unwrap());
+        let app = Router::new().nest("/xapi", crate::routes::xapi::create_routes(lrs, LrsCredentials::new("local", "local")));
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        // Requests must carry the LRS's credentials and declare the xAPI version
+        let http = reqwest::Client::new();
+        let response = http.get(format!("{}/statements", endpoint))
+            .header("X-Experience-API-Version", "1.0.3")
+            .basic_auth("local", Some("wrong"))
+            .send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = http.get(format!("{}/statements", endpoint))
+            .basic_auth("local", Some("local"))
+            .send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        let about: Value = reqwest::get(format!("{}/about", endpoint)).await.unwrap().json().await.unwrap();
+        assert_eq!(about["version"][0], "1.0.3");
+
+        // Statements from the quiz engine's own client round trip
+        let client = XApiClient::new(XApiClientConfig {
+            endpoint: endpoint.clone(),
+            username: "local".to_string(),
+            password: "local".to_string(),
+            version: "1.0.3".to_string(),
+        }).unwrap();
+        let mut started = started("quiz-1");
+        started.id = Some(Uuid::new_v4().to_string());
+        assert_eq!(client.post_statements(std::slice::from_ref(&started)).await.unwrap(), Delivery::Stored);
+        assert_eq!(client.put_statement(&started).await.unwrap(), Delivery::Stored);
+
+        let params = HashMap::from([("activity".to_string(), "http://example.com/quizzes/quiz-1".to_string())]);
+        let statements = client.query_statements(&params).await.unwrap();
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].id, started.id);
+
+        let fetched = client.get_statement(started.id.as_deref().unwrap()).await.unwrap();
+        assert_eq!(fetched.verb.id, started.verb.id);
+
+        // cmi5 content keeps its state in the State resource
+        let cmi5 = Cmi5Client::new(&endpoint, Some("local:local")).unwrap();
+        let state = json!({ "location": "page-3" });
+        cmi5.set_state(QUIZ, "learner@example.com", "suspend", &state, None).await.unwrap();
+        assert_eq!(cmi5.get_state(QUIZ, "learner@example.com", "suspend", None).await.unwrap(), state);
+    }
+}
@@ -0,0 +1,81 @@
+use axum::{
+    extract::{Form, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::quiz::lti::{LtiError, LtiService};
+use crate::quiz::lti::lti13::OidcLoginRequest;
+
+/// Form posted by the platform to the tool's launch URL
+#[derive(Debug, Deserialize)]
+pub struct LaunchForm {
+    pub id_token: String,
+    pub state: String,
+}
+
+/// LTI 1.3 tool endpoints: OIDC login initiation, launch and public JWKS
+///
+/// The router carries its own state, so it can be nested into a router
+/// with any state.
+pub fn create_routes<S: Clone + Send + Sync + 'static>(lti_service: Arc<Mutex<LtiService>>) -> Router<S> {
+    Router::new()
+        .route("/login", get(login_get).post(login_post))
+        .route("/launch", axum::routing::post(launch))
+        .route("/jwks", get(jwks))
+        .with_state(lti_service)
+}
+
+// Platforms may initiate the login with either GET or POST
+async fn login_get(
+    State(lti_service): State<Arc<Mutex<LtiService>>>,
+    Query(request): Query<OidcLoginRequest>,
+) -> Response {
+    login(lti_service, request).await
+}
+
+async fn login_post(
+    State(lti_service): State<Arc<Mutex<LtiService>>>,
+    Form(request): Form<OidcLoginRequest>,
+) -> Response {
+    login(lti_service, request).await
+}
+
+async fn login(lti_service: Arc<Mutex<LtiService>>, request: OidcLoginRequest) -> Response {
+    let lti_service = lti_service.lock().await;
+    match lti_service.handle_oidc_login(&request).await {
+        Ok(redirect_url) => Redirect::to(&redirect_url).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn launch(
+    State(lti_service): State<Arc<Mutex<LtiService>>>,
+    Form(form): Form<LaunchForm>,
+) -> Response {
+    let lti_service = lti_service.lock().await;
+    match lti_service.validate_lti_1_3_launch(&form.id_token, &form.state).await {
+        Ok(launch_request) => Json(launch_request).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn jwks(State(lti_service): State<Arc<Mutex<LtiService>>>) -> impl IntoResponse {
+    let lti_service = lti_service.lock().await;
+    Json(lti_service.tool_jwks())
+}
+
+fn error_response(error: LtiError) -> Response {
+    let status = match error {
+        LtiError::Storage(_) | LtiError::Jwks(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        LtiError::PlatformNotFound(_) => StatusCode::NOT_FOUND,
+        _ => StatusCode::UNAUTHORIZED,
+    };
+
+    (status, Json(serde_json::json!({ "error": error.to_string() }))).into_response()
+}
@@ -2,6 +2,8 @@ mod categories;
 mod topics;
 mod posts;
 mod users;
+pub mod lti;
+pub mod xapi;
 
 pub use categories::{list_categories, get_category, create_category, update_category, delete_category};
 pub use topics::{list_topics, get_topic, create_topic, update_topic, list_topic_posts};
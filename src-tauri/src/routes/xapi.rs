@@ -0,0 +1,267 @@
+use axum::{
+    body::Bytes,
+    extract::{OriginalUri, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, MethodRouter},
+    Json, Router,
+};
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::quiz::xapi::lrs::{
+    parse_timestamp, DocumentKind, DocumentScope, LocalLrs, LrsCredentials, LrsError, Preconditions,
+    StatementQuery, VERSION_HEADER, XAPI_VERSION,
+};
+
+type Params = Query<HashMap<String, String>>;
+
+/// xAPI resources of the embedded LRS: statements, state, activity and agent
+/// profiles, and the version information at `/about`
+pub fn create_routes(lrs: LocalLrs, credentials: LrsCredentials) -> Router {
+    Router::new()
+        .route("/statements", get(get_statements).put(put_statement).post(post_statements))
+        .route("/activities/state", document_routes(DocumentKind::State))
+        .route("/activities/profile", document_routes(DocumentKind::ActivityProfile))
+        .route("/agents/profile", document_routes(DocumentKind::AgentProfile))
+        .route_layer(middleware::from_fn(require_version))
+        .route_layer(middleware::from_fn_with_state(credentials, require_credentials))
+        .route("/about", get(about))
+        .with_state(lrs)
+}
+
+// Every resource except /about requires the LRS's credentials
+async fn require_credentials(State(credentials): State<LrsCredentials>, request: Request, next: Next) -> Response {
+    let authorized = request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .map_or(false, |authorization| credentials.authorize(authorization));
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"xAPI\"")],
+            Json(json!({ "error": "missing or invalid credentials" })),
+        ).into_response();
+    }
+
+    next.run(request).await
+}
+
+// Every resource except /about requires a supported version header
+async fn require_version(request: Request, next: Next) -> Response {
+    let supported = request.headers()
+        .get(VERSION_HEADER)
+        .and_then(|version| version.to_str().ok())
+        .map_or(false, |version| version.starts_with("1.0"));
+    if !supported {
+        return error_response(LrsError::InvalidParameter(format!("missing or unsupported {} header", VERSION_HEADER)));
+    }
+
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(VERSION_HEADER, HeaderValue::from_static(XAPI_VERSION));
+    response
+}
+
+async fn about() -> impl IntoResponse {
+    Json(json!({ "version": [XAPI_VERSION] }))
+}
+
+async fn get_statements(State(lrs): State<LocalLrs>, OriginalUri(uri): OriginalUri, Query(params): Params) -> Response {
+    let single = match (params.get("statementId"), params.get("voidedStatementId")) {
+        (Some(id), None) => Some((id, false)),
+        (None, Some(id)) => Some((id, true)),
+        (None, None) => None,
+        (Some(_), Some(_)) => {
+            return error_response(LrsError::InvalidParameter("statementId and voidedStatementId are exclusive".to_string()));
+        }
+    };
+
+    let consistent_through = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+    let result = match single {
+        Some((id, voided)) => {
+            if params.keys().any(|name| !matches!(name.as_str(), "statementId" | "voidedStatementId" | "format" | "attachments")) {
+                return error_response(LrsError::InvalidParameter("only format and attachments may be combined with a statement ID".to_string()));
+            }
+
+            lrs.statement(id, voided).await.map(|statement| match statement {
+                Some(statement) => Json(statement).into_response(),
+                None => StatusCode::NOT_FOUND.into_response(),
+            })
+        }
+        None => match StatementQuery::from_params(&params) {
+            Ok(query) => lrs.query_statements(&query).await.map(|page| {
+                let more = page.more
+                    .map(|more| format!("{}?{}", uri.path(), url::form_urlencoded::Serializer::new(String::new()).extend_pairs(more.to_params()).finish()))
+                    .unwrap_or_default();
+                Json(json!({ "statements": page.statements, "more": more })).into_response()
+            }),
+            Err(e) => Err(e),
+        },
+    };
+
+    match result {
+        Ok(mut response) => {
+            if let Ok(value) = HeaderValue::from_str(&consistent_through) {
+                response.headers_mut().insert("X-Experience-API-Consistent-Through", value);
+            }
+            response
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+async fn put_statement(State(lrs): State<LocalLrs>, Query(params): Params, Json(statement): Json<Value>) -> Response {
+    let Some(statement_id) = params.get("statementId") else {
+        return error_response(LrsError::InvalidParameter("statementId is required".to_string()));
+    };
+
+    match lrs.put_statement(statement_id, statement).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn post_statements(State(lrs): State<LocalLrs>, Json(body): Json<Value>) -> Response {
+    let statements = match body {
+        Value::Array(statements) => statements,
+        statement => vec![statement],
+    };
+
+    match lrs.store_statements(statements).await {
+        Ok(ids) => Json(ids).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+fn document_routes(kind: DocumentKind) -> MethodRouter<LocalLrs> {
+    get(move |State(lrs): State<LocalLrs>, Query(params): Params| get_document(lrs, kind, params))
+        .put(move |State(lrs): State<LocalLrs>, Query(params): Params, headers: HeaderMap, body: Bytes| {
+            write_document(lrs, kind, params, headers, body, false)
+        })
+        .post(move |State(lrs): State<LocalLrs>, Query(params): Params, headers: HeaderMap, body: Bytes| {
+            write_document(lrs, kind, params, headers, body, true)
+        })
+        .delete(move |State(lrs): State<LocalLrs>, Query(params): Params, headers: HeaderMap| {
+            delete_document(lrs, kind, params, headers)
+        })
+}
+
+/// Scope and document ID addressed by the query parameters of a document request
+fn document_params(kind: DocumentKind, params: &HashMap<String, String>) -> Result<(DocumentScope, Option<String>), LrsError> {
+    let required = |name: &str| {
+        params.get(name)
+            .map(String::as_str)
+            .ok_or_else(|| LrsError::InvalidParameter(format!("{} is required", name)))
+    };
+
+    match kind {
+        DocumentKind::State => Ok((
+            DocumentScope::state(required("activityId")?, required("agent")?, params.get("registration").map(String::as_str))?,
+            params.get("stateId").cloned(),
+        )),
+        DocumentKind::ActivityProfile => Ok((
+            DocumentScope::activity_profile(required("activityId")?),
+            params.get("profileId").cloned(),
+        )),
+        DocumentKind::AgentProfile => Ok((
+            DocumentScope::agent_profile(required("agent")?)?,
+            params.get("profileId").cloned(),
+        )),
+    }
+}
+
+async fn get_document(lrs: LocalLrs, kind: DocumentKind, params: HashMap<String, String>) -> Response {
+    find_document(&lrs, kind, &params).await.unwrap_or_else(error_response)
+}
+
+async fn find_document(lrs: &LocalLrs, kind: DocumentKind, params: &HashMap<String, String>) -> Result<Response, LrsError> {
+    let (scope, document_id) = document_params(kind, params)?;
+
+    let Some(document_id) = document_id else {
+        let since = params.get("since").map(|since| parse_timestamp("since", since)).transpose()?;
+        return Ok(Json(lrs.document_ids(&scope, since).await?).into_response());
+    };
+
+    Ok(match lrs.document(&scope, &document_id).await? {
+        Some(document) => (
+            [
+                (header::CONTENT_TYPE, document.content_type),
+                (header::ETAG, document.etag),
+                (header::LAST_MODIFIED, document.updated_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()),
+            ],
+            document.content,
+        ).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    })
+}
+
+async fn write_document(
+    lrs: LocalLrs,
+    kind: DocumentKind,
+    params: HashMap<String, String>,
+    headers: HeaderMap,
+    body: Bytes,
+    merge: bool,
+) -> Response {
+    match store_document(&lrs, kind, &params, &headers, body, merge).await {
+        Ok(etag) => (StatusCode::NO_CONTENT, [(header::ETAG, etag)]).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn store_document(
+    lrs: &LocalLrs,
+    kind: DocumentKind,
+    params: &HashMap<String, String>,
+    headers: &HeaderMap,
+    body: Bytes,
+    merge: bool,
+) -> Result<String, LrsError> {
+    let (scope, document_id) = document_params(kind, params)?;
+    let document_id = document_id
+        .ok_or_else(|| LrsError::InvalidParameter("a document ID is required".to_string()))?;
+    let content_type = headers.get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or("application/octet-stream");
+
+    if merge {
+        lrs.post_document(&scope, &document_id, body.to_vec(), content_type, &preconditions(headers)).await
+    } else {
+        lrs.put_document(&scope, &document_id, body.to_vec(), content_type, &preconditions(headers)).await
+    }
+}
+
+async fn delete_document(lrs: LocalLrs, kind: DocumentKind, params: HashMap<String, String>, headers: HeaderMap) -> Response {
+    let result = match document_params(kind, &params) {
+        Ok((scope, Some(document_id))) => lrs.delete_document(&scope, &document_id, &preconditions(&headers)).await,
+        Ok((scope, None)) => lrs.delete_documents(&scope).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+fn preconditions(headers: &HeaderMap) -> Preconditions {
+    let value = |name: header::HeaderName| headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+
+    Preconditions {
+        if_match: value(header::IF_MATCH),
+        if_none_match: value(header::IF_NONE_MATCH),
+    }
+}
+
+fn error_response(error: LrsError) -> Response {
+    let status = match error {
+        LrsError::InvalidStatement(_) | LrsError::InvalidParameter(_) | LrsError::InvalidDocument(_) => StatusCode::BAD_REQUEST,
+        LrsError::Conflict(_) | LrsError::ConcurrencyRequired => StatusCode::CONFLICT,
+        LrsError::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+        LrsError::Storage(_) | LrsError::Serialization(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    (status, Json(json!({ "error": error.to_string() }))).into_response()
+}
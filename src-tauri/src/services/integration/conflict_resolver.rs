@@ -3,6 +3,7 @@ use crate::models::forum::post::Post;
 use crate::error::Error;
 use chrono::{DateTime, Utc};
 use log::{info, warn};
+use std::cmp::Ordering;
 use crate::sync::version_vector::{VersionVector, CausalRelation};
 
 /// Conflict resolution strategies when the same entity is modified in both systems
@@ -105,11 +106,13 @@ impl ConflictResolver {
     
     /// Resolve conflicts between a Canvas topic and a Discourse topic
     pub fn resolve_topic_conflict(&self, canvas_topic: &Topic, discourse_topic: &Topic) -> Result<Topic, Error> {
+        let canvas_is_later = self.canvas_is_later(canvas_topic.updated_at, discourse_topic.updated_at);
+
         match self.strategy {
             ConflictStrategy::PreferCanvas => Ok(canvas_topic.clone()),
             ConflictStrategy::PreferDiscourse => Ok(discourse_topic.clone()),
             ConflictStrategy::PreferMostRecent => {
-                if canvas_topic.updated_at > discourse_topic.updated_at {
+                if canvas_is_later {
                     info!("Using Canvas topic (more recent): {}", canvas_topic.id);
                     Ok(canvas_topic.clone())
                 } else {
@@ -128,16 +131,10 @@ impl ConflictResolver {
                 // Smart merge logic
                 let mut result = base;
                 
-                // Keep the most recent content if it was updated
-                if canvas_topic.updated_at > discourse_topic.updated_at && 
-                   canvas_topic.updated_at > result.updated_at {
-                    result.content = canvas_topic.content.clone();
-                    result.title = canvas_topic.title.clone();
-                } else if discourse_topic.updated_at > canvas_topic.updated_at && 
-                          discourse_topic.updated_at > result.updated_at {
-                    result.content = discourse_topic.content.clone();
-                    result.title = discourse_topic.title.clone();
-                }
+                // Keep the most recent content
+                let latest = if canvas_is_later { canvas_topic } else { discourse_topic };
+                result.content = latest.content.clone();
+                result.title = latest.title.clone();
                 
                 // Combine tags from both sources
                 let mut combined_tags = result.tags.clone();
@@ -169,7 +166,7 @@ impl ConflictResolver {
                 result.tags = combined_tags;
                 
                 // Use the most recent views/post counts
-                if discourse_topic.updated_at > canvas_topic.updated_at {
+                if !canvas_is_later {
                     result.views = discourse_topic.views;
                     result.post_count = discourse_topic.post_count;
                 }
@@ -184,11 +181,13 @@ impl ConflictResolver {
     
     /// Resolve conflicts between a Canvas post and a Discourse post
     pub fn resolve_post_conflict(&self, canvas_post: &Post, discourse_post: &Post) -> Result<Post, Error> {
+        let canvas_is_later = self.canvas_is_later(canvas_post.updated_at, discourse_post.updated_at);
+
         match self.strategy {
             ConflictStrategy::PreferCanvas => Ok(canvas_post.clone()),
             ConflictStrategy::PreferDiscourse => Ok(discourse_post.clone()),
             ConflictStrategy::PreferMostRecent => {
-                if canvas_post.updated_at > discourse_post.updated_at {
+                if canvas_is_later {
                     info!("Using Canvas post (more recent): {}", canvas_post.id);
                     Ok(canvas_post.clone())
                 } else {
@@ -207,14 +206,9 @@ impl ConflictResolver {
                 // Smart merge logic
                 let mut result = base;
                 
-                // Keep the most recent content if it was updated
-                if canvas_post.updated_at > discourse_post.updated_at && 
-                   canvas_post.updated_at > result.updated_at {
-                    result.content = canvas_post.content.clone();
-                } else if discourse_post.updated_at > canvas_post.updated_at && 
-                          discourse_post.updated_at > result.updated_at {
-                    result.content = discourse_post.content.clone();
-                }
+                // Keep the most recent content
+                let latest = if canvas_is_later { canvas_post } else { discourse_post };
+                result.content = latest.content.clone();
                 
                 // Keep Discourse-specific data
                 if let Some(html) = &discourse_post.html_content {
@@ -233,6 +227,22 @@ impl ConflictResolver {
         }
     }
     
+    /// Whether the Canvas update comes after the Discourse one, by the update
+    /// times the two systems report. Neither system stamps its updates with a
+    /// logical clock, so skewed server clocks can misorder them. Updates at
+    /// the same time go to the side the strategy prefers, if any, and
+    /// otherwise to Discourse.
+    fn canvas_is_later(&self, canvas_updated_at: DateTime<Utc>, discourse_updated_at: DateTime<Utc>) -> bool {
+        match canvas_updated_at.cmp(&discourse_updated_at) {
+            Ordering::Greater => true,
+            Ordering::Less => false,
+            Ordering::Equal => matches!(
+                self.strategy,
+                ConflictStrategy::PreferCanvas | ConflictStrategy::MergePreferCanvas
+            ),
+        }
+    }
+
     /// Determine whether a sync is needed based on timestamps
     pub fn needs_sync(&self, local_updated_at: DateTime<Utc>, remote_updated_at: DateTime<Utc>, last_sync: Option<DateTime<Utc>>) -> bool {
         // If never synced, then yes
@@ -328,4 +338,28 @@ mod tests {
         // Should take the higher like count
         assert_eq!(result.likes, 10);
     }
+    
+    #[test]
+    fn test_updates_at_the_same_time() {
+        let updated_at = Utc::now();
+        let canvas_topic = Topic {
+            title: "Canvas Topic".to_string(),
+            updated_at,
+            ..Default::default()
+        };
+        let discourse_topic = Topic {
+            title: "Discourse Topic".to_string(),
+            updated_at,
+            ..Default::default()
+        };
+        
+        // The preferred side wins a tie, and Discourse otherwise
+        let resolver = ConflictResolver::new(ConflictStrategy::MergePreferCanvas);
+        let result = resolver.resolve_topic_conflict(&canvas_topic, &discourse_topic).unwrap();
+        assert_eq!(result.title, "Canvas Topic");
+        
+        let resolver = ConflictResolver::new(ConflictStrategy::PreferMostRecent);
+        let result = resolver.resolve_topic_conflict(&canvas_topic, &discourse_topic).unwrap();
+        assert_eq!(result.title, "Discourse Topic");
+    }
 }
@@ -4,10 +4,12 @@ use crate::models::forum::mapping::{TopicMapping, PostMapping, SyncStatus};
 use crate::db::DB;
 use crate::error::Error;
 use uuid::Uuid;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use log::warn;
 use std::time::Instant;
 use serde_json::Value as JsonValue;
 use crate::api::integration_commands::ConflictResolutionStrategy;
+use crate::services::notification::notification_service::NotificationService;
 
 use super::canvas_integration::CanvasIntegration;
 use super::discourse_integration::DiscourseIntegration;
@@ -313,6 +315,25 @@ where
         Ok(())
     }
 
+    /// Record a conflict left for review, which `get_sync_conflicts` lists
+    /// from the mapping's status: tell the user and keep it in the history
+    async fn record_conflict(&self, entity_type: &str, entity_id: &str, entity_name: &str) -> Result<(), Error> {
+        warn!("{} {} was changed in both Canvas and Discourse, leaving it for review", entity_type, entity_id);
+
+        NotificationService::new(self.db.clone())
+            .create_conflict_notification(entity_type, entity_id, entity_name)
+            .await?;
+
+        self.record_sync_history(
+            "conflict",
+            Some(entity_id),
+            Some(entity_type),
+            false,
+            Some("Changed in both Canvas and Discourse since the last sync"),
+            0
+        ).await
+    }
+
     // Modify your sync methods to record history, for example:
     pub async fn sync_topic(&self, topic_id: &str) -> Result<(), Error> {
         let start_time = Instant::now();
@@ -869,13 +890,27 @@ where
             let canvas_topic = self.canvas.sync_topic(canvas_id).await?;
             let discourse_topic = self.discourse.sync_topic(discourse_id).await?;
 
-            // 2. Resolve any conflicts
+            // 2. Edits made in both systems since the last sync cannot be
+            // resolved without dropping one, so they wait for review
+            let mut mapping = TopicMapping::find_by_local_id(&self.db, local_topic_id).await?;
+            if mapping.sync_status == SyncStatus::Conflict {
+                return Ok(local_topic);
+            }
+            let differ = canvas_topic.title != discourse_topic.title || canvas_topic.content != discourse_topic.content;
+            if differ && changed_in_both(canvas_topic.updated_at, discourse_topic.updated_at, mapping.last_sync_at) {
+                mapping.sync_status = SyncStatus::Conflict;
+                mapping.update(&self.db).await?;
+                self.record_conflict("Topic", &local_topic_id.to_string(), &local_topic.title).await?;
+                return Ok(local_topic);
+            }
+
+            // 3. Resolve any conflicts
             result_topic = self.conflict_resolver.resolve_topic_conflict(&canvas_topic, &discourse_topic)?;
 
-            // 3. Update local storage
+            // 4. Update local storage
             result_topic.update(&self.db).await?;
 
-            // 4. Push resolved version back to both systems to ensure consistency
+            // 5. Push resolved version back to both systems to ensure consistency
             if canvas_topic.updated_at != result_topic.updated_at {
                 self.canvas.push_topic_to_canvas(&result_topic).await?;
             }
@@ -884,14 +919,13 @@ where
                 self.discourse.push_topic_to_discourse(&result_topic).await?;
             }
 
-            // 5. Update sync status
-            let mapping = TopicMapping::find_by_local_id(&self.db, local_topic_id).await?;
-            let mut updated_mapping = mapping.clone();
+            // 6. Update sync status
+            let mut updated_mapping = mapping;
             updated_mapping.last_sync_at = Utc::now();
             updated_mapping.sync_status = SyncStatus::Synced;
             updated_mapping.update(&self.db).await?;
 
-            // 6. Now sync all posts for this topic with the same approach
+            // 7. Now sync all posts for this topic with the same approach
             let posts = Post::find_by_topic_id(&self.db, local_topic_id).await?;
 
             for post in posts {
@@ -942,13 +976,28 @@ where
             let canvas_post = self.canvas.sync_post(canvas_id).await?;
             let discourse_post = self.discourse.sync_post(discourse_id).await?;
 
-            // 2. Resolve any conflicts
+            // 2. Edits made in both systems since the last sync cannot be
+            // resolved without dropping one, so they wait for review
+            let mut mapping = PostMapping::find_by_local_id(&self.db, local_post_id).await?;
+            if mapping.sync_status == SyncStatus::Conflict {
+                return Ok(local_post);
+            }
+            let differ = canvas_post.content != discourse_post.content;
+            if differ && changed_in_both(canvas_post.updated_at, discourse_post.updated_at, mapping.last_sync_at) {
+                mapping.sync_status = SyncStatus::Conflict;
+                mapping.update(&self.db).await?;
+                let excerpt: String = local_post.content.chars().take(50).collect();
+                self.record_conflict("Post", &local_post_id.to_string(), &excerpt).await?;
+                return Ok(local_post);
+            }
+
+            // 3. Resolve any conflicts
             result_post = self.conflict_resolver.resolve_post_conflict(&canvas_post, &discourse_post)?;
 
-            // 3. Update local storage
+            // 4. Update local storage
             result_post.update(&self.db).await?;
 
-            // 4. Push resolved version back to both systems to ensure consistency
+            // 5. Push resolved version back to both systems to ensure consistency
             if canvas_post.updated_at != result_post.updated_at {
                 self.canvas.push_post_to_canvas(&result_post).await?;
             }
@@ -957,9 +1006,8 @@ where
                 self.discourse.push_post_to_discourse(&result_post).await?;
             }
 
-            // 5. Update sync status
-            let mapping = PostMapping::find_by_local_id(&self.db, local_post_id).await?;
-            let mut updated_mapping = mapping.clone();
+            // 6. Update sync status
+            let mut updated_mapping = mapping;
             updated_mapping.last_sync_at = Utc::now();
             updated_mapping.sync_status = SyncStatus::Synced;
             updated_mapping.update(&self.db).await?;
@@ -983,4 +1031,9 @@ where
 
         Ok(result_post)
     }
-}
\ No newline at end of file
+}
+
+/// Whether both systems changed an entity since it was last synced
+fn changed_in_both(canvas_updated_at: DateTime<Utc>, discourse_updated_at: DateTime<Utc>, last_sync_at: DateTime<Utc>) -> bool {
+    canvas_updated_at > last_sync_at && discourse_updated_at > last_sync_at
+}
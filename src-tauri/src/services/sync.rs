@@ -1,3 +1,5 @@
+pub mod maintenance_service;
+
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time;
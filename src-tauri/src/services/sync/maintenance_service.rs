@@ -1,4 +1,6 @@
 use crate::sync::sync_queue::SyncQueue;
+use crate::sync::compaction::CompactionReport;
+use crate::sync::engine::SyncEngine;
 use crate::error::Error;
 use sqlx::Pool;
 use sqlx::Sqlite;
@@ -6,6 +8,7 @@ use std::sync::Arc;
 use tokio::time::{interval, Duration};
 use log::{info, warn, error};
 use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::RwLock;
 
 /// Configuration for sync cleanup operations
 #[derive(Debug, Clone)]
@@ -20,6 +23,8 @@ pub struct SyncCleanupConfig {
     pub max_batch_size: u32,
     /// Whether to enable detailed logging
     pub enable_detailed_logging: bool,
+    /// Maximum free database pages returned to the file system per run
+    pub vacuum_pages_per_run: u32,
 }
 
 impl Default for SyncCleanupConfig {
@@ -30,6 +35,7 @@ impl Default for SyncCleanupConfig {
             cleanup_interval_hours: 24,    // Run cleanup once a day
             max_batch_size: 1000,          // Clean up to 1000 items at a time
             enable_detailed_logging: true, // Enable detailed logging
+            vacuum_pages_per_run: 2048,    // Reclaim up to 2048 pages (8 MB with 4 KB pages) at a time
         }
     }
 }
@@ -40,6 +46,9 @@ pub struct SyncMaintenanceService {
     db_pool: Pool<Sqlite>,
     config: SyncCleanupConfig,
     running: Arc<AtomicBool>,
+    /// Engine whose operation log is compacted, if any
+    sync_engine: Option<Arc<SyncEngine>>,
+    last_compaction: Arc<RwLock<Option<CompactionReport>>>,
 }
 
 impl SyncMaintenanceService {
@@ -50,8 +59,16 @@ impl SyncMaintenanceService {
             db_pool,
             config,
             running: Arc::new(AtomicBool::new(false)),
+            sync_engine: None,
+            last_compaction: Arc::new(RwLock::new(None)),
         }
     }
+
+    /// Also compact the operation log of a sync engine on each run
+    pub fn with_sync_engine(mut self, sync_engine: Arc<SyncEngine>) -> Self {
+        self.sync_engine = Some(sync_engine);
+        self
+    }
     
     /// Start the maintenance service
     pub async fn start(&self) -> Result<(), Error> {
@@ -62,6 +79,8 @@ impl SyncMaintenanceService {
         let sync_queue = Arc::clone(&self.sync_queue);
         let config = self.config.clone();
         let running = Arc::clone(&self.running);
+        let sync_engine = self.sync_engine.clone();
+        let last_compaction = Arc::clone(&self.last_compaction);
         
         tokio::spawn(async move {
             info!("Sync maintenance service started");
@@ -101,6 +120,21 @@ impl SyncMaintenanceService {
                     }
                 }
                 
+                // Compact the sync operation log
+                if let Some(sync_engine) = &sync_engine {
+                    match sync_engine.compact(config.vacuum_pages_per_run as i64).await {
+                        Ok(report) => {
+                            if config.enable_detailed_logging || report.bytes_reclaimed > 0 {
+                                info!("Compacted sync operation log, reclaiming {} bytes", report.bytes_reclaimed);
+                            }
+                            *last_compaction.write().await = Some(report);
+                        },
+                        Err(e) => {
+                            error!("Error compacting sync operation log: {}", e);
+                        }
+                    }
+                }
+                
                 // Add other maintenance tasks here as needed
                 // For example: archive sync history, consistency checks, etc.
             }
@@ -133,6 +167,28 @@ impl SyncMaintenanceService {
         Ok((completed_count, failed_count))
     }
     
+    /// Run an immediate compaction of the sync operation log, if there is
+    /// an engine to compact
+    pub async fn run_compaction(&self) -> Result<Option<CompactionReport>, Error> {
+        let Some(sync_engine) = &self.sync_engine else {
+            return Ok(None);
+        };
+        
+        info!("Running immediate sync operation log compaction");
+        let report = sync_engine
+            .compact(self.config.vacuum_pages_per_run as i64)
+            .await
+            .map_err(|e| Error::Internal(e.to_string()))?;
+            
+        *self.last_compaction.write().await = Some(report.clone());
+        Ok(Some(report))
+    }
+    
+    /// What the last compaction did, including the space it reclaimed
+    pub async fn last_compaction_report(&self) -> Option<CompactionReport> {
+        self.last_compaction.read().await.clone()
+    }
+    
     /// Run consistency checks on sync data
     pub async fn run_consistency_checks(&self) -> Result<(), Error> {
         info!("Running sync data consistency checks");
@@ -281,6 +337,7 @@ mod tests {
             cleanup_interval_hours: 24,
             max_batch_size: 100,
             enable_detailed_logging: true,
+            vacuum_pages_per_run: 100,
         };
         
         let maintenance_service = SyncMaintenanceService::new(
@@ -307,4 +364,82 @@ mod tests {
         
         Ok(())
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_sync_log_compaction() -> Result<(), Error> {
+        use crate::sync::operations::OperationType;
+        use crate::sync::version_vector::VersionVector;
+        use sqlx::sqlite::SqliteConnectOptions;
+        
+        let path = std::env::temp_dir().join(format!("sync-compaction-{}.db", Uuid::new_v4()));
+        let pool = SqlitePoolOptions::new()
+            .connect_with(SqliteConnectOptions::new().filename(&path).create_if_missing(true))
+            .await?;
+        sqlx::query(include_str!("../../../migrations/20261017000000_rebuild_sync_operations.sql"))
+            .execute(&pool)
+            .await?;
+        let engine = Arc::new(SyncEngine::open(pool.clone()).await.map_err(|e| Error::Internal(e.to_string()))?);
+        engine.enable_incremental_vacuum().await.map_err(|e| Error::Internal(e.to_string()))?;
+        
+        // Long posts edited many times, and a topic created then deleted
+        let body = "lorem ipsum ".repeat(500);
+        for i in 0..20 {
+            let post_id = format!("post-{}", i);
+            engine.queue_operation(1, OperationType::Create, "post", Some(&post_id), serde_json::json!({ "title": post_id, "body": body }))
+                .await
+                .map_err(|e| Error::Internal(e.to_string()))?;
+            for revision in 0..4 {
+                engine.queue_operation(1, OperationType::Update, "post", Some(&post_id), serde_json::json!({ "body": format!("{}{}", body, revision) }))
+                    .await
+                    .map_err(|e| Error::Internal(e.to_string()))?;
+            }
+        }
+        engine.queue_operation(1, OperationType::Create, "topic", Some("topic-0"), serde_json::json!({ "title": "Welcome" }))
+            .await
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        engine.queue_operation(1, OperationType::Delete, "topic", Some("topic-0"), serde_json::Value::Null)
+            .await
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        let known = engine.known_sequences(1).await.map_err(|e| Error::Internal(e.to_string()))?;
+        
+        let maintenance_service = SyncMaintenanceService::new(
+            Arc::new(SyncQueue::new(pool.clone())),
+            pool.clone(),
+            SyncCleanupConfig::default()
+        ).with_sync_engine(Arc::clone(&engine));
+        
+        // Nothing is compacted before another device holds it
+        let report = maintenance_service.run_compaction().await?.unwrap();
+        assert_eq!((report.operations_folded, report.tombstones_expired), (0, 0));
+        
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        engine.save_device_progress(1, "laptop-b", &known, now)
+            .await
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        let report = maintenance_service.run_compaction().await?.unwrap();
+        assert_eq!((report.operations_folded, report.snapshots_written), (100, 20));
+        assert_eq!((report.tombstones_expired, report.operations_expired), (1, 2));
+        assert!(report.bytes_reclaimed > 0);
+        assert_eq!(maintenance_service.last_compaction_report().await, Some(report));
+        
+        // Each post is left with its latest state, and the known sequences stay
+        let operations = engine.operations_missing_from(1, &VersionVector::new(), usize::MAX)
+            .await
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        assert_eq!(operations.len(), 20);
+        assert!(operations.iter().all(|operation| operation.payload["body"] == format!("{}3", body)));
+        assert_eq!(engine.known_sequences(1).await.map_err(|e| Error::Internal(e.to_string()))?.to_hashmap(), known.to_hashmap());
+        
+        // The device keeps its ID and sequences carry on after a restart
+        let restarted = SyncEngine::open(pool.clone()).await.map_err(|e| Error::Internal(e.to_string()))?;
+        assert_eq!(restarted.device_id(), engine.device_id());
+        let operation = restarted.queue_operation(1, OperationType::Create, "topic", Some("topic-1"), serde_json::json!({ "title": "Office hours" }))
+            .await
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        assert_eq!(operation.sequence(), known.get(engine.device_id()) + 1);
+        
+        pool.close().await;
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+-- Quiz question bank schema
+
+-- Reusable question banks; questions are stored as JSON
+CREATE TABLE IF NOT EXISTS question_banks (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    description TEXT,
+    author_id TEXT,
+    visibility TEXT NOT NULL,
+    tags TEXT NOT NULL,
+    questions TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Random draws from a bank into a quiz
+CREATE TABLE IF NOT EXISTS quiz_question_groups (
+    id TEXT PRIMARY KEY,
+    quiz_id TEXT NOT NULL,
+    bank_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    draw_count INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    FOREIGN KEY (bank_id) REFERENCES question_banks (id) ON DELETE CASCADE
+);
+
+-- The form generated for each attempt
+CREATE TABLE IF NOT EXISTS quiz_attempt_forms (
+    attempt_id TEXT PRIMARY KEY,
+    form TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_question_banks_author_id ON question_banks (author_id);
+CREATE INDEX IF NOT EXISTS idx_quiz_question_groups_quiz_id ON quiz_question_groups (quiz_id);
@@ -0,0 +1,39 @@
+-- Timed exam schema
+-- The timer and answers of an attempt are kept with its quiz session
+
+-- Timed attempts, for finding the ones still running after a restart
+CREATE TABLE IF NOT EXISTS exam_attempts (
+    session_id TEXT PRIMARY KEY,
+    quiz_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    base_limit_ms INTEGER NOT NULL, -- Time limit before accommodations
+    status TEXT NOT NULL,           -- in_progress, submitted or expired
+    started_at TEXT NOT NULL,
+    finished_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_exam_attempts_status ON exam_attempts(status, user_id);
+
+-- Audit trail of each attempt
+CREATE TABLE IF NOT EXISTS exam_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    elapsed_ms INTEGER NOT NULL,    -- Time used when the event happened
+    detail TEXT,
+    occurred_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_exam_events_session ON exam_events(session_id, id);
+
+-- Extra time granted to students, for one quiz or, with an empty quiz ID, every quiz
+CREATE TABLE IF NOT EXISTS exam_accommodations (
+    user_id TEXT NOT NULL,
+    quiz_id TEXT NOT NULL DEFAULT '',
+    time_multiplier REAL NOT NULL DEFAULT 1.0,
+    extra_minutes INTEGER NOT NULL DEFAULT 0,
+    granted_by TEXT,
+    reason TEXT,
+    granted_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, quiz_id)
+);
@@ -0,0 +1,55 @@
+-- Embedded LRS schema
+
+-- Statements as accepted, never updated except for the `voided` flag. `seq`
+-- orders statements by the time they were stored and serves as paging cursor;
+-- the other columns are extracted from the statement for filtering.
+CREATE TABLE IF NOT EXISTS lrs_statements (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    id TEXT NOT NULL UNIQUE,
+    statement TEXT NOT NULL,
+    verb_id TEXT NOT NULL,
+    registration TEXT,
+    statement_ref TEXT,
+    voided INTEGER NOT NULL DEFAULT 0,
+    stored TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_lrs_statements_verb ON lrs_statements(verb_id);
+CREATE INDEX IF NOT EXISTS idx_lrs_statements_ref ON lrs_statements(statement_ref);
+
+-- Agents a statement is about, by inverse functional identifier. `related` is
+-- 0 for the actor and object and 1 for authority, instructor, team and
+-- sub-statement agents, which only match with `related_agents`.
+CREATE TABLE IF NOT EXISTS lrs_statement_agents (
+    seq INTEGER NOT NULL REFERENCES lrs_statements(seq),
+    ifi TEXT NOT NULL,
+    related INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_lrs_statement_agents ON lrs_statement_agents(ifi, seq);
+
+-- Activities a statement is about. `related` is 0 for the object and 1 for
+-- context activities and sub-statement activities.
+CREATE TABLE IF NOT EXISTS lrs_statement_activities (
+    seq INTEGER NOT NULL REFERENCES lrs_statements(seq),
+    activity_id TEXT NOT NULL,
+    related INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_lrs_statement_activities ON lrs_statement_activities(activity_id, seq);
+
+-- State, Activity Profile and Agent Profile documents. Unused key columns are
+-- empty strings so that they can be part of the primary key; `agent` is the
+-- agent's inverse functional identifier.
+CREATE TABLE IF NOT EXISTS lrs_documents (
+    kind TEXT NOT NULL,
+    activity_id TEXT NOT NULL,
+    agent TEXT NOT NULL,
+    registration TEXT NOT NULL,
+    document_id TEXT NOT NULL,
+    content BLOB NOT NULL,
+    content_type TEXT NOT NULL,
+    etag TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (kind, activity_id, agent, registration, document_id)
+);
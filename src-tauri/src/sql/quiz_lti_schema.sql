@@ -0,0 +1,63 @@
+-- Quiz LTI integration schema
+
+-- OAuth 1.0a nonces seen on LTI 1.x launches (replay protection)
+CREATE TABLE IF NOT EXISTS lti_oauth_nonces (
+    consumer_key TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    received_at TEXT NOT NULL,
+    PRIMARY KEY (consumer_key, nonce)
+);
+
+CREATE INDEX IF NOT EXISTS idx_lti_oauth_nonces_timestamp ON lti_oauth_nonces(timestamp);
+
+-- Pending LTI 1.3 OIDC logins, keyed by the state handed to the platform
+CREATE TABLE IF NOT EXISTS lti_login_states (
+    state TEXT PRIMARY KEY,
+    nonce TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    target_link_uri TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- RSA keys the tool signs LTI 1.3 JWTs with (published via the tool JWKS)
+CREATE TABLE IF NOT EXISTS lti_tool_keys (
+    kid TEXT PRIMARY KEY,
+    private_key_pem TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Where grades for LTI-launched quizzes are sent (latest launch per quiz and user)
+CREATE TABLE IF NOT EXISTS lti_outcome_bindings (
+    quiz_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    lti_user_id TEXT NOT NULL,
+    outcome_url TEXT,
+    source_id TEXT,
+    lineitem_url TEXT,
+    lineitems_url TEXT,
+    resource_link_id TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (quiz_id, user_id)
+);
+
+-- Course rosters imported through Names and Role Provisioning Services
+CREATE TABLE IF NOT EXISTS lti_roster_members (
+    platform_id TEXT NOT NULL,
+    context_id TEXT NOT NULL,
+    lti_user_id TEXT NOT NULL,
+    name TEXT,
+    email TEXT,
+    roles TEXT NOT NULL,
+    status TEXT,
+    imported_at TEXT NOT NULL,
+    PRIMARY KEY (platform_id, context_id, lti_user_id)
+);
+
+-- Registered platforms, loaded into the LTI service at startup
+CREATE TABLE IF NOT EXISTS lti_platforms (
+    id TEXT PRIMARY KEY,
+    config TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
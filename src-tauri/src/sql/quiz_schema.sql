@@ -22,6 +22,7 @@ CREATE TABLE IF NOT EXISTS quiz_settings (
     show_correct_answers INTEGER NOT NULL DEFAULT 1,
     passing_score REAL DEFAULT NULL,
     study_mode TEXT NOT NULL,
+    wrong_choice_penalty TEXT NOT NULL DEFAULT '"PerChoice"',
     FOREIGN KEY (quiz_id) REFERENCES quizzes (id) ON DELETE CASCADE
 );
 
@@ -43,6 +44,7 @@ CREATE TABLE IF NOT EXISTS choices (
     text TEXT NOT NULL,
     rich_text TEXT,
     image_url TEXT,
+    feedback TEXT,
     FOREIGN KEY (question_id) REFERENCES questions (id) ON DELETE CASCADE
 );
 
@@ -55,10 +57,31 @@ CREATE TABLE IF NOT EXISTS flashcard_data (
     repetitions INTEGER NOT NULL DEFAULT 0,
     due_date TEXT NOT NULL,
     last_reviewed TEXT NOT NULL,
+    stability REAL,
+    difficulty REAL,
     PRIMARY KEY (question_id, user_id),
     FOREIGN KEY (question_id) REFERENCES questions (id) ON DELETE CASCADE
 );
 
+-- Flashcard review log, used to fit FSRS weights
+CREATE TABLE IF NOT EXISTS flashcard_reviews (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    question_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    deck_id TEXT NOT NULL,
+    rating INTEGER NOT NULL,
+    reviewed_at TEXT NOT NULL
+);
+
+-- Scheduling algorithm and FSRS settings of each user's decks
+CREATE TABLE IF NOT EXISTS flashcard_deck_settings (
+    user_id TEXT NOT NULL,
+    deck_id TEXT NOT NULL,
+    settings TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, deck_id)
+);
+
 -- Quiz attempts
 CREATE TABLE IF NOT EXISTS quiz_attempts (
     id TEXT PRIMARY KEY,
@@ -89,6 +112,7 @@ CREATE INDEX IF NOT EXISTS idx_questions_quiz_id ON questions (quiz_id);
 CREATE INDEX IF NOT EXISTS idx_choices_question_id ON choices (question_id);
 CREATE INDEX IF NOT EXISTS idx_flashcard_data_user_id ON flashcard_data (user_id);
 CREATE INDEX IF NOT EXISTS idx_flashcard_data_due_date ON flashcard_data (due_date);
+CREATE INDEX IF NOT EXISTS idx_flashcard_reviews_user_id ON flashcard_reviews (user_id);
 CREATE INDEX IF NOT EXISTS idx_quiz_attempts_user_id ON quiz_attempts (user_id);
 CREATE INDEX IF NOT EXISTS idx_quiz_attempts_quiz_id ON quiz_attempts (quiz_id);
 
@@ -0,0 +1,21 @@
+-- Quiz SCORM integration schema
+
+-- Imported SCORM packages; `metadata` is the package metadata as JSON
+CREATE TABLE IF NOT EXISTS scorm_packages (
+    id TEXT PRIMARY KEY,
+    metadata TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Learner sessions; `data` holds the session with its CMI data model and
+-- `sequencing` the SCORM 2004 sequencing state, both as JSON
+CREATE TABLE IF NOT EXISTS scorm_sessions (
+    id TEXT PRIMARY KEY,
+    package_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    data TEXT NOT NULL,
+    sequencing TEXT,
+    updated_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_scorm_sessions_user ON scorm_sessions(user_id, package_id);
@@ -31,6 +31,22 @@ CREATE TABLE IF NOT EXISTS quiz_question_templates (
     FOREIGN KEY (template_id) REFERENCES quiz_templates (id) ON DELETE CASCADE
 );
 
+-- Formulas of formula question templates, as JSON
+CREATE TABLE IF NOT EXISTS quiz_question_formulas (
+    question_template_id TEXT PRIMARY KEY,
+    formula TEXT NOT NULL,
+    FOREIGN KEY (question_template_id) REFERENCES quiz_question_templates (id) ON DELETE CASCADE
+);
+
+-- Values of formula questions drawn for attempts, as JSON
+CREATE TABLE IF NOT EXISTS quiz_formula_instances (
+    attempt_id TEXT NOT NULL,
+    question_template_id TEXT NOT NULL,
+    instance TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (attempt_id, question_template_id)
+);
+
 -- Quiz template ratings table
 CREATE TABLE IF NOT EXISTS quiz_template_ratings (
     id TEXT PRIMARY KEY,
@@ -0,0 +1,20 @@
+-- Quiz xAPI integration schema
+
+-- Outbound statements waiting to be forwarded to the LRS. `id` is the
+-- statement UUID assigned when it was queued, so a statement that reaches the
+-- LRS twice is recognised as a duplicate. `status` is one of pending, sent or
+-- rejected; timestamps are RFC 3339 in UTC with a fixed precision so that they
+-- compare as text.
+CREATE TABLE IF NOT EXISTS xapi_statement_queue (
+    id TEXT PRIMARY KEY,
+    statement TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TEXT NOT NULL,
+    last_attempt_at TEXT,
+    last_error TEXT,
+    queued_at TEXT NOT NULL,
+    sent_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_xapi_statement_queue_due ON xapi_statement_queue(status, next_attempt_at);
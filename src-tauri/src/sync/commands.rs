@@ -0,0 +1,146 @@
+use super::engine::SyncEngine;
+use super::protocol::{self, http::HttpTransport, tcp::TcpTransport, SyncReport};
+use super::review::{self, ConflictChoice, ConflictDiff, ConflictStatus, SyncConflict};
+use crate::services::unified_services::AuthService;
+use tauri::State;
+use std::sync::Arc;
+
+/// The signed-in user, from their token
+fn signed_in_user(token: &str, auth: &AuthService) -> Result<i64, String> {
+    let claims = auth.verify_token(token).map_err(|e| e.to_string())?;
+    claims.sub.parse().map_err(|_| "Invalid user ID in token".to_string())
+}
+
+/// A conflict of the signed-in user; other users' conflicts are not found
+async fn owned_conflict(engine: &SyncEngine, conflict_id: &str, user_id: i64) -> Result<SyncConflict, String> {
+    let conflict = engine.conflict_queue()
+        .get(conflict_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if conflict.user_id != user_id {
+        return Err(format!("Sync conflict {} not found", conflict_id));
+    }
+    Ok(conflict)
+}
+
+/// The signed-in user's conflicts kept for review: "open" (the default),
+/// "resolved" or "all"
+#[tauri::command]
+pub async fn list_conflicts(
+    token: String,
+    status: Option<String>,
+    auth: State<'_, Arc<AuthService>>,
+    engine: State<'_, Arc<SyncEngine>>,
+) -> Result<Vec<SyncConflict>, String> {
+    let user_id = signed_in_user(&token, &auth)?;
+    let status = match status.as_deref().unwrap_or("open") {
+        "open" => Some(ConflictStatus::Open),
+        "resolved" => Some(ConflictStatus::Resolved),
+        "all" => None,
+        other => return Err(format!("Unknown conflict status: {}", other)),
+    };
+
+    engine.conflict_queue()
+        .list(user_id, status)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_conflict_diff(
+    conflict_id: String,
+    token: String,
+    auth: State<'_, Arc<AuthService>>,
+    engine: State<'_, Arc<SyncEngine>>,
+) -> Result<ConflictDiff, String> {
+    let user_id = signed_in_user(&token, &auth)?;
+    let conflict = owned_conflict(&engine, &conflict_id, user_id).await?;
+
+    let fields = review::three_way_diff(&conflict);
+    Ok(ConflictDiff { conflict, fields })
+}
+
+#[tauri::command]
+pub async fn resolve_conflict(
+    conflict_id: String,
+    choice: ConflictChoice,
+    token: String,
+    auth: State<'_, Arc<AuthService>>,
+    engine: State<'_, Arc<SyncEngine>>,
+) -> Result<SyncConflict, String> {
+    let user_id = signed_in_user(&token, &auth)?;
+    owned_conflict(&engine, &conflict_id, user_id).await?;
+
+    engine.resolve_review_conflict(&conflict_id, choice)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Pair another device for syncing the signed-in user's operations over the
+/// LAN. The returned token is entered on that device.
+#[tauri::command]
+pub async fn create_sync_pairing(
+    token: String,
+    auth: State<'_, Arc<AuthService>>,
+    engine: State<'_, Arc<SyncEngine>>,
+) -> Result<String, String> {
+    let user_id = signed_in_user(&token, &auth)?;
+
+    engine.create_pairing(user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Let paired devices sync with this one over the LAN, or stop them. Until
+/// it is turned on, the LAN listener only answers on this machine; the
+/// change applies from the next launch.
+#[tauri::command]
+pub async fn set_lan_sync(
+    enabled: bool,
+    token: String,
+    auth: State<'_, Arc<AuthService>>,
+    engine: State<'_, Arc<SyncEngine>>,
+) -> Result<(), String> {
+    signed_in_user(&token, &auth)?;
+
+    engine.set_lan_sync_enabled(enabled)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Sync the signed-in user's operations with a paired device on the LAN
+#[tauri::command]
+pub async fn sync_with_peer(
+    address: String,
+    pairing_token: String,
+    token: String,
+    auth: State<'_, Arc<AuthService>>,
+    engine: State<'_, Arc<SyncEngine>>,
+) -> Result<SyncReport, String> {
+    let user_id = signed_in_user(&token, &auth)?;
+    let mut transport = TcpTransport::connect(address.as_str(), &pairing_token)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    protocol::synchronize(&engine, &mut transport, user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Sync the signed-in user's operations with the hub, which accepts the same
+/// token
+#[tauri::command]
+pub async fn sync_with_hub(
+    hub_url: String,
+    token: String,
+    auth: State<'_, Arc<AuthService>>,
+    engine: State<'_, Arc<SyncEngine>>,
+) -> Result<SyncReport, String> {
+    let user_id = signed_in_user(&token, &auth)?;
+    let mut transport = HttpTransport::new(&hub_url, Some(token));
+
+    protocol::synchronize(&engine, &mut transport, user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
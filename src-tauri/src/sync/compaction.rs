@@ -0,0 +1,149 @@
+// Compaction of the operation log
+//
+// An operation is causally stable once every device holds it: every device
+// the user's operations came from or that reported what it holds, directly
+// or relayed by the hub, so that a laptop only ever syncing with the hub
+// still waits for the other laptops behind it. Every operation any of them
+// makes from then on follows it, so nothing concurrent with it can still
+// arrive. Nothing is stable while what one of the devices holds is unknown.
+// Devices not heard from for a while are left out, as vector clocks prune
+// inactive entries, so a device that was lost does not stop compaction.
+//
+// Once all operations on an entity are stable they are no longer needed to
+// merge new ones, and are folded into a single snapshot: the latest write,
+// carrying the merged state of the entity. An entity whose latest operation
+// is a delete is dropped altogether, tombstone included.
+//
+// What was folded away is recorded per device, so the sequences known to
+// this device never go back and operations resent by peers are not stored
+// again. Space freed in the database is reclaimed with incremental vacuums,
+// once the database was switched to them.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::crdt::{self, MergeSchema};
+use super::operations::{OperationType, SyncOperation};
+use super::version_vector::VersionVector;
+
+/// What became of the operation log in a compaction
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompactionReport {
+    /// Operations folded into snapshots
+    pub operations_folded: usize,
+    /// Snapshots written, one per entity compacted
+    pub snapshots_written: usize,
+    /// Deleted entities dropped from the log
+    pub tombstones_expired: usize,
+    /// Operations dropped with them
+    pub operations_expired: usize,
+    /// Space returned to the file system, in bytes
+    pub bytes_reclaimed: i64,
+}
+
+impl CompactionReport {
+    pub fn add(&mut self, other: &CompactionReport) {
+        self.operations_folded += other.operations_folded;
+        self.snapshots_written += other.snapshots_written;
+        self.tombstones_expired += other.tombstones_expired;
+        self.operations_expired += other.operations_expired;
+        self.bytes_reclaimed += other.bytes_reclaimed;
+    }
+}
+
+/// What to do with the operations on an entity
+#[derive(Debug, Clone)]
+pub enum Compaction {
+    Keep,
+    /// Replace the operation with the snapshot's ID by the snapshot, and
+    /// remove the others
+    Fold { snapshot: SyncOperation, removed: Vec<String> },
+    /// Remove every operation on the entity
+    Expire { removed: Vec<String> },
+}
+
+/// The version vector every one of `devices` has reached, given what each
+/// is known to hold: for each origin device, the lowest sequence held among
+/// them. Empty while what one of them holds is unknown.
+pub fn stable_vector(devices: &[String], progress: &HashMap<String, VersionVector>) -> VersionVector {
+    let mut known = Vec::new();
+    for device_id in devices {
+        match progress.get(device_id) {
+            Some(vector) => known.push(vector),
+            None => return VersionVector::new(),
+        }
+    }
+
+    let mut counters: HashMap<String, i64> = HashMap::new();
+    for vector in &known {
+        for device_id in vector.to_hashmap().keys() {
+            counters.insert(device_id.clone(), 0);
+        }
+    }
+    for (device_id, counter) in counters.iter_mut() {
+        *counter = known.iter().map(|vector| vector.get(device_id)).min().unwrap_or(0);
+    }
+
+    VersionVector::from_hashmap(counters)
+}
+
+/// How to compact the operations on an entity, given the stable vector
+pub fn plan(operations: &[SyncOperation], stable: &VersionVector) -> Compaction {
+    // Operations still to be stable could be concurrent with any other
+    if operations.is_empty() || !operations.iter().all(|operation| clock(operation).is_dominated_by(stable)) {
+        return Compaction::Keep;
+    }
+
+    let all: Vec<&SyncOperation> = operations.iter().collect();
+    let Some(latest) = crdt::latest(&all) else { return Compaction::Keep };
+    if latest.operation_type == OperationType::Delete {
+        return Compaction::Expire { removed: ids(&all) };
+    }
+
+    // References are not part of the entity's state and stay as they are
+    let folded: Vec<&SyncOperation> = all
+        .iter()
+        .copied()
+        .filter(|operation| operation.operation_type != OperationType::Reference)
+        .collect();
+    let writes: Vec<&SyncOperation> = folded
+        .iter()
+        .copied()
+        .filter(|operation| operation.operation_type != OperationType::Delete)
+        .collect();
+    let Some(base) = crdt::latest(&writes) else { return Compaction::Keep };
+    if folded.len() < 2 {
+        return Compaction::Keep;
+    }
+
+    let mut snapshot = base.clone();
+    let owned: Vec<SyncOperation> = writes.iter().map(|&operation| operation.clone()).collect();
+    snapshot.payload = Value::Object(crdt::merge_payloads(&owned, &MergeSchema::default()));
+    if writes.iter().any(|operation| operation.operation_type == OperationType::Create) {
+        snapshot.operation_type = OperationType::Create;
+    }
+
+    let mut vector_clock = VersionVector::new();
+    for operation in &folded {
+        vector_clock.merge(&clock(operation));
+    }
+    snapshot.vector_clock = vector_clock.to_hashmap();
+    snapshot.timestamp = folded.iter().map(|operation| operation.timestamp).max().unwrap_or(snapshot.timestamp);
+    snapshot.hlc = folded.iter().map(|operation| operation.hlc.clone()).max().unwrap_or_default();
+
+    let removed = folded
+        .iter()
+        .filter(|operation| operation.id != snapshot.id)
+        .map(|operation| operation.id.clone())
+        .collect();
+    Compaction::Fold { snapshot, removed }
+}
+
+fn ids(operations: &[&SyncOperation]) -> Vec<String> {
+    operations.iter().map(|operation| operation.id.clone()).collect()
+}
+
+fn clock(operation: &SyncOperation) -> VersionVector {
+    VersionVector::from_hashmap(operation.vector_clock.clone())
+}
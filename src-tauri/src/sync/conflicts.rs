@@ -5,6 +5,7 @@ use log::{debug, info, warn};
 use std::time::{Duration, Instant};
 use std::sync::{Arc, Mutex};
 
+use super::crdt::{self, MergeSchema};
 use super::operations::{SyncOperation, OperationType};
 use super::version_vector::{VersionVector, CausalRelation};
 
@@ -207,7 +208,7 @@ impl ConflictResolver {
                     processed.insert(*j);
                 },
                 ConflictResolution::Merge => {
-                    result.push(Self::merge_updates(op1, op2));
+                    result.push(Self::merge_with_history(operations, op1, op2));
                     processed.insert(*i);
                     processed.insert(*j);
                 },
@@ -290,33 +291,39 @@ impl ConflictResolver {
         }
     }
 
-    /// Merge two update operations
+    /// Merge two update operations field by field, see `crdt`
     pub fn merge_updates(op1: &SyncOperation, op2: &SyncOperation) -> SyncOperation {
-        let mut merged_payload = match &op1.payload {
-            Value::Object(map) => map.clone(),
-            _ => serde_json::Map::new(),
-        };
+        Self::merge_with_history(&[], op1, op2)
+    }
 
-        if let Value::Object(map2) = &op2.payload {
-            for (key, value) in map2 {
-                merged_payload.insert(key.clone(), value.clone());
-            }
+    /// Merge two operations on an entity, reading each write against the
+    /// entity's other operations in `history`
+    pub fn merge_with_history(history: &[SyncOperation], op1: &SyncOperation, op2: &SyncOperation) -> SyncOperation {
+        let mut operations: Vec<SyncOperation> = history
+            .iter()
+            .filter(|op| op.entity_type == op1.entity_type && op.entity_id == op1.entity_id)
+            .cloned()
+            .collect();
+        operations.push(op1.clone());
+        operations.push(op2.clone());
+
+        // The merged operation stands in for the latest of the two, so each
+        // side of an exchange ends up with the same one
+        let mut merged_op = crdt::latest(&[op1, op2]).unwrap_or(op1).clone();
+        merged_op.payload = Value::Object(crdt::merge_payloads(&operations, &MergeSchema::default()));
+        if op1.operation_type == OperationType::Create || op2.operation_type == OperationType::Create {
+            merged_op.operation_type = OperationType::Create;
         }
 
-        // Create a new operation with merged data
-        let mut merged_op = op1.clone();
-        merged_op.payload = Value::Object(merged_payload);
-
         // Merge vector clocks using VersionVector
         let vv1 = VersionVector::from_hashmap(op1.vector_clock.clone());
         let vv2 = VersionVector::from_hashmap(op2.vector_clock.clone());
         let merged_vv = vv1.merged_with(&vv2);
         merged_op.vector_clock = merged_vv.to_hashmap();
 
-        // Use the later timestamp
-        if op2.timestamp > op1.timestamp {
-            merged_op.timestamp = op2.timestamp;
-        }
+        // Use the later timestamp and stamp
+        merged_op.timestamp = op1.timestamp.max(op2.timestamp);
+        merged_op.hlc = op1.hlc.clone().max(op2.hlc.clone());
 
         info!("Merged operations: {} and {}", op1.id, op2.id);
         debug!("Merged vector clock: {:?}", merged_op.vector_clock);
@@ -332,8 +339,8 @@ impl ConflictResolver {
             Ordering::Greater => ConflictResolution::KeepFirst,
             Ordering::Less => ConflictResolution::KeepSecond,
             Ordering::Equal => {
-                // If equal number of fields, choose the later one
-                if op1.timestamp >= op2.timestamp {
+                // If equal number of fields, choose the later one by hybrid logical clock
+                if op1.hlc >= op2.hlc {
                     ConflictResolution::KeepFirst
                 } else {
                     ConflictResolution::KeepSecond
@@ -356,14 +363,14 @@ impl ConflictResolver {
         // Delete followed by create means the entity is recreated
         match (op1.operation_type, op2.operation_type) {
             (OperationType::Create, OperationType::Delete) => {
-                if op1.timestamp < op2.timestamp {
+                if op1.hlc < op2.hlc {
                     ConflictResolution::KeepSecond
                 } else {
                     ConflictResolution::KeepFirst
                 }
             },
             (OperationType::Delete, OperationType::Create) => {
-                if op1.timestamp < op2.timestamp {
+                if op1.hlc < op2.hlc {
                     ConflictResolution::KeepSecond
                 } else {
                     ConflictResolution::KeepFirst
@@ -396,9 +403,9 @@ impl ConflictResolver {
                 ConflictResolution::Merge
             },
             CausalRelation::Identical => {
-                // Identical version vectors, keep the one with the later timestamp
-                debug!("Update-Update: identical version vectors, using timestamp");
-                if op1.timestamp >= op2.timestamp {
+                // Identical version vectors, keep the one with the later stamp
+                debug!("Update-Update: identical version vectors, using hybrid logical clock");
+                if op1.hlc >= op2.hlc {
                     ConflictResolution::KeepFirst
                 } else {
                     ConflictResolution::KeepSecond
@@ -412,14 +419,14 @@ impl ConflictResolver {
         // Delete followed by update is invalid, but we handle it as a delete
         match (op1.operation_type, op2.operation_type) {
             (OperationType::Update, OperationType::Delete) => {
-                if op1.timestamp < op2.timestamp {
+                if op1.hlc < op2.hlc {
                     ConflictResolution::KeepSecond
                 } else {
                     ConflictResolution::KeepFirst
                 }
             },
             (OperationType::Delete, OperationType::Update) => {
-                if op1.timestamp < op2.timestamp {
+                if op1.hlc < op2.hlc {
                     ConflictResolution::KeepSecond
                 } else {
                     ConflictResolution::KeepFirst
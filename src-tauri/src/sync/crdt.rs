@@ -0,0 +1,321 @@
+// Field-level merge of concurrent entity updates
+//
+// An entity's state is rebuilt field by field from the create and update
+// operations that wrote it, so it depends only on which operations are
+// merged and never on the order they arrived in. Each field is merged by
+// its kind in the `MergeSchema`:
+//
+// - Registers, the default, hold the value written last. A write loses to
+//   any write whose version vector follows its own; among the remaining,
+//   concurrent writes the later hybrid logical clock stamp wins, then the
+//   higher operation ID.
+// - Sets, such as tags and attachments, are observed-remove sets. A write
+//   of an array adds the elements it lists and removes those the writing
+//   device had seen but left out; a write of `{"add": [..], "remove": [..]}`
+//   says so directly. An element added concurrently with its removal stays.
+// - Text, such as post bodies, is a sequence CRDT held in an automerge
+//   document. A write is read as the splice turning the text the writing
+//   device had seen into the written text, so concurrent edits to different
+//   parts of the text both survive.
+//
+// What a device had seen when writing is the state built from the merged
+// operations whose version vectors precede the write's.
+
+use automerge::transaction::{CommitOptions, Transactable};
+use automerge::{ActorId, AutoCommit, AutomergeError, ObjId, ObjType, ReadDoc, ROOT};
+use log::warn;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use super::hlc::HlcTimestamp;
+use super::operations::{OperationType, SyncOperation};
+use super::version_vector::{CausalRelation, VersionVector};
+
+/// Actor of the change creating the text object every write edits
+const GENESIS_ACTOR: &[u8] = b"ordo-sync-genesis";
+const TEXT_KEY: &str = "text";
+
+/// How a field's concurrent writes are merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Register,
+    Set,
+    Text,
+}
+
+/// Kinds of the fields of each entity type
+#[derive(Debug, Clone)]
+pub struct MergeSchema {
+    fields: HashMap<String, FieldKind>,
+    entity_fields: HashMap<(String, String), FieldKind>,
+}
+
+impl MergeSchema {
+    /// Schema merging every field as a register
+    pub fn new() -> Self {
+        Self {
+            fields: HashMap::new(),
+            entity_fields: HashMap::new(),
+        }
+    }
+
+    /// Merge `field` as `kind` on every entity type
+    pub fn with_field(mut self, field: &str, kind: FieldKind) -> Self {
+        self.fields.insert(field.to_string(), kind);
+        self
+    }
+
+    /// Merge `field` as `kind` on `entity_type`, over `with_field`
+    pub fn with_entity_field(mut self, entity_type: &str, field: &str, kind: FieldKind) -> Self {
+        self.entity_fields.insert((entity_type.to_string(), field.to_string()), kind);
+        self
+    }
+
+    pub fn field_kind(&self, entity_type: &str, field: &str) -> FieldKind {
+        self.entity_fields
+            .get(&(entity_type.to_string(), field.to_string()))
+            .or_else(|| self.fields.get(field))
+            .copied()
+            .unwrap_or(FieldKind::Register)
+    }
+}
+
+impl Default for MergeSchema {
+    fn default() -> Self {
+        Self::new()
+            .with_field("tags", FieldKind::Set)
+            .with_field("attachments", FieldKind::Set)
+            .with_entity_field("post", "content", FieldKind::Text)
+            .with_entity_field("post", "body", FieldKind::Text)
+            .with_entity_field("assignment", "description", FieldKind::Text)
+    }
+}
+
+/// The operation whose writes win over the others': one no other operation
+/// follows, and the last of those by hybrid logical clock and operation ID
+pub fn latest<'a>(operations: &[&'a SyncOperation]) -> Option<&'a SyncOperation> {
+    let clocks: Vec<_> = operations.iter().map(|operation| clock(operation)).collect();
+    let followed = |i: usize| {
+        (0..operations.len()).any(|j| clocks[i].causal_relation(&clocks[j]) == CausalRelation::HappensBefore)
+    };
+
+    (0..operations.len())
+        .filter(|&i| !followed(i))
+        .map(|i| operations[i])
+        .max_by(|a, b| tiebreak(a).cmp(&tiebreak(b)))
+}
+
+/// State of an entity merged from operations on it, in any order.
+/// Operations other than creates and updates are ignored.
+pub fn merge_payloads(operations: &[SyncOperation], schema: &MergeSchema) -> Map<String, Value> {
+    let history = History::new(operations);
+    let entity_type = operations.first().map(|operation| operation.entity_type.as_str()).unwrap_or_default();
+
+    let fields: BTreeSet<&str> = history
+        .operations
+        .iter()
+        .filter_map(|operation| operation.payload.as_object())
+        .flat_map(|payload| payload.keys().map(String::as_str))
+        .collect();
+
+    let mut merged = Map::new();
+    for field in fields {
+        let value = match schema.field_kind(entity_type, field) {
+            FieldKind::Register => history.register(field),
+            FieldKind::Set => history.set(field),
+            FieldKind::Text => history.text(field).unwrap_or_else(|e| {
+                warn!("Failed to merge text of field {}, keeping the latest write: {}", field, e);
+                history.register(field)
+            }),
+        };
+        if let Some(value) = value {
+            merged.insert(field.to_string(), value);
+        }
+    }
+
+    merged
+}
+
+/// The writes being merged, each after all the writes it had seen
+struct History<'a> {
+    operations: Vec<&'a SyncOperation>,
+    /// Indices of the writes each write had seen
+    seen: Vec<Vec<usize>>,
+}
+
+impl<'a> History<'a> {
+    fn new(operations: &'a [SyncOperation]) -> Self {
+        let mut ids = HashSet::new();
+        let mut operations: Vec<&SyncOperation> = operations
+            .iter()
+            .filter(|operation| matches!(operation.operation_type, OperationType::Create | OperationType::Update))
+            .filter(|operation| operation.payload.is_object())
+            .filter(|operation| ids.insert(operation.id.as_str()))
+            .collect();
+
+        // A write's counters add up to more than those of any write it had
+        // seen, so this puts those first
+        operations.sort_by(|a, b| {
+            let total = |operation: &SyncOperation| operation.vector_clock.values().sum::<i64>();
+            total(a).cmp(&total(b)).then_with(|| a.id.cmp(&b.id))
+        });
+
+        let clocks: Vec<_> = operations.iter().map(|operation| clock(operation)).collect();
+        let seen = (0..operations.len())
+            .map(|i| {
+                (0..i)
+                    .filter(|&j| clocks[j].causal_relation(&clocks[i]) == CausalRelation::HappensBefore)
+                    .collect()
+            })
+            .collect();
+
+        Self { operations, seen }
+    }
+
+    fn written(&self, i: usize, field: &str) -> Option<&'a Value> {
+        self.operations[i].payload.get(field)
+    }
+
+    fn register(&self, field: &str) -> Option<Value> {
+        let writes: Vec<_> = self
+            .operations
+            .iter()
+            .copied()
+            .filter(|operation| operation.payload.get(field).is_some())
+            .collect();
+
+        latest(&writes).and_then(|operation| operation.payload.get(field)).cloned()
+    }
+
+    fn set(&self, field: &str) -> Option<Value> {
+        let count = self.operations.len();
+        let mut added: Vec<BTreeMap<String, Value>> = vec![BTreeMap::new(); count];
+        let mut removed: Vec<HashSet<(String, usize)>> = vec![HashSet::new(); count];
+        let mut any_written = false;
+
+        for i in 0..count {
+            let Some(value) = self.written(i, field) else { continue };
+            any_written = true;
+
+            let observed = observed_set(&self.seen[i], &added, &removed);
+            let removals: BTreeSet<String> = match value.as_object() {
+                Some(delta) => {
+                    added[i] = elements(delta.get("add"));
+                    elements(delta.get("remove")).into_keys().collect()
+                }
+                None => {
+                    added[i] = elements(Some(value));
+                    observed.keys().filter(|key| !added[i].contains_key(*key)).cloned().collect()
+                }
+            };
+
+            // Only the additions this device had seen are removed
+            for key in removals {
+                if let Some((_, tags)) = observed.get(&key) {
+                    removed[i].extend(tags.iter().map(|&tag| (key.clone(), tag)));
+                }
+            }
+        }
+
+        if !any_written {
+            return None;
+        }
+        let all: Vec<usize> = (0..count).collect();
+        let state = observed_set(&all, &added, &removed);
+        Some(Value::Array(state.into_values().map(|(value, _)| value).collect()))
+    }
+
+    fn text(&self, field: &str) -> Result<Option<Value>, AutomergeError> {
+        if !(0..self.operations.len()).any(|i| self.written(i, field).is_some()) {
+            return Ok(None);
+        }
+
+        // Every write edits the same text object, created by a change that
+        // is identical on every device
+        let mut genesis = AutoCommit::new().with_actor(ActorId::from(GENESIS_ACTOR));
+        let text = genesis.put_object(ROOT, TEXT_KEY, ObjType::Text)?;
+        genesis.commit_with(CommitOptions::default().with_time(0));
+
+        let mut documents: Vec<Option<AutoCommit>> = (0..self.operations.len()).map(|_| None).collect();
+        for i in 0..self.operations.len() {
+            let Some(value) = self.written(i, field) else { continue };
+            let operation = self.operations[i];
+
+            let mut document = genesis.fork();
+            for &j in &self.seen[i] {
+                if let Some(seen) = documents[j].as_mut() {
+                    document.merge(seen)?;
+                }
+            }
+
+            let seen = document.text(&text)?;
+            document.set_actor(ActorId::from(operation.id.as_bytes()));
+            splice(&mut document, &text, &seen, value.as_str().unwrap_or_default())?;
+            document.commit_with(CommitOptions::default().with_time(operation.timestamp));
+            documents[i] = Some(document);
+        }
+
+        let mut merged = genesis.fork();
+        for document in documents.iter_mut().flatten() {
+            merged.merge(document)?;
+        }
+        Ok(Some(Value::String(merged.text(&text)?)))
+    }
+}
+
+/// Elements of a set, with the indices of the writes that added each,
+/// after the writes at `indices`
+fn observed_set(
+    indices: &[usize],
+    added: &[BTreeMap<String, Value>],
+    removed: &[HashSet<(String, usize)>],
+) -> BTreeMap<String, (Value, Vec<usize>)> {
+    let removed: HashSet<&(String, usize)> = indices.iter().flat_map(|&i| removed[i].iter()).collect();
+
+    let mut state: BTreeMap<String, (Value, Vec<usize>)> = BTreeMap::new();
+    for &i in indices {
+        for (key, value) in &added[i] {
+            if !removed.contains(&(key.clone(), i)) {
+                state.entry(key.clone()).or_insert_with(|| (value.clone(), Vec::new())).1.push(i);
+            }
+        }
+    }
+    state
+}
+
+/// Elements of a written array, keyed by their JSON
+fn elements(value: Option<&Value>) -> BTreeMap<String, Value> {
+    value
+        .and_then(Value::as_array)
+        .map(|items| items.iter().map(|item| (item.to_string(), item.clone())).collect())
+        .unwrap_or_default()
+}
+
+/// Edit the text from `seen` to `written` with a single splice
+fn splice(document: &mut AutoCommit, text: &ObjId, seen: &str, written: &str) -> Result<(), AutomergeError> {
+    let seen: Vec<char> = seen.chars().collect();
+    let written: Vec<char> = written.chars().collect();
+
+    let prefix = seen.iter().zip(&written).take_while(|(a, b)| a == b).count();
+    let suffix = seen[prefix..]
+        .iter()
+        .rev()
+        .zip(written[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let deleted = seen.len() - prefix - suffix;
+    let inserted: String = written[prefix..written.len() - suffix].iter().collect();
+    if deleted > 0 || !inserted.is_empty() {
+        document.splice_text(text, prefix, deleted as isize, &inserted)?;
+    }
+    Ok(())
+}
+
+fn clock(operation: &SyncOperation) -> VersionVector {
+    VersionVector::from_hashmap(operation.vector_clock.clone())
+}
+
+fn tiebreak(operation: &SyncOperation) -> (&HlcTimestamp, &str) {
+    (&operation.hlc, &operation.id)
+}
@@ -1,4 +1,5 @@
-use sqlx::{Pool, Sqlite};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Row, Sqlite};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -8,97 +9,145 @@ use log::{debug, info, warn};
 use crate::core::errors::AppError;
 use super::operations::{SyncOperation, SyncBatch, OperationType};
 use super::conflicts::{ConflictResolver, ConflictResolution};
+use super::hlc::{ClockDrift, HlcTimestamp, HybridLogicalClock};
+use super::review::{self, ConflictChoice, ConflictQueue, ConflictStatus, SyncConflict};
+use super::compaction::{self, Compaction, CompactionReport};
+use super::protocol::DeviceProgress;
 use super::version_vector::VersionVector;
 
+/// Days after which a device not heard from no longer holds back
+/// compaction, see `SyncEngine::with_peer_expiry`
+pub const DEFAULT_PEER_EXPIRY_DAYS: i64 = 30;
+
 pub struct SyncEngine {
     db: Pool<Sqlite>,
     device_id: String,
     vector_clock: Arc<Mutex<VersionVector>>,
-    conflict_resolver: Arc<ConflictResolver>,
+    hlc: Arc<Mutex<HybridLogicalClock>>,
+    conflict_queue: ConflictQueue,
     // Configuration for large systems
     max_batch_size: usize,
     prune_threshold: i64,
     compression_enabled: bool,
+    peer_expiry_days: i64,
 }
 
 impl SyncEngine {
-    pub fn new(db: Pool<Sqlite>) -> Self {
-        // Generate a unique device ID or load from storage
-        let device_id = Uuid::new_v4().to_string();
+    /// Open the engine of this device, under the ID stored in the database
+    /// on first launch, and initialize it
+    pub async fn open(db: Pool<Sqlite>) -> Result<Self, AppError> {
+        sqlx::query("INSERT OR IGNORE INTO sync_device (id, device_id, created_at) VALUES (1, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(time::OffsetDateTime::now_utc().unix_timestamp())
+            .execute(&db)
+            .await?;
+        let device_id: String = sqlx::query_scalar("SELECT device_id FROM sync_device WHERE id = 1")
+            .fetch_one(&db)
+            .await?;
 
-        Self {
-            db,
-            device_id,
-            vector_clock: Arc::new(Mutex::new(VersionVector::new())),
-            conflict_resolver: Arc::new(ConflictResolver::new()),
-            max_batch_size: 1000,
-            prune_threshold: 10,
-            compression_enabled: true,
-        }
+        let engine = Self::with_config(db, Some(device_id), 1000, 10, true);
+        engine.initialize().await?;
+        Ok(engine)
     }
 
-    /// Create a new SyncEngine with custom configuration
+    /// Create a new SyncEngine with custom configuration. Without a
+    /// `device_id`, the engine gets a new one that is not stored; see `open`
+    /// for the device's own.
     pub fn with_config(
         db: Pool<Sqlite>,
         device_id: Option<String>,
         max_batch_size: usize,
         prune_threshold: i64,
         compression_enabled: bool,
-        conflict_cache_size: usize,
     ) -> Self {
         let device_id = device_id.unwrap_or_else(|| Uuid::new_v4().to_string());
 
         Self {
+            conflict_queue: ConflictQueue::new(db.clone()),
             db,
+            hlc: Arc::new(Mutex::new(HybridLogicalClock::new(&device_id))),
             device_id,
             vector_clock: Arc::new(Mutex::new(VersionVector::new())),
-            conflict_resolver: Arc::new(ConflictResolver::with_config(
-                conflict_cache_size,
-                max_batch_size / 10, // Use smaller batches for conflict resolution
-            )),
             max_batch_size,
             prune_threshold,
             compression_enabled,
+            peer_expiry_days: DEFAULT_PEER_EXPIRY_DAYS,
         }
     }
 
-    // Initialize vector clock from database
+    /// Report peers whose clocks drift from this device's by more than
+    /// `max_drift_ms`
+    pub fn with_max_clock_drift(self, max_drift_ms: i64) -> Self {
+        let hlc = HybridLogicalClock::new(&self.device_id).with_max_drift(max_drift_ms);
+        Self { hlc: Arc::new(Mutex::new(hlc)), ..self }
+    }
+
+    /// Leave devices not heard from in `days` out of compaction, and forget
+    /// the checkpoints of peers not synced with since
+    pub fn with_peer_expiry(self, days: i64) -> Self {
+        Self { peer_expiry_days: days, ..self }
+    }
+
+    // Initialize vector clock from database, whose schema the
+    // `rebuild_sync_operations` migration sets up
     pub async fn initialize(&self) -> Result<(), AppError> {
         let mut clock = self.vector_clock.lock().await;
 
-        // Load vector clock from database
-        let rows = sqlx::query!(
-            r#"
-            SELECT device_id, MAX(timestamp) as last_timestamp
-            FROM sync_operations
-            GROUP BY device_id
-            "#,
+        // Load vector clock from the highest sequence stored or compacted
+        // per device
+        let rows = sqlx::query(
+            "SELECT device_id, MAX(sequence) AS sequence FROM (
+                SELECT device_id, sequence FROM sync_operations
+                UNION ALL SELECT device_id, sequence FROM sync_compaction
+             ) GROUP BY device_id"
         )
         .fetch_all(&self.db)
         .await?;
 
+        let mut counters = clock.to_hashmap();
         for row in rows {
-            if let (Some(device_id), Some(timestamp)) = (row.device_id, row.last_timestamp) {
-                let counters = clock.to_hashmap();
-                let mut new_counters = counters.clone();
-                new_counters.insert(device_id, timestamp);
-                *clock = VersionVector::from_hashmap(new_counters);
-            }
+            let device_id: String = row.try_get("device_id")?;
+            let sequence: i64 = row.try_get("sequence")?;
+            let counter = counters.entry(device_id).or_insert(0);
+            *counter = (*counter).max(sequence);
         }
 
         // Ensure current device is in vector clock
-        let device_counter = clock.get(&self.device_id);
-        if device_counter == 0 {
-            let counters = clock.to_hashmap();
-            let mut new_counters = counters.clone();
-            new_counters.insert(self.device_id.clone(), 0);
-            *clock = VersionVector::from_hashmap(new_counters);
-        }
+        counters.entry(self.device_id.clone()).or_insert(0);
+        *clock = VersionVector::from_hashmap(counters);
 
         info!("Initialized vector clock: {:?}", clock);
+
+        // Stamp new operations after every stored one, even if the wall
+        // clock has gone back since
+        let latest: Option<String> = sqlx::query_scalar("SELECT MAX(hlc) FROM sync_operations")
+            .fetch_one(&self.db)
+            .await?;
+        if let Some(latest) = latest.and_then(|latest| latest.parse::<HlcTimestamp>().ok()) {
+            self.hlc.lock().await.restore(&latest);
+        }
+
         Ok(())
     }
 
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    pub fn max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    /// Conflicts kept for review
+    pub fn conflict_queue(&self) -> &ConflictQueue {
+        &self.conflict_queue
+    }
+
+    /// Peers whose clocks were last seen drifting beyond the threshold
+    pub async fn clock_drift(&self) -> Vec<ClockDrift> {
+        self.hlc.lock().await.drifts()
+    }
+
     // Queue a new operation for later sync
     pub async fn queue_operation(
         &self,
@@ -115,7 +164,7 @@ impl SyncEngine {
         debug!("Incremented vector clock for device {}: {:?}", self.device_id, clock);
 
         // Create the operation
-        let operation = SyncOperation::new(
+        let mut operation = SyncOperation::new(
             &self.device_id,
             user_id,
             operation_type,
@@ -124,6 +173,7 @@ impl SyncEngine {
             payload,
             clock.to_hashmap(),
         );
+        operation.hlc = self.hlc.lock().await.now();
 
         // Store in database
         self.store_operation(&operation).await?;
@@ -134,90 +184,530 @@ impl SyncEngine {
 
     // Store operation in database
     async fn store_operation(&self, operation: &SyncOperation) -> Result<(), AppError> {
-        let op_json = serde_json::to_string(&operation)
-            .map_err(|e| AppError::SyncError(format!("Failed to serialize operation: {}", e)))?;
+        let payload_json = serde_json::to_string(&operation.payload)
+            .map_err(|e| AppError::SyncError(format!("Failed to serialize payload: {}", e)))?;
 
         let vector_clock_json = serde_json::to_string(&operation.vector_clock)
             .map_err(|e| AppError::SyncError(format!("Failed to serialize vector clock: {}", e)))?;
 
-        sqlx::query!(
+        sqlx::query(
             r#"
             INSERT INTO sync_operations
             (id, device_id, user_id, operation_type, entity_type, entity_id, payload,
-             timestamp, vector_clock, synced, synced_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-            "#,
-            operation.id,
-            operation.device_id,
-            operation.user_id,
-            operation.operation_type as i32, // This requires a custom FROM/TO implementation
-            operation.entity_type,
-            operation.entity_id,
-            op_json,
-            operation.timestamp,
-            vector_clock_json,
-            operation.synced,
-            operation.synced_at,
+             timestamp, hlc, vector_clock, sequence, synced, synced_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#
         )
+        .bind(&operation.id)
+        .bind(&operation.device_id)
+        .bind(operation.user_id)
+        .bind(operation.operation_type as i32)
+        .bind(&operation.entity_type)
+        .bind(&operation.entity_id)
+        .bind(payload_json)
+        .bind(operation.timestamp)
+        .bind(operation.hlc.to_string())
+        .bind(vector_clock_json)
+        .bind(operation.sequence())
+        .bind(operation.synced)
+        .bind(operation.synced_at)
         .execute(&self.db)
         .await?;
 
         Ok(())
     }
 
-    // Get pending operations to sync
-    pub async fn get_pending_operations(&self, limit: i64) -> Result<Vec<SyncOperation>, AppError> {
-        let rows = sqlx::query!(
-            r#"
-            SELECT id, device_id, user_id, operation_type, entity_type, entity_id,
-                   payload, timestamp, vector_clock, synced, synced_at
-            FROM sync_operations
-            WHERE synced = 0
-            ORDER BY timestamp
-            LIMIT ?
-            "#,
-            limit
+    async fn has_operation(&self, operation_id: &str) -> Result<bool, AppError> {
+        let row = sqlx::query("SELECT 1 FROM sync_operations WHERE id = ?")
+            .bind(operation_id)
+            .fetch_optional(&self.db)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    /// Highest sequence stored or compacted per device among a user's
+    /// operations
+    pub async fn known_sequences(&self, user_id: i64) -> Result<VersionVector, AppError> {
+        let rows = sqlx::query(
+            "SELECT device_id, MAX(sequence) AS sequence FROM (
+                SELECT device_id, sequence FROM sync_operations WHERE user_id = ?
+                UNION ALL SELECT device_id, sequence FROM sync_compaction WHERE user_id = ?
+             ) GROUP BY device_id"
         )
+        .bind(user_id)
+        .bind(user_id)
         .fetch_all(&self.db)
         .await?;
 
+        let mut counters = HashMap::new();
+        for row in rows {
+            counters.insert(row.try_get::<String, _>("device_id")?, row.try_get::<i64, _>("sequence")?);
+        }
+
+        Ok(VersionVector::from_hashmap(counters))
+    }
+
+    /// A user's operations missing from a peer holding `known`, by device
+    /// then sequence, at most `limit` of them
+    pub async fn operations_missing_from(
+        &self,
+        user_id: i64,
+        known: &VersionVector,
+        limit: usize,
+    ) -> Result<Vec<SyncOperation>, AppError> {
+        let local = self.known_sequences(user_id).await?;
+        let mut delta: Vec<_> = known.create_delta(&local).into_iter().collect();
+        delta.sort();
+
         let mut operations = Vec::new();
+        for (device_id, _) in delta {
+            if operations.len() >= limit {
+                break;
+            }
 
-        for row in rows {
-            let op_type = match row.operation_type {
-                0 => OperationType::Create,
-                1 => OperationType::Update,
-                2 => OperationType::Delete,
-                3 => OperationType::Reference,
-                _ => continue, // Skip unknown operation types
-            };
-
-            let vector_clock: HashMap<String, i64> = serde_json::from_str(&row.vector_clock)
-                .map_err(|e| AppError::SyncError(format!("Failed to deserialize vector clock: {}", e)))?;
-
-            let payload: serde_json::Value = serde_json::from_str(&row.payload)
-                .map_err(|e| AppError::SyncError(format!("Failed to deserialize payload: {}", e)))?;
-
-            let operation = SyncOperation {
-                id: row.id,
-                device_id: row.device_id,
-                user_id: row.user_id,
-                operation_type: op_type,
-                entity_type: row.entity_type,
-                entity_id: row.entity_id,
-                payload,
-                timestamp: row.timestamp,
-                vector_clock,
-                synced: row.synced != 0,
-                synced_at: row.synced_at,
-            };
+            let rows = sqlx::query(
+                "SELECT * FROM sync_operations WHERE user_id = ? AND device_id = ? AND sequence > ?
+                 ORDER BY sequence LIMIT ?"
+            )
+            .bind(user_id)
+            .bind(&device_id)
+            .bind(known.get(&device_id))
+            .bind((limit - operations.len()) as i64)
+            .fetch_all(&self.db)
+            .await?;
 
-            operations.push(operation);
+            for row in rows {
+                operations.push(self.row_to_operation(row)?);
+            }
         }
 
         Ok(operations)
     }
 
+    /// How far exchanges of a user's operations with a peer got in one
+    /// direction, `sent` or `received`
+    pub async fn peer_checkpoint(&self, peer_id: &str, user_id: i64, direction: &str) -> Result<VersionVector, AppError> {
+        let rows = sqlx::query(
+            "SELECT device_id, sequence FROM sync_checkpoints WHERE peer_id = ? AND user_id = ? AND direction = ?"
+        )
+        .bind(peer_id)
+        .bind(user_id)
+        .bind(direction)
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut counters = HashMap::new();
+        for row in rows {
+            counters.insert(row.try_get::<String, _>("device_id")?, row.try_get::<i64, _>("sequence")?);
+        }
+
+        Ok(VersionVector::from_hashmap(counters))
+    }
+
+    pub async fn save_peer_checkpoint(
+        &self,
+        peer_id: &str,
+        user_id: i64,
+        direction: &str,
+        checkpoint: &VersionVector,
+    ) -> Result<(), AppError> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+        for (device_id, sequence) in checkpoint.to_hashmap() {
+            sqlx::query(
+                "INSERT INTO sync_checkpoints (peer_id, user_id, direction, device_id, sequence, updated_at)
+                 VALUES (?, ?, ?, ?, ?, ?)
+                 ON CONFLICT (peer_id, user_id, direction, device_id) DO UPDATE SET
+                    sequence = MAX(sequence, excluded.sequence),
+                    updated_at = excluded.updated_at"
+            )
+            .bind(peer_id)
+            .bind(user_id)
+            .bind(direction)
+            .bind(device_id)
+            .bind(sequence)
+            .bind(now)
+            .execute(&self.db)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Record what a device reported holding of a user's operations at
+    /// `reported_at`, directly or relayed by a peer
+    pub async fn save_device_progress(
+        &self,
+        user_id: i64,
+        device_id: &str,
+        held: &VersionVector,
+        reported_at: i64,
+    ) -> Result<(), AppError> {
+        // This device knows best what it holds
+        if device_id == self.device_id {
+            return Ok(());
+        }
+
+        for (origin_device_id, sequence) in held.to_hashmap() {
+            sqlx::query(
+                "INSERT INTO sync_progress (user_id, device_id, origin_device_id, sequence, updated_at)
+                 VALUES (?, ?, ?, ?, ?)
+                 ON CONFLICT (user_id, device_id, origin_device_id) DO UPDATE SET
+                    sequence = MAX(sequence, excluded.sequence),
+                    updated_at = MAX(updated_at, excluded.updated_at)"
+            )
+            .bind(user_id)
+            .bind(device_id)
+            .bind(origin_device_id)
+            .bind(sequence)
+            .bind(reported_at)
+            .execute(&self.db)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// What each device of a user last reported holding, this one included
+    pub async fn device_progress(&self, user_id: i64) -> Result<HashMap<String, DeviceProgress>, AppError> {
+        let rows = sqlx::query(
+            "SELECT device_id, origin_device_id, sequence, updated_at FROM sync_progress WHERE user_id = ?"
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut progress: HashMap<String, DeviceProgress> = HashMap::new();
+        for row in rows {
+            let device = progress.entry(row.try_get("device_id")?).or_default();
+            device.held.insert(row.try_get("origin_device_id")?, row.try_get("sequence")?);
+            device.reported_at = device.reported_at.max(row.try_get("updated_at")?);
+        }
+        progress.insert(self.device_id.clone(), DeviceProgress {
+            held: self.known_sequences(user_id).await?.to_hashmap(),
+            reported_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+        });
+
+        Ok(progress)
+    }
+
+    /// The version vector every device has reached for a user's operations:
+    /// this one, each device found in the vector clocks of the operations,
+    /// and each device that reported what it holds, directly or relayed.
+    /// Devices not heard from in `peer_expiry_days` are left out. Nothing is
+    /// stable before another device is known, nor while what one of them
+    /// holds is unknown.
+    pub async fn stable_vector(&self, user_id: i64) -> Result<VersionVector, AppError> {
+        // A device is heard from when it makes an operation or reports what
+        // it holds; one only found in vector clocks was never heard from
+        let rows = sqlx::query(
+            "SELECT device_id, MAX(seen_at) AS seen_at FROM (
+                SELECT clock.key AS device_id, NULL AS seen_at
+                    FROM sync_operations AS operation, json_each(operation.vector_clock) AS clock
+                    WHERE operation.user_id = ?
+                UNION ALL SELECT device_id, timestamp FROM sync_operations WHERE user_id = ?
+                UNION ALL SELECT device_id, updated_at FROM sync_progress WHERE user_id = ?
+             ) GROUP BY device_id ORDER BY device_id"
+        )
+        .bind(user_id)
+        .bind(user_id)
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        let cutoff = self.peer_expiry_cutoff();
+        let mut devices = vec![self.device_id.clone()];
+        for row in rows {
+            let device_id: String = row.try_get("device_id")?;
+            let seen_at: Option<i64> = row.try_get("seen_at")?;
+            if device_id != self.device_id && seen_at.map_or(true, |seen_at| seen_at >= cutoff) {
+                devices.push(device_id);
+            }
+        }
+        if devices.len() < 2 {
+            return Ok(VersionVector::new());
+        }
+
+        let progress: HashMap<String, VersionVector> = self
+            .device_progress(user_id)
+            .await?
+            .into_iter()
+            .map(|(device_id, progress)| (device_id, VersionVector::from_hashmap(progress.held)))
+            .collect();
+        Ok(compaction::stable_vector(&devices, &progress))
+    }
+
+    /// Forget the checkpoints of peers not synced with in
+    /// `peer_expiry_days`, returning how many were dropped. Devices not
+    /// heard from since no longer hold back compaction either.
+    pub async fn expire_inactive_peers(&self) -> Result<u64, AppError> {
+        let expired = sqlx::query("DELETE FROM sync_checkpoints WHERE updated_at < ?")
+            .bind(self.peer_expiry_cutoff())
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+
+        if expired > 0 {
+            debug!("Expired {} checkpoints of inactive peers", expired);
+        }
+        Ok(expired)
+    }
+
+    fn peer_expiry_cutoff(&self) -> i64 {
+        time::OffsetDateTime::now_utc().unix_timestamp() - self.peer_expiry_days * 24 * 60 * 60
+    }
+
+    /// Highest sequence per device up to which a user's operations were compacted
+    async fn compacted_sequences(&self, user_id: i64) -> Result<VersionVector, AppError> {
+        let rows = sqlx::query("SELECT device_id, sequence FROM sync_compaction WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await?;
+
+        let mut counters = HashMap::new();
+        for row in rows {
+            counters.insert(row.try_get::<String, _>("device_id")?, row.try_get::<i64, _>("sequence")?);
+        }
+
+        Ok(VersionVector::from_hashmap(counters))
+    }
+
+    /// Fold every user's causally stable operations into entity snapshots,
+    /// drop stable tombstones, and reclaim up to `vacuum_pages` pages of the
+    /// space freed
+    pub async fn compact(&self, vacuum_pages: i64) -> Result<CompactionReport, AppError> {
+        let user_ids: Vec<i64> = sqlx::query_scalar("SELECT DISTINCT user_id FROM sync_operations")
+            .fetch_all(&self.db)
+            .await?;
+
+        self.expire_inactive_peers().await?;
+
+        let mut report = CompactionReport::default();
+        for user_id in user_ids {
+            report.add(&self.compact_user(user_id).await?);
+        }
+        report.bytes_reclaimed = self.reclaim_space(vacuum_pages).await?;
+
+        info!(
+            "Compacted sync log: {} operations folded into {} snapshots, {} tombstones expired, {} bytes reclaimed",
+            report.operations_folded, report.snapshots_written, report.tombstones_expired, report.bytes_reclaimed
+        );
+        Ok(report)
+    }
+
+    /// Compact a user's stable operations
+    pub async fn compact_user(&self, user_id: i64) -> Result<CompactionReport, AppError> {
+        let mut report = CompactionReport::default();
+        let stable = self.stable_vector(user_id).await?;
+        if stable.size() == 0 {
+            return Ok(report);
+        }
+
+        // Record what is folded away first, so the known sequences never go
+        // back even if compaction stops midway
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        for (device_id, sequence) in stable.to_hashmap() {
+            sqlx::query(
+                "INSERT INTO sync_compaction (user_id, device_id, sequence, updated_at) VALUES (?, ?, ?, ?)
+                 ON CONFLICT (user_id, device_id) DO UPDATE SET
+                    sequence = MAX(sequence, excluded.sequence),
+                    updated_at = excluded.updated_at"
+            )
+            .bind(user_id)
+            .bind(device_id)
+            .bind(sequence)
+            .bind(now)
+            .execute(&self.db)
+            .await?;
+        }
+
+        let entities = sqlx::query(
+            "SELECT DISTINCT entity_type, entity_id FROM sync_operations WHERE user_id = ? AND entity_id IS NOT NULL"
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        for entity in entities {
+            let entity_type: String = entity.try_get("entity_type")?;
+            let entity_id: String = entity.try_get("entity_id")?;
+            let rows = sqlx::query("SELECT * FROM sync_operations WHERE user_id = ? AND entity_type = ? AND entity_id = ?")
+                .bind(user_id)
+                .bind(&entity_type)
+                .bind(&entity_id)
+                .fetch_all(&self.db)
+                .await?;
+            let operations = rows
+                .into_iter()
+                .map(|row| self.row_to_operation(row))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match compaction::plan(&operations, &stable) {
+                Compaction::Keep => {},
+                Compaction::Fold { snapshot, removed } => {
+                    let payload_json = serde_json::to_string(&snapshot.payload)
+                        .map_err(|e| AppError::SyncError(format!("Failed to serialize payload: {}", e)))?;
+                    let vector_clock_json = serde_json::to_string(&snapshot.vector_clock)
+                        .map_err(|e| AppError::SyncError(format!("Failed to serialize vector clock: {}", e)))?;
+
+                    let mut tx = self.db.begin().await?;
+                    sqlx::query(
+                        "UPDATE sync_operations SET operation_type = ?, payload = ?, timestamp = ?, hlc = ?, vector_clock = ?
+                         WHERE id = ?"
+                    )
+                    .bind(snapshot.operation_type as i32)
+                    .bind(payload_json)
+                    .bind(snapshot.timestamp)
+                    .bind(snapshot.hlc.to_string())
+                    .bind(vector_clock_json)
+                    .bind(&snapshot.id)
+                    .execute(&mut *tx)
+                    .await?;
+                    for operation_id in &removed {
+                        sqlx::query("DELETE FROM sync_operations WHERE id = ?")
+                            .bind(operation_id)
+                            .execute(&mut *tx)
+                            .await?;
+                    }
+                    tx.commit().await?;
+
+                    debug!("Folded {} operations on {} {} into {}", removed.len() + 1, entity_type, entity_id, snapshot.id);
+                    report.operations_folded += removed.len() + 1;
+                    report.snapshots_written += 1;
+                },
+                Compaction::Expire { removed } => {
+                    let mut tx = self.db.begin().await?;
+                    for operation_id in &removed {
+                        sqlx::query("DELETE FROM sync_operations WHERE id = ?")
+                            .bind(operation_id)
+                            .execute(&mut *tx)
+                            .await?;
+                    }
+                    tx.commit().await?;
+
+                    debug!("Expired the tombstone of {} {}", entity_type, entity_id);
+                    report.tombstones_expired += 1;
+                    report.operations_expired += removed.len();
+                },
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Set the database up for incremental vacuums, so `reclaim_space` can
+    /// return the space compaction frees. The first time, this rewrites the
+    /// whole database with a full vacuum, so it is a step of its own, taken
+    /// when the app can afford it.
+    pub async fn enable_incremental_vacuum(&self) -> Result<(), AppError> {
+        let mut conn = self.db.acquire().await?;
+
+        // 2 is INCREMENTAL
+        let auto_vacuum: i64 = sqlx::query_scalar("PRAGMA auto_vacuum").fetch_one(&mut *conn).await?;
+        if auto_vacuum != 2 {
+            info!("Switching the database to incremental vacuums");
+            sqlx::query("PRAGMA auto_vacuum = INCREMENTAL").execute(&mut *conn).await?;
+            sqlx::query("VACUUM").execute(&mut *conn).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Return up to `max_pages` free pages of the database to the file
+    /// system, in bytes. Nothing is returned before the database was set up
+    /// with `enable_incremental_vacuum`.
+    pub async fn reclaim_space(&self, max_pages: i64) -> Result<i64, AppError> {
+        let mut conn = self.db.acquire().await?;
+        let auto_vacuum: i64 = sqlx::query_scalar("PRAGMA auto_vacuum").fetch_one(&mut *conn).await?;
+        if auto_vacuum != 2 {
+            debug!("Not reclaiming space, the database is not set up for incremental vacuums");
+            return Ok(0);
+        }
+
+        let page_size: i64 = sqlx::query_scalar("PRAGMA page_size").fetch_one(&mut *conn).await?;
+        let before: i64 = sqlx::query_scalar("PRAGMA page_count").fetch_one(&mut *conn).await?;
+        sqlx::query(&format!("PRAGMA incremental_vacuum({})", max_pages.max(0)))
+            .execute(&mut *conn)
+            .await?;
+
+        let after: i64 = sqlx::query_scalar("PRAGMA page_count").fetch_one(&mut *conn).await?;
+        Ok(((before - after) * page_size).max(0))
+    }
+
+    // Get pending operations to sync
+    pub async fn get_pending_operations(&self, limit: i64) -> Result<Vec<SyncOperation>, AppError> {
+        let rows = sqlx::query("SELECT * FROM sync_operations WHERE synced = 0 ORDER BY hlc LIMIT ?")
+            .bind(limit)
+            .fetch_all(&self.db)
+            .await?;
+
+        rows.into_iter().map(|row| self.row_to_operation(row)).collect()
+    }
+
+    /// Mark operations as synced, those of `user_id` only
+    pub async fn mark_user_operations_synced(&self, user_id: i64, operation_ids: &[String]) -> Result<(), AppError> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+        for id in operation_ids {
+            sqlx::query("UPDATE sync_operations SET synced = 1, synced_at = ? WHERE id = ? AND user_id = ?")
+                .bind(now)
+                .bind(id)
+                .bind(user_id)
+                .execute(&self.db)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether paired devices may sync with this one over the LAN. Off
+    /// until the user turns it on, and for engines not opened with `open`.
+    pub async fn lan_sync_enabled(&self) -> Result<bool, AppError> {
+        let enabled: Option<bool> = sqlx::query_scalar("SELECT lan_sync FROM sync_device WHERE id = 1")
+            .fetch_optional(&self.db)
+            .await?;
+
+        Ok(enabled.unwrap_or(false))
+    }
+
+    /// Turn LAN sync on or off; the listener is bound accordingly on the
+    /// next launch
+    pub async fn set_lan_sync_enabled(&self, enabled: bool) -> Result<(), AppError> {
+        sqlx::query("UPDATE sync_device SET lan_sync = ? WHERE id = 1")
+            .bind(enabled)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Pair a device with this one for syncing a user's operations over the
+    /// LAN, returning the token the device presents in its `Hello`
+    pub async fn create_pairing(&self, user_id: i64) -> Result<String, AppError> {
+        let token = hex::encode(rand::random::<[u8; 32]>());
+
+        sqlx::query("INSERT INTO sync_pairings (user_id, token_hash, created_at) VALUES (?, ?, ?)")
+            .bind(user_id)
+            .bind(pairing_hash(&token))
+            .bind(time::OffsetDateTime::now_utc().unix_timestamp())
+            .execute(&self.db)
+            .await?;
+
+        Ok(token)
+    }
+
+    /// Whether a device presenting `token` was paired for a user's operations
+    pub async fn is_paired(&self, user_id: i64, token: &str) -> Result<bool, AppError> {
+        let row = sqlx::query("SELECT 1 FROM sync_pairings WHERE user_id = ? AND token_hash = ?")
+            .bind(user_id)
+            .bind(pairing_hash(token))
+            .fetch_optional(&self.db)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
     // Mark operations as synced
     pub async fn mark_as_synced(&self, operation_ids: &[String]) -> Result<(), AppError> {
         let now = time::OffsetDateTime::now_utc().unix_timestamp();
@@ -262,10 +752,21 @@ impl SyncEngine {
 
     // Apply operations from a received sync batch
     pub async fn apply_sync_batch(&self, batch: SyncBatch) -> Result<(), AppError> {
+        // Compare the sender's wall clock, read when it built the batch, and
+        // move the hybrid logical clock past every stamp received. A batch
+        // stamped too far ahead is rejected whole.
+        {
+            let mut hlc = self.hlc.lock().await;
+            hlc.check_drift(&batch.device_id, batch.timestamp * 1000);
+            for operation in &batch.operations {
+                hlc.observe(&operation.hlc).map_err(AppError::SyncError)?;
+            }
+        }
+
         let mut clock = self.vector_clock.lock().await;
 
         // Merge vector clocks using VersionVector
-        let remote_vv = VersionVector::from_hashmap(batch.vector_clock);
+        let remote_vv = VersionVector::from_hashmap(batch.vector_clock.clone());
         clock.merge(&remote_vv);
 
         // Prune inactive entries if enabled
@@ -278,103 +779,64 @@ impl SyncEngine {
 
         info!("Merged vector clock with remote: {:?}", clock);
 
+        // Operations folded away by compaction are not stored again
+        let compacted = self.compacted_sequences(batch.user_id).await?;
+
         // For large batches, use optimized batch processing
         if batch.operations.len() > self.max_batch_size {
-            return self.apply_large_sync_batch(batch).await;
+            return self.apply_large_sync_batch(batch, &compacted).await;
         }
 
         // Process operations with conflict resolution
         for remote_op in batch.operations {
-            // Find any conflicting operations
-            let conflicts = self.find_conflicts(&remote_op).await?;
-
-            if conflicts.is_empty() {
-                // No conflicts, just store the operation
-                debug!("No conflicts found for operation {}", remote_op.id);
-                self.store_operation(&remote_op).await?;
-            } else {
-                // Resolve conflicts
-                info!("Found {} conflicts for operation {}", conflicts.len(), remote_op.id);
-                self.resolve_conflicts(remote_op, conflicts).await?;
-            }
+            self.apply_operation(remote_op, &compacted).await?;
         }
 
         Ok(())
     }
 
-    // Apply a large sync batch using optimized batch processing
-    async fn apply_large_sync_batch(&self, batch: SyncBatch) -> Result<(), AppError> {
-        info!("Processing large sync batch with {} operations", batch.operations.len());
-
-        // Split the batch into smaller chunks
-        for chunk in batch.operations.chunks(self.max_batch_size) {
-            // First, find all local operations that might conflict with this chunk
-            let mut potential_conflicts = Vec::new();
-
-            // Get unique entity types and IDs in this chunk
-            let mut entity_types = HashSet::new();
-            let mut entity_ids = HashSet::new();
-
-            for op in chunk {
-                entity_types.insert(op.entity_type.clone());
-                if let Some(id) = &op.entity_id {
-                    entity_ids.insert(id.clone());
-                }
-            }
-
-            // Query for potential conflicts
-            for entity_type in &entity_types {
-                let mut query = "SELECT * FROM sync_operations WHERE entity_type = ?".to_string();
-                let mut params = vec![entity_type.clone()];
-
-                if !entity_ids.is_empty() {
-                    query.push_str(" AND entity_id IN (");
-                    for (i, _) in entity_ids.iter().enumerate() {
-                        if i > 0 {
-                            query.push_str(", ?");
-                        } else {
-                            query.push_str("?");
-                        }
-                    }
-                    query.push_str(")");
-
-                    for id in &entity_ids {
-                        params.push(id.clone());
-                    }
-                }
-
-                // Execute the query
-                let rows = sqlx::query(&query)
-                    .bind_all(params)
-                    .fetch_all(&self.db)
-                    .await?;
-
-                // Convert rows to operations
-                for row in rows {
-                    let op = self.row_to_operation(row)?;
-                    potential_conflicts.push(op);
-                }
-            }
+    // Store a remote operation, resolving its conflicts with stored ones,
+    // unless it was compacted or is already stored
+    async fn apply_operation(&self, remote_op: SyncOperation, compacted: &VersionVector) -> Result<(), AppError> {
+        if remote_op.sequence() <= compacted.get(&remote_op.device_id) {
+            debug!("Skipping operation {} already compacted", remote_op.id);
+            return Ok(());
+        }
 
-            // Combine remote operations and potential conflicts
-            let mut all_operations = Vec::new();
-            all_operations.extend(chunk.to_vec());
-            all_operations.extend(potential_conflicts);
+        // Operations resent after an interrupted exchange are already stored
+        if self.has_operation(&remote_op.id).await? {
+            debug!("Skipping operation {} already stored", remote_op.id);
+            return Ok(());
+        }
 
-            // Use batch conflict resolution
-            let resolved_ops = self.conflict_resolver.resolve_conflicts_batch(&all_operations);
+        // Find any conflicting operations
+        let conflicts = self.find_conflicts(&remote_op).await?;
+
+        if conflicts.is_empty() {
+            // No conflicts, just store the operation
+            debug!("No conflicts found for operation {}", remote_op.id);
+            self.store_operation(&remote_op).await
+        } else {
+            // Resolve conflicts, keeping those that dropped a change for review
+            info!("Found {} conflicts for operation {}", conflicts.len(), remote_op.id);
+            self.resolve_conflicts(remote_op, conflicts).await
+        }
+    }
 
-            // Store the resolved operations
-            for op in resolved_ops {
-                // Only store operations that aren't already in the database
-                let existing = sqlx::query!("SELECT id FROM sync_operations WHERE id = ?", op.id)
-                    .fetch_optional(&self.db)
-                    .await?;
+    // Apply a large sync batch chunk by chunk. Each operation goes through
+    // the same checks and conflict resolution as in smaller batches, so
+    // compacted operations are skipped and conflicts that dropped a change
+    // are kept for review here too.
+    async fn apply_large_sync_batch(&self, batch: SyncBatch, compacted: &VersionVector) -> Result<(), AppError> {
+        info!("Processing large sync batch with {} operations", batch.operations.len());
 
-                if existing.is_none() {
-                    self.store_operation(&op).await?;
-                }
+        let chunks = batch.operations.chunks(self.max_batch_size.max(1));
+        let chunk_count = chunks.len();
+        for (index, chunk) in chunks.enumerate() {
+            for remote_op in chunk {
+                self.apply_operation(remote_op.clone(), compacted).await?;
             }
+            debug!("Applied chunk {} of {} of large sync batch", index + 1, chunk_count);
         }
 
         Ok(())
@@ -382,102 +844,127 @@ impl SyncEngine {
 
     // Find operations that might conflict with a given operation
     async fn find_conflicts(&self, operation: &SyncOperation) -> Result<Vec<SyncOperation>, AppError> {
-        // This is a simplified conflict detection
-        // In a real implementation, this would be more sophisticated
-
-        match &operation.entity_id {
-            Some(entity_id) => {
-                // Find operations for the same entity
-                let rows = sqlx::query!(
-                    r#"
-                    SELECT id, device_id, user_id, operation_type, entity_type, entity_id,
-                           payload, timestamp, vector_clock, synced, synced_at
-                    FROM sync_operations
-                    WHERE entity_type = ? AND entity_id = ?
-                    "#,
-                    operation.entity_type,
-                    entity_id
-                )
-                .fetch_all(&self.db)
-                .await?;
+        let conflicts = self
+            .entity_operations(operation)
+            .await?
+            .into_iter()
+            .filter(|local_op| ConflictResolver::detect_conflict(local_op, operation).is_some())
+            .collect();
+
+        Ok(conflicts)
+    }
 
-                let mut conflicts = Vec::new();
-
-                for row in rows {
-                    // Parse the row into a SyncOperation
-                    // (Similar code as in get_pending_operations)
-                    // Add to conflicts list if it conflicts with the operation
-                    // This is just an outline - you'd need to implement the actual parsing
-
-                    let op_type = match row.operation_type {
-                        0 => OperationType::Create,
-                        1 => OperationType::Update,
-                        2 => OperationType::Delete,
-                        3 => OperationType::Reference,
-                        _ => continue, // Skip unknown operation types
-                    };
-
-                    let vector_clock: HashMap<String, i64> = serde_json::from_str(&row.vector_clock)
-                        .map_err(|e| AppError::SyncError(format!("Failed to deserialize vector clock: {}", e)))?;
-
-                    let payload: serde_json::Value = serde_json::from_str(&row.payload)
-                        .map_err(|e| AppError::SyncError(format!("Failed to deserialize payload: {}", e)))?;
-
-                    let local_op = SyncOperation {
-                        id: row.id,
-                        device_id: row.device_id,
-                        user_id: row.user_id,
-                        operation_type: op_type,
-                        entity_type: row.entity_type,
-                        entity_id: row.entity_id,
-                        payload,
-                        timestamp: row.timestamp,
-                        vector_clock,
-                        synced: row.synced != 0,
-                        synced_at: row.synced_at,
-                    };
-
-                    // Check if there's a conflict
-                    if ConflictResolver::detect_conflict(&local_op, operation).is_some() {
-                        conflicts.push(local_op);
-                    }
-                }
+    // Stored operations on the same entity as a given operation
+    async fn entity_operations(&self, operation: &SyncOperation) -> Result<Vec<SyncOperation>, AppError> {
+        let entity_id = match &operation.entity_id {
+            Some(entity_id) => entity_id,
+            None => return Ok(Vec::new()),
+        };
 
-                Ok(conflicts)
-            },
-            None => Ok(Vec::new()),
-        }
+        let rows = sqlx::query("SELECT * FROM sync_operations WHERE entity_type = ? AND entity_id = ?")
+            .bind(&operation.entity_type)
+            .bind(entity_id)
+            .fetch_all(&self.db)
+            .await?;
+
+        rows.into_iter().map(|row| self.row_to_operation(row)).collect()
     }
 
     // Resolve conflicts between operations
     async fn resolve_conflicts(&self, remote_op: SyncOperation, conflicts: Vec<SyncOperation>) -> Result<(), AppError> {
         for local_op in conflicts {
+            let history = self.entity_operations(&remote_op).await?;
+            let mut conflict = SyncConflict::new(&history, &local_op, &remote_op);
+
             match ConflictResolver::resolve_conflict(&local_op, &remote_op) {
                 ConflictResolution::KeepFirst => {
                     // Keep the local operation, discard remote
                     // Nothing to do, as local operation is already stored
+                    conflict.applied = conflict.local.clone();
                 },
                 ConflictResolution::KeepSecond => {
                     // Replace local with remote
                     self.delete_operation(&local_op.id).await?;
                     self.store_operation(&remote_op).await?;
+                    conflict.applied = conflict.remote.clone();
                 },
                 ConflictResolution::Merge => {
-                    // Merge operations
-                    let merged_op = ConflictResolver::merge_updates(&local_op, &remote_op);
+                    // Merge operations, field by field over the entity's history
+                    let merged_op = ConflictResolver::merge_with_history(&history, &local_op, &remote_op);
                     self.delete_operation(&local_op.id).await?;
+                    // A merge with an earlier conflict may already stand in for the remote operation
+                    self.delete_operation(&merged_op.id).await?;
                     self.store_operation(&merged_op).await?;
+                    conflict.applied = merged_op.payload.clone();
                 },
                 ConflictResolution::KeepBoth => {
                     // Store both operations
                     self.store_operation(&remote_op).await?;
+                    continue;
                 },
             }
+
+            // Keep conflicts whose resolution dropped a change for review
+            if conflict.lost_changes() {
+                info!("Conflict on {} {} dropped changes, keeping it for review", conflict.entity_type, conflict.entity_id);
+                self.conflict_queue.record(&conflict).await?;
+            }
         }
 
         Ok(())
     }
 
+    /// Resolve a conflict kept for review with the version the user chose,
+    /// queueing the operation that brings the entity to it
+    pub async fn resolve_review_conflict(
+        &self,
+        conflict_id: &str,
+        choice: ConflictChoice,
+    ) -> Result<SyncConflict, AppError> {
+        let conflict = self.conflict_queue.get(conflict_id).await?;
+        if conflict.status == ConflictStatus::Resolved {
+            return Err(AppError::ValidationError(format!("Sync conflict {} is already resolved", conflict_id)));
+        }
+
+        let chosen = conflict.chosen(&choice);
+        let (operation_type, payload) = match (&chosen, &conflict.applied) {
+            (serde_json::Value::Null, _) => (OperationType::Delete, serde_json::Value::Null),
+            (serde_json::Value::Object(_), serde_json::Value::Null) => (OperationType::Create, chosen.clone()),
+            (serde_json::Value::Object(_), applied) => {
+                (OperationType::Update, review::resolution_payload(applied, &chosen))
+            },
+            _ => {
+                return Err(AppError::ValidationError(
+                    "A merged version must be an object of fields, or null to delete".to_string(),
+                ))
+            },
+        };
+
+        // Claim the conflict first, so that two resolutions racing each
+        // other cannot both queue an operation
+        self.conflict_queue.mark_resolved(conflict_id, &choice).await?;
+        let operation = match self
+            .queue_operation(
+                conflict.user_id,
+                operation_type,
+                &conflict.entity_type,
+                Some(&conflict.entity_id),
+                payload,
+            )
+            .await
+        {
+            Ok(operation) => operation,
+            Err(e) => {
+                self.conflict_queue.reopen(conflict_id).await?;
+                return Err(e);
+            }
+        };
+        self.conflict_queue.set_resolution_operation(conflict_id, &operation.id).await?;
+
+        info!("Resolved sync conflict {} with operation {}", conflict_id, operation.id);
+        self.conflict_queue.get(conflict_id).await
+    }
+
     // Delete an operation by ID
     async fn delete_operation(&self, operation_id: &str) -> Result<(), AppError> {
         sqlx::query!(
@@ -503,6 +990,7 @@ impl SyncEngine {
         let entity_id: Option<String> = row.try_get("entity_id")?;
         let payload_str: String = row.try_get("payload")?;
         let timestamp: i64 = row.try_get("timestamp")?;
+        let hlc: String = row.try_get("hlc")?;
         let vector_clock_str: String = row.try_get("vector_clock")?;
         let synced: i64 = row.try_get("synced")?;
         let synced_at: Option<i64> = row.try_get("synced_at")?;
@@ -521,6 +1009,13 @@ impl SyncEngine {
         let payload: serde_json::Value = serde_json::from_str(&payload_str)
             .map_err(|e| AppError::SyncError(format!("Failed to deserialize payload: {}", e)))?;
 
+        // Operations stored before they carried a stamp get one from their timestamp
+        let hlc = if hlc.is_empty() {
+            HlcTimestamp::from_unix_timestamp(timestamp, &device_id)
+        } else {
+            hlc.parse().map_err(AppError::SyncError)?
+        };
+
         Ok(SyncOperation {
             id,
             device_id,
@@ -530,9 +1025,15 @@ impl SyncEngine {
             entity_id,
             payload,
             timestamp,
+            hlc,
             vector_clock,
             synced: synced != 0,
             synced_at,
         })
     }
-}
\ No newline at end of file
+}
+
+/// Pairing tokens are stored hashed, so reading the database does not pair
+fn pairing_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,203 @@
+// Hybrid logical clocks
+//
+// A hybrid logical clock stamps each event with the wall-clock time, a
+// logical counter and the device, such that a stamp is always later than
+// those of the events the device had seen, however skewed its clock: a
+// device receiving a stamp ahead of its own clock carries on from it rather
+// than going back in time. Stamps compare by physical time, then counter,
+// then device ID, a total order that conflict resolution breaks ties with.
+//
+// Clocks of peers are also compared with this device's whenever a batch
+// arrives, and those further apart than a threshold are reported. Stamps
+// further ahead of this device's clock than the threshold are rejected, so
+// a peer with a clock far in the future cannot drag every later stamp along.
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use time::OffsetDateTime;
+
+/// Drift from a peer's clock above which it is reported, in milliseconds
+pub const DEFAULT_MAX_DRIFT_MS: i64 = 60_000;
+
+/// Stamp of a hybrid logical clock
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct HlcTimestamp {
+    /// Milliseconds since the Unix epoch
+    pub physical: i64,
+    /// Orders stamps of the same millisecond
+    pub logical: u32,
+    pub device_id: String,
+}
+
+impl HlcTimestamp {
+    pub fn new(physical: i64, logical: u32, device_id: &str) -> Self {
+        Self {
+            physical,
+            logical,
+            device_id: device_id.to_string(),
+        }
+    }
+
+    /// Stamp of an event at a wall-clock time in seconds, for operations
+    /// made without a clock
+    pub fn from_unix_timestamp(seconds: i64, device_id: &str) -> Self {
+        Self::new(seconds * 1000, 0, device_id)
+    }
+}
+
+/// Stored as text that sorts in stamp order
+impl fmt::Display for HlcTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:013}-{:010}-{}", self.physical, self.logical, self.device_id)
+    }
+}
+
+impl FromStr for HlcTimestamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, '-');
+        let (Some(physical), Some(logical), Some(device_id)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(format!("Invalid hybrid logical clock stamp: {}", s));
+        };
+
+        Ok(Self {
+            physical: physical.parse().map_err(|e| format!("Invalid physical time in {}: {}", s, e))?,
+            logical: logical.parse().map_err(|e| format!("Invalid logical counter in {}: {}", s, e))?,
+            device_id: device_id.to_string(),
+        })
+    }
+}
+
+/// A peer whose clock is off from this device's
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClockDrift {
+    pub device_id: String,
+    /// How far the peer's clock is ahead, or behind if negative, in milliseconds
+    pub offset_ms: i64,
+    /// When it was last observed, in milliseconds since the Unix epoch
+    pub observed_at: i64,
+}
+
+pub struct HybridLogicalClock {
+    device_id: String,
+    last: HlcTimestamp,
+    max_drift_ms: i64,
+    drifts: HashMap<String, ClockDrift>,
+}
+
+impl HybridLogicalClock {
+    pub fn new(device_id: &str) -> Self {
+        Self {
+            device_id: device_id.to_string(),
+            last: HlcTimestamp::default(),
+            max_drift_ms: DEFAULT_MAX_DRIFT_MS,
+            drifts: HashMap::new(),
+        }
+    }
+
+    pub fn with_max_drift(mut self, max_drift_ms: i64) -> Self {
+        self.max_drift_ms = max_drift_ms;
+        self
+    }
+
+    /// The last stamp issued
+    pub fn last(&self) -> &HlcTimestamp {
+        &self.last
+    }
+
+    /// Stamp a local event
+    pub fn now(&mut self) -> HlcTimestamp {
+        self.now_at(wall_clock_ms())
+    }
+
+    /// Stamp a local event with the wall clock reading `wall_ms`
+    pub fn now_at(&mut self, wall_ms: i64) -> HlcTimestamp {
+        let physical = wall_ms.max(self.last.physical);
+        let logical = if physical == self.last.physical { self.last.logical + 1 } else { 0 };
+
+        self.last = HlcTimestamp::new(physical, logical, &self.device_id);
+        self.last.clone()
+    }
+
+    /// Move past a stamp received from another device, unless it is further
+    /// ahead of the wall clock than the drift threshold
+    pub fn observe(&mut self, remote: &HlcTimestamp) -> Result<(), String> {
+        self.observe_at(remote, wall_clock_ms())
+    }
+
+    /// Move past a stamp received from another device, with the wall clock
+    /// reading `wall_ms`. A stamp further ahead than the drift threshold is
+    /// rejected and leaves the clock as it was.
+    pub fn observe_at(&mut self, remote: &HlcTimestamp, wall_ms: i64) -> Result<(), String> {
+        let ahead_ms = remote.physical - wall_ms;
+        if ahead_ms > self.max_drift_ms {
+            return Err(format!(
+                "Stamp {} of device {} is {} ms ahead of this device's clock",
+                remote, remote.device_id, ahead_ms
+            ));
+        }
+
+        self.advance(remote, wall_ms);
+        Ok(())
+    }
+
+    /// Move past a stamp this device stored, however far ahead of the wall
+    /// clock, so that stamps never go back across restarts
+    pub fn restore(&mut self, stored: &HlcTimestamp) {
+        self.advance(stored, wall_clock_ms())
+    }
+
+    fn advance(&mut self, remote: &HlcTimestamp, wall_ms: i64) {
+        let physical = wall_ms.max(self.last.physical).max(remote.physical);
+        let logical = match (physical == self.last.physical, physical == remote.physical) {
+            (true, true) => self.last.logical.max(remote.logical) + 1,
+            (true, false) => self.last.logical + 1,
+            (false, true) => remote.logical + 1,
+            (false, false) => 0,
+        };
+
+        self.last = HlcTimestamp::new(physical, logical, &self.device_id);
+    }
+
+    /// Compare a peer's wall clock, read at `remote_ms`, with this device's
+    pub fn check_drift(&mut self, device_id: &str, remote_ms: i64) {
+        self.check_drift_at(device_id, remote_ms, wall_clock_ms())
+    }
+
+    /// Compare a peer's wall clock, read at `remote_ms`, with this device's
+    /// reading `wall_ms`
+    pub fn check_drift_at(&mut self, device_id: &str, remote_ms: i64, wall_ms: i64) {
+        let offset_ms = remote_ms - wall_ms;
+        if offset_ms.abs() <= self.max_drift_ms {
+            self.drifts.remove(device_id);
+            return;
+        }
+
+        if !self.drifts.contains_key(device_id) {
+            warn!("Clock of device {} is off by {} ms", device_id, offset_ms);
+        }
+        self.drifts.insert(
+            device_id.to_string(),
+            ClockDrift {
+                device_id: device_id.to_string(),
+                offset_ms,
+                observed_at: wall_ms,
+            },
+        );
+    }
+
+    /// Peers whose clocks were last seen further off than the threshold
+    pub fn drifts(&self) -> Vec<ClockDrift> {
+        let mut drifts: Vec<_> = self.drifts.values().cloned().collect();
+        drifts.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        drifts
+    }
+}
+
+fn wall_clock_ms() -> i64 {
+    (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64
+}
@@ -3,6 +3,12 @@ pub mod conflicts;
 pub mod engine;
 pub mod version_vector;
 pub mod version_vector_sync;
+pub mod protocol;
+pub mod crdt;
+pub mod hlc;
+pub mod review;
+pub mod compaction;
+pub mod commands;
 
 #[cfg(test)]
 pub mod tests;
@@ -4,8 +4,10 @@ use std::collections::HashMap;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
+use super::hlc::HlcTimestamp;
+
 /// Represents a CRDT operation type
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum OperationType {
     Create,
     Update,
@@ -24,6 +26,9 @@ pub struct SyncOperation {
     pub entity_id: Option<String>,
     pub payload: Value,
     pub timestamp: i64,
+    /// Hybrid logical clock stamp, ordering concurrent operations
+    #[serde(default)]
+    pub hlc: HlcTimestamp,
     pub vector_clock: HashMap<String, i64>,
     pub synced: bool,
     pub synced_at: Option<i64>,
@@ -50,12 +55,19 @@ impl SyncOperation {
             entity_id: entity_id.map(ToString::to_string),
             payload,
             timestamp: now,
+            hlc: HlcTimestamp::from_unix_timestamp(now, device_id),
             vector_clock,
             synced: false,
             synced_at: None,
         }
     }
     
+    /// The operation's counter in its device's version vector entry,
+    /// which orders the operations of a device
+    pub fn sequence(&self) -> i64 {
+        self.vector_clock.get(&self.device_id).copied().unwrap_or(0)
+    }
+    
     // Factory methods for common operations
     
     pub fn create(
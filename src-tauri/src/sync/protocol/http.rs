@@ -0,0 +1,54 @@
+// Sync over HTTP, against the hub
+//
+// Every request is posted as JSON to the hub's exchange endpoint, which
+// answers with the response; see `api::sync::exchange_sync`.
+
+use async_trait::async_trait;
+
+use crate::core::errors::AppError;
+use super::{SyncRequest, SyncResponse, SyncTransport};
+
+/// Path of the hub's exchange endpoint
+pub const EXCHANGE_PATH: &str = "/api/sync/exchange";
+
+pub struct HttpTransport {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl HttpTransport {
+    /// Transport to the hub at `hub_url`, authenticating with a bearer token
+    pub fn new(hub_url: &str, token: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: format!("{}{}", hub_url.trim_end_matches('/'), EXCHANGE_PATH),
+            token,
+        }
+    }
+}
+
+#[async_trait]
+impl SyncTransport for HttpTransport {
+    async fn call(&mut self, request: SyncRequest) -> Result<SyncResponse, AppError> {
+        let mut builder = self.client.post(&self.url).json(&request);
+        if let Some(token) = &self.token {
+            builder = builder.bearer_auth(token);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| AppError::ExternalServiceError(format!("Failed to reach sync hub: {}", e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::ExternalServiceError(format!("Sync hub answered {}: {}", status, body)));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| AppError::SyncError(format!("Invalid response from sync hub: {}", e)))
+    }
+}
@@ -0,0 +1,344 @@
+// Device-to-device sync protocol
+//
+// A sync exchange runs between an initiating device and a peer, which is
+// either the hub, over HTTP, or another device on the LAN, over TCP. Both
+// transports carry the same requests, each answered by one response.
+//
+// The devices first swap version vectors of the user's operations they
+// hold. The initiator pushes the operations the peer lacks in batches,
+// ordered by origin device and sequence, and the peer acknowledges each
+// batch once applied, whereupon the initiator marks the operations synced.
+// The initiator then pulls what it lacks the same way. A batch's version
+// vector covers only the operations in it, so an interrupted exchange
+// leaves both sides knowing exactly how far it got. Each side also records
+// per-peer checkpoints of what was acknowledged and applied, and the next
+// exchange resumes from them. Operations that arrive twice are skipped.
+//
+// Each side also records what the other reported holding, in its `Hello`
+// and in each acknowledgement, and passes on in its own `Hello` what every
+// other device it heard of reported. Devices that only ever sync with the
+// hub thus learn how far the others got, which compaction waits for; see
+// `SyncEngine::stable_vector`. Relayed reports ahead of what the receiving
+// device holds are ignored.
+//
+// Each exchange is authenticated as one user: by bearer token against the
+// hub, and by a pairing token in the initiator's `Hello` over the LAN. The
+// peer only exchanges that user's operations.
+
+pub mod http;
+pub mod tcp;
+
+use async_trait::async_trait;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::core::errors::AppError;
+use super::engine::SyncEngine;
+use super::operations::{SyncBatch, SyncOperation};
+use super::version_vector::VersionVector;
+
+/// Version of the protocol spoken by this build
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Directions of peer checkpoints, see `SyncEngine::peer_checkpoint`
+pub const SENT: &str = "sent";
+pub const RECEIVED: &str = "received";
+
+/// What a device last reported holding of the user's operations
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceProgress {
+    /// Highest sequence held per origin device
+    pub held: HashMap<String, i64>,
+    /// When the device reported it, in seconds since the Unix epoch
+    pub reported_at: i64,
+}
+
+/// Opening message of each side
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub device_id: String,
+    pub user_id: i64,
+    /// Highest sequence held per origin device of the user's operations
+    pub version_vector: HashMap<String, i64>,
+    /// Token the initiator was paired with, see `SyncEngine::create_pairing`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pairing_token: Option<String>,
+    /// What the user's other devices reported holding, by device
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub progress: HashMap<String, DeviceProgress>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "body", rename_all = "snake_case")]
+pub enum SyncRequest {
+    Hello(Hello),
+    /// Operations for the peer to apply
+    Push(SyncBatch),
+    /// Ask for the user's operations missing from `known`
+    Pull {
+        user_id: i64,
+        known: HashMap<String, i64>,
+        limit: usize,
+    },
+    /// The operations of the last pulled batch were applied, after which
+    /// the device holds `version_vector`
+    Ack {
+        operation_ids: Vec<String>,
+        device_id: String,
+        version_vector: HashMap<String, i64>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "body", rename_all = "snake_case")]
+pub enum SyncResponse {
+    Hello(Hello),
+    /// The pushed operations were applied
+    Ack {
+        operation_ids: Vec<String>,
+        version_vector: HashMap<String, i64>,
+    },
+    /// The next batch of a pull, or `None` once nothing is missing
+    Batch(Option<SyncBatch>),
+    Done,
+    Error(String),
+}
+
+/// Carries requests to a peer and brings back its responses
+#[async_trait]
+pub trait SyncTransport: Send {
+    async fn call(&mut self, request: SyncRequest) -> Result<SyncResponse, AppError>;
+}
+
+/// Outcome of an exchange
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub peer_device_id: String,
+    pub operations_sent: usize,
+    pub operations_received: usize,
+    pub batches_sent: usize,
+    pub batches_received: usize,
+}
+
+/// Exchange a user's operations with a peer until each holds all of the
+/// other's
+pub async fn synchronize<T: SyncTransport + ?Sized>(
+    engine: &SyncEngine,
+    transport: &mut T,
+    user_id: i64,
+) -> Result<SyncReport, AppError> {
+    let hello = Hello {
+        protocol_version: PROTOCOL_VERSION,
+        device_id: engine.device_id().to_string(),
+        user_id,
+        version_vector: engine.known_sequences(user_id).await?.to_hashmap(),
+        pairing_token: None,
+        progress: engine.device_progress(user_id).await?,
+    };
+    let peer = match transport.call(SyncRequest::Hello(hello)).await? {
+        SyncResponse::Hello(peer) => peer,
+        other => return Err(unexpected(other)),
+    };
+    check_version(&peer)?;
+    save_progress(engine, user_id, &peer).await?;
+
+    let mut report = SyncReport { peer_device_id: peer.device_id.clone(), ..SyncReport::default() };
+    let limit = engine.max_batch_size().max(1);
+
+    // Push what the peer lacks. What it acknowledged before counts as held
+    // even if it has since dropped it.
+    let mut sent = VersionVector::from_hashmap(peer.version_vector);
+    sent.merge(&engine.peer_checkpoint(&peer.device_id, user_id, SENT).await?);
+    loop {
+        let operations = engine.operations_missing_from(user_id, &sent, limit).await?;
+        if operations.is_empty() {
+            break;
+        }
+
+        let covered = covered(&operations);
+        let count = operations.len();
+        let batch = SyncBatch::new(engine.device_id(), user_id, operations, covered.clone());
+        let (operation_ids, held) = match transport.call(SyncRequest::Push(batch)).await? {
+            SyncResponse::Ack { operation_ids, version_vector } => (operation_ids, version_vector),
+            other => return Err(unexpected(other)),
+        };
+
+        engine.mark_as_synced(&operation_ids).await?;
+        sent.apply_delta(&covered);
+        engine.save_peer_checkpoint(&peer.device_id, user_id, SENT, &sent).await?;
+        engine
+            .save_device_progress(user_id, &peer.device_id, &VersionVector::from_hashmap(held), now())
+            .await?;
+        report.operations_sent += count;
+        report.batches_sent += 1;
+        debug!("Pushed {} operations to {}", count, peer.device_id);
+    }
+
+    // Pull what this device lacks. What it applied before counts as held
+    // even if conflict resolution dropped it.
+    let mut received = engine.peer_checkpoint(&peer.device_id, user_id, RECEIVED).await?;
+    loop {
+        let known = engine.known_sequences(user_id).await?.merged_with(&received);
+        let request = SyncRequest::Pull { user_id, known: known.to_hashmap(), limit };
+        let batch = match transport.call(request).await? {
+            SyncResponse::Batch(Some(batch)) => batch,
+            SyncResponse::Batch(None) => break,
+            other => return Err(unexpected(other)),
+        };
+
+        let covered = batch.vector_clock.clone();
+        if batch.operations.is_empty() || known.dominates(&VersionVector::from_hashmap(covered.clone())) {
+            return Err(AppError::SyncError(format!("Peer {} sent a batch that brings nothing new", peer.device_id)));
+        }
+        let operation_ids = operation_ids(&batch.operations);
+        let count = operation_ids.len();
+        engine.apply_sync_batch(batch).await?;
+        received.apply_delta(&covered);
+        engine.save_peer_checkpoint(&peer.device_id, user_id, RECEIVED, &received).await?;
+
+        let ack = SyncRequest::Ack {
+            operation_ids,
+            device_id: engine.device_id().to_string(),
+            version_vector: engine.known_sequences(user_id).await?.merged_with(&received).to_hashmap(),
+        };
+        match transport.call(ack).await? {
+            SyncResponse::Done => {}
+            other => return Err(unexpected(other)),
+        }
+        report.operations_received += count;
+        report.batches_received += 1;
+        debug!("Pulled {} operations from {}", count, peer.device_id);
+    }
+
+    info!(
+        "Synced with {}: {} operations sent, {} received",
+        peer.device_id, report.operations_sent, report.operations_received
+    );
+    Ok(report)
+}
+
+/// Answer a request from a peer running `synchronize`, authenticated as
+/// `user_id`
+pub async fn handle_request(engine: &SyncEngine, user_id: i64, request: SyncRequest) -> Result<SyncResponse, AppError> {
+    check_user(&request, user_id)?;
+
+    match request {
+        SyncRequest::Hello(hello) => {
+            check_version(&hello)?;
+            save_progress(engine, user_id, &hello).await?;
+            Ok(SyncResponse::Hello(Hello {
+                protocol_version: PROTOCOL_VERSION,
+                device_id: engine.device_id().to_string(),
+                user_id,
+                version_vector: engine.known_sequences(user_id).await?.to_hashmap(),
+                pairing_token: None,
+                progress: engine.device_progress(user_id).await?,
+            }))
+        }
+        SyncRequest::Push(batch) => {
+            let operation_ids = operation_ids(&batch.operations);
+            engine.apply_sync_batch(batch).await?;
+            Ok(SyncResponse::Ack {
+                operation_ids,
+                version_vector: engine.known_sequences(user_id).await?.to_hashmap(),
+            })
+        }
+        SyncRequest::Pull { known, limit, .. } => {
+            let limit = limit.clamp(1, engine.max_batch_size().max(1));
+            let operations = engine
+                .operations_missing_from(user_id, &VersionVector::from_hashmap(known), limit)
+                .await?;
+            if operations.is_empty() {
+                return Ok(SyncResponse::Batch(None));
+            }
+
+            let covered = covered(&operations);
+            Ok(SyncResponse::Batch(Some(SyncBatch::new(engine.device_id(), user_id, operations, covered))))
+        }
+        SyncRequest::Ack { operation_ids, device_id, version_vector } => {
+            engine.mark_user_operations_synced(user_id, &operation_ids).await?;
+            engine
+                .save_device_progress(user_id, &device_id, &VersionVector::from_hashmap(version_vector), now())
+                .await?;
+            Ok(SyncResponse::Done)
+        }
+    }
+}
+
+/// Record what the device saying `hello` holds, and what it relayed of the
+/// user's other devices
+async fn save_progress(engine: &SyncEngine, user_id: i64, hello: &Hello) -> Result<(), AppError> {
+    let held = VersionVector::from_hashmap(hello.version_vector.clone());
+    engine.save_device_progress(user_id, &hello.device_id, &held, now()).await?;
+
+    // A relayed report is only taken if it claims nothing this device has
+    // not received itself, so a peer cannot report others caught up ahead
+    // of time, and never as newer than now, so a skewed clock cannot keep
+    // a lost device from expiring
+    let known = engine.known_sequences(user_id).await?;
+    for (device_id, progress) in &hello.progress {
+        let held = VersionVector::from_hashmap(progress.held.clone());
+        if !held.is_dominated_by(&known) {
+            debug!("Ignoring progress of {} relayed by {}, ahead of this device", device_id, hello.device_id);
+            continue;
+        }
+        engine.save_device_progress(user_id, device_id, &held, progress.reported_at.min(now())).await?;
+    }
+    Ok(())
+}
+
+/// Reject requests for another user's operations than the authenticated one's
+fn check_user(request: &SyncRequest, user_id: i64) -> Result<(), AppError> {
+    let requested_user_id = match request {
+        SyncRequest::Hello(hello) => Some(hello.user_id),
+        SyncRequest::Push(batch) => Some(batch.user_id),
+        SyncRequest::Pull { user_id, .. } => Some(*user_id),
+        SyncRequest::Ack { .. } => None,
+    };
+    if requested_user_id.is_some_and(|id| id != user_id) {
+        return Err(AppError::AuthorizationError("User ID in sync request does not match authenticated user".to_string()));
+    }
+    if let SyncRequest::Push(batch) = request {
+        if batch.operations.iter().any(|operation| operation.user_id != user_id) {
+            return Err(AppError::AuthorizationError("Sync batch contains another user's operations".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Version vector covering exactly the given operations
+fn covered(operations: &[SyncOperation]) -> HashMap<String, i64> {
+    let mut covered = HashMap::new();
+    for operation in operations {
+        let sequence = covered.entry(operation.device_id.clone()).or_insert(0);
+        *sequence = operation.sequence().max(*sequence);
+    }
+    covered
+}
+
+fn operation_ids(operations: &[SyncOperation]) -> Vec<String> {
+    operations.iter().map(|operation| operation.id.clone()).collect()
+}
+
+fn now() -> i64 {
+    time::OffsetDateTime::now_utc().unix_timestamp()
+}
+
+fn check_version(hello: &Hello) -> Result<(), AppError> {
+    if hello.protocol_version != PROTOCOL_VERSION {
+        return Err(AppError::SyncError(format!(
+            "Device {} speaks sync protocol version {}, expected {}",
+            hello.device_id, hello.protocol_version, PROTOCOL_VERSION
+        )));
+    }
+    Ok(())
+}
+
+fn unexpected(response: SyncResponse) -> AppError {
+    match response {
+        SyncResponse::Error(message) => AppError::SyncError(message),
+        other => AppError::SyncError(format!("Unexpected response from peer: {:?}", other)),
+    }
+}
@@ -0,0 +1,163 @@
+// Sync over a direct TCP connection, between two devices on a LAN
+//
+// Each message is a frame of JSON preceded by its length as a 4-byte
+// big-endian integer. The connecting device sends requests and the
+// listening one answers each with a response on the same connection.
+//
+// The first request must be a `Hello` carrying a token the listening device
+// paired for the user, see `SyncEngine::create_pairing`; otherwise the
+// connection is answered with an error and closed. The connection then
+// exchanges that user's operations only, and acknowledges only operations
+// it pulled.
+
+use async_trait::async_trait;
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::core::errors::AppError;
+use super::super::engine::SyncEngine;
+use super::{handle_request, SyncRequest, SyncResponse, SyncTransport};
+
+/// Largest frame accepted, so a bad length cannot exhaust memory
+pub const MAX_FRAME_BYTES: usize = 64 * 1024 * 1024;
+
+/// Connection to a device listening with `serve`
+pub struct TcpTransport {
+    stream: TcpStream,
+    pairing_token: String,
+}
+
+impl TcpTransport {
+    /// Connect to a device that paired this one with `pairing_token`
+    pub async fn connect(addr: impl ToSocketAddrs, pairing_token: &str) -> Result<Self, AppError> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .map_err(|e| AppError::SyncError(format!("Failed to connect to peer: {}", e)))?;
+        stream.set_nodelay(true).ok();
+        Ok(Self { stream, pairing_token: pairing_token.to_string() })
+    }
+}
+
+#[async_trait]
+impl SyncTransport for TcpTransport {
+    async fn call(&mut self, mut request: SyncRequest) -> Result<SyncResponse, AppError> {
+        if let SyncRequest::Hello(hello) = &mut request {
+            hello.pairing_token = Some(self.pairing_token.clone());
+        }
+        write_frame(&mut self.stream, &request).await?;
+        read_frame(&mut self.stream)
+            .await?
+            .ok_or_else(|| AppError::SyncError("Peer closed the connection".to_string()))
+    }
+}
+
+/// Answer sync requests from devices connecting to `listener`, until it fails
+pub async fn serve(listener: TcpListener, engine: Arc<SyncEngine>) -> Result<(), AppError> {
+    loop {
+        let (stream, addr) = listener
+            .accept()
+            .await
+            .map_err(|e| AppError::SyncError(format!("Failed to accept sync connection: {}", e)))?;
+        debug!("Sync connection from {}", addr);
+
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_connection(stream, &engine).await {
+                warn!("Sync connection from {} failed: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn serve_connection(mut stream: TcpStream, engine: &SyncEngine) -> Result<(), AppError> {
+    let user_id = match authenticate(&mut stream, engine).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            write_frame(&mut stream, &SyncResponse::Error(e.to_string())).await?;
+            return Err(e);
+        }
+    };
+
+    // Operations of the batches pulled over this connection
+    let mut pulled = HashSet::new();
+    while let Some(request) = read_frame::<_, SyncRequest>(&mut stream).await? {
+        let response = match request {
+            SyncRequest::Ack { operation_ids, .. } if !operation_ids.iter().all(|id| pulled.contains(id)) => {
+                SyncResponse::Error("Acknowledged operations were not pulled".to_string())
+            }
+            request => handle_request(engine, user_id, request)
+                .await
+                .unwrap_or_else(|e| SyncResponse::Error(e.to_string())),
+        };
+        if let SyncResponse::Batch(Some(batch)) = &response {
+            pulled.extend(batch.operations.iter().map(|operation| operation.id.clone()));
+        }
+        write_frame(&mut stream, &response).await?;
+    }
+    Ok(())
+}
+
+/// Answer the opening `Hello` if it carries a pairing token for its user,
+/// returning the user, or `None` if the connection closed first
+async fn authenticate(stream: &mut TcpStream, engine: &SyncEngine) -> Result<Option<i64>, AppError> {
+    let hello = match read_frame::<_, SyncRequest>(stream).await? {
+        Some(SyncRequest::Hello(hello)) => hello,
+        Some(_) => return Err(AppError::AuthError("Sync connection must open with a Hello".to_string())),
+        None => return Ok(None),
+    };
+
+    let paired = match &hello.pairing_token {
+        Some(token) => engine.is_paired(hello.user_id, token).await?,
+        None => false,
+    };
+    if !paired {
+        return Err(AppError::AuthError(format!("Device {} is not paired for this user", hello.device_id)));
+    }
+
+    let user_id = hello.user_id;
+    let response = handle_request(engine, user_id, SyncRequest::Hello(hello))
+        .await
+        .unwrap_or_else(|e| SyncResponse::Error(e.to_string()));
+    write_frame(stream, &response).await?;
+    Ok(Some(user_id))
+}
+
+async fn write_frame<W: AsyncWrite + Unpin, T: Serialize>(writer: &mut W, message: &T) -> Result<(), AppError> {
+    let body = serde_json::to_vec(message)
+        .map_err(|e| AppError::SyncError(format!("Failed to serialize sync message: {}", e)))?;
+    if body.len() > MAX_FRAME_BYTES {
+        return Err(AppError::SyncError(format!("Sync message of {} bytes is too large", body.len())));
+    }
+
+    writer.write_u32(body.len() as u32).await.map_err(io_error)?;
+    writer.write_all(&body).await.map_err(io_error)?;
+    writer.flush().await.map_err(io_error)
+}
+
+/// Read the next frame, or `None` if the connection was closed between frames
+async fn read_frame<R: AsyncRead + Unpin, T: DeserializeOwned>(reader: &mut R) -> Result<Option<T>, AppError> {
+    let length = match reader.read_u32().await {
+        Ok(length) => length as usize,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(io_error(e)),
+    };
+    if length > MAX_FRAME_BYTES {
+        return Err(AppError::SyncError(format!("Sync message of {} bytes is too large", length)));
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await.map_err(io_error)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| AppError::SyncError(format!("Invalid sync message: {}", e)))
+}
+
+fn io_error(error: std::io::Error) -> AppError {
+    AppError::SyncError(format!("Sync connection failed: {}", error))
+}
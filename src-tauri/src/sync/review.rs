@@ -0,0 +1,408 @@
+// Review of conflicts that automatic resolution settled by dropping a change
+//
+// When an operation received from another device conflicts with a local
+// one and the resolution drops either side's change to a field, the engine
+// records the conflict with three versions of the entity: the ancestor both
+// devices had seen, and the local and remote versions, each rebuilt from
+// the operation log. Users go through the open conflicts, compare the
+// versions field by field and resolve each by picking a side or merging by
+// hand. A resolution is queued as an operation on the entity, so it reaches
+// other devices like any other edit.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Pool, Row, Sqlite};
+use std::collections::{BTreeSet, HashMap};
+use uuid::Uuid;
+
+use crate::core::errors::AppError;
+use super::crdt::{self, MergeSchema};
+use super::operations::{OperationType, SyncOperation};
+use super::version_vector::{CausalRelation, VersionVector};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStatus {
+    Open,
+    Resolved,
+}
+
+impl ConflictStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictStatus::Open => "open",
+            ConflictStatus::Resolved => "resolved",
+        }
+    }
+}
+
+/// How a user resolves a conflict
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "choice", content = "value", rename_all = "snake_case")]
+pub enum ConflictChoice {
+    Local,
+    Remote,
+    /// A version merged by hand, or null to delete the entity
+    Manual(Value),
+}
+
+impl ConflictChoice {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictChoice::Local => "local",
+            ConflictChoice::Remote => "remote",
+            ConflictChoice::Manual(_) => "manual",
+        }
+    }
+}
+
+/// A conflict and the versions of the entity it was between. A version is
+/// the entity's fields as a JSON object, or null if it was deleted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub id: String,
+    pub user_id: i64,
+    pub entity_type: String,
+    pub entity_id: String,
+    /// What both devices had seen, if anything
+    pub ancestor: Option<Value>,
+    pub local: Value,
+    pub remote: Value,
+    /// The version automatic resolution kept
+    pub applied: Value,
+    pub local_operation_id: String,
+    pub remote_operation_id: String,
+    pub status: ConflictStatus,
+    pub resolution: Option<String>,
+    /// The operation that carried the resolution
+    pub resolution_operation_id: Option<String>,
+    pub created_at: i64,
+    pub resolved_at: Option<i64>,
+}
+
+impl SyncConflict {
+    /// Conflict between a local and a remote operation on an entity, whose
+    /// other operations are in `history`, before any version was applied
+    pub fn new(history: &[SyncOperation], local_op: &SyncOperation, remote_op: &SyncOperation) -> Self {
+        let mut operations: Vec<SyncOperation> = history.to_vec();
+        operations.push(local_op.clone());
+        operations.push(remote_op.clone());
+
+        // The ancestor is what both operations had seen
+        let local_clock = &local_op.vector_clock;
+        let common: HashMap<String, i64> = remote_op
+            .vector_clock
+            .iter()
+            .filter_map(|(device, &counter)| local_clock.get(device).map(|&local| (device.clone(), local.min(counter))))
+            .collect();
+        let common = VersionVector::from_hashmap(common);
+        let ancestor = operations.iter().any(|operation| seen(operation, &common)).then(|| version(&operations, &common));
+
+        let local = match local_op.operation_type {
+            OperationType::Delete => Value::Null,
+            _ => version(&operations, &clock(local_op)),
+        };
+        let remote = match remote_op.operation_type {
+            OperationType::Delete => Value::Null,
+            _ => version(&operations, &clock(remote_op)),
+        };
+
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id: remote_op.user_id,
+            entity_type: remote_op.entity_type.clone(),
+            entity_id: remote_op.entity_id.clone().unwrap_or_default(),
+            ancestor,
+            local,
+            remote,
+            applied: Value::Null,
+            local_operation_id: local_op.id.clone(),
+            remote_operation_id: remote_op.id.clone(),
+            status: ConflictStatus::Open,
+            resolution: None,
+            resolution_operation_id: None,
+            created_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+            resolved_at: None,
+        }
+    }
+
+    /// Whether the applied version is missing a change either side made
+    pub fn lost_changes(&self) -> bool {
+        three_way_diff(self).iter().any(FieldDiff::lost)
+    }
+
+    /// The version a choice settles on
+    pub fn chosen(&self, choice: &ConflictChoice) -> Value {
+        match choice {
+            ConflictChoice::Local => self.local.clone(),
+            ConflictChoice::Remote => self.remote.clone(),
+            ConflictChoice::Manual(value) => value.clone(),
+        }
+    }
+}
+
+/// How a field differs between the versions of a conflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldChange {
+    Unchanged,
+    /// Changed on this device only
+    Local,
+    /// Changed on the other device only
+    Remote,
+    /// Changed the same way on both
+    Same,
+    /// Changed differently on both
+    Conflict,
+}
+
+/// A field in each version of a conflict, absent where the version lacks it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldDiff {
+    pub field: String,
+    pub ancestor: Option<Value>,
+    pub local: Option<Value>,
+    pub remote: Option<Value>,
+    pub applied: Option<Value>,
+    pub change: FieldChange,
+}
+
+impl FieldDiff {
+    /// Whether the applied version is missing a side's change to the field.
+    /// Changes on both sides are lost unless they were merged into a value
+    /// neither side had.
+    pub fn lost(&self) -> bool {
+        match self.change {
+            FieldChange::Local => self.applied != self.local,
+            FieldChange::Remote => self.applied != self.remote,
+            FieldChange::Conflict => self.applied == self.local || self.applied == self.remote,
+            FieldChange::Unchanged | FieldChange::Same => false,
+        }
+    }
+}
+
+/// A conflict with its field-by-field diff
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictDiff {
+    pub conflict: SyncConflict,
+    pub fields: Vec<FieldDiff>,
+}
+
+/// Compare the versions of a conflict field by field
+pub fn three_way_diff(conflict: &SyncConflict) -> Vec<FieldDiff> {
+    let versions = [conflict.ancestor.as_ref(), Some(&conflict.local), Some(&conflict.remote), Some(&conflict.applied)];
+    let fields: BTreeSet<&String> = versions
+        .iter()
+        .flatten()
+        .filter_map(|version| version.as_object())
+        .flat_map(|object| object.keys())
+        .collect();
+
+    fields
+        .into_iter()
+        .map(|field| {
+            let value = |version: Option<&Value>| version.and_then(|version| version.get(field)).cloned();
+            let ancestor = value(conflict.ancestor.as_ref());
+            let local = value(Some(&conflict.local));
+            let remote = value(Some(&conflict.remote));
+
+            let change = if local == remote {
+                if local == ancestor { FieldChange::Unchanged } else { FieldChange::Same }
+            } else if local == ancestor {
+                FieldChange::Remote
+            } else if remote == ancestor {
+                FieldChange::Local
+            } else {
+                FieldChange::Conflict
+            };
+
+            FieldDiff {
+                field: field.clone(),
+                applied: value(Some(&conflict.applied)),
+                ancestor,
+                local,
+                remote,
+                change,
+            }
+        })
+        .collect()
+}
+
+/// Payload of an update from the `current` version to the `chosen` one,
+/// clearing the fields only the current version has
+pub fn resolution_payload(current: &Value, chosen: &Value) -> Value {
+    let mut payload = chosen.as_object().cloned().unwrap_or_default();
+    if let Some(current) = current.as_object() {
+        for field in current.keys() {
+            payload.entry(field.clone()).or_insert(Value::Null);
+        }
+    }
+    Value::Object(payload)
+}
+
+/// Stored conflicts
+#[derive(Clone)]
+pub struct ConflictQueue {
+    db: Pool<Sqlite>,
+}
+
+impl ConflictQueue {
+    pub fn new(db: Pool<Sqlite>) -> Self {
+        Self { db }
+    }
+
+    pub async fn record(&self, conflict: &SyncConflict) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO sync_conflicts
+            (id, user_id, entity_type, entity_id, ancestor, local, remote, applied,
+             local_operation_id, remote_operation_id, status, resolution, resolution_operation_id,
+             created_at, resolved_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(&conflict.id)
+        .bind(conflict.user_id)
+        .bind(&conflict.entity_type)
+        .bind(&conflict.entity_id)
+        .bind(conflict.ancestor.as_ref().map(Value::to_string))
+        .bind(conflict.local.to_string())
+        .bind(conflict.remote.to_string())
+        .bind(conflict.applied.to_string())
+        .bind(&conflict.local_operation_id)
+        .bind(&conflict.remote_operation_id)
+        .bind(conflict.status.as_str())
+        .bind(&conflict.resolution)
+        .bind(&conflict.resolution_operation_id)
+        .bind(conflict.created_at)
+        .bind(conflict.resolved_at)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(&self, conflict_id: &str) -> Result<SyncConflict, AppError> {
+        let row = sqlx::query("SELECT * FROM sync_conflicts WHERE id = ?")
+            .bind(conflict_id)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Sync conflict {} not found", conflict_id)))?;
+
+        row_to_conflict(row)
+    }
+
+    /// A user's conflicts with the given status, or all of them, newest first
+    pub async fn list(&self, user_id: i64, status: Option<ConflictStatus>) -> Result<Vec<SyncConflict>, AppError> {
+        let rows = sqlx::query(
+            "SELECT * FROM sync_conflicts WHERE user_id = ? AND (? IS NULL OR status = ?)
+             ORDER BY created_at DESC, id"
+        )
+        .bind(user_id)
+        .bind(status.map(|status| status.as_str()))
+        .bind(status.map(|status| status.as_str()))
+        .fetch_all(&self.db)
+        .await?;
+
+        rows.into_iter().map(row_to_conflict).collect()
+    }
+
+    /// Mark an open conflict resolved, failing if it is not open, such as
+    /// when it was resolved meanwhile
+    pub async fn mark_resolved(&self, conflict_id: &str, choice: &ConflictChoice) -> Result<(), AppError> {
+        let result = sqlx::query(
+            "UPDATE sync_conflicts SET status = ?, resolution = ?, resolved_at = ?
+             WHERE id = ? AND status = ?"
+        )
+        .bind(ConflictStatus::Resolved.as_str())
+        .bind(choice.as_str())
+        .bind(time::OffsetDateTime::now_utc().unix_timestamp())
+        .bind(conflict_id)
+        .bind(ConflictStatus::Open.as_str())
+        .execute(&self.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::ValidationError(format!("Sync conflict {} is not open", conflict_id)));
+        }
+        Ok(())
+    }
+
+    /// Record the operation that carried a conflict's resolution
+    pub async fn set_resolution_operation(&self, conflict_id: &str, operation_id: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE sync_conflicts SET resolution_operation_id = ? WHERE id = ?")
+            .bind(operation_id)
+            .bind(conflict_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Open a resolved conflict again, when its resolution could not be queued
+    pub async fn reopen(&self, conflict_id: &str) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE sync_conflicts SET status = ?, resolution = NULL, resolution_operation_id = NULL, resolved_at = NULL
+             WHERE id = ?"
+        )
+        .bind(ConflictStatus::Open.as_str())
+        .bind(conflict_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn row_to_conflict(row: sqlx::sqlite::SqliteRow) -> Result<SyncConflict, AppError> {
+    let json = |column: &str| -> Result<Value, AppError> {
+        let text: String = row.try_get(column)?;
+        serde_json::from_str(&text)
+            .map_err(|e| AppError::SyncError(format!("Failed to deserialize conflict {}: {}", column, e)))
+    };
+
+    let ancestor = match row.try_get::<Option<String>, _>("ancestor")? {
+        Some(_) => Some(json("ancestor")?),
+        None => None,
+    };
+    let status = match row.try_get::<String, _>("status")?.as_str() {
+        "open" => ConflictStatus::Open,
+        "resolved" => ConflictStatus::Resolved,
+        other => return Err(AppError::SyncError(format!("Unknown conflict status: {}", other))),
+    };
+
+    Ok(SyncConflict {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        entity_type: row.try_get("entity_type")?,
+        entity_id: row.try_get("entity_id")?,
+        ancestor,
+        local: json("local")?,
+        remote: json("remote")?,
+        applied: json("applied")?,
+        local_operation_id: row.try_get("local_operation_id")?,
+        remote_operation_id: row.try_get("remote_operation_id")?,
+        status,
+        resolution: row.try_get("resolution")?,
+        resolution_operation_id: row.try_get("resolution_operation_id")?,
+        created_at: row.try_get("created_at")?,
+        resolved_at: row.try_get("resolved_at")?,
+    })
+}
+
+/// The entity as seen by a device at `clock`
+fn version(operations: &[SyncOperation], clock: &VersionVector) -> Value {
+    let writes: Vec<SyncOperation> = operations.iter().filter(|operation| seen(operation, clock)).cloned().collect();
+    Value::Object(crdt::merge_payloads(&writes, &MergeSchema::default()))
+}
+
+fn seen(operation: &SyncOperation, clock: &VersionVector) -> bool {
+    matches!(
+        self::clock(operation).causal_relation(clock),
+        CausalRelation::HappensBefore | CausalRelation::Identical
+    )
+}
+
+fn clock(operation: &SyncOperation) -> VersionVector {
+    VersionVector::from_hashmap(operation.vector_clock.clone())
+}
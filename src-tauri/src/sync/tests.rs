@@ -1,6 +1,10 @@
 use super::version_vector::{VersionVector, CausalRelation};
 use super::operations::{SyncOperation, OperationType};
 use super::conflicts::{ConflictResolver, ConflictResolution};
+use super::crdt::{self, MergeSchema};
+use super::hlc::{HlcTimestamp, HybridLogicalClock};
+use super::review::{self, FieldChange, SyncConflict};
+use super::compaction::{self, Compaction};
 use std::collections::HashMap;
 use serde_json::json;
 use uuid::Uuid;
@@ -181,6 +185,7 @@ mod tests {
             HashMap::from([("device2".to_string(), 1)]),
         );
         op2.timestamp = op1.timestamp + 100; // Make it later
+        op2.hlc = HlcTimestamp::from_unix_timestamp(op2.timestamp, "device2");
 
         // Resolve the conflict - delete should win because it's later
         let resolution = ConflictResolver::resolve_conflict(&op1, &op2);
@@ -188,6 +193,529 @@ mod tests {
     }
 }
 
+#[cfg(test)]
+mod hlc_tests {
+    use super::*;
+
+    #[test]
+    fn test_stamps_never_go_back() {
+        let mut clock = HybridLogicalClock::new("laptop");
+        assert_eq!(clock.now_at(1_000), HlcTimestamp::new(1_000, 0, "laptop"));
+        // The wall clock was set back
+        assert_eq!(clock.now_at(900), HlcTimestamp::new(1_000, 1, "laptop"));
+        assert_eq!(clock.now_at(2_000), HlcTimestamp::new(2_000, 0, "laptop"));
+    }
+
+    #[test]
+    fn test_stamps_follow_what_was_received() {
+        // A laptop whose clock is ten seconds ahead stamps an update
+        let mut skewed = HybridLogicalClock::new("laptop-a");
+        let update = skewed.now_at(3_600_000);
+
+        // A device that received it stamps later, whatever its own clock says
+        let mut clock = HybridLogicalClock::new("laptop-b");
+        clock.now_at(1_000);
+        clock.observe_at(&update, 3_590_000).unwrap();
+        let next = clock.now_at(3_590_001);
+        assert!(next > update);
+        assert_eq!(next, HlcTimestamp::new(3_600_000, 2, "laptop-b"));
+    }
+
+    #[test]
+    fn test_stamps_too_far_ahead_are_rejected() {
+        // A laptop whose clock is an hour ahead stamps an update
+        let mut skewed = HybridLogicalClock::new("laptop-a");
+        let update = skewed.now_at(3_600_000);
+
+        let mut clock = HybridLogicalClock::new("laptop-b").with_max_drift(60_000);
+        clock.now_at(1_000);
+        assert!(clock.observe_at(&update, 1_000).is_err());
+        assert_eq!(clock.now_at(1_001), HlcTimestamp::new(1_001, 0, "laptop-b"));
+
+        // Stamps this device stored are restored however far ahead
+        clock.restore(&update);
+        assert!(clock.now_at(1_002) > update);
+    }
+
+    #[test]
+    fn test_stamps_order_as_text() {
+        let stamps = vec![
+            HlcTimestamp::new(999, 7, "b"),
+            HlcTimestamp::new(1_000, 0, "b"),
+            HlcTimestamp::new(1_000, 1, "a"),
+            HlcTimestamp::new(1_000, 1, "b"),
+        ];
+        for pair in stamps.windows(2) {
+            assert!(pair[0] < pair[1]);
+            assert!(pair[0].to_string() < pair[1].to_string());
+        }
+
+        let stamp = HlcTimestamp::new(1_700_000_000_123, 4, "device-with-dashes");
+        assert_eq!(stamp.to_string().parse::<HlcTimestamp>().unwrap(), stamp);
+        assert!("not a stamp".parse::<HlcTimestamp>().is_err());
+    }
+
+    #[test]
+    fn test_drift_is_reported_beyond_threshold() {
+        let mut clock = HybridLogicalClock::new("laptop").with_max_drift(60_000);
+        clock.check_drift_at("laptop-b", 100_000, 50_000);
+        assert!(clock.drifts().is_empty());
+
+        clock.check_drift_at("laptop-b", 10_000, 200_000);
+        let drifts = clock.drifts();
+        assert_eq!(drifts.len(), 1);
+        assert_eq!((drifts[0].device_id.as_str(), drifts[0].offset_ms), ("laptop-b", -190_000));
+
+        // Reported until the clocks agree again
+        clock.check_drift_at("laptop-b", 300_000, 300_500);
+        assert!(clock.drifts().is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_updates_resolve_by_stamp() {
+        let mut op1 = create_test_operation(
+            "device1",
+            OperationType::Create,
+            "course",
+            Some("course123"),
+            json!({"name": "Math 101"}),
+            HashMap::from([("device1".to_string(), 1)]),
+        );
+        let mut op2 = create_test_operation(
+            "device2",
+            OperationType::Delete,
+            "course",
+            Some("course123"),
+            json!(null),
+            HashMap::from([("device2".to_string(), 1)]),
+        );
+
+        // The wall clock of device 1 is ahead, but its stamp is not
+        op1.timestamp += 3600;
+        op1.hlc = HlcTimestamp::new(1_000, 0, "device1");
+        op2.hlc = HlcTimestamp::new(1_000, 1, "device2");
+        assert!(matches!(ConflictResolver::resolve_conflict(&op1, &op2), ConflictResolution::KeepSecond));
+
+        // Equal times and counters fall to the device ID
+        op2.hlc = HlcTimestamp::new(1_000, 0, "device0");
+        assert!(matches!(ConflictResolver::resolve_conflict(&op1, &op2), ConflictResolution::KeepFirst));
+    }
+}
+
+#[cfg(test)]
+mod crdt_tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const DEVICES: usize = 3;
+
+    fn post_operation(
+        id: &str,
+        device_id: &str,
+        operation_type: OperationType,
+        payload: serde_json::Value,
+        vector_clock: HashMap<String, i64>,
+        timestamp: i64,
+    ) -> SyncOperation {
+        let mut operation = create_test_operation(device_id, operation_type, "post", Some("post-1"), payload, vector_clock);
+        operation.id = id.to_string();
+        operation.timestamp = timestamp;
+        operation.hlc = HlcTimestamp::from_unix_timestamp(timestamp, device_id);
+        operation
+    }
+
+    fn clock(entries: &[(&str, i64)]) -> HashMap<String, i64> {
+        entries.iter().map(|(device, counter)| (device.to_string(), *counter)).collect()
+    }
+
+    fn merge(operations: &[SyncOperation]) -> serde_json::Map<String, serde_json::Value> {
+        crdt::merge_payloads(operations, &MergeSchema::default())
+    }
+
+    #[test]
+    fn test_registers_merge_per_field() {
+        let create = post_operation("a", "device1", OperationType::Create, json!({"title": "Draft", "pinned": false}), clock(&[("device1", 1)]), 100);
+        let rename = post_operation("b", "device1", OperationType::Update, json!({"title": "Week 1"}), clock(&[("device1", 2)]), 300);
+        let pin = post_operation("c", "device2", OperationType::Update, json!({"pinned": true}), clock(&[("device1", 1), ("device2", 1)]), 200);
+
+        let merged = merge(&[create, rename, pin]);
+        assert_eq!(merged["title"], json!("Week 1"));
+        assert_eq!(merged["pinned"], json!(true));
+    }
+
+    #[test]
+    fn test_register_write_wins_over_what_it_had_seen() {
+        // The second write saw the first, so it wins despite an older clock
+        let first = post_operation("a", "device1", OperationType::Update, json!({"title": "First"}), clock(&[("device1", 1)]), 500);
+        let second = post_operation("b", "device2", OperationType::Update, json!({"title": "Second"}), clock(&[("device1", 1), ("device2", 1)]), 100);
+        assert_eq!(merge(&[first.clone(), second.clone()])["title"], json!("Second"));
+
+        // Concurrent writes go to the later timestamp
+        let concurrent = post_operation("c", "device3", OperationType::Update, json!({"title": "Third"}), clock(&[("device3", 1)]), 200);
+        assert_eq!(merge(&[first, second, concurrent])["title"], json!("Third"));
+    }
+
+    #[test]
+    fn test_sets_keep_concurrent_additions() {
+        let create = post_operation("a", "device1", OperationType::Create, json!({"tags": ["algebra", "draft"]}), clock(&[("device1", 1)]), 100);
+        // Device 1 removes "draft", device 2 concurrently tags the post "exam"
+        let publish = post_operation("b", "device1", OperationType::Update, json!({"tags": ["algebra"]}), clock(&[("device1", 2)]), 200);
+        let tag = post_operation("c", "device2", OperationType::Update, json!({"tags": {"add": ["exam"]}}), clock(&[("device1", 1), ("device2", 1)]), 200);
+
+        assert_eq!(merge(&[create.clone(), publish.clone(), tag.clone()])["tags"], json!(["algebra", "exam"]));
+
+        // A removal only covers the additions its device had seen
+        let readd = post_operation("d", "device3", OperationType::Update, json!({"tags": {"add": ["draft"]}}), clock(&[("device1", 1), ("device3", 1)]), 150);
+        assert_eq!(merge(&[create, publish, tag, readd])["tags"], json!(["algebra", "draft", "exam"]));
+    }
+
+    #[test]
+    fn test_text_keeps_concurrent_edits() {
+        let create = post_operation("a", "device1", OperationType::Create, json!({"content": "The quick fox"}), clock(&[("device1", 1)]), 100);
+        let edit1 = post_operation("b", "device1", OperationType::Update, json!({"content": "The quick brown fox"}), clock(&[("device1", 2)]), 200);
+        let edit2 = post_operation("c", "device2", OperationType::Update, json!({"content": "The quick fox jumps"}), clock(&[("device1", 1), ("device2", 1)]), 200);
+
+        assert_eq!(merge(&[create.clone(), edit1.clone(), edit2.clone()])["content"], json!("The quick brown fox jumps"));
+        assert_eq!(merge(&[edit2, create, edit1])["content"], json!("The quick brown fox jumps"));
+    }
+
+    #[test]
+    fn test_merge_updates_is_symmetric() {
+        let op1 = post_operation("a", "device1", OperationType::Update, json!({"title": "One", "tags": ["x"]}), clock(&[("device1", 1)]), 100);
+        let op2 = post_operation("b", "device2", OperationType::Update, json!({"title": "Two"}), clock(&[("device2", 1)]), 100);
+
+        let merged = ConflictResolver::merge_updates(&op1, &op2);
+        let reversed = ConflictResolver::merge_updates(&op2, &op1);
+        assert_eq!(merged.id, "b");
+        assert_eq!(merged.id, reversed.id);
+        assert_eq!(merged.payload, reversed.payload);
+        assert_eq!(merged.payload, json!({"title": "Two", "tags": ["x"]}));
+    }
+
+    #[derive(Debug, Clone)]
+    enum Edit {
+        Title(u8),
+        AddTag(u8),
+        RemoveTag(u8),
+        Insert(u8, char),
+        Delete(u8),
+        /// Receive everything another device holds
+        Sync(usize),
+    }
+
+    fn edit() -> impl Strategy<Value = Edit> {
+        prop_oneof![
+            any::<u8>().prop_map(Edit::Title),
+            (0u8..6).prop_map(Edit::AddTag),
+            any::<u8>().prop_map(Edit::RemoveTag),
+            (any::<u8>(), prop::sample::select(vec!['a', 'b', ' ', '\u{e9}'])).prop_map(|(at, c)| Edit::Insert(at, c)),
+            any::<u8>().prop_map(Edit::Delete),
+            (0..DEVICES).prop_map(Edit::Sync),
+        ]
+    }
+
+    struct Device {
+        id: String,
+        clock: HashMap<String, i64>,
+        known: Vec<SyncOperation>,
+    }
+
+    /// Operations of devices editing a post, each writing over what it had
+    /// seen, and syncing with each other now and then
+    fn simulate(edits: &[(usize, Edit, i64)]) -> Vec<SyncOperation> {
+        let create = post_operation(
+            "op-0",
+            "device0",
+            OperationType::Create,
+            json!({"title": "Draft", "tags": ["intro"], "content": "hello world"}),
+            clock(&[("device0", 1)]),
+            0,
+        );
+        let mut devices: Vec<Device> = (0..DEVICES)
+            .map(|i| Device { id: format!("device{}", i), clock: create.vector_clock.clone(), known: vec![create.clone()] })
+            .collect();
+        let mut operations = vec![create];
+
+        for (step, (device, edit, timestamp)) in edits.iter().enumerate() {
+            if let Edit::Sync(from) = edit {
+                let (known, from_clock) = (devices[*from].known.clone(), devices[*from].clock.clone());
+                let device = &mut devices[*device];
+                for operation in known {
+                    if !device.known.iter().any(|held| held.id == operation.id) {
+                        device.known.push(operation);
+                    }
+                }
+                for (id, counter) in from_clock {
+                    let entry = device.clock.entry(id).or_insert(0);
+                    *entry = (*entry).max(counter);
+                }
+                continue;
+            }
+
+            let device = &mut devices[*device];
+            let view = merge(&device.known);
+            let mut tags = view["tags"].as_array().cloned().unwrap_or_default();
+            let mut content: Vec<char> = view["content"].as_str().unwrap_or_default().chars().collect();
+            let payload = match edit {
+                Edit::Title(n) => json!({"title": format!("Title {}", n)}),
+                Edit::AddTag(n) => {
+                    tags.push(json!(format!("tag-{}", n)));
+                    json!({"tags": tags})
+                }
+                Edit::RemoveTag(n) if !tags.is_empty() => {
+                    let tag = tags[*n as usize % tags.len()].clone();
+                    json!({"tags": {"remove": [tag]}})
+                }
+                Edit::Insert(at, c) => {
+                    content.insert(*at as usize % (content.len() + 1), *c);
+                    json!({"content": content.iter().collect::<String>()})
+                }
+                Edit::Delete(at) if !content.is_empty() => {
+                    content.remove(*at as usize % content.len());
+                    json!({"content": content.iter().collect::<String>()})
+                }
+                _ => continue,
+            };
+
+            *device.clock.entry(device.id.clone()).or_insert(0) += 1;
+            let operation = post_operation(
+                &format!("op-{}", step + 1),
+                &device.id,
+                OperationType::Update,
+                payload,
+                device.clock.clone(),
+                *timestamp,
+            );
+            device.known.push(operation.clone());
+            operations.push(operation);
+        }
+
+        operations
+    }
+
+    proptest! {
+        #[test]
+        fn test_merge_ignores_arrival_order(
+            edits in prop::collection::vec((0..DEVICES, edit(), 0i64..4), 0..40),
+            rotation in any::<usize>(),
+        ) {
+            let operations = simulate(&edits);
+            let merged = merge(&operations);
+
+            let mut reversed = operations.clone();
+            reversed.reverse();
+            prop_assert_eq!(&merged, &merge(&reversed));
+
+            let mut rotated = operations.clone();
+            rotated.rotate_left(rotation % operations.len());
+            prop_assert_eq!(&merged, &merge(&rotated));
+
+            // Operations arriving twice change nothing
+            let mut repeated = operations.clone();
+            repeated.extend(operations.iter().cloned());
+            prop_assert_eq!(&merged, &merge(&repeated));
+        }
+
+        #[test]
+        fn test_merge_updates_is_commutative(
+            edits in prop::collection::vec((0..DEVICES, edit(), 0i64..4), 1..30),
+            first in any::<usize>(),
+            second in any::<usize>(),
+        ) {
+            let operations = simulate(&edits);
+            let op1 = &operations[first % operations.len()];
+            let op2 = &operations[second % operations.len()];
+
+            let merged = ConflictResolver::merge_with_history(&operations, op1, op2);
+            let reversed = ConflictResolver::merge_with_history(&operations, op2, op1);
+            prop_assert_eq!(&merged.id, &reversed.id);
+            prop_assert_eq!(&merged.payload, &reversed.payload);
+            prop_assert_eq!(merged.vector_clock, reversed.vector_clock);
+        }
+    }
+}
+
+#[cfg(test)]
+mod review_tests {
+    use super::*;
+
+    fn graded() -> (SyncOperation, SyncOperation, SyncOperation) {
+        let create = create_test_operation(
+            "device1",
+            OperationType::Create,
+            "submission",
+            Some("submission-1"),
+            json!({ "title": "Essay", "grade_comment": "", "points": 5 }),
+            HashMap::from([("device1".to_string(), 1)]),
+        );
+        let local = create_test_operation(
+            "device1",
+            OperationType::Update,
+            "submission",
+            Some("submission-1"),
+            json!({ "grade_comment": "Well argued", "points": 8 }),
+            HashMap::from([("device1".to_string(), 2)]),
+        );
+        let remote = create_test_operation(
+            "device2",
+            OperationType::Update,
+            "submission",
+            Some("submission-1"),
+            json!({ "grade_comment": "Late", "title": "Final essay" }),
+            HashMap::from([("device1".to_string(), 1), ("device2".to_string(), 1)]),
+        );
+        (create, local, remote)
+    }
+
+    #[test]
+    fn test_versions_are_rebuilt_from_history() {
+        let (create, local, remote) = graded();
+        let conflict = SyncConflict::new(&[create], &local, &remote);
+
+        assert_eq!(conflict.ancestor, Some(json!({ "title": "Essay", "grade_comment": "", "points": 5 })));
+        assert_eq!(conflict.local, json!({ "title": "Essay", "grade_comment": "Well argued", "points": 8 }));
+        assert_eq!(conflict.remote, json!({ "title": "Final essay", "grade_comment": "Late", "points": 5 }));
+    }
+
+    #[test]
+    fn test_three_way_diff_classifies_fields() {
+        let (create, local, remote) = graded();
+        let mut conflict = SyncConflict::new(&[create.clone()], &local, &remote);
+        conflict.applied = ConflictResolver::merge_with_history(&[create], &local, &remote).payload;
+
+        let changes: Vec<_> = review::three_way_diff(&conflict)
+            .into_iter()
+            .map(|diff| (diff.field.clone(), diff.change, diff.lost()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("grade_comment".to_string(), FieldChange::Conflict, true),
+                ("points".to_string(), FieldChange::Local, false),
+                ("title".to_string(), FieldChange::Remote, false),
+            ]
+        );
+        assert!(conflict.lost_changes());
+    }
+
+    #[test]
+    fn test_keeping_one_side_loses_the_other() {
+        let (create, local, remote) = graded();
+        let mut conflict = SyncConflict::new(&[create], &local, &remote);
+        conflict.applied = conflict.local.clone();
+
+        let lost: Vec<_> = review::three_way_diff(&conflict)
+            .into_iter()
+            .filter(|diff| diff.lost())
+            .map(|diff| diff.field)
+            .collect();
+        assert_eq!(lost, vec!["grade_comment".to_string(), "title".to_string()]);
+    }
+
+    #[test]
+    fn test_resolution_payload_clears_dropped_fields() {
+        let applied = json!({ "title": "Essay", "grade_comment": "Late" });
+        let chosen = json!({ "grade_comment": "Well argued" });
+
+        assert_eq!(
+            review::resolution_payload(&applied, &chosen),
+            json!({ "title": null, "grade_comment": "Well argued" })
+        );
+    }
+}
+
+#[cfg(test)]
+mod compaction_tests {
+    use super::*;
+
+    fn clock(entries: &[(&str, i64)]) -> HashMap<String, i64> {
+        entries.iter().map(|(device, counter)| (device.to_string(), *counter)).collect()
+    }
+
+    fn topic(device_id: &str, operation_type: OperationType, payload: serde_json::Value, entries: &[(&str, i64)]) -> SyncOperation {
+        create_test_operation(device_id, operation_type, "topic", Some("topic-1"), payload, clock(entries))
+    }
+
+    fn progress(entries: &[(&str, &[(&str, i64)])]) -> HashMap<String, VersionVector> {
+        entries
+            .iter()
+            .map(|(device, held)| (device.to_string(), VersionVector::from_hashmap(clock(held))))
+            .collect()
+    }
+
+    #[test]
+    fn test_stable_vector_takes_lowest_counters() {
+        let devices = vec!["device1".to_string(), "device2".to_string()];
+        let stable = compaction::stable_vector(&devices, &progress(&[
+            ("device1", &[("device1", 3), ("device2", 1)]),
+            ("device2", &[("device1", 2)]),
+        ]));
+
+        assert_eq!(stable.to_hashmap(), clock(&[("device1", 2), ("device2", 0)]));
+    }
+
+    #[test]
+    fn test_nothing_is_stable_while_a_device_is_unheard_of() {
+        // Device 3 made operations, but what it holds was never reported
+        let devices = vec!["device1".to_string(), "device2".to_string(), "device3".to_string()];
+        let stable = compaction::stable_vector(&devices, &progress(&[
+            ("device1", &[("device1", 3), ("device3", 1)]),
+            ("device2", &[("device1", 3), ("device3", 1)]),
+        ]));
+
+        assert_eq!(stable.size(), 0);
+    }
+
+    #[test]
+    fn test_unstable_operations_are_kept() {
+        let operations = vec![
+            topic("device1", OperationType::Create, json!({ "title": "Syllabus" }), &[("device1", 1)]),
+            topic("device2", OperationType::Update, json!({ "title": "Course syllabus" }), &[("device1", 1), ("device2", 1)]),
+        ];
+        let stable = VersionVector::from_hashmap(clock(&[("device1", 1)]));
+
+        assert!(matches!(compaction::plan(&operations, &stable), Compaction::Keep));
+    }
+
+    #[test]
+    fn test_stable_operations_fold_into_snapshot() {
+        let operations = vec![
+            topic("device1", OperationType::Create, json!({ "title": "Syllabus", "tags": ["week-1"] }), &[("device1", 1)]),
+            topic("device1", OperationType::Update, json!({ "tags": ["week-1", "exam"] }), &[("device1", 2)]),
+            topic("device2", OperationType::Update, json!({ "title": "Course syllabus" }), &[("device1", 1), ("device2", 1)]),
+        ];
+        let stable = VersionVector::from_hashmap(clock(&[("device1", 2), ("device2", 1)]));
+
+        let Compaction::Fold { snapshot, removed } = compaction::plan(&operations, &stable) else {
+            panic!("expected the operations to be folded");
+        };
+        assert_eq!(snapshot.operation_type, OperationType::Create);
+        assert_eq!(snapshot.payload, json!({ "title": "Course syllabus", "tags": ["exam", "week-1"] }));
+        assert_eq!(snapshot.vector_clock, clock(&[("device1", 2), ("device2", 1)]));
+        assert_eq!(removed.len(), 2);
+        assert!(!removed.contains(&snapshot.id));
+    }
+
+    #[test]
+    fn test_stable_tombstone_expires() {
+        let operations = vec![
+            topic("device1", OperationType::Create, json!({ "title": "Syllabus" }), &[("device1", 1)]),
+            topic("device2", OperationType::Delete, serde_json::Value::Null, &[("device1", 1), ("device2", 1)]),
+        ];
+        let ids: Vec<String> = operations.iter().map(|operation| operation.id.clone()).collect();
+
+        let stable = VersionVector::from_hashmap(clock(&[("device1", 1), ("device2", 1)]));
+        let Compaction::Expire { removed } = compaction::plan(&operations, &stable) else {
+            panic!("expected the tombstone to expire");
+        };
+        assert_eq!(removed, ids);
+
+        // Not before the delete is stable
+        let stable = VersionVector::from_hashmap(clock(&[("device1", 1)]));
+        assert!(matches!(compaction::plan(&operations, &stable), Compaction::Keep));
+    }
+}
+
 // Helper function to create a test operation
 fn create_test_operation(
     device_id: &str,
@@ -197,6 +725,7 @@ fn create_test_operation(
     payload: serde_json::Value,
     vector_clock: HashMap<String, i64>,
 ) -> SyncOperation {
+    let timestamp = chrono::Utc::now().timestamp();
     SyncOperation {
         id: Uuid::new_v4().to_string(),
         device_id: device_id.to_string(),
@@ -205,7 +734,8 @@ fn create_test_operation(
         entity_type: entity_type.to_string(),
         entity_id: entity_id.map(|s| s.to_string()),
         payload,
-        timestamp: chrono::Utc::now().timestamp(),
+        timestamp,
+        hlc: HlcTimestamp::from_unix_timestamp(timestamp, device_id),
         vector_clock,
         synced: false,
         synced_at: None,
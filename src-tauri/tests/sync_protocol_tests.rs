@@ -0,0 +1,460 @@
+use async_trait::async_trait;
+use lms_lib::core::errors::AppError;
+use lms_lib::sync::crdt::{self, MergeSchema};
+use lms_lib::sync::engine::SyncEngine;
+use lms_lib::sync::hlc::HlcTimestamp;
+use lms_lib::sync::operations::{OperationType, SyncBatch, SyncOperation};
+use lms_lib::sync::protocol::{self, tcp, SyncRequest, SyncResponse, SyncTransport};
+use lms_lib::sync::review::{ConflictChoice, ConflictStatus};
+use lms_lib::sync::version_vector::VersionVector;
+use serde_json::json;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+const USER_ID: i64 = 7;
+
+/// An engine on its own SQLite file, with batches small enough that every
+/// exchange takes several
+async fn engine(device_id: &str) -> (Arc<SyncEngine>, PathBuf) {
+    let path = std::env::temp_dir().join(format!("sync-{}-{}.db", device_id, Uuid::new_v4()));
+    let pool = SqlitePoolOptions::new()
+        .connect_with(SqliteConnectOptions::new().filename(&path).create_if_missing(true))
+        .await
+        .unwrap();
+    sqlx::query(include_str!("../migrations/20261017000000_rebuild_sync_operations.sql"))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let engine = SyncEngine::with_config(pool, Some(device_id.to_string()), 3, 0, false);
+    engine.initialize().await.unwrap();
+    (Arc::new(engine), path)
+}
+
+async fn queue(engine: &SyncEngine, operation_type: OperationType, entity_type: &str, entity_id: &str) {
+    engine
+        .queue_operation(USER_ID, operation_type, entity_type, Some(entity_id), json!({ "title": entity_id }))
+        .await
+        .unwrap();
+}
+
+async fn operation_ids(engine: &SyncEngine) -> HashSet<String> {
+    engine
+        .operations_missing_from(USER_ID, &VersionVector::new(), usize::MAX)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|operation| operation.id)
+        .collect()
+}
+
+async fn assert_converged(a: &SyncEngine, b: &SyncEngine, operations: usize) {
+    let ids = operation_ids(a).await;
+    assert_eq!(ids.len(), operations);
+    assert_eq!(ids, operation_ids(b).await);
+    assert_eq!(
+        a.known_sequences(USER_ID).await.unwrap().to_hashmap(),
+        b.known_sequences(USER_ID).await.unwrap().to_hashmap()
+    );
+}
+
+/// Calls the peer's engine directly, dropping the connection after a
+/// number of calls
+struct Direct {
+    peer: Arc<SyncEngine>,
+    calls_left: Option<usize>,
+}
+
+#[async_trait]
+impl SyncTransport for Direct {
+    async fn call(&mut self, request: SyncRequest) -> Result<SyncResponse, AppError> {
+        if let Some(calls_left) = &mut self.calls_left {
+            if *calls_left == 0 {
+                return Err(AppError::SyncError("Connection dropped".to_string()));
+            }
+            *calls_left -= 1;
+        }
+        protocol::handle_request(&self.peer, USER_ID, request).await
+    }
+}
+
+#[tokio::test]
+async fn test_two_devices_converge() {
+    let (a, a_path) = engine("laptop-a").await;
+    let (b, b_path) = engine("laptop-b").await;
+
+    for i in 0..5 {
+        queue(&a, OperationType::Create, "topic", &format!("topic-{}", i)).await;
+    }
+    for i in 0..4 {
+        queue(&b, OperationType::Create, "post", &format!("post-{}", i)).await;
+    }
+
+    // Laptop A connects to laptop B over the LAN
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(tcp::serve(listener, b.clone()));
+
+    let token = b.create_pairing(USER_ID).await.unwrap();
+    let mut transport = tcp::TcpTransport::connect(addr, &token).await.unwrap();
+    let report = protocol::synchronize(&a, &mut transport, USER_ID).await.unwrap();
+    assert_eq!(report.peer_device_id, "laptop-b");
+    assert_eq!((report.operations_sent, report.batches_sent), (5, 2));
+    assert_eq!((report.operations_received, report.batches_received), (4, 2));
+    assert_converged(&a, &b, 9).await;
+
+    // Acknowledged operations are marked synced on the device they came from
+    let pending = a.get_pending_operations(100).await.unwrap();
+    assert!(pending.iter().all(|operation| operation.device_id == "laptop-b"));
+
+    // Nothing left to exchange
+    let report = protocol::synchronize(&a, &mut transport, USER_ID).await.unwrap();
+    assert_eq!((report.operations_sent, report.operations_received), (0, 0));
+
+    // Edits made after the exchange build on what the other device sent
+    for i in 0..4 {
+        queue(&a, OperationType::Update, "post", &format!("post-{}", i)).await;
+    }
+    queue(&b, OperationType::Update, "topic", "topic-0").await;
+    queue(&b, OperationType::Delete, "topic", "topic-1").await;
+
+    // The connection drops after the first batch is pushed
+    let mut flaky = Direct { peer: b.clone(), calls_left: Some(2) };
+    assert!(protocol::synchronize(&a, &mut flaky, USER_ID).await.is_err());
+    assert_eq!(operation_ids(&b).await.len(), 14);
+
+    // The next exchange resumes where it stopped
+    let mut direct = Direct { peer: b.clone(), calls_left: None };
+    let report = protocol::synchronize(&a, &mut direct, USER_ID).await.unwrap();
+    assert_eq!((report.operations_sent, report.operations_received), (1, 2));
+    assert_converged(&a, &b, 15).await;
+
+    let _ = std::fs::remove_file(a_path);
+    let _ = std::fs::remove_file(b_path);
+}
+
+#[tokio::test]
+async fn test_lan_peers_must_be_paired() {
+    let (a, a_path) = engine("laptop-a").await;
+    let (b, b_path) = engine("laptop-b").await;
+    queue(&a, OperationType::Create, "topic", "topic-0").await;
+    queue(&b, OperationType::Create, "post", "post-0").await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(tcp::serve(listener, b.clone()));
+
+    // A device presenting no known token, or one paired for another user,
+    // gets nothing
+    let mut transport = tcp::TcpTransport::connect(addr, "not-a-token").await.unwrap();
+    assert!(protocol::synchronize(&a, &mut transport, USER_ID).await.is_err());
+    let token = b.create_pairing(USER_ID + 1).await.unwrap();
+    let mut transport = tcp::TcpTransport::connect(addr, &token).await.unwrap();
+    assert!(protocol::synchronize(&a, &mut transport, USER_ID).await.is_err());
+    assert_eq!(operation_ids(&b).await.len(), 1);
+
+    // A paired device may only exchange its user's operations
+    let token = b.create_pairing(USER_ID).await.unwrap();
+    let mut transport = tcp::TcpTransport::connect(addr, &token).await.unwrap();
+    let hello = protocol::Hello {
+        protocol_version: protocol::PROTOCOL_VERSION,
+        device_id: "laptop-a".to_string(),
+        user_id: USER_ID,
+        version_vector: HashMap::new(),
+        pairing_token: None,
+        progress: HashMap::new(),
+    };
+    assert!(matches!(transport.call(SyncRequest::Hello(hello)).await.unwrap(), SyncResponse::Hello(_)));
+
+    let mut operation = a
+        .operations_missing_from(USER_ID, &VersionVector::new(), 1)
+        .await
+        .unwrap()
+        .remove(0);
+    operation.user_id = USER_ID + 1;
+    let covered = HashMap::from([("laptop-a".to_string(), 1)]);
+    let batch = SyncBatch::new("laptop-a", USER_ID, vec![operation], covered);
+    assert!(matches!(transport.call(SyncRequest::Push(batch)).await.unwrap(), SyncResponse::Error(_)));
+    let pull = SyncRequest::Pull { user_id: USER_ID + 1, known: HashMap::new(), limit: 10 };
+    assert!(matches!(transport.call(pull).await.unwrap(), SyncResponse::Error(_)));
+    assert_eq!(operation_ids(&b).await.len(), 1);
+
+    let _ = std::fs::remove_file(a_path);
+    let _ = std::fs::remove_file(b_path);
+}
+
+#[tokio::test]
+async fn test_lan_sync_is_off_until_turned_on() {
+    let path = std::env::temp_dir().join(format!("sync-lan-{}.db", Uuid::new_v4()));
+    let pool = SqlitePoolOptions::new()
+        .connect_with(SqliteConnectOptions::new().filename(&path).create_if_missing(true))
+        .await
+        .unwrap();
+    sqlx::query(include_str!("../migrations/20261017000000_rebuild_sync_operations.sql"))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let engine = SyncEngine::open(pool.clone()).await.unwrap();
+    assert!(!engine.lan_sync_enabled().await.unwrap());
+    engine.set_lan_sync_enabled(true).await.unwrap();
+
+    // The setting outlives the engine, as the device ID does
+    let reopened = SyncEngine::open(pool).await.unwrap();
+    assert!(reopened.lan_sync_enabled().await.unwrap());
+    assert_eq!(reopened.device_id(), engine.device_id());
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn test_skewed_clocks() {
+    let (a, a_path) = engine("laptop-a").await;
+
+    // A laptop whose clock is an hour ahead sends an edit, which is
+    // rejected and its clock reported
+    let skewed_edit = |ahead: i64| {
+        let mut edit = SyncOperation::update(
+            "laptop-skewed",
+            USER_ID,
+            "topic",
+            "topic-0",
+            json!({ "title": "Edited" }),
+            HashMap::from([("laptop-skewed".to_string(), 1)]),
+        );
+        edit.timestamp += ahead;
+        edit.hlc = HlcTimestamp::from_unix_timestamp(edit.timestamp, "laptop-skewed");
+        let mut batch = SyncBatch::new("laptop-skewed", USER_ID, vec![edit.clone()], edit.vector_clock.clone());
+        batch.timestamp += ahead;
+        (edit, batch)
+    };
+    let (_, batch) = skewed_edit(3600);
+    assert!(a.apply_sync_batch(batch).await.is_err());
+    assert!(a.get_pending_operations(10).await.unwrap().is_empty());
+
+    let drift = a.clock_drift().await;
+    assert_eq!(drift.len(), 1);
+    assert_eq!(drift[0].device_id, "laptop-skewed");
+    assert!((drift[0].offset_ms - 3_600_000).abs() < 5_000);
+
+    // Once it is set to within the threshold, its edits are taken
+    let (edit, batch) = skewed_edit(30);
+    a.apply_sync_batch(batch).await.unwrap();
+    assert!(a.clock_drift().await.is_empty());
+
+    // Edits made after receiving it are stamped after it
+    let reply = a
+        .queue_operation(USER_ID, OperationType::Update, "topic", Some("topic-0"), json!({ "title": "Reply" }))
+        .await
+        .unwrap();
+    assert!(reply.hlc > edit.hlc);
+    assert_eq!(reply.hlc.device_id, "laptop-a");
+
+    // Stamps survive a round trip through the database
+    let stored = a.get_pending_operations(10).await.unwrap();
+    assert_eq!(stored.iter().map(|operation| &operation.hlc).collect::<Vec<_>>(), vec![&edit.hlc, &reply.hlc]);
+
+    let _ = std::fs::remove_file(a_path);
+}
+
+#[tokio::test]
+async fn test_dropped_edits_are_kept_for_review() {
+    let (a, a_path) = engine("laptop-a").await;
+    let (b, b_path) = engine("laptop-b").await;
+    let mut direct = Direct { peer: b.clone(), calls_left: None };
+
+    queue(&a, OperationType::Create, "submission", "submission-0").await;
+    protocol::synchronize(&a, &mut direct, USER_ID).await.unwrap();
+
+    // Two instructors comment on the same submission while offline
+    a.queue_operation(
+        USER_ID,
+        OperationType::Update,
+        "submission",
+        Some("submission-0"),
+        json!({ "grade_comment": "Well argued" }),
+    )
+    .await
+    .unwrap();
+    b.queue_operation(
+        USER_ID,
+        OperationType::Update,
+        "submission",
+        Some("submission-0"),
+        json!({ "grade_comment": "Late" }),
+    )
+    .await
+    .unwrap();
+    protocol::synchronize(&a, &mut direct, USER_ID).await.unwrap();
+
+    // The later comment won on laptop B, which kept the conflict
+    let conflicts = b.conflict_queue().list(USER_ID, Some(ConflictStatus::Open)).await.unwrap();
+    assert_eq!(conflicts.len(), 1);
+    let conflict = &conflicts[0];
+    assert_eq!(conflict.ancestor, Some(json!({ "title": "submission-0" })));
+    assert_eq!(conflict.local["grade_comment"], "Late");
+    assert_eq!(conflict.remote["grade_comment"], "Well argued");
+    assert_eq!(conflict.applied["grade_comment"], "Late");
+
+    // Keeping the other comment is queued as an edit and reaches laptop A
+    let resolved = b.resolve_review_conflict(&conflict.id, ConflictChoice::Remote).await.unwrap();
+    assert_eq!(resolved.status, ConflictStatus::Resolved);
+    assert_eq!(resolved.resolution.as_deref(), Some("remote"));
+    assert!(b.resolve_review_conflict(&conflict.id, ConflictChoice::Local).await.is_err());
+    // A resolution racing this one finds the conflict no longer open
+    assert!(b.conflict_queue().mark_resolved(&conflict.id, &ConflictChoice::Local).await.is_err());
+    assert!(b.conflict_queue().list(USER_ID, Some(ConflictStatus::Open)).await.unwrap().is_empty());
+
+    protocol::synchronize(&a, &mut direct, USER_ID).await.unwrap();
+    let operations: Vec<SyncOperation> = a
+        .operations_missing_from(USER_ID, &VersionVector::new(), usize::MAX)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|operation| operation.entity_id.as_deref() == Some("submission-0"))
+        .collect();
+    assert!(operations.iter().any(|operation| Some(&operation.id) == resolved.resolution_operation_id.as_ref()));
+    let state = crdt::merge_payloads(&operations, &MergeSchema::default());
+    assert_eq!(state["grade_comment"], "Well argued");
+    assert_eq!(state["title"], "submission-0");
+
+    let _ = std::fs::remove_file(a_path);
+    let _ = std::fs::remove_file(b_path);
+}
+
+#[tokio::test]
+async fn test_large_batches_keep_conflicts_for_review() {
+    let (a, a_path) = engine("laptop-a").await;
+    let (b, b_path) = engine("laptop-b").await;
+    let mut direct = Direct { peer: b.clone(), calls_left: None };
+
+    queue(&a, OperationType::Create, "submission", "submission-0").await;
+    protocol::synchronize(&a, &mut direct, USER_ID).await.unwrap();
+
+    // Laptop A comments on the submission among more edits than fit a batch,
+    // while laptop B comments on it too
+    a.queue_operation(USER_ID, OperationType::Update, "submission", Some("submission-0"), json!({ "grade_comment": "Well argued" }))
+        .await
+        .unwrap();
+    for i in 0..4 {
+        queue(&a, OperationType::Create, "topic", &format!("topic-{}", i)).await;
+    }
+    b.queue_operation(USER_ID, OperationType::Update, "submission", Some("submission-0"), json!({ "grade_comment": "Late" }))
+        .await
+        .unwrap();
+
+    let known = b.known_sequences(USER_ID).await.unwrap();
+    let operations = a.operations_missing_from(USER_ID, &known, usize::MAX).await.unwrap();
+    assert_eq!(operations.len(), 5);
+    let covered = a.known_sequences(USER_ID).await.unwrap().to_hashmap();
+    b.apply_sync_batch(SyncBatch::new("laptop-a", USER_ID, operations, covered)).await.unwrap();
+
+    let conflicts = b.conflict_queue().list(USER_ID, Some(ConflictStatus::Open)).await.unwrap();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].local["grade_comment"], "Late");
+    assert_eq!(conflicts[0].remote["grade_comment"], "Well argued");
+    assert_eq!(operation_ids(&b).await.len(), 6);
+
+    let _ = std::fs::remove_file(a_path);
+    let _ = std::fs::remove_file(b_path);
+}
+
+#[tokio::test]
+async fn test_compaction_waits_for_devices_behind_the_hub() {
+    let (a, a_path) = engine("laptop-a").await;
+    let (b, b_path) = engine("laptop-b").await;
+    let (hub, hub_path) = engine("hub").await;
+    let mut a_to_hub = Direct { peer: hub.clone(), calls_left: None };
+    let mut b_to_hub = Direct { peer: hub.clone(), calls_left: None };
+
+    // Laptop B syncs once, then laptop A writes and revises a topic
+    queue(&b, OperationType::Create, "post", "post-0").await;
+    protocol::synchronize(&b, &mut b_to_hub, USER_ID).await.unwrap();
+    queue(&a, OperationType::Create, "topic", "topic-0").await;
+    a.queue_operation(USER_ID, OperationType::Update, "topic", Some("topic-0"), json!({ "body": "Week 1" }))
+        .await
+        .unwrap();
+    protocol::synchronize(&a, &mut a_to_hub, USER_ID).await.unwrap();
+
+    // The hub holds the revisions, but laptop B does not yet
+    let report = a.compact(0).await.unwrap();
+    assert_eq!(report.operations_folded, 0);
+
+    // Laptop B pulls them, and the hub passes its acknowledgement on
+    protocol::synchronize(&b, &mut b_to_hub, USER_ID).await.unwrap();
+    protocol::synchronize(&a, &mut a_to_hub, USER_ID).await.unwrap();
+    let report = a.compact(0).await.unwrap();
+    assert_eq!((report.operations_folded, report.snapshots_written), (2, 1));
+
+    // Laptop B edits the topic after the revisions, and the edit merges
+    // with the snapshot
+    b.queue_operation(USER_ID, OperationType::Update, "topic", Some("topic-0"), json!({ "title": "Syllabus" }))
+        .await
+        .unwrap();
+    protocol::synchronize(&b, &mut b_to_hub, USER_ID).await.unwrap();
+    protocol::synchronize(&a, &mut a_to_hub, USER_ID).await.unwrap();
+
+    let topic_operations = |engine: Arc<SyncEngine>| async move {
+        engine
+            .operations_missing_from(USER_ID, &VersionVector::new(), usize::MAX)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|operation| operation.entity_id.as_deref() == Some("topic-0"))
+            .collect::<Vec<_>>()
+    };
+    let operations = topic_operations(a.clone()).await;
+    assert_eq!(operations.len(), 2);
+    let state = crdt::merge_payloads(&operations, &MergeSchema::default());
+    assert_eq!(state["title"], "Syllabus");
+    assert_eq!(state["body"], "Week 1");
+    assert!(a.conflict_queue().list(USER_ID, Some(ConflictStatus::Open)).await.unwrap().is_empty());
+
+    // Operations folded away are not stored again when resent, in large
+    // batches too
+    let resent = b.operations_missing_from(USER_ID, &VersionVector::new(), usize::MAX).await.unwrap();
+    assert!(resent.len() > a.max_batch_size());
+    let covered = b.known_sequences(USER_ID).await.unwrap().to_hashmap();
+    a.apply_sync_batch(SyncBatch::new("laptop-b", USER_ID, resent, covered)).await.unwrap();
+    assert_eq!(topic_operations(a.clone()).await.len(), 2);
+
+    let _ = std::fs::remove_file(a_path);
+    let _ = std::fs::remove_file(b_path);
+    let _ = std::fs::remove_file(hub_path);
+}
+
+#[tokio::test]
+async fn test_relayed_progress_ahead_of_the_hub_is_ignored() {
+    let (a, a_path) = engine("laptop-a").await;
+    let (hub, hub_path) = engine("hub").await;
+    let mut a_to_hub = Direct { peer: hub.clone(), calls_left: None };
+    queue(&a, OperationType::Create, "topic", "topic-0").await;
+    protocol::synchronize(&a, &mut a_to_hub, USER_ID).await.unwrap();
+
+    // Laptop B relays one device holding what the hub holds, and another
+    // claiming operations the hub never received
+    let relayed = |sequence| protocol::DeviceProgress {
+        held: HashMap::from([("laptop-a".to_string(), sequence)]),
+        reported_at: i64::MAX,
+    };
+    let hello = protocol::Hello {
+        protocol_version: protocol::PROTOCOL_VERSION,
+        device_id: "laptop-b".to_string(),
+        user_id: USER_ID,
+        version_vector: HashMap::new(),
+        pairing_token: None,
+        progress: HashMap::from([("laptop-c".to_string(), relayed(1)), ("laptop-d".to_string(), relayed(5))]),
+    };
+    protocol::handle_request(&hub, USER_ID, SyncRequest::Hello(hello)).await.unwrap();
+
+    let progress = hub.device_progress(USER_ID).await.unwrap();
+    assert!(progress["laptop-c"].reported_at < i64::MAX);
+    assert!(!progress.contains_key("laptop-d"));
+
+    let _ = std::fs::remove_file(a_path);
+    let _ = std::fs::remove_file(hub_path);
+}
@@ -36,6 +36,13 @@ pub struct Cmi5Result {
     pub completion: Option<bool>,
 }
 
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Cmi5Launch {
+    pub session_id: String,
+    pub url: String,
+    pub launch_method: String,
+}
+
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = ["window", "__TAURI__", "tauri"])]
@@ -164,12 +171,17 @@ pub fn Cmi5Manager() -> impl IntoView {
         })).unwrap();
 
         match invoke("launch_cmi5_assignable_unit", args).await {
-            Ok(url) => {
-                let url_str = url.as_string().unwrap_or_default();
-                set_launch_url.set(Some(url_str.clone()));
+            Ok(launch) => {
+                let launch: Cmi5Launch = launch.into_serde().unwrap_or_default();
+                set_launch_url.set(Some(launch.url.clone()));
+                
+                // AUs with launchMethod OwnWindow must not be framed by the LMS
+                if launch.launch_method == "OwnWindow" {
+                    let window = web_sys::window().unwrap();
+                    window.open_with_url_and_target(&launch.url, "_blank").unwrap();
+                }
                 
-                // In a real application, you would open this URL in a new window or iframe
-                console_log!("Launch URL: {}", url_str);
+                console_log!("Launch URL: {}", launch.url);
             },
             Err(e) => {
                 set_error.set(Some(format!("Failed to launch assignable unit: {}", e)));